@@ -0,0 +1,25 @@
+#!/bin/bash
+set -e
+
+# Downloads Hunspell dictionaries for the offline proofreader into DICTIONARY_DIR
+# Usage: deploy/fetch_dictionaries.sh [language ...]   (default: en_US)
+# Dictionaries come from the LibreOffice dictionaries repository, check each one's license
+
+DICTIONARY_DIR="${DICTIONARY_DIR:-dictionaries}"
+BASE_URL="https://raw.githubusercontent.com/LibreOffice/dictionaries/master"
+LANGUAGES=("$@")
+if [ ${#LANGUAGES[@]} -eq 0 ]; then
+    LANGUAGES=("en_US")
+fi
+
+mkdir -p "$DICTIONARY_DIR"
+
+for LANGUAGE in "${LANGUAGES[@]}"; do
+    # The repository groups dictionaries by language, e.g. en/en_US.dic, de/de_DE_frami.dic
+    FOLDER="${LANGUAGE%%_*}"
+    echo "Fetching $LANGUAGE dictionary..."
+    curl -fsSL "$BASE_URL/$FOLDER/$LANGUAGE.aff" -o "$DICTIONARY_DIR/$LANGUAGE.aff"
+    curl -fsSL "$BASE_URL/$FOLDER/$LANGUAGE.dic" -o "$DICTIONARY_DIR/$LANGUAGE.dic"
+done
+
+echo "Dictionaries saved to $DICTIONARY_DIR"
@@ -7,20 +7,61 @@ DROP TABLE IF EXISTS commands CASCADE;
 DROP TABLE IF EXISTS user_preferences CASCADE;
 DROP TABLE IF EXISTS default_preferences CASCADE;
 DROP TABLE IF EXISTS user_backgrounds CASCADE;
-
+DROP TABLE IF EXISTS user_dictionary_words CASCADE;
+
+DROP TABLE IF EXISTS assistant_personas CASCADE;
+DROP TABLE IF EXISTS writing_goals CASCADE;
+DROP TABLE IF EXISTS writing_activity CASCADE;
+DROP TABLE IF EXISTS document_tagging_runs CASCADE;
+DROP TABLE IF EXISTS document_tag_suggestions CASCADE;
+DROP TABLE IF EXISTS document_tags CASCADE;
+DROP TABLE IF EXISTS tags CASCADE;
+DROP TABLE IF EXISTS consistency_findings CASCADE;
+DROP TABLE IF EXISTS consistency_reports CASCADE;
+DROP TABLE IF EXISTS entity_mentions CASCADE;
+DROP TABLE IF EXISTS project_entities CASCADE;
+DROP TABLE IF EXISTS document_translations CASCADE;
+DROP TABLE IF EXISTS project_glossary_terms CASCADE;
+DROP TABLE IF EXISTS project_stats_daily CASCADE;
+DROP TABLE IF EXISTS project_stats CASCADE;
+DROP TABLE IF EXISTS document_stats_daily CASCADE;
+DROP TABLE IF EXISTS document_stats CASCADE;
+DROP TABLE IF EXISTS ai_credit_ledger CASCADE;
+DROP TABLE IF EXISTS embedding_jobs CASCADE;
+DROP TABLE IF EXISTS document_chunks CASCADE;
 DROP TABLE IF EXISTS document_permissions CASCADE;
 DROP TABLE IF EXISTS document_projects CASCADE;
 DROP TABLE IF EXISTS documents CASCADE;
 DROP TABLE IF EXISTS project_permissions CASCADE;
 DROP TABLE IF EXISTS projects CASCADE;
 DROP TABLE IF EXISTS users CASCADE;
+DROP TABLE IF EXISTS plans CASCADE;
 DROP TABLE IF EXISTS user_profile_images CASCADE;
+DROP TABLE IF EXISTS writing_assistant_tool_calls CASCADE;
 DROP TABLE IF EXISTS writing_assistant_messages CASCADE;
 DROP TABLE IF EXISTS writing_assistant_sessions CASCADE;
 DROP TYPE IF EXISTS message_role_enum CASCADE;
 
 CREATE EXTENSION IF NOT EXISTS vector; -- Use PGVECTOR
 
+-- Create plans table
+-- A plan bundles the monthly AI credit allowance with the storage limits of its users
+CREATE TABLE plans (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(50) NOT NULL UNIQUE,
+    monthly_credits INT NOT NULL,
+    max_projects INT NOT NULL,
+    max_documents INT NOT NULL,
+    storage_quota_bytes BIGINT NOT NULL
+);
+
+INSERT INTO plans (id, name, monthly_credits, max_projects, max_documents, storage_quota_bytes) VALUES
+    (1, 'free', 10, 3, 10, 10485760),
+    (2, 'pro', 500, 50, 500, 1073741824),
+    (3, 'team', 2000, 200, 5000, 10737418240);
+
+SELECT setval('plans_id_seq', (SELECT MAX(id) FROM plans));
+
 -- Create users table
 CREATE TABLE users (
     id SERIAL PRIMARY KEY,
@@ -29,10 +70,52 @@ CREATE TABLE users (
     password VARCHAR(255) NOT NULL,
     ai_credits INT NOT NULL DEFAULT 10,
     storage_bytes BIGINT NOT NULL DEFAULT 0,
-    max_projects INT NOT NULL DEFAULT 3,
-    max_documents INT NOT NULL DEFAULT 10
+    plan_id INT NOT NULL DEFAULT 1 REFERENCES plans(id),
+    credits_refilled_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
 
+-- Create AI credit ledger table
+-- Every change to users.ai_credits is recorded here: debits are negative, refunds and grants positive
+CREATE TABLE ai_credit_ledger (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    operation VARCHAR(50) NOT NULL,
+    amount INT NOT NULL,
+    balance_after INT NOT NULL,
+    prompt_tokens INT NOT NULL DEFAULT 0,
+    completion_tokens INT,
+    refund_of INT REFERENCES ai_credit_ledger(id) ON DELETE SET NULL,
+    note TEXT,
+    prompt_template VARCHAR(100), -- Template version of the charged prompt, e.g. grammar_check@v1
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_ai_credit_ledger_user ON ai_credit_ledger(user_id, created_at DESC);
+
+-- Create assistant personas table
+-- System prompts for the writing assistant. Built-in personas have no user_id, users can add their own
+CREATE TABLE assistant_personas (
+    id SERIAL PRIMARY KEY,
+    user_id INT REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    system_prompt TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO assistant_personas (id, user_id, name, description, system_prompt) VALUES
+    (1, NULL, 'Writing assistant', 'General help with writing and documents',
+     'You are a helpful writing assistant. Your goal is to help the user improve their writing, provide suggestions, and answer questions about their documents. Focus on being constructive and providing clear, actionable feedback that helps the user improve their writing.'),
+    (2, NULL, 'Developmental editor', 'Big-picture feedback on structure, plot, pacing and characters',
+     'You are an experienced developmental editor. Focus on the big picture: structure, argument or plot, pacing, character and voice. Point out what works, name the most important problems first and suggest concrete revisions. Do not line-edit unless asked.'),
+    (3, NULL, 'Technical reviewer', 'Checks accuracy, precision and clarity of technical writing',
+     'You are a meticulous technical reviewer. Check statements for accuracy, precision and completeness, flag ambiguous terminology and unsupported claims, and suggest clearer wording. Be direct and specific, and keep the author''s technical level.'),
+    (4, NULL, 'Translator into Spanish', 'Translates and answers in Spanish',
+     'You are a professional translator into Spanish. Translate the text the user provides into natural, idiomatic Spanish that keeps the meaning, tone and formatting of the original. Answer questions in Spanish.');
+
+SELECT setval('assistant_personas_id_seq', (SELECT MAX(id) FROM assistant_personas));
+CREATE INDEX idx_assistant_personas_user ON assistant_personas(user_id);
+
 -- Create projects table
 CREATE TABLE projects (
     id SERIAL PRIMARY KEY,
@@ -41,7 +124,10 @@ CREATE TABLE projects (
     updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
     is_starred BOOLEAN DEFAULT FALSE,
     is_trashed BOOLEAN DEFAULT FALSE,
-    user_id INT REFERENCES users(id) ON DELETE CASCADE
+    user_id INT REFERENCES users(id) ON DELETE CASCADE,
+    persona_id INT REFERENCES assistant_personas(id) ON DELETE SET NULL, -- Assistant persona for sessions on the project's documents
+    system_prompt TEXT, -- Custom system prompt, takes precedence over persona_id
+    style_guide TEXT -- Style guide the assistant follows for the project's documents
 );
 
 -- Create documents table
@@ -61,6 +147,41 @@ CREATE TABLE documents (
 -- Create vector index for similarity search
 CREATE INDEX document_embedding_idx ON documents USING ivfflat (embedding vector_cosine_ops) WITH (lists = 100);
 
+-- Create document_chunks table for chunk-level embeddings
+-- Offsets are character offsets into the plain text of the document
+CREATE TABLE document_chunks (
+    id SERIAL PRIMARY KEY,
+    document_id INT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    chunk_index INT NOT NULL,
+    heading TEXT,
+    content TEXT NOT NULL,
+    start_offset INT NOT NULL,
+    end_offset INT NOT NULL,
+    embedding vector(1536),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (document_id, chunk_index)
+);
+
+-- Create indexes for chunk lookups and chunk similarity search
+CREATE INDEX idx_document_chunks_document_id ON document_chunks(document_id);
+CREATE INDEX document_chunks_embedding_idx ON document_chunks USING ivfflat (embedding vector_cosine_ops) WITH (lists = 100);
+
+-- Create embedding_jobs table used as a durable queue for document embedding
+CREATE TABLE embedding_jobs (
+    id SERIAL PRIMARY KEY,
+    document_id INT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'running', 'done', 'dead')),
+    attempts INT NOT NULL DEFAULT 0,
+    run_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Only one pending job per document, later saves push its run_at back (debounce)
+CREATE UNIQUE INDEX idx_embedding_jobs_pending_document ON embedding_jobs(document_id) WHERE status = 'pending';
+CREATE INDEX idx_embedding_jobs_status_run_at ON embedding_jobs(status, run_at);
+
 -- Create junction table for many-to-many relationship
 CREATE TABLE document_projects (
     document_id INT REFERENCES documents(id) ON DELETE CASCADE,
@@ -68,6 +189,197 @@ CREATE TABLE document_projects (
     PRIMARY KEY (document_id, project_id)
 );
 
+-- Create writing statistics tables, see models::stats
+-- The cache is keyed by an md5 of the text the metrics were computed from, projects by the ids and md5s of their documents
+CREATE TABLE document_stats (
+    document_id INT PRIMARY KEY REFERENCES documents(id) ON DELETE CASCADE,
+    content_hash VARCHAR(32) NOT NULL,
+    metrics JSONB NOT NULL,
+    computed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Last statistics of each day the document changed, the history
+CREATE TABLE document_stats_daily (
+    document_id INT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    word_count INT NOT NULL,
+    metrics JSONB NOT NULL,
+    PRIMARY KEY (document_id, day)
+);
+
+CREATE TABLE project_stats (
+    project_id INT PRIMARY KEY REFERENCES projects(id) ON DELETE CASCADE,
+    content_hash TEXT NOT NULL,
+    metrics JSONB NOT NULL,
+    computed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE project_stats_daily (
+    project_id INT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    word_count INT NOT NULL,
+    metrics JSONB NOT NULL,
+    PRIMARY KEY (project_id, day)
+);
+
+-- Create translation tables, see models::translation
+-- Glossary of a project: terms kept as written or always translated the same way into a language
+CREATE TABLE project_glossary_terms (
+    id SERIAL PRIMARY KEY,
+    project_id INT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    term VARCHAR(200) NOT NULL,
+    rule VARCHAR(20) NOT NULL CHECK (rule IN ('keep', 'translate')),
+    translation VARCHAR(200),
+    language VARCHAR(16), -- Target language code, NULL for keep rules of every language
+    notes TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK (rule = 'keep' OR (translation IS NOT NULL AND language IS NOT NULL))
+);
+
+CREATE UNIQUE INDEX idx_project_glossary_terms_term ON project_glossary_terms(project_id, LOWER(term), COALESCE(language, ''));
+
+-- Translated documents and their sources. The translation is stale once the md5 of the source differs from source_hash.
+CREATE TABLE document_translations (
+    document_id INT PRIMARY KEY REFERENCES documents(id) ON DELETE CASCADE,
+    source_document_id INT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    target_language VARCHAR(16) NOT NULL,
+    source_hash VARCHAR(32) NOT NULL,
+    translated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_document_translations_source ON document_translations(source_document_id, target_language);
+
+-- Create knowledge base tables, see models::knowledge
+-- Characters, places and terms of a project, the assistant sees them with the style guide
+CREATE TABLE project_entities (
+    id SERIAL PRIMARY KEY,
+    project_id INT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('character', 'place', 'organization', 'item', 'term', 'other')),
+    name VARCHAR(200) NOT NULL,
+    aliases TEXT[] NOT NULL DEFAULT '{}',
+    description TEXT,
+    notes TEXT,
+    source VARCHAR(20) NOT NULL DEFAULT 'manual' CHECK (source IN ('manual', 'extracted')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_project_entities_name ON project_entities(project_id, LOWER(name));
+
+-- Documents mentioning an entity by name or alias, offsets are characters of the stored content
+CREATE TABLE entity_mentions (
+    entity_id INT NOT NULL REFERENCES project_entities(id) ON DELETE CASCADE,
+    document_id INT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    mention_count INT NOT NULL,
+    offsets INT[] NOT NULL DEFAULT '{}', -- The first 100 mentions
+    PRIMARY KEY (entity_id, document_id)
+);
+
+CREATE INDEX idx_entity_mentions_document ON entity_mentions(document_id);
+
+-- Create consistency check tables, see models::consistency
+-- A report is filled in by a background check of every document of a project
+CREATE TABLE consistency_reports (
+    id SERIAL PRIMARY KEY,
+    project_id INT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'running', 'done', 'failed')),
+    use_ai BOOLEAN NOT NULL DEFAULT TRUE,
+    documents_total INT NOT NULL DEFAULT 0,
+    documents_checked INT NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    started_at TIMESTAMP,
+    finished_at TIMESTAMP
+);
+
+CREATE INDEX idx_consistency_reports_project ON consistency_reports(project_id, created_at DESC);
+-- One unfinished check per project
+CREATE UNIQUE INDEX idx_consistency_reports_active ON consistency_reports(project_id) WHERE status IN ('pending', 'running');
+
+-- Offsets are characters of the stored content of the document
+CREATE TABLE consistency_findings (
+    id SERIAL PRIMARY KEY,
+    report_id INT NOT NULL REFERENCES consistency_reports(id) ON DELETE CASCADE,
+    kind VARCHAR(30) NOT NULL CHECK (kind IN ('name_variant', 'knowledge_contradiction', 'tense_drift', 'pov_drift', 'glossary_violation', 'timeline_conflict')),
+    source VARCHAR(10) NOT NULL CHECK (source IN ('rule', 'ai')),
+    document_id INT REFERENCES documents(id) ON DELETE CASCADE,
+    start_offset INT,
+    end_offset INT,
+    excerpt TEXT,
+    message TEXT NOT NULL,
+    suggestion TEXT,
+    entity_id INT REFERENCES project_entities(id) ON DELETE SET NULL,
+    related_document_id INT REFERENCES documents(id) ON DELETE SET NULL,
+    related_offset INT
+);
+
+CREATE INDEX idx_consistency_findings_report ON consistency_findings(report_id);
+
+-- Create document tags tables, see models::tags
+-- Names are normalized (trimmed, lowercase, single spaces) and shared by every document
+CREATE TABLE tags (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(50) NOT NULL UNIQUE
+);
+
+-- Tags the users put on a document
+CREATE TABLE document_tags (
+    document_id INT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    tag_id INT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    added_by INT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (document_id, tag_id)
+);
+
+CREATE INDEX idx_document_tags_tag ON document_tags(tag_id);
+
+-- Tags and topics the AI suggests once a saved document is embedded, replaced by every new suggestion
+CREATE TABLE document_tag_suggestions (
+    document_id INT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    tag_id INT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    kind VARCHAR(10) NOT NULL CHECK (kind IN ('tag', 'topic')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (document_id, tag_id)
+);
+
+-- Text the current suggestions of a document were made from, unchanged text is not tagged again
+CREATE TABLE document_tagging_runs (
+    document_id INT PRIMARY KEY REFERENCES documents(id) ON DELETE CASCADE,
+    content_hash TEXT NOT NULL,
+    tagged_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create writing goals tables, see models::goals
+-- Net words of each save, per user, document and day. Not tied to the document row so that
+-- emptying the trash does not rewrite past days and break streaks.
+CREATE TABLE writing_activity (
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    document_id INT NOT NULL,
+    day DATE NOT NULL,
+    words_added INT NOT NULL DEFAULT 0,
+    words_removed INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, document_id, day)
+);
+
+CREATE INDEX idx_writing_activity_user_day ON writing_activity(user_id, day);
+
+-- Word-count goals, a total for a project by a deadline or a number of words every day
+CREATE TABLE writing_goals (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    project_id INT REFERENCES projects(id) ON DELETE CASCADE, -- NULL for daily goals counting every document
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('project', 'daily')),
+    target_words INT NOT NULL CHECK (target_words > 0),
+    start_date DATE NOT NULL DEFAULT CURRENT_DATE,
+    deadline DATE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK (kind = 'daily' OR project_id IS NOT NULL),
+    CHECK (deadline IS NULL OR deadline >= start_date)
+);
+
+CREATE INDEX idx_writing_goals_user ON writing_goals(user_id);
+
 -- Create document_permissions table for role-based access
 CREATE TABLE document_permissions (
     document_id INT REFERENCES documents(id) ON DELETE CASCADE,
@@ -116,6 +428,15 @@ CREATE TABLE user_profile_images (
     content_type VARCHAR(255) NOT NULL DEFAULT 'image/jpeg'
 );
 
+-- Create user dictionary table
+-- Words a user added to their custom dictionary, never reported by the offline spell check
+CREATE TABLE user_dictionary_words (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    word VARCHAR(64) NOT NULL, -- Stored lowercase
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, word)
+);
+
 -- Create tables for user preferences
 CREATE TABLE default_preferences (
     preference_id SERIAL PRIMARY KEY,
@@ -276,7 +597,7 @@ SELECT setval('commands_command_id_seq', (SELECT MAX(command_id) FROM commands))
 SELECT setval('documents_id_seq', (SELECT MAX(id) FROM documents));
 
 -- Create enum type for message roles
-CREATE TYPE message_role_enum AS ENUM ('user', 'assistant');
+CREATE TYPE message_role_enum AS ENUM ('system', 'user', 'assistant');
 
 -- Create tables for AI writing assistant functionality
 
@@ -287,24 +608,51 @@ CREATE TABLE IF NOT EXISTS writing_assistant_sessions (
     document_id INT REFERENCES documents(id) ON DELETE SET NULL,
     title VARCHAR(255) NOT NULL DEFAULT 'New Writing Session',
     created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    summary TEXT, -- Rolling summary of the messages up to summary_message_id, see rag::summary
+    summary_message_id INT,
+    summary_updated_at TIMESTAMP,
+    active_message_id INT, -- Last message of the branch the session is on
+    persona_id INT REFERENCES assistant_personas(id) ON DELETE SET NULL,
+    system_prompt TEXT, -- Custom system prompt, takes precedence over persona_id and the project's persona
+    auto_title BOOLEAN NOT NULL DEFAULT FALSE -- Title is generated from the first exchange
 );
 
 -- Writing assistant messages table
 CREATE TABLE IF NOT EXISTS writing_assistant_messages (
     id SERIAL PRIMARY KEY,
     session_id INT NOT NULL REFERENCES writing_assistant_sessions(id) ON DELETE CASCADE,
+    parent_message_id INT REFERENCES writing_assistant_messages(id) ON DELETE CASCADE, -- Edits and regenerations branch off the parent
     role message_role_enum NOT NULL,
     content TEXT NOT NULL,
     created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
-    embedding vector(1536)
+    embedding vector(1536),
+    citations JSONB NOT NULL DEFAULT '[]', -- Sources cited by assistant answers, see rag::citations
+    prompt_template VARCHAR(100) -- Template version that produced an assistant answer, e.g. chat@v1
 );
 
 -- Indexes for faster queries
 CREATE INDEX IF NOT EXISTS idx_writing_messages_session_id ON writing_assistant_messages(session_id);
+CREATE INDEX IF NOT EXISTS idx_writing_messages_parent_id ON writing_assistant_messages(parent_message_id);
 CREATE INDEX IF NOT EXISTS idx_writing_sessions_user_id ON writing_assistant_sessions(user_id);
 CREATE INDEX IF NOT EXISTS idx_writing_sessions_document_id ON writing_assistant_sessions(document_id);
 
+-- Create tool calls table
+-- Tools the assistant called in agent mode, see rag::agent. message_id is the answer they led to
+CREATE TABLE IF NOT EXISTS writing_assistant_tool_calls (
+    id SERIAL PRIMARY KEY,
+    session_id INT NOT NULL REFERENCES writing_assistant_sessions(id) ON DELETE CASCADE,
+    message_id INT REFERENCES writing_assistant_messages(id) ON DELETE CASCADE,
+    step INT NOT NULL,
+    tool VARCHAR(50) NOT NULL,
+    arguments JSONB NOT NULL DEFAULT '{}',
+    status VARCHAR(20) NOT NULL, -- ok, denied (no permission) or error
+    result_summary TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_writing_tool_calls_session_id ON writing_assistant_tool_calls(session_id, id);
+
 -- Set initial sequence values for writing assistant tables
 SELECT setval('writing_assistant_sessions_id_seq', 1, false);
 SELECT setval('writing_assistant_messages_id_seq', 1, false);
@@ -51,12 +51,36 @@ pub enum Error {
     EmbeddingError,
     APIKeyError,
     LlmQueryError,
+    InvalidLlmOutputError,
+    ProviderConfigError,
+    PromptTemplateError { template: String },
     InsufficientAiCredits,
     FailedApplyChanges,
+    MessageNotFoundError { message_id: i32 },
+    PersonaNotFoundError { persona_id: i32 },
     
     // Preference Errors
     PreferenceNotFoundError { preference_id: i32 },
     BackgroudImageError,
+
+    // Proofreading Errors
+    DictionaryWordNotFoundError { word: String },
+    ProofreadError,
+
+    // Goal Errors
+    GoalNotFoundError { goal_id: i32 },
+
+    // Translation Errors
+    GlossaryTermNotFoundError { term_id: i32 },
+    GlossaryTermExistsError { term: String },
+
+    // Knowledge Base Errors
+    EntityNotFoundError { entity_id: i32 },
+    EntityExistsError { name: String },
+
+    // Consistency Check Errors
+    ConsistencyReportNotFoundError { report_id: i32 },
+    ConsistencyCheckRunningError { project_id: i32 },
     
     // Limit Errors
     LimitExceededError { message: String }
@@ -108,6 +132,9 @@ impl Error {
             Self::APIKeyError => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR), // Could be config issue
             Self::EmbeddingError => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
             Self::LlmQueryError => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
+            Self::InvalidLlmOutputError => (StatusCode::BAD_GATEWAY, ClientError::SERVICE_ERROR),
+            Self::ProviderConfigError => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
+            Self::PromptTemplateError { .. } => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
 
             // Apply Suggestion Errors
             Self::FailedApplyChanges { .. } => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
@@ -115,6 +142,8 @@ impl Error {
             // Resource Errors (Could argue some are Forbidden/No_Auth if based on user context)
             Self::DocumentNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
             Self::ProjectNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
+            Self::MessageNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
+            Self::PersonaNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
             Self::DocumentCreationError => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
             Self::DocumentUpdateError { .. } => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
             Self::DocumentDeletionError { .. } => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
@@ -135,6 +164,25 @@ impl Error {
             // Preference Errors
             Self::PreferenceNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
 
+            // Proofreading Errors
+            Self::DictionaryWordNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
+            Self::ProofreadError => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
+
+            // Goal Errors
+            Self::GoalNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
+
+            // Translation Errors
+            Self::GlossaryTermNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
+            Self::GlossaryTermExistsError { .. } => (StatusCode::CONFLICT, ClientError::INVALID_PARAMS),
+
+            // Knowledge Base Errors
+            Self::EntityNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
+            Self::EntityExistsError { .. } => (StatusCode::CONFLICT, ClientError::INVALID_PARAMS),
+
+            // Consistency Check Errors
+            Self::ConsistencyReportNotFoundError { .. } => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
+            Self::ConsistencyCheckRunningError { .. } => (StatusCode::CONFLICT, ClientError::INVALID_PARAMS),
+
             // Limit Errors
             Self::LimitExceededError { .. } => (StatusCode::INTERNAL_SERVER_ERROR, ClientError::SERVICE_ERROR),
 
@@ -26,6 +26,65 @@ pub async fn test_reset_db(hc: &Client) -> Result<()> {
     Ok(())
 }
 
+/// Admin key of the server under test, read from ADMIN_SECRET_KEY like the server does
+pub fn admin_secret() -> String {
+    dotenvy::dotenv().ok();
+    std::env::var("ADMIN_SECRET_KEY").unwrap_or_default()
+}
+
+/// Waits until the background embedding worker has drained its queue
+pub async fn wait_for_embedding_queue(hc: &Client) -> Result<()> {
+    for _ in 0..60 {
+        let response = hc.do_get("/api/db/embeddings/jobs?secret=secret_key").await?;
+        let body = response.json_body()?;
+        let busy = body["result"]["jobs"]
+            .as_array()
+            .map(|jobs| {
+                jobs.iter().any(|job| {
+                    matches!(job["status"].as_str(), Some("pending") | Some("running"))
+                        && job["count"].as_i64().unwrap_or(0) > 0
+                })
+            })
+            .unwrap_or(false);
+
+        if !busy {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    }
+
+    Err(anyhow::anyhow!("Embedding queue did not drain in time"))
+}
+
+/// JSON body of a successful response, an error naming the action otherwise
+pub async fn expect_success(response: httpc_test::Response, action: &str) -> Result<serde_json::Value> {
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("{} failed with status: {}", action, response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+/// Id of the logged in user's newest credit ledger entry, 0 if there is none
+pub async fn last_ledger_id(hc: &Client) -> Result<i64> {
+    let history = hc.do_get("/api/users/credits?limit=1").await?.json_body()?;
+    Ok(history["entries"][0]["id"].as_i64().unwrap_or(0))
+}
+
+/// Number of charges for an AI operation in the logged in user's ledger after the entry `after_id`
+pub async fn credit_charges_since(hc: &Client, operation: &str, after_id: i64) -> Result<usize> {
+    let history = hc.do_get("/api/users/credits?limit=200").await?.json_body()?;
+    Ok(history["entries"]
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .filter(|entry| entry["id"].as_i64().unwrap_or(0) > after_id)
+                .filter(|entry| entry["operation"] == operation && entry["amount"].as_i64().unwrap_or(0) < 0)
+                .count()
+        })
+        .unwrap_or(0))
+}
+
 // Helper function to extract user ID from auth cookie
 pub fn get_user_id_from_cookie(cookies: &Cookies) -> Option<i32> {
     cookies.get("auth-token").and_then(|cookie| {
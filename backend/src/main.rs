@@ -6,6 +6,8 @@ mod error;
 mod models;
 mod web;
 mod rag;
+mod proofread;
+mod stats;
 mod log;
 
 use axum::middleware;
@@ -45,6 +47,38 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     */
     let pool = create_pool().await;
 
+    // Load the prompt templates, a broken template should stop the server before it takes requests
+    rag::templates::init().map_err(|e| format!("Invalid prompt templates:\n{}", e))?;
+
+    /*
+    / Admin commands
+    / `cargo run -- backfill-embeddings` queues every document without an embedding
+    / `cargo run -- backfill-embeddings --all` re-embeds every document (e.g. after changing the embedding model)
+    / The jobs are processed by the embedding worker of the running server
+    */
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("backfill-embeddings") {
+        let only_missing = !args.iter().any(|arg| arg == "--all");
+        let queued = rag::jobs::enqueue_backfill(&pool, only_missing)
+            .await
+            .map_err(|e| format!("Backfill failed: {:?}", e))?;
+        println!("Queued {} documents for embedding", queued);
+        return Ok(());
+    }
+
+    // Start the background worker that processes queued document embeddings
+    rag::jobs::spawn_embedding_worker(pool.clone());
+
+    // Consistency checks run inside the server, the ones a previous process left unfinished never complete
+    match models::consistency::ConsistencyManager::fail_interrupted(&pool).await {
+        Ok(0) => {}
+        Ok(failed) => println!("->> {:<12} - {} interrupted consistency checks failed", "CONSISTENCY", failed),
+        Err(e) => eprintln!("->> {:<12} - Failed to fail interrupted consistency checks: {:?}", "CONSISTENCY", e),
+    }
+
+    // Start the scheduled job that tops up AI credits to each plan's monthly allowance
+    models::plan::spawn_credit_refill_job(pool.clone());
+
     /*
     / Configure CORS
     / CORS is needed when a frontend (running on one domain or port)
@@ -76,6 +110,8 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let key_api_routes = web::routes::key_controller::key_routes();
     let writing_assistant_routes = web::routes::ai_controller::writing_assistant_routes();
     let pref_api_routes = web::routes::pref_controller::pref_routes();
+    let proofread_api_routes = web::routes::proofread_controller::proofread_routes();
+    let goal_api_routes = web::routes::goal_controller::goal_routes();
 
     let cookie_layer = CookieManagerLayer::new();
 
@@ -87,6 +123,8 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         .nest("/api/command", key_api_routes)
         .nest("/api/writing-assistant", writing_assistant_routes)
         .nest("/api/preference", pref_api_routes)
+        .nest("/api/proofread", proofread_api_routes)
+        .nest("/api/goals", goal_api_routes)
         .layer(Extension(pool.clone())) // Make the pool available to all handlers,Attachs the PgPool as an Axum Extension
         .layer(middleware::from_fn(mw_log_requests))
         .layer(cookie_layer)
@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 use chrono::NaiveDateTime;
+use sqlx::types::Json;
 
 #[derive(Debug, Serialize, Deserialize, Clone, sqlx::Type, PartialEq)]
 #[sqlx(type_name = "message_role_enum", rename_all = "lowercase")]
 pub enum MessageRole {
+    /// Instructions for the assistant (persona), never shown as part of the conversation
+    System,
     User,
     Assistant,
 }
@@ -16,15 +19,43 @@ pub struct WritingAssistantSession {
     pub title: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// Last message of the branch the session is on, new messages are added below it
+    pub active_message_id: Option<i32>,
+    /// Persona of the session, see models::persona for how the system prompt is chosen
+    pub persona_id: Option<i32>,
+    pub system_prompt: Option<String>,
+    /// The title is still to be generated from the first exchange
+    pub auto_title: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct WritingAssistantMessage {
     pub id: i32,
     pub session_id: i32,
+    /// Message this one answers or follows. Edited and regenerated messages share the parent
+    /// of the message they replace, which starts a new branch.
+    pub parent_message_id: Option<i32>,
     pub role: MessageRole,
     pub content: String,
     pub created_at: NaiveDateTime,
+    /// Sources cited by an assistant message, empty for user messages
+    pub citations: Json<Vec<Citation>>,
+    /// Prompt template version that produced an assistant message, e.g. "chat@v1"
+    pub prompt_template: Option<String>,
+}
+
+/// A source referenced by an assistant answer through a `[S1]` style marker in its content.
+/// Offsets are character offsets into the plain text of the document (see rag::chunk);
+/// they are empty when a whole document was used as context.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Citation {
+    pub label: String,
+    pub document_id: i32,
+    pub document_name: String,
+    pub chunk_id: Option<i32>,
+    pub heading: Option<String>,
+    pub start_offset: Option<i32>,
+    pub end_offset: Option<i32>,
 }
 
 pub struct SessionWithMessageContent {
@@ -40,7 +71,10 @@ pub struct SessionWithMessageContent {
 #[derive(Debug, Deserialize)]
 pub struct CreateSessionPayload {
     pub document_id: Option<i32>,
-    pub title: String,
+    /// Generated from the first exchange when missing
+    pub title: Option<String>,
+    pub persona_id: Option<i32>,
+    pub system_prompt: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -59,6 +93,24 @@ pub struct RewritePayload {
     pub style: String,
 }
 
+/// Payload for editing a user message and sending it again
+#[derive(Debug, Deserialize)]
+pub struct EditMessagePayload {
+    pub content: String,
+}
+
+/// One branch of a session's conversation, identified by its last message
+#[derive(Debug, Serialize)]
+pub struct ChatBranch {
+    pub leaf_message_id: i32,
+    /// First message of this branch that is not on the active branch, None for the active branch
+    pub fork_message_id: Option<i32>,
+    pub message_count: usize,
+    pub last_message_snippet: String,
+    pub updated_at: NaiveDateTime,
+    pub is_active: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct SessionWithMessages {
     pub session: WritingAssistantSession,
@@ -72,28 +124,54 @@ pub struct ChatMessage {
 }
 
 /// Represents a complete conversation history 
+/// Long sessions keep only their recent messages in `messages`, older ones are folded into
+/// `summary` (see rag::summary) and the ones relevant to the current query are `recalled`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatHistory {
     pub messages: Vec<ChatMessage>,
+    pub summary: Option<String>,
+    /// Summarized messages similar to the current query, oldest first
+    pub recalled: Vec<ChatMessage>,
+    /// Knowledge base of the projects of the session's document, see models::knowledge
+    pub knowledge: Option<String>,
 }
 
+/// System prompt of sessions without a persona, same as the built-in "Writing assistant" persona
+pub const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful writing assistant. Your goal is to help the user improve their writing, \
+                                         provide suggestions, and answer questions about their documents. Focus on being constructive \
+                                         and providing clear, actionable feedback that helps the user improve their writing.";
+
 impl ChatHistory {
-    /// Create a new chat history with a system prompt
+    /// Create a new chat history with the default system prompt
     pub fn new() -> Self {
-        let system_prompt = "You are a helpful writing assistant. Your goal is to help the user improve their writing, \
-                            provide suggestions, and answer questions about their documents. Focus on being constructive \
-                            and providing clear, actionable feedback that helps the user improve their writing.";
-        
+        Self::with_system_prompt(DEFAULT_SYSTEM_PROMPT.to_string())
+    }
+
+    /// Create a new chat history with a persona's system prompt
+    pub fn with_system_prompt(system_prompt: String) -> Self {
         Self {
             messages: vec![
                 ChatMessage {
-                    role: MessageRole::User,
-                    content: system_prompt.to_string(),
+                    role: MessageRole::System,
+                    content: system_prompt,
                 }
             ],
+            summary: None,
+            recalled: Vec::new(),
+            knowledge: None,
         }
     }
 
+    /// The system prompt(s) of the history, joined
+    pub fn system_prompt(&self) -> String {
+        self.messages
+            .iter()
+            .filter(|message| message.role == MessageRole::System)
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
     /// Add a user message to the chat history
     pub fn add_user_message(&mut self, content: String) {
         self.messages.push(ChatMessage {
@@ -135,12 +213,86 @@ pub struct SuggestedDocumentChange {
     pub document_id: i32,
     pub old_content: String,
     pub new_content: String,
+    /// Number of hunks applied to produce `new_content`
+    pub edit_count: usize,
 }
 
+/// One search/replace hunk proposed by the model.
+/// `search` is copied from the current document and must occur in it exactly once,
+/// an empty `search` appends `replace` to the end of the document.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmDocEdit {
+    pub document_id: i32,
+    pub search: String,
+    pub replace: String,
+}
+
+/// Structured answer of the apply-suggestion prompt, see rag::structured and rag::patch
 #[derive(Debug, Deserialize)]
-pub struct LlmDocChange {
+#[serde(deny_unknown_fields)]
+pub struct LlmDocEdits {
+    pub edits: Vec<LlmDocEdit>,
+}
+
+/// A proposed change that was not applied, with the reason
+#[derive(Debug, Serialize)]
+pub struct DocumentChangeFailure {
     pub document_id: i32,
-    pub new_content: String,
+    pub reason: String,
+}
+
+/// Result of applying a suggestion: usable changes and the documents that could not be changed
+#[derive(Debug, Serialize)]
+pub struct ApplySuggestionResult {
+    pub changes: Vec<SuggestedDocumentChange>,
+    pub failures: Vec<DocumentChangeFailure>,
+    /// IDs of the documents sent to the model. Large projects only send the ones relevant to the suggestion.
+    pub considered_document_ids: Vec<i32>,
+}
+
+/// One step of the agent loop as answered by the model, see rag::agent.
+/// `action` is a tool name or "answer"; only the arguments of the chosen tool are set.
+/// A flat object with nullable fields, so providers with strict JSON schemas accept it.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmAgentStep {
+    pub action: String,
+    pub query: Option<String>,
+    pub exact: Option<bool>,
+    pub document_id: Option<i32>,
+    pub offset: Option<i32>,
+    pub search: Option<String>,
+    pub replace: Option<String>,
+    pub answer: Option<String>,
+}
+
+/// A tool the assistant called in agent mode
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AgentToolCall {
+    pub id: i32,
+    pub session_id: i32,
+    /// Answer the call led to, None while the agent is still running or if it failed
+    pub message_id: Option<i32>,
+    pub step: i32,
+    pub tool: String,
+    pub arguments: Json<serde_json::Value>,
+    /// "ok", "denied" (the user cannot access the document) or "error"
+    pub status: String,
+    pub result_summary: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Response of an agent turn
+#[derive(Debug, Serialize)]
+pub struct AgentResponse {
+    pub message_id: i32,
+    pub role: String,
+    pub content: String,
+    pub tool_calls: Vec<AgentToolCall>,
+    /// Edits proposed with the propose_edit tool, not applied
+    pub proposed_changes: Vec<SuggestedDocumentChange>,
+    pub prompt_template: String,
 }
 
 #[derive(Serialize)]
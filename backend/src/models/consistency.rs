@@ -0,0 +1,508 @@
+// src/models/consistency.rs
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::rag::consistency::{CheckedDocument, TranslationSource};
+use crate::{Error, Result};
+
+/// Findings kept per report, a check stops recording beyond this
+pub const MAX_REPORT_FINDINGS: usize = 1000;
+/// Longest excerpt stored with a finding, in characters
+pub const MAX_EXCERPT_CHARS: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// A name one or two letters away from an entity or a name the project uses more often
+    NameVariant,
+    /// A fact that contradicts the knowledge base
+    KnowledgeContradiction,
+    /// Narration in another tense than the project
+    TenseDrift,
+    /// Narration from another point of view than the project
+    PovDrift,
+    /// A glossary term written differently, or broken by a translation
+    GlossaryViolation,
+    /// An event that does not fit the timeline of the earlier documents
+    TimelineConflict,
+}
+
+impl FindingKind {
+    pub const ALL: [FindingKind; 6] = [
+        FindingKind::NameVariant,
+        FindingKind::KnowledgeContradiction,
+        FindingKind::TenseDrift,
+        FindingKind::PovDrift,
+        FindingKind::GlossaryViolation,
+        FindingKind::TimelineConflict,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FindingKind::NameVariant => "name_variant",
+            FindingKind::KnowledgeContradiction => "knowledge_contradiction",
+            FindingKind::TenseDrift => "tense_drift",
+            FindingKind::PovDrift => "pov_drift",
+            FindingKind::GlossaryViolation => "glossary_violation",
+            FindingKind::TimelineConflict => "timeline_conflict",
+        }
+    }
+
+    fn from_db(kind: &str) -> Self {
+        FindingKind::ALL.into_iter().find(|known| known.as_str() == kind).unwrap_or(FindingKind::KnowledgeContradiction)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingSource {
+    /// Found by the rules of rag::consistency, without a model
+    Rule,
+    Ai,
+}
+
+impl FindingSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FindingSource::Rule => "rule",
+            FindingSource::Ai => "ai",
+        }
+    }
+
+    fn from_db(source: &str) -> Self {
+        match source {
+            "ai" => FindingSource::Ai,
+            _ => FindingSource::Rule,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    Pending,
+    Running,
+    Done,
+    /// Stopped by an error, the findings up to then are kept
+    Failed,
+}
+
+impl ReportStatus {
+    fn from_db(status: &str) -> Self {
+        match status {
+            "running" => ReportStatus::Running,
+            "done" => ReportStatus::Done,
+            "failed" => ReportStatus::Failed,
+            _ => ReportStatus::Pending,
+        }
+    }
+}
+
+/// A consistency check of a project and its progress
+#[derive(Debug, Serialize)]
+pub struct ConsistencyReport {
+    pub id: i32,
+    pub project_id: i32,
+    pub user_id: i32,
+    pub status: ReportStatus,
+    /// Whether the model checked the documents too, or only the rules
+    pub use_ai: bool,
+    pub documents_total: i32,
+    pub documents_checked: i32,
+    pub finding_count: i32,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+struct ReportRow {
+    id: i32,
+    project_id: i32,
+    user_id: i32,
+    status: String,
+    use_ai: bool,
+    documents_total: i32,
+    documents_checked: i32,
+    finding_count: i32,
+    error: Option<String>,
+    created_at: NaiveDateTime,
+    started_at: Option<NaiveDateTime>,
+    finished_at: Option<NaiveDateTime>,
+}
+
+impl From<ReportRow> for ConsistencyReport {
+    fn from(row: ReportRow) -> Self {
+        ConsistencyReport {
+            id: row.id,
+            project_id: row.project_id,
+            user_id: row.user_id,
+            status: ReportStatus::from_db(&row.status),
+            use_ai: row.use_ai,
+            documents_total: row.documents_total,
+            documents_checked: row.documents_checked,
+            finding_count: row.finding_count,
+            error: row.error,
+            created_at: row.created_at,
+            started_at: row.started_at,
+            finished_at: row.finished_at,
+        }
+    }
+}
+
+/// An inconsistency in a document. Offsets are characters of the stored content, `end_offset` is
+/// exclusive. They are missing when the finding concerns the whole document or the model quoted
+/// text that could not be found.
+#[derive(Debug, Serialize)]
+pub struct ConsistencyFinding {
+    pub id: i32,
+    pub kind: FindingKind,
+    pub source: FindingSource,
+    pub document_id: Option<i32>,
+    pub document_name: Option<String>,
+    pub start_offset: Option<i32>,
+    pub end_offset: Option<i32>,
+    pub excerpt: Option<String>,
+    pub message: String,
+    pub suggestion: Option<String>,
+    /// The knowledge base entity the finding is about
+    pub entity_id: Option<i32>,
+    /// The document the finding conflicts with, e.g. the earlier mention of an event
+    pub related_document_id: Option<i32>,
+    pub related_offset: Option<i32>,
+}
+
+struct FindingRow {
+    id: i32,
+    kind: String,
+    source: String,
+    document_id: Option<i32>,
+    document_name: Option<String>,
+    start_offset: Option<i32>,
+    end_offset: Option<i32>,
+    excerpt: Option<String>,
+    message: String,
+    suggestion: Option<String>,
+    entity_id: Option<i32>,
+    related_document_id: Option<i32>,
+    related_offset: Option<i32>,
+}
+
+impl From<FindingRow> for ConsistencyFinding {
+    fn from(row: FindingRow) -> Self {
+        ConsistencyFinding {
+            id: row.id,
+            kind: FindingKind::from_db(&row.kind),
+            source: FindingSource::from_db(&row.source),
+            document_id: row.document_id,
+            document_name: row.document_name,
+            start_offset: row.start_offset,
+            end_offset: row.end_offset,
+            excerpt: row.excerpt,
+            message: row.message,
+            suggestion: row.suggestion,
+            entity_id: row.entity_id,
+            related_document_id: row.related_document_id,
+            related_offset: row.related_offset,
+        }
+    }
+}
+
+/// A finding before it is stored
+#[derive(Debug, Clone)]
+pub struct NewFinding {
+    pub kind: FindingKind,
+    pub source: FindingSource,
+    pub document_id: Option<i32>,
+    pub start_offset: Option<i32>,
+    pub end_offset: Option<i32>,
+    pub excerpt: Option<String>,
+    pub message: String,
+    pub suggestion: Option<String>,
+    pub entity_id: Option<i32>,
+    pub related_document_id: Option<i32>,
+    pub related_offset: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsistencyReportDetail {
+    #[serde(flatten)]
+    pub report: ConsistencyReport,
+    pub findings: Vec<ConsistencyFinding>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartConsistencyCheckPayload {
+    /// Let the model look for contradictions and timeline conflicts too, true when missing.
+    /// Without it only the free rule-based checks run.
+    pub use_ai: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FindingListParams {
+    pub kind: Option<FindingKind>,
+    pub document_id: Option<i32>,
+}
+
+/// Structured answer of the consistency_check prompt, see rag::structured
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmConsistencyCheck {
+    pub findings: Vec<LlmFinding>,
+    /// Events of the document in story order, for the timeline of the later documents
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmFinding {
+    pub kind: FindingKind,
+    /// Exact text of the document the finding is about
+    pub quote: String,
+    pub explanation: String,
+    pub suggestion: Option<String>,
+    /// Name of the knowledge base entity concerned
+    pub entity: Option<String>,
+    /// Name of the earlier document the finding conflicts with
+    pub conflicts_with: Option<String>,
+}
+
+/// Consistency reports of projects. A report is created pending, filled in by the background
+/// check in ai_controller and kept until the project is deleted.
+pub struct ConsistencyManager;
+
+impl ConsistencyManager {
+    /// Creates a pending report, ConsistencyCheckRunningError while another check of the project is unfinished
+    pub async fn create(pool: &PgPool, project_id: i32, user_id: i32, use_ai: bool, documents_total: i32) -> Result<ConsistencyReport> {
+        let report_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO consistency_reports (project_id, user_id, use_ai, documents_total)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+            project_id,
+            user_id,
+            use_ai,
+            documents_total
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => Error::ConsistencyCheckRunningError { project_id },
+            _ => Error::DatabaseError,
+        })?;
+
+        Self::report(pool, project_id, report_id).await
+    }
+
+    /// Reports of a project, newest first
+    pub async fn list(pool: &PgPool, project_id: i32) -> Result<Vec<ConsistencyReport>> {
+        let rows = sqlx::query_as!(
+            ReportRow,
+            r#"
+            SELECT r.id, r.project_id, r.user_id, r.status, r.use_ai, r.documents_total, r.documents_checked,
+                   (SELECT COUNT(*) FROM consistency_findings f WHERE f.report_id = r.id)::INT AS "finding_count!",
+                   r.error, r.created_at, r.started_at, r.finished_at
+            FROM consistency_reports r
+            WHERE r.project_id = $1
+            ORDER BY r.created_at DESC, r.id DESC
+            "#,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(rows.into_iter().map(ConsistencyReport::from).collect())
+    }
+
+    async fn report(pool: &PgPool, project_id: i32, report_id: i32) -> Result<ConsistencyReport> {
+        let row = sqlx::query_as!(
+            ReportRow,
+            r#"
+            SELECT r.id, r.project_id, r.user_id, r.status, r.use_ai, r.documents_total, r.documents_checked,
+                   (SELECT COUNT(*) FROM consistency_findings f WHERE f.report_id = r.id)::INT AS "finding_count!",
+                   r.error, r.created_at, r.started_at, r.finished_at
+            FROM consistency_reports r
+            WHERE r.id = $1 AND r.project_id = $2
+            "#,
+            report_id,
+            project_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::ConsistencyReportNotFoundError { report_id })?;
+        Ok(row.into())
+    }
+
+    /// A report and its findings in the order the documents were checked, optionally of one kind or document
+    pub async fn get(pool: &PgPool, project_id: i32, report_id: i32, params: &FindingListParams) -> Result<ConsistencyReportDetail> {
+        let report = Self::report(pool, project_id, report_id).await?;
+        let rows = sqlx::query_as!(
+            FindingRow,
+            r#"
+            SELECT f.id, f.kind, f.source, f.document_id, d.name AS "document_name?", f.start_offset, f.end_offset,
+                   f.excerpt, f.message, f.suggestion, f.entity_id, f.related_document_id, f.related_offset
+            FROM consistency_findings f
+            LEFT JOIN documents d ON d.id = f.document_id
+            WHERE f.report_id = $1
+              AND ($2::VARCHAR IS NULL OR f.kind = $2)
+              AND ($3::INT IS NULL OR f.document_id = $3)
+            ORDER BY f.id ASC
+            "#,
+            report_id,
+            params.kind.map(|kind| kind.as_str()),
+            params.document_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        Ok(ConsistencyReportDetail {
+            report,
+            findings: rows.into_iter().map(ConsistencyFinding::from).collect(),
+        })
+    }
+
+    /// Documents of a project to check, oldest first, with the sources of translations
+    pub async fn documents(pool: &PgPool, project_id: i32) -> Result<Vec<CheckedDocument>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT d.id, d.name, d.content,
+                   t.source_document_id AS "source_id?", t.target_language AS "target_language?",
+                   s.name AS "source_name?", s.content AS "source_content?"
+            FROM documents d
+            JOIN document_projects dp ON dp.document_id = d.id
+            LEFT JOIN document_translations t ON t.document_id = d.id
+            LEFT JOIN documents s ON s.id = t.source_document_id
+            WHERE dp.project_id = $1 AND NOT COALESCE(d.is_trashed, FALSE)
+            ORDER BY d.created_at ASC, d.id ASC
+            "#,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| CheckedDocument {
+                id: row.id,
+                name: row.name,
+                content: row.content.unwrap_or_default(),
+                translation_of: match (row.source_id, row.target_language, row.source_name) {
+                    (Some(document_id), Some(target_language), Some(name)) => Some(TranslationSource {
+                        document_id,
+                        name,
+                        content: row.source_content.unwrap_or_default(),
+                        target_language,
+                    }),
+                    _ => None,
+                },
+            })
+            .collect())
+    }
+
+    pub async fn start(pool: &PgPool, report_id: i32) -> Result<()> {
+        sqlx::query!(
+            "UPDATE consistency_reports SET status = 'running', started_at = CURRENT_TIMESTAMP WHERE id = $1",
+            report_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(())
+    }
+
+    /// Stores the findings of one document and counts it as checked. Returns the findings stored,
+    /// fewer than given once the report holds MAX_REPORT_FINDINGS.
+    pub async fn record_document(pool: &PgPool, report_id: i32, findings: &[NewFinding]) -> Result<usize> {
+        let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+
+        let stored = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM consistency_findings WHERE report_id = $1"#,
+            report_id
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)? as usize;
+        let room = MAX_REPORT_FINDINGS.saturating_sub(stored);
+
+        for finding in findings.iter().take(room) {
+            sqlx::query!(
+                r#"
+                INSERT INTO consistency_findings
+                    (report_id, kind, source, document_id, start_offset, end_offset, excerpt, message, suggestion,
+                     entity_id, related_document_id, related_offset)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                "#,
+                report_id,
+                finding.kind.as_str(),
+                finding.source.as_str(),
+                finding.document_id,
+                finding.start_offset,
+                finding.end_offset,
+                finding.excerpt.as_deref().map(|excerpt| excerpt.chars().take(MAX_EXCERPT_CHARS).collect::<String>()),
+                finding.message,
+                finding.suggestion,
+                finding.entity_id,
+                finding.related_document_id,
+                finding.related_offset
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+        }
+
+        sqlx::query!(
+            "UPDATE consistency_reports SET documents_checked = documents_checked + 1 WHERE id = $1",
+            report_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        tx.commit().await.map_err(|_| Error::DatabaseError)?;
+        Ok(findings.len().min(room))
+    }
+
+    pub async fn finish(pool: &PgPool, report_id: i32) -> Result<()> {
+        sqlx::query!(
+            "UPDATE consistency_reports SET status = 'done', finished_at = CURRENT_TIMESTAMP WHERE id = $1",
+            report_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(())
+    }
+
+    pub async fn fail(pool: &PgPool, report_id: i32, error: &str) -> Result<()> {
+        sqlx::query!(
+            "UPDATE consistency_reports SET status = 'failed', error = $2, finished_at = CURRENT_TIMESTAMP WHERE id = $1",
+            report_id,
+            error
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(())
+    }
+
+    /// Fails the checks a previous process left unfinished, they will never complete.
+    /// Returns the number of reports failed.
+    pub async fn fail_interrupted(pool: &PgPool) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE consistency_reports
+            SET status = 'failed', error = 'Interrupted by a server restart', finished_at = CURRENT_TIMESTAMP
+            WHERE status IN ('pending', 'running')
+            "#
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(result.rows_affected())
+    }
+}
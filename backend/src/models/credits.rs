@@ -0,0 +1,291 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::env;
+
+use crate::{Error, Result};
+
+/// AI operations that cost credits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AiOperation {
+    Chat,
+    Grammar,
+    SpellCheck,
+    Summarize,
+    Rephrase,
+    Expand,
+    Shrink,
+    Rewrite,
+    FactCheck,
+    ApplySuggestion,
+    /// One step of the agent loop, see rag::agent
+    AgentStep,
+    /// One segment of a translation, see rag::translate
+    Translate,
+    /// Entities found in one document, see models::knowledge
+    ExtractEntities,
+    /// One document of a project consistency check, see rag::consistency
+    ConsistencyCheck,
+    /// One step of a fact check against the user's sources, see rag::fact_check
+    EvidenceCheck,
+    /// Folding old messages into a session's summary, see rag::summary
+    SessionSummary,
+    /// Deciding which extra context a chat message needs
+    ContextDecision,
+    /// Tags suggested for a saved document, charged to its owner, see rag::tagging
+    TagSuggestion,
+}
+
+impl AiOperation {
+    /// Name stored in the ledger
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AiOperation::Chat => "chat",
+            AiOperation::Grammar => "grammar",
+            AiOperation::SpellCheck => "spellcheck",
+            AiOperation::Summarize => "summarize",
+            AiOperation::Rephrase => "rephrase",
+            AiOperation::Expand => "expand",
+            AiOperation::Shrink => "shrink",
+            AiOperation::Rewrite => "rewrite",
+            AiOperation::FactCheck => "factcheck",
+            AiOperation::ApplySuggestion => "apply_suggestion",
+            AiOperation::AgentStep => "agent_step",
+            AiOperation::Translate => "translate",
+            AiOperation::ExtractEntities => "extract_entities",
+            AiOperation::ConsistencyCheck => "consistency_check",
+            AiOperation::EvidenceCheck => "evidence_check",
+            AiOperation::SessionSummary => "session_summary",
+            AiOperation::ContextDecision => "context_decision",
+            AiOperation::TagSuggestion => "tag_suggestion",
+        }
+    }
+}
+
+/// Price of one operation: a flat fee plus a share per 1000 prompt tokens
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct OperationPrice {
+    pub base: i32,
+    pub per_1k_tokens: i32,
+}
+
+impl OperationPrice {
+    /// Credits charged for a prompt of `prompt_tokens` tokens
+    pub fn cost(&self, prompt_tokens: usize) -> i32 {
+        let token_part = (prompt_tokens as i64 * self.per_1k_tokens as i64 / 1000) as i32;
+        self.base.saturating_add(token_part)
+    }
+}
+
+/// Credit pricing that can be adjusted based on environment.
+/// Each operation reads `AI_PRICE_<OPERATION>` as "base,per_1k_tokens", e.g. AI_PRICE_APPLY_SUGGESTION=2,1.
+/// Invalid or negative values fall back to the default price.
+#[derive(Debug, Clone)]
+pub struct CreditPricing;
+
+impl CreditPricing {
+    pub fn price(operation: AiOperation) -> OperationPrice {
+        // Default: 1 credit, plus 1 per 1000 prompt tokens (whole projects cost more than a sentence)
+        let default = match operation {
+            AiOperation::ApplySuggestion => OperationPrice { base: 2, per_1k_tokens: 1 },
+            _ => OperationPrice { base: 1, per_1k_tokens: 1 },
+        };
+
+        let variable = format!("AI_PRICE_{}", operation.as_str().to_uppercase());
+        env::var(&variable)
+            .ok()
+            .and_then(|value| {
+                let mut parts = value.split(',').map(|part| part.trim().parse::<i32>());
+                let price = match (parts.next(), parts.next()) {
+                    (Some(Ok(base)), Some(Ok(per_1k_tokens))) => Some(OperationPrice { base, per_1k_tokens }),
+                    (Some(Ok(base)), None) => Some(OperationPrice { base, per_1k_tokens: 0 }),
+                    _ => None,
+                };
+                // A negative price would add credits on every charge
+                match price {
+                    Some(price) if price.base >= 0 && price.per_1k_tokens >= 0 => Some(price),
+                    _ => {
+                        eprintln!("Ignoring invalid {}='{}', expected 'base,per_1k_tokens' of non-negative numbers", variable, value);
+                        None
+                    }
+                }
+            })
+            .unwrap_or(default)
+    }
+}
+
+/// A row of the credit ledger. Debits are negative, refunds and grants positive.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CreditLedgerEntry {
+    pub id: i32,
+    pub user_id: i32,
+    pub operation: String,
+    pub amount: i32,
+    pub balance_after: i32,
+    pub prompt_tokens: i32,
+    pub completion_tokens: Option<i32>,
+    pub refund_of: Option<i32>,
+    pub note: Option<String>,
+    /// Template version of the charged prompt, e.g. "grammar_check@v1"
+    pub prompt_template: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreditHistoryParams {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// A debit that has been taken, needed to record the result or refund it
+#[derive(Debug, Clone)]
+pub struct CreditCharge {
+    pub ledger_id: i32,
+    pub user_id: i32,
+    pub operation: AiOperation,
+    pub amount: i32,
+}
+
+pub struct CreditLedger;
+
+impl CreditLedger {
+    /// Fails with InsufficientAiCredits unless the user could pay the base price of the operation.
+    /// Used before doing paid preparation work (e.g. embedding the query) whose final cost is not known yet.
+    pub async fn ensure_balance(pool: &PgPool, user_id: i32, operation: AiOperation) -> Result<()> {
+        let balance = Self::balance(pool, user_id).await?;
+        if balance < CreditPricing::price(operation).base {
+            println!("->> {:<12} - User {} has insufficient AI credits ({}) for {}", "CREDITS", user_id, balance, operation.as_str());
+            return Err(Error::InsufficientAiCredits);
+        }
+        Ok(())
+    }
+
+    /// Current balance of a user
+    pub async fn balance(pool: &PgPool, user_id: i32) -> Result<i32> {
+        Ok(sqlx::query!("SELECT ai_credits FROM users WHERE id = $1", user_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?
+            .ok_or(Error::UserNotFoundError { user_id })?
+            .ai_credits)
+    }
+
+    /// Debits the price of an operation and records it in the ledger with the template version of its prompt.
+    /// The balance never goes negative, the charge fails instead.
+    pub async fn charge(pool: &PgPool, user_id: i32, operation: AiOperation, prompt_tokens: usize, prompt_template: &str) -> Result<CreditCharge> {
+        let amount = CreditPricing::price(operation).cost(prompt_tokens);
+        let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+
+        let updated = sqlx::query!(
+            "UPDATE users SET ai_credits = ai_credits - $2 WHERE id = $1 AND ai_credits >= $2 RETURNING ai_credits",
+            user_id,
+            amount
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        let balance_after = match updated {
+            Some(row) => row.ai_credits,
+            None => {
+                println!("->> {:<12} - User {} cannot afford {} credits for {}", "CREDITS", user_id, amount, operation.as_str());
+                return Err(Error::InsufficientAiCredits);
+            }
+        };
+
+        let entry = sqlx::query!(
+            r#"
+            INSERT INTO ai_credit_ledger (user_id, operation, amount, balance_after, prompt_tokens, prompt_template)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id
+            "#,
+            user_id,
+            operation.as_str(),
+            -amount,
+            balance_after,
+            prompt_tokens as i32,
+            prompt_template
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("DB Error recording credit charge: {:?}", e);
+            Error::DatabaseError
+        })?;
+
+        tx.commit().await.map_err(|_| Error::DatabaseError)?;
+
+        println!("->> {:<12} - Charged user {} {} credits for {} ({} prompt tokens). Remaining: {}", "CREDITS", user_id, amount, operation.as_str(), prompt_tokens, balance_after);
+        Ok(CreditCharge { ledger_id: entry.id, user_id, operation, amount })
+    }
+
+    /// Stores the size of the completion on the charge's ledger row
+    pub async fn record_completion(pool: &PgPool, charge: &CreditCharge, completion_tokens: usize) -> Result<()> {
+        sqlx::query!(
+            "UPDATE ai_credit_ledger SET completion_tokens = $2 WHERE id = $1",
+            charge.ledger_id,
+            completion_tokens as i32
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(())
+    }
+
+    /// Gives the credits of a charge back, e.g. when the LLM call failed
+    pub async fn refund(pool: &PgPool, charge: &CreditCharge, reason: &str) -> Result<()> {
+        let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+
+        let balance_after = sqlx::query!(
+            "UPDATE users SET ai_credits = ai_credits + $2 WHERE id = $1 RETURNING ai_credits",
+            charge.user_id,
+            charge.amount
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ai_credits;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO ai_credit_ledger (user_id, operation, amount, balance_after, refund_of, note)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            charge.user_id,
+            charge.operation.as_str(),
+            charge.amount,
+            balance_after,
+            charge.ledger_id,
+            reason
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        tx.commit().await.map_err(|_| Error::DatabaseError)?;
+
+        println!("->> {:<12} - Refunded user {} {} credits for {} ({})", "CREDITS", charge.user_id, charge.amount, charge.operation.as_str(), reason);
+        Ok(())
+    }
+
+    /// Ledger entries of a user, newest first
+    pub async fn history(pool: &PgPool, user_id: i32, limit: i64, offset: i64) -> Result<Vec<CreditLedgerEntry>> {
+        sqlx::query_as!(
+            CreditLedgerEntry,
+            r#"
+            SELECT id, user_id, operation, amount, balance_after, prompt_tokens,
+                   completion_tokens, refund_of, note, prompt_template, created_at
+            FROM ai_credit_ledger
+            WHERE user_id = $1
+            ORDER BY created_at DESC, id DESC
+            LIMIT $2 OFFSET $3
+            "#,
+            user_id,
+            limit,
+            offset
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+}
@@ -0,0 +1,103 @@
+// src/models/fact_check.rs
+use serde::{Deserialize, Serialize};
+
+use crate::models::ai::Citation;
+
+/// Claims checked per request, the model is asked for no more and the rest are left out
+pub const MAX_CLAIMS: usize = 12;
+/// Longest text checked per request, in characters
+pub const MAX_FACT_CHECK_CHARS: usize = 20000;
+
+#[derive(Debug, Deserialize)]
+pub struct EvidenceFactCheckPayload {
+    pub content: String,
+    /// Project whose documents are the evidence
+    pub project_id: Option<i32>,
+    /// Further documents the user can read, e.g. research notes outside the project
+    pub reference_document_ids: Option<Vec<i32>>,
+    /// The document the text comes from, left out of the evidence so it cannot support itself
+    pub document_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimVerdict {
+    /// A cited passage says the same
+    Supported,
+    /// A cited passage says otherwise
+    Contradicted,
+    /// The sources do not say
+    Unknown,
+}
+
+impl ClaimVerdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClaimVerdict::Supported => "supported",
+            ClaimVerdict::Contradicted => "contradicted",
+            ClaimVerdict::Unknown => "unknown",
+        }
+    }
+}
+
+/// A passage of the sources placed in the prompt.
+/// Offsets are character offsets into the plain text of the document (see rag::chunk).
+#[derive(Debug, Clone, Serialize)]
+pub struct EvidencePassage {
+    #[serde(flatten)]
+    pub citation: Citation,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckedClaim {
+    /// The claim as a statement of its own
+    pub claim: String,
+    /// Where the claim is made, as written in the checked text
+    pub quote: String,
+    /// Character offsets of the quote in the checked text, empty when it was not found
+    pub start_offset: Option<i32>,
+    pub end_offset: Option<i32>,
+    pub verdict: ClaimVerdict,
+    pub explanation: String,
+    /// The passages the verdict rests on, empty for unknown claims
+    pub citations: Vec<EvidencePassage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvidenceFactCheck {
+    pub claims: Vec<CheckedClaim>,
+    /// Every passage the claims were checked against, by label
+    pub sources: Vec<EvidencePassage>,
+}
+
+/// A claim the model found in the text
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmClaim {
+    pub claim: String,
+    pub quote: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmClaims {
+    pub claims: Vec<LlmClaim>,
+}
+
+/// The model's verdict on one claim, by its number in the prompt
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmClaimVerdict {
+    pub claim: usize,
+    pub verdict: ClaimVerdict,
+    pub explanation: String,
+    /// Labels of the passages the verdict rests on, e.g. "S2"
+    pub sources: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmClaimVerdicts {
+    pub verdicts: Vec<LlmClaimVerdict>,
+}
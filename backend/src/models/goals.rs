@@ -0,0 +1,467 @@
+// src/models/goals.rs
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+
+use crate::stats::word_count;
+use crate::{Error, Result};
+
+/// Days of activity returned when a request does not say
+pub const DEFAULT_ACTIVITY_DAYS: i32 = 30;
+/// Longest activity history returned
+pub const MAX_ACTIVITY_DAYS: i32 = 366;
+/// Largest word-count target of a goal
+pub const MAX_TARGET_WORDS: i32 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalKind {
+    /// Write `target_words` in a project between the start date and the deadline
+    Project,
+    /// Write `target_words` every day, in one project or anywhere
+    Daily,
+}
+
+impl GoalKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GoalKind::Project => "project",
+            GoalKind::Daily => "daily",
+        }
+    }
+
+    fn from_db(kind: &str) -> Self {
+        match kind {
+            "project" => GoalKind::Project,
+            _ => GoalKind::Daily,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WritingGoal {
+    pub id: i32,
+    pub project_id: Option<i32>,
+    pub kind: GoalKind,
+    pub target_words: i32,
+    pub start_date: NaiveDate,
+    pub deadline: Option<NaiveDate>,
+    pub created_at: NaiveDateTime,
+}
+
+struct GoalRow {
+    id: i32,
+    project_id: Option<i32>,
+    kind: String,
+    target_words: i32,
+    start_date: NaiveDate,
+    deadline: Option<NaiveDate>,
+    created_at: NaiveDateTime,
+}
+
+impl From<GoalRow> for WritingGoal {
+    fn from(row: GoalRow) -> Self {
+        WritingGoal {
+            id: row.id,
+            project_id: row.project_id,
+            kind: GoalKind::from_db(&row.kind),
+            target_words: row.target_words,
+            start_date: row.start_date,
+            deadline: row.deadline,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// A goal with the progress made towards it. Days are server dates, `today` included.
+#[derive(Debug, Serialize)]
+pub struct GoalProgress {
+    #[serde(flatten)]
+    pub goal: WritingGoal,
+    pub today: NaiveDate,
+    pub words_today: i32,
+    /// Net words since the start date for project goals, today for daily goals
+    pub words_written: i32,
+    pub words_remaining: i32,
+    pub percent_complete: f64,
+    pub completed: bool,
+    /// Day the target was first reached
+    pub completed_on: Option<NaiveDate>,
+    /// Words a day that make a streak day: the target of daily goals, the even pace to the deadline of
+    /// project goals, any words at all for project goals without a deadline
+    pub daily_target: Option<i32>,
+    /// Words a day still needed to reach the target by the deadline
+    pub words_needed_per_day: Option<i32>,
+    pub days_elapsed: i32,
+    /// Days left until the deadline, today included
+    pub days_remaining: Option<i32>,
+    pub average_words_per_day: f64,
+    /// Consecutive days the daily target was met, up to today or yesterday while today is in progress
+    pub current_streak: i32,
+    pub longest_streak: i32,
+    /// Day the target is reached at the average pace so far
+    pub projected_completion_date: Option<NaiveDate>,
+    /// Whether the projected completion date is on or before the deadline
+    pub on_track: Option<bool>,
+}
+
+/// Words a user wrote on one day. A save counts the difference of the word counts before and
+/// after it, so rewriting a sentence with as many words is not counted.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyWords {
+    pub day: NaiveDate,
+    pub words_added: i32,
+    pub words_removed: i32,
+    pub net_words: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateGoalPayload {
+    pub kind: GoalKind,
+    /// Required for project goals, limits a daily goal to the documents of the project
+    pub project_id: Option<i32>,
+    pub target_words: i32,
+    /// Defaults to today
+    pub start_date: Option<NaiveDate>,
+    pub deadline: Option<NaiveDate>,
+}
+
+/// Replaces the target and the deadline of a goal, a missing deadline removes it.
+/// A missing start date keeps the current one.
+#[derive(Debug, Deserialize)]
+pub struct UpdateGoalPayload {
+    pub target_words: i32,
+    pub start_date: Option<NaiveDate>,
+    pub deadline: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GoalListParams {
+    pub project_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityParams {
+    pub days: Option<i32>,
+    pub project_id: Option<i32>,
+}
+
+impl ActivityParams {
+    pub fn days(&self) -> i32 {
+        self.days.unwrap_or(DEFAULT_ACTIVITY_DAYS).clamp(1, MAX_ACTIVITY_DAYS)
+    }
+}
+
+/// Net words written per user, document and day, recorded on every save
+pub struct WritingActivity;
+
+impl WritingActivity {
+    /// Records the words a save of `document_id` by `user_id` added or removed today
+    pub async fn record(pool: &PgPool, user_id: i32, document_id: i32, old_content: &str, new_content: &str) -> Result<()> {
+        let net_words = word_count(new_content) - word_count(old_content);
+        if net_words == 0 {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO writing_activity (user_id, document_id, day, words_added, words_removed)
+            VALUES ($1, $2, CURRENT_DATE, $3, $4)
+            ON CONFLICT (user_id, document_id, day) DO UPDATE SET
+                words_added = writing_activity.words_added + EXCLUDED.words_added,
+                words_removed = writing_activity.words_removed + EXCLUDED.words_removed
+            "#,
+            user_id,
+            document_id,
+            net_words.max(0),
+            (-net_words).max(0)
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        Ok(())
+    }
+
+    /// Words of each day with activity since `since`, optionally in the documents of a project, oldest first
+    pub async fn daily(pool: &PgPool, user_id: i32, project_id: Option<i32>, since: NaiveDate) -> Result<Vec<DailyWords>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT a.day, SUM(a.words_added)::INT AS "words_added!", SUM(a.words_removed)::INT AS "words_removed!"
+            FROM writing_activity a
+            WHERE a.user_id = $1 AND a.day >= $2
+              AND ($3::INT IS NULL OR a.document_id IN (SELECT document_id FROM document_projects WHERE project_id = $3))
+            GROUP BY a.day
+            ORDER BY a.day ASC
+            "#,
+            user_id,
+            since,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DailyWords {
+                day: row.day,
+                words_added: row.words_added,
+                words_removed: row.words_removed,
+                net_words: row.words_added - row.words_removed,
+            })
+            .collect())
+    }
+
+    /// Words of each day with activity over the last `days` days, today included
+    pub async fn history(pool: &PgPool, user_id: i32, project_id: Option<i32>, days: i32) -> Result<Vec<DailyWords>> {
+        let today = today(pool).await?;
+        Self::daily(pool, user_id, project_id, today - Duration::days(days as i64 - 1)).await
+    }
+}
+
+/// Current date of the database, the day saves are recorded on
+async fn today(pool: &PgPool) -> Result<NaiveDate> {
+    sqlx::query_scalar!(r#"SELECT CURRENT_DATE AS "today!""#)
+        .fetch_one(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+}
+
+/// Word-count goals of users. Progress is computed from the writing activity on every read.
+pub struct GoalManager;
+
+impl GoalManager {
+    pub async fn list(pool: &PgPool, user_id: i32, project_id: Option<i32>) -> Result<Vec<GoalProgress>> {
+        let rows = sqlx::query_as!(
+            GoalRow,
+            r#"
+            SELECT id, project_id, kind, target_words, start_date, deadline, created_at
+            FROM writing_goals
+            WHERE user_id = $1 AND ($2::INT IS NULL OR project_id = $2)
+            ORDER BY created_at ASC, id ASC
+            "#,
+            user_id,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        let mut goals = Vec::with_capacity(rows.len());
+        for row in rows {
+            goals.push(Self::progress(pool, user_id, row.into()).await?);
+        }
+        Ok(goals)
+    }
+
+    pub async fn get(pool: &PgPool, user_id: i32, goal_id: i32) -> Result<GoalProgress> {
+        let goal = Self::find(pool, user_id, goal_id).await?;
+        Self::progress(pool, user_id, goal).await
+    }
+
+    /// Creates a goal, the caller checks the permission on the project
+    pub async fn create(pool: &PgPool, user_id: i32, payload: &CreateGoalPayload) -> Result<GoalProgress> {
+        if payload.kind == GoalKind::Project && payload.project_id.is_none() {
+            return Err(Error::InvalidRequestFormatError);
+        }
+        let start_date = match payload.start_date {
+            Some(start_date) => start_date,
+            None => today(pool).await?,
+        };
+        validate_goal(payload.target_words, start_date, payload.deadline)?;
+
+        let goal = sqlx::query_as!(
+            GoalRow,
+            r#"
+            INSERT INTO writing_goals (user_id, project_id, kind, target_words, start_date, deadline)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, project_id, kind, target_words, start_date, deadline, created_at
+            "#,
+            user_id,
+            payload.project_id,
+            payload.kind.as_str(),
+            payload.target_words,
+            start_date,
+            payload.deadline
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        Self::progress(pool, user_id, goal.into()).await
+    }
+
+    pub async fn update(pool: &PgPool, user_id: i32, goal_id: i32, payload: &UpdateGoalPayload) -> Result<GoalProgress> {
+        let current = Self::find(pool, user_id, goal_id).await?;
+        let start_date = payload.start_date.unwrap_or(current.start_date);
+        validate_goal(payload.target_words, start_date, payload.deadline)?;
+
+        let goal = sqlx::query_as!(
+            GoalRow,
+            r#"
+            UPDATE writing_goals
+            SET target_words = $1, start_date = $2, deadline = $3
+            WHERE id = $4 AND user_id = $5
+            RETURNING id, project_id, kind, target_words, start_date, deadline, created_at
+            "#,
+            payload.target_words,
+            start_date,
+            payload.deadline,
+            goal_id,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::GoalNotFoundError { goal_id })?;
+
+        Self::progress(pool, user_id, goal.into()).await
+    }
+
+    pub async fn delete(pool: &PgPool, user_id: i32, goal_id: i32) -> Result<()> {
+        let deleted = sqlx::query!("DELETE FROM writing_goals WHERE id = $1 AND user_id = $2", goal_id, user_id)
+            .execute(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::GoalNotFoundError { goal_id });
+        }
+        Ok(())
+    }
+
+    // Goals of other users are not found
+    async fn find(pool: &PgPool, user_id: i32, goal_id: i32) -> Result<WritingGoal> {
+        sqlx::query_as!(
+            GoalRow,
+            r#"
+            SELECT id, project_id, kind, target_words, start_date, deadline, created_at
+            FROM writing_goals
+            WHERE id = $1 AND user_id = $2
+            "#,
+            goal_id,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .map(WritingGoal::from)
+        .ok_or(Error::GoalNotFoundError { goal_id })
+    }
+
+    async fn progress(pool: &PgPool, user_id: i32, goal: WritingGoal) -> Result<GoalProgress> {
+        let today = today(pool).await?;
+        let daily = WritingActivity::daily(pool, user_id, goal.project_id, goal.start_date)
+            .await?
+            .into_iter()
+            .map(|day| (day.day, day.net_words))
+            .collect();
+        Ok(compute_progress(goal, &daily, today))
+    }
+}
+
+fn validate_goal(target_words: i32, start_date: NaiveDate, deadline: Option<NaiveDate>) -> Result<()> {
+    if !(1..=MAX_TARGET_WORDS).contains(&target_words) || deadline.is_some_and(|deadline| deadline < start_date) {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    Ok(())
+}
+
+/// Progress of a goal from the net words of each day, days without activity are missing from `daily`
+pub fn compute_progress(goal: WritingGoal, daily: &BTreeMap<NaiveDate, i32>, today: NaiveDate) -> GoalProgress {
+    let end = goal.deadline.map_or(today, |deadline| deadline.min(today));
+    let period: Vec<(NaiveDate, i32)> = goal
+        .start_date
+        .iter_days()
+        .take_while(|day| *day <= end)
+        .map(|day| (day, daily.get(&day).copied().unwrap_or(0)))
+        .collect();
+
+    let target = goal.target_words;
+    let words_today = daily.get(&today).copied().unwrap_or(0);
+    let total: i32 = period.iter().map(|(_, words)| words).sum();
+    let days_elapsed = period.len() as i32;
+
+    let daily_target = match goal.kind {
+        GoalKind::Daily => Some(target),
+        GoalKind::Project => goal
+            .deadline
+            .map(|deadline| div_ceil(target, ((deadline - goal.start_date).num_days() + 1) as i32)),
+    };
+
+    // Streaks, today only breaks the current streak once it is over
+    let threshold = daily_target.unwrap_or(1);
+    let mut longest_streak = 0;
+    let mut run = 0;
+    for (_, words) in &period {
+        run = if *words >= threshold { run + 1 } else { 0 };
+        longest_streak = longest_streak.max(run);
+    }
+    let current_streak = period
+        .iter()
+        .rev()
+        .skip_while(|(day, words)| *day == today && *words < threshold)
+        .take_while(|(_, words)| *words >= threshold)
+        .count() as i32;
+
+    let words_written = match goal.kind {
+        GoalKind::Project => total,
+        GoalKind::Daily => words_today,
+    };
+    let words_remaining = (target - words_written).max(0);
+    let completed = words_written >= target;
+    let completed_on = match goal.kind {
+        GoalKind::Daily => completed.then_some(today),
+        GoalKind::Project if completed => {
+            let mut cumulative = 0;
+            period.iter().find_map(|(day, words)| {
+                cumulative += words;
+                (cumulative >= target).then_some(*day)
+            })
+        }
+        GoalKind::Project => None,
+    };
+
+    let days_remaining = goal.deadline.map(|deadline| ((deadline - today).num_days() + 1).max(0) as i32);
+    let words_needed_per_day = match (goal.kind, days_remaining) {
+        (GoalKind::Project, Some(days)) if !completed && days > 0 => Some(div_ceil(words_remaining, days)),
+        _ => None,
+    };
+    let average = if days_elapsed > 0 { total as f64 / days_elapsed as f64 } else { 0.0 };
+    let projected_completion_date = match goal.kind {
+        GoalKind::Project if completed => completed_on,
+        GoalKind::Project if average > 0.0 => Some(today + Duration::days((words_remaining as f64 / average).ceil() as i64)),
+        _ => None,
+    };
+    let on_track = match (goal.kind, goal.deadline) {
+        (GoalKind::Project, Some(deadline)) => Some(completed || projected_completion_date.is_some_and(|date| date <= deadline)),
+        _ => None,
+    };
+
+    GoalProgress {
+        today,
+        words_today,
+        words_written,
+        words_remaining,
+        percent_complete: ((words_written.max(0) as f64 / target as f64) * 10000.0).round() / 100.0,
+        completed,
+        completed_on,
+        daily_target,
+        words_needed_per_day,
+        days_elapsed,
+        days_remaining,
+        average_words_per_day: (average * 100.0).round() / 100.0,
+        current_streak,
+        longest_streak,
+        projected_completion_date,
+        on_track,
+        goal,
+    }
+}
+
+fn div_ceil(words: i32, days: i32) -> i32 {
+    let days = days.max(1);
+    (words + days - 1) / days
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EmbeddingJob {
+    pub id: i32,
+    pub document_id: i32,
+    pub status: String,
+    pub attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddingJobStatusCount {
+    pub status: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackfillParams {
+    pub secret: Option<String>,
+    /// "missing" (default) only queues documents without an embedding, "all" re-embeds everything
+    pub mode: Option<String>,
+}
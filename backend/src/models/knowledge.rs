@@ -0,0 +1,818 @@
+// src/models/knowledge.rs
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+use crate::{Error, Result};
+
+/// Longest entity name or alias, in characters
+pub const MAX_ENTITY_NAME_CHARS: usize = 200;
+/// Most aliases of one entity
+pub const MAX_ENTITY_ALIASES: usize = 20;
+/// Longest description or notes of an entity, in characters
+pub const MAX_ENTITY_TEXT_CHARS: usize = 4000;
+/// Longest style guide of a project, in characters
+pub const MAX_STYLE_GUIDE_CHARS: usize = 20000;
+/// Offsets kept per entity and document, the count covers all mentions
+const MAX_STORED_OFFSETS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Character,
+    Place,
+    Organization,
+    Item,
+    Term,
+    Other,
+}
+
+impl EntityKind {
+    /// Order of the sections in the assistant's context
+    pub const ALL: [EntityKind; 6] = [
+        EntityKind::Character,
+        EntityKind::Place,
+        EntityKind::Organization,
+        EntityKind::Item,
+        EntityKind::Term,
+        EntityKind::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntityKind::Character => "character",
+            EntityKind::Place => "place",
+            EntityKind::Organization => "organization",
+            EntityKind::Item => "item",
+            EntityKind::Term => "term",
+            EntityKind::Other => "other",
+        }
+    }
+
+    fn heading(&self) -> &'static str {
+        match self {
+            EntityKind::Character => "Characters",
+            EntityKind::Place => "Places",
+            EntityKind::Organization => "Organizations",
+            EntityKind::Item => "Items",
+            EntityKind::Term => "Terms",
+            EntityKind::Other => "Other",
+        }
+    }
+
+    fn from_db(kind: &str) -> Self {
+        EntityKind::ALL.into_iter().find(|known| known.as_str() == kind).unwrap_or(EntityKind::Other)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntitySource {
+    Manual,
+    /// Found by the model in the project's documents, becomes manual once edited
+    Extracted,
+}
+
+impl EntitySource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EntitySource::Manual => "manual",
+            EntitySource::Extracted => "extracted",
+        }
+    }
+
+    fn from_db(source: &str) -> Self {
+        match source {
+            "extracted" => EntitySource::Extracted,
+            _ => EntitySource::Manual,
+        }
+    }
+}
+
+/// A character, place or term of a project's knowledge base
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectEntity {
+    pub id: i32,
+    pub project_id: i32,
+    pub kind: EntityKind,
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
+    pub notes: Option<String>,
+    pub source: EntitySource,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// Documents of the project that mention the entity by name or alias
+    pub document_count: i32,
+    pub mention_count: i32,
+}
+
+struct EntityRow {
+    id: i32,
+    project_id: i32,
+    kind: String,
+    name: String,
+    aliases: Vec<String>,
+    description: Option<String>,
+    notes: Option<String>,
+    source: String,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    document_count: i32,
+    mention_count: i32,
+}
+
+impl From<EntityRow> for ProjectEntity {
+    fn from(row: EntityRow) -> Self {
+        ProjectEntity {
+            id: row.id,
+            project_id: row.project_id,
+            kind: EntityKind::from_db(&row.kind),
+            name: row.name,
+            aliases: row.aliases,
+            description: row.description,
+            notes: row.notes,
+            source: EntitySource::from_db(&row.source),
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            document_count: row.document_count,
+            mention_count: row.mention_count,
+        }
+    }
+}
+
+/// Where a document mentions an entity, offsets are characters of the stored content
+#[derive(Debug, Serialize)]
+pub struct EntityMention {
+    pub document_id: i32,
+    pub document_name: String,
+    pub mention_count: i32,
+    /// The first mentions, at most 100
+    pub offsets: Vec<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityDetail {
+    #[serde(flatten)]
+    pub entity: ProjectEntity,
+    pub mentions: Vec<EntityMention>,
+}
+
+/// An entity mentioned in a document
+#[derive(Debug, Serialize)]
+pub struct DocumentEntity {
+    pub entity_id: i32,
+    pub project_id: i32,
+    pub kind: EntityKind,
+    pub name: String,
+    pub mention_count: i32,
+    pub offsets: Vec<i32>,
+}
+
+struct DocumentEntityRow {
+    entity_id: i32,
+    project_id: i32,
+    kind: String,
+    name: String,
+    mention_count: i32,
+    offsets: Vec<i32>,
+}
+
+/// Style guide and entities of a project
+#[derive(Debug, Serialize)]
+pub struct KnowledgeBase {
+    pub project_id: i32,
+    pub style_guide: Option<String>,
+    pub entities: Vec<ProjectEntity>,
+}
+
+/// Creates or replaces an entity
+#[derive(Debug, Deserialize)]
+pub struct EntityPayload {
+    pub kind: EntityKind,
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EntityListParams {
+    pub kind: Option<EntityKind>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StyleGuidePayload {
+    /// Removed when null or empty
+    pub style_guide: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExtractEntitiesPayload {
+    /// Documents of the project to analyze, all of them when missing
+    pub document_ids: Option<Vec<i32>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtractionResult {
+    pub project_id: i32,
+    pub documents: Vec<i32>,
+    pub created: Vec<ProjectEntity>,
+    /// Existing entities that got new aliases or a description
+    pub updated: Vec<ProjectEntity>,
+}
+
+/// Structured answer of the knowledge_extract prompt, see rag::structured
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmExtractedEntities {
+    pub entities: Vec<LlmEntity>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmEntity {
+    pub name: String,
+    pub kind: EntityKind,
+    pub aliases: Vec<String>,
+    pub description: String,
+}
+
+/// Knowledge bases of projects: entities, the documents mentioning them and the style guide
+pub struct KnowledgeManager;
+
+impl KnowledgeManager {
+    pub async fn knowledge_base(pool: &PgPool, project_id: i32) -> Result<KnowledgeBase> {
+        Ok(KnowledgeBase {
+            project_id,
+            style_guide: Self::style_guide(pool, project_id).await?,
+            entities: Self::list(pool, project_id, None).await?,
+        })
+    }
+
+    /// Entities of a project by kind and name, with the documents mentioning them counted
+    pub async fn list(pool: &PgPool, project_id: i32, kind: Option<EntityKind>) -> Result<Vec<ProjectEntity>> {
+        let rows = sqlx::query_as!(
+            EntityRow,
+            r#"
+            SELECT e.id, e.project_id, e.kind, e.name, e.aliases, e.description, e.notes, e.source, e.created_at, e.updated_at,
+                   COALESCE(c.documents, 0)::INT AS "document_count!", COALESCE(c.mentions, 0)::INT AS "mention_count!"
+            FROM project_entities e
+            LEFT JOIN LATERAL (
+                SELECT COUNT(*) AS documents, SUM(m.mention_count) AS mentions
+                FROM entity_mentions m
+                JOIN documents d ON d.id = m.document_id
+                JOIN document_projects dp ON dp.document_id = m.document_id AND dp.project_id = e.project_id
+                WHERE m.entity_id = e.id AND NOT COALESCE(d.is_trashed, FALSE)
+            ) c ON TRUE
+            WHERE e.project_id = $1 AND ($2::VARCHAR IS NULL OR e.kind = $2)
+            ORDER BY e.kind ASC, LOWER(e.name) ASC
+            "#,
+            project_id,
+            kind.map(|kind| kind.as_str())
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(rows.into_iter().map(ProjectEntity::from).collect())
+    }
+
+    async fn entity(pool: &PgPool, project_id: i32, entity_id: i32) -> Result<ProjectEntity> {
+        let row = sqlx::query_as!(
+            EntityRow,
+            r#"
+            SELECT e.id, e.project_id, e.kind, e.name, e.aliases, e.description, e.notes, e.source, e.created_at, e.updated_at,
+                   COALESCE(c.documents, 0)::INT AS "document_count!", COALESCE(c.mentions, 0)::INT AS "mention_count!"
+            FROM project_entities e
+            LEFT JOIN LATERAL (
+                SELECT COUNT(*) AS documents, SUM(m.mention_count) AS mentions
+                FROM entity_mentions m
+                JOIN documents d ON d.id = m.document_id
+                JOIN document_projects dp ON dp.document_id = m.document_id AND dp.project_id = e.project_id
+                WHERE m.entity_id = e.id AND NOT COALESCE(d.is_trashed, FALSE)
+            ) c ON TRUE
+            WHERE e.id = $1 AND e.project_id = $2
+            "#,
+            entity_id,
+            project_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::EntityNotFoundError { entity_id })?;
+        Ok(row.into())
+    }
+
+    /// An entity and the documents of the project that mention it, most mentions first
+    pub async fn get(pool: &PgPool, project_id: i32, entity_id: i32) -> Result<EntityDetail> {
+        let entity = Self::entity(pool, project_id, entity_id).await?;
+        let mentions = sqlx::query_as!(
+            EntityMention,
+            r#"
+            SELECT m.document_id, d.name AS document_name, m.mention_count, m.offsets
+            FROM entity_mentions m
+            JOIN documents d ON d.id = m.document_id
+            JOIN document_projects dp ON dp.document_id = m.document_id AND dp.project_id = $2
+            WHERE m.entity_id = $1 AND NOT COALESCE(d.is_trashed, FALSE)
+            ORDER BY m.mention_count DESC, d.name ASC
+            "#,
+            entity_id,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(EntityDetail { entity, mentions })
+    }
+
+    pub async fn create(pool: &PgPool, project_id: i32, payload: &EntityPayload) -> Result<ProjectEntity> {
+        let entity = validate_entity(payload)?;
+        let entity_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO project_entities (project_id, kind, name, aliases, description, notes, source)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+            project_id,
+            payload.kind.as_str(),
+            entity.name,
+            &entity.aliases,
+            entity.description,
+            entity.notes,
+            EntitySource::Manual.as_str()
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| entity_write_error(e, &entity.name))?;
+
+        Self::refresh_entity_mentions(pool, entity_id).await?;
+        Self::entity(pool, project_id, entity_id).await
+    }
+
+    /// Replaces an entity, extracted entities become manual
+    pub async fn update(pool: &PgPool, project_id: i32, entity_id: i32, payload: &EntityPayload) -> Result<ProjectEntity> {
+        let entity = validate_entity(payload)?;
+        sqlx::query_scalar!(
+            r#"
+            UPDATE project_entities
+            SET kind = $3, name = $4, aliases = $5, description = $6, notes = $7, source = $8, updated_at = CURRENT_TIMESTAMP
+            WHERE id = $1 AND project_id = $2
+            RETURNING id
+            "#,
+            entity_id,
+            project_id,
+            payload.kind.as_str(),
+            entity.name,
+            &entity.aliases,
+            entity.description,
+            entity.notes,
+            EntitySource::Manual.as_str()
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| entity_write_error(e, &entity.name))?
+        .ok_or(Error::EntityNotFoundError { entity_id })?;
+
+        Self::refresh_entity_mentions(pool, entity_id).await?;
+        Self::entity(pool, project_id, entity_id).await
+    }
+
+    pub async fn delete(pool: &PgPool, project_id: i32, entity_id: i32) -> Result<()> {
+        let deleted = sqlx::query!(
+            "DELETE FROM project_entities WHERE id = $1 AND project_id = $2",
+            entity_id,
+            project_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::EntityNotFoundError { entity_id });
+        }
+        Ok(())
+    }
+
+    pub async fn style_guide(pool: &PgPool, project_id: i32) -> Result<Option<String>> {
+        sqlx::query_scalar!("SELECT style_guide FROM projects WHERE id = $1", project_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?
+            .ok_or(Error::ProjectNotFoundError { project_id })
+    }
+
+    pub async fn set_style_guide(pool: &PgPool, project_id: i32, style_guide: Option<String>) -> Result<Option<String>> {
+        let style_guide = style_guide.map(|guide| guide.trim().to_string()).filter(|guide| !guide.is_empty());
+        if style_guide.as_deref().is_some_and(|guide| guide.chars().count() > MAX_STYLE_GUIDE_CHARS) {
+            return Err(Error::InvalidRequestFormatError);
+        }
+        sqlx::query_scalar!(
+            "UPDATE projects SET style_guide = $2, updated_at = CURRENT_TIMESTAMP WHERE id = $1 RETURNING style_guide",
+            project_id,
+            style_guide
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::ProjectNotFoundError { project_id })
+    }
+
+    /// Entities of the projects of a document that it mentions
+    pub async fn for_document(pool: &PgPool, document_id: i32) -> Result<Vec<DocumentEntity>> {
+        let rows = sqlx::query_as!(
+            DocumentEntityRow,
+            r#"
+            SELECT e.id AS entity_id, e.project_id, e.kind, e.name, m.mention_count, m.offsets
+            FROM entity_mentions m
+            JOIN project_entities e ON e.id = m.entity_id
+            JOIN document_projects dp ON dp.document_id = m.document_id AND dp.project_id = e.project_id
+            WHERE m.document_id = $1
+            ORDER BY m.offsets[1] ASC NULLS LAST, LOWER(e.name) ASC
+            "#,
+            document_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DocumentEntity {
+                entity_id: row.entity_id,
+                project_id: row.project_id,
+                kind: EntityKind::from_db(&row.kind),
+                name: row.name,
+                mention_count: row.mention_count,
+                offsets: row.offsets,
+            })
+            .collect())
+    }
+
+    /// Finds the mentions of an entity in every document of its project again
+    pub async fn refresh_entity_mentions(pool: &PgPool, entity_id: i32) -> Result<()> {
+        let entity = sqlx::query!(
+            "SELECT project_id, name, aliases FROM project_entities WHERE id = $1",
+            entity_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::EntityNotFoundError { entity_id })?;
+
+        let documents = sqlx::query!(
+            r#"
+            SELECT d.id, d.content
+            FROM documents d
+            JOIN document_projects dp ON dp.document_id = d.id
+            WHERE dp.project_id = $1 AND NOT COALESCE(d.is_trashed, FALSE)
+            "#,
+            entity.project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        let names = entity_names(&entity.name, &entity.aliases);
+        let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+        sqlx::query!("DELETE FROM entity_mentions WHERE entity_id = $1", entity_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+        for document in documents {
+            let offsets = find_mentions(document.content.as_deref().unwrap_or(""), &names);
+            insert_mentions(&mut tx, entity_id, document.id, &offsets).await?;
+        }
+        tx.commit().await.map_err(|_| Error::DatabaseError)?;
+        Ok(())
+    }
+
+    /// Finds the mentions of the entities of a document's projects in the document again
+    pub async fn refresh_document_mentions(pool: &PgPool, document_id: i32) -> Result<()> {
+        let content = sqlx::query_scalar!("SELECT content FROM documents WHERE id = $1", document_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?
+            .ok_or(Error::DocumentNotFoundError { document_id })?
+            .unwrap_or_default();
+
+        let entities = sqlx::query!(
+            r#"
+            SELECT e.id, e.name, e.aliases
+            FROM project_entities e
+            JOIN document_projects dp ON dp.project_id = e.project_id
+            WHERE dp.document_id = $1
+            "#,
+            document_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+        sqlx::query!("DELETE FROM entity_mentions WHERE document_id = $1", document_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+        for entity in entities {
+            let offsets = find_mentions(&content, &entity_names(&entity.name, &entity.aliases));
+            insert_mentions(&mut tx, entity.id, document_id, &offsets).await?;
+        }
+        tx.commit().await.map_err(|_| Error::DatabaseError)?;
+        Ok(())
+    }
+
+    /// Adds entities found by the model. An entity whose name or an alias is already known
+    /// extends the existing one with new aliases and a missing description instead.
+    /// Returns the ids of the created and of the updated entities.
+    pub async fn merge_extracted(pool: &PgPool, project_id: i32, found: Vec<LlmEntity>) -> Result<(Vec<i32>, Vec<i32>)> {
+        let mut known = sqlx::query!(
+            "SELECT id, name, aliases, description FROM project_entities WHERE project_id = $1",
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .into_iter()
+        .map(|row| (row.id, row.name, row.aliases, row.description))
+        .collect::<Vec<_>>();
+
+        let mut created = Vec::new();
+        let mut updated = Vec::new();
+        for candidate in found {
+            let payload = EntityPayload {
+                kind: candidate.kind,
+                name: candidate.name,
+                aliases: candidate.aliases,
+                description: Some(candidate.description),
+                notes: None,
+            };
+            // Answers that break the limits are dropped, not fatal
+            let Ok(entity) = validate_entity(&payload) else {
+                continue;
+            };
+            let candidate_names: HashSet<String> = entity_names(&entity.name, &entity.aliases).iter().map(|name| name.to_lowercase()).collect();
+
+            let existing = known.iter_mut().find(|(_, name, aliases, _)| {
+                entity_names(name, aliases).iter().any(|name| candidate_names.contains(&name.to_lowercase()))
+            });
+            match existing {
+                Some((id, name, aliases, description)) => {
+                    let mut changed = false;
+                    for alias in entity_names(&entity.name, &entity.aliases) {
+                        let is_known = entity_names(name, aliases).iter().any(|known| known.to_lowercase() == alias.to_lowercase());
+                        if !is_known && aliases.len() < MAX_ENTITY_ALIASES {
+                            aliases.push(alias);
+                            changed = true;
+                        }
+                    }
+                    if description.is_none() && entity.description.is_some() {
+                        *description = entity.description.clone();
+                        changed = true;
+                    }
+                    if !changed {
+                        continue;
+                    }
+                    sqlx::query!(
+                        "UPDATE project_entities SET aliases = $2, description = $3, updated_at = CURRENT_TIMESTAMP WHERE id = $1",
+                        *id,
+                        &aliases[..],
+                        description.as_deref()
+                    )
+                    .execute(pool)
+                    .await
+                    .map_err(|_| Error::DatabaseError)?;
+                    if !created.contains(id) && !updated.contains(id) {
+                        updated.push(*id);
+                    }
+                }
+                None => {
+                    let entity_id = sqlx::query_scalar!(
+                        r#"
+                        INSERT INTO project_entities (project_id, kind, name, aliases, description, source)
+                        VALUES ($1, $2, $3, $4, $5, $6)
+                        ON CONFLICT DO NOTHING
+                        RETURNING id
+                        "#,
+                        project_id,
+                        payload.kind.as_str(),
+                        entity.name,
+                        &entity.aliases,
+                        entity.description,
+                        EntitySource::Extracted.as_str()
+                    )
+                    .fetch_optional(pool)
+                    .await
+                    .map_err(|_| Error::DatabaseError)?;
+                    if let Some(entity_id) = entity_id {
+                        created.push(entity_id);
+                        known.push((entity_id, entity.name, entity.aliases, entity.description));
+                    }
+                }
+            }
+        }
+
+        for entity_id in created.iter().chain(updated.iter()) {
+            Self::refresh_entity_mentions(pool, *entity_id).await?;
+        }
+        Ok((created, updated))
+    }
+
+    /// Fetches entities of a project by id, in the order given
+    pub async fn entities(pool: &PgPool, project_id: i32, entity_ids: &[i32]) -> Result<Vec<ProjectEntity>> {
+        let mut entities = Vec::with_capacity(entity_ids.len());
+        for entity_id in entity_ids {
+            entities.push(Self::entity(pool, project_id, *entity_id).await?);
+        }
+        Ok(entities)
+    }
+
+    /// Knowledge bases of the projects of a session's document that the session's user can view,
+    /// as text for the assistant's prompt. Entities the document mentions come first in every section.
+    pub async fn context_for_session(pool: &PgPool, session_id: i32) -> Result<Option<String>> {
+        let projects = sqlx::query!(
+            r#"
+            SELECT p.id, p.name, p.style_guide, s.document_id AS "document_id!"
+            FROM writing_assistant_sessions s
+            JOIN document_projects dp ON dp.document_id = s.document_id
+            JOIN projects p ON p.id = dp.project_id
+            JOIN project_permissions pp ON pp.project_id = p.id AND pp.user_id = s.user_id
+            WHERE s.id = $1 AND NOT COALESCE(p.is_trashed, FALSE)
+            ORDER BY p.id ASC
+            "#,
+            session_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        let mut sections = Vec::new();
+        for project in projects {
+            let entities = Self::list(pool, project.id, None).await?;
+            let focus: HashSet<i32> = sqlx::query_scalar!(
+                "SELECT entity_id FROM entity_mentions WHERE document_id = $1",
+                project.document_id
+            )
+            .fetch_all(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?
+            .into_iter()
+            .collect();
+
+            if let Some(section) = render_knowledge(&project.name, project.style_guide.as_deref(), &entities, &focus) {
+                sections.push(section);
+            }
+        }
+        Ok((!sections.is_empty()).then(|| sections.join("\n\n")))
+    }
+}
+
+/// Spawns a refresh of the entity mentions of a saved document on the tokio runtime
+pub fn spawn_mentions_refresh(pool: PgPool, document_id: i32) {
+    tokio::spawn(async move {
+        if let Err(e) = KnowledgeManager::refresh_document_mentions(&pool, document_id).await {
+            eprintln!("->> {:<12} - Mention refresh for document {} failed: {:?}", "KNOWLEDGE", document_id, e);
+        }
+    });
+}
+
+struct ValidEntity {
+    name: String,
+    aliases: Vec<String>,
+    description: Option<String>,
+    notes: Option<String>,
+}
+
+// Trimmed name, distinct aliases other than the name, description and notes of a valid payload
+fn validate_entity(payload: &EntityPayload) -> Result<ValidEntity> {
+    let clean = |value: &Option<String>| value.as_deref().map(str::trim).filter(|value| !value.is_empty()).map(str::to_string);
+    let name = payload.name.trim().to_string();
+
+    let mut aliases: Vec<String> = Vec::new();
+    for alias in payload.aliases.iter().map(|alias| alias.trim()).filter(|alias| !alias.is_empty()) {
+        let lower = alias.to_lowercase();
+        if lower != name.to_lowercase() && !aliases.iter().any(|known| known.to_lowercase() == lower) {
+            aliases.push(alias.to_string());
+        }
+    }
+
+    let description = clean(&payload.description);
+    let notes = clean(&payload.notes);
+    let too_long = |value: &str, limit: usize| value.chars().count() > limit;
+    let valid = !name.is_empty()
+        && !too_long(&name, MAX_ENTITY_NAME_CHARS)
+        && aliases.len() <= MAX_ENTITY_ALIASES
+        && !aliases.iter().any(|alias| too_long(alias, MAX_ENTITY_NAME_CHARS))
+        && !description.as_deref().is_some_and(|text| too_long(text, MAX_ENTITY_TEXT_CHARS))
+        && !notes.as_deref().is_some_and(|text| too_long(text, MAX_ENTITY_TEXT_CHARS));
+    if !valid {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    Ok(ValidEntity { name, aliases, description, notes })
+}
+
+// Entity names are unique per project, ignoring case
+fn entity_write_error(error: sqlx::Error, name: &str) -> Error {
+    match error {
+        sqlx::Error::Database(e) if e.is_unique_violation() => Error::EntityExistsError { name: name.to_string() },
+        _ => Error::DatabaseError,
+    }
+}
+
+fn entity_names(name: &str, aliases: &[String]) -> Vec<String> {
+    std::iter::once(name.to_string()).chain(aliases.iter().cloned()).collect()
+}
+
+async fn insert_mentions(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, entity_id: i32, document_id: i32, offsets: &[i32]) -> Result<()> {
+    if offsets.is_empty() {
+        return Ok(());
+    }
+    let stored = &offsets[..offsets.len().min(MAX_STORED_OFFSETS)];
+    sqlx::query!(
+        "INSERT INTO entity_mentions (entity_id, document_id, mention_count, offsets) VALUES ($1, $2, $3, $4)",
+        entity_id,
+        document_id,
+        offsets.len() as i32,
+        stored
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    Ok(())
+}
+
+/// Character offsets of the whole-word mentions of any of the names in `content`, ignoring case.
+/// Longer names win where names overlap, e.g. "Mira Holt" over "Mira".
+pub fn find_mentions(content: &str, names: &[String]) -> Vec<i32> {
+    let lower = content.to_lowercase();
+    // Lowercasing may change lengths, offsets are only exact when it does not
+    let text = if lower.len() == content.len() { lower } else { content.to_string() };
+    let mut needles: Vec<String> = names.iter().map(|name| name.trim().to_lowercase()).filter(|name| !name.is_empty()).collect();
+    needles.sort_by_key(|needle| std::cmp::Reverse(needle.len()));
+
+    let is_word_char = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+    let mut matches: Vec<(usize, usize)> = Vec::new();
+    for needle in &needles {
+        let mut from = 0;
+        while let Some(position) = text[from..].find(needle.as_str()) {
+            let start = from + position;
+            let end = start + needle.len();
+            let bounded = !is_word_char(text[..start].chars().next_back()) && !is_word_char(text[end..].chars().next());
+            let overlaps = matches.iter().any(|&(s, e)| start < e && s < end);
+            if bounded && !overlaps {
+                matches.push((start, end));
+            }
+            from = start + needle.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    matches.sort();
+
+    // Byte positions to character offsets
+    let mut offsets = Vec::with_capacity(matches.len());
+    let mut chars = 0;
+    let mut last = 0;
+    for (start, _) in matches {
+        chars += text[last..start].chars().count();
+        last = start;
+        offsets.push(chars as i32);
+    }
+    offsets
+}
+
+/// Text of a project's knowledge base for the assistant, None when it is empty.
+/// Entities in `focus` (mentioned in the current document) come first within their kind.
+pub fn render_knowledge(project_name: &str, style_guide: Option<&str>, entities: &[ProjectEntity], focus: &HashSet<i32>) -> Option<String> {
+    let style_guide = style_guide.map(str::trim).filter(|guide| !guide.is_empty());
+    if style_guide.is_none() && entities.is_empty() {
+        return None;
+    }
+
+    let mut text = format!("Project \"{}\":", project_name);
+    if let Some(guide) = style_guide {
+        text.push_str("\nStyle Guide:\n");
+        text.push_str(guide);
+    }
+    for kind in EntityKind::ALL {
+        let mut of_kind: Vec<&ProjectEntity> = entities.iter().filter(|entity| entity.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        of_kind.sort_by_key(|entity| (!focus.contains(&entity.id), entity.name.to_lowercase()));
+
+        text.push_str(&format!("\n{}:", kind.heading()));
+        for entity in of_kind {
+            text.push_str(&format!("\n- {}", entity.name));
+            if !entity.aliases.is_empty() {
+                text.push_str(&format!(" (also: {})", entity.aliases.join(", ")));
+            }
+            if let Some(description) = &entity.description {
+                text.push_str(&format!(": {}", description));
+            }
+            if let Some(notes) = &entity.notes {
+                text.push_str(&format!(" Notes: {}", notes));
+            }
+        }
+    }
+    Some(text)
+}
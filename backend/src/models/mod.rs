@@ -6,4 +6,18 @@ pub mod project_permission;
 pub mod user;
 pub mod commands;
 pub mod ai;
-pub mod storage;
\ No newline at end of file
+pub mod storage;
+pub mod job;
+pub mod credits;
+pub mod plan;
+pub mod persona;
+pub mod proofread;
+
+pub mod stats;
+pub mod goals;
+pub mod translation;
+pub mod knowledge;
+pub mod consistency;
+pub mod fact_check;
+pub mod similarity;
+pub mod tags;
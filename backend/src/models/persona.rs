@@ -0,0 +1,161 @@
+// src/models/persona.rs
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::models::ai::DEFAULT_SYSTEM_PROMPT;
+use crate::{Error, Result};
+
+/// Longest accepted custom system prompt, in characters
+pub const MAX_SYSTEM_PROMPT_CHARS: usize = 4000;
+
+/// A system prompt for the writing assistant. Built-in personas have no user_id.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Persona {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub name: String,
+    pub description: String,
+    pub system_prompt: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePersonaPayload {
+    pub name: String,
+    pub description: Option<String>,
+    pub system_prompt: String,
+}
+
+/// Persona of a session or project. A custom `system_prompt` takes precedence over `persona_id`,
+/// both empty goes back to the default.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersonaSettings {
+    pub persona_id: Option<i32>,
+    pub system_prompt: Option<String>,
+}
+
+impl PersonaSettings {
+    /// Trims the custom prompt, an empty one counts as none. InvalidRequestFormatError if it is too long.
+    pub fn normalized(self) -> Result<Self> {
+        Ok(PersonaSettings {
+            persona_id: self.persona_id,
+            system_prompt: normalize_system_prompt(self.system_prompt)?,
+        })
+    }
+}
+
+/// Trims a custom system prompt, None if it is empty
+pub fn normalize_system_prompt(system_prompt: Option<String>) -> Result<Option<String>> {
+    let Some(system_prompt) = system_prompt.map(|prompt| prompt.trim().to_string()) else {
+        return Ok(None);
+    };
+    if system_prompt.chars().count() > MAX_SYSTEM_PROMPT_CHARS {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    Ok(Some(system_prompt).filter(|prompt| !prompt.is_empty()))
+}
+
+/// Personas of the writing assistant.
+/// The system prompt of a session is the first one set of: the session's custom prompt, the
+/// session's persona, the custom prompt of the project of its document, that project's persona.
+/// Sessions without any use DEFAULT_SYSTEM_PROMPT.
+pub struct PersonaManager;
+
+impl PersonaManager {
+    /// Built-in personas followed by the user's own, by name
+    pub async fn list(pool: &PgPool, user_id: i32) -> Result<Vec<Persona>> {
+        sqlx::query_as!(
+            Persona,
+            r#"
+            SELECT id, user_id, name, description, system_prompt, created_at
+            FROM assistant_personas
+            WHERE user_id IS NULL OR user_id = $1
+            ORDER BY user_id NULLS FIRST, name ASC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+
+    pub async fn create(pool: &PgPool, user_id: i32, payload: CreatePersonaPayload) -> Result<Persona> {
+        let name = payload.name.trim();
+        let system_prompt = normalize_system_prompt(Some(payload.system_prompt))?.ok_or(Error::InvalidRequestFormatError)?;
+        if name.is_empty() || name.chars().count() > 100 {
+            return Err(Error::InvalidRequestFormatError);
+        }
+
+        sqlx::query_as!(
+            Persona,
+            r#"
+            INSERT INTO assistant_personas (user_id, name, description, system_prompt)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, name, description, system_prompt, created_at
+            "#,
+            user_id,
+            name,
+            payload.description.as_deref().unwrap_or("").trim(),
+            system_prompt
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+
+    /// Deletes one of the user's personas, sessions and projects using it go back to the default.
+    /// Built-in personas cannot be deleted.
+    pub async fn delete(pool: &PgPool, user_id: i32, persona_id: i32) -> Result<()> {
+        let deleted = sqlx::query!(
+            "DELETE FROM assistant_personas WHERE id = $1 AND user_id = $2",
+            persona_id,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::PersonaNotFoundError { persona_id });
+        }
+        Ok(())
+    }
+
+    /// PersonaNotFoundError unless the persona is built-in or belongs to the user
+    pub async fn ensure_usable(pool: &PgPool, user_id: i32, persona_id: i32) -> Result<()> {
+        let usable = sqlx::query!(
+            "SELECT id FROM assistant_personas WHERE id = $1 AND (user_id IS NULL OR user_id = $2)",
+            persona_id,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        usable.map(|_| ()).ok_or(Error::PersonaNotFoundError { persona_id })
+    }
+
+    /// System prompt the assistant answers a session with
+    pub async fn system_prompt_for_session(pool: &PgPool, session_id: i32) -> Result<String> {
+        let resolved = sqlx::query!(
+            r#"
+            SELECT COALESCE(s.system_prompt, sp.system_prompt, p.system_prompt, pp.system_prompt) AS system_prompt
+            FROM writing_assistant_sessions s
+            LEFT JOIN assistant_personas sp ON sp.id = s.persona_id
+            LEFT JOIN document_projects dp ON dp.document_id = s.document_id
+            LEFT JOIN projects p ON p.id = dp.project_id
+            LEFT JOIN assistant_personas pp ON pp.id = p.persona_id
+            WHERE s.id = $1
+            "#,
+            session_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        Ok(resolved
+            .and_then(|row| row.system_prompt)
+            .unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string()))
+    }
+}
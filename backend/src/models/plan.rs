@@ -0,0 +1,225 @@
+use chrono::{Months, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::env;
+use tokio::time;
+
+use crate::{Error, Result};
+
+/// A subscription plan, bundling the AI allowance with the storage limits
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Plan {
+    pub id: i32,
+    pub name: String,
+    /// Credits the balance is topped up to at every monthly refill
+    pub monthly_credits: i32,
+    pub max_projects: i32,
+    pub max_documents: i32,
+    pub storage_quota_bytes: i64,
+}
+
+/// A user's plan together with their refill schedule
+#[derive(Debug, Clone, Serialize)]
+pub struct UserPlan {
+    pub plan: Plan,
+    pub ai_credits: i32,
+    pub credits_refilled_at: NaiveDateTime,
+    pub next_refill_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GrantCreditsPayload {
+    pub user_id: i32,
+    /// Credits to add, negative amounts take credits away
+    pub amount: i32,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefillParams {
+    pub secret: Option<String>,
+    /// Refill only this user, even if their refill is not due yet
+    pub user_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetPlanPayload {
+    pub plan: String,
+}
+
+pub struct PlanManager;
+
+impl PlanManager {
+    /// Date of the refill following `refilled_at`
+    pub fn next_refill(refilled_at: NaiveDateTime) -> NaiveDateTime {
+        refilled_at.checked_add_months(Months::new(1)).unwrap_or(refilled_at)
+    }
+
+    /// All plans, cheapest first
+    pub async fn list_plans(pool: &PgPool) -> Result<Vec<Plan>> {
+        sqlx::query_as!(
+            Plan,
+            "SELECT id, name, monthly_credits, max_projects, max_documents, storage_quota_bytes FROM plans ORDER BY monthly_credits, id"
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+
+    /// Plan and refill schedule of a user
+    pub async fn get_user_plan(pool: &PgPool, user_id: i32) -> Result<UserPlan> {
+        let row = sqlx::query!(
+            r#"
+            SELECT p.id, p.name, p.monthly_credits, p.max_projects, p.max_documents, p.storage_quota_bytes,
+                   u.ai_credits, u.credits_refilled_at
+            FROM users u
+            JOIN plans p ON p.id = u.plan_id
+            WHERE u.id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::UserNotFoundError { user_id })?;
+
+        Ok(UserPlan {
+            plan: Plan {
+                id: row.id,
+                name: row.name,
+                monthly_credits: row.monthly_credits,
+                max_projects: row.max_projects,
+                max_documents: row.max_documents,
+                storage_quota_bytes: row.storage_quota_bytes,
+            },
+            ai_credits: row.ai_credits,
+            credits_refilled_at: row.credits_refilled_at,
+            next_refill_at: Self::next_refill(row.credits_refilled_at),
+        })
+    }
+
+    /// Limits of a user's plan
+    pub async fn get_user_limits(pool: &PgPool, user_id: i32) -> Result<Plan> {
+        Ok(Self::get_user_plan(pool, user_id).await?.plan)
+    }
+
+    /// Moves a user to another plan. The new allowance applies from the next refill.
+    pub async fn set_user_plan(pool: &PgPool, user_id: i32, plan_name: &str) -> Result<Plan> {
+        let plan = sqlx::query_as!(
+            Plan,
+            "SELECT id, name, monthly_credits, max_projects, max_documents, storage_quota_bytes FROM plans WHERE name = $1",
+            plan_name
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::InvalidRequestFormatError)?;
+
+        let updated = sqlx::query!("UPDATE users SET plan_id = $2 WHERE id = $1", user_id, plan.id)
+            .execute(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+        if updated.rows_affected() == 0 {
+            return Err(Error::UserNotFoundError { user_id });
+        }
+
+        println!("->> {:<12} - User {} moved to plan '{}'", "PLANS", user_id, plan.name);
+        Ok(plan)
+    }
+
+    /// Adds (or with a negative amount removes) credits outside of the monthly refill.
+    /// Returns the new balance.
+    pub async fn grant_credits(pool: &PgPool, user_id: i32, amount: i32, note: Option<&str>) -> Result<i32> {
+        let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+
+        // The balance may not drop below zero
+        let balance_after = sqlx::query!(
+            "UPDATE users SET ai_credits = ai_credits + $2 WHERE id = $1 AND ai_credits + $2 >= 0 RETURNING ai_credits",
+            user_id,
+            amount
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::InvalidRequestFormatError)?
+        .ai_credits;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO ai_credit_ledger (user_id, operation, amount, balance_after, note)
+            VALUES ($1, 'admin_grant', $2, $3, $4)
+            "#,
+            user_id,
+            amount,
+            balance_after,
+            note
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        tx.commit().await.map_err(|_| Error::DatabaseError)?;
+
+        println!("->> {:<12} - Granted user {} {} credits. Balance: {}", "PLANS", user_id, amount, balance_after);
+        Ok(balance_after)
+    }
+
+    /// Tops up every user whose refill is due to their plan's monthly allowance.
+    /// With `user_id` set only that user is topped up, whether their refill is due or not.
+    /// Balances above the allowance (e.g. from grants) are kept, but their refill date still moves on.
+    /// Returns the number of users that received credits.
+    pub async fn refill_due_credits(pool: &PgPool, user_id: Option<i32>) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            WITH due AS (
+                SELECT u.id, GREATEST(p.monthly_credits - u.ai_credits, 0) AS top_up
+                FROM users u
+                JOIN plans p ON p.id = u.plan_id
+                WHERE CASE WHEN $1::int IS NULL THEN u.credits_refilled_at + INTERVAL '1 month' <= NOW() ELSE u.id = $1 END
+                FOR UPDATE OF u SKIP LOCKED
+            ),
+            refilled AS (
+                UPDATE users u
+                SET ai_credits = u.ai_credits + due.top_up, credits_refilled_at = NOW()
+                FROM due
+                WHERE u.id = due.id
+                RETURNING u.id, u.ai_credits, due.top_up
+            )
+            INSERT INTO ai_credit_ledger (user_id, operation, amount, balance_after, note)
+            SELECT id, 'monthly_refill', top_up, ai_credits, 'Monthly plan allowance'
+            FROM refilled
+            WHERE top_up > 0
+            "#,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("DB Error refilling credits: {:?}", e);
+            Error::DatabaseError
+        })?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Starts the scheduled credit refill on the tokio runtime.
+/// Checks for due refills every CREDIT_REFILL_CHECK_SECS seconds (default: 1h).
+pub fn spawn_credit_refill_job(pool: PgPool) {
+    let interval_secs = env::var("CREDIT_REFILL_CHECK_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(3600);
+
+    tokio::spawn(async move {
+        println!("->> {:<12} - Credit refill job started (every {}s)", "PLANS", interval_secs);
+        loop {
+            match PlanManager::refill_due_credits(&pool, None).await {
+                Ok(0) => {}
+                Ok(count) => println!("->> {:<12} - Refilled credits for {} users", "PLANS", count),
+                Err(e) => eprintln!("->> {:<12} - Credit refill failed: {:?}", "PLANS", e),
+            }
+            time::sleep(std::time::Duration::from_secs(interval_secs)).await;
+        }
+    });
+}
@@ -0,0 +1,116 @@
+// src/models/proofread.rs
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+use crate::proofread::Issue;
+use crate::{Error, Result};
+
+/// Longest text accepted by the offline checker, in characters
+pub const MAX_PROOFREAD_CHARS: usize = 200_000;
+/// Longest word accepted in a custom dictionary
+pub const MAX_DICTIONARY_WORD_CHARS: usize = 64;
+
+#[derive(Debug, Deserialize)]
+pub struct ProofreadPayload {
+    pub content: String,
+    /// Dictionary to check spelling with, e.g. "en_US". Defaults to DEFAULT_DICTIONARY.
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProofreadResponse {
+    pub language: String,
+    /// False when there is no dictionary for the language, only style issues are reported then
+    pub dictionary_loaded: bool,
+    pub word_count: usize,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProofreadLanguages {
+    pub default_language: String,
+    pub languages: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DictionaryWord {
+    pub word: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddDictionaryWordPayload {
+    pub word: String,
+}
+
+/// Lowercased, trimmed word. InvalidRequestFormatError if it is empty, too long or contains whitespace.
+pub fn normalize_dictionary_word(word: &str) -> Result<String> {
+    let word = word.trim().replace('’', "'").to_lowercase();
+    if word.is_empty() || word.chars().count() > MAX_DICTIONARY_WORD_CHARS || word.chars().any(char::is_whitespace) {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    Ok(word)
+}
+
+/// Words a user added to their custom dictionary. They are matched case-insensitively.
+pub struct UserDictionary;
+
+impl UserDictionary {
+    pub async fn list(pool: &PgPool, user_id: i32) -> Result<Vec<DictionaryWord>> {
+        sqlx::query_as!(
+            DictionaryWord,
+            "SELECT word, created_at FROM user_dictionary_words WHERE user_id = $1 ORDER BY word ASC",
+            user_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+
+    /// The user's words, lowercase, for the checker
+    pub async fn words(pool: &PgPool, user_id: i32) -> Result<HashSet<String>> {
+        let rows = sqlx::query!("SELECT word FROM user_dictionary_words WHERE user_id = $1", user_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+        Ok(rows.into_iter().map(|row| row.word).collect())
+    }
+
+    /// Adds a word, adding it again is not an error
+    pub async fn add(pool: &PgPool, user_id: i32, word: &str) -> Result<DictionaryWord> {
+        let word = normalize_dictionary_word(word)?;
+        sqlx::query_as!(
+            DictionaryWord,
+            r#"
+            INSERT INTO user_dictionary_words (user_id, word)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id, word) DO UPDATE SET word = EXCLUDED.word
+            RETURNING word, created_at
+            "#,
+            user_id,
+            word
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+
+    pub async fn remove(pool: &PgPool, user_id: i32, word: &str) -> Result<()> {
+        let word = normalize_dictionary_word(word)?;
+        let deleted = sqlx::query!(
+            "DELETE FROM user_dictionary_words WHERE user_id = $1 AND word = $2",
+            user_id,
+            word
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::DictionaryWordNotFoundError { word });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+// src/models/similarity.rs
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::proofread::{self, Token};
+use crate::rag::chunk::to_plain_text;
+use crate::{Error, Result};
+
+/// Similarity of two passages' embeddings a report starts from, by default
+pub const DEFAULT_MIN_SCORE: f64 = 0.9;
+/// Lowest similarity a report may ask for, below that everything is similar to everything
+pub const MIN_SCORE_FLOOR: f64 = 0.5;
+/// Passages at least this similar are near duplicates, whatever their words
+const NEAR_DUPLICATE_SCORE: f64 = 0.97;
+/// Share of a passage's words in shared spans that makes it a near duplicate
+const NEAR_DUPLICATE_COVERAGE: f64 = 0.8;
+/// Share of the shorter document's words in shared spans that makes two documents copies
+const COPY_COVERAGE: f64 = 0.8;
+/// Shortest run of words two texts share that counts as reused text
+const MIN_SPAN_WORDS: usize = 8;
+/// Passage pairs a report compares in detail, the most similar first
+const MAX_REPORT_PASSAGES: i64 = 500;
+/// Spans listed per document pair, the longest first
+const MAX_SPANS_PER_PAIR: usize = 50;
+/// Embedded passages a report compares with each other, larger scopes are refused
+const MAX_COMPARED_CHUNKS: i64 = 5000;
+/// Longest excerpt or span text in a report, in characters
+const MAX_EXCERPT_CHARS: usize = 300;
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarityParams {
+    /// Compare the documents of a project instead of the user's own documents
+    pub project_id: Option<i32>,
+    pub min_score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PassageKind {
+    /// Nearly the same paragraph twice
+    NearDuplicate,
+    /// A similar passage that shares a run of words with the other one
+    ReusedText,
+    /// About the same thing in other words
+    Similar,
+}
+
+/// A run of words both documents contain, ignoring case and punctuation.
+/// Offsets are character offsets into the plain text of the documents (see rag::chunk).
+#[derive(Debug, Serialize)]
+pub struct AlignedSpan {
+    pub start_offset: i32,
+    pub end_offset: i32,
+    pub other_start_offset: i32,
+    pub other_end_offset: i32,
+    pub word_count: i32,
+    pub text: String,
+}
+
+/// Two passages with similar embeddings, offsets as in `AlignedSpan`
+#[derive(Debug, Serialize)]
+pub struct SimilarPassage {
+    pub kind: PassageKind,
+    /// Cosine similarity of the two passages' embeddings
+    pub score: f64,
+    pub chunk_id: i32,
+    pub start_offset: i32,
+    pub end_offset: i32,
+    pub excerpt: String,
+    pub other_chunk_id: i32,
+    pub other_start_offset: i32,
+    pub other_end_offset: i32,
+    pub other_excerpt: String,
+}
+
+/// Two documents with similar passages, or one document repeating itself when both ids are the same
+#[derive(Debug, Serialize)]
+pub struct SimilarDocumentPair {
+    pub document_id: i32,
+    pub document_name: String,
+    pub other_document_id: i32,
+    pub other_document_name: String,
+    /// Cosine similarity of the two documents' embeddings
+    pub document_score: Option<f64>,
+    /// Share of the shorter document's words in shared spans
+    pub coverage: f64,
+    /// The documents are copies of each other, or the shorter one is a copy of part of the other
+    pub is_copy: bool,
+    pub passages: Vec<SimilarPassage>,
+    pub spans: Vec<AlignedSpan>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarityReport {
+    pub project_id: Option<i32>,
+    pub min_score: f64,
+    pub documents_compared: usize,
+    /// Documents without embeddings yet, they are compared once the embedding worker reaches them
+    pub pending_document_ids: Vec<i32>,
+    /// Copies first, then the pairs with the most similar passages
+    pub pairs: Vec<SimilarDocumentPair>,
+}
+
+struct ScopeDocument {
+    id: i32,
+    name: String,
+    content: Option<String>,
+    embedded: bool,
+}
+
+struct PassagePair {
+    chunk_id: i32,
+    document_id: i32,
+    start_offset: i32,
+    end_offset: i32,
+    content: String,
+    other_chunk_id: i32,
+    other_document_id: i32,
+    other_start_offset: i32,
+    other_end_offset: i32,
+    other_content: String,
+    score: f64,
+    document_score: Option<f64>,
+}
+
+// A run of `length` words starting at word `start` of one text and `other_start` of the other
+struct Run {
+    start: usize,
+    other_start: usize,
+    length: usize,
+}
+
+pub struct SimilarityManager;
+
+impl SimilarityManager {
+    /// Similar passages and copies among the documents the user owns, or the documents of a project.
+    /// Permissions are checked by the caller.
+    pub async fn report(pool: &PgPool, user_id: i32, project_id: Option<i32>, min_score: f64) -> Result<SimilarityReport> {
+        let documents = sqlx::query_as!(
+            ScopeDocument,
+            r#"
+            SELECT d.id, d.name, d.content,
+                   EXISTS (SELECT 1 FROM document_chunks c WHERE c.document_id = d.id AND c.embedding IS NOT NULL) AS "embedded!"
+            FROM documents d
+            WHERE NOT COALESCE(d.is_trashed, FALSE)
+              AND CASE WHEN $1::int IS NULL
+                  THEN EXISTS (SELECT 1 FROM document_permissions dp WHERE dp.document_id = d.id AND dp.user_id = $2 AND dp.role = 'owner')
+                  ELSE EXISTS (SELECT 1 FROM document_projects dp WHERE dp.document_id = d.id AND dp.project_id = $1)
+              END
+            ORDER BY d.id
+            "#,
+            project_id,
+            user_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        let compared: Vec<i32> = documents.iter().filter(|document| document.embedded).map(|document| document.id).collect();
+        let pending_document_ids: Vec<i32> = documents
+            .iter()
+            .filter(|document| !document.embedded && !document.content.as_deref().unwrap_or_default().trim().is_empty())
+            .map(|document| document.id)
+            .collect();
+
+        let chunk_count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM document_chunks WHERE document_id = ANY($1) AND embedding IS NOT NULL"#,
+            &compared
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        if chunk_count > MAX_COMPARED_CHUNKS {
+            return Err(Error::LimitExceededError {
+                message: format!("{} passages are too many to compare, at most {}", chunk_count, MAX_COMPARED_CHUNKS),
+            });
+        }
+
+        // Every pair once: documents in id order, passages of one document in text order without overlap
+        let passages = sqlx::query_as!(
+            PassagePair,
+            r#"
+            SELECT a.id AS chunk_id, a.document_id, a.start_offset, a.end_offset, a.content,
+                   b.id AS other_chunk_id, b.document_id AS other_document_id, b.start_offset AS other_start_offset,
+                   b.end_offset AS other_end_offset, b.content AS other_content,
+                   (1 - (a.embedding <=> b.embedding))::float8 AS "score!",
+                   (1 - (da.embedding <=> db.embedding))::float8 AS document_score
+            FROM document_chunks a
+            JOIN document_chunks b
+              ON b.document_id > a.document_id
+              OR (b.document_id = a.document_id AND b.start_offset >= a.end_offset)
+            JOIN documents da ON da.id = a.document_id
+            JOIN documents db ON db.id = b.document_id
+            WHERE a.document_id = ANY($1) AND b.document_id = ANY($1)
+              AND a.embedding IS NOT NULL AND b.embedding IS NOT NULL
+              AND 1 - (a.embedding <=> b.embedding) >= $2
+            ORDER BY a.embedding <=> b.embedding, a.id, b.id
+            LIMIT $3
+            "#,
+            &compared,
+            min_score,
+            MAX_REPORT_PASSAGES
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("DB Error comparing passages: {:?}", e);
+            Error::DatabaseError
+        })?;
+
+        // Passages by document pair, in order of their best passage
+        let mut grouped: Vec<((i32, i32), Vec<PassagePair>)> = Vec::new();
+        for passage in passages {
+            let key = (passage.document_id, passage.other_document_id);
+            match grouped.iter_mut().find(|(known, _)| *known == key) {
+                Some((_, group)) => group.push(passage),
+                None => grouped.push((key, vec![passage])),
+            }
+        }
+
+        let by_id: HashMap<i32, &ScopeDocument> = documents.iter().map(|document| (document.id, document)).collect();
+        let mut pairs = Vec::with_capacity(grouped.len());
+        for ((document_id, other_document_id), group) in grouped {
+            let (Some(document), Some(other)) = (by_id.get(&document_id), by_id.get(&other_document_id)) else {
+                continue;
+            };
+            pairs.push(compare_documents(document, other, group));
+        }
+        pairs.sort_by(|a, b| {
+            let best = |pair: &SimilarDocumentPair| pair.passages.first().map_or(0.0, |passage| passage.score);
+            b.is_copy.cmp(&a.is_copy).then(best(b).total_cmp(&best(a)))
+        });
+
+        println!(
+            "->> {:<12} - Compared {} documents of user {} (project {:?}): {} similar pairs",
+            "SIMILARITY", compared.len(), user_id, project_id, pairs.len()
+        );
+        Ok(SimilarityReport { project_id, min_score, documents_compared: compared.len(), pending_document_ids, pairs })
+    }
+}
+
+// Shared spans, coverage and passage kinds of two documents with similar passages
+fn compare_documents(document: &ScopeDocument, other: &ScopeDocument, passages: Vec<PassagePair>) -> SimilarDocumentPair {
+    let same = document.id == other.id;
+    let text = to_plain_text(document.content.as_deref().unwrap_or_default());
+    let words = proofread::tokenize(&text);
+    let other_words = if same { Vec::new() } else { proofread::tokenize(&to_plain_text(other.content.as_deref().unwrap_or_default())) };
+    let other_words = if same { &words } else { &other_words };
+
+    let mut runs = aligned_runs(&words, other_words, same);
+
+    // Words of either text in a run
+    let mut covered = vec![false; words.len()];
+    let mut other_covered = vec![false; other_words.len()];
+    for run in &runs {
+        covered[run.start..run.start + run.length].iter_mut().for_each(|word| *word = true);
+        other_covered[run.other_start..run.other_start + run.length].iter_mut().for_each(|word| *word = true);
+    }
+    if same {
+        covered.iter_mut().zip(&other_covered).for_each(|(word, repeated)| *word |= *repeated);
+    }
+    let share = |covered: &[bool]| if covered.is_empty() { 0.0 } else { covered.iter().filter(|word| **word).count() as f64 / covered.len() as f64 };
+    let coverage = if same || words.len() <= other_words.len() { share(&covered) } else { share(&other_covered) };
+
+    let document_score = passages.first().and_then(|passage| passage.document_score);
+    let passages = passages
+        .into_iter()
+        .map(|passage| {
+            // Share of the passage's words in a run, and whether it has any
+            let in_passage = |word: &&Token| word.start as i32 >= passage.start_offset && (word.end as i32) <= passage.end_offset;
+            let flags: Vec<bool> = words.iter().zip(&covered).filter(|(word, _)| in_passage(word)).map(|(_, covered)| *covered).collect();
+            let kind = if passage.score >= NEAR_DUPLICATE_SCORE || (!flags.is_empty() && share(&flags) >= NEAR_DUPLICATE_COVERAGE) {
+                PassageKind::NearDuplicate
+            } else if flags.iter().any(|covered| *covered) {
+                PassageKind::ReusedText
+            } else {
+                PassageKind::Similar
+            };
+            SimilarPassage {
+                kind,
+                score: passage.score,
+                chunk_id: passage.chunk_id,
+                start_offset: passage.start_offset,
+                end_offset: passage.end_offset,
+                excerpt: passage.content.chars().take(MAX_EXCERPT_CHARS).collect(),
+                other_chunk_id: passage.other_chunk_id,
+                other_start_offset: passage.other_start_offset,
+                other_end_offset: passage.other_end_offset,
+                other_excerpt: passage.other_content.chars().take(MAX_EXCERPT_CHARS).collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    runs.sort_by(|a, b| b.length.cmp(&a.length).then(a.start.cmp(&b.start)));
+    let spans = runs
+        .iter()
+        .take(MAX_SPANS_PER_PAIR)
+        .map(|run| {
+            let (first, last) = (&words[run.start], &words[run.start + run.length - 1]);
+            let (other_first, other_last) = (&other_words[run.other_start], &other_words[run.other_start + run.length - 1]);
+            AlignedSpan {
+                start_offset: first.start as i32,
+                end_offset: last.end as i32,
+                other_start_offset: other_first.start as i32,
+                other_end_offset: other_last.end as i32,
+                word_count: run.length as i32,
+                text: text[first.start_byte..last.end_byte].chars().take(MAX_EXCERPT_CHARS).collect(),
+            }
+        })
+        .collect();
+
+    SimilarDocumentPair {
+        document_id: document.id,
+        document_name: document.name.clone(),
+        other_document_id: other.id,
+        other_document_name: other.name.clone(),
+        document_score,
+        coverage,
+        is_copy: !same && coverage >= COPY_COVERAGE,
+        passages,
+        spans,
+    }
+}
+
+// Runs of at least MIN_SPAN_WORDS words both texts share, found greedily from the start of the
+// first text. Within one text (`same`) only later runs that do not overlap count.
+fn aligned_runs(words: &[Token], other: &[Token], same: bool) -> Vec<Run> {
+    if words.len() < MIN_SPAN_WORDS || other.len() < MIN_SPAN_WORDS {
+        return Vec::new();
+    }
+    let key = |tokens: &[Token]| tokens.iter().map(|token| token.lowercase.as_str()).collect::<Vec<&str>>().join(" ");
+    let mut shingles: HashMap<String, Vec<usize>> = HashMap::new();
+    for start in 0..=other.len() - MIN_SPAN_WORDS {
+        shingles.entry(key(&other[start..start + MIN_SPAN_WORDS])).or_default().push(start);
+    }
+
+    let mut runs = Vec::new();
+    let mut start = 0;
+    while start + MIN_SPAN_WORDS <= words.len() {
+        let candidates = shingles.get(&key(&words[start..start + MIN_SPAN_WORDS]));
+        let best = candidates
+            .into_iter()
+            .flatten()
+            .filter(|&&other_start| !same || other_start >= start + MIN_SPAN_WORDS)
+            .map(|&other_start| {
+                let limit = if same { other_start - start } else { usize::MAX };
+                let length = words[start..]
+                    .iter()
+                    .zip(&other[other_start..])
+                    .take(limit)
+                    .take_while(|(word, other_word)| word.lowercase == other_word.lowercase)
+                    .count();
+                (other_start, length)
+            })
+            .max_by_key(|&(_, length)| length);
+        match best {
+            Some((other_start, length)) => {
+                runs.push(Run { start, other_start, length });
+                start += length;
+            }
+            None => start += 1,
+        }
+    }
+    runs
+}
@@ -0,0 +1,282 @@
+// src/models/stats.rs
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json as SqlJson;
+use sqlx::PgPool;
+
+use crate::rag::chunk::to_plain_text;
+use crate::stats::{compute_metrics, document_metrics, WritingMetrics};
+use crate::{Error, Result};
+
+/// Days of history returned when a request does not say
+pub const DEFAULT_HISTORY_DAYS: i32 = 30;
+/// Longest history returned
+pub const MAX_HISTORY_DAYS: i32 = 365;
+
+#[derive(Debug, Serialize)]
+pub struct DocumentStats {
+    pub document_id: i32,
+    pub metrics: WritingMetrics,
+    pub computed_at: NaiveDateTime,
+}
+
+/// Headline numbers of one document of a project
+#[derive(Debug, Serialize)]
+pub struct ProjectDocumentStats {
+    pub document_id: i32,
+    pub name: String,
+    pub word_count: i32,
+    pub reading_time_minutes: f64,
+    pub flesch_reading_ease: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectStats {
+    pub project_id: i32,
+    /// Statistics of all documents of the project read as one text
+    pub metrics: WritingMetrics,
+    pub documents: Vec<ProjectDocumentStats>,
+    pub computed_at: NaiveDateTime,
+}
+
+/// Statistics at the end of a day on which the text changed
+#[derive(Debug, Serialize)]
+pub struct StatsSnapshot {
+    pub day: NaiveDate,
+    pub word_count: i32,
+    pub metrics: SqlJson<WritingMetrics>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsHistoryParams {
+    pub days: Option<i32>,
+}
+
+impl StatsHistoryParams {
+    pub fn days(&self) -> i32 {
+        self.days.unwrap_or(DEFAULT_HISTORY_DAYS).clamp(1, MAX_HISTORY_DAYS)
+    }
+}
+
+/// Readability and writing statistics of documents and projects.
+/// Statistics are cached with an md5 of the text they were computed from and recomputed when
+/// the text changed, so a read never returns stale numbers. Every computation also updates the
+/// snapshot of the day, which is the history. Saves refresh them in the background.
+pub struct StatsManager;
+
+impl StatsManager {
+    /// Statistics of a document, from the cache while its content is unchanged
+    pub async fn document_stats(pool: &PgPool, document_id: i32) -> Result<DocumentStats> {
+        let row = sqlx::query!(
+            r#"
+            SELECT d.content, md5(COALESCE(d.content, '')) AS "content_hash!",
+                   s.content_hash AS "cached_hash?", s.metrics AS "metrics?: SqlJson<WritingMetrics>", s.computed_at AS "computed_at?"
+            FROM documents d
+            LEFT JOIN document_stats s ON s.document_id = d.id
+            WHERE d.id = $1
+            "#,
+            document_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::DocumentNotFoundError { document_id })?;
+
+        if let (Some(cached_hash), Some(SqlJson(metrics)), Some(computed_at)) = (row.cached_hash, row.metrics, row.computed_at) {
+            if cached_hash == row.content_hash {
+                return Ok(DocumentStats { document_id, metrics, computed_at });
+            }
+        }
+
+        let metrics = document_metrics(row.content.as_deref().unwrap_or(""));
+        let computed_at = sqlx::query_scalar!(
+            r#"
+            INSERT INTO document_stats (document_id, content_hash, metrics, computed_at)
+            VALUES ($1, $2, $3, CURRENT_TIMESTAMP)
+            ON CONFLICT (document_id) DO UPDATE SET
+                content_hash = EXCLUDED.content_hash, metrics = EXCLUDED.metrics, computed_at = EXCLUDED.computed_at
+            RETURNING computed_at
+            "#,
+            document_id,
+            row.content_hash,
+            SqlJson(&metrics) as _
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO document_stats_daily (document_id, day, word_count, metrics)
+            VALUES ($1, CURRENT_DATE, $2, $3)
+            ON CONFLICT (document_id, day) DO UPDATE SET word_count = EXCLUDED.word_count, metrics = EXCLUDED.metrics
+            "#,
+            document_id,
+            metrics.word_count,
+            SqlJson(&metrics) as _
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        Ok(DocumentStats { document_id, metrics, computed_at })
+    }
+
+    /// Statistics of the documents of a project that are not in the trash
+    pub async fn project_stats(pool: &PgPool, project_id: i32) -> Result<ProjectStats> {
+        let documents = sqlx::query!(
+            r#"
+            SELECT d.id, d.name, d.content, md5(COALESCE(d.content, '')) AS "content_hash!"
+            FROM documents d
+            JOIN document_projects dp ON dp.document_id = d.id
+            WHERE dp.project_id = $1 AND NOT COALESCE(d.is_trashed, FALSE)
+            ORDER BY d.id
+            "#,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        let mut document_stats = Vec::with_capacity(documents.len());
+        for document in &documents {
+            let metrics = Self::document_stats(pool, document.id).await?.metrics;
+            document_stats.push(ProjectDocumentStats {
+                document_id: document.id,
+                name: document.name.clone(),
+                word_count: metrics.word_count,
+                reading_time_minutes: metrics.reading_time_minutes,
+                flesch_reading_ease: metrics.flesch_reading_ease,
+            });
+        }
+
+        // The project is unchanged while the same documents have the same content
+        let content_key = documents
+            .iter()
+            .map(|document| format!("{}:{}", document.id, document.content_hash))
+            .collect::<Vec<_>>()
+            .join(",");
+        let cached = sqlx::query!(
+            r#"
+            SELECT s.content_hash AS "content_hash?", s.metrics AS "metrics?: SqlJson<WritingMetrics>", s.computed_at AS "computed_at?"
+            FROM projects p
+            LEFT JOIN project_stats s ON s.project_id = p.id
+            WHERE p.id = $1
+            "#,
+            project_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::ProjectNotFoundError { project_id })?;
+
+        if let (Some(cached_key), Some(SqlJson(metrics)), Some(computed_at)) = (cached.content_hash, cached.metrics, cached.computed_at) {
+            if cached_key == content_key {
+                return Ok(ProjectStats { project_id, metrics, documents: document_stats, computed_at });
+            }
+        }
+
+        let text = documents
+            .iter()
+            .map(|document| to_plain_text(document.content.as_deref().unwrap_or("")))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let metrics = compute_metrics(&text);
+
+        let computed_at = sqlx::query_scalar!(
+            r#"
+            INSERT INTO project_stats (project_id, content_hash, metrics, computed_at)
+            VALUES ($1, $2, $3, CURRENT_TIMESTAMP)
+            ON CONFLICT (project_id) DO UPDATE SET
+                content_hash = EXCLUDED.content_hash, metrics = EXCLUDED.metrics, computed_at = EXCLUDED.computed_at
+            RETURNING computed_at
+            "#,
+            project_id,
+            content_key,
+            SqlJson(&metrics) as _
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO project_stats_daily (project_id, day, word_count, metrics)
+            VALUES ($1, CURRENT_DATE, $2, $3)
+            ON CONFLICT (project_id, day) DO UPDATE SET word_count = EXCLUDED.word_count, metrics = EXCLUDED.metrics
+            "#,
+            project_id,
+            metrics.word_count,
+            SqlJson(&metrics) as _
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        Ok(ProjectStats { project_id, metrics, documents: document_stats, computed_at })
+    }
+
+    /// Daily snapshots of a document over the last `days` days, oldest first
+    pub async fn document_history(pool: &PgPool, document_id: i32, days: i32) -> Result<Vec<StatsSnapshot>> {
+        // Make sure today reflects the current content
+        Self::document_stats(pool, document_id).await?;
+
+        sqlx::query_as!(
+            StatsSnapshot,
+            r#"
+            SELECT day, word_count, metrics AS "metrics: SqlJson<WritingMetrics>"
+            FROM document_stats_daily
+            WHERE document_id = $1 AND day > CURRENT_DATE - $2::INT
+            ORDER BY day ASC
+            "#,
+            document_id,
+            days
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+
+    /// Daily snapshots of a project over the last `days` days, oldest first
+    pub async fn project_history(pool: &PgPool, project_id: i32, days: i32) -> Result<Vec<StatsSnapshot>> {
+        Self::project_stats(pool, project_id).await?;
+
+        sqlx::query_as!(
+            StatsSnapshot,
+            r#"
+            SELECT day, word_count, metrics AS "metrics: SqlJson<WritingMetrics>"
+            FROM project_stats_daily
+            WHERE project_id = $1 AND day > CURRENT_DATE - $2::INT
+            ORDER BY day ASC
+            "#,
+            project_id,
+            days
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+
+    /// Refreshes the statistics of a saved document and of its projects
+    pub async fn refresh_after_save(pool: &PgPool, document_id: i32) -> Result<()> {
+        Self::document_stats(pool, document_id).await?;
+
+        let projects = sqlx::query!("SELECT project_id FROM document_projects WHERE document_id = $1", document_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+        for project in projects {
+            Self::project_stats(pool, project.project_id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Refreshes the statistics of a saved document on the tokio runtime
+pub fn spawn_stats_refresh(pool: PgPool, document_id: i32) {
+    tokio::spawn(async move {
+        if let Err(e) = StatsManager::refresh_after_save(&pool, document_id).await {
+            eprintln!("->> {:<12} - Stats refresh for document {} failed: {:?}", "STATS", document_id, e);
+        }
+    });
+}
@@ -8,9 +8,6 @@ pub struct StorageConfig {
     /// Total database storage in bytes (default: 15GB - Render Basic plan)
     pub total_db_storage: i64,
     
-    /// Default max storage per user in bytes (default: 10MB)
-    pub default_user_quota: i64,
-    
     /// Maximum number of users we expect to support
     pub expected_max_users: i32,
 }
@@ -22,11 +19,6 @@ impl Default for StorageConfig {
             .and_then(|v| v.parse::<i64>().ok())
             .unwrap_or(15); // Default: 15GB (Render Basic plan)
             
-        let user_quota_mb = env::var("USER_STORAGE_QUOTA_MB")
-            .ok()
-            .and_then(|v| v.parse::<i64>().ok())
-            .unwrap_or(10); // Default: 10MB per user
-            
         let expected_max_users = env::var("EXPECTED_MAX_USERS")
             .ok()
             .and_then(|v| v.parse::<i32>().ok())
@@ -34,7 +26,6 @@ impl Default for StorageConfig {
             
         StorageConfig {
             total_db_storage: total_storage_gb * 1024 * 1024 * 1024, // Convert GB to bytes
-            default_user_quota: user_quota_mb * 1024 * 1024, // Convert MB to bytes
             expected_max_users,
         }
     }
@@ -61,11 +52,6 @@ impl StorageManager {
         StorageConfig::default().total_db_storage
     }
     
-    /// Get user storage quota
-    pub fn get_user_quota() -> i64 {
-        StorageConfig::default().default_user_quota
-    }
-    
     /// Get current database usage percentage
     pub async fn get_db_usage_percentage(pool: &PgPool) -> Result<f64, sqlx::Error> {
         let current_size = Self::get_db_size(pool).await?;
@@ -0,0 +1,317 @@
+// src/models/tags.rs
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::rag::retrieval::DOCUMENT_ACCESS_FILTER;
+use crate::{Error, Result};
+
+/// Longest tag name, in characters
+pub const MAX_TAG_CHARS: usize = 50;
+/// Most tags on one document
+pub const MAX_TAGS_PER_DOCUMENT: usize = 20;
+/// Related documents returned by default
+pub const DEFAULT_RELATED_DOCUMENTS: i64 = 5;
+/// Most related documents returned at once
+pub const MAX_RELATED_DOCUMENTS: i64 = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct TagFilterParams {
+    /// Only list documents with this tag, or projects with a document with this tag
+    pub tag: Option<String>,
+}
+
+impl TagFilterParams {
+    /// The normalized tag to filter by, an empty tag filters nothing
+    pub fn tag(&self) -> Option<String> {
+        self.tag.as_deref().and_then(normalize_tag)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DocumentTagsPayload {
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RelatedDocumentsParams {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionKind {
+    /// A keyword of the document, e.g. "lighthouse"
+    Tag,
+    /// A broader subject the document is about, e.g. "maritime history"
+    Topic,
+}
+
+impl SuggestionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuggestionKind::Tag => "tag",
+            SuggestionKind::Topic => "topic",
+        }
+    }
+
+    fn from_db(kind: &str) -> Self {
+        if kind == "topic" { SuggestionKind::Topic } else { SuggestionKind::Tag }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagSuggestion {
+    pub name: String,
+    pub kind: SuggestionKind,
+}
+
+/// The tags of a document in alphabetical order, and the suggestions of the AI that are not tags yet
+#[derive(Debug, Serialize)]
+pub struct DocumentTags {
+    pub document_id: i32,
+    pub tags: Vec<String>,
+    pub suggestions: Vec<TagSuggestion>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagCount {
+    pub name: String,
+    pub document_count: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RelatedDocument {
+    pub id: i32,
+    pub name: String,
+    /// Cosine similarity of the two documents' embeddings
+    pub score: f64,
+    /// Tags both documents have
+    pub shared_tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelatedDocuments {
+    pub document_id: i32,
+    /// The document has no embedding yet, related documents are found once the embedding worker reaches it
+    pub pending: bool,
+    /// The most related first
+    pub documents: Vec<RelatedDocument>,
+}
+
+/// Tags and topics the model suggests for a document
+#[derive(Debug, Deserialize)]
+pub struct LlmTagSuggestions {
+    pub tags: Vec<String>,
+    pub topics: Vec<String>,
+}
+
+/// A tag as stored: trimmed, lowercase, single spaces and without a leading '#'.
+/// None for empty or too long names.
+pub fn normalize_tag(name: &str) -> Option<String> {
+    let name = name.trim().trim_start_matches('#').split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    if name.is_empty() || name.chars().count() > MAX_TAG_CHARS {
+        return None;
+    }
+    Some(name)
+}
+
+pub struct TagManager;
+
+impl TagManager {
+    pub async fn for_document(pool: &PgPool, document_id: i32) -> Result<DocumentTags> {
+        let tags = sqlx::query_scalar!(
+            r#"
+            SELECT t.name FROM document_tags dt
+            JOIN tags t ON t.id = dt.tag_id
+            WHERE dt.document_id = $1
+            ORDER BY t.name
+            "#,
+            document_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        let suggestions = sqlx::query!(
+            r#"
+            SELECT t.name, s.kind FROM document_tag_suggestions s
+            JOIN tags t ON t.id = s.tag_id
+            WHERE s.document_id = $1
+              AND NOT EXISTS (SELECT 1 FROM document_tags dt WHERE dt.document_id = s.document_id AND dt.tag_id = s.tag_id)
+            ORDER BY s.kind, t.name
+            "#,
+            document_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .into_iter()
+        .map(|row| TagSuggestion { name: row.name, kind: SuggestionKind::from_db(&row.kind) })
+        .collect();
+
+        Ok(DocumentTags { document_id, tags, suggestions })
+    }
+
+    /// Replaces the tags of a document. Accepting a suggestion is adding it to the tags.
+    pub async fn set_document_tags(pool: &PgPool, document_id: i32, user_id: i32, names: &[String]) -> Result<DocumentTags> {
+        let mut tags: Vec<String> = Vec::new();
+        for name in names {
+            let tag = normalize_tag(name).ok_or(Error::InvalidRequestFormatError)?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        if tags.len() > MAX_TAGS_PER_DOCUMENT {
+            return Err(Error::InvalidRequestFormatError);
+        }
+
+        let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+        let tag_ids = tag_ids(&mut tx, &tags).await?;
+        sqlx::query!(
+            "DELETE FROM document_tags WHERE document_id = $1 AND NOT (tag_id = ANY($2))",
+            document_id,
+            &tag_ids
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        sqlx::query!(
+            r#"
+            INSERT INTO document_tags (document_id, tag_id, added_by)
+            SELECT $1, UNNEST($2::int[]), $3
+            ON CONFLICT (document_id, tag_id) DO NOTHING
+            "#,
+            document_id,
+            &tag_ids,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        tx.commit().await.map_err(|_| Error::DatabaseError)?;
+
+        println!("->> {:<12} - Document {} tagged {:?} by user {}", "TAGS", document_id, tags, user_id);
+        Self::for_document(pool, document_id).await
+    }
+
+    /// Replaces the suggestions of a document with the model's answer, returns the number stored
+    pub async fn store_suggestions(pool: &PgPool, document_id: i32, suggestions: Vec<(String, SuggestionKind)>) -> Result<usize> {
+        let names: Vec<String> = suggestions.iter().map(|(name, _)| name.clone()).collect();
+        let kinds: Vec<String> = suggestions.iter().map(|(_, kind)| kind.as_str().to_string()).collect();
+
+        let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+        tag_ids(&mut tx, &names).await?;
+        sqlx::query!("DELETE FROM document_tag_suggestions WHERE document_id = $1", document_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+        sqlx::query!(
+            r#"
+            INSERT INTO document_tag_suggestions (document_id, tag_id, kind)
+            SELECT $1, t.id, s.kind
+            FROM UNNEST($2::text[], $3::text[]) AS s(name, kind)
+            JOIN tags t ON t.name = s.name
+            ON CONFLICT (document_id, tag_id) DO NOTHING
+            "#,
+            document_id,
+            &names,
+            &kinds
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        tx.commit().await.map_err(|_| Error::DatabaseError)?;
+
+        Ok(suggestions.len())
+    }
+
+    pub async fn dismiss_suggestions(pool: &PgPool, document_id: i32) -> Result<()> {
+        sqlx::query!("DELETE FROM document_tag_suggestions WHERE document_id = $1", document_id)
+            .execute(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+        Ok(())
+    }
+
+    /// The tags on documents the user can read, the most used first
+    pub async fn for_user(pool: &PgPool, user_id: i32) -> Result<Vec<TagCount>> {
+        let query = format!(
+            "SELECT t.name, COUNT(DISTINCT d.id) AS document_count \
+             FROM tags t \
+             JOIN document_tags dt ON dt.tag_id = t.id \
+             JOIN documents d ON d.id = dt.document_id \
+             WHERE d.is_trashed = false AND {} \
+             GROUP BY t.name \
+             ORDER BY document_count DESC, t.name",
+            DOCUMENT_ACCESS_FILTER
+        );
+        let rows: Vec<(String, i64)> = sqlx::query_as(&query)
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                eprintln!("DB Error listing tags: {:?}", e);
+                Error::DatabaseError
+            })?;
+
+        Ok(rows.into_iter().map(|(name, document_count)| TagCount { name, document_count }).collect())
+    }
+
+    /// The documents the user can read whose embeddings are the most similar to the document's.
+    /// Permissions on the document itself are checked by the caller.
+    pub async fn related_documents(pool: &PgPool, user_id: i32, document_id: i32, limit: i64) -> Result<RelatedDocuments> {
+        let pending = sqlx::query_scalar!(r#"SELECT embedding IS NULL AS "pending!" FROM documents WHERE id = $1"#, document_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?
+            .ok_or(Error::DocumentNotFoundError { document_id })?;
+        if pending {
+            return Ok(RelatedDocuments { document_id, pending, documents: Vec::new() });
+        }
+
+        let query = format!(
+            "SELECT d.id, d.name, (1 - (d.embedding <=> target.embedding))::float8 AS score, \
+                    ARRAY(SELECT t.name::text FROM document_tags own \
+                          JOIN document_tags other ON other.tag_id = own.tag_id AND other.document_id = target.id \
+                          JOIN tags t ON t.id = own.tag_id \
+                          WHERE own.document_id = d.id ORDER BY t.name) AS shared_tags \
+             FROM documents d \
+             JOIN documents target ON target.id = $2 \
+             WHERE d.id <> target.id \
+               AND d.embedding IS NOT NULL \
+               AND d.is_trashed = false \
+               AND {} \
+             ORDER BY d.embedding <=> target.embedding, d.id \
+             LIMIT $3",
+            DOCUMENT_ACCESS_FILTER
+        );
+        let documents = sqlx::query_as::<_, RelatedDocument>(&query)
+            .bind(user_id)
+            .bind(document_id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                eprintln!("DB Error finding related documents: {:?}", e);
+                Error::DatabaseError
+            })?;
+
+        Ok(RelatedDocuments { document_id, pending, documents })
+    }
+}
+
+// Ids of the tags with the given normalized names, created as needed
+async fn tag_ids(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, names: &[String]) -> Result<Vec<i32>> {
+    sqlx::query!(
+        "INSERT INTO tags (name) SELECT UNNEST($1::text[]) ON CONFLICT (name) DO NOTHING",
+        names
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    sqlx::query_scalar!("SELECT id FROM tags WHERE name = ANY($1)", names)
+        .fetch_all(&mut **tx)
+        .await
+        .map_err(|_| Error::DatabaseError)
+}
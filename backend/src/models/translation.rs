@@ -0,0 +1,420 @@
+// src/models/translation.rs
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::rag::translate::normalize_language;
+use crate::{Error, Result};
+
+/// Longest glossary term or fixed translation, in characters
+pub const MAX_GLOSSARY_TERM_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GlossaryRule {
+    /// The term is never translated, e.g. names and brands
+    Keep,
+    /// The term is always translated as `translation` into `language`
+    Translate,
+}
+
+impl GlossaryRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GlossaryRule::Keep => "keep",
+            GlossaryRule::Translate => "translate",
+        }
+    }
+
+    fn from_db(rule: &str) -> Self {
+        match rule {
+            "translate" => GlossaryRule::Translate,
+            _ => GlossaryRule::Keep,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GlossaryTerm {
+    pub id: i32,
+    pub project_id: i32,
+    pub term: String,
+    pub rule: GlossaryRule,
+    pub translation: Option<String>,
+    /// Target language the rule applies to, every language for keep rules without one
+    pub language: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+struct GlossaryRow {
+    id: i32,
+    project_id: i32,
+    term: String,
+    rule: String,
+    translation: Option<String>,
+    language: Option<String>,
+    notes: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+// Cleaned fields of a valid GlossaryTermPayload
+struct ValidTerm {
+    term: String,
+    translation: Option<String>,
+    language: Option<String>,
+    notes: Option<String>,
+}
+
+impl From<GlossaryRow> for GlossaryTerm {
+    fn from(row: GlossaryRow) -> Self {
+        GlossaryTerm {
+            id: row.id,
+            project_id: row.project_id,
+            term: row.term,
+            rule: GlossaryRule::from_db(&row.rule),
+            translation: row.translation,
+            language: row.language,
+            notes: row.notes,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// Creates or replaces a glossary term
+#[derive(Debug, Deserialize)]
+pub struct GlossaryTermPayload {
+    pub term: String,
+    pub rule: GlossaryRule,
+    /// Required for translate rules
+    pub translation: Option<String>,
+    /// Language code, required for translate rules
+    pub language: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// A glossary term the translation does not follow
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct GlossaryViolation {
+    pub term: String,
+    /// Text the translation must contain for every occurrence of the term
+    pub expected: String,
+    pub source_occurrences: usize,
+    pub translation_occurrences: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TranslateTextPayload {
+    pub content: String,
+    pub target_language: String,
+    /// Detected by the model when missing
+    pub source_language: Option<String>,
+    /// Project whose glossary applies
+    pub project_id: Option<i32>,
+    /// Document the selection is from, the glossaries of its projects apply when no project is given
+    pub document_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TranslateDocumentPayload {
+    pub target_language: String,
+    pub source_language: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TranslationResult {
+    pub translation: String,
+    pub target_language: String,
+    /// Glossary rules still broken after the repair attempts
+    pub glossary_violations: Vec<GlossaryViolation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DocumentTranslationResult {
+    /// The translated document
+    pub document_id: i32,
+    pub source_document_id: i32,
+    pub target_language: String,
+    /// False when an existing translation was updated
+    pub created: bool,
+    pub glossary_violations: Vec<GlossaryViolation>,
+}
+
+/// A document of a project that could not be translated, with the reason
+#[derive(Debug, Serialize)]
+pub struct DocumentTranslationFailure {
+    pub document_id: i32,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectTranslationResult {
+    pub project_id: i32,
+    pub target_language: String,
+    pub documents: Vec<DocumentTranslationResult>,
+    /// Documents whose translation is up to date
+    pub up_to_date: Vec<i32>,
+    /// Documents of the project the user cannot read
+    pub skipped: Vec<i32>,
+    /// Documents whose translation failed, the others are translated anyway
+    pub failures: Vec<DocumentTranslationFailure>,
+}
+
+/// A translated document and its source
+#[derive(Debug, Serialize)]
+pub struct TranslationLink {
+    pub document_id: i32,
+    pub document_name: String,
+    pub source_document_id: i32,
+    pub source_name: String,
+    pub target_language: String,
+    pub translated_at: NaiveDateTime,
+    /// The source changed since it was translated
+    pub stale: bool,
+}
+
+/// The source of a document, if it is a translation, and its translations
+#[derive(Debug, Serialize)]
+pub struct DocumentTranslations {
+    pub source: Option<TranslationLink>,
+    pub translations: Vec<TranslationLink>,
+}
+
+/// Glossaries of projects, followed by every translation of their documents
+pub struct Glossary;
+
+impl Glossary {
+    pub async fn list(pool: &PgPool, project_id: i32) -> Result<Vec<GlossaryTerm>> {
+        let rows = sqlx::query_as!(
+            GlossaryRow,
+            r#"
+            SELECT id, project_id, term, rule, translation, language, notes, created_at
+            FROM project_glossary_terms
+            WHERE project_id = $1
+            ORDER BY LOWER(term) ASC, language ASC NULLS FIRST
+            "#,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(rows.into_iter().map(GlossaryTerm::from).collect())
+    }
+
+    /// Terms of the glossaries of all projects of a document
+    pub async fn for_document(pool: &PgPool, document_id: i32) -> Result<Vec<GlossaryTerm>> {
+        let rows = sqlx::query_as!(
+            GlossaryRow,
+            r#"
+            SELECT g.id, g.project_id, g.term, g.rule, g.translation, g.language, g.notes, g.created_at
+            FROM project_glossary_terms g
+            JOIN document_projects dp ON dp.project_id = g.project_id
+            WHERE dp.document_id = $1
+            ORDER BY g.id ASC
+            "#,
+            document_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(rows.into_iter().map(GlossaryTerm::from).collect())
+    }
+
+    pub async fn create(pool: &PgPool, project_id: i32, payload: &GlossaryTermPayload) -> Result<GlossaryTerm> {
+        let ValidTerm { term, translation, language, notes } = validate_term(payload)?;
+        let row = sqlx::query_as!(
+            GlossaryRow,
+            r#"
+            INSERT INTO project_glossary_terms (project_id, term, rule, translation, language, notes)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, project_id, term, rule, translation, language, notes, created_at
+            "#,
+            project_id,
+            term,
+            payload.rule.as_str(),
+            translation,
+            language,
+            notes
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| term_write_error(e, &term))?;
+        Ok(row.into())
+    }
+
+    pub async fn update(pool: &PgPool, project_id: i32, term_id: i32, payload: &GlossaryTermPayload) -> Result<GlossaryTerm> {
+        let ValidTerm { term, translation, language, notes } = validate_term(payload)?;
+        let row = sqlx::query_as!(
+            GlossaryRow,
+            r#"
+            UPDATE project_glossary_terms
+            SET term = $3, rule = $4, translation = $5, language = $6, notes = $7
+            WHERE id = $1 AND project_id = $2
+            RETURNING id, project_id, term, rule, translation, language, notes, created_at
+            "#,
+            term_id,
+            project_id,
+            term,
+            payload.rule.as_str(),
+            translation,
+            language,
+            notes
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| term_write_error(e, &term))?
+        .ok_or(Error::GlossaryTermNotFoundError { term_id })?;
+        Ok(row.into())
+    }
+
+    pub async fn delete(pool: &PgPool, project_id: i32, term_id: i32) -> Result<()> {
+        let deleted = sqlx::query!(
+            "DELETE FROM project_glossary_terms WHERE id = $1 AND project_id = $2",
+            term_id,
+            project_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::GlossaryTermNotFoundError { term_id });
+        }
+        Ok(())
+    }
+}
+
+// Trimmed term, translation, normalized language and notes of a valid payload
+fn validate_term(payload: &GlossaryTermPayload) -> Result<ValidTerm> {
+    let clean = |value: &Option<String>| value.as_deref().map(str::trim).filter(|value| !value.is_empty()).map(str::to_string);
+    let term = payload.term.trim().to_string();
+    let translation = clean(&payload.translation);
+    let language = match clean(&payload.language) {
+        Some(language) => Some(normalize_language(&language).ok_or(Error::InvalidRequestFormatError)?),
+        None => None,
+    };
+
+    let too_long = |value: &str| value.chars().count() > MAX_GLOSSARY_TERM_CHARS;
+    let valid = !term.is_empty()
+        && !too_long(&term)
+        && !translation.as_deref().is_some_and(too_long)
+        && match payload.rule {
+            GlossaryRule::Keep => translation.is_none(),
+            GlossaryRule::Translate => translation.is_some() && language.is_some(),
+        };
+    if !valid {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    Ok(ValidTerm { term, translation, language, notes: clean(&payload.notes) })
+}
+
+// A term is unique per project and language, ignoring case
+fn term_write_error(error: sqlx::Error, term: &str) -> Error {
+    match error {
+        sqlx::Error::Database(e) if e.is_unique_violation() => Error::GlossaryTermExistsError { term: term.to_string() },
+        _ => Error::DatabaseError,
+    }
+}
+
+/// Links between translated documents and their sources.
+/// A translation is stale when the md5 of its source differs from the one it was translated from.
+pub struct TranslationManager;
+
+impl TranslationManager {
+    /// Translation of a document into a language that is not in the trash
+    pub async fn find_translation(pool: &PgPool, source_document_id: i32, target_language: &str) -> Result<Option<i32>> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT t.document_id
+            FROM document_translations t
+            JOIN documents d ON d.id = t.document_id
+            WHERE t.source_document_id = $1 AND t.target_language = $2 AND NOT COALESCE(d.is_trashed, FALSE)
+            ORDER BY t.translated_at DESC
+            LIMIT 1
+            "#,
+            source_document_id,
+            target_language
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+
+    /// Whether the translation of a document into a language is missing or stale
+    pub async fn needs_translation(pool: &PgPool, source_document_id: i32, target_language: &str) -> Result<bool> {
+        let up_to_date = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM document_translations t
+                JOIN documents d ON d.id = t.document_id
+                JOIN documents s ON s.id = t.source_document_id
+                WHERE t.source_document_id = $1 AND t.target_language = $2 AND NOT COALESCE(d.is_trashed, FALSE)
+                  AND t.source_hash = md5(COALESCE(s.content, ''))
+            ) AS "up_to_date!"
+            "#,
+            source_document_id,
+            target_language
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(!up_to_date)
+    }
+
+    /// Records that `document_id` translates the current content of `source_document_id`
+    pub async fn link(pool: &PgPool, document_id: i32, source_document_id: i32, target_language: &str, source_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO document_translations (document_id, source_document_id, target_language, source_hash, translated_at)
+            VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP)
+            ON CONFLICT (document_id) DO UPDATE SET
+                source_document_id = EXCLUDED.source_document_id, target_language = EXCLUDED.target_language,
+                source_hash = EXCLUDED.source_hash, translated_at = EXCLUDED.translated_at
+            "#,
+            document_id,
+            source_document_id,
+            target_language,
+            source_hash
+        )
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        Ok(())
+    }
+
+    /// The source of a document and its translations, trashed documents left out
+    pub async fn for_document(pool: &PgPool, document_id: i32) -> Result<DocumentTranslations> {
+        let mut links = Self::links(pool, Some(document_id), None).await?;
+        let source = links.iter().position(|link| link.document_id == document_id).map(|index| links.remove(index));
+        Ok(DocumentTranslations { source, translations: links })
+    }
+
+    /// Translations whose source is in a project
+    pub async fn for_project(pool: &PgPool, project_id: i32) -> Result<Vec<TranslationLink>> {
+        Self::links(pool, None, Some(project_id)).await
+    }
+
+    async fn links(pool: &PgPool, document_id: Option<i32>, project_id: Option<i32>) -> Result<Vec<TranslationLink>> {
+        sqlx::query_as!(
+            TranslationLink,
+            r#"
+            SELECT t.document_id, d.name AS document_name, t.source_document_id, s.name AS source_name,
+                   t.target_language, t.translated_at,
+                   t.source_hash <> md5(COALESCE(s.content, '')) AS "stale!"
+            FROM document_translations t
+            JOIN documents d ON d.id = t.document_id
+            JOIN documents s ON s.id = t.source_document_id
+            WHERE NOT COALESCE(d.is_trashed, FALSE) AND NOT COALESCE(s.is_trashed, FALSE)
+              AND ($1::INT IS NULL OR t.document_id = $1 OR t.source_document_id = $1)
+              AND ($2::INT IS NULL OR t.source_document_id IN (SELECT document_id FROM document_projects WHERE project_id = $2))
+            ORDER BY s.name ASC, t.target_language ASC
+            "#,
+            document_id,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)
+    }
+}
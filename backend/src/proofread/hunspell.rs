@@ -0,0 +1,429 @@
+// Hunspell-format dictionaries
+//
+// Reads the .aff/.dic pair of a Hunspell dictionary (e.g. en_US from LibreOffice) and expands
+// every stem with its prefix and suffix rules into the set of valid word forms up front, so a
+// lookup is a single hash probe. Supported from the .aff file:
+//   - SET (only UTF-8 and ISO8859-1 are decoded), FLAG (single character, long, num, UTF-8)
+//   - PFX / SFX with strip, add, condition and cross product; continuation flags are ignored
+//   - TRY and REP for suggestions, NEEDAFFIX, FORBIDDENWORD, NOSUGGEST
+// Compounding and morphology are not supported, words with digits are never checked.
+
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Suggestions returned for a misspelled word
+const MAX_SUGGESTIONS: usize = 5;
+/// Words longer than this only get single edit suggestions
+const MAX_DOUBLE_EDIT_CHARS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FlagType {
+    Char,
+    Long,
+    Num,
+}
+
+// One line of a PFX/SFX group
+#[derive(Debug)]
+struct AffixRule {
+    strip: String,
+    add: String,
+    condition: Option<Regex>,
+}
+
+#[derive(Debug)]
+struct AffixGroup {
+    cross_product: bool,
+    rules: Vec<AffixRule>,
+}
+
+/// A loaded dictionary
+pub struct Dictionary {
+    /// Valid word forms as written in the dictionary
+    words: HashSet<String>,
+    /// Lowercased forms, for words written with a capital at the start of a sentence
+    lowercase_words: HashSet<String>,
+    /// Forms that must never be suggested
+    no_suggest: HashSet<String>,
+    /// Characters tried when generating suggestions, most common first
+    try_chars: Vec<char>,
+    /// Common misspellings, (from, to)
+    replacements: Vec<(String, String)>,
+}
+
+impl Dictionary {
+    /// Loads `<base>.aff` and `<base>.dic`
+    pub fn load(aff_path: &Path, dic_path: &Path) -> Result<Self, String> {
+        let aff_bytes = fs::read(aff_path).map_err(|e| format!("cannot read {}: {}", aff_path.display(), e))?;
+        let dic_bytes = fs::read(dic_path).map_err(|e| format!("cannot read {}: {}", dic_path.display(), e))?;
+
+        // The encoding is declared inside the .aff file, which is ASCII up to that point
+        let encoding = String::from_utf8_lossy(&aff_bytes)
+            .lines()
+            .find_map(|line| line.strip_prefix("SET ").map(|value| value.trim().to_uppercase()))
+            .unwrap_or_else(|| "UTF-8".to_string());
+        let aff = decode(&aff_bytes, &encoding);
+        let dic = decode(&dic_bytes, &encoding);
+        Self::parse(&aff, &dic)
+    }
+
+    /// Builds a dictionary from the contents of an .aff and a .dic file
+    pub fn parse(aff: &str, dic: &str) -> Result<Self, String> {
+        let mut flag_type = FlagType::Char;
+        let mut prefixes: HashMap<String, AffixGroup> = HashMap::new();
+        let mut suffixes: HashMap<String, AffixGroup> = HashMap::new();
+        let mut try_chars: Vec<char> = Vec::new();
+        let mut replacements = Vec::new();
+        let mut need_affix = None;
+        let mut forbidden = None;
+        let mut no_suggest_flag = None;
+
+        for line in aff.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["FLAG", value, ..] => {
+                    flag_type = match *value {
+                        "long" => FlagType::Long,
+                        "num" => FlagType::Num,
+                        _ => FlagType::Char,
+                    };
+                }
+                ["TRY", chars, ..] => try_chars = chars.chars().collect(),
+                ["REP", from, to, ..] => replacements.push((from.replace('_', " "), to.replace('_', " "))),
+                ["NEEDAFFIX", flag, ..] => need_affix = Some(flag.to_string()),
+                ["FORBIDDENWORD", flag, ..] => forbidden = Some(flag.to_string()),
+                ["NOSUGGEST", flag, ..] => no_suggest_flag = Some(flag.to_string()),
+                [kind @ ("PFX" | "SFX"), flag, cross, count] if count.parse::<usize>().is_ok() && (*cross == "Y" || *cross == "N") => {
+                    let groups = if *kind == "PFX" { &mut prefixes } else { &mut suffixes };
+                    groups.insert(flag.to_string(), AffixGroup { cross_product: *cross == "Y", rules: Vec::new() });
+                }
+                [kind @ ("PFX" | "SFX"), flag, strip, add, rest @ ..] => {
+                    let is_prefix = *kind == "PFX";
+                    let groups = if is_prefix { &mut prefixes } else { &mut suffixes };
+                    let Some(group) = groups.get_mut(*flag) else {
+                        continue;
+                    };
+                    // "0" stands for nothing, continuation flags after "/" are not supported
+                    let strip = if *strip == "0" { String::new() } else { strip.to_string() };
+                    let add = add.split('/').next().unwrap_or("");
+                    let add = if add == "0" { String::new() } else { add.to_string() };
+                    let condition = match rest.first() {
+                        Some(condition) if *condition != "." => Some(condition_regex(condition, is_prefix)?),
+                        _ => None,
+                    };
+                    group.rules.push(AffixRule { strip, add, condition });
+                }
+                _ => {}
+            }
+        }
+
+        let mut words = HashSet::new();
+        let mut no_suggest = HashSet::new();
+        let mut forbidden_words = HashSet::new();
+
+        // The first line is the approximate number of entries
+        for line in dic.lines().skip(1) {
+            let entry = line.split(['\t', ' ']).next().unwrap_or("").trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let (stem, flags) = split_entry(entry);
+            let flags = parse_flags(flags, flag_type);
+            let has = |flag: &Option<String>| flag.as_ref().is_some_and(|flag| flags.contains(flag));
+
+            let forms = expand(stem, &flags, &prefixes, &suffixes, has(&need_affix));
+            if has(&forbidden) {
+                forbidden_words.extend(forms);
+            } else {
+                if has(&no_suggest_flag) {
+                    no_suggest.extend(forms.iter().cloned());
+                }
+                words.extend(forms);
+            }
+        }
+        for word in &forbidden_words {
+            words.remove(word);
+        }
+        if words.is_empty() {
+            return Err("the dictionary has no words".to_string());
+        }
+
+        if try_chars.is_empty() {
+            try_chars = "esianrtolcdugmphbyfvkwzxjq'".chars().collect();
+        }
+        let lowercase_words = words.iter().map(|word| word.to_lowercase()).collect();
+        Ok(Dictionary { words, lowercase_words, no_suggest, try_chars, replacements })
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Whether a word is spelled correctly. A capitalized or all caps word is also correct
+    /// when its lowercase form is (sentence starts, headings); the reverse is not true ("paris").
+    pub fn check(&self, word: &str) -> bool {
+        if self.words.contains(word) {
+            return true;
+        }
+        let lowercase = word.to_lowercase();
+        let mut chars = word.chars();
+        let first_upper = chars.next().is_some_and(char::is_uppercase);
+        let rest_lower = chars.clone().all(|c| !c.is_uppercase());
+        let all_upper = word.chars().all(|c| !c.is_lowercase());
+
+        if first_upper && (rest_lower || all_upper) && self.words.contains(&lowercase) {
+            return true;
+        }
+        // "PARIS" is fine if "Paris" is
+        all_upper && self.words.contains(&capitalize(&lowercase))
+    }
+
+    /// Likely intended words for a misspelling, best first, in the case of the original
+    pub fn suggest(&self, word: &str) -> Vec<String> {
+        let lowercase = word.to_lowercase();
+        let mut suggestions: Vec<String> = Vec::new();
+        let push = |candidate: String, suggestions: &mut Vec<String>| {
+            if !suggestions.contains(&candidate) && suggestions.len() < MAX_SUGGESTIONS {
+                suggestions.push(candidate);
+            }
+        };
+
+        // Known misspellings first
+        for (from, to) in &self.replacements {
+            let mut start = 0;
+            while let Some(position) = lowercase[start..].find(from.as_str()) {
+                let at = start + position;
+                let candidate = format!("{}{}{}", &lowercase[..at], to, &lowercase[at + from.len()..]);
+                if self.suggestable(&candidate) {
+                    push(self.known_form(&candidate), &mut suggestions);
+                }
+                start = at + from.len().max(1);
+                if start >= lowercase.len() {
+                    break;
+                }
+            }
+        }
+
+        // Single edits, and two words run together
+        let single = self.edits(&lowercase);
+        for candidate in single.iter().filter(|candidate| self.suggestable(candidate)) {
+            push(self.known_form(candidate), &mut suggestions);
+        }
+        for (at, _) in lowercase.char_indices().skip(1) {
+            let (left, right) = lowercase.split_at(at);
+            if left.chars().count() > 1 && right.chars().count() > 1 && self.suggestable(left) && self.suggestable(right) {
+                push(format!("{} {}", self.known_form(left), self.known_form(right)), &mut suggestions);
+            }
+        }
+
+        // Two edits only if nothing closer was found
+        if suggestions.is_empty() && lowercase.chars().count() <= MAX_DOUBLE_EDIT_CHARS {
+            for first in &single {
+                for candidate in self.edits(first) {
+                    if self.suggestable(&candidate) {
+                        push(self.known_form(&candidate), &mut suggestions);
+                    }
+                }
+                if suggestions.len() >= MAX_SUGGESTIONS {
+                    break;
+                }
+            }
+        }
+
+        suggestions.into_iter().map(|suggestion| match_case(word, &suggestion)).collect()
+    }
+
+    // A lowercase candidate that may be suggested
+    fn suggestable(&self, candidate: &str) -> bool {
+        self.lowercase_words.contains(candidate) && !self.no_suggest.contains(candidate)
+    }
+
+    // The dictionary spelling of a lowercase candidate ("paris" -> "Paris")
+    fn known_form(&self, candidate: &str) -> String {
+        if self.words.contains(candidate) {
+            return candidate.to_string();
+        }
+        let capitalized = capitalize(candidate);
+        if self.words.contains(&capitalized) {
+            return capitalized;
+        }
+        self.words
+            .iter()
+            .find(|word| word.to_lowercase() == candidate)
+            .cloned()
+            .unwrap_or_else(|| candidate.to_string())
+    }
+
+    // Every string one deletion, transposition, replacement or insertion away, in that order
+    fn edits(&self, word: &str) -> Vec<String> {
+        let chars: Vec<char> = word.chars().collect();
+        let mut edits = Vec::new();
+        for i in 0..chars.len() {
+            edits.push(chars[..i].iter().chain(&chars[i + 1..]).collect());
+        }
+        for i in 0..chars.len().saturating_sub(1) {
+            let mut swapped = chars.clone();
+            swapped.swap(i, i + 1);
+            edits.push(swapped.into_iter().collect());
+        }
+        for i in 0..chars.len() {
+            for &c in &self.try_chars {
+                if c != chars[i] {
+                    let mut replaced = chars.clone();
+                    replaced[i] = c;
+                    edits.push(replaced.into_iter().collect());
+                }
+            }
+        }
+        for i in 0..=chars.len() {
+            for &c in &self.try_chars {
+                let mut inserted = chars.clone();
+                inserted.insert(i, c);
+                edits.push(inserted.into_iter().collect());
+            }
+        }
+        edits
+    }
+}
+
+// Stem and flags of a .dic entry, "/" inside a word is escaped as "\/"
+fn split_entry(entry: &str) -> (String, &str) {
+    let mut stem = String::new();
+    let mut chars = entry.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' if chars.peek().map(|(_, next)| *next) == Some('/') => {
+                stem.push('/');
+                chars.next();
+            }
+            '/' => return (stem, &entry[index + 1..]),
+            _ => stem.push(c),
+        }
+    }
+    (stem, "")
+}
+
+fn parse_flags(flags: &str, flag_type: FlagType) -> Vec<String> {
+    match flag_type {
+        FlagType::Char => flags.chars().map(|c| c.to_string()).collect(),
+        FlagType::Long => flags
+            .chars()
+            .collect::<Vec<_>>()
+            .chunks(2)
+            .map(|pair| pair.iter().collect())
+            .collect(),
+        FlagType::Num => flags.split(',').map(|flag| flag.trim().to_string()).filter(|flag| !flag.is_empty()).collect(),
+    }
+}
+
+// Hunspell conditions are a tiny regex dialect: characters, "." and [...] / [^...] classes
+fn condition_regex(condition: &str, is_prefix: bool) -> Result<Regex, String> {
+    let mut pattern = String::new();
+    let mut in_class = false;
+    for c in condition.chars() {
+        match c {
+            '[' => {
+                in_class = true;
+                pattern.push('[');
+            }
+            ']' => {
+                in_class = false;
+                pattern.push(']');
+            }
+            '^' if in_class => pattern.push('^'),
+            '.' if !in_class => pattern.push('.'),
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    let anchored = if is_prefix { format!("^(?:{})", pattern) } else { format!("(?:{})$", pattern) };
+    Regex::new(&anchored).map_err(|e| format!("invalid affix condition {}: {}", condition, e))
+}
+
+// Applies a rule to a word, None if its condition or strip does not match
+fn apply_rule(word: &str, rule: &AffixRule, is_prefix: bool) -> Option<String> {
+    if let Some(condition) = &rule.condition {
+        if !condition.is_match(word) {
+            return None;
+        }
+    }
+    if is_prefix {
+        let rest = word.strip_prefix(rule.strip.as_str())?;
+        Some(format!("{}{}", rule.add, rest))
+    } else {
+        let rest = word.strip_suffix(rule.strip.as_str())?;
+        Some(format!("{}{}", rest, rule.add))
+    }
+}
+
+// All forms of a stem: the stem itself (unless it needs an affix), with each prefix, with each
+// suffix, and with a prefix and a suffix when both allow the cross product
+fn expand(
+    stem: String,
+    flags: &[String],
+    prefixes: &HashMap<String, AffixGroup>,
+    suffixes: &HashMap<String, AffixGroup>,
+    needs_affix: bool,
+) -> Vec<String> {
+    let mut forms = Vec::new();
+    let mut suffixed: Vec<String> = Vec::new();
+
+    for group in flags.iter().filter_map(|flag| suffixes.get(flag)) {
+        for rule in &group.rules {
+            if let Some(form) = apply_rule(&stem, rule, false) {
+                if group.cross_product {
+                    suffixed.push(form.clone());
+                }
+                forms.push(form);
+            }
+        }
+    }
+    for group in flags.iter().filter_map(|flag| prefixes.get(flag)) {
+        for rule in &group.rules {
+            if let Some(form) = apply_rule(&stem, rule, true) {
+                forms.push(form);
+            }
+            if group.cross_product {
+                for word in &suffixed {
+                    if let Some(form) = apply_rule(word, rule, true) {
+                        forms.push(form);
+                    }
+                }
+            }
+        }
+    }
+
+    if !needs_affix {
+        forms.push(stem);
+    }
+    forms
+}
+
+fn decode(bytes: &[u8], encoding: &str) -> String {
+    match encoding {
+        // Latin-1 maps every byte to the code point of the same value
+        "ISO8859-1" | "ISO-8859-1" => bytes.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// Writes a suggestion in the case of the misspelled word
+fn match_case(original: &str, suggestion: &str) -> String {
+    let letters: Vec<char> = original.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+        suggestion.to_uppercase()
+    } else if original.chars().next().is_some_and(char::is_uppercase) {
+        capitalize(suggestion)
+    } else {
+        suggestion.to_string()
+    }
+}
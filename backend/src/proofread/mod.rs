@@ -0,0 +1,188 @@
+// Offline proofreading
+//
+// Spelling against Hunspell dictionaries plus rule-based style checks, without a model and
+// without AI credits. Dictionaries are read from DICTIONARY_DIR (default "dictionaries") as
+// `<language>.aff` / `<language>.dic` pairs, see deploy/fetch_dictionaries.sh, and are loaded
+// the first time a language is used. Without a dictionary only the style checks run.
+// The LLM grammar and spell checks of the writing assistant remain for deeper rewrites.
+
+pub mod hunspell;
+pub mod rules;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use hunspell::Dictionary;
+
+/// Language used when a request does not name one, override with DEFAULT_DICTIONARY
+pub const DEFAULT_LANGUAGE: &str = "en_US";
+
+lazy_static! {
+    static ref WORD: Regex = Regex::new(r"[\p{L}\p{N}]+(?:['’][\p{L}]+)*").unwrap();
+    // Spans that are not prose and should not be spell checked
+    static ref NOT_PROSE: Regex = Regex::new(r"(?i)\b(?:https?://|www\.)\S+|\S+@\S+\.\w+").unwrap();
+    static ref LANGUAGE: Regex = Regex::new(r"^[a-z]{2,3}(?:_[A-Z]{2})?$").unwrap();
+    // Loaded dictionaries, None for languages without one so the files are only looked for once
+    static ref DICTIONARIES: Mutex<HashMap<String, Option<Arc<Dictionary>>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    Spelling,
+    RepeatedWord,
+    PassiveVoice,
+    LongSentence,
+}
+
+/// A problem in the checked text. Offsets are character offsets, `end_offset` is exclusive.
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub message: String,
+    pub start_offset: usize,
+    pub end_offset: usize,
+    pub text: String,
+    /// Replacements for the whole span, best first
+    pub suggestions: Vec<String>,
+}
+
+/// A word of the checked text, with character and byte offsets
+#[derive(Debug)]
+pub struct Token {
+    pub text: String,
+    pub lowercase: String,
+    pub start: usize,
+    pub end: usize,
+    pub start_byte: usize,
+    pub end_byte: usize,
+}
+
+pub fn default_language() -> String {
+    env::var("DEFAULT_DICTIONARY").unwrap_or_else(|_| DEFAULT_LANGUAGE.to_string())
+}
+
+fn dictionary_dir() -> PathBuf {
+    PathBuf::from(env::var("DICTIONARY_DIR").unwrap_or_else(|_| "dictionaries".to_string()))
+}
+
+/// Whether a language code is well formed ("en", "en_US"), it is used in file names
+pub fn is_valid_language(language: &str) -> bool {
+    LANGUAGE.is_match(language)
+}
+
+/// Languages with a dictionary in DICTIONARY_DIR, sorted
+pub fn available_languages() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dictionary_dir()) else {
+        return Vec::new();
+    };
+    let mut languages: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".aff").map(str::to_string))
+        .filter(|language| is_valid_language(language) && dictionary_dir().join(format!("{}.dic", language)).exists())
+        .collect();
+    languages.sort();
+    languages
+}
+
+/// The dictionary of a language, loading it on first use. Blocks while a dictionary is parsed,
+/// call it from a blocking task.
+pub fn dictionary(language: &str) -> Option<Arc<Dictionary>> {
+    if !is_valid_language(language) {
+        return None;
+    }
+    let mut dictionaries = DICTIONARIES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(loaded) = dictionaries.get(language) {
+        return loaded.clone();
+    }
+
+    let dir = dictionary_dir();
+    let aff = dir.join(format!("{}.aff", language));
+    let dic = dir.join(format!("{}.dic", language));
+    let loaded = if aff.exists() && dic.exists() {
+        match Dictionary::load(&aff, &dic) {
+            Ok(dictionary) => {
+                println!("->> {:<12} - Loaded {} dictionary ({} forms)", "PROOFREAD", language, dictionary.word_count());
+                Some(Arc::new(dictionary))
+            }
+            Err(e) => {
+                eprintln!("->> {:<12} - Failed to load {} dictionary: {}", "PROOFREAD", language, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    dictionaries.insert(language.to_string(), loaded.clone());
+    loaded
+}
+
+/// Splits text into words. Curly apostrophes are read as straight ones.
+pub fn tokenize(text: &str) -> Vec<Token> {
+    // Byte to character offsets, computed in one pass
+    let mut char_offsets = HashMap::new();
+    let mut chars = 0;
+    for (byte, _) in text.char_indices() {
+        char_offsets.insert(byte, chars);
+        chars += 1;
+    }
+    char_offsets.insert(text.len(), chars);
+
+    WORD.find_iter(text)
+        .map(|word| {
+            let normalized = word.as_str().replace('’', "'");
+            Token {
+                lowercase: normalized.to_lowercase(),
+                text: normalized,
+                start: char_offsets[&word.start()],
+                end: char_offsets[&word.end()],
+                start_byte: word.start(),
+                end_byte: word.end(),
+            }
+        })
+        .collect()
+}
+
+/// Checks text, sorted by position. `custom_words` are the user's own words, lowercase.
+pub fn check_text(text: &str, dictionary: Option<&Dictionary>, custom_words: &HashSet<String>) -> Vec<Issue> {
+    let tokens = tokenize(text);
+    let mut issues = Vec::new();
+
+    if let Some(dictionary) = dictionary {
+        let skipped: Vec<(usize, usize)> = NOT_PROSE.find_iter(text).map(|span| (span.start(), span.end())).collect();
+        let mut suggestions: HashMap<String, Vec<String>> = HashMap::new();
+
+        for token in &tokens {
+            let word = token.text.as_str();
+            if word.chars().count() < 2
+                || word.chars().any(|c| c.is_numeric())
+                || custom_words.contains(&token.lowercase)
+                || skipped.iter().any(|&(start, end)| token.start_byte >= start && token.end_byte <= end)
+                || dictionary.check(word)
+                || word.strip_suffix("'s").is_some_and(|base| dictionary.check(base))
+            {
+                continue;
+            }
+            let suggested = suggestions.entry(word.to_string()).or_insert_with(|| dictionary.suggest(word));
+            issues.push(Issue {
+                kind: IssueKind::Spelling,
+                message: format!("\"{}\" may be misspelled", word),
+                start_offset: token.start,
+                end_offset: token.end,
+                text: text[token.start_byte..token.end_byte].to_string(),
+                suggestions: suggested.clone(),
+            });
+        }
+    }
+
+    issues.extend(rules::repeated_words(text, &tokens));
+    issues.extend(rules::passive_voice(text, &tokens));
+    issues.extend(rules::long_sentences(text, &tokens));
+    issues.sort_by_key(|issue| (issue.start_offset, issue.end_offset));
+    issues
+}
@@ -0,0 +1,145 @@
+// Rule-based style checks
+//
+// Cheap heuristics that run without a model: the same word twice in a row, passive voice and
+// sentences that are too long to follow. They are hints, not errors, a passive sentence is
+// often the right choice.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::env;
+
+use super::{Issue, IssueKind, Token};
+
+/// Sentences with more words than this are reported, override with LONG_SENTENCE_WORDS
+const DEFAULT_LONG_SENTENCE_WORDS: usize = 35;
+
+lazy_static! {
+    // End of a sentence: terminal punctuation (and closing quotes) before whitespace, or a blank line
+    static ref SENTENCE_END: Regex = Regex::new(r#"[.!?]+["'”’)\]]*(?:\s+|$)|\n\s*\n"#).unwrap();
+}
+
+// Forms of "to be" that start a passive construction
+const BE_FORMS: &[&str] = &["am", "is", "are", "was", "were", "be", "been", "being", "isn't", "aren't", "wasn't", "weren't"];
+
+// Past participles that do not end in -ed
+const IRREGULAR_PARTICIPLES: &[&str] = &[
+    "awoken", "beaten", "become", "begun", "bent", "bitten", "blown", "broken", "brought", "built", "bought",
+    "caught", "chosen", "cut", "dealt", "done", "drawn", "driven", "eaten", "fallen", "fed", "felt", "fought",
+    "found", "forbidden", "forgotten", "forgiven", "frozen", "given", "grown", "heard", "held", "hidden", "hit",
+    "hung", "hurt", "kept", "known", "laid", "led", "left", "lent", "lost", "made", "meant", "met", "paid", "put",
+    "read", "ridden", "run", "said", "seen", "sent", "set", "shaken", "shot", "shown", "shut", "sold", "spent",
+    "spoken", "spread", "stolen", "struck", "sung", "sworn", "taken", "taught", "thrown", "told", "torn",
+    "thought", "understood", "upset", "won", "woken", "worn", "written",
+];
+
+// Adjectives ending in -ed that read as states rather than actions ("is tired")
+const ED_ADJECTIVES: &[&str] = &["interested", "tired", "bored", "excited", "worried", "married", "supposed", "used", "pleased", "scared", "embarrassed", "confused"];
+
+// Doubled words that are usually intentional ("had had", "that that")
+const ALLOWED_REPEATS: &[&str] = &["had", "that", "is", "bye", "ha", "no", "very"];
+
+fn long_sentence_words() -> usize {
+    env::var("LONG_SENTENCE_WORDS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|&words| words > 0)
+        .unwrap_or(DEFAULT_LONG_SENTENCE_WORDS)
+}
+
+/// The same word twice or more in a row, separated by whitespace only. A run is one issue.
+pub fn repeated_words(text: &str, tokens: &[Token]) -> Vec<Issue> {
+    let repeats = |first: &Token, second: &Token| {
+        first.lowercase == second.lowercase && text[first.end_byte..second.start_byte].chars().all(char::is_whitespace)
+    };
+
+    let mut issues = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        let first = &tokens[index];
+        let mut last = index;
+        while last + 1 < tokens.len() && repeats(&tokens[last], &tokens[last + 1]) {
+            last += 1;
+        }
+        if last > index && !ALLOWED_REPEATS.contains(&first.lowercase.as_str()) {
+            issues.push(Issue {
+                kind: IssueKind::RepeatedWord,
+                message: format!("\"{}\" is repeated", first.text),
+                start_offset: first.start,
+                end_offset: tokens[last].end,
+                text: text[first.start_byte..tokens[last].end_byte].to_string(),
+                suggestions: vec![first.text.clone()],
+            });
+        }
+        index = last + 1;
+    }
+    issues
+}
+
+/// A form of "to be" followed, optionally after an adverb, by a past participle
+pub fn passive_voice(text: &str, tokens: &[Token]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (index, be) in tokens.iter().enumerate() {
+        if !BE_FORMS.contains(&be.lowercase.as_str()) {
+            continue;
+        }
+        let mut next = index + 1;
+        if tokens.get(next).is_some_and(|token| token.lowercase.ends_with("ly") || token.lowercase == "not") {
+            next += 1;
+        }
+        let Some(participle) = tokens.get(next) else {
+            continue;
+        };
+        // Both words in the same sentence
+        if text[be.end_byte..participle.start_byte].contains(['.', '!', '?', ';', '\n']) {
+            continue;
+        }
+        let word = participle.lowercase.as_str();
+        let regular = word.chars().count() > 4 && word.ends_with("ed") && !ED_ADJECTIVES.contains(&word);
+        if regular || IRREGULAR_PARTICIPLES.contains(&word) {
+            issues.push(Issue {
+                kind: IssueKind::PassiveVoice,
+                message: format!("\"{} {}\" may be passive voice, consider naming who acts", be.text, participle.text),
+                start_offset: be.start,
+                end_offset: participle.end,
+                text: text[be.start_byte..participle.end_byte].to_string(),
+                suggestions: Vec::new(),
+            });
+        }
+    }
+    issues
+}
+
+/// Sentences with more than LONG_SENTENCE_WORDS words
+pub fn long_sentences(text: &str, tokens: &[Token]) -> Vec<Issue> {
+    let limit = long_sentence_words();
+    let mut issues = Vec::new();
+    let mut start_byte = 0;
+    let mut ends: Vec<usize> = SENTENCE_END.find_iter(text).map(|end| end.end()).collect();
+    ends.push(text.len());
+
+    for end_byte in ends {
+        if end_byte <= start_byte {
+            continue;
+        }
+        let words: Vec<&Token> = tokens
+            .iter()
+            .filter(|token| token.start_byte >= start_byte && token.end_byte <= end_byte)
+            .collect();
+        if let (Some(first), Some(last)) = (words.first(), words.last()) {
+            if words.len() > limit {
+                let sentence_end = text[last.end_byte..end_byte].trim_end().len() + last.end_byte;
+                let sentence = &text[first.start_byte..sentence_end];
+                issues.push(Issue {
+                    kind: IssueKind::LongSentence,
+                    message: format!("This sentence has {} words, consider splitting it (limit {})", words.len(), limit),
+                    start_offset: first.start,
+                    end_offset: first.start + sentence.chars().count(),
+                    text: sentence.to_string(),
+                    suggestions: Vec::new(),
+                });
+            }
+        }
+        start_byte = end_byte;
+    }
+    issues
+}
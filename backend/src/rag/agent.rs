@@ -0,0 +1,473 @@
+// Agent mode of the writing assistant
+//
+// Instead of one prompt with the top-k chunks, the model works in a bounded loop (see
+// ai_controller::api_agent_writing_message). Every step it answers with an LlmAgentStep: either
+// a tool call, which is run here and whose result is added to the next prompt, or the answer.
+//   - tools only see documents the user can read, every document they touch is checked with
+//     check_document_permission (propose_edit needs editor rights)
+//   - the scope is the project of the session's document, or the user's own documents
+//   - every call is logged in writing_assistant_tool_calls, linked to the answer once it is stored
+//   - propose_edit only validates search/replace edits (rag::patch), nothing is written
+
+use pgvector::Vector;
+use serde_json::{json, Value};
+use sqlx::types::Json as SqlJson;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::env;
+
+use crate::models::ai::{AgentToolCall, LlmAgentStep, LlmDocEdit, SuggestedDocumentChange};
+use crate::rag::chunk::to_plain_text;
+use crate::rag::embed::EmbeddingModel;
+use crate::rag::patch;
+use crate::rag::retrieval;
+use crate::rag::tokenizer::Tokenizer;
+use crate::web::middleware::middleware::check_document_permission;
+use crate::{Error, Result};
+
+/// Steps of the loop by default, the last one must answer (override with AGENT_MAX_STEPS)
+const DEFAULT_AGENT_MAX_STEPS: usize = 6;
+/// Upper bound for AGENT_MAX_STEPS
+const MAX_AGENT_STEPS: usize = 20;
+/// Chunks returned by a semantic search_project call
+const AGENT_SEARCH_K: i64 = 8;
+/// Characters of context around an exact match
+const SNIPPET_CHARS: usize = 60;
+
+pub fn max_steps() -> usize {
+    env::var("AGENT_MAX_STEPS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_AGENT_MAX_STEPS)
+        .clamp(1, MAX_AGENT_STEPS)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AgentTool {
+    SearchProject,
+    ReadDocument,
+    ListOutline,
+    CountWords,
+    ProposeEdit,
+}
+
+impl AgentTool {
+    pub const ALL: [AgentTool; 5] = [
+        AgentTool::SearchProject,
+        AgentTool::ReadDocument,
+        AgentTool::ListOutline,
+        AgentTool::CountWords,
+        AgentTool::ProposeEdit,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AgentTool::SearchProject => "search_project",
+            AgentTool::ReadDocument => "read_document",
+            AgentTool::ListOutline => "list_outline",
+            AgentTool::CountWords => "count_words",
+            AgentTool::ProposeEdit => "propose_edit",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|tool| tool.as_str() == name)
+    }
+
+    /// Checks that a step calling this tool has its arguments
+    pub fn check_arguments(&self, step: &LlmAgentStep) -> std::result::Result<(), String> {
+        let missing = |argument: &str| Err(format!("{} needs \"{}\"", self.as_str(), argument));
+        match self {
+            AgentTool::SearchProject if step.query.as_deref().is_none_or(|query| query.trim().is_empty()) => missing("query"),
+            AgentTool::ReadDocument | AgentTool::CountWords | AgentTool::ProposeEdit if step.document_id.is_none() => missing("document_id"),
+            AgentTool::ProposeEdit if step.search.is_none() => missing("search"),
+            AgentTool::ProposeEdit if step.replace.is_none() => missing("replace"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Description of the tools for the prompt
+    pub fn descriptions() -> Value {
+        json!([
+            {
+                "name": "search_project",
+                "arguments": { "query": "text to look for", "exact": "true to count literal matches in every document instead of a semantic search" },
+                "returns": "semantic: the most relevant passages with their document; exact: every document with its number of matches (also 0) and the first match"
+            },
+            {
+                "name": "read_document",
+                "arguments": { "document_id": "document to read", "offset": "character to start at, null for the beginning" },
+                "returns": "the stored content of the document, long documents in parts with the offset to continue at"
+            },
+            {
+                "name": "list_outline",
+                "arguments": {},
+                "returns": "every document of the project with its ID, word count and headings"
+            },
+            {
+                "name": "count_words",
+                "arguments": { "document_id": "document to count" },
+                "returns": "words, characters and paragraphs of the document"
+            },
+            {
+                "name": "propose_edit",
+                "arguments": {
+                    "document_id": "document to change",
+                    "search": "text copied exactly from the document content (read it first) that occurs exactly once, empty to append",
+                    "replace": "new text for it"
+                },
+                "returns": "whether the edit applies, valid edits are shown to the user for review"
+            }
+        ])
+    }
+}
+
+/// Where the tools look and who they work for
+pub struct AgentScope<'a> {
+    pub pool: &'a PgPool,
+    pub user_id: i32,
+    pub session_id: i32,
+    /// Project of the session's document, None for the user's own documents
+    pub project_id: Option<i32>,
+    pub embedding_model: &'a EmbeddingModel,
+    pub tokenizer: Tokenizer,
+    /// Tokens a single tool result may take in the next prompt
+    pub result_tokens: usize,
+}
+
+/// What the loop has done so far
+#[derive(Default)]
+pub struct AgentState {
+    pub tool_calls: Vec<AgentToolCall>,
+    /// Tool calls and their results, rendered for the next prompt
+    pub transcript: String,
+    // Original content and valid edits of every document with proposed edits
+    proposals: HashMap<i32, (String, Vec<LlmDocEdit>)>,
+    proposal_order: Vec<i32>,
+}
+
+impl AgentState {
+    /// Documents with their proposed edits applied, in the order they were first edited
+    pub fn proposed_changes(&self) -> Vec<SuggestedDocumentChange> {
+        self.proposal_order
+            .iter()
+            .filter_map(|document_id| {
+                let (original, edits) = self.proposals.get(document_id)?;
+                let edit_refs: Vec<&LlmDocEdit> = edits.iter().collect();
+                let new_content = patch::apply_edits(original, &edit_refs).ok()?;
+                Some(SuggestedDocumentChange {
+                    document_id: *document_id,
+                    old_content: original.clone(),
+                    new_content,
+                    edit_count: edits.len(),
+                })
+            })
+            .collect()
+    }
+}
+
+// Outcome of one tool call
+struct ToolOutcome {
+    status: &'static str,
+    result: String,
+}
+
+impl ToolOutcome {
+    fn ok(result: String) -> Self {
+        ToolOutcome { status: "ok", result }
+    }
+
+    fn denied(document_id: i32) -> Self {
+        ToolOutcome { status: "denied", result: format!("Permission denied: the user cannot access document {}.", document_id) }
+    }
+
+    fn error(result: String) -> Self {
+        ToolOutcome { status: "error", result }
+    }
+}
+
+// A document in the scope of the agent
+struct ScopeDocument {
+    id: i32,
+    name: String,
+    content: Option<String>,
+}
+
+/// Runs the tool a step calls, logs the call and adds its result to the transcript
+pub async fn run_tool(scope: &AgentScope<'_>, state: &mut AgentState, step_number: i32, tool: AgentTool, step: &LlmAgentStep) -> Result<()> {
+    println!("->> {:<12} - Step {} of session {}: {} {:?}", "AGENT", step_number, scope.session_id, tool.as_str(), step);
+
+    let outcome = match tool {
+        AgentTool::SearchProject => search_project(scope, step.query.as_deref().unwrap_or(""), step.exact.unwrap_or(false)).await?,
+        AgentTool::ReadDocument => read_document(scope, step.document_id.unwrap_or_default(), step.offset.unwrap_or(0)).await?,
+        AgentTool::ListOutline => list_outline(scope).await?,
+        AgentTool::CountWords => count_words(scope, step.document_id.unwrap_or_default()).await?,
+        AgentTool::ProposeEdit => {
+            let edit = LlmDocEdit {
+                document_id: step.document_id.unwrap_or_default(),
+                search: step.search.clone().unwrap_or_default(),
+                replace: step.replace.clone().unwrap_or_default(),
+            };
+            propose_edit(scope, state, edit).await?
+        }
+    };
+    let result = if scope.tokenizer.count(&outcome.result) > scope.result_tokens {
+        format!("{}\n(Result truncated)", scope.tokenizer.truncate(&outcome.result, scope.result_tokens))
+    } else {
+        outcome.result
+    };
+
+    let arguments = tool_arguments(tool, step);
+    let summary: String = result.chars().take(500).collect();
+    let tool_call = sqlx::query_as!(
+        AgentToolCall,
+        r#"
+        INSERT INTO writing_assistant_tool_calls (session_id, step, tool, arguments, status, result_summary)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, session_id, message_id, step, tool, arguments AS "arguments: SqlJson<Value>", status, result_summary, created_at
+        "#,
+        scope.session_id,
+        step_number,
+        tool.as_str(),
+        SqlJson(arguments.clone()) as _,
+        outcome.status,
+        summary
+    )
+    .fetch_one(scope.pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    state.transcript.push_str(&format!("Step {}: {} {}\nResult ({}):\n{}\n\n", step_number, tool.as_str(), arguments, outcome.status, result));
+    state.tool_calls.push(tool_call);
+    Ok(())
+}
+
+/// Links the tool calls of a finished run to the answer they led to
+pub async fn link_tool_calls(pool: &PgPool, state: &mut AgentState, message_id: i32) -> Result<()> {
+    let ids: Vec<i32> = state.tool_calls.iter().map(|call| call.id).collect();
+    sqlx::query!(
+        "UPDATE writing_assistant_tool_calls SET message_id = $1 WHERE id = ANY($2)",
+        message_id,
+        &ids
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    for call in state.tool_calls.iter_mut() {
+        call.message_id = Some(message_id);
+    }
+    Ok(())
+}
+
+/// Tool calls of a session, oldest first
+pub async fn session_tool_calls(pool: &PgPool, session_id: i32) -> Result<Vec<AgentToolCall>> {
+    sqlx::query_as!(
+        AgentToolCall,
+        r#"
+        SELECT id, session_id, message_id, step, tool, arguments AS "arguments: SqlJson<Value>", status, result_summary, created_at
+        FROM writing_assistant_tool_calls
+        WHERE session_id = $1
+        ORDER BY id ASC
+        "#,
+        session_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)
+}
+
+// The arguments a tool actually uses, for the log
+fn tool_arguments(tool: AgentTool, step: &LlmAgentStep) -> Value {
+    match tool {
+        AgentTool::SearchProject => json!({ "query": step.query, "exact": step.exact.unwrap_or(false) }),
+        AgentTool::ReadDocument => json!({ "document_id": step.document_id, "offset": step.offset.unwrap_or(0) }),
+        AgentTool::ListOutline => json!({}),
+        AgentTool::CountWords => json!({ "document_id": step.document_id }),
+        AgentTool::ProposeEdit => json!({ "document_id": step.document_id, "search": step.search, "replace": step.replace }),
+    }
+}
+
+// Documents of the scope the user may read, by name
+async fn scope_documents(scope: &AgentScope<'_>) -> Result<Vec<ScopeDocument>> {
+    let candidates = sqlx::query_as!(
+        ScopeDocument,
+        r#"
+        SELECT d.id, d.name, d.content
+        FROM documents d
+        WHERE d.is_trashed = false
+          AND CASE
+              WHEN $2::int IS NULL THEN d.user_id = $1
+              ELSE d.id IN (SELECT document_id FROM document_projects WHERE project_id = $2)
+          END
+        ORDER BY d.name ASC, d.id ASC
+        "#,
+        scope.user_id,
+        scope.project_id
+    )
+    .fetch_all(scope.pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    let mut documents = Vec::with_capacity(candidates.len());
+    for document in candidates {
+        if check_document_permission(scope.pool, scope.user_id, document.id, "viewer").await? {
+            documents.push(document);
+        }
+    }
+    Ok(documents)
+}
+
+// A single document the user may access with `role`
+async fn scope_document(scope: &AgentScope<'_>, document_id: i32, role: &str) -> Result<Option<ScopeDocument>> {
+    if !check_document_permission(scope.pool, scope.user_id, document_id, role).await? {
+        return Ok(None);
+    }
+    sqlx::query_as!(
+        ScopeDocument,
+        "SELECT id, name, content FROM documents WHERE id = $1 AND is_trashed = false",
+        document_id
+    )
+    .fetch_optional(scope.pool)
+    .await
+    .map_err(|_| Error::DatabaseError)
+}
+
+async fn search_project(scope: &AgentScope<'_>, query: &str, exact: bool) -> Result<ToolOutcome> {
+    // An empty needle would match between every two characters
+    if query.trim().is_empty() {
+        return Ok(ToolOutcome::error("search_project needs a non-empty \"query\".".to_string()));
+    }
+    if exact {
+        let needle = query.to_lowercase();
+        let mut lines = Vec::new();
+        for document in scope_documents(scope).await? {
+            let text = to_plain_text(document.content.as_deref().unwrap_or(""));
+            let lowered = text.to_lowercase();
+            let count = lowered.matches(&needle).count();
+            let first = lowered.find(&needle).map(|position| snippet(&lowered, position, needle.len()));
+            match first {
+                Some(first) => lines.push(format!("- Document {} \"{}\": {} match(es), first: \"{}\"", document.id, document.name, count, first)),
+                None => lines.push(format!("- Document {} \"{}\": 0 matches", document.id, document.name)),
+            }
+        }
+        if lines.is_empty() {
+            return Ok(ToolOutcome::ok("No documents to search.".to_string()));
+        }
+        return Ok(ToolOutcome::ok(lines.join("\n")));
+    }
+
+    let embedding: Vector = scope.embedding_model.embed_document(query).await?;
+    let chunks = retrieval::semantic_search(scope.pool, scope.user_id, scope.project_id, &embedding, AGENT_SEARCH_K).await?;
+    let mut passages = Vec::new();
+    for chunk in chunks {
+        if !check_document_permission(scope.pool, scope.user_id, chunk.document_id, "viewer").await? {
+            continue;
+        }
+        let section = chunk.heading.as_deref().map(|heading| format!(", section \"{}\"", heading)).unwrap_or_default();
+        passages.push(format!("--- Document {} \"{}\"{} ---\n{}", chunk.document_id, chunk.document_name, section, chunk.content));
+    }
+    if passages.is_empty() {
+        return Ok(ToolOutcome::ok("No relevant passages found.".to_string()));
+    }
+    Ok(ToolOutcome::ok(passages.join("\n")))
+}
+
+async fn read_document(scope: &AgentScope<'_>, document_id: i32, offset: i32) -> Result<ToolOutcome> {
+    let Some(document) = scope_document(scope, document_id, "viewer").await? else {
+        return Ok(ToolOutcome::denied(document_id));
+    };
+    let content = document.content.unwrap_or_default();
+    let total_chars = content.chars().count();
+    let start = (offset.max(0) as usize).min(total_chars);
+    let rest: String = content.chars().skip(start).collect();
+
+    // Leave room for the header, the part that fits is returned with where to continue
+    let header_tokens = 64;
+    let part = scope.tokenizer.truncate(&rest, scope.result_tokens.saturating_sub(header_tokens));
+    let end = start + part.chars().count();
+    let continuation = if end < total_chars {
+        format!("\n(Document continues, read on with offset {})", end)
+    } else {
+        String::new()
+    };
+
+    Ok(ToolOutcome::ok(format!(
+        "Document {} \"{}\", characters {}-{} of {}:\n{}{}",
+        document.id, document.name, start, end, total_chars, part, continuation
+    )))
+}
+
+async fn list_outline(scope: &AgentScope<'_>) -> Result<ToolOutcome> {
+    let documents = scope_documents(scope).await?;
+    if documents.is_empty() {
+        return Ok(ToolOutcome::ok("No documents.".to_string()));
+    }
+
+    let mut lines = Vec::new();
+    for document in documents {
+        let text = to_plain_text(document.content.as_deref().unwrap_or(""));
+        lines.push(format!("- Document {} \"{}\" ({} words)", document.id, document.name, text.split_whitespace().count()));
+        for heading in text.lines().filter(|line| line.starts_with('#')) {
+            let level = heading.chars().take_while(|c| *c == '#').count();
+            lines.push(format!("{}{}", "  ".repeat(level), heading.trim_start_matches('#').trim()));
+        }
+    }
+    Ok(ToolOutcome::ok(lines.join("\n")))
+}
+
+async fn count_words(scope: &AgentScope<'_>, document_id: i32) -> Result<ToolOutcome> {
+    let Some(document) = scope_document(scope, document_id, "viewer").await? else {
+        return Ok(ToolOutcome::denied(document_id));
+    };
+    let text = to_plain_text(document.content.as_deref().unwrap_or(""));
+    let paragraphs = text.split("\n\n").filter(|paragraph| !paragraph.trim().is_empty()).count();
+
+    Ok(ToolOutcome::ok(format!(
+        "Document {} \"{}\": {} words, {} characters, {} paragraphs",
+        document.id, document.name, text.split_whitespace().count(), text.chars().count(), paragraphs
+    )))
+}
+
+async fn propose_edit(scope: &AgentScope<'_>, state: &mut AgentState, edit: LlmDocEdit) -> Result<ToolOutcome> {
+    let document_id = edit.document_id;
+    let Some(ScopeDocument { name, content, .. }) = scope_document(scope, document_id, "editor").await? else {
+        return Ok(ToolOutcome::denied(document_id));
+    };
+    if edit.search == edit.replace {
+        return Ok(ToolOutcome::error("The edit does not change anything.".to_string()));
+    }
+
+    // The new edit must apply together with the ones proposed before
+    let (original, edits) = state.proposals
+        .entry(document_id)
+        .or_insert_with(|| (content.unwrap_or_default(), Vec::new()));
+    let mut candidate: Vec<&LlmDocEdit> = edits.iter().collect();
+    candidate.push(&edit);
+    if let Err(reason) = patch::apply_edits(original, &candidate) {
+        if edits.is_empty() {
+            state.proposals.remove(&document_id);
+        }
+        return Ok(ToolOutcome::error(format!("The edit was rejected: {}.", reason)));
+    }
+
+    edits.push(edit);
+    let count = edits.len();
+    if !state.proposal_order.contains(&document_id) {
+        state.proposal_order.push(document_id);
+    }
+    Ok(ToolOutcome::ok(format!("Edit accepted for review, document {} \"{}\" now has {} proposed edit(s).", document_id, name, count)))
+}
+
+// Text around a match, on character boundaries
+fn snippet(text: &str, position: usize, length: usize) -> String {
+    let mut start = position.saturating_sub(SNIPPET_CHARS);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (position + length + SNIPPET_CHARS).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    text[start..end].split_whitespace().collect::<Vec<_>>().join(" ")
+}
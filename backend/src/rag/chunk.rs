@@ -0,0 +1,209 @@
+// Heading and paragraph aware chunking of documents for chunk-level embeddings
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+/// Soft upper bound on the size of a single chunk (in bytes of plain text)
+pub const MAX_CHUNK_CHARS: usize = 1200;
+/// How much trailing text of a chunk is repeated at the start of the next one
+pub const CHUNK_OVERLAP_CHARS: usize = 200;
+
+lazy_static! {
+    static ref HTML_HEADING_OPEN: Regex = Regex::new(r"(?i)<h([1-6])[^>]*>").unwrap();
+    static ref HTML_BLOCK_CLOSE: Regex =
+        Regex::new(r"(?i)</h[1-6]>|</p>|<br\s*/?>|</div>|</li>|</blockquote>|</pre>").unwrap();
+    static ref HTML_TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    static ref EXTRA_NEWLINES: Regex = Regex::new(r"\n[ \t]*\n(?:[ \t]*\n)+").unwrap();
+    static ref BLOCK_SEPARATOR: Regex = Regex::new(r"\n[ \t]*\n").unwrap();
+}
+
+/// A contiguous slice of a document's plain text, ready to be embedded.
+/// Offsets are character offsets into the output of `to_plain_text`.
+#[derive(Debug, Clone)]
+pub struct TextChunk {
+    pub chunk_index: i32,
+    pub heading: Option<String>,
+    pub content: String,
+    pub start_offset: i32,
+    pub end_offset: i32,
+}
+
+impl TextChunk {
+    /// Text sent to the embedding model; the section heading is prepended so that
+    /// paragraphs deep inside a chapter still carry its topic.
+    pub fn embedding_text(&self) -> String {
+        match &self.heading {
+            Some(heading) if !self.content.starts_with('#') => format!("{}\n\n{}", heading, self.content),
+            _ => self.content.clone(),
+        }
+    }
+}
+
+/// Converts stored document content (editor HTML or markdown) into plain text.
+/// HTML headings become markdown style `#` lines and block elements become blank lines,
+/// so the chunker can treat both formats the same way.
+pub fn to_plain_text(content: &str) -> String {
+    let text = content.replace("\r\n", "\n");
+    let text = HTML_HEADING_OPEN.replace_all(&text, |caps: &Captures| {
+        let level = caps[1].parse::<usize>().unwrap_or(1);
+        format!("\n\n{} ", "#".repeat(level))
+    });
+    let text = HTML_BLOCK_CLOSE.replace_all(&text, "\n\n");
+    let text = HTML_TAG.replace_all(&text, "");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    EXTRA_NEWLINES.replace_all(&text, "\n\n").trim().to_string()
+}
+
+/// Splits a document into overlapping chunks.
+/// A new chunk is always started at a heading; paragraphs are packed into chunks of
+/// at most `MAX_CHUNK_CHARS`, and paragraphs longer than that are split on sentence ends.
+pub fn chunk_document(content: &str) -> Vec<TextChunk> {
+    let text = to_plain_text(content);
+    let mut chunks: Vec<TextChunk> = Vec::new();
+
+    let mut heading: Option<String> = None;
+    let mut current: Vec<(usize, usize)> = Vec::new(); // byte ranges in the current chunk
+    let mut current_len = 0;
+    let mut current_has_body = false;
+
+    for (start, end) in split_blocks(&text) {
+        let block = &text[start..end];
+
+        if is_heading(block) {
+            // Headings always start a fresh chunk, without overlap from the previous section
+            if current_has_body {
+                push_chunk(&text, &current, &heading, &mut chunks);
+            }
+            heading = Some(block.trim_start_matches('#').trim().to_string());
+            current = vec![(start, end)];
+            current_len = end - start;
+            current_has_body = false;
+            continue;
+        }
+
+        for (piece_start, piece_end) in split_long_block(&text, start, end) {
+            let piece_len = piece_end - piece_start;
+
+            if current_has_body && current_len + piece_len > MAX_CHUNK_CHARS {
+                push_chunk(&text, &current, &heading, &mut chunks);
+
+                // Carry the tail of the previous chunk over for continuity
+                let (_, last_end) = *current.last().unwrap();
+                let overlap_start = overlap_start(&text, current[0].0, last_end);
+                current = if overlap_start < last_end { vec![(overlap_start, last_end)] } else { Vec::new() };
+                current_len = last_end - overlap_start;
+            }
+
+            current.push((piece_start, piece_end));
+            current_len += piece_len;
+            current_has_body = true;
+        }
+    }
+
+    if current_has_body || (chunks.is_empty() && !current.is_empty()) {
+        push_chunk(&text, &current, &heading, &mut chunks);
+    }
+
+    chunks
+}
+
+// Byte ranges of the non-empty paragraphs of the text
+fn split_blocks(text: &str) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for separator in BLOCK_SEPARATOR.find_iter(text) {
+        push_trimmed(text, start, separator.start(), &mut blocks);
+        start = separator.end();
+    }
+    push_trimmed(text, start, text.len(), &mut blocks);
+    blocks
+}
+
+fn push_trimmed(text: &str, start: usize, end: usize, blocks: &mut Vec<(usize, usize)>) {
+    let slice = &text[start..end];
+    let trimmed = slice.trim();
+    if trimmed.is_empty() {
+        return;
+    }
+    let leading = slice.len() - slice.trim_start().len();
+    blocks.push((start + leading, start + leading + trimmed.len()));
+}
+
+fn is_heading(block: &str) -> bool {
+    block.starts_with('#') && !block.contains('\n') && block.trim_start_matches('#').starts_with(' ')
+}
+
+// Splits a paragraph that is too large for one chunk, preferring sentence boundaries
+fn split_long_block(text: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut pieces = Vec::new();
+    let mut piece_start = start;
+
+    while end - piece_start > MAX_CHUNK_CHARS {
+        let window_end = floor_char_boundary(text, piece_start + MAX_CHUNK_CHARS);
+        let window = &text[piece_start..window_end];
+
+        let sentence_cut = [". ", "! ", "? ", ".\n"]
+            .iter()
+            .filter_map(|end_marker| window.rfind(end_marker).map(|i| i + 1))
+            .max()
+            .filter(|cut| *cut > MAX_CHUNK_CHARS / 2);
+        let cut = sentence_cut
+            .or_else(|| window.rfind(char::is_whitespace).filter(|cut| *cut > 0))
+            .unwrap_or(window.len());
+
+        pieces.push((piece_start, piece_start + cut));
+
+        // Skip the whitespace between the two pieces
+        let rest = &text[piece_start + cut..end];
+        piece_start = piece_start + cut + (rest.len() - rest.trim_start().len());
+    }
+
+    if piece_start < end {
+        pieces.push((piece_start, end));
+    }
+    pieces
+}
+
+// Start of the overlap carried into the next chunk, aligned to a word boundary
+fn overlap_start(text: &str, chunk_start: usize, chunk_end: usize) -> usize {
+    if chunk_end - chunk_start <= CHUNK_OVERLAP_CHARS {
+        return chunk_end;
+    }
+    let candidate = floor_char_boundary(text, chunk_end - CHUNK_OVERLAP_CHARS);
+    match text[candidate..chunk_end].find(char::is_whitespace) {
+        Some(i) => {
+            let rest = &text[candidate + i..chunk_end];
+            candidate + i + (rest.len() - rest.trim_start().len())
+        }
+        None => chunk_end,
+    }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn push_chunk(text: &str, ranges: &[(usize, usize)], heading: &Option<String>, chunks: &mut Vec<TextChunk>) {
+    let start = ranges.first().map(|r| r.0).unwrap_or(0);
+    let end = ranges.last().map(|r| r.1).unwrap_or(0);
+    if start >= end {
+        return;
+    }
+    chunks.push(TextChunk {
+        chunk_index: chunks.len() as i32,
+        heading: heading.clone(),
+        content: text[start..end].to_string(),
+        start_offset: text[..start].chars().count() as i32,
+        end_offset: text[..end].chars().count() as i32,
+    });
+}
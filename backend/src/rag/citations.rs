@@ -0,0 +1,83 @@
+// Source citations in assistant answers
+//
+// Every context chunk placed in a chat prompt gets a label (S1, S2, ...) and the model is asked
+// to cite the labels it used, e.g. "... as described in chapter two [S2]." After the answer
+// arrives, markers are checked against the chunks that were actually sent: valid ones become
+// `Citation`s, unknown labels are removed from the text.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::models::ai::Citation;
+use crate::rag::retrieval::RetrievedChunk;
+
+lazy_static! {
+    // [S1] or [S1, S3]
+    static ref CITATION_MARKER: Regex = Regex::new(r"\s*\[(S\d+(?:\s*,\s*S\d+)*)\]").unwrap();
+}
+
+/// Label of the n-th source placed in a prompt (0-based)
+pub fn citation_label(index: usize) -> String {
+    format!("S{}", index + 1)
+}
+
+/// Citation a chunk would produce if the answer refers to it
+pub fn citation_for_chunk(label: String, chunk: &RetrievedChunk) -> Citation {
+    Citation {
+        label,
+        document_id: chunk.document_id,
+        document_name: chunk.document_name.clone(),
+        chunk_id: chunk.chunk_id,
+        heading: chunk.heading.clone(),
+        start_offset: chunk.start_offset,
+        end_offset: chunk.end_offset,
+    }
+}
+
+/// Validates the citation markers in `answer` against the sources of its prompt.
+/// Returns the answer without unknown markers and the cited sources in order of first use.
+pub fn extract_citations(answer: &str, sources: &[Citation]) -> (String, Vec<Citation>) {
+    let mut cleaned = String::with_capacity(answer.len());
+    let mut cited: Vec<Citation> = Vec::new();
+    let mut last_end = 0;
+
+    for captures in CITATION_MARKER.captures_iter(answer) {
+        let marker = captures.get(0).unwrap();
+        cleaned.push_str(&answer[last_end..marker.start()]);
+        last_end = marker.end();
+
+        let valid_labels: Vec<&str> = captures[1]
+            .split(',')
+            .map(|label| label.trim())
+            .filter(|label| sources.iter().any(|source| source.label == *label))
+            .collect();
+
+        if valid_labels.len() < captures[1].split(',').count() {
+            println!("->> {:<12} - Dropping unknown citation labels in '{}'", "CITATIONS", marker.as_str().trim());
+        }
+        if valid_labels.is_empty() {
+            continue;
+        }
+
+        // Keep the whitespace that preceded the marker
+        let leading = &marker.as_str()[..marker.as_str().find('[').unwrap_or(0)];
+        cleaned.push_str(leading);
+        cleaned.push_str(&format!("[{}]", valid_labels.join(", ")));
+
+        for label in valid_labels {
+            if !cited.iter().any(|citation| citation.label == label) {
+                if let Some(source) = sources.iter().find(|source| source.label == label) {
+                    cited.push(source.clone());
+                }
+            }
+        }
+    }
+    cleaned.push_str(&answer[last_end..]);
+
+    (cleaned, cited)
+}
+
+/// Removes citation markers, labels of earlier answers mean nothing in a new prompt
+pub fn strip_citation_markers(text: &str) -> String {
+    CITATION_MARKER.replace_all(text, "").into_owned()
+}
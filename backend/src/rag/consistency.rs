@@ -0,0 +1,647 @@
+// Project-wide consistency checks
+//
+// A check reads every document of a project in order, oldest first, and reports what does not fit
+// together, with character offsets into the stored content. Rules find most of it without a model:
+//   - name_variant: a capitalized word one edit (two for long words) away from a word of an entity
+//     name or alias, or from a name the project uses more often, e.g. "Mria" for "Mira". Words the
+//     project also writes in lowercase or the dictionary knows are regular words and are skipped
+//   - tense_drift / pov_drift: narration outside quoted dialogue in another tense or person than the
+//     style guide names ("past tense", "third person") or than most of the project uses. A document
+//     that drifted as a whole is one finding, single sentences are one finding each
+//   - glossary_violation: a keep term with capital letters written in another case, and
+//     translations in the project that break the glossary of their language
+// With AI, the model compares each document with the knowledge base and with the events of the
+// earlier documents, for knowledge_contradiction and timeline_conflict findings. The events it
+// lists for a document are added to the timeline the later documents are checked against.
+// Translations are only checked against the glossary, the rules are written for English.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+use crate::models::consistency::{FindingKind, FindingSource, LlmFinding, NewFinding};
+use crate::models::knowledge::{find_mentions, ProjectEntity};
+use crate::models::translation::{GlossaryRule, GlossaryTerm};
+use crate::proofread::hunspell::Dictionary;
+use crate::proofread;
+use crate::rag::translate;
+
+/// Rule findings of one kind per document, the rest are left out
+const MAX_RULE_FINDINGS_PER_KIND: usize = 20;
+/// Uses of a capitalized word before it counts as a name the project uses
+const MIN_NAME_USES: usize = 3;
+/// Shortest word compared for name variants, in characters
+const MIN_NAME_CHARS: usize = 4;
+/// Share of a document's sentences with pronouns that makes it first person
+const FIRST_PERSON_SHARE: f64 = 0.3;
+/// Share of a project's sentences (or documents) that sets the tense (or point of view) to expect
+const MAJORITY_SHARE: f64 = 2.0 / 3.0;
+/// Events kept from one document for the timeline
+pub const MAX_EVENTS_PER_DOCUMENT: usize = 20;
+
+// Common verb forms that mark the tense of a sentence
+const PAST_MARKERS: &[&str] = &[
+    "was", "were", "had", "did", "said", "went", "came", "saw", "looked", "walked", "thought", "knew", "felt",
+    "took", "made", "got", "told", "asked", "turned", "stood", "sat", "ran", "heard", "found", "left", "seemed",
+];
+const PRESENT_MARKERS: &[&str] = &[
+    "is", "are", "am", "has", "does", "says", "goes", "comes", "sees", "looks", "walks", "thinks", "knows", "feels",
+    "takes", "makes", "gets", "tells", "asks", "turns", "stands", "sits", "runs", "hears", "finds", "leaves", "seems",
+];
+const FIRST_PERSON: &[&str] = &["i", "me", "my", "mine", "myself", "we", "us", "our", "ours", "ourselves"];
+const THIRD_PERSON: &[&str] = &["he", "she", "him", "her", "his", "hers", "himself", "herself", "they", "them", "their", "themselves"];
+
+lazy_static! {
+    // Tags that end a paragraph or line, and with it a sentence
+    static ref BLOCK_TAG: Regex = Regex::new(r"(?i)^/?(?:p|h[1-6]|li|ul|ol|div|blockquote|pre|br|tr|td|th)\b").unwrap();
+    static ref STYLE_TENSE: Regex = Regex::new(r"(?i)\b(past|present)[ -]tense\b").unwrap();
+    static ref STYLE_PERSON: Regex = Regex::new(r"(?i)\b(first|third)[ -]person\b").unwrap();
+}
+
+/// A document of the checked project
+#[derive(Debug)]
+pub struct CheckedDocument {
+    pub id: i32,
+    pub name: String,
+    pub content: String,
+    /// The source document and language, for translations
+    pub translation_of: Option<TranslationSource>,
+}
+
+#[derive(Debug)]
+pub struct TranslationSource {
+    pub document_id: i32,
+    pub name: String,
+    pub content: String,
+    pub target_language: String,
+}
+
+/// An event of an earlier document, for the timeline the model checks against
+#[derive(Debug, Clone)]
+pub struct TimelineEvent {
+    pub document_name: String,
+    pub event: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tense {
+    Past,
+    Present,
+}
+
+impl Tense {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Tense::Past => "past",
+            Tense::Present => "present",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Person {
+    First,
+    Third,
+}
+
+impl Person {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Person::First => "first",
+            Person::Third => "third",
+        }
+    }
+}
+
+// A sentence of narration, offsets are characters of the content
+struct Sentence {
+    start: usize,
+    end: usize,
+    tense: Option<Tense>,
+    person: Option<Person>,
+}
+
+// A word the project uses as a name
+struct NameReference {
+    word: String,
+    lowercase: String,
+    uses: usize,
+    entity: Option<(i32, String)>,
+}
+
+/// What the rules know about the whole project before its documents are checked one by one
+pub struct ProjectProfile<'a> {
+    tense: Option<Tense>,
+    person: Option<Person>,
+    names: Vec<NameReference>,
+    word_uses: HashMap<String, usize>,
+    lowercase_words: HashSet<String>,
+    glossary: &'a [GlossaryTerm],
+    dictionary: Option<&'a Dictionary>,
+}
+
+impl<'a> ProjectProfile<'a> {
+    /// The tense and point of view to expect and the names of the project
+    pub fn build(
+        documents: &[CheckedDocument],
+        entities: &[ProjectEntity],
+        style_guide: Option<&str>,
+        glossary: &'a [GlossaryTerm],
+        dictionary: Option<&'a Dictionary>,
+    ) -> Self {
+        let originals: Vec<&CheckedDocument> = documents.iter().filter(|document| document.translation_of.is_none()).collect();
+
+        // Tense by sentences and point of view by documents, unless the style guide names them
+        let (mut past, mut present) = (0, 0);
+        let mut persons = Vec::new();
+        for document in &originals {
+            let sentences = narration_sentences(&document.content);
+            past += sentences.iter().filter(|sentence| sentence.tense == Some(Tense::Past)).count();
+            present += sentences.iter().filter(|sentence| sentence.tense == Some(Tense::Present)).count();
+            persons.extend(document_person(&sentences));
+        }
+        let tense = style_guide
+            .and_then(|guide| STYLE_TENSE.captures(guide))
+            .map(|caps| if caps[1].eq_ignore_ascii_case("past") { Tense::Past } else { Tense::Present })
+            .or_else(|| majority(&[(Tense::Past, past), (Tense::Present, present)]));
+        let person = style_guide
+            .and_then(|guide| STYLE_PERSON.captures(guide))
+            .map(|caps| if caps[1].eq_ignore_ascii_case("first") { Person::First } else { Person::Third })
+            .or_else(|| {
+                let first = persons.iter().filter(|person| **person == Person::First).count();
+                majority(&[(Person::First, first), (Person::Third, persons.len() - first)])
+            });
+
+        // Uses of every capitalized word, and the words also written in lowercase
+        let mut word_uses: HashMap<String, usize> = HashMap::new();
+        let mut inside_sentence: HashSet<String> = HashSet::new();
+        let mut lowercase_words: HashSet<String> = HashSet::new();
+        for document in &originals {
+            let text = blank_out(&document.content, false);
+            let chars: Vec<char> = text.chars().collect();
+            for token in proofread::tokenize(&text) {
+                if token.text.starts_with(char::is_uppercase) {
+                    *word_uses.entry(token.text.clone()).or_insert(0) += 1;
+                    if !starts_sentence(&chars, token.start) {
+                        inside_sentence.insert(token.text.clone());
+                    }
+                } else if token.text.starts_with(char::is_lowercase) {
+                    lowercase_words.insert(token.lowercase.clone());
+                }
+            }
+        }
+
+        let mut names: Vec<NameReference> = Vec::new();
+        for entity in entities {
+            let words = std::iter::once(&entity.name).chain(&entity.aliases).flat_map(|name| name.split_whitespace());
+            for word in words.filter(|word| word.starts_with(char::is_uppercase) && word.chars().count() >= MIN_NAME_CHARS) {
+                let lowercase = word.to_lowercase();
+                if !names.iter().any(|name| name.lowercase == lowercase) {
+                    names.push(NameReference {
+                        word: word.to_string(),
+                        uses: word_uses.get(word).copied().unwrap_or(0),
+                        lowercase,
+                        entity: Some((entity.id, entity.name.clone())),
+                    });
+                }
+            }
+        }
+        for (word, &uses) in &word_uses {
+            let lowercase = word.to_lowercase();
+            let is_name = uses >= MIN_NAME_USES
+                && word.chars().count() >= MIN_NAME_CHARS
+                && inside_sentence.contains(word)
+                && !lowercase_words.contains(&lowercase);
+            if is_name && !names.iter().any(|name| name.lowercase == lowercase) {
+                names.push(NameReference { word: word.clone(), lowercase, uses, entity: None });
+            }
+        }
+        // Entities first, then the most used names
+        names.sort_by(|a, b| b.entity.is_some().cmp(&a.entity.is_some()).then(b.uses.cmp(&a.uses)).then(a.word.cmp(&b.word)));
+
+        ProjectProfile { tense, person, names, word_uses, lowercase_words, glossary, dictionary }
+    }
+
+    /// Rule findings of one document, by position
+    pub fn check_document(&self, document: &CheckedDocument) -> Vec<NewFinding> {
+        let mut findings = Vec::new();
+        match &document.translation_of {
+            Some(source) => findings.extend(self.translation_glossary(document, source)),
+            None => {
+                findings.extend(self.name_variants(document));
+                let sentences = narration_sentences(&document.content);
+                findings.extend(self.tense_drift(document, &sentences));
+                findings.extend(self.pov_drift(document, &sentences));
+                findings.extend(self.glossary_case(document));
+            }
+        }
+        findings.sort_by_key(|finding| finding.start_offset.unwrap_or(-1));
+        findings
+    }
+
+    fn name_variants(&self, document: &CheckedDocument) -> Vec<NewFinding> {
+        let text = blank_out(&document.content, false);
+        let mut findings = Vec::new();
+        for token in proofread::tokenize(&text) {
+            // "Mria's" is compared as "Mria"
+            let word = token.text.strip_suffix("'s").unwrap_or(&token.text);
+            let lowercase = word.to_lowercase();
+            let length = word.chars().count();
+            if !word.starts_with(char::is_uppercase)
+                || length < MIN_NAME_CHARS
+                || self.lowercase_words.contains(&lowercase)
+                || self.names.iter().any(|name| name.lowercase == lowercase)
+                || self.dictionary.is_some_and(|dictionary| dictionary.check(word))
+            {
+                continue;
+            }
+            let uses = self.word_uses.get(word).copied().unwrap_or(0);
+            let max_distance = if length >= 7 { 2 } else { 1 };
+            let closest = self.names
+                .iter()
+                .filter(|name| name.entity.is_some() || name.uses > uses)
+                .map(|name| (edit_distance(&lowercase, &name.lowercase), name))
+                .filter(|(distance, _)| *distance <= max_distance)
+                .min_by_key(|(distance, _)| *distance);
+            let Some((_, name)) = closest else {
+                continue;
+            };
+
+            let message = match &name.entity {
+                Some((_, entity_name)) if *entity_name != name.word => {
+                    format!("\"{}\" looks like a misspelling of \"{}\" ({})", word, name.word, entity_name)
+                }
+                _ => format!("\"{}\" looks like a misspelling of \"{}\", used {} times in the project", word, name.word, name.uses),
+            };
+            findings.push(NewFinding {
+                kind: FindingKind::NameVariant,
+                source: FindingSource::Rule,
+                document_id: Some(document.id),
+                start_offset: Some(token.start as i32),
+                end_offset: Some((token.start + length) as i32),
+                excerpt: Some(word.to_string()),
+                message,
+                suggestion: Some(name.word.clone()),
+                entity_id: name.entity.as_ref().map(|(entity_id, _)| *entity_id),
+                related_document_id: None,
+                related_offset: None,
+            });
+            if findings.len() == MAX_RULE_FINDINGS_PER_KIND {
+                break;
+            }
+        }
+        findings
+    }
+
+    fn tense_drift(&self, document: &CheckedDocument, sentences: &[Sentence]) -> Vec<NewFinding> {
+        let Some(expected) = self.tense else {
+            return Vec::new();
+        };
+        let drifted: Vec<&Sentence> = sentences.iter().filter(|sentence| sentence.tense.is_some_and(|tense| tense != expected)).collect();
+        let Some(first) = drifted.first() else {
+            return Vec::new();
+        };
+        let other = first.tense.unwrap_or(expected);
+        let determined = sentences.iter().filter(|sentence| sentence.tense.is_some()).count();
+
+        if drifted.len() * 2 > determined {
+            let message = format!(
+                "The document is narrated in the {} tense ({} of {} sentences), the project uses the {} tense",
+                other.as_str(), drifted.len(), determined, expected.as_str()
+            );
+            return vec![sentence_finding(FindingKind::TenseDrift, document, first, message)];
+        }
+        drifted
+            .iter()
+            .take(MAX_RULE_FINDINGS_PER_KIND)
+            .map(|sentence| {
+                let message = format!("{} tense in {} tense narration", capitalize(other.as_str()), expected.as_str());
+                sentence_finding(FindingKind::TenseDrift, document, sentence, message)
+            })
+            .collect()
+    }
+
+    fn pov_drift(&self, document: &CheckedDocument, sentences: &[Sentence]) -> Vec<NewFinding> {
+        let Some(expected) = self.person else {
+            return Vec::new();
+        };
+        let Some(person) = document_person(sentences) else {
+            return Vec::new();
+        };
+        let first_of = |wanted: Person| sentences.iter().find(|sentence| sentence.person == Some(wanted));
+
+        if person != expected {
+            let Some(first) = first_of(person) else {
+                return Vec::new();
+            };
+            let message = format!("The document is narrated in the {} person, the project uses the {} person", person.as_str(), expected.as_str());
+            return vec![sentence_finding(FindingKind::PovDrift, document, first, message)];
+        }
+        // First person slips into third person narration, the other way round is normal
+        if expected != Person::Third {
+            return Vec::new();
+        }
+        sentences
+            .iter()
+            .filter(|sentence| sentence.person == Some(Person::First))
+            .take(MAX_RULE_FINDINGS_PER_KIND)
+            .map(|sentence| sentence_finding(FindingKind::PovDrift, document, sentence, "First person in third person narration".to_string()))
+            .collect()
+    }
+
+    // Keep terms with capitals written in another case, e.g. "VYNN" for "Vynn"
+    fn glossary_case(&self, document: &CheckedDocument) -> Vec<NewFinding> {
+        let text = blank_out(&document.content, false);
+        let chars: Vec<char> = text.chars().collect();
+        let mut findings = Vec::new();
+        for term in self.glossary.iter().filter(|term| term.rule == GlossaryRule::Keep) {
+            let term_text = term.term.trim();
+            if !term_text.chars().any(char::is_uppercase) {
+                continue;
+            }
+            let length = term_text.chars().count();
+            for offset in find_mentions(&text, &[term_text.to_string()]) {
+                let start = offset as usize;
+                let written: String = chars[start..(start + length).min(chars.len())].iter().collect();
+                if written == term_text || findings.len() == MAX_RULE_FINDINGS_PER_KIND {
+                    continue;
+                }
+                findings.push(NewFinding {
+                    kind: FindingKind::GlossaryViolation,
+                    source: FindingSource::Rule,
+                    document_id: Some(document.id),
+                    start_offset: Some(start as i32),
+                    end_offset: Some((start + length) as i32),
+                    message: format!("\"{}\" is written \"{}\" in the glossary", written, term_text),
+                    excerpt: Some(written),
+                    suggestion: Some(term_text.to_string()),
+                    entity_id: None,
+                    related_document_id: None,
+                    related_offset: None,
+                });
+            }
+        }
+        findings
+    }
+
+    // Glossary rules a translation breaks, found where the untranslated term is still there
+    fn translation_glossary(&self, document: &CheckedDocument, source: &TranslationSource) -> Vec<NewFinding> {
+        let terms = translate::applicable_terms(self.glossary, &source.target_language);
+        let text = blank_out(&document.content, false);
+        let source_text = blank_out(&source.content, false);
+        translate::check_glossary(&source.content, &document.content, &terms)
+            .into_iter()
+            .map(|violation| {
+                let start = find_mentions(&text, std::slice::from_ref(&violation.term)).first().copied();
+                NewFinding {
+                    kind: FindingKind::GlossaryViolation,
+                    source: FindingSource::Rule,
+                    document_id: Some(document.id),
+                    start_offset: start,
+                    end_offset: start.map(|start| start + violation.term.chars().count() as i32),
+                    excerpt: start.map(|_| violation.term.clone()),
+                    message: format!(
+                        "\"{}\" appears {} times in \"{}\" but \"{}\" only {} times in this translation",
+                        violation.term, violation.source_occurrences, source.name, violation.expected, violation.translation_occurrences
+                    ),
+                    suggestion: Some(violation.expected),
+                    entity_id: None,
+                    related_document_id: Some(source.document_id),
+                    related_offset: find_mentions(&source_text, &[violation.term]).first().copied(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Findings of the model for a document, located in its content. Entities and earlier documents
+/// are matched by name, ignoring case.
+pub fn ai_findings(document: &CheckedDocument, found: Vec<LlmFinding>, entities: &[ProjectEntity], earlier: &[CheckedDocument]) -> Vec<NewFinding> {
+    found
+        .into_iter()
+        .map(|finding| {
+            let span = locate_quote(&document.content, &finding.quote);
+            let entity_id = finding.entity.as_deref().map(str::trim).and_then(|name| {
+                let name = name.to_lowercase();
+                entities
+                    .iter()
+                    .find(|entity| entity.name.to_lowercase() == name || entity.aliases.iter().any(|alias| alias.to_lowercase() == name))
+                    .map(|entity| entity.id)
+            });
+            let related = finding.conflicts_with.as_deref().map(str::trim).and_then(|name| {
+                earlier.iter().find(|other| other.name.trim().eq_ignore_ascii_case(name))
+            });
+            let suggestion = finding.suggestion.map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+
+            NewFinding {
+                kind: finding.kind,
+                source: FindingSource::Ai,
+                document_id: Some(document.id),
+                start_offset: span.map(|(start, _)| start),
+                end_offset: span.map(|(_, end)| end),
+                excerpt: Some(finding.quote.trim().to_string()),
+                message: finding.explanation.trim().to_string(),
+                suggestion,
+                entity_id,
+                related_document_id: related.map(|other| other.id),
+                related_offset: None,
+            }
+        })
+        .collect()
+}
+
+/// Character offsets of a quote in the content, exact or ignoring case
+pub fn locate_quote(content: &str, quote: &str) -> Option<(i32, i32)> {
+    let quote = quote.trim();
+    if quote.is_empty() {
+        return None;
+    }
+    let start = match content.find(quote) {
+        Some(start) => content[..start].chars().count(),
+        None => {
+            // Lowercasing may change lengths, only search when it does not
+            let lower = content.to_lowercase();
+            if lower.len() != content.len() {
+                return None;
+            }
+            let start = lower.find(&quote.to_lowercase())?;
+            content[..start].chars().count()
+        }
+    };
+    Some((start as i32, (start + quote.chars().count()) as i32))
+}
+
+// The value with at least MAJORITY_SHARE of the counts
+fn majority<T: Copy>(counts: &[(T, usize)]) -> Option<T> {
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    counts
+        .iter()
+        .find(|(_, count)| total > 0 && *count as f64 >= total as f64 * MAJORITY_SHARE)
+        .map(|(value, _)| *value)
+}
+
+// First person when enough sentences with pronouns use it, third person when none do
+fn document_person(sentences: &[Sentence]) -> Option<Person> {
+    let first = sentences.iter().filter(|sentence| sentence.person == Some(Person::First)).count();
+    let with_pronouns = sentences.iter().filter(|sentence| sentence.person.is_some()).count();
+    if with_pronouns == 0 {
+        None
+    } else if first as f64 >= with_pronouns as f64 * FIRST_PERSON_SHARE {
+        Some(Person::First)
+    } else {
+        Some(Person::Third)
+    }
+}
+
+// Sentences of the narration, without quoted dialogue, with their tense and point of view
+fn narration_sentences(content: &str) -> Vec<Sentence> {
+    let text = blank_out(content, true);
+    let tokens = proofread::tokenize(&text);
+    let chars: Vec<char> = text.chars().collect();
+
+    let mut sentences = Vec::new();
+    let mut next_token = 0;
+    for end in 0..=chars.len() {
+        let boundary = end == chars.len() || matches!(chars[end], '.' | '!' | '?' | '\n');
+        if !boundary {
+            continue;
+        }
+        let first = next_token;
+        while next_token < tokens.len() && tokens[next_token].start < end {
+            next_token += 1;
+        }
+        let words = &tokens[first..next_token];
+        if let (Some(first_word), Some(last_word)) = (words.first(), words.last()) {
+            let has = |list: &[&str]| words.iter().filter(|word| list.contains(&word.lowercase.as_str())).count();
+            let (past, present) = (has(PAST_MARKERS), has(PRESENT_MARKERS));
+            let (first_person, third_person) = (has(FIRST_PERSON), has(THIRD_PERSON));
+            sentences.push(Sentence {
+                start: first_word.start,
+                // Up to the punctuation
+                end: if end < chars.len() && chars[end] != '\n' { end + 1 } else { last_word.end },
+                tense: match (past, present) {
+                    (0, 0) => None,
+                    (_, 0) => Some(Tense::Past),
+                    (0, _) => Some(Tense::Present),
+                    _ => None,
+                },
+                person: if first_person > 0 {
+                    Some(Person::First)
+                } else if third_person > 0 {
+                    Some(Person::Third)
+                } else {
+                    None
+                },
+            });
+        }
+    }
+    sentences
+}
+
+// The content with markup and HTML entities blanked out character for character, so offsets into
+// it are offsets into the content. Block tags become line breaks, which end a sentence. With
+// `narration` quoted dialogue is blanked out too, a new paragraph ends an unclosed quote.
+fn blank_out(content: &str, narration: bool) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut text = String::with_capacity(content.len());
+    let mut in_dialogue = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '<' {
+            if let Some(length) = chars[i..].iter().position(|&c| c == '>') {
+                let tag: String = chars[i + 1..i + length].iter().collect();
+                if BLOCK_TAG.is_match(&tag) {
+                    text.push('\n');
+                    in_dialogue = false;
+                } else {
+                    text.push(' ');
+                }
+                text.extend(std::iter::repeat_n(' ', length));
+                i += length + 1;
+                continue;
+            }
+        }
+        if c == '&' {
+            let entity = chars[i + 1..].iter().take(10).position(|&c| c == ';');
+            if let Some(length) = entity.filter(|&length| length > 0 && chars[i + 1..i + 1 + length].iter().all(|c| c.is_ascii_alphanumeric() || *c == '#')) {
+                text.extend(std::iter::repeat_n(' ', length + 2));
+                i += length + 2;
+                continue;
+            }
+        }
+        if c == '\n' {
+            in_dialogue = false;
+        }
+        if narration && matches!(c, '"' | '“' | '”') {
+            in_dialogue = match c {
+                '“' => true,
+                '”' => false,
+                _ => !in_dialogue,
+            };
+            text.push(' ');
+        } else if narration && in_dialogue {
+            text.push(' ');
+        } else {
+            text.push(c);
+        }
+        i += 1;
+    }
+    text
+}
+
+// Whether the word at `start` begins a sentence: nothing but spaces and quotes since the last
+// sentence end
+fn starts_sentence(chars: &[char], start: usize) -> bool {
+    chars[..start]
+        .iter()
+        .rev()
+        .find(|c| !c.is_whitespace() || **c == '\n')
+        .is_none_or(|&c| matches!(c, '.' | '!' | '?' | '\n' | '"' | '“' | ':'))
+}
+
+// Optimal string alignment distance: insertions, deletions, substitutions and swaps of neighbours
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+fn sentence_finding(kind: FindingKind, document: &CheckedDocument, sentence: &Sentence, message: String) -> NewFinding {
+    let excerpt: String = document.content.chars().skip(sentence.start).take(sentence.end - sentence.start).collect();
+    NewFinding {
+        kind,
+        source: FindingSource::Rule,
+        document_id: Some(document.id),
+        start_offset: Some(sentence.start as i32),
+        end_offset: Some(sentence.end as i32),
+        excerpt: Some(excerpt),
+        message,
+        suggestion: None,
+        entity_id: None,
+        related_document_id: None,
+        related_offset: None,
+    }
+}
@@ -1,117 +1,245 @@
-use langchain_rust::embedding::{openai::OpenAiEmbedder, Embedder};
-use langchain_rust::llm::OpenAIConfig;
-use std::env;
+use crate::rag::provider::{embedding_provider, EmbeddingProvider};
 use crate::Error;
 use pgvector::Vector;
-use crate::models::ai::WritingAssistantMessage;
+use crate::models::ai::Citation;
+use sqlx::types::Json;
 use sqlx::PgPool;
 use chrono::Utc;
 use crate::models::ai::MessageRole;
+use crate::rag::chunk::chunk_document;
+
+/// Width of the vector columns in the database.
+/// Smaller embeddings (e.g. from local models) are zero padded, which keeps cosine similarity unchanged.
+pub const EMBEDDING_DIMENSIONS: usize = 1536;
 
 pub struct EmbeddingModel {
-    model: OpenAiEmbedder<OpenAIConfig>
+    provider: Box<dyn EmbeddingProvider>
 }
 
 impl EmbeddingModel {
+    /// Creates an embedding model backed by the provider selected with `EMBEDDING_PROVIDER`
     pub fn new() -> Result<Self, Error> {    
-        let embedding_model = OpenAiEmbedder::new({
-            OpenAIConfig::default().with_api_key(env::var("OPENAI_API_KEY")
-        .map_err(|_| Error::APIKeyError)?)
-        });
-          
-        Ok(Self { model: embedding_model })
+        Ok(Self { provider: embedding_provider()? })
+    }
+
+    /// Name of the provider and model computing embeddings, e.g. "openai/text-embedding-ada-002"
+    pub fn model_name(&self) -> String {
+        format!("{}/{}", self.provider.name(), self.provider.model())
     }
 
-    pub async fn embed_message(&self, message: &WritingAssistantMessage) -> Result<Vector, Error> {
-        let embedding_vec_f64 = self.model.embed_query(&message.content).await
+    // Fit a provider embedding into the database column
+    fn to_vector(embedding: Vec<f32>) -> Result<Vector, Error> {
+        if embedding.is_empty() || embedding.len() > EMBEDDING_DIMENSIONS {
+            eprintln!("Embedding has {} dimensions, expected 1..={}", embedding.len(), EMBEDDING_DIMENSIONS);
+            return Err(Error::EmbeddingError);
+        }
+        let mut padded = embedding;
+        padded.resize(EMBEDDING_DIMENSIONS, 0.0);
+        Ok(Vector::from(padded))
+    }
+
+    pub async fn embed_document(&self, content: &str) -> Result<Vector, Error> {
+        let mut embeddings = self.provider.embed(&[content.to_string()]).await
             .map_err(|e| {
-                eprintln!("OpenAI embedding query failed for message: {:?}", e);
+                eprintln!("Embedding query failed for document: {:?}", e);
                 Error::EmbeddingError
             })?;
         
-        // Map to pgvector f32
-        let embedding_vec_f32: Vec<f32> = embedding_vec_f64.into_iter().map(|f| f as f32).collect();
-        Ok(Vector::from(embedding_vec_f32))
+        Self::to_vector(embeddings.pop().ok_or(Error::EmbeddingError)?)
     }
 
-    pub async fn embed_document(&self, content: &str) -> Result<Vector, Error> {
-        let embedding_vec_f64 = self.model.embed_query(content).await
+    /// Embeds several texts in a single request, preserving their order.
+    pub async fn embed_documents(&self, contents: &[String]) -> Result<Vec<Vector>, Error> {
+        if contents.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let embeddings = self.provider.embed(contents).await
             .map_err(|e| {
-                eprintln!("OpenAI embedding query failed for document: {:?}", e);
+                eprintln!("Embedding query failed for document chunks: {:?}", e);
                 Error::EmbeddingError
             })?;
-        
-        // Map to pgvector f32
-        let embedding_vec_f32: Vec<f32> = embedding_vec_f64.into_iter().map(|f| f as f32).collect();
-        Ok(Vector::from(embedding_vec_f32))
+
+        embeddings.into_iter().map(Self::to_vector).collect()
     }
 }
 
-// Function to embed and store a user message, now returns the embedding Vector
-pub async fn embed_and_store_user_message(
+// Average of a set of embeddings, used as the document-level embedding
+fn mean_vector(vectors: &[Vector]) -> Option<Vector> {
+    let first = vectors.first()?;
+    let mut sum = vec![0f32; first.as_slice().len()];
+    for vector in vectors {
+        for (total, value) in sum.iter_mut().zip(vector.as_slice()) {
+            *total += value;
+        }
+    }
+    let count = vectors.len() as f32;
+    Some(Vector::from(sum.into_iter().map(|total| total / count).collect::<Vec<f32>>()))
+}
+
+/// Splits a document into chunks, embeds every chunk and replaces the stored chunks.
+/// The document-level embedding is refreshed as the mean of the chunk embeddings,
+/// so long documents are no longer truncated into a single vector.
+/// Returns the number of chunks stored.
+pub async fn embed_and_store_document_chunks(
     embedding_model: &EmbeddingModel,
     pool: &PgPool,
-    session_id: i32,
-    content: &WritingAssistantMessage,
-) -> Result<Vector, Error> {
-    println!("->> {:<12} - Embedding user message content", "EMBED");
-    let user_embedding = embedding_model.embed_message(content).await?;
+    document_id: i32,
+    content: &str,
+) -> Result<usize, Error> {
+    let chunks = chunk_document(content);
+    println!("->> {:<12} - Embedding {} chunks for document {}", "EMBED", chunks.len(), document_id);
+
+    let texts: Vec<String> = chunks.iter().map(|chunk| chunk.embedding_text()).collect();
+    let embeddings = embedding_model.embed_documents(&texts).await?;
+
+    let mut tx = pool.begin().await.map_err(|e| {
+        eprintln!("DB Error starting chunk transaction: {:?}", e);
+        Error::DatabaseError
+    })?;
+
+    sqlx::query!("DELETE FROM document_chunks WHERE document_id = $1", document_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("DB Error clearing document chunks: {:?}", e);
+            Error::DatabaseError
+        })?;
+
+    for (chunk, embedding) in chunks.iter().zip(embeddings.iter()) {
+        sqlx::query!(
+            r#"
+            INSERT INTO document_chunks (document_id, chunk_index, heading, content, start_offset, end_offset, embedding)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            document_id,
+            chunk.chunk_index,
+            chunk.heading,
+            chunk.content,
+            chunk.start_offset,
+            chunk.end_offset,
+            embedding.clone() as _
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("DB Error storing document chunk: {:?}", e);
+            Error::DatabaseError
+        })?;
+    }
 
-    // Store User Message (with embedding)
-    println!("->> {:<12} - Storing user message", "EMBED");
     sqlx::query!(
         r#"
-        INSERT INTO writing_assistant_messages (session_id, role, content, created_at, embedding)
-        VALUES ($1, $2, $3, $4, $5)
+        UPDATE documents
+        SET embedding = $1, embedding_updated_at = $2
+        WHERE id = $3
         "#,
-        session_id,
-        MessageRole::User as _,
-        content.content,
-        Utc::now().naive_utc(),
-        user_embedding as _
+        mean_vector(&embeddings) as _,
+        Utc::now(),
+        document_id
     )
-    .execute(pool)
+    .execute(&mut *tx)
     .await
     .map_err(|e| {
-        eprintln!("DB Error storing user message: {:?}", e);
+        eprintln!("DB Error updating document embedding: {:?}", e);
+        Error::DatabaseError
+    })?;
+
+    tx.commit().await.map_err(|e| {
+        eprintln!("DB Error committing document chunks: {:?}", e);
         Error::DatabaseError
     })?;
 
-    // Remove the session timestamp update from here, it belongs in the controller
-    // sqlx::query!( ... ).execute(pool).await.map_err(|_| Error::DatabaseError)?;
+    Ok(chunks.len())
+}
 
-    Ok(user_embedding) // Return the calculated embedding
+/// A user message that is stored together with its answer, see `embed_and_store_exchange`
+pub struct PendingUserMessage {
+    pub content: String,
+    pub embedding: Vector,
 }
 
-// Function to embed and store an assistant message
-pub async fn embed_and_store_assistant_message(
+/// The answer of a chat turn, see `embed_and_store_exchange`
+pub struct AssistantAnswer<'a> {
+    pub content: &'a str,
+    pub citations: &'a [Citation],
+    pub prompt_template: &'a str,
+}
+
+/// Stores an answered chat turn and moves the session to it, all or nothing.
+/// The user message (none when an existing one was answered again) is added below `parent_message_id`,
+/// the answer below it. Nothing is stored for a turn without an answer, so the session stays on its branch.
+/// Returns the ID of the stored answer.
+pub async fn embed_and_store_exchange(
     embedding_model: &EmbeddingModel,
     pool: &PgPool,
     session_id: i32,
-    content: &str,
-) -> Result<(), Error> { // Return Result<(), Error> for now
+    parent_message_id: Option<i32>,
+    user_message: Option<&PendingUserMessage>,
+    answer: AssistantAnswer<'_>,
+) -> Result<i32, Error> {
     println!("->> {:<12} - Embedding assistant message content", "EMBED");
-    // Embed the provided content directly using the passed model instance
-    let assistant_embedding = embedding_model.embed_document(content).await?;
+    let assistant_embedding = embedding_model.embed_document(answer.content).await?;
+
+    let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+    let mut parent_message_id = parent_message_id;
+    if let Some(user_message) = user_message {
+        println!("->> {:<12} - Storing user message", "EMBED");
+        let stored = sqlx::query!(
+            r#"
+            INSERT INTO writing_assistant_messages (session_id, parent_message_id, role, content, created_at, embedding)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id
+            "#,
+            session_id,
+            parent_message_id,
+            MessageRole::User as _,
+            user_message.content,
+            Utc::now().naive_utc(),
+            user_message.embedding.clone() as _
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("DB Error storing user message: {:?}", e);
+            Error::DatabaseError
+        })?;
+        parent_message_id = Some(stored.id);
+    }
 
-    // Store Assistant Message (with embedding)
     println!("->> {:<12} - Storing assistant message", "EMBED");
-    sqlx::query!(
+    let stored = sqlx::query!(
         r#"
-        INSERT INTO writing_assistant_messages (session_id, role, content, created_at, embedding)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO writing_assistant_messages (session_id, parent_message_id, role, content, created_at, embedding, citations, prompt_template)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING id
         "#,
         session_id,
-        MessageRole::Assistant as _, // Use the enum
-        content, // Use the passed content
+        parent_message_id,
+        MessageRole::Assistant as _,
+        answer.content,
         Utc::now().naive_utc(),
-        assistant_embedding as _
+        assistant_embedding as _,
+        Json(answer.citations) as _,
+        answer.prompt_template
     )
-    .execute(pool) // Use the passed pool
+    .fetch_one(&mut *tx)
     .await
     .map_err(|e| {
         eprintln!("DB Error storing assistant message: {:?}", e);
-        Error::DatabaseError // Map to your custom DB error
+        Error::DatabaseError
     })?;
-    Ok(())
+
+    sqlx::query!(
+        "UPDATE writing_assistant_sessions SET active_message_id = $2, updated_at = $3 WHERE id = $1",
+        session_id,
+        stored.id,
+        Utc::now().naive_utc()
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    tx.commit().await.map_err(|_| Error::DatabaseError)?;
+
+    Ok(stored.id)
 }
\ No newline at end of file
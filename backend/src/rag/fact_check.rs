@@ -0,0 +1,93 @@
+// Fact checks against the user's own sources
+//
+// The model first lists the factual claims of the text. Every claim is embedded and the most
+// similar chunks of the project's documents and the reference documents are retrieved (see
+// retrieval::search_sources). The passages get labels (S1, S2, ...) like chat sources, and the
+// model judges every claim only by them: supported, contradicted or unknown, citing the labels.
+// A verdict without a valid citation is unknown, the model's own knowledge is no evidence.
+
+use crate::models::fact_check::{CheckedClaim, ClaimVerdict, EvidencePassage, LlmClaim, LlmClaimVerdicts};
+use crate::rag::citations::{citation_for_chunk, citation_label};
+use crate::rag::consistency::locate_quote;
+use crate::rag::retrieval::RetrievedChunk;
+
+/// Chunks retrieved per claim
+pub const EVIDENCE_PER_CLAIM: i64 = 3;
+
+/// The passages retrieved for the claims, each chunk once and labeled in order of retrieval,
+/// and for every claim the labels of its passages
+pub fn label_passages(per_claim: Vec<Vec<RetrievedChunk>>) -> (Vec<EvidencePassage>, Vec<Vec<String>>) {
+    let mut passages: Vec<EvidencePassage> = Vec::new();
+    let mut claim_labels = Vec::with_capacity(per_claim.len());
+    for chunks in per_claim {
+        let mut labels = Vec::new();
+        for chunk in chunks {
+            let known = passages.iter().find(|passage| passage.citation.chunk_id == chunk.chunk_id && passage.citation.document_id == chunk.document_id);
+            let label = match known {
+                Some(passage) => passage.citation.label.clone(),
+                None => {
+                    let label = citation_label(passages.len());
+                    passages.push(EvidencePassage { citation: citation_for_chunk(label.clone(), &chunk), text: chunk.content });
+                    label
+                }
+            };
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        claim_labels.push(labels);
+    }
+    (passages, claim_labels)
+}
+
+/// The checked claims, located in the text. Claims the model gave no verdict for, or no valid
+/// citation for a supported or contradicted verdict, are unknown.
+pub fn checked_claims(content: &str, claims: Vec<LlmClaim>, answer: Option<LlmClaimVerdicts>, passages: &[EvidencePassage]) -> Vec<CheckedClaim> {
+    let mut verdicts = answer.map(|answer| answer.verdicts).unwrap_or_default();
+    claims
+        .into_iter()
+        .enumerate()
+        .map(|(index, claim)| {
+            let span = locate_quote(content, &claim.quote);
+            let verdict = verdicts
+                .iter()
+                .position(|verdict| verdict.claim == index + 1)
+                .map(|position| verdicts.swap_remove(position));
+
+            let (verdict, explanation, citations) = match verdict {
+                None if passages.is_empty() => (ClaimVerdict::Unknown, "No passage of the sources is about this claim".to_string(), Vec::new()),
+                None => (ClaimVerdict::Unknown, "The sources were not judged for this claim".to_string(), Vec::new()),
+                Some(verdict) => {
+                    let mut citations: Vec<EvidencePassage> = Vec::new();
+                    for label in &verdict.sources {
+                        let label = label.trim().trim_start_matches('[').trim_end_matches(']');
+                        match passages.iter().find(|passage| passage.citation.label == label) {
+                            Some(passage) if !citations.iter().any(|cited| cited.citation.label == label) => citations.push(passage.clone()),
+                            Some(_) => {}
+                            None => println!("->> {:<12} - Dropping unknown source '{}' of claim {}", "FACT_CHECK", label, index + 1),
+                        }
+                    }
+                    match verdict.verdict {
+                        ClaimVerdict::Unknown => (ClaimVerdict::Unknown, verdict.explanation.trim().to_string(), Vec::new()),
+                        _ if citations.is_empty() => (
+                            ClaimVerdict::Unknown,
+                            format!("{} (no source was cited)", verdict.explanation.trim()),
+                            Vec::new(),
+                        ),
+                        found => (found, verdict.explanation.trim().to_string(), citations),
+                    }
+                }
+            };
+
+            CheckedClaim {
+                claim: claim.claim.trim().to_string(),
+                quote: claim.quote.trim().to_string(),
+                start_offset: span.map(|(start, _)| start),
+                end_offset: span.map(|(_, end)| end),
+                verdict,
+                explanation,
+                citations,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,337 @@
+// Durable Postgres-backed queue for document embedding jobs
+//
+// Saving a document only enqueues a job; a background worker picks jobs up, chunks and
+// embeds the document, and retries failures with exponential backoff. Jobs that keep
+// failing are moved to the 'dead' status so they can be inspected and re-queued.
+// Finished jobs are kept for a while for the admin overview, then purged.
+// Once a document is embedded its tag suggestions are refreshed (see rag::tagging).
+
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use std::env;
+use tokio::time;
+
+use crate::models::job::{EmbeddingJob, EmbeddingJobStatusCount};
+use crate::rag::embed::{embed_and_store_document_chunks, EmbeddingModel};
+use crate::rag::tagging::suggest_document_tags;
+use crate::{Error, Result};
+
+/// Tunables for the embedding queue, read from the environment
+#[derive(Debug, Clone)]
+pub struct EmbeddingJobConfig {
+    /// Delay before a queued document is embedded; later saves push it back (default: 30s)
+    pub debounce_secs: i64,
+    /// Attempts before a job is dead-lettered (default: 5)
+    pub max_attempts: i32,
+    /// Base delay of the exponential retry backoff (default: 30s)
+    pub retry_base_secs: i64,
+    /// Upper bound of the retry backoff (default: 1h)
+    pub retry_max_secs: i64,
+    /// How often the worker polls for due jobs (default: 5s)
+    pub poll_interval_secs: u64,
+    /// How long finished jobs are kept before they are purged (default: 24h)
+    pub done_retention_secs: i64,
+}
+
+impl Default for EmbeddingJobConfig {
+    fn default() -> Self {
+        fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+            env::var(name).ok().and_then(|v| v.parse::<T>().ok()).unwrap_or(default)
+        }
+
+        EmbeddingJobConfig {
+            debounce_secs: env_or("EMBEDDING_JOB_DEBOUNCE_SECS", 30),
+            max_attempts: env_or("EMBEDDING_JOB_MAX_ATTEMPTS", 5),
+            retry_base_secs: env_or("EMBEDDING_JOB_RETRY_BASE_SECS", 30),
+            retry_max_secs: env_or("EMBEDDING_JOB_RETRY_MAX_SECS", 3600),
+            poll_interval_secs: env_or("EMBEDDING_JOB_POLL_SECS", 5),
+            done_retention_secs: env_or("EMBEDDING_JOB_DONE_RETENTION_SECS", 86400),
+        }
+    }
+}
+
+impl EmbeddingJobConfig {
+    /// Backoff before the next attempt, doubling after every failure
+    pub fn retry_delay(&self, attempts: i32) -> Duration {
+        let exponent = (attempts - 1).clamp(0, 16) as u32;
+        let secs = self.retry_base_secs.saturating_mul(2i64.pow(exponent));
+        Duration::seconds(secs.min(self.retry_max_secs))
+    }
+}
+
+/// Queues a document for (re-)embedding.
+/// If a pending job already exists for the document its run time is pushed back,
+/// so a burst of saves results in a single embedding call.
+pub async fn enqueue_document_embedding(pool: &PgPool, document_id: i32) -> Result<()> {
+    let config = EmbeddingJobConfig::default();
+    let run_at = Utc::now().naive_utc() + Duration::seconds(config.debounce_secs);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO embedding_jobs (document_id, run_at)
+        VALUES ($1, $2)
+        ON CONFLICT (document_id) WHERE status = 'pending'
+        DO UPDATE SET run_at = EXCLUDED.run_at, updated_at = NOW()
+        "#,
+        document_id,
+        run_at
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        eprintln!("DB Error enqueuing embedding job for document {}: {:?}", document_id, e);
+        Error::DatabaseError
+    })?;
+
+    println!("->> {:<12} - Queued embedding for document {} at {}", "EMBED_JOB", document_id, run_at);
+    Ok(())
+}
+
+/// Queues every non-trashed document for embedding, e.g. after changing the embedding model.
+/// With `only_missing` set, documents that already have an embedding are skipped.
+/// Returns the number of documents queued.
+pub async fn enqueue_backfill(pool: &PgPool, only_missing: bool) -> Result<u64> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO embedding_jobs (document_id, run_at)
+        SELECT id, $2 FROM documents
+        WHERE is_trashed = false
+          AND content IS NOT NULL
+          AND ($1 = false OR embedding IS NULL)
+        ON CONFLICT (document_id) WHERE status = 'pending'
+        DO UPDATE SET run_at = EXCLUDED.run_at, updated_at = NOW()
+        "#,
+        only_missing,
+        Utc::now().naive_utc()
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        eprintln!("DB Error enqueuing embedding backfill: {:?}", e);
+        Error::DatabaseError
+    })?;
+
+    println!("->> {:<12} - Backfill queued {} documents (only_missing: {})", "EMBED_JOB", result.rows_affected(), only_missing);
+    Ok(result.rows_affected())
+}
+
+/// Moves dead-lettered jobs back into the queue. Returns the number of jobs revived.
+pub async fn retry_dead_jobs(pool: &PgPool) -> Result<u64> {
+    // A document may already have a newer pending job, in which case the dead one is dropped
+    sqlx::query!(
+        r#"
+        DELETE FROM embedding_jobs dead
+        WHERE dead.status = 'dead'
+          AND EXISTS (SELECT 1 FROM embedding_jobs p WHERE p.document_id = dead.document_id AND p.status = 'pending')
+        "#
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE embedding_jobs
+        SET status = 'pending', attempts = 0, run_at = $1, updated_at = NOW()
+        WHERE id IN (
+            SELECT DISTINCT ON (document_id) id FROM embedding_jobs
+            WHERE status = 'dead'
+            ORDER BY document_id, updated_at DESC
+        )
+        "#,
+        Utc::now().naive_utc()
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    Ok(result.rows_affected())
+}
+
+/// Number of jobs per status, for the admin overview
+pub async fn job_status_counts(pool: &PgPool) -> Result<Vec<EmbeddingJobStatusCount>> {
+    let rows = sqlx::query!(
+        r#"SELECT status, COUNT(*) AS "count!" FROM embedding_jobs GROUP BY status ORDER BY status"#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| EmbeddingJobStatusCount { status: row.status, count: row.count })
+        .collect())
+}
+
+/// Starts the embedding worker on the tokio runtime
+pub fn spawn_embedding_worker(pool: PgPool) {
+    tokio::spawn(run_embedding_worker(pool));
+}
+
+async fn run_embedding_worker(pool: PgPool) {
+    let config = EmbeddingJobConfig::default();
+    println!("->> {:<12} - Embedding worker started ({:?})", "EMBED_JOB", config);
+
+    // Jobs left running by a previous process will never finish, put them back in the queue
+    if let Err(e) = requeue_running_jobs(&pool).await {
+        eprintln!("->> {:<12} - Failed to requeue running jobs: {:?}", "EMBED_JOB", e);
+    }
+
+    let mut last_purge: Option<time::Instant> = None;
+    loop {
+        match claim_next_job(&pool).await {
+            Ok(Some(job)) => process_job(&pool, &config, job).await,
+            Ok(None) => {
+                // Purge finished jobs while idle, at most once per retention period
+                let retention = std::time::Duration::from_secs(config.done_retention_secs.max(0) as u64);
+                if last_purge.is_none_or(|at| at.elapsed() >= retention) {
+                    match purge_done_jobs(&pool, config.done_retention_secs).await {
+                        Ok(purged) if purged > 0 => println!("->> {:<12} - Purged {} finished jobs", "EMBED_JOB", purged),
+                        Ok(_) => {}
+                        Err(e) => eprintln!("->> {:<12} - Failed to purge finished jobs: {:?}", "EMBED_JOB", e),
+                    }
+                    last_purge = Some(time::Instant::now());
+                }
+                time::sleep(std::time::Duration::from_secs(config.poll_interval_secs)).await
+            }
+            Err(e) => {
+                eprintln!("->> {:<12} - Failed to claim embedding job: {:?}", "EMBED_JOB", e);
+                time::sleep(std::time::Duration::from_secs(config.poll_interval_secs)).await;
+            }
+        }
+    }
+}
+
+// Puts running jobs back in the queue. A document has at most one pending job, so a running job
+// is dropped when the document was queued again meanwhile, or when it has a newer running job.
+async fn requeue_running_jobs(pool: &PgPool) -> Result<u64> {
+    let mut tx = pool.begin().await.map_err(|_| Error::DatabaseError)?;
+    sqlx::query!(
+        r#"
+        DELETE FROM embedding_jobs running
+        WHERE running.status = 'running'
+          AND EXISTS (
+              SELECT 1 FROM embedding_jobs other
+              WHERE other.document_id = running.document_id
+                AND (other.status = 'pending' OR (other.status = 'running' AND other.id > running.id))
+          )
+        "#
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    let result = sqlx::query!(
+        "UPDATE embedding_jobs SET status = 'pending', updated_at = NOW() WHERE status = 'running'"
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    tx.commit().await.map_err(|_| Error::DatabaseError)?;
+
+    Ok(result.rows_affected())
+}
+
+// Deletes finished jobs older than the retention period, returns the number deleted
+async fn purge_done_jobs(pool: &PgPool, retention_secs: i64) -> Result<u64> {
+    let result = sqlx::query!(
+        "DELETE FROM embedding_jobs WHERE status = 'done' AND updated_at < $1",
+        Utc::now().naive_utc() - Duration::seconds(retention_secs)
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    Ok(result.rows_affected())
+}
+
+// Atomically marks the oldest due job as running, skipping jobs held by other workers
+async fn claim_next_job(pool: &PgPool) -> Result<Option<EmbeddingJob>> {
+    sqlx::query_as!(
+        EmbeddingJob,
+        r#"
+        UPDATE embedding_jobs
+        SET status = 'running', attempts = attempts + 1, updated_at = NOW()
+        WHERE id = (
+            SELECT id FROM embedding_jobs
+            WHERE status = 'pending' AND run_at <= $1
+            ORDER BY run_at
+            FOR UPDATE SKIP LOCKED
+            LIMIT 1
+        )
+        RETURNING id, document_id, status, attempts, run_at, last_error
+        "#,
+        Utc::now().naive_utc()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        eprintln!("DB Error claiming embedding job: {:?}", e);
+        Error::DatabaseError
+    })
+}
+
+async fn process_job(pool: &PgPool, config: &EmbeddingJobConfig, job: EmbeddingJob) {
+    println!("->> {:<12} - Running job {} for document {} (attempt {})", "EMBED_JOB", job.id, job.document_id, job.attempts);
+
+    match embed_document(pool, job.document_id).await {
+        Ok(chunk_count) => {
+            println!("->> {:<12} - Job {} embedded {} chunks", "EMBED_JOB", job.id, chunk_count);
+            // Suggestions are a bonus, the embedding is done either way
+            if let Err(e) = suggest_document_tags(pool, job.document_id).await {
+                eprintln!("->> {:<12} - Tag suggestions for document {} failed: {:?}", "EMBED_JOB", job.document_id, e);
+            }
+            let _ = sqlx::query!(
+                "UPDATE embedding_jobs SET status = 'done', last_error = NULL, updated_at = NOW() WHERE id = $1",
+                job.id
+            )
+            .execute(pool)
+            .await
+            .map_err(|e| eprintln!("->> {:<12} - Failed to complete job {}: {:?}", "EMBED_JOB", job.id, e));
+        }
+        Err(err) => {
+            let last_error = format!("{:?}", err);
+            if job.attempts >= config.max_attempts {
+                eprintln!("->> {:<12} - Job {} dead-lettered after {} attempts: {}", "EMBED_JOB", job.id, job.attempts, last_error);
+                let _ = sqlx::query!(
+                    "UPDATE embedding_jobs SET status = 'dead', last_error = $2, updated_at = NOW() WHERE id = $1",
+                    job.id,
+                    last_error
+                )
+                .execute(pool)
+                .await;
+            } else {
+                let run_at = Utc::now().naive_utc() + config.retry_delay(job.attempts);
+                eprintln!("->> {:<12} - Job {} failed ({}), retrying at {}", "EMBED_JOB", job.id, last_error, run_at);
+                // A newer pending job for the same document supersedes this retry
+                let requeue = sqlx::query!(
+                    "UPDATE embedding_jobs SET status = 'pending', run_at = $2, last_error = $3, updated_at = NOW() WHERE id = $1",
+                    job.id,
+                    run_at,
+                    last_error
+                )
+                .execute(pool)
+                .await;
+                if requeue.is_err() {
+                    let _ = sqlx::query!("DELETE FROM embedding_jobs WHERE id = $1", job.id)
+                        .execute(pool)
+                        .await;
+                }
+            }
+        }
+    }
+}
+
+async fn embed_document(pool: &PgPool, document_id: i32) -> Result<usize> {
+    let document = sqlx::query!(
+        "SELECT content FROM documents WHERE id = $1",
+        document_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?
+    .ok_or(Error::DocumentNotFoundError { document_id })?;
+
+    let embedding_model = EmbeddingModel::new()?;
+    embed_and_store_document_chunks(&embedding_model, pool, document_id, &document.content.unwrap_or_default()).await
+}
@@ -1,29 +1,81 @@
-use langchain_rust::language_models::llm::LLM;
-use langchain_rust::llm::openai::OpenAI;
-use langchain_rust::llm::OpenAIConfig;
-use std::env;
+use sqlx::PgPool;
+
+use crate::models::credits::{AiOperation, CreditLedger};
+use crate::rag::provider::{completion_provider, CompletionProvider, OutputSchema, TokenStream};
+use crate::rag::templates::RenderedPrompt;
+use crate::rag::tokenizer::Tokenizer;
 use crate::Error;
 
 pub struct QueryModel {
-    model: OpenAI<OpenAIConfig>
+    provider: Box<dyn CompletionProvider>
 }
 
 impl QueryModel {
+    /// Creates a query model backed by the provider selected with `LLM_PROVIDER`
     pub fn new() -> Result<Self, Error> {
-        let open_ai = OpenAI::default().with_config(
-            OpenAIConfig::default().with_api_key(
-                env::var("OPENAI_API_KEY")
-                    .map_err(|_| Error::APIKeyError)?
-            )
-        );
-        Ok(Self { model: open_ai })
+        Ok(Self { provider: completion_provider()? })
+    }
+
+    /// Model answering queries, as known to its provider
+    pub fn model(&self) -> &str {
+        self.provider.model()
+    }
+
+    /// Name of the provider and model answering queries, e.g. "openai/gpt-3.5-turbo"
+    pub fn model_name(&self) -> String {
+        format!("{}/{}", self.provider.name(), self.provider.model())
+    }
+
+    pub async fn query_model(&self, prompt: &RenderedPrompt) -> Result<String, Error> {
+        self.provider.complete(prompt).await
+            .map_err(|err| {
+                eprintln!("LLM Query Error occurred ({}): {:?}", self.model_name(), err);
+                Error::LlmQueryError
+            })
     }
 
-    pub async fn query_model(&self, prompt: &str) -> Result<String, Error> {
-        self.model.invoke(prompt).await
-            .map_err(|_err| {
-                eprintln!("LLM Query Error occurred");
+    /// Queries for an answer following `schema`, see rag::structured
+    pub async fn query_structured(&self, prompt: &RenderedPrompt, schema: &OutputSchema) -> Result<String, Error> {
+        self.provider.complete_structured(prompt, schema).await
+            .map_err(|err| {
+                eprintln!("LLM Structured Query Error occurred ({}): {:?}", self.model_name(), err);
                 Error::LlmQueryError
             })
     }
-}
\ No newline at end of file
+
+    /// Starts a streamed completion. Dropping the returned stream cancels the upstream request.
+    pub async fn stream_model(&self, prompt: &RenderedPrompt) -> Result<TokenStream, Error> {
+        self.provider.complete_stream(prompt).await
+            .map_err(|err| {
+                eprintln!("LLM Stream Error occurred ({}): {:?}", self.model_name(), err);
+                Error::LlmQueryError
+            })
+    }
+}
+
+/// Charges a user for a prompt and queries the LLM.
+/// The price depends on the operation and the prompt size; a failed LLM call is refunded.
+pub async fn charged_query(
+    pool: &PgPool,
+    user_id: i32,
+    operation: AiOperation,
+    query_model: &QueryModel,
+    prompt: &RenderedPrompt,
+) -> Result<String, Error> {
+    let tokenizer = Tokenizer::for_model(query_model.model());
+    let charge = CreditLedger::charge(pool, user_id, operation, tokenizer.count(&prompt.text), &prompt.template).await?;
+
+    match query_model.query_model(prompt).await {
+        Ok(response) => {
+            if let Err(e) = CreditLedger::record_completion(pool, &charge, tokenizer.count(&response)).await {
+                eprintln!("->> {:<12} - Failed to record completion tokens: {:?}", "CREDITS", e);
+            }
+            Ok(response)
+        }
+        Err(Error::LlmQueryError) => {
+            CreditLedger::refund(pool, &charge, "LLM query failed").await?;
+            Err(Error::LlmQueryError)
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -1,4 +1,19 @@
+pub mod chunk;
+pub mod citations;
 pub mod embed;
 pub mod prompt;
+pub mod templates;
+pub mod structured;
+pub mod patch;
+pub mod summary;
+pub mod title;
+pub mod translate;
+pub mod consistency;
+pub mod fact_check;
+pub mod tagging;
+pub mod agent;
+pub mod tokenizer;
 pub mod retrieval;
-pub mod llm;
\ No newline at end of file
+pub mod llm;
+pub mod jobs;
+pub mod provider;
\ No newline at end of file
@@ -0,0 +1,80 @@
+// Search/replace edits proposed by the model
+//
+// Apply-suggestion does not ask the model to repeat whole documents. It answers with hunks
+// ({document_id, search, replace}) that are applied here:
+//   - every hunk is located in the ORIGINAL document, never in partly edited text
+//   - `search` must match exactly once; if it does not match verbatim, a match that only
+//     differs in whitespace is accepted (models often re-wrap lines)
+//   - hunks of one document may not overlap
+//   - a document is only changed when all of its hunks apply, otherwise it is reported
+//     as a failure with the reason and left untouched
+
+use regex::Regex;
+
+use crate::models::ai::LlmDocEdit;
+
+/// Byte range of a hunk in the original document and its replacement
+struct LocatedEdit<'a> {
+    start: usize,
+    end: usize,
+    replace: &'a str,
+}
+
+/// Applies the hunks of one document to its original content.
+/// Returns the new content, or why the hunks cannot be applied.
+pub fn apply_edits(original: &str, edits: &[&LlmDocEdit]) -> Result<String, String> {
+    let mut located: Vec<LocatedEdit> = Vec::with_capacity(edits.len());
+    let mut appended = String::new();
+
+    for (index, edit) in edits.iter().enumerate() {
+        // Empty search appends, which is also how empty documents get their first content
+        if edit.search.is_empty() {
+            appended.push_str(&edit.replace);
+            continue;
+        }
+        let (start, end) = locate(original, &edit.search).map_err(|problem| format!("Edit {} {}", index + 1, problem))?;
+        located.push(LocatedEdit { start, end, replace: &edit.replace });
+    }
+
+    located.sort_by_key(|edit| edit.start);
+    for pair in located.windows(2) {
+        if pair[1].start < pair[0].end {
+            return Err("Two edits change the same text".to_string());
+        }
+    }
+
+    let mut new_content = String::with_capacity(original.len() + appended.len());
+    let mut last_end = 0;
+    for edit in &located {
+        new_content.push_str(&original[last_end..edit.start]);
+        new_content.push_str(edit.replace);
+        last_end = edit.end;
+    }
+    new_content.push_str(&original[last_end..]);
+    new_content.push_str(&appended);
+
+    Ok(new_content)
+}
+
+// Byte range of the single occurrence of `search` in `original`
+fn locate(original: &str, search: &str) -> Result<(usize, usize), String> {
+    let exact: Vec<(usize, &str)> = original.match_indices(search).take(2).collect();
+    match exact.len() {
+        1 => return Ok((exact[0].0, exact[0].0 + search.len())),
+        0 => {}
+        _ => return Err("matches more than one place, its search text must be unique".to_string()),
+    }
+
+    // Same words, different whitespace
+    let words: Vec<String> = search.split_whitespace().map(regex::escape).collect();
+    if words.is_empty() {
+        return Err("searches for whitespace only".to_string());
+    }
+    let pattern = Regex::new(&words.join(r"\s+")).map_err(|_| "has an unusable search text".to_string())?;
+    let loose: Vec<(usize, usize)> = pattern.find_iter(original).take(2).map(|m| (m.start(), m.end())).collect();
+    match loose.len() {
+        1 => Ok(loose[0]),
+        0 => Err("was not found in the document".to_string()),
+        _ => Err("matches more than one place, its search text must be unique".to_string()),
+    }
+}
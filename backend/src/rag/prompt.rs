@@ -1,241 +1,553 @@
-use crate::models::ai::{ChatHistory, MessageRole, ContextDocument, ProactiveDiffContextPayload};
+use crate::models::ai::{ChatHistory, ChatMessage, MessageRole, ContextDocument, ProactiveDiffContextPayload, Citation};
+use crate::models::knowledge::ProjectEntity;
+use crate::models::fact_check::{EvidencePassage, LlmClaim, MAX_CLAIMS};
+use crate::rag::consistency::TimelineEvent;
+use crate::rag::chunk::to_plain_text;
 use crate::rag::retrieval::RetrievedChunk;
+use crate::rag::citations::{citation_for_chunk, citation_label, strip_citation_markers};
+use crate::rag::templates::{self, RenderedPrompt};
+use crate::rag::tokenizer::{context_window_for_model, Tokenizer};
+use crate::{Error, Result};
+use std::env;
+
+/// Completion tokens kept free by default (override with LLM_RESERVED_OUTPUT_TOKENS)
+const DEFAULT_RESERVED_OUTPUT_TOKENS: usize = 1024;
+/// Share of the input budget each section may claim when everything does not fit:
+/// context chunks, chat history, user query. Unused share flows to the other sections.
+const SECTION_WEIGHTS: [f64; 3] = [0.55, 0.30, 0.15];
+/// A context chunk that does not fit is cut down if at least this many tokens are left for it
+const MIN_PARTIAL_CHUNK_TOKENS: usize = 64;
+/// Share of the input budget the project knowledge base may take before the sections are allocated
+const KNOWLEDGE_SHARE: f64 = 0.15;
+
+/// Token budget of a prompt, derived from the model answering it
+pub struct PromptBudget {
+    /// Total tokens the model accepts (prompt + completion)
+    pub context_window: usize,
+    /// Tokens left free for the completion
+    pub reserved_output: usize,
+    pub tokenizer: Tokenizer,
+}
+
+impl PromptBudget {
+    /// Budget for a model name, `LLM_CONTEXT_TOKENS` and `LLM_RESERVED_OUTPUT_TOKENS` override the defaults
+    pub fn for_model(model: &str) -> Self {
+        fn env_usize(name: &str) -> Option<usize> {
+            env::var(name).ok().and_then(|v| v.parse::<usize>().ok())
+        }
+
+        PromptBudget {
+            context_window: env_usize("LLM_CONTEXT_TOKENS").unwrap_or_else(|| context_window_for_model(model)),
+            reserved_output: env_usize("LLM_RESERVED_OUTPUT_TOKENS").unwrap_or(DEFAULT_RESERVED_OUTPUT_TOKENS),
+            tokenizer: Tokenizer::for_model(model),
+        }
+    }
+
+    /// Tokens available for the prompt itself
+    pub fn input_tokens(&self) -> usize {
+        self.context_window.saturating_sub(self.reserved_output)
+    }
 
-const MAX_HISTORY_TOKENS: usize = 1000; // Example token limit for history
-const MAX_CONTEXT_TOKENS: usize = 1500; // Example token limit for context
+    pub fn count(&self, text: &str) -> usize {
+        self.tokenizer.count(text)
+    }
+
+    /// Tokens the chat history may claim when context and query need their full share,
+    /// longer histories are summarized (see rag::summary)
+    pub fn history_tokens(&self) -> usize {
+        (self.input_tokens() as f64 * SECTION_WEIGHTS[1]).floor() as usize
+    }
+
+    /// Tokens the project knowledge base may take, it is cut at the end beyond that
+    pub fn knowledge_tokens(&self) -> usize {
+        (self.input_tokens() as f64 * KNOWLEDGE_SHARE).floor() as usize
+    }
+}
 
-// Basic token counting heuristic (split by space)
-fn estimate_tokens(text: &str) -> usize {
-    text.split_whitespace().count()
+// Knowledge base of the history cut to its share, or a placeholder
+fn knowledge_section(chat_history: &ChatHistory, budget: &PromptBudget) -> String {
+    match chat_history.knowledge.as_deref().map(str::trim).filter(|knowledge| !knowledge.is_empty()) {
+        Some(knowledge) if budget.count(knowledge) > budget.knowledge_tokens() => {
+            println!("->> {:<12} - Project knowledge base truncated due to length", "PROMPT");
+            budget.tokenizer.truncate(knowledge, budget.knowledge_tokens())
+        }
+        Some(knowledge) => knowledge.to_string(),
+        None => "(No project knowledge base)".to_string(),
+    }
+}
+
+/// Splits `available` tokens between sections asking for `demands` tokens.
+/// Sections that need less than their weighted share get what they need, the rest is
+/// shared by the remaining sections in proportion to their weights.
+fn allocate_budget(available: usize, demands: [usize; 3], weights: [f64; 3]) -> [usize; 3] {
+    let mut allocation = [0usize; 3];
+    let mut remaining = available;
+    let mut active: Vec<usize> = (0..3).filter(|&i| demands[i] > 0).collect();
+
+    while !active.is_empty() && remaining > 0 {
+        let weight_sum: f64 = active.iter().map(|&i| weights[i]).sum();
+        let share = |i: usize| (remaining as f64 * weights[i] / weight_sum).floor() as usize;
+
+        let satisfied: Vec<usize> = active.iter().copied().filter(|&i| demands[i] <= share(i)).collect();
+        if satisfied.is_empty() {
+            // Nobody fits in their share, split what is left and stop
+            let shares: Vec<(usize, usize)> = active.iter().map(|&i| (i, share(i))).collect();
+            for (i, tokens) in shares {
+                allocation[i] = tokens;
+            }
+            break;
+        }
+        for i in satisfied {
+            allocation[i] = demands[i];
+            remaining -= demands[i];
+            active.retain(|&j| j != i);
+        }
+    }
+
+    allocation
+}
+
+fn chunk_header(label: &str, chunk: &RetrievedChunk) -> String {
+    source_header(label, chunk.document_id, &chunk.document_name, chunk.heading.as_deref())
+}
+
+fn source_header(label: &str, document_id: i32, document_name: &str, heading: Option<&str>) -> String {
+    match heading {
+        Some(heading) => format!("--- Source [{}] (Document ID: {}, Name: {}, Section: {}) ---\n", label, document_id, document_name, heading),
+        None => format!("--- Source [{}] (Document ID: {}, Name: {}) ---\n", label, document_id, document_name),
+    }
+}
+
+// Chat history line of a message
+fn history_line(message: &ChatMessage) -> String {
+    let role_str = match message.role {
+        MessageRole::System => "System",
+        MessageRole::User => "User",
+        MessageRole::Assistant => "Assistant",
+    };
+    format!("{}: {}\n", role_str, strip_citation_markers(&message.content))
 }
 
-/// Constructs a generic prompt for the LLM using chat history and context.
+/// Constructs a generic prompt for the LLM using chat history and context, from the `chat` template.
+/// Context, history and query share the model's input budget, see `allocate_budget`.
+/// The history share covers the session summary (cut to a third of it if the recent messages need
+/// the rest), the recent messages and, with what is left, the recalled earlier messages.
+/// Returns the prompt and the citable sources it contains, one per context chunk that fit.
 pub fn construct_generic_prompt(
     user_query: &str,
     chat_history: &ChatHistory,
     context_chunks: &[RetrievedChunk],
     current_doc_id: Option<i32>,
-    current_doc_name: Option<&str>
-) -> String {
-    let mut prompt = String::new();
-
-    prompt.push_str(
-        "You are a helpful writing assistant. \
-        Use the following 'Relevant Context' retrieved from the user's documents \
-        and the 'Chat History' to answer the 'User Query'. \
-        Synthesize information from the context and history to provide a specific and helpful response. \
-        If the context contains information relevant to the query, use it directly in your answer. \
-        Your response should be plain text only, without any markdown, HTML, or code formatting.\n\n"
-    );
-
-    prompt.push_str("Current Document Focus:\n");
-    match (current_doc_id, current_doc_name) {
-        (Some(id), Some(name)) => prompt.push_str(&format!("- ID: {}, Name: {}\n\n", id, name)),
-        (Some(id), None) => prompt.push_str(&format!("- ID: {}\n\n", id)),
-        _ => prompt.push_str("- No specific document associated with this chat.\n\n"),
+    current_doc_name: Option<&str>,
+    budget: &PromptBudget,
+) -> Result<(RenderedPrompt, Vec<Citation>)> {
+    let document_focus = match (current_doc_id, current_doc_name) {
+        (Some(id), Some(name)) => format!("- ID: {}, Name: {}", id, name),
+        (Some(id), None) => format!("- ID: {}", id),
+        _ => "- No specific document associated with this chat.".to_string(),
+    };
+
+    // Everything except the three sections is always sent, the knowledge base included
+    let system_prompt = chat_history.system_prompt();
+    let knowledge = knowledge_section(chat_history, budget);
+    let frame = templates::render("chat", &[
+        ("system_prompt", system_prompt.as_str().into()),
+        ("knowledge", knowledge.as_str().into()),
+        ("document_focus", document_focus.as_str().into()),
+        ("context", "".into()),
+        ("summary", "".into()),
+        ("earlier_messages", "".into()),
+        ("history", "".into()),
+        ("query", "".into()),
+    ])?;
+    let fixed_tokens = budget.count(&frame.text);
+    let available = budget.input_tokens().saturating_sub(fixed_tokens);
+
+    // System messages are the persona, they open the prompt instead of appearing in the history
+    let history_lines: Vec<String> = chat_history.messages.iter()
+        .filter(|message| message.role != MessageRole::System)
+        .map(history_line)
+        .collect();
+    let recalled_lines: Vec<String> = chat_history.recalled.iter().map(history_line).collect();
+    let summary = chat_history.summary.as_deref().unwrap_or("").trim();
+
+    let context_demand: usize = context_chunks.iter().enumerate()
+        .map(|(i, chunk)| budget.count(&chunk_header(&citation_label(i), chunk)) + budget.count(&chunk.content) + budget.count("\n---\n"))
+        .sum();
+    let summary_demand = budget.count(summary);
+    let recalled_demand: usize = recalled_lines.iter().map(|line| budget.count(line)).sum();
+    let recent_demand: usize = history_lines.iter().map(|line| budget.count(line)).sum();
+    let history_demand = summary_demand + recalled_demand + recent_demand;
+    let query_demand = budget.count(user_query);
+
+    let [context_budget, history_budget, query_budget] =
+        allocate_budget(available, [context_demand, history_demand, query_demand], SECTION_WEIGHTS);
+    println!("->> {:<12} - Budget {} of {} tokens: context {}/{}, history {}/{}, query {}/{}",
+             "PROMPT", available, budget.context_window,
+             context_budget, context_demand, history_budget, history_demand, query_budget, query_demand);
+
+    // Add context in relevance order
+    let mut context = String::new();
+    let mut sources: Vec<Citation> = Vec::new();
+    let mut current_context_tokens = 0;
+    for chunk in context_chunks {
+        // Labels are handed out to included chunks only, so they stay consecutive
+        let label = citation_label(sources.len());
+        let header = chunk_header(&label, chunk);
+        let overhead = budget.count(&header) + budget.count("\n---\n");
+        let chunk_tokens = overhead + budget.count(&chunk.content);
+        let remaining = context_budget.saturating_sub(current_context_tokens);
+
+        let content = if chunk_tokens <= remaining {
+            chunk.content.clone()
+        } else if remaining >= overhead + MIN_PARTIAL_CHUNK_TOKENS {
+            println!("->> {:<12} - Context chunk from document {} truncated to fit", "PROMPT", chunk.document_id);
+            budget.tokenizer.truncate(&chunk.content, remaining - overhead)
+        } else {
+            // Skip this chunk but keep going, a smaller chunk further down may still fit
+            println!("->> {:<12} - Context chunk from document {} skipped due to length", "PROMPT", chunk.document_id);
+            continue;
+        };
+
+        current_context_tokens += overhead + budget.count(&content);
+        sources.push(citation_for_chunk(label, chunk));
+        context.push_str(&header);
+        context.push_str(&content);
+        context.push_str("\n---\n"); // Separator after each chunk
+    }
+    if context.is_empty() {
+        context.push_str("(No relevant context found from other documents)"); // Indicate no context was found
     }
-    prompt.push_str("---\n\n");
-
-    // Add context if available (truncated if too long)
-    prompt.push_str("Relevant Context (from related documents):\n");
-    if !context_chunks.is_empty() {
-        let mut current_context_tokens = 0;
-        for chunk in context_chunks {
-            let chunk_header = format!("--- Source Document (ID: {}, Name: {}) ---\n", chunk.document_id, chunk.document_name);
-            let chunk_content = &chunk.content;
-            let chunk_tokens = estimate_tokens(&chunk_header) + estimate_tokens(chunk_content);
-
-            if current_context_tokens + chunk_tokens > MAX_CONTEXT_TOKENS {
-                println!("->> {:<12} - Context truncated due to length (skipping remaining chunks)", "PROMPT");
-                break; // Stop adding context if limit exceeded
-            }
-            prompt.push_str(&chunk_header);
-            prompt.push_str(chunk_content);
-            prompt.push_str("\n---\n"); // Separator after each chunk
-            current_context_tokens += chunk_tokens;
-        }
-        prompt.push_str("\n"); // Add a final newline after context section
 
+    // Summary of the older conversation, cut down if it would crowd out the recent messages
+    let summary_budget = history_budget.saturating_sub(recent_demand).max(history_budget / 3);
+    let summary_str = if summary.is_empty() {
+        "(No earlier conversation)".to_string()
+    } else if summary_demand > summary_budget {
+        println!("->> {:<12} - Session summary truncated due to length", "PROMPT");
+        budget.tokenizer.truncate(summary, summary_budget)
     } else {
-        prompt.push_str("(No relevant context found from other documents)\n\n"); // Indicate no context was found
-    }
-    prompt.push_str("---\n\n"); // Separator after context section
+        summary.to_string()
+    };
+    let mut current_history_tokens = if summary.is_empty() { 0 } else { budget.count(&summary_str) };
 
-    // Add chat history (recent messages first, truncated if too long)
-    prompt.push_str("Chat History (Recent first):\n");
-    let mut current_history_tokens = 0;
+    // Add chat history, keeping the most recent messages that fit
     let mut history_str = String::new();
-    // Skip the very first message if it's the initial system prompt from ChatHistory::new()
-    for message in chat_history.messages.iter().rev().skip(1) { // <-- Added .skip(1)
-        let role_str = match message.role {
-            MessageRole::User => "User",
-            MessageRole::Assistant => "Assistant",
-        };
-        let message_line = format!("{}: {}\n", role_str, message.content);
-        let message_tokens = estimate_tokens(&message_line);
+    for message_line in history_lines.iter().rev() {
+        let message_tokens = budget.count(message_line);
 
-        if current_history_tokens + message_tokens > MAX_HISTORY_TOKENS {
+        if current_history_tokens + message_tokens > history_budget {
             println!("->> {:<12} - History truncated due to length", "PROMPT");
-            break; // Stop adding history if limit exceeded
+            break; // Older messages are dropped
         }
-        history_str.insert_str(0, &message_line);
+        history_str.insert_str(0, message_line);
         current_history_tokens += message_tokens;
     }
     if history_str.is_empty() {
-        prompt.push_str("(No relevant chat history)\n");
-    } else {
-        prompt.push_str(&history_str);
+        history_str.push_str("(No relevant chat history)");
+    }
+
+    // Earlier messages recalled for this query get what the recent ones left
+    let mut earlier_str = String::new();
+    for message_line in &recalled_lines {
+        let message_tokens = budget.count(message_line);
+        if current_history_tokens + message_tokens > history_budget {
+            println!("->> {:<12} - Recalled message skipped due to length", "PROMPT");
+            continue;
+        }
+        earlier_str.push_str(message_line);
+        current_history_tokens += message_tokens;
+    }
+    if earlier_str.is_empty() {
+        earlier_str.push_str("(No relevant earlier messages)");
     }
-    prompt.push_str("\n---\n\n");
 
     // Add the current user query
-    prompt.push_str("User Query:\n");
-    prompt.push_str(user_query);
-    // Add a stronger, final instruction for plain text output
-    prompt.push_str("\n\nIMPORTANT: Generate the response as plain text ONLY. Do NOT use any Markdown (like **, lists, etc.), HTML, or other formatting.\n\nAssistant Response:");
-    // Keep the final log statement
-    println!("->> {:<12} - Prompt constructed ({} tokens estimated)", "PROMPT", estimate_tokens(&prompt));
-
-    prompt
+    let query = if query_demand > query_budget {
+        println!("->> {:<12} - User query truncated due to length", "PROMPT");
+        budget.tokenizer.truncate(user_query, query_budget)
+    } else {
+        user_query.to_string()
+    };
+
+    let prompt = templates::render("chat", &[
+        ("system_prompt", system_prompt.into()),
+        ("knowledge", knowledge.into()),
+        ("document_focus", document_focus.into()),
+        ("context", context.trim_end().into()),
+        ("summary", summary_str.into()),
+        ("earlier_messages", earlier_str.trim_end().into()),
+        ("history", history_str.trim_end().into()),
+        ("query", query.into()),
+    ])?;
+    println!("->> {:<12} - Prompt constructed from {} ({} tokens, {} sources)", "PROMPT", prompt.template, budget.count(&prompt.text), sources.len());
+
+    Ok((prompt, sources))
 }
 
-pub fn construct_grammar_check_prompt(text: &str) -> String {
-    format!(
-        "Please correct the grammar and spelling of the following text. Only return the corrected text without any explanations or introductory phrase.\n\n\
-        Text to Correct:\n\
-        ```\n\
-        {}\n\
-        ```\n\n\
-        If you have no recommended changes or are unable to fix the grammar/spelling for any reason, ONLY return the exact string '__VYNN_NO_CHANGE__'. Otherwise, return ONLY the corrected text.",
-        text
-    )
+/// Constructs one step of the agent loop from the `agent_step` template, see rag::agent.
+/// The chat history (session summary first) gets the history share of the budget, recent messages
+/// first; the query is cut to its share. The transcript of tool results is sized by the agent itself.
+pub fn construct_agent_step_prompt(
+    user_query: &str,
+    chat_history: &ChatHistory,
+    document_focus: &str,
+    tools: serde_json::Value,
+    transcript: &str,
+    remaining_steps: usize,
+    budget: &PromptBudget,
+) -> Result<RenderedPrompt> {
+    let history_budget = budget.history_tokens();
+    let mut history_str = String::new();
+    let mut current_history_tokens = 0;
+    let recent_lines: Vec<String> = chat_history.messages.iter()
+        .filter(|message| message.role != MessageRole::System)
+        .map(history_line)
+        .collect();
+    for message_line in recent_lines.iter().rev() {
+        let message_tokens = budget.count(message_line);
+        if current_history_tokens + message_tokens > history_budget {
+            break; // Older messages are dropped
+        }
+        history_str.insert_str(0, message_line);
+        current_history_tokens += message_tokens;
+    }
+    if let Some(summary) = chat_history.summary.as_deref().filter(|summary| !summary.trim().is_empty()) {
+        let summary_budget = history_budget.saturating_sub(current_history_tokens).max(history_budget / 3);
+        let summary_line = format!("Summary of the earlier conversation: {}\n", budget.tokenizer.truncate(summary.trim(), summary_budget));
+        history_str.insert_str(0, &summary_line);
+    }
+    if history_str.is_empty() {
+        history_str.push_str("(No chat history)");
+    }
+
+    let query_budget = (budget.input_tokens() as f64 * SECTION_WEIGHTS[2]).floor() as usize;
+    let query = if budget.count(user_query) > query_budget {
+        println!("->> {:<12} - User query truncated due to length", "PROMPT");
+        budget.tokenizer.truncate(user_query, query_budget)
+    } else {
+        user_query.to_string()
+    };
+    let transcript = if transcript.trim().is_empty() { "(none yet)" } else { transcript.trim_end() };
+
+    templates::render("agent_step", &[
+        ("system_prompt", chat_history.system_prompt().into()),
+        ("knowledge", knowledge_section(chat_history, budget).into()),
+        ("tools", tools.into()),
+        ("document_focus", document_focus.into()),
+        ("history", history_str.trim_end().into()),
+        ("query", query.into()),
+        ("steps", transcript.into()),
+        ("remaining_steps", (remaining_steps as i32).into()),
+    ])
 }
 
-pub fn construct_spell_check_prompt(text: &str) -> String {
-    format!(
-        "Please correct only the spelling mistakes in the following text, keeping the original grammar and sentence structure intact. Only return the corrected text without any explanations or introductory phrase.\n\n\
-        Text to Correct:\n\
-        ```\n\
-        {}\n\
-        ```\n\n\
-        If you find no spelling mistakes or are unable to correct spelling for any reason, ONLY return the exact string '__VYNN_NO_CHANGE__'. Otherwise, return ONLY the corrected text.",
-        text
-    )
+pub fn construct_grammar_check_prompt(text: &str) -> Result<RenderedPrompt> {
+    templates::render("grammar_check", &[("text", text.into())])
 }
 
-pub fn construct_summarize_prompt(text: &str) -> String {
-    format!(
-        "Please provide a concise summary of the following text. Only return the summary without any explanations or introductory phrase.\n\n\
-        Text to Summarize:\n\
-        ```\n\
-        {}\n\
-        ```\n\n\
-        If you are unable to summarize the text for any reason, ONLY return the exact string '__VYNN_NO_CHANGE__'. Otherwise, return ONLY the summary.",
-        text
-    )
+pub fn construct_spell_check_prompt(text: &str) -> Result<RenderedPrompt> {
+    templates::render("spell_check", &[("text", text.into())])
 }
 
-pub fn construct_rephrase_prompt(text: &str) -> String {
-    format!(
-        "Please rephrase the following text to improve clarity and flow. Only return the rephrased text without any explanations or introductory phrases.\n\n\
-        Text to Rephrase:\n\
-        ```\n\
-        {}\n\
-        ```\n\n\
-        If you have no recommended changes or are unable to rephrase for any reason, ONLY return the exact string '__VYNN_NO_CHANGE__'. Otherwise, return ONLY the rephrased text.",
-        text
-    )
+pub fn construct_summarize_prompt(text: &str) -> Result<RenderedPrompt> {
+    templates::render("summarize", &[("text", text.into())])
 }
 
-pub fn construct_expand_prompt(text: &str) -> String {
-    format!(
-        "Please expand on the following text, adding more detail and explanation where appropriate. Only return the expanded text without any explanations or introductory phrases.\n\n\
-        Text to Expand:\n\
-        ```\n\
-        {}\n\
-        ```\n\n\
-        If you have no recommended changes or are unable to expand for any reason, ONLY return the exact string '__VYNN_NO_CHANGE__'. Otherwise, return ONLY the expanded text.",
-        text
-    )
+pub fn construct_rephrase_prompt(text: &str) -> Result<RenderedPrompt> {
+    templates::render("rephrase", &[("text", text.into())])
+}
+
+pub fn construct_expand_prompt(text: &str) -> Result<RenderedPrompt> {
+    templates::render("expand", &[("text", text.into())])
+}
+
+pub fn construct_shrink_prompt(text: &str) -> Result<RenderedPrompt> {
+    templates::render("shrink", &[("text", text.into())])
+}
+
+pub fn construct_rewrite_prompt(text: &str, style: &str) -> Result<RenderedPrompt> {
+    templates::render("rewrite", &[("text", text.into()), ("style", style.into())])
 }
 
-pub fn construct_shrink_prompt(text: &str) -> String {
-    format!(
-        "Please shrink the following text, making it more concise while retaining the core meaning. Only return the shrinked text without any explanations or introductory phrases.\n\n\
-        Text to Shrink:\n\
-        ```\n\
-        {}\n\
-        ```\n\n\
-        If you have no recommended changes or are unable to shrink for any reason, ONLY return the exact string '__VYNN_NO_CHANGE__'. Otherwise, return ONLY the shrinked text.",
+pub fn construct_fact_check_prompt(text: &str) -> Result<RenderedPrompt> {
+    templates::render("fact_check", &[("text", text.into())])
+}
+
+/// Constructs the prompt listing the factual claims of a text, from the `fact_check_claims` template.
+/// The text gets half of the input budget and is cut at the end beyond that.
+pub fn construct_fact_check_claims_prompt(content: &str, budget: &PromptBudget) -> Result<RenderedPrompt> {
+    let text = to_plain_text(content);
+    let text_budget = budget.input_tokens() / 2;
+    let text = if budget.count(&text) > text_budget {
+        println!("->> {:<12} - Text truncated for claim extraction", "PROMPT");
+        budget.tokenizer.truncate(&text, text_budget)
+    } else {
         text
-    )
+    };
+
+    templates::render("fact_check_claims", &[
+        ("max_claims", (MAX_CLAIMS as i32).into()),
+        ("text", text.into()),
+    ])
+}
+
+/// Constructs the prompt judging claims by the passages of the user's sources, from the
+/// `fact_check_evidence` template. Every claim lists the labels of the passages retrieved for it.
+/// The passages share what the frame and the claims leave of the input budget equally, so every
+/// claim keeps its evidence; longer passages are cut at the end.
+pub fn construct_fact_check_evidence_prompt(
+    claims: &[LlmClaim],
+    claim_labels: &[Vec<String>],
+    passages: &[EvidencePassage],
+    budget: &PromptBudget,
+) -> Result<RenderedPrompt> {
+    let claims = claims.iter()
+        .zip(claim_labels)
+        .enumerate()
+        .map(|(i, (claim, labels))| if labels.is_empty() {
+            format!("[C{}] {} (no passages found)", i + 1, claim.claim.trim())
+        } else {
+            format!("[C{}] {} (passages found: {})", i + 1, claim.claim.trim(), labels.join(", "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let frame = templates::render("fact_check_evidence", &[("sources", "".into()), ("claims", claims.as_str().into())])?;
+    let headers: Vec<String> = passages.iter()
+        .map(|passage| {
+            let citation = &passage.citation;
+            source_header(&citation.label, citation.document_id, &citation.document_name, citation.heading.as_deref())
+        })
+        .collect();
+    let overhead: usize = headers.iter().map(|header| budget.count(header) + budget.count("\n---\n")).sum();
+    let available = budget.input_tokens().saturating_sub(budget.count(&frame.text) + overhead);
+    let passage_budget = available / passages.len().max(1);
+
+    let mut sources = String::new();
+    for (header, passage) in headers.iter().zip(passages) {
+        sources.push_str(header);
+        if budget.count(&passage.text) > passage_budget {
+            println!("->> {:<12} - Source passage {} truncated to fit", "PROMPT", passage.citation.label);
+            sources.push_str(&budget.tokenizer.truncate(&passage.text, passage_budget));
+        } else {
+            sources.push_str(&passage.text);
+        }
+        sources.push_str("\n---\n");
+    }
+    if sources.is_empty() {
+        sources.push_str("(No passages found)");
+    }
+
+    templates::render("fact_check_evidence", &[("sources", sources.into()), ("claims", claims.into())])
 }
 
-pub fn construct_rewrite_prompt(text: &str, style: &str) -> String {
-    format!(
-        "Please rewrite the following text in the style of '{}'. Only return the rewritten text without any explanations or introductory phrases.\n\n\
-        Text to Rewrite:\n\
-        ```\n\
-        {}\n\
-        ```\n\n\
-        If you are unable to rewrite the text for any reason, ONLY return the exact string '__VYNN_NO_CHANGE__'. Otherwise, return ONLY the rewritten text.",
-        style, text
-    )
+/// Constructs the prompt finding the entities of one document, from the `knowledge_extract` template.
+/// The known entities are listed so the model reuses their names; the document gets half of the
+/// input budget and is cut at the end beyond that.
+pub fn construct_knowledge_extract_prompt(
+    project_name: &str,
+    document_name: &str,
+    content: &str,
+    known: &[ProjectEntity],
+    budget: &PromptBudget,
+) -> Result<RenderedPrompt> {
+    let known_entities = if known.is_empty() {
+        "(none yet)".to_string()
+    } else {
+        known.iter()
+            .map(|entity| if entity.aliases.is_empty() {
+                format!("- {} ({})", entity.name, entity.kind.as_str())
+            } else {
+                format!("- {} ({}, also: {})", entity.name, entity.kind.as_str(), entity.aliases.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let known_entities = budget.tokenizer.truncate(&known_entities, budget.knowledge_tokens());
+
+    let text = to_plain_text(content);
+    let text_budget = budget.input_tokens() / 2;
+    let text = if budget.count(&text) > text_budget {
+        println!("->> {:<12} - Document '{}' truncated for entity extraction", "PROMPT", document_name);
+        budget.tokenizer.truncate(&text, text_budget)
+    } else {
+        text
+    };
+
+    templates::render("knowledge_extract", &[
+        ("project_name", project_name.into()),
+        ("document_name", document_name.into()),
+        ("known_entities", known_entities.into()),
+        ("text", text.into()),
+    ])
 }
 
-pub fn construct_fact_check_prompt(text: &str) -> String {
-    format!(
-        "Please critically evaluate the factual claims in the following text based on your knowledge. Identify any potential inaccuracies or statements that might require verification. Respond concisely.\n\n\
-        Text to Fact-Check:\n\
-        ```\n\
-        {}\n\
-        ```\n\n\
-        If you are unable to fact-check the text for any reason, ONLY return the exact string '__VYNN_NO_CHANGE__'. Otherwise, return ONLY your concise evaluation.",
+/// Constructs the prompt checking one document against the knowledge base and the timeline of the
+/// earlier documents, from the `consistency_check` template. Knowledge base and timeline each get
+/// the knowledge share of the budget, the timeline keeps its earliest events; the document gets
+/// half of the input budget and is cut at the end beyond that.
+pub fn construct_consistency_check_prompt(
+    project_name: &str,
+    document_name: &str,
+    content: &str,
+    knowledge: Option<&str>,
+    timeline: &[TimelineEvent],
+    budget: &PromptBudget,
+) -> Result<RenderedPrompt> {
+    let knowledge = match knowledge.map(str::trim).filter(|knowledge| !knowledge.is_empty()) {
+        Some(knowledge) => budget.tokenizer.truncate(knowledge, budget.knowledge_tokens()),
+        None => "(No project knowledge base)".to_string(),
+    };
+
+    let timeline = if timeline.is_empty() {
+        "(This is the first document)".to_string()
+    } else {
+        let events = timeline.iter()
+            .map(|event| format!("- ({}) {}", event.document_name, event.event))
+            .collect::<Vec<_>>()
+            .join("\n");
+        budget.tokenizer.truncate(&events, budget.knowledge_tokens())
+    };
+
+    let text = to_plain_text(content);
+    let text_budget = budget.input_tokens() / 2;
+    let text = if budget.count(&text) > text_budget {
+        println!("->> {:<12} - Document '{}' truncated for the consistency check", "PROMPT", document_name);
+        budget.tokenizer.truncate(&text, text_budget)
+    } else {
         text
-    )
+    };
+
+    templates::render("consistency_check", &[
+        ("project_name", project_name.into()),
+        ("document_name", document_name.into()),
+        ("knowledge", knowledge.into()),
+        ("timeline", timeline.into()),
+        ("text", text.into()),
+    ])
 }
 
 /// Constructs a prompt for applying an AI suggestion across project documents.
 pub fn construct_apply_suggestion_prompt(
     project_documents: &[(i32, String, String)], // List of (id, name, content)
     suggestion_to_apply: &str,
-    active_document_id: Option<i32>, // New parameter
-) -> Result<String, serde_json::Error> { 
-    let mut prompt = String::new();
-
-    // --- System Instructions ---
-    let mut system_instruction = String::from("You are an AI assistant tasked with applying a given suggestion to a set of documents within a project. ");
-
-    if let Some(active_id) = active_document_id {
-        system_instruction.push_str(&format!(
-            "The user is currently focused on Document ID: {}. Prioritize applying the suggestion to this document. ", active_id
-        ));
-        
-        // Check if the active document is empty
-        if let Some((_, _, active_doc_content)) = project_documents.iter().find(|(id, _, _)| *id == active_id) {
-            if active_doc_content.trim().is_empty() {
-                system_instruction.push_str(
-                    "This active document is currently empty. If the 'Suggestion to Apply' is suitable as new content for an empty document (e.g., a complete story, article, or section), then the 'new_content' for this active document should be the 'Suggestion to Apply' itself. "
-                );
-            } else {
-                system_instruction.push_str(
-                    "This active document has existing content. Determine how the 'Suggestion to Apply' modifies this existing content. "
-                );
-            }
+    active_document_id: Option<i32>,
+) -> Result<RenderedPrompt> {
+    // How to treat the document the user is focused on
+    let focus_instruction = match active_document_id {
+        Some(active_id) => {
+            let active_is_empty = project_documents
+                .iter()
+                .find(|(id, _, _)| *id == active_id)
+                .map(|(_, _, content)| content.trim().is_empty())
+                .unwrap_or(false);
+            let template = if active_is_empty { "apply_suggestion_focus_empty" } else { "apply_suggestion_focus_existing" };
+            templates::render(template, &[("active_document_id", active_id.into())])?
         }
-    } else {
-        system_instruction.push_str(
-            "No specific document is marked as active. Analyze the provided 'Project Documents' and the 'Suggestion to Apply'. "
-        );
-    }
-
-    system_instruction.push_str(
-        "Determine which documents need modification based on the suggestion. For ONLY the documents that need changes, generate their complete new content. Your response MUST be a JSON array containing objects, where each object represents a changed document and has the following structure: { \"document_id\": <integer>, \"new_content\": \"<full_new_document_content_as_string>\" }. Do NOT include documents that remain unchanged in the JSON array. Ensure the 'new_content' is the complete text of the document after applying the suggestion. If the suggestion cannot be applied or no documents need changes, return an empty JSON array []. Output ONLY the JSON array, with no other text before or after it. Do not return any markdown text!\n\n"
-    );
-    prompt.push_str(&system_instruction);
+        None => templates::render("apply_suggestion_focus_none", &[])?,
+    };
 
-    // --- Add Project Documents ---
-    prompt.push_str("Project Documents:\n");
     let context_docs: Vec<ContextDocument> = project_documents
         .iter()
         .map(|(id, name, content)| ContextDocument {
@@ -244,30 +556,56 @@ pub fn construct_apply_suggestion_prompt(
             content: content.clone(),
         })
         .collect();
+    let docs_json = serde_json::to_value(&context_docs).map_err(|e| {
+        eprintln!("Error serializing documents for prompt: {:?}", e);
+        Error::FailedApplyChanges
+    })?;
 
-    // Serialize the documents context into a JSON string for clarity in the prompt
-    let docs_json = serde_json::to_string_pretty(&context_docs)?; // Use pretty print for readability
-    prompt.push_str("```json\n");
-    prompt.push_str(&docs_json);
-    prompt.push_str("\n```\n\n");
-    prompt.push_str("---\n\nSuggestion to Apply:\n");
-    prompt.push_str(suggestion_to_apply);
-    prompt.push_str("\n\n---\n\n");
-    prompt.push_str("JSON Response (array of changed documents, or [] if none):\n");
+    let prompt = templates::render("apply_suggestion", &[
+        ("focus_instruction", focus_instruction.text.into()),
+        ("documents", docs_json.into()),
+        ("suggestion", suggestion_to_apply.into()),
+    ])?;
 
-    println!("->> {:<12} - Apply Suggestion Prompt constructed ({} chars)", "PROMPT", prompt.len()); // Use char count for large prompts
+    println!("->> {:<12} - Apply Suggestion Prompt constructed ({} chars, {})", "PROMPT", prompt.text.len(), prompt.template); // Use char count for large prompts
 
     Ok(prompt)
 }
 
+/// Fits the documents of an apply-suggestion prompt into the model's input budget.
+/// `ranked_documents` is in order of relevance, documents are taken in that order and skipped
+/// once they no longer fit. Used when the whole project is too large for one prompt.
+pub fn select_apply_suggestion_documents(
+    ranked_documents: &[(i32, String, String)], // List of (id, name, content)
+    suggestion_to_apply: &str,
+    active_document_id: Option<i32>,
+    budget: &PromptBudget,
+) -> Result<Vec<(i32, String, String)>> {
+    let frame = construct_apply_suggestion_prompt(&[], suggestion_to_apply, active_document_id)?;
+    let available = budget.input_tokens().saturating_sub(budget.count(&frame.text));
+
+    let mut selected = Vec::new();
+    let mut used_tokens = 0;
+    for (id, name, content) in ranked_documents {
+        let document = ContextDocument { id: *id, name: name.clone(), content: content.clone() };
+        let document_tokens = budget.count(&serde_json::to_string_pretty(&document).unwrap_or_default());
+        if used_tokens + document_tokens > available {
+            println!("->> {:<12} - Document {} skipped, it does not fit the apply suggestion budget", "PROMPT", id);
+            continue;
+        }
+        used_tokens += document_tokens;
+        selected.push((*id, name.clone(), content.clone()));
+    }
+
+    println!("->> {:<12} - Selected {} of {} documents ({} of {} tokens)", "PROMPT", selected.len(), ranked_documents.len(), used_tokens, available);
+    Ok(selected)
+}
+
 pub fn construct_proactive_diff_decision_prompt(
     ai_response_content: &str,
     context: &ProactiveDiffContextPayload,
     document_content_snippet: Option<&str>, 
-) -> String {
-    // System instruction for the LLM
-    // You are a decision-making AI. Based on the AI's response content, the user's context, and an optional snippet of the current document, decide if it is appropriate to proactively show a diff to the user. 
-
+) -> Result<RenderedPrompt> {
     // Restore user_intent_description logic
     let user_intent_description = match context.r#type.as_str() {
         "chat" => format!("User asked: '{}' in chat.", context.user_prompt.as_deref().unwrap_or("N/A")),
@@ -287,45 +625,20 @@ pub fn construct_proactive_diff_decision_prompt(
     } else {
         "No specific document content snippet provided (assume it might be empty or irrelevant to this decision).".to_string()
     };
-    
-    // Construct the prompt with clear instructions for the LLM
-    let mut prompt_segments: Vec<String> = Vec::new();
-
-    prompt_segments.push("You are an AI assistant that helps decide if a user interface should proactively show a diff view. Your sole output MUST be 'True' or 'False'.".to_string());
-    prompt_segments.push(format!("User's action: {}", user_intent_description));
-    prompt_segments.push(format!("Current document state: {}", document_context_description));
-    prompt_segments.push(format!("AI's response to user: \"{}\".", ai_response_content.chars().take(500).collect::<String>())); // Limit length of AI response in prompt
-    prompt_segments.push("Decision criteria:".to_string());
-    prompt_segments.push("- If the AI's response is a direct answer, question, or general statement that doesn't imply changes to a document, output: False".to_string());
-    prompt_segments.push("- If the AI's response explicitly suggests or implies content to be added, removed, or modified in a document (e.g., writing a story, suggesting a paragraph, providing code), output: True".to_string());
-    prompt_segments.push("- If the current document is empty and the AI's response is substantial content, output: True".to_string());
-    prompt_segments.push("- If unsure, lean towards False.".to_string());
-    prompt_segments.push("Based on the above, should a diff be proactively shown to the user? Output True or False.".to_string());
-
-    // Join segments to form the final prompt string
-    prompt_segments.join("\n\n")
+
+    templates::render("proactive_diff_decision", &[
+        ("user_action", user_intent_description.into()),
+        ("document_state", document_context_description.into()),
+        // Limit length of AI response in prompt
+        ("ai_response", ai_response_content.chars().take(500).collect::<String>().into()),
+    ])
 }
 
 /// Constructs a prompt for sanitizing text by removing HTML and Markdown.
-pub fn construct_sanitize_text_prompt(text_with_markdown_html: &str) -> String {
-    format!(
-        "You are a text sanitization AI. Your task is to remove ALL HTML tags (e.g., <p>, <div>, <span>, <img>) and ALL Markdown syntax (e.g., **, _, #, ##, ```, [link](url), ![image](url), lists like * or - or 1.) from the provided text. \
-        Preserve the original textual content and its meaning as much as possible. \
-        If the input text consists *only* of HTML/Markdown and would result in an empty string after sanitization, return an empty string. \
-        Do NOT add any explanations, apologies, or introductory/concluding phrases. \
-        Return ONLY the sanitized plain text.\
-\n\
-        Text to Sanitize:\
-        ```text\
-        {}\n        ```\n\n\
-        Sanitized Text:",
-        text_with_markdown_html
-    )
+pub fn construct_sanitize_text_prompt(text_with_markdown_html: &str) -> Result<RenderedPrompt> {
+    templates::render("sanitize_text", &[("text", text_with_markdown_html.into())])
 }
 
-pub fn construct_context_decision_prompt(user_prompt: &str) -> String {
-    format!(
-        "You are an AI assistant. Based on the following user prompt, decide if the AI needs context from the current document, the current project, or neither. Respond with only 'document', 'project', or 'none'.\n\nUser Prompt:\n{}\n\nResponse:",
-        user_prompt
-    )
+pub fn construct_context_decision_prompt(user_prompt: &str) -> Result<RenderedPrompt> {
+    templates::render("context_decision", &[("user_prompt", user_prompt.into())])
 }
@@ -0,0 +1,241 @@
+// Offline deterministic provider
+// Produces stable answers and embeddings without any network access, so the
+// integration tests can run without an API key or spending money.
+// Completions follow the output contract of the template the prompt was rendered from,
+// the prompt text only supplies the values to answer with;
+// embeddings are hashed bag-of-words vectors, so texts sharing words are similar.
+
+use async_trait::async_trait;
+use futures_util::stream::{self, StreamExt};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{CompletionProvider, EmbeddingProvider, TokenStream};
+use crate::rag::embed::EMBEDDING_DIMENSIONS;
+use crate::rag::templates::RenderedPrompt;
+use crate::{Error, Result};
+
+lazy_static! {
+    // Capitalized words, the entities of a document
+    static ref CAPITALIZED_WORD: Regex = Regex::new(r"\b[A-Z][a-z]{2,}\b").unwrap();
+    // A claim to verify and the labels of its passages, e.g. "[C1] ... (passages found: S1, S2)"
+    static ref CLAIM_LINE: Regex = Regex::new(r"(?m)^\[C(\d+)\] .* \((?:passages found: ([^)]*)|no passages found)\)$").unwrap();
+}
+
+/// Texts containing this marker fail to embed, so tests can exercise the retry and dead-letter paths
+pub const MOCK_EMBEDDING_FAILURE: &str = "MOCK_EMBEDDING_FAILURE";
+
+pub struct MockProvider;
+
+#[async_trait]
+impl CompletionProvider for MockProvider {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn model(&self) -> &str {
+        "mock-completion"
+    }
+
+    async fn complete(&self, prompt: &RenderedPrompt) -> Result<String> {
+        Ok(mock_completion(prompt))
+    }
+
+    async fn complete_stream(&self, prompt: &RenderedPrompt) -> Result<TokenStream> {
+        // Word by word, so clients see more than one delta
+        let tokens: Vec<Result<String>> = mock_completion(prompt)
+            .split_inclusive(' ')
+            .map(|token| Ok(token.to_string()))
+            .collect();
+        Ok(stream::iter(tokens).boxed())
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for MockProvider {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn model(&self) -> &str {
+        "mock-embedding"
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.iter().any(|text| text.contains(MOCK_EMBEDDING_FAILURE)) {
+            return Err(Error::EmbeddingError);
+        }
+        Ok(texts.iter().map(|text| mock_embedding(text)).collect())
+    }
+}
+
+/// Answers in the format the template of the prompt asks for
+pub fn mock_completion(prompt: &RenderedPrompt) -> String {
+    // "chat@v4" -> "chat"
+    let template = prompt.template.split('@').next().unwrap_or_default();
+    let text = prompt.text.as_str();
+    match template {
+        "context_decision" => "none".to_string(),
+        "proactive_diff_decision" => "False".to_string(),
+        // No edits to apply
+        "apply_suggestion" => "[]".to_string(),
+        // Agent steps: look at the outline first, then answer. The last step offers no tools.
+        "agent_step" => {
+            let nothing_done = section_after(text, "Tool Results So Far:\n").as_deref() == Some("(none yet)");
+            let tools_offered = section_after(text, "Available Tools:\n").as_deref() != Some("[]");
+            let step = if nothing_done && tools_offered {
+                serde_json::json!({ "action": "list_outline", "query": null, "exact": null, "document_id": null, "offset": null, "search": null, "replace": null, "answer": null })
+            } else {
+                let query = section_after(text, "User Query:\n").unwrap_or_default();
+                let answer = format!("Mock agent answer to: {}", query);
+                serde_json::json!({ "action": "answer", "query": null, "exact": null, "document_id": null, "offset": null, "search": null, "replace": null, "answer": answer })
+            };
+            step.to_string()
+        }
+        // Entity extraction lists the capitalized words of the document
+        "knowledge_extract" => mock_entities(&first_fenced_block(text).unwrap_or_default()),
+        // Consistency checks flag the last sentence of the document and list the first one as an event
+        "consistency_check" => mock_consistency(&first_fenced_block(text).unwrap_or_default()),
+        // Evidence fact checks list every sentence as a claim, a claim with passages is supported by the first one
+        "fact_check_claims" => mock_claims(&first_fenced_block(text).unwrap_or_default()),
+        "fact_check_evidence" => mock_verdicts(text),
+        // Tag suggestions are always the same, written the way models tend to answer
+        "document_tags" => serde_json::json!({ "tags": ["Draft", "#Notes", "draft"], "topics": ["Writing", "notes"] }).to_string(),
+        // Chat prompts open with the system prompt and end with the user's query, repeat both
+        // and cite the top source when there is one
+        "chat" => {
+            let query = section_after(text, "User Query:\n").unwrap_or_default();
+            let system_prompt = text.split("\n\n").next().unwrap_or_default().trim();
+            let citation = if text.contains("--- Source [S1]") { " [S1]" } else { "" };
+            format!("Mock assistant response to: {}{} (system prompt: {})", query, citation, system_prompt)
+        }
+        // Text tools and translations wrap their input in a fenced block, hand it back unchanged.
+        // A translation repair repeats the original prompt, so it gets the source text as well.
+        "grammar_check" | "spell_check" | "fact_check" | "summarize" | "rephrase" | "expand" | "shrink" | "rewrite"
+        | "sanitize_text" | "translate" | "translate_repair" => first_fenced_block(text).unwrap_or_default(),
+        // Titles, summaries and anything else get a stable answer of their own
+        _ => format!("Mock response ({:016x})", fnv1a(text.as_bytes())),
+    }
+}
+
+/// Hashed bag-of-words embedding, L2 normalised
+pub fn mock_embedding(text: &str) -> Vec<f32> {
+    let mut vector = vec![0f32; EMBEDDING_DIMENSIONS];
+    for token in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+    {
+        let hash = fnv1a(token.to_lowercase().as_bytes());
+        let index = (hash % EMBEDDING_DIMENSIONS as u64) as usize;
+        let sign = if (hash >> 63) == 0 { 1.0 } else { -1.0 };
+        vector[index] += sign;
+    }
+
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    } else {
+        // Keep empty texts comparable instead of producing a zero vector
+        vector[0] = 1.0;
+    }
+    vector
+}
+
+// Contents of the first ``` fenced block, without the language tag
+fn first_fenced_block(prompt: &str) -> Option<String> {
+    let start = prompt.find("```")? + 3;
+    let rest = &prompt[start..];
+    let end = rest.find("```")?;
+    let block = &rest[..end];
+
+    let block = match block.split_once('\n') {
+        Some((tag, body)) if tag.trim().chars().all(|c| c.is_ascii_alphanumeric()) => body,
+        _ => block,
+    };
+    Some(block.trim().to_string())
+}
+
+// Distinct capitalized words in order of first use, as extracted entities
+fn mock_entities(text: &str) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for found in CAPITALIZED_WORD.find_iter(text) {
+        if !names.contains(&found.as_str()) {
+            names.push(found.as_str());
+        }
+    }
+    let entities: Vec<serde_json::Value> = names
+        .into_iter()
+        .map(|name| serde_json::json!({ "name": name, "kind": "other", "aliases": [], "description": "Found in the document." }))
+        .collect();
+    serde_json::json!({ "entities": entities }).to_string()
+}
+
+fn sentences(text: &str) -> Vec<&str> {
+    text.split_inclusive(['.', '!', '?', '\n'])
+        .map(str::trim)
+        .filter(|sentence| !sentence.is_empty())
+        .collect()
+}
+
+fn mock_consistency(text: &str) -> String {
+    let sentences = sentences(text);
+    let findings: Vec<serde_json::Value> = sentences
+        .last()
+        .map(|sentence| {
+            serde_json::json!({
+                "kind": "knowledge_contradiction",
+                "quote": sentence,
+                "explanation": "The knowledge base says otherwise.",
+                "suggestion": null,
+                "entity": null,
+                "conflicts_with": null
+            })
+        })
+        .into_iter()
+        .collect();
+    let events: Vec<&str> = sentences.into_iter().take(1).collect();
+    serde_json::json!({ "findings": findings, "events": events }).to_string()
+}
+
+fn mock_claims(text: &str) -> String {
+    let claims: Vec<serde_json::Value> = sentences(text)
+        .into_iter()
+        .map(|sentence| serde_json::json!({ "claim": sentence, "quote": sentence }))
+        .collect();
+    serde_json::json!({ "claims": claims }).to_string()
+}
+
+fn mock_verdicts(prompt: &str) -> String {
+    let verdicts: Vec<serde_json::Value> = CLAIM_LINE
+        .captures_iter(prompt)
+        .map(|caps| {
+            let number: usize = caps[1].parse().unwrap_or(0);
+            match caps.get(2).and_then(|labels| labels.as_str().split(", ").next()) {
+                Some(label) => serde_json::json!({ "claim": number, "verdict": "supported", "explanation": format!("Passage {} says so.", label), "sources": [label] }),
+                None => serde_json::json!({ "claim": number, "verdict": "unknown", "explanation": "The passages do not say.", "sources": [] }),
+            }
+        })
+        .collect();
+    serde_json::json!({ "verdicts": verdicts }).to_string()
+}
+
+// First paragraph following a section marker
+fn section_after(prompt: &str, marker: &str) -> Option<String> {
+    let start = prompt.rfind(marker)? + marker.len();
+    let section = prompt[start..].split("\n\n").next()?.trim();
+    if section.is_empty() {
+        None
+    } else {
+        Some(section.to_string())
+    }
+}
+
+// Stable 64-bit FNV-1a hash, independent of the Rust version
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
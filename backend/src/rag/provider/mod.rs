@@ -0,0 +1,168 @@
+// Pluggable LLM backends
+//
+// `QueryModel` and `EmbeddingModel` talk to a provider through the traits below.
+// The provider is chosen at runtime from the environment:
+//   LLM_PROVIDER       = openai (default) | ollama | mock
+//   EMBEDDING_PROVIDER = openai | ollama | mock (defaults to LLM_PROVIDER)
+// The mock provider is deterministic and needs no network access, which lets the
+// integration tests run offline.
+//
+// Completions can also be streamed: `complete_stream` yields text deltas as the upstream
+// produces them. Dropping the stream closes the upstream connection, cancelling generation.
+//
+// Providers receive the rendered prompt together with the template it came from. The HTTP
+// providers only send the text, the mock provider answers according to the template.
+//
+// Answers the server parses can be requested with `complete_structured`. Providers with a
+// JSON schema mode constrain the output to the schema; the others answer as usual, so callers
+// validate the result either way (see rag::structured).
+
+pub mod mock;
+pub mod ollama;
+pub mod openai;
+
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use serde_json::Value;
+use std::env;
+
+use crate::rag::templates::RenderedPrompt;
+use crate::{Error, Result};
+
+/// A backend that turns a prompt into a completion
+#[async_trait]
+pub trait CompletionProvider: Send + Sync {
+    /// Short provider name, e.g. "openai"
+    fn name(&self) -> &str;
+    /// Model used for completions
+    fn model(&self) -> &str;
+    async fn complete(&self, prompt: &RenderedPrompt) -> Result<String>;
+    /// Streams the completion as text deltas.
+    /// Providers without native streaming return the whole completion as a single delta.
+    async fn complete_stream(&self, prompt: &RenderedPrompt) -> Result<TokenStream> {
+        let completion = self.complete(prompt).await?;
+        Ok(stream::once(async move { Ok(completion) }).boxed())
+    }
+    /// Completion that should follow a JSON schema.
+    /// Providers without a structured output mode ignore the schema.
+    async fn complete_structured(&self, prompt: &RenderedPrompt, _schema: &OutputSchema) -> Result<String> {
+        self.complete(prompt).await
+    }
+}
+
+/// JSON schema an answer must follow
+#[derive(Debug, Clone)]
+pub struct OutputSchema {
+    /// Identifier sent to providers that name their schemas, e.g. "document_changes"
+    pub name: &'static str,
+    pub schema: Value,
+}
+
+/// Text deltas of a streamed completion, in order
+pub type TokenStream = BoxStream<'static, Result<String>>;
+
+/// A backend that turns texts into embedding vectors
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Short provider name, e.g. "openai"
+    fn name(&self) -> &str;
+    /// Model used for embeddings
+    fn model(&self) -> &str;
+    /// Embeds every text, returning one vector per input in the same order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProviderKind {
+    OpenAi,
+    Ollama,
+    Mock,
+}
+
+impl ProviderKind {
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "openai" => Ok(Self::OpenAi),
+            "ollama" => Ok(Self::Ollama),
+            "mock" => Ok(Self::Mock),
+            other => {
+                eprintln!("Unknown LLM provider '{}'", other);
+                Err(Error::ProviderConfigError)
+            }
+        }
+    }
+
+    /// Provider used for completions
+    pub fn completion_from_env() -> Result<Self> {
+        Self::parse(&env::var("LLM_PROVIDER").unwrap_or_else(|_| "openai".to_string()))
+    }
+
+    /// Provider used for embeddings, falls back to the completion provider
+    pub fn embedding_from_env() -> Result<Self> {
+        match env::var("EMBEDDING_PROVIDER") {
+            Ok(value) => Self::parse(&value),
+            Err(_) => Self::completion_from_env(),
+        }
+    }
+}
+
+/// Builds the completion provider selected by the environment
+pub fn completion_provider() -> Result<Box<dyn CompletionProvider>> {
+    Ok(match ProviderKind::completion_from_env()? {
+        ProviderKind::OpenAi => Box::new(openai::OpenAiProvider::from_env()?),
+        ProviderKind::Ollama => Box::new(ollama::OllamaProvider::from_env()),
+        ProviderKind::Mock => Box::new(mock::MockProvider),
+    })
+}
+
+/// Builds the embedding provider selected by the environment
+pub fn embedding_provider() -> Result<Box<dyn EmbeddingProvider>> {
+    Ok(match ProviderKind::embedding_from_env()? {
+        ProviderKind::OpenAi => Box::new(openai::OpenAiProvider::from_env()?),
+        ProviderKind::Ollama => Box::new(ollama::OllamaProvider::from_env()),
+        ProviderKind::Mock => Box::new(mock::MockProvider),
+    })
+}
+
+// Splits a streamed HTTP body into trimmed lines, as used by SSE and NDJSON responses.
+// The final line is yielded even when the body does not end with a newline.
+pub(crate) fn response_lines(response: reqwest::Response) -> BoxStream<'static, std::result::Result<String, String>> {
+    struct LineState {
+        body: BoxStream<'static, reqwest::Result<Vec<u8>>>,
+        buffer: Vec<u8>,
+        finished: bool,
+    }
+
+    let state = LineState {
+        body: response.bytes_stream().map(|chunk| chunk.map(|bytes| bytes.to_vec())).boxed(),
+        buffer: Vec::new(),
+        finished: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(pos) = state.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = state.buffer.drain(..=pos).collect();
+                return Some((Ok(String::from_utf8_lossy(&line).trim().to_string()), state));
+            }
+            if state.finished {
+                if state.buffer.is_empty() {
+                    return None;
+                }
+                let line = String::from_utf8_lossy(&state.buffer).trim().to_string();
+                state.buffer.clear();
+                return Some((Ok(line), state));
+            }
+            match state.body.next().await {
+                Some(Ok(bytes)) => state.buffer.extend_from_slice(&bytes),
+                Some(Err(e)) => {
+                    state.finished = true;
+                    state.buffer.clear();
+                    return Some((Err(format!("stream interrupted: {:?}", e)), state));
+                }
+                None => state.finished = true,
+            }
+        }
+    })
+    .boxed()
+}
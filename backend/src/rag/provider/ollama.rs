@@ -0,0 +1,200 @@
+// Local Ollama provider
+//   OLLAMA_BASE_URL        - default: http://localhost:11434
+//   OLLAMA_MODEL           - default: llama3
+//   OLLAMA_EMBEDDING_MODEL - default: nomic-embed-text
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::env;
+
+use futures_util::StreamExt;
+
+use super::{response_lines, CompletionProvider, EmbeddingProvider, OutputSchema, TokenStream};
+use crate::rag::templates::RenderedPrompt;
+use crate::{Error, Result};
+
+pub struct OllamaProvider {
+    client: Client,
+    base_url: String,
+    model: String,
+    embedding_model: String,
+}
+
+impl OllamaProvider {
+    pub fn from_env() -> Self {
+        Self {
+            client: Client::new(),
+            base_url: env::var("OLLAMA_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:11434".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            model: env::var("OLLAMA_MODEL").unwrap_or_else(|_| "llama3".to_string()),
+            embedding_model: env::var("OLLAMA_EMBEDDING_MODEL")
+                .unwrap_or_else(|_| "nomic-embed-text".to_string()),
+        }
+    }
+
+    async fn post(&self, path: &str, body: Value) -> std::result::Result<Value, String> {
+        let response = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("request failed: {:?}", e))?;
+
+        let status = response.status();
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("invalid response body: {:?}", e))?;
+
+        if !status.is_success() {
+            return Err(format!("status {}: {}", status, body));
+        }
+        Ok(body)
+    }
+
+    // Starts a streaming request, the body is consumed by the caller
+    async fn post_stream(&self, path: &str, body: Value) -> std::result::Result<reqwest::Response, String> {
+        let response = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("request failed: {:?}", e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("status {}: {}", status, body));
+        }
+        Ok(response)
+    }
+
+    // Sends a non-streaming chat request and returns the message content
+    async fn chat(&self, body: Value) -> Result<String> {
+        let response = self.post("/api/chat", body).await.map_err(|e| {
+            eprintln!("Ollama completion failed: {}", e);
+            Error::LlmQueryError
+        })?;
+
+        response["message"]["content"]
+            .as_str()
+            .map(|content| content.to_string())
+            .ok_or_else(|| {
+                eprintln!("Ollama completion response had no content: {}", response);
+                Error::LlmQueryError
+            })
+    }
+}
+
+#[async_trait]
+impl CompletionProvider for OllamaProvider {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, prompt: &RenderedPrompt) -> Result<String> {
+        let body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt.text }],
+            "stream": false
+        });
+        self.chat(body).await
+    }
+
+    async fn complete_stream(&self, prompt: &RenderedPrompt) -> Result<TokenStream> {
+        let body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt.text }],
+            "stream": true
+        });
+
+        let response = self.post_stream("/api/chat", body).await.map_err(|e| {
+            eprintln!("Ollama streaming completion failed: {}", e);
+            Error::LlmQueryError
+        })?;
+
+        // One JSON object per line, each carrying the next piece of the message
+        let tokens = response_lines(response).filter_map(|line| async move {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("Ollama completion stream failed: {}", e);
+                    return Some(Err(Error::LlmQueryError));
+                }
+            };
+            let chunk: Value = serde_json::from_str(&line).ok()?;
+            if let Some(error) = chunk["error"].as_str() {
+                eprintln!("Ollama completion stream returned an error: {}", error);
+                return Some(Err(Error::LlmQueryError));
+            }
+            chunk["message"]["content"]
+                .as_str()
+                .filter(|content| !content.is_empty())
+                .map(|content| Ok(content.to_string()))
+        });
+        Ok(tokens.boxed())
+    }
+
+    async fn complete_structured(&self, prompt: &RenderedPrompt, schema: &OutputSchema) -> Result<String> {
+        // `format` takes a JSON schema since Ollama 0.5
+        let body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt.text }],
+            "stream": false,
+            "format": schema.schema
+        });
+        self.chat(body).await
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OllamaProvider {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    // The completion model is `self.model`, embeddings use their own
+    #[allow(clippy::misnamed_getters)]
+    fn model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let body = json!({
+            "model": self.embedding_model,
+            "input": texts
+        });
+
+        let response = self.post("/api/embed", body).await.map_err(|e| {
+            eprintln!("Ollama embedding failed: {}", e);
+            Error::EmbeddingError
+        })?;
+
+        let embeddings: Vec<Vec<f32>> = response["embeddings"]
+            .as_array()
+            .ok_or(Error::EmbeddingError)?
+            .iter()
+            .map(|embedding| {
+                embedding
+                    .as_array()
+                    .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        if embeddings.len() != texts.len() {
+            eprintln!("Ollama returned {} embeddings for {} inputs", embeddings.len(), texts.len());
+            return Err(Error::EmbeddingError);
+        }
+        Ok(embeddings)
+    }
+}
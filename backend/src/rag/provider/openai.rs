@@ -0,0 +1,224 @@
+// OpenAI-compatible HTTP provider
+// Works with api.openai.com and any server exposing the same /chat/completions and /embeddings API.
+//   OPENAI_API_KEY         - required
+//   OPENAI_BASE_URL        - default: https://api.openai.com/v1
+//   OPENAI_MODEL           - default: gpt-3.5-turbo
+//   OPENAI_EMBEDDING_MODEL - default: text-embedding-ada-002
+//   OPENAI_STRUCTURED_OUTPUT - true | false, whether the model supports JSON schema response formats
+//                            (default: true for the gpt-4o, gpt-4.1 and o-series families)
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::env;
+
+use futures_util::StreamExt;
+
+use super::{response_lines, CompletionProvider, EmbeddingProvider, OutputSchema, TokenStream};
+use crate::rag::templates::RenderedPrompt;
+use crate::{Error, Result};
+
+pub struct OpenAiProvider {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    embedding_model: String,
+    structured_output: bool,
+}
+
+impl OpenAiProvider {
+    pub fn from_env() -> Result<Self> {
+        let api_key = env::var("OPENAI_API_KEY").map_err(|_| Error::APIKeyError)?;
+        let model = env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-3.5-turbo".to_string());
+        // Older models reject the json_schema response format
+        let structured_output = match env::var("OPENAI_STRUCTURED_OUTPUT") {
+            Ok(value) => value.trim().eq_ignore_ascii_case("true"),
+            Err(_) => ["gpt-4o", "gpt-4.1", "o1", "o3", "o4"].iter().any(|prefix| model.starts_with(prefix)),
+        };
+        Ok(Self {
+            client: Client::new(),
+            base_url: env::var("OPENAI_BASE_URL")
+                .unwrap_or_else(|_| "https://api.openai.com/v1".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            api_key,
+            model,
+            embedding_model: env::var("OPENAI_EMBEDDING_MODEL")
+                .unwrap_or_else(|_| "text-embedding-ada-002".to_string()),
+            structured_output,
+        })
+    }
+
+    async fn post(&self, path: &str, body: Value) -> std::result::Result<Value, String> {
+        let response = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .bearer_auth(&self.api_key)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("request failed: {:?}", e))?;
+
+        let status = response.status();
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("invalid response body: {:?}", e))?;
+
+        if !status.is_success() {
+            return Err(format!("status {}: {}", status, body));
+        }
+        Ok(body)
+    }
+
+    // Starts a streaming request, the body is consumed by the caller
+    async fn post_stream(&self, path: &str, body: Value) -> std::result::Result<reqwest::Response, String> {
+        let response = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .bearer_auth(&self.api_key)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("request failed: {:?}", e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("status {}: {}", status, body));
+        }
+        Ok(response)
+    }
+
+    // Sends a non-streaming chat completion request and returns the message content
+    async fn chat_completion(&self, body: Value) -> Result<String> {
+        let response = self.post("/chat/completions", body).await.map_err(|e| {
+            eprintln!("OpenAI completion failed: {}", e);
+            Error::LlmQueryError
+        })?;
+
+        response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|content| content.to_string())
+            .ok_or_else(|| {
+                eprintln!("OpenAI completion response had no content: {}", response);
+                Error::LlmQueryError
+            })
+    }
+}
+
+#[async_trait]
+impl CompletionProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, prompt: &RenderedPrompt) -> Result<String> {
+        let body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt.text }]
+        });
+        self.chat_completion(body).await
+    }
+
+    async fn complete_stream(&self, prompt: &RenderedPrompt) -> Result<TokenStream> {
+        let body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt.text }],
+            "stream": true
+        });
+
+        let response = self.post_stream("/chat/completions", body).await.map_err(|e| {
+            eprintln!("OpenAI streaming completion failed: {}", e);
+            Error::LlmQueryError
+        })?;
+
+        // Server-sent events: `data: {chunk}` lines, terminated by `data: [DONE]`
+        let tokens = response_lines(response).filter_map(|line| async move {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("OpenAI completion stream failed: {}", e);
+                    return Some(Err(Error::LlmQueryError));
+                }
+            };
+            let data = line.strip_prefix("data:")?.trim();
+            if data == "[DONE]" {
+                return None;
+            }
+            let chunk: Value = serde_json::from_str(data).ok()?;
+            chunk["choices"][0]["delta"]["content"]
+                .as_str()
+                .filter(|content| !content.is_empty())
+                .map(|content| Ok(content.to_string()))
+        });
+        Ok(tokens.boxed())
+    }
+
+    async fn complete_structured(&self, prompt: &RenderedPrompt, schema: &OutputSchema) -> Result<String> {
+        if !self.structured_output {
+            return self.complete(prompt).await;
+        }
+        let body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt.text }],
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": { "name": schema.name, "strict": true, "schema": schema.schema }
+            }
+        });
+        self.chat_completion(body).await
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    // The completion model is `self.model`, embeddings use their own
+    #[allow(clippy::misnamed_getters)]
+    fn model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let body = json!({
+            "model": self.embedding_model,
+            "input": texts
+        });
+
+        let response = self.post("/embeddings", body).await.map_err(|e| {
+            eprintln!("OpenAI embedding failed: {}", e);
+            Error::EmbeddingError
+        })?;
+
+        // Results carry an index, order them to match the inputs
+        let mut data: Vec<(usize, Vec<f32>)> = response["data"]
+            .as_array()
+            .ok_or(Error::EmbeddingError)?
+            .iter()
+            .map(|item| {
+                let index = item["index"].as_u64().unwrap_or(0) as usize;
+                let embedding = item["embedding"]
+                    .as_array()
+                    .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+                    .unwrap_or_default();
+                (index, embedding)
+            })
+            .collect();
+        data.sort_by_key(|(index, _)| *index);
+
+        if data.len() != texts.len() {
+            eprintln!("OpenAI returned {} embeddings for {} inputs", data.len(), texts.len());
+            return Err(Error::EmbeddingError);
+        }
+        Ok(data.into_iter().map(|(_, embedding)| embedding).collect())
+    }
+}
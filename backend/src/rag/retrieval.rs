@@ -3,47 +3,87 @@
 use sqlx::{PgPool, Row};
 use pgvector::Vector;
 use crate::{Error, Result};
-use regex::Regex;
 // Import necessary models
-use crate::models::ai::{WritingAssistantMessage, ChatHistory, MessageRole};
+use crate::models::ai::{WritingAssistantMessage, ChatHistory, ChatMessage, MessageRole, Citation};
+use crate::models::persona::PersonaManager;
+use crate::models::knowledge::KnowledgeManager;
+use sqlx::types::Json;
+use std::collections::HashMap;
 
-// Add this struct definition near the top
+/// SQL predicate restricting `documents d` to rows readable by the user bound to `$1`.
+/// Mirrors the access rules used by the project document listing.
+pub const DOCUMENT_ACCESS_FILTER: &str = "(\
+    d.user_id = $1 \
+    OR EXISTS (SELECT 1 FROM document_permissions perm WHERE perm.document_id = d.id AND perm.user_id = $1) \
+    OR EXISTS (SELECT 1 FROM document_projects acc_dp \
+               JOIN project_permissions acc_pp ON acc_pp.project_id = acc_dp.project_id \
+               WHERE acc_dp.document_id = d.id AND acc_pp.user_id = $1)\
+)";
+
+/// A piece of document text placed into a prompt.
+/// Chunks found by semantic search carry their chunk ID, section heading and offsets;
+/// whole documents added as extra context leave those fields empty.
 #[derive(Debug)] // For logging
 pub struct RetrievedChunk {
     pub document_id: i32,
     pub document_name: String, // Assuming name is always available
     pub content: String,
+    pub chunk_id: Option<i32>,
+    pub heading: Option<String>,
+    pub start_offset: Option<i32>,
+    pub end_offset: Option<i32>,
+}
+
+impl RetrievedChunk {
+    /// Wraps an entire document as a single piece of context
+    pub fn whole_document(document_id: i32, document_name: String, content: String) -> Self {
+        Self {
+            document_id,
+            document_name,
+            content,
+            chunk_id: None,
+            heading: None,
+            start_offset: None,
+            end_offset: None,
+        }
+    }
 }
 
-/// Retrieves the top 'k' most relevant document chunks.
-/// Returns a vector of RetrievedChunk containing ID, name, and content.
+/// Retrieves the top 'k' most relevant document chunks the requesting user is allowed to read.
+/// A document is readable if the user owns it, has a direct document permission, or has
+/// a permission on any project that contains it. Trashed documents are never returned.
+/// Returns the best matching chunks (not whole documents), most relevant first.
 pub async fn semantic_search(
     pool: &PgPool, 
+    user_id: i32,
     project_id: Option<i32>, 
     query_embedding: &Vector, 
     k: i64
 ) -> Result<Vec<RetrievedChunk>> {
     
-    println!("->> {:<12} - Retrieving relevant chunks (k={}) for project_id: {:?}", "RETRIEVAL", k, project_id);
+    println!("->> {:<12} - Retrieving relevant chunks (k={}) for user {} and project_id: {:?}", "RETRIEVAL", k, user_id, project_id);
     // Log a snippet of the query embedding
     println!("->> {:<12} - Using query embedding (first 5 dims): {:?}", "RETRIEVAL", query_embedding.as_slice().iter().take(5).collect::<Vec<_>>());
 
     // Define the base query selecting necessary fields
-    let base_select = "SELECT d.id, d.name, d.content "; // <-- Select id and name
-    let order_limit = "ORDER BY d.embedding <=> $vector::vector LIMIT $lim";
+    let base_select = "SELECT c.id AS chunk_id, c.heading, c.content, c.start_offset, c.end_offset, d.id, d.name \
+                       FROM document_chunks c \
+                       JOIN documents d ON d.id = c.document_id ";
+    let order_limit = "ORDER BY c.embedding <=> $vector::vector LIMIT $lim";
 
     let rows = if let Some(p_id) = project_id {
         let query_str = format!(
             "{} \
-             FROM documents d \
              JOIN document_projects dp ON d.id = dp.document_id \
-             WHERE dp.project_id = $1 \
-               AND d.embedding IS NOT NULL \
+             WHERE dp.project_id = $2 \
+               AND c.embedding IS NOT NULL \
                AND d.is_trashed = false \
-             {}", base_select, order_limit
+               AND {} \
+             {}", base_select, DOCUMENT_ACCESS_FILTER, order_limit
         );
         // Query within a specific project
-        sqlx::query(&query_str.replace("$vector", "$2").replace("$lim", "$3")) // Replace placeholders
+        sqlx::query(&query_str.replace("$vector", "$3").replace("$lim", "$4")) // Replace placeholders
+            .bind(user_id)
             .bind(p_id)
             .bind(query_embedding)
             .bind(k)
@@ -56,13 +96,14 @@ pub async fn semantic_search(
     } else {
         let query_str = format!(
             "{} \
-             FROM documents d \
-             WHERE d.embedding IS NOT NULL \
+             WHERE c.embedding IS NOT NULL \
                AND d.is_trashed = false \
-             {}", base_select, order_limit
+               AND {} \
+             {}", base_select, DOCUMENT_ACCESS_FILTER, order_limit
         );
-        // Query across all documents
-         sqlx::query(&query_str.replace("$vector", "$1").replace("$lim", "$2")) // Replace placeholders
+        // Query across every document the user can read
+         sqlx::query(&query_str.replace("$vector", "$2").replace("$lim", "$3")) // Replace placeholders
+            .bind(user_id)
             .bind(query_embedding)
             .bind(k)
             .fetch_all(pool)
@@ -75,77 +116,298 @@ pub async fn semantic_search(
 
     println!("->> {:<12} - Rows fetched from DB: {}", "RETRIEVAL_DEBUG", rows.len());
 
-    let html_tag_regex = Regex::new("<[^>]*>")
-        .map_err(|e| {
-            eprintln!("Regex creation failed: {:?}", e);
-            Error::DatabaseError // Map to DatabaseError (adjust if a better variant exists)
-        })?;
-    
-
-    // Map rows to Vec<RetrievedChunk>
+    // Map rows to Vec<RetrievedChunk>; chunk content is already plain text
     let chunks: Vec<RetrievedChunk> = rows.iter()
         .filter_map(|row| { // Use filter_map to handle potential errors in getting columns
-            let id_res = row.try_get("id"); // Infer type
-            let name_res = row.try_get("name"); // Infer type
-            let content_res = row.try_get::<String, _>("content"); // Keep String type here
-
-            match (id_res, name_res, content_res) {
-                (Ok(id), Ok(name), Ok(html_content)) => {
-                    let plain_text = html_tag_regex.replace_all(&html_content, "").to_string();
-                    Some(RetrievedChunk {
-                        document_id: id,
-                        document_name: name,
-                        content: plain_text,
-                    })
-                }
-                _ => {
-                    eprintln!("->> {:<12} - Failed to get id, name, or content from row", "RETRIEVAL_DEBUG");
+            let chunk = (|| -> std::result::Result<RetrievedChunk, sqlx::Error> {
+                Ok(RetrievedChunk {
+                    document_id: row.try_get("id")?,
+                    document_name: row.try_get("name")?,
+                    content: row.try_get("content")?,
+                    chunk_id: Some(row.try_get("chunk_id")?),
+                    heading: row.try_get("heading")?,
+                    start_offset: Some(row.try_get("start_offset")?),
+                    end_offset: Some(row.try_get("end_offset")?),
+                })
+            })();
+
+            match chunk {
+                Ok(chunk) => Some(chunk),
+                Err(e) => {
+                    eprintln!("->> {:<12} - Failed to read chunk row: {:?}", "RETRIEVAL_DEBUG", e);
                     None // Skip row if any column is missing/wrong type
                 }
             }
         })
         .collect();
 
-    println!("->> {:<12} - Retrieved {} relevant chunks (after stripping HTML)", "RETRIEVAL", chunks.len());
-    Ok(chunks) // <--- Return Vec<RetrievedChunk>
+    println!("->> {:<12} - Retrieved {} relevant chunks", "RETRIEVAL", chunks.len());
+    Ok(chunks)
 }
 
-// Updated function to retrieve chat history for a given session_id
-pub async fn retrieve_chat_history(
-    pool: &PgPool, 
-    session_id: i32
-) -> Result<ChatHistory> { // Use Result<ChatHistory> instead of Result<ChatHistory, Error>
-    println!("->> {:<12} - Retrieving chat history for session {}", "RETRIEVAL", session_id);
-    let db_messages = sqlx::query_as!(
+/// Retrieves the top 'k' chunks of a project's documents and of further documents, most relevant
+/// first, with the same access rules as `semantic_search`. `exclude_document_id` is left out,
+/// so a text is never its own evidence.
+pub async fn search_sources(
+    pool: &PgPool,
+    user_id: i32,
+    project_id: Option<i32>,
+    document_ids: &[i32],
+    exclude_document_id: Option<i32>,
+    query_embedding: &Vector,
+    k: i64,
+) -> Result<Vec<RetrievedChunk>> {
+    let query_str = format!(
+        "SELECT c.id AS chunk_id, c.heading, c.content, c.start_offset, c.end_offset, d.id, d.name \
+         FROM document_chunks c \
+         JOIN documents d ON d.id = c.document_id \
+         WHERE c.embedding IS NOT NULL \
+           AND d.is_trashed = false \
+           AND {} \
+           AND (EXISTS (SELECT 1 FROM document_projects src_dp WHERE src_dp.document_id = d.id AND src_dp.project_id = $2) \
+                OR d.id = ANY($3)) \
+           AND d.id IS DISTINCT FROM $4 \
+         ORDER BY c.embedding <=> $5::vector LIMIT $6",
+        DOCUMENT_ACCESS_FILTER
+    );
+    let rows = sqlx::query(&query_str)
+        .bind(user_id)
+        .bind(project_id)
+        .bind(document_ids)
+        .bind(exclude_document_id)
+        .bind(query_embedding)
+        .bind(k)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("DB Error retrieving source chunks: {:?}", e);
+            Error::DatabaseError
+        })?;
+
+    let chunks = rows.iter()
+        .map(|row| -> std::result::Result<RetrievedChunk, sqlx::Error> {
+            Ok(RetrievedChunk {
+                document_id: row.try_get("id")?,
+                document_name: row.try_get("name")?,
+                content: row.try_get("content")?,
+                chunk_id: Some(row.try_get("chunk_id")?),
+                heading: row.try_get("heading")?,
+                start_offset: Some(row.try_get("start_offset")?),
+                end_offset: Some(row.try_get("end_offset")?),
+            })
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| {
+            eprintln!("->> {:<12} - Failed to read source chunk row: {:?}", "RETRIEVAL", e);
+            Error::DatabaseError
+        })?;
+
+    println!("->> {:<12} - Retrieved {} source chunks for user {} and project_id: {:?}", "RETRIEVAL", chunks.len(), user_id, project_id);
+    Ok(chunks)
+}
+
+/// Retrieves every message of a session, across all branches, oldest first
+pub async fn retrieve_session_messages(pool: &PgPool, session_id: i32) -> Result<Vec<WritingAssistantMessage>> {
+    sqlx::query_as!(
         WritingAssistantMessage,
         r#"
         SELECT 
             id, 
             session_id, 
+            parent_message_id,
             role AS "role: MessageRole", 
             content, 
-            created_at
+            created_at,
+            citations AS "citations: Json<Vec<Citation>>",
+            prompt_template
         FROM writing_assistant_messages
         WHERE session_id = $1
-        ORDER BY created_at ASC
+        ORDER BY created_at ASC, id ASC
         "#,
         session_id
     )
     .fetch_all(pool) // Use the passed pool reference
     .await
     .map_err(|e| {
-        eprintln!("DB Error retrieving chat history: {:?}", e);
+        eprintln!("DB Error retrieving session messages: {:?}", e);
         Error::DatabaseError
-    })?;
+    })
+}
+
+/// Messages from the first message of a session down to `leaf_id`, oldest first.
+/// `messages` are all messages of the session.
+pub fn branch_path(messages: Vec<WritingAssistantMessage>, leaf_id: i32) -> Vec<WritingAssistantMessage> {
+    let mut by_id: HashMap<i32, WritingAssistantMessage> = messages.into_iter().map(|message| (message.id, message)).collect();
+    let mut path = Vec::new();
+    let mut next = Some(leaf_id);
+    while let Some(id) = next {
+        let Some(message) = by_id.remove(&id) else {
+            break; // Also ends a (corrupt) cycle, every message is visited once
+        };
+        next = message.parent_message_id;
+        path.push(message);
+    }
+    path.reverse();
+    path
+}
+
+/// Newest message below `message_id`, following the most recent child at every step.
+/// Switching to an older message continues on its latest branch.
+pub fn latest_leaf(messages: &[WritingAssistantMessage], message_id: i32) -> i32 {
+    // Messages are ordered by creation, so the last child inserted for a parent is its newest
+    let mut newest_child: HashMap<i32, i32> = messages
+        .iter()
+        .filter_map(|message| message.parent_message_id.map(|parent_id| (parent_id, message.id)))
+        .collect();
+    let mut current = message_id;
+    // Also ends a (corrupt) cycle, every parent is followed once
+    while let Some(child_id) = newest_child.remove(&current) {
+        current = child_id;
+    }
+    current
+}
+
+/// The last message of the branch a session is on, None for a session without messages
+pub async fn active_leaf(pool: &PgPool, session_id: i32) -> Result<Option<i32>> {
+    let active_message_id = sqlx::query!(
+        "SELECT active_message_id FROM writing_assistant_sessions WHERE id = $1",
+        session_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?
+    .and_then(|session| session.active_message_id);
+    if active_message_id.is_some() {
+        return Ok(active_message_id);
+    }
+
+    // Without an active message the session is on its newest branch
+    sqlx::query_scalar!(
+        "SELECT id FROM writing_assistant_messages WHERE session_id = $1 ORDER BY created_at DESC, id DESC LIMIT 1",
+        session_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)
+}
+
+/// Messages of the branch ending at `leaf_id`, oldest first. None is the empty branch.
+pub async fn retrieve_branch(pool: &PgPool, session_id: i32, leaf_id: Option<i32>) -> Result<Vec<WritingAssistantMessage>> {
+    let Some(leaf_id) = leaf_id else {
+        return Ok(Vec::new());
+    };
+    let messages = retrieve_session_messages(pool, session_id).await?;
+    Ok(branch_path(messages, leaf_id))
+}
+
+/// Messages of the branch a session is on, oldest first
+pub async fn retrieve_active_branch(pool: &PgPool, session_id: i32) -> Result<Vec<WritingAssistantMessage>> {
+    let leaf_id = active_leaf(pool, session_id).await?;
+    retrieve_branch(pool, session_id, leaf_id).await
+}
+
+/// Number of messages at the start of `branch` covered by the session summary.
+/// A summary written on another branch covers none of them.
+pub fn summarized_count(branch: &[WritingAssistantMessage], summary_message_id: Option<i32>) -> usize {
+    summary_message_id
+        .and_then(|summary_id| branch.iter().position(|message| message.id == summary_id))
+        .map(|position| position + 1)
+        .unwrap_or(0)
+}
+
+/// Retrieves the chat history of the branch of a session ending at `leaf_id`, see `active_leaf`.
+/// Messages already folded into the session summary are replaced by the summary.
+/// The knowledge base of the session's project comes along, the assistant always sees it.
+pub async fn retrieve_chat_history(
+    pool: &PgPool, 
+    session_id: i32,
+    leaf_id: Option<i32>
+) -> Result<ChatHistory> { // Use Result<ChatHistory> instead of Result<ChatHistory, Error>
+    println!("->> {:<12} - Retrieving chat history for session {}", "RETRIEVAL", session_id);
+    let summary_state = sqlx::query!(
+        "SELECT summary, summary_message_id FROM writing_assistant_sessions WHERE id = $1",
+        session_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    let (summary, summary_message_id) = summary_state
+        .map(|state| (state.summary, state.summary_message_id))
+        .unwrap_or((None, None));
+
+    let branch = retrieve_branch(pool, session_id, leaf_id).await?;
+    let summarized = summarized_count(&branch, summary_message_id);
 
-    // Build ChatHistory struct
-    let mut chat_history = ChatHistory::new();
-    for msg in &db_messages {
+    // Build ChatHistory struct, starting with the persona of the session
+    let system_prompt = PersonaManager::system_prompt_for_session(pool, session_id).await?;
+    let mut chat_history = ChatHistory::with_system_prompt(system_prompt);
+    if summarized > 0 {
+        chat_history.summary = summary;
+    }
+    chat_history.knowledge = KnowledgeManager::context_for_session(pool, session_id).await?;
+    for msg in &branch[summarized..] {
         if msg.role == MessageRole::User {
             chat_history.add_user_message(msg.content.clone());
         } else if msg.role == MessageRole::Assistant {
             chat_history.add_assistant_message(msg.content.clone());
+        } else {
+            chat_history.messages.push(ChatMessage { role: MessageRole::System, content: msg.content.clone() });
         }
     }
     Ok(chat_history) // Return the history
-}
\ No newline at end of file
+}
+
+/// Retrieves up to `k` summarized messages of the branch ending at `leaf_id` that are most similar to the query,
+/// using the embeddings stored with every message. Returned oldest first.
+pub async fn retrieve_earlier_messages(
+    pool: &PgPool,
+    session_id: i32,
+    leaf_id: Option<i32>,
+    query_embedding: &Vector,
+    k: i64
+) -> Result<Vec<ChatMessage>> {
+    let summary_message_id = sqlx::query!(
+        "SELECT summary_message_id FROM writing_assistant_sessions WHERE id = $1",
+        session_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?
+    .and_then(|session| session.summary_message_id);
+
+    let branch = retrieve_branch(pool, session_id, leaf_id).await?;
+    let summarized_ids: Vec<i32> = branch[..summarized_count(&branch, summary_message_id)]
+        .iter()
+        .map(|message| message.id)
+        .collect();
+    if summarized_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT recalled.role AS "role: MessageRole", recalled.content
+        FROM (
+            SELECT id, role, content
+            FROM writing_assistant_messages
+            WHERE id = ANY($1)
+              AND embedding IS NOT NULL
+            ORDER BY embedding <=> $2
+            LIMIT $3
+        ) recalled
+        ORDER BY recalled.id ASC
+        "#,
+        &summarized_ids,
+        query_embedding as _,
+        k
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        eprintln!("DB Error retrieving earlier messages: {:?}", e);
+        Error::DatabaseError
+    })?;
+
+    println!("->> {:<12} - Recalled {} earlier messages for session {}", "RETRIEVAL", rows.len(), session_id);
+    Ok(rows.into_iter().map(|row| ChatMessage { role: row.role, content: row.content }).collect())
+}
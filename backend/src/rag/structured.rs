@@ -0,0 +1,439 @@
+// Structured (JSON) answers
+//
+// Some answers are parsed by the server instead of shown to the user, e.g. the document
+// edits of apply-suggestion. Every such answer type has a JSON schema:
+//   - providers with a structured output mode are asked to follow it (see rag::provider)
+//   - every answer is parsed into its Rust type and validated, whatever the provider
+//   - an invalid answer is sent back to the model together with the error, up to
+//     STRUCTURED_OUTPUT_MAX_REPAIRS times (default: 2), before the request fails
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::env;
+
+use crate::models::ai::{LlmAgentStep, LlmDocEdits};
+use crate::models::credits::{AiOperation, CreditLedger};
+use crate::models::consistency::{FindingKind, LlmConsistencyCheck};
+use crate::models::fact_check::{ClaimVerdict, LlmClaimVerdicts, LlmClaims};
+use crate::models::knowledge::{EntityKind, LlmExtractedEntities};
+use crate::models::tags::{LlmTagSuggestions, MAX_TAG_CHARS};
+use crate::rag::agent::AgentTool;
+use crate::rag::llm::QueryModel;
+use crate::rag::provider::OutputSchema;
+use crate::rag::templates::{self, RenderedPrompt};
+use crate::rag::tokenizer::Tokenizer;
+use crate::{Error, Result};
+
+/// Repair attempts after the first answer, by default
+const DEFAULT_MAX_REPAIRS: usize = 2;
+
+/// An answer type the model must produce as JSON
+pub trait StructuredOutput: DeserializeOwned {
+    fn schema() -> OutputSchema;
+
+    /// Checks that go beyond the schema, e.g. no duplicate entries
+    fn validate(&self) -> std::result::Result<(), String> {
+        Ok(())
+    }
+}
+
+impl StructuredOutput for LlmDocEdits {
+    fn schema() -> OutputSchema {
+        OutputSchema {
+            name: "document_edits",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "edits": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "document_id": { "type": "integer" },
+                                "search": { "type": "string" },
+                                "replace": { "type": "string" }
+                            },
+                            "required": ["document_id", "search", "replace"],
+                            "additionalProperties": false
+                        }
+                    }
+                },
+                "required": ["edits"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        let mut seen = HashSet::new();
+        for (index, edit) in self.edits.iter().enumerate() {
+            if edit.search == edit.replace {
+                return Err(format!("edit {} does not change anything, leave it out", index + 1));
+            }
+            if !edit.search.is_empty() && !seen.insert((edit.document_id, edit.search.as_str())) {
+                return Err(format!("edit {} repeats the search text of an earlier edit to document {}, merge them", index + 1, edit.document_id));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StructuredOutput for LlmAgentStep {
+    fn schema() -> OutputSchema {
+        let mut actions: Vec<&str> = AgentTool::ALL.iter().map(|tool| tool.as_str()).collect();
+        actions.push("answer");
+        OutputSchema {
+            name: "agent_step",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "action": { "type": "string", "enum": actions },
+                    "query": { "type": ["string", "null"] },
+                    "exact": { "type": ["boolean", "null"] },
+                    "document_id": { "type": ["integer", "null"] },
+                    "offset": { "type": ["integer", "null"] },
+                    "search": { "type": ["string", "null"] },
+                    "replace": { "type": ["string", "null"] },
+                    "answer": { "type": ["string", "null"] }
+                },
+                "required": ["action", "query", "exact", "document_id", "offset", "search", "replace", "answer"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.action == "answer" {
+            return match self.answer.as_deref() {
+                Some(answer) if !answer.trim().is_empty() => Ok(()),
+                _ => Err("action \"answer\" needs a non-empty \"answer\"".to_string()),
+            };
+        }
+        match AgentTool::parse(&self.action) {
+            Some(tool) => tool.check_arguments(self),
+            None => Err(format!("unknown action \"{}\", use a tool name or \"answer\"", self.action)),
+        }
+    }
+}
+
+impl StructuredOutput for LlmExtractedEntities {
+    fn schema() -> OutputSchema {
+        let kinds: Vec<&str> = EntityKind::ALL.iter().map(|kind| kind.as_str()).collect();
+        OutputSchema {
+            name: "extracted_entities",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "entities": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": { "type": "string" },
+                                "kind": { "type": "string", "enum": kinds },
+                                "aliases": { "type": "array", "items": { "type": "string" } },
+                                "description": { "type": "string" }
+                            },
+                            "required": ["name", "kind", "aliases", "description"],
+                            "additionalProperties": false
+                        }
+                    }
+                },
+                "required": ["entities"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        let mut seen = HashSet::new();
+        for (index, entity) in self.entities.iter().enumerate() {
+            let name = entity.name.trim().to_lowercase();
+            if name.is_empty() {
+                return Err(format!("entity {} has an empty name", index + 1));
+            }
+            if !seen.insert(name) {
+                return Err(format!("entity {} (\"{}\") is listed twice, merge the entries", index + 1, entity.name.trim()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StructuredOutput for LlmConsistencyCheck {
+    fn schema() -> OutputSchema {
+        let kinds = [FindingKind::KnowledgeContradiction.as_str(), FindingKind::TimelineConflict.as_str()];
+        OutputSchema {
+            name: "consistency_check",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "findings": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "kind": { "type": "string", "enum": kinds },
+                                "quote": { "type": "string" },
+                                "explanation": { "type": "string" },
+                                "suggestion": { "type": ["string", "null"] },
+                                "entity": { "type": ["string", "null"] },
+                                "conflicts_with": { "type": ["string", "null"] }
+                            },
+                            "required": ["kind", "quote", "explanation", "suggestion", "entity", "conflicts_with"],
+                            "additionalProperties": false
+                        }
+                    },
+                    "events": { "type": "array", "items": { "type": "string" } }
+                },
+                "required": ["findings", "events"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        for (index, finding) in self.findings.iter().enumerate() {
+            if !matches!(finding.kind, FindingKind::KnowledgeContradiction | FindingKind::TimelineConflict) {
+                return Err(format!("finding {} has kind \"{}\", use \"knowledge_contradiction\" or \"timeline_conflict\"", index + 1, finding.kind.as_str()));
+            }
+            if finding.quote.trim().is_empty() || finding.explanation.trim().is_empty() {
+                return Err(format!("finding {} needs a quote and an explanation", index + 1));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StructuredOutput for LlmClaims {
+    fn schema() -> OutputSchema {
+        OutputSchema {
+            name: "fact_check_claims",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "claims": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "claim": { "type": "string" },
+                                "quote": { "type": "string" }
+                            },
+                            "required": ["claim", "quote"],
+                            "additionalProperties": false
+                        }
+                    }
+                },
+                "required": ["claims"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        for (index, claim) in self.claims.iter().enumerate() {
+            if claim.claim.trim().is_empty() || claim.quote.trim().is_empty() {
+                return Err(format!("claim {} needs a claim and a quote", index + 1));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StructuredOutput for LlmClaimVerdicts {
+    fn schema() -> OutputSchema {
+        let verdicts = [ClaimVerdict::Supported.as_str(), ClaimVerdict::Contradicted.as_str(), ClaimVerdict::Unknown.as_str()];
+        OutputSchema {
+            name: "fact_check_verdicts",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "verdicts": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "claim": { "type": "integer" },
+                                "verdict": { "type": "string", "enum": verdicts },
+                                "explanation": { "type": "string" },
+                                "sources": { "type": "array", "items": { "type": "string" } }
+                            },
+                            "required": ["claim", "verdict", "explanation", "sources"],
+                            "additionalProperties": false
+                        }
+                    }
+                },
+                "required": ["verdicts"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        let mut seen = HashSet::new();
+        for verdict in &self.verdicts {
+            if !seen.insert(verdict.claim) {
+                return Err(format!("claim {} has more than one verdict", verdict.claim));
+            }
+            if verdict.explanation.trim().is_empty() {
+                return Err(format!("the verdict on claim {} needs an explanation", verdict.claim));
+            }
+            if verdict.verdict != ClaimVerdict::Unknown && verdict.sources.is_empty() {
+                return Err(format!("the verdict on claim {} cites no sources, cite the passages or use \"unknown\"", verdict.claim));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StructuredOutput for LlmTagSuggestions {
+    fn schema() -> OutputSchema {
+        OutputSchema {
+            name: "document_tags",
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                    "topics": { "type": "array", "items": { "type": "string" } }
+                },
+                "required": ["tags", "topics"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        for tag in self.tags.iter().chain(&self.topics) {
+            if tag.trim().is_empty() {
+                return Err("tags and topics must not be empty".to_string());
+            }
+            if tag.chars().count() > MAX_TAG_CHARS {
+                return Err(format!("\"{}\" is too long, use at most {} characters", tag, MAX_TAG_CHARS));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A valid answer and everything the model produced to get there
+pub struct StructuredAnswer<T> {
+    pub value: T,
+    /// All raw answers, including the rejected ones, for token accounting
+    pub completion: String,
+    pub attempts: usize,
+}
+
+/// Parses and validates a raw answer.
+/// Code fences and text around the JSON are ignored. A bare array is accepted for schemas whose
+/// only property is an array, since prompts often ask for "a JSON array".
+pub fn parse_structured<T: StructuredOutput>(raw: &str) -> std::result::Result<T, String> {
+    let json_text = extract_json(raw);
+    let mut value: Value = serde_json::from_str(json_text).map_err(|e| format!("the answer is not valid JSON: {}", e))?;
+
+    if value.is_array() {
+        let schema = T::schema().schema;
+        if let Some(properties) = schema["properties"].as_object() {
+            if properties.len() == 1 {
+                let (name, property) = properties.iter().next().unwrap();
+                if property["type"] == "array" {
+                    value = json!({ name.clone(): value });
+                }
+            }
+        }
+    }
+
+    let parsed: T = serde_json::from_value(value).map_err(|e| format!("the answer does not match the schema: {}", e))?;
+    parsed.validate()?;
+    Ok(parsed)
+}
+
+// The JSON part of an answer
+fn extract_json(raw: &str) -> &str {
+    let trimmed = raw.trim();
+
+    // ```json ... ``` around the whole answer
+    if let Some(fenced) = trimmed.strip_prefix("```") {
+        let body = fenced.split_once('\n').map(|(_, body)| body).unwrap_or(fenced);
+        return body.trim_end().strip_suffix("```").unwrap_or(body).trim();
+    }
+
+    // Text before or after the JSON
+    let start = trimmed.find(['[', '{']);
+    let end = trimmed.rfind([']', '}']);
+    match (start, end) {
+        (Some(start), Some(end)) if end > start => &trimmed[start..=end],
+        _ => trimmed,
+    }
+}
+
+fn max_repairs() -> usize {
+    env::var("STRUCTURED_OUTPUT_MAX_REPAIRS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_REPAIRS)
+}
+
+/// Queries the model for a `T`, repairing invalid answers.
+/// Fails with InvalidLlmOutputError when no valid answer was produced.
+pub async fn query_structured<T: StructuredOutput>(query_model: &QueryModel, prompt: &RenderedPrompt) -> Result<StructuredAnswer<T>> {
+    let schema = T::schema();
+    let max_attempts = 1 + max_repairs();
+    let mut completion = String::new();
+    let mut current_prompt = prompt.clone();
+
+    for attempt in 1..=max_attempts {
+        let raw = query_model.query_structured(&current_prompt, &schema).await?;
+        completion.push_str(&raw);
+
+        match parse_structured::<T>(&raw) {
+            Ok(value) => {
+                println!("->> {:<12} - Valid {} answer after {} attempt(s)", "STRUCTURED", schema.name, attempt);
+                return Ok(StructuredAnswer { value, completion, attempts: attempt });
+            }
+            Err(problem) => {
+                println!("->> {:<12} - Invalid {} answer (attempt {}/{}): {}", "STRUCTURED", schema.name, attempt, max_attempts, problem);
+                current_prompt = templates::render("structured_repair", &[
+                    ("original_prompt", prompt.text.as_str().into()),
+                    ("response", raw.into()),
+                    ("error", problem.into()),
+                    ("schema", schema.schema.clone().into()),
+                ])?;
+            }
+        }
+    }
+
+    Err(Error::InvalidLlmOutputError)
+}
+
+/// Charges a user for a prompt and queries the model for a `T`, see `query_structured`.
+/// The charge is refunded if the LLM call fails or no valid answer could be produced.
+pub async fn charged_structured_query<T: StructuredOutput>(
+    pool: &PgPool,
+    user_id: i32,
+    operation: AiOperation,
+    query_model: &QueryModel,
+    prompt: &RenderedPrompt,
+) -> Result<T> {
+    let tokenizer = Tokenizer::for_model(query_model.model());
+    let charge = CreditLedger::charge(pool, user_id, operation, tokenizer.count(&prompt.text), &prompt.template).await?;
+
+    match query_structured::<T>(query_model, prompt).await {
+        Ok(answer) => {
+            if answer.attempts > 1 {
+                println!("->> {:<12} - {} needed {} attempts", "STRUCTURED", operation.as_str(), answer.attempts);
+            }
+            if let Err(e) = CreditLedger::record_completion(pool, &charge, tokenizer.count(&answer.completion)).await {
+                eprintln!("->> {:<12} - Failed to record completion tokens: {:?}", "CREDITS", e);
+            }
+            Ok(answer.value)
+        }
+        Err(e @ (Error::LlmQueryError | Error::InvalidLlmOutputError)) => {
+            CreditLedger::refund(pool, &charge, "LLM returned no usable answer").await?;
+            Err(e)
+        }
+        Err(e) => Err(e),
+    }
+}
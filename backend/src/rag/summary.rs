@@ -0,0 +1,152 @@
+// Rolling summaries of long writing assistant sessions
+//
+// Chat prompts only have room for part of a long conversation. Once the messages that are not
+// yet summarized outgrow the history share of the prompt budget (or SESSION_SUMMARY_TRIGGER_TOKENS),
+// the oldest of them are folded into the session's summary in the background:
+//   - the summary covers the messages of the active branch up to `summary_message_id`; after
+//     switching to a branch that does not contain it, the summary is ignored and rebuilt
+//   - the most recent messages (about half the trigger) always stay verbatim
+//   - retrieval::retrieve_chat_history returns the summary instead of the folded messages,
+//     retrieval::retrieve_earlier_messages recalls folded messages similar to a new query
+//   - the owner of the session is charged for every update
+
+use lazy_static::lazy_static;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::env;
+use std::sync::Mutex;
+
+use crate::models::ai::MessageRole;
+use crate::models::credits::AiOperation;
+use crate::rag::citations::strip_citation_markers;
+use crate::rag::llm::{charged_query, QueryModel};
+use crate::rag::prompt::PromptBudget;
+use crate::rag::retrieval;
+use crate::rag::templates;
+use crate::{Error, Result};
+
+lazy_static! {
+    // Sessions with a summary update in progress, one update per session at a time
+    static ref UPDATING_SESSIONS: Mutex<HashSet<i32>> = Mutex::new(HashSet::new());
+}
+
+// Marks a session as being summarized until dropped, also when the update fails or panics
+struct UpdatingSession(i32);
+
+impl UpdatingSession {
+    fn start(session_id: i32) -> Option<Self> {
+        UPDATING_SESSIONS.lock().unwrap_or_else(|e| e.into_inner()).insert(session_id).then(|| Self(session_id))
+    }
+}
+
+impl Drop for UpdatingSession {
+    fn drop(&mut self) {
+        UPDATING_SESSIONS.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.0);
+    }
+}
+
+/// Unsummarized tokens that trigger an update, by default the history share of the prompt budget
+fn trigger_tokens(budget: &PromptBudget) -> usize {
+    env::var("SESSION_SUMMARY_TRIGGER_TOKENS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or_else(|| budget.history_tokens())
+}
+
+/// Folds the oldest unsummarized messages of a session into its summary if they exceed the trigger.
+/// Returns whether the summary changed.
+pub async fn update_session_summary(pool: &PgPool, session_id: i32) -> Result<bool> {
+    let session = sqlx::query!(
+        "SELECT user_id, summary, summary_message_id FROM writing_assistant_sessions WHERE id = $1",
+        session_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?
+    .ok_or(Error::PermissionError)?;
+
+    // Only the branch the session is on is summarized, a summary from another branch starts over
+    let branch = retrieval::retrieve_active_branch(pool, session_id).await?;
+    let summarized = retrieval::summarized_count(&branch, session.summary_message_id);
+    let previous_summary = if summarized > 0 { session.summary.as_deref() } else { None };
+    let messages = &branch[summarized..];
+
+    let query_model = QueryModel::new()?;
+    let budget = PromptBudget::for_model(query_model.model());
+    let trigger = trigger_tokens(&budget);
+
+    let lines: Vec<String> = messages
+        .iter()
+        .map(|message| {
+            let role_str = match message.role {
+                MessageRole::System => "System",
+                MessageRole::User => "User",
+                MessageRole::Assistant => "Assistant",
+            };
+            format!("{}: {}\n", role_str, strip_citation_markers(&message.content))
+        })
+        .collect();
+    let line_tokens: Vec<usize> = lines.iter().map(|line| budget.count(line)).collect();
+    let total_tokens: usize = line_tokens.iter().sum();
+    if total_tokens <= trigger {
+        return Ok(false);
+    }
+
+    // Fold from the oldest message until the rest is down to half the trigger
+    let mut folded_tokens = 0;
+    let mut fold_count = 0;
+    while fold_count < messages.len() && total_tokens - folded_tokens > trigger / 2 {
+        folded_tokens += line_tokens[fold_count];
+        fold_count += 1;
+    }
+    let Some(last_folded) = messages.get(fold_count.saturating_sub(1)) else {
+        return Ok(false);
+    };
+
+    let prompt = templates::render("session_summary", &[
+        ("previous_summary", previous_summary.unwrap_or("(No summary yet)").into()),
+        ("messages", lines[..fold_count].concat().trim_end().into()),
+    ])?;
+    let summary = charged_query(pool, session.user_id, AiOperation::SessionSummary, &query_model, &prompt).await?;
+    let summary = summary.trim();
+    if summary.is_empty() {
+        return Err(Error::LlmQueryError);
+    }
+
+    // Only store the summary if no other update moved the session on in the meantime
+    let updated = sqlx::query!(
+        r#"
+        UPDATE writing_assistant_sessions
+        SET summary = $2, summary_message_id = $3, summary_updated_at = NOW()
+        WHERE id = $1 AND summary_message_id IS NOT DISTINCT FROM $4
+        "#,
+        session_id,
+        summary,
+        last_folded.id,
+        session.summary_message_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    if updated.rows_affected() == 0 {
+        println!("->> {:<12} - Session {} was summarized concurrently, dropping this summary", "SUMMARY", session_id);
+        return Ok(false);
+    }
+    println!("->> {:<12} - Folded {} messages ({} tokens) of session {} into its summary ({})", "SUMMARY", fold_count, folded_tokens, session_id, prompt.template);
+    Ok(true)
+}
+
+/// Updates the summary of a session on the tokio runtime, skipped if an update is already running
+pub fn spawn_summary_update(pool: PgPool, session_id: i32) {
+    let Some(updating) = UpdatingSession::start(session_id) else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let _updating = updating;
+        if let Err(e) = update_session_summary(&pool, session_id).await {
+            eprintln!("->> {:<12} - Summary update for session {} failed: {:?}", "SUMMARY", session_id, e);
+        }
+    });
+}
@@ -0,0 +1,120 @@
+// Suggested tags of saved documents
+//
+// Saving a document queues an embedding job (see rag::jobs), so a burst of saves is embedded once.
+// After the job embedded the document the model suggests tags and topics from its text, preferring
+// the tags its owner already uses. Suggestions replace the previous ones and are never applied on
+// their own: the user accepts one by adding it to the document's tags (see models::tags).
+// Suggestions are charged to the owner of the document and skipped while their balance is empty.
+// Only a change of the text is tagged again, saves that rename or star a document cost nothing.
+
+use sqlx::PgPool;
+
+use crate::models::credits::{AiOperation, CreditLedger};
+use crate::models::tags::{normalize_tag, LlmTagSuggestions, SuggestionKind, TagManager};
+use crate::rag::chunk::to_plain_text;
+use crate::rag::llm::QueryModel;
+use crate::rag::structured::charged_structured_query;
+use crate::rag::templates;
+use crate::{Error, Result};
+
+/// Tags suggested per document
+const MAX_SUGGESTED_TAGS: usize = 5;
+/// Topics suggested per document
+const MAX_SUGGESTED_TOPICS: usize = 3;
+/// Characters of the document the suggestions are made from
+const MAX_TAGGING_CHARS: usize = 6000;
+/// Tags of the owner listed in the prompt, the most used first
+const MAX_EXISTING_TAGS: usize = 50;
+
+/// Replaces the tag suggestions of a document whose text changed since it was last tagged.
+/// Returns the number of new suggestions, 0 when the document was not tagged again.
+pub async fn suggest_document_tags(pool: &PgPool, document_id: i32) -> Result<usize> {
+    let document = sqlx::query!(
+        r#"SELECT name, content, user_id, md5(COALESCE(content, '')) AS "content_hash!" FROM documents WHERE id = $1"#,
+        document_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?
+    .ok_or(Error::DocumentNotFoundError { document_id })?;
+    // Nobody to charge
+    let Some(owner) = document.user_id else {
+        return Ok(0);
+    };
+
+    let tagged_hash = sqlx::query_scalar!("SELECT content_hash FROM document_tagging_runs WHERE document_id = $1", document_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+    if tagged_hash.as_deref() == Some(document.content_hash.as_str()) {
+        println!("->> {:<12} - Text of document {} unchanged, keeping its suggestions", "TAGS", document_id);
+        return Ok(0);
+    }
+
+    let text: String = to_plain_text(document.content.as_deref().unwrap_or_default()).chars().take(MAX_TAGGING_CHARS).collect();
+    if text.trim().is_empty() {
+        let stored = TagManager::store_suggestions(pool, document_id, Vec::new()).await?;
+        record_tagging_run(pool, document_id, &document.content_hash).await?;
+        return Ok(stored);
+    }
+
+    // The previous suggestions stay until the owner has credits again
+    match CreditLedger::ensure_balance(pool, owner, AiOperation::TagSuggestion).await {
+        Ok(()) => {}
+        Err(Error::InsufficientAiCredits) => return Ok(0),
+        Err(e) => return Err(e),
+    }
+
+    let existing = TagManager::for_user(pool, owner).await?;
+    let existing_tags = if existing.is_empty() {
+        "(none yet)".to_string()
+    } else {
+        existing.iter().take(MAX_EXISTING_TAGS).map(|tag| tag.name.as_str()).collect::<Vec<_>>().join(", ")
+    };
+
+    let prompt = templates::render("document_tags", &[
+        ("max_tags", (MAX_SUGGESTED_TAGS as i32).into()),
+        ("existing_tags", existing_tags.into()),
+        ("document_name", document.name.as_str().into()),
+        ("text", text.into()),
+    ])?;
+    let answer: LlmTagSuggestions = charged_structured_query(pool, owner, AiOperation::TagSuggestion, &QueryModel::new()?, &prompt).await?;
+
+    // Tags already on the document are no suggestions, a topic that is also a tag is kept as the tag
+    let current = TagManager::for_document(pool, document_id).await?.tags;
+    let mut suggestions: Vec<(String, SuggestionKind)> = Vec::new();
+    let candidates = answer
+        .tags
+        .iter()
+        .filter_map(|tag| normalize_tag(tag))
+        .map(|tag| (tag, SuggestionKind::Tag))
+        .take(MAX_SUGGESTED_TAGS)
+        .chain(answer.topics.iter().filter_map(|topic| normalize_tag(topic)).map(|topic| (topic, SuggestionKind::Topic)).take(MAX_SUGGESTED_TOPICS));
+    for (name, kind) in candidates {
+        if !current.contains(&name) && !suggestions.iter().any(|(known, _)| *known == name) {
+            suggestions.push((name, kind));
+        }
+    }
+
+    let stored = TagManager::store_suggestions(pool, document_id, suggestions).await?;
+    record_tagging_run(pool, document_id, &document.content_hash).await?;
+    println!("->> {:<12} - Suggested {} tags for document {} ({})", "TAGS", stored, document_id, prompt.template);
+    Ok(stored)
+}
+
+// Remembers the text the suggestions of a document were made from
+async fn record_tagging_run(pool: &PgPool, document_id: i32, content_hash: &str) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO document_tagging_runs (document_id, content_hash)
+        VALUES ($1, $2)
+        ON CONFLICT (document_id) DO UPDATE SET content_hash = EXCLUDED.content_hash, tagged_at = CURRENT_TIMESTAMP
+        "#,
+        document_id,
+        content_hash
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    Ok(())
+}
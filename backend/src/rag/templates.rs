@@ -0,0 +1,424 @@
+// Prompt template registry
+//
+// Prompts live in files under backend/prompts (override with PROMPT_TEMPLATE_DIR), one directory
+// per template and one file per version:
+//
+//     prompts/grammar_check/v1.txt
+//
+//     name: grammar_check
+//     version: 1
+//     description: Quick action: correct grammar and spelling of a selection
+//     variables: text:text
+//     ---
+//     Please correct the grammar ... {{text}} ...
+//
+// Variables are typed (text, integer, json) and must match what the code passes in, see
+// TEMPLATE_SPECS. The newest version of every template is used unless it is pinned with
+// PROMPT_VERSION_<NAME>=<version>, e.g. PROMPT_VERSION_CHAT=1. Everything is checked once at
+// startup so a broken template stops the server instead of failing a user's request.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::{Error, Result};
+
+/// Directory templates are loaded from by default, relative to the backend
+const DEFAULT_TEMPLATE_DIR: &str = "prompts";
+
+lazy_static! {
+    // {{variable}}
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap();
+}
+
+static REGISTRY: OnceLock<PromptRegistry> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableType {
+    Text,
+    Integer,
+    Json,
+}
+
+impl VariableType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(VariableType::Text),
+            "integer" => Some(VariableType::Integer),
+            "json" => Some(VariableType::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A value passed into a template
+#[derive(Debug, Clone)]
+pub enum PromptValue {
+    Text(String),
+    Integer(i64),
+    Json(serde_json::Value),
+}
+
+impl PromptValue {
+    fn variable_type(&self) -> VariableType {
+        match self {
+            PromptValue::Text(_) => VariableType::Text,
+            PromptValue::Integer(_) => VariableType::Integer,
+            PromptValue::Json(_) => VariableType::Json,
+        }
+    }
+
+    fn render(&self) -> String {
+        match self {
+            PromptValue::Text(text) => text.clone(),
+            PromptValue::Integer(value) => value.to_string(),
+            PromptValue::Json(value) => serde_json::to_string_pretty(value).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&str> for PromptValue {
+    fn from(text: &str) -> Self {
+        PromptValue::Text(text.to_string())
+    }
+}
+
+impl From<String> for PromptValue {
+    fn from(text: String) -> Self {
+        PromptValue::Text(text)
+    }
+}
+
+impl From<i32> for PromptValue {
+    fn from(value: i32) -> Self {
+        PromptValue::Integer(value as i64)
+    }
+}
+
+impl From<serde_json::Value> for PromptValue {
+    fn from(value: serde_json::Value) -> Self {
+        PromptValue::Json(value)
+    }
+}
+
+/// Variables the code passes to a template; a template version may use a subset of them
+pub struct TemplateSpec {
+    pub name: &'static str,
+    pub variables: &'static [(&'static str, VariableType)],
+}
+
+use VariableType::{Integer, Json, Text};
+
+/// Every template the backend renders
+pub const TEMPLATE_SPECS: &[TemplateSpec] = &[
+    TemplateSpec { name: "chat", variables: &[("system_prompt", Text), ("knowledge", Text), ("document_focus", Text), ("context", Text), ("summary", Text), ("earlier_messages", Text), ("history", Text), ("query", Text)] },
+    TemplateSpec { name: "session_summary", variables: &[("previous_summary", Text), ("messages", Text)] },
+    TemplateSpec { name: "session_title", variables: &[("first_message", Text), ("first_answer", Text)] },
+    TemplateSpec { name: "agent_step", variables: &[("system_prompt", Text), ("knowledge", Text), ("tools", Json), ("document_focus", Text), ("history", Text), ("query", Text), ("steps", Text), ("remaining_steps", Integer)] },
+    TemplateSpec { name: "grammar_check", variables: &[("text", Text)] },
+    TemplateSpec { name: "spell_check", variables: &[("text", Text)] },
+    TemplateSpec { name: "summarize", variables: &[("text", Text)] },
+    TemplateSpec { name: "rephrase", variables: &[("text", Text)] },
+    TemplateSpec { name: "expand", variables: &[("text", Text)] },
+    TemplateSpec { name: "shrink", variables: &[("text", Text)] },
+    TemplateSpec { name: "rewrite", variables: &[("text", Text), ("style", Text)] },
+    TemplateSpec { name: "fact_check", variables: &[("text", Text)] },
+    TemplateSpec { name: "fact_check_claims", variables: &[("max_claims", Integer), ("text", Text)] },
+    TemplateSpec { name: "fact_check_evidence", variables: &[("sources", Text), ("claims", Text)] },
+    TemplateSpec { name: "translate", variables: &[("text", Text), ("source_language", Text), ("target_language", Text), ("glossary", Text)] },
+    TemplateSpec { name: "translate_repair", variables: &[("original_prompt", Text), ("translation", Text), ("violations", Text)] },
+    TemplateSpec { name: "knowledge_extract", variables: &[("project_name", Text), ("document_name", Text), ("known_entities", Text), ("text", Text)] },
+    TemplateSpec { name: "document_tags", variables: &[("max_tags", Integer), ("existing_tags", Text), ("document_name", Text), ("text", Text)] },
+    TemplateSpec { name: "consistency_check", variables: &[("project_name", Text), ("document_name", Text), ("knowledge", Text), ("timeline", Text), ("text", Text)] },
+    TemplateSpec { name: "apply_suggestion", variables: &[("focus_instruction", Text), ("documents", Json), ("suggestion", Text)] },
+    TemplateSpec { name: "apply_suggestion_focus_empty", variables: &[("active_document_id", Integer)] },
+    TemplateSpec { name: "apply_suggestion_focus_existing", variables: &[("active_document_id", Integer)] },
+    TemplateSpec { name: "apply_suggestion_focus_none", variables: &[] },
+    TemplateSpec { name: "proactive_diff_decision", variables: &[("user_action", Text), ("document_state", Text), ("ai_response", Text)] },
+    TemplateSpec { name: "sanitize_text", variables: &[("text", Text)] },
+    TemplateSpec { name: "context_decision", variables: &[("user_prompt", Text)] },
+    TemplateSpec { name: "structured_repair", variables: &[("original_prompt", Text), ("response", Text), ("error", Text), ("schema", Json)] },
+];
+
+/// One version of a template
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub version: u32,
+    pub description: String,
+    pub variables: Vec<(String, VariableType)>,
+    #[serde(skip)]
+    pub body: String,
+}
+
+impl PromptTemplate {
+    /// Identifier stored with AI output, e.g. "chat@v2"
+    pub fn tag(&self) -> String {
+        format!("{}@v{}", self.name, self.version)
+    }
+}
+
+/// A rendered prompt and the template version it came from
+#[derive(Debug, Clone)]
+pub struct RenderedPrompt {
+    pub text: String,
+    pub template: String,
+}
+
+pub struct PromptRegistry {
+    // All versions, oldest first
+    templates: HashMap<String, Vec<PromptTemplate>>,
+    // Version used for each template
+    active: HashMap<String, u32>,
+}
+
+impl PromptRegistry {
+    /// Loads and validates every template in `dir`. All problems are reported at once.
+    pub fn load(dir: &Path) -> std::result::Result<Self, String> {
+        let mut problems: Vec<String> = Vec::new();
+        let mut templates: HashMap<String, Vec<PromptTemplate>> = HashMap::new();
+
+        let entries = fs::read_dir(dir).map_err(|e| format!("Cannot read prompt directory {}: {}", dir.display(), e))?;
+        for entry in entries.flatten() {
+            let template_dir = entry.path();
+            if !template_dir.is_dir() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(spec) = TEMPLATE_SPECS.iter().find(|spec| spec.name == name) else {
+                println!("->> {:<12} - Ignoring unknown prompt template '{}'", "PROMPTS", name);
+                continue;
+            };
+
+            let files = match fs::read_dir(&template_dir) {
+                Ok(files) => files,
+                Err(e) => {
+                    problems.push(format!("{}: cannot read directory: {}", name, e));
+                    continue;
+                }
+            };
+            for file in files.flatten() {
+                let path = file.path();
+                let file_name = file.file_name().to_string_lossy().to_string();
+                let Some(file_version) = file_name
+                    .strip_prefix('v')
+                    .and_then(|rest| rest.strip_suffix(".txt"))
+                    .and_then(|version| version.parse::<u32>().ok())
+                else {
+                    continue;
+                };
+
+                let source = match fs::read_to_string(&path) {
+                    Ok(source) => source,
+                    Err(e) => {
+                        problems.push(format!("{}/{}: {}", name, file_name, e));
+                        continue;
+                    }
+                };
+                match parse_template(&source).and_then(|template| validate_template(template, spec, file_version)) {
+                    Ok(template) => templates.entry(name.clone()).or_default().push(template),
+                    Err(problem) => problems.push(format!("{}/{}: {}", name, file_name, problem)),
+                }
+            }
+        }
+
+        // Every template the code renders needs a usable version
+        let mut active = HashMap::new();
+        for spec in TEMPLATE_SPECS {
+            let Some(versions) = templates.get_mut(spec.name) else {
+                problems.push(format!("{}: no template found", spec.name));
+                continue;
+            };
+            versions.sort_by_key(|template| template.version);
+
+            let variable = format!("PROMPT_VERSION_{}", spec.name.to_uppercase());
+            let version = match env::var(&variable) {
+                Ok(pinned) => match pinned.trim().trim_start_matches('v').parse::<u32>() {
+                    Ok(version) if versions.iter().any(|template| template.version == version) => version,
+                    _ => {
+                        problems.push(format!("{}: {}='{}' does not name an existing version", spec.name, variable, pinned));
+                        continue;
+                    }
+                },
+                Err(_) => versions.last().map(|template| template.version).unwrap_or(1),
+            };
+            active.insert(spec.name.to_string(), version);
+        }
+
+        if !problems.is_empty() {
+            return Err(problems.join("\n"));
+        }
+        Ok(PromptRegistry { templates, active })
+    }
+
+    /// Version of a template in use
+    pub fn get(&self, name: &str) -> Result<&PromptTemplate> {
+        let version = self.active.get(name).ok_or_else(|| Error::PromptTemplateError { template: name.to_string() })?;
+        self.templates
+            .get(name)
+            .and_then(|versions| versions.iter().find(|template| template.version == *version))
+            .ok_or_else(|| Error::PromptTemplateError { template: name.to_string() })
+    }
+
+    /// Active versions of all templates
+    pub fn active_templates(&self) -> Vec<&PromptTemplate> {
+        TEMPLATE_SPECS.iter().filter_map(|spec| self.get(spec.name).ok()).collect()
+    }
+
+    /// All loaded versions of a template, oldest first
+    pub fn versions(&self, name: &str) -> Vec<u32> {
+        self.templates
+            .get(name)
+            .map(|versions| versions.iter().map(|template| template.version).collect())
+            .unwrap_or_default()
+    }
+
+    /// Renders the active version of a template. Every variable it declares must be passed with its type.
+    pub fn render(&self, name: &str, values: &[(&str, PromptValue)]) -> Result<RenderedPrompt> {
+        let template = self.get(name)?;
+
+        for (variable, variable_type) in &template.variables {
+            match values.iter().find(|(key, _)| key == variable) {
+                Some((_, value)) if value.variable_type() == *variable_type => {}
+                Some((_, value)) => {
+                    eprintln!("->> {:<12} - {}: '{}' must be {:?}, got {:?}", "PROMPTS", template.tag(), variable, variable_type, value.variable_type());
+                    return Err(Error::PromptTemplateError { template: template.tag() });
+                }
+                None => {
+                    eprintln!("->> {:<12} - {}: missing value for '{}'", "PROMPTS", template.tag(), variable);
+                    return Err(Error::PromptTemplateError { template: template.tag() });
+                }
+            }
+        }
+
+        // Substituted values are not scanned again, so user text containing {{...}} stays as it is
+        let text = PLACEHOLDER.replace_all(&template.body, |captures: &regex::Captures| {
+            values
+                .iter()
+                .find(|(key, _)| *key == &captures[1])
+                .map(|(_, value)| value.render())
+                .unwrap_or_default()
+        });
+
+        Ok(RenderedPrompt { text: text.into_owned(), template: template.tag() })
+    }
+}
+
+// Splits the header from the body and reads the header fields
+fn parse_template(source: &str) -> std::result::Result<PromptTemplate, String> {
+    let source = source.replace("\r\n", "\n");
+    let (header, body) = source
+        .split_once("\n---\n")
+        .ok_or("missing '---' line between header and body")?;
+    // Files end with a newline that is not part of the prompt
+    let body = body.strip_suffix('\n').unwrap_or(body);
+
+    let mut name = None;
+    let mut version = None;
+    let mut description = String::new();
+    let mut variables = Vec::new();
+
+    for line in header.lines().filter(|line| !line.trim().is_empty()) {
+        let (key, value) = line.split_once(':').ok_or_else(|| format!("invalid header line '{}'", line))?;
+        let value = value.trim();
+        match key.trim() {
+            "name" => name = Some(value.to_string()),
+            "version" => version = Some(value.parse::<u32>().map_err(|_| format!("invalid version '{}'", value))?),
+            "description" => description = value.to_string(),
+            "variables" => {
+                for declaration in value.split(',').map(str::trim).filter(|declaration| !declaration.is_empty()) {
+                    let (variable, type_name) = declaration
+                        .split_once(':')
+                        .ok_or_else(|| format!("variable '{}' needs a type, e.g. '{}:text'", declaration, declaration))?;
+                    let variable_type = VariableType::parse(type_name.trim())
+                        .ok_or_else(|| format!("unknown type '{}' of variable '{}'", type_name.trim(), variable.trim()))?;
+                    variables.push((variable.trim().to_string(), variable_type));
+                }
+            }
+            other => return Err(format!("unknown header field '{}'", other)),
+        }
+    }
+
+    Ok(PromptTemplate {
+        name: name.ok_or("missing 'name'")?,
+        version: version.ok_or("missing 'version'")?,
+        description,
+        variables,
+        body: body.to_string(),
+    })
+}
+
+// Checks a parsed template against its file and what the code passes in
+fn validate_template(template: PromptTemplate, spec: &TemplateSpec, file_version: u32) -> std::result::Result<PromptTemplate, String> {
+    if template.name != spec.name {
+        return Err(format!("header names template '{}'", template.name));
+    }
+    if template.version != file_version {
+        return Err(format!("header version {} does not match the file name", template.version));
+    }
+
+    for (variable, variable_type) in &template.variables {
+        match spec.variables.iter().find(|(name, _)| name == variable) {
+            Some((_, expected)) if expected == variable_type => {}
+            Some((_, expected)) => return Err(format!("variable '{}' is declared {:?} but the code passes {:?}", variable, variable_type, expected)),
+            None => return Err(format!("variable '{}' is not passed by the code", variable)),
+        }
+    }
+
+    let mut used: Vec<&str> = Vec::new();
+    for captures in PLACEHOLDER.captures_iter(&template.body) {
+        let variable = captures.get(1).unwrap().as_str();
+        if !template.variables.iter().any(|(name, _)| name == variable) {
+            return Err(format!("placeholder {{{{{}}}}} is not declared", variable));
+        }
+        used.push(variable);
+    }
+    for (variable, _) in &template.variables {
+        if !used.contains(&variable.as_str()) {
+            println!("->> {:<12} - {}: variable '{}' is declared but never used", "PROMPTS", template.tag(), variable);
+        }
+    }
+
+    Ok(template)
+}
+
+/// Loads the templates now, so problems surface at startup
+pub fn init() -> std::result::Result<(), String> {
+    if REGISTRY.get().is_some() {
+        return Ok(());
+    }
+    let registry = load_from_env()?;
+    for template in registry.active_templates() {
+        println!("->> {:<12} - Using {}", "PROMPTS", template.tag());
+    }
+    let _ = REGISTRY.set(registry);
+    Ok(())
+}
+
+fn load_from_env() -> std::result::Result<PromptRegistry, String> {
+    let dir = env::var("PROMPT_TEMPLATE_DIR").unwrap_or_else(|_| DEFAULT_TEMPLATE_DIR.to_string());
+    PromptRegistry::load(Path::new(&dir))
+}
+
+/// The loaded registry. Loads it on first use if `init` was not called.
+pub fn registry() -> Result<&'static PromptRegistry> {
+    if let Some(registry) = REGISTRY.get() {
+        return Ok(registry);
+    }
+    let registry = load_from_env().map_err(|problems| {
+        eprintln!("->> {:<12} - Invalid prompt templates:\n{}", "PROMPTS", problems);
+        Error::PromptTemplateError { template: "*".to_string() }
+    })?;
+    Ok(REGISTRY.get_or_init(|| registry))
+}
+
+/// Renders the active version of a template
+pub fn render(name: &str, values: &[(&str, PromptValue)]) -> Result<RenderedPrompt> {
+    registry()?.render(name, values)
+}
@@ -0,0 +1,103 @@
+// Generated titles of writing assistant sessions
+//
+// Sessions created without a title get `auto_title` set. Once the first answer is stored the title
+// is generated in the background from the first user message and its answer on the active branch.
+// The job claims the session by clearing `auto_title`, so every session is titled at most once and
+// a title the user sets in the meantime is never overwritten. Titles are not charged.
+
+use sqlx::PgPool;
+
+use crate::models::ai::MessageRole;
+use crate::rag::citations::strip_citation_markers;
+use crate::rag::llm::QueryModel;
+use crate::rag::retrieval;
+use crate::rag::templates;
+use crate::{Error, Result};
+
+/// Longest generated title, in characters
+const MAX_TITLE_CHARS: usize = 80;
+/// Characters of each message the title is generated from
+const MAX_EXCERPT_CHARS: usize = 2000;
+
+/// Generates the title of a session if it still needs one. Returns the new title.
+pub async fn generate_session_title(pool: &PgPool, session_id: i32) -> Result<Option<String>> {
+    let pending = sqlx::query!("SELECT auto_title FROM writing_assistant_sessions WHERE id = $1", session_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+    if !pending.is_some_and(|session| session.auto_title) {
+        return Ok(None);
+    }
+
+    let branch = retrieval::retrieve_active_branch(pool, session_id).await?;
+    let Some(question) = branch.iter().position(|message| message.role == MessageRole::User) else {
+        return Ok(None);
+    };
+    let Some(answer) = branch[question..].iter().find(|message| message.role == MessageRole::Assistant) else {
+        return Ok(None);
+    };
+
+    // Claim the session, a concurrent job or a title set by the user wins
+    let claimed = sqlx::query!(
+        "UPDATE writing_assistant_sessions SET auto_title = FALSE WHERE id = $1 AND auto_title RETURNING id",
+        session_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    if claimed.is_none() {
+        return Ok(None);
+    }
+
+    let excerpt = |content: &str| strip_citation_markers(content).chars().take(MAX_EXCERPT_CHARS).collect::<String>();
+    let prompt = templates::render("session_title", &[
+        ("first_message", excerpt(&branch[question].content).as_str().into()),
+        ("first_answer", excerpt(&answer.content).as_str().into()),
+    ])?;
+    let query_model = QueryModel::new()?;
+    let title = match query_model.query_model(&prompt).await.map(|raw| clean_title(&raw)) {
+        Ok(title) if !title.is_empty() => title,
+        result => {
+            // Let the next answer try again
+            sqlx::query!("UPDATE writing_assistant_sessions SET auto_title = TRUE WHERE id = $1", session_id)
+                .execute(pool)
+                .await
+                .map_err(|_| Error::DatabaseError)?;
+            return result.map(|_| None);
+        }
+    };
+
+    sqlx::query!("UPDATE writing_assistant_sessions SET title = $2 WHERE id = $1", session_id, title)
+        .execute(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+    println!("->> {:<12} - Session {} titled \"{}\" ({})", "TITLE", session_id, title, prompt.template);
+    Ok(Some(title))
+}
+
+/// Generates the title of a session on the tokio runtime
+pub fn spawn_title_generation(pool: PgPool, session_id: i32) {
+    tokio::spawn(async move {
+        if let Err(e) = generate_session_title(&pool, session_id).await {
+            eprintln!("->> {:<12} - Title generation for session {} failed: {:?}", "TITLE", session_id, e);
+        }
+    });
+}
+
+// First line of the model output without quotes, labels and trailing punctuation
+fn clean_title(raw: &str) -> String {
+    let line = raw.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("");
+    let line = line.strip_prefix("Title:").unwrap_or(line).trim();
+    let line = line.trim_matches(|c: char| matches!(c, '"' | '\'' | '*' | '`' | '“' | '”')).trim();
+    let line = line.trim_end_matches(['.', '!', ':', ';']).trim();
+
+    if line.chars().count() <= MAX_TITLE_CHARS {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(MAX_TITLE_CHARS).collect();
+    // Cut at a word boundary when there is one
+    match cut.rfind(' ') {
+        Some(space) if space > MAX_TITLE_CHARS / 2 => format!("{}...", cut[..space].trim_end()),
+        _ => format!("{}...", cut),
+    }
+}
@@ -0,0 +1,96 @@
+// BPE token counting for prompt budgets
+//
+// OpenAI models are counted with the encoding they actually use (o200k_base for the gpt-4o
+// family, cl100k_base otherwise). Other models (e.g. Ollama) have their own vocabularies; they
+// are counted with cl100k_base plus a safety margin, which errs on the side of shorter prompts.
+
+use lazy_static::lazy_static;
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer as Encoding};
+use tiktoken_rs::CoreBPE;
+
+/// Extra share added to counts of models without an exact encoding
+const APPROXIMATION_MARGIN: f64 = 0.1;
+
+lazy_static! {
+    static ref CL100K_BASE: CoreBPE = tiktoken_rs::cl100k_base().expect("cl100k_base encoding should load");
+    static ref O200K_BASE: CoreBPE = tiktoken_rs::o200k_base().expect("o200k_base encoding should load");
+}
+
+#[derive(Clone, Copy)]
+pub struct Tokenizer {
+    bpe: &'static CoreBPE,
+    // Whether the encoding is the model's own
+    exact: bool,
+}
+
+impl Tokenizer {
+    /// Tokenizer matching the given model name, e.g. "gpt-3.5-turbo"
+    pub fn for_model(model: &str) -> Self {
+        match get_tokenizer(model) {
+            Some(Encoding::O200kBase) => Tokenizer { bpe: &O200K_BASE, exact: true },
+            Some(Encoding::Cl100kBase) => Tokenizer { bpe: &CL100K_BASE, exact: true },
+            _ => Tokenizer { bpe: &CL100K_BASE, exact: false },
+        }
+    }
+
+    /// Number of tokens the model will see for `text`
+    pub fn count(&self, text: &str) -> usize {
+        let tokens = self.bpe.encode_with_special_tokens(text).len();
+        if self.exact {
+            tokens
+        } else {
+            (tokens as f64 * (1.0 + APPROXIMATION_MARGIN)).ceil() as usize
+        }
+    }
+
+    /// Keeps the beginning of `text`, cut to at most `max_tokens` tokens
+    pub fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        if self.count(text) <= max_tokens {
+            return text.to_string();
+        }
+
+        let tokens = self.bpe.encode_with_special_tokens(text);
+        let mut keep = if self.exact {
+            max_tokens
+        } else {
+            (max_tokens as f64 / (1.0 + APPROXIMATION_MARGIN)).floor() as usize
+        }
+        .min(tokens.len());
+
+        // A cut inside a multi-byte character does not decode, back off a token at a time
+        while keep > 0 {
+            if let Ok(truncated) = self.bpe.decode(tokens[..keep].to_vec()) {
+                return truncated;
+            }
+            keep -= 1;
+        }
+        String::new()
+    }
+}
+
+/// Size of the context window (prompt + completion) of a model, in tokens
+pub fn context_window_for_model(model: &str) -> usize {
+    let model = model.to_lowercase();
+    // Most specific prefixes first
+    let known: [(&str, usize); 13] = [
+        ("gpt-4o", 128_000),
+        ("gpt-4.1", 1_047_576),
+        ("gpt-4-turbo", 128_000),
+        ("gpt-4-1106", 128_000),
+        ("gpt-4-0125", 128_000),
+        ("gpt-4-32k", 32_768),
+        ("gpt-4", 8_192),
+        ("gpt-3.5-turbo-instruct", 4_096),
+        ("gpt-3.5-turbo", 16_385),
+        ("llama3.1", 131_072),
+        ("llama3.2", 131_072),
+        ("llama3", 8_192),
+        ("mistral", 32_768),
+    ];
+
+    known
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+        .unwrap_or(8_192)
+}
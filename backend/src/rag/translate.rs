@@ -0,0 +1,228 @@
+// Translation with glossary enforcement
+//
+// Documents are translated segment by segment: the content is cut after block elements (or blank
+// lines) into pieces of at most TRANSLATION_SEGMENT_CHARS characters, so long documents fit the
+// model and the markup survives. Every segment is checked against the glossary of its projects:
+// each occurrence of a term in the source must be matched by the fixed text in the translation,
+// the term itself for keep rules, its translation for translate rules. Matching ignores case and
+// needs word boundaries. A translation that breaks the glossary is sent back to the model with the
+// problems, up to TRANSLATION_MAX_REPAIRS times (default: 1); what is still broken is reported to
+// the client instead of failing the request.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::env;
+
+use crate::models::translation::{GlossaryRule, GlossaryTerm, GlossaryViolation};
+use crate::rag::chunk::to_plain_text;
+use crate::rag::templates::{self, RenderedPrompt};
+use crate::Result;
+
+/// Longest segment sent to the model at once, in characters
+const DEFAULT_SEGMENT_CHARS: usize = 6000;
+/// Repair attempts of a segment that breaks the glossary, by default
+const DEFAULT_MAX_REPAIRS: usize = 1;
+
+lazy_static! {
+    // Language codes such as "de", "pt-BR" or "zh_Hant"
+    static ref LANGUAGE_CODE: Regex = Regex::new(r"^([A-Za-z]{2,3})(?:[-_]([A-Za-z]{2}|[A-Za-z]{4}))?$").unwrap();
+    // Places a document may be cut: after a block element or at a blank line
+    static ref SEGMENT_BREAK: Regex =
+        Regex::new(r"(?i)</(?:p|h[1-6]|li|ul|ol|blockquote|pre|table|div)>|\n[ \t]*\n").unwrap();
+}
+
+/// Canonical form of a language code ("de", "pt-BR", "zh-Hant"), None if it is not one
+pub fn normalize_language(code: &str) -> Option<String> {
+    let caps = LANGUAGE_CODE.captures(code.trim())?;
+    let language = caps[1].to_lowercase();
+    Some(match caps.get(2).map(|region| region.as_str()) {
+        Some(region) if region.len() == 2 => format!("{}-{}", language, region.to_uppercase()),
+        Some(script) => format!("{}-{}{}", language, script[..1].to_uppercase(), script[1..].to_lowercase()),
+        None => language,
+    })
+}
+
+fn segment_chars() -> usize {
+    env::var("TRANSLATION_SEGMENT_CHARS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|&chars| chars > 0)
+        .unwrap_or(DEFAULT_SEGMENT_CHARS)
+}
+
+pub fn max_repairs() -> usize {
+    env::var("TRANSLATION_MAX_REPAIRS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_REPAIRS)
+}
+
+/// Cuts content into segments of at most TRANSLATION_SEGMENT_CHARS characters at block boundaries.
+/// The segments joined together are the content. A single block longer than the limit is one segment.
+pub fn split_segments(content: &str) -> Vec<String> {
+    let limit = segment_chars();
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for block_end in SEGMENT_BREAK.find_iter(content) {
+        blocks.push(&content[start..block_end.end()]);
+        start = block_end.end();
+    }
+    if start < content.len() {
+        blocks.push(&content[start..]);
+    }
+
+    let mut segments: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for block in blocks {
+        if !current.is_empty() && current.chars().count() + block.chars().count() > limit {
+            segments.push(std::mem::take(&mut current));
+        }
+        current.push_str(block);
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+    segments
+}
+
+/// Glossary rules that apply to a target language: keep rules for every language or this one,
+/// translate rules for this one
+pub fn applicable_terms(terms: &[GlossaryTerm], target_language: &str) -> Vec<GlossaryTerm> {
+    let mut applicable: Vec<GlossaryTerm> = Vec::new();
+    for term in terms {
+        let language_matches = match term.language.as_deref() {
+            Some(language) => language.eq_ignore_ascii_case(target_language),
+            None => term.rule == GlossaryRule::Keep,
+        };
+        // The same term from the glossaries of two projects counts once
+        if language_matches && !applicable.iter().any(|other| other.term.to_lowercase() == term.term.to_lowercase()) {
+            applicable.push(term.clone());
+        }
+    }
+    applicable
+}
+
+/// Text the translation must contain instead of the term
+pub fn expected_text(term: &GlossaryTerm) -> &str {
+    match term.rule {
+        GlossaryRule::Keep => &term.term,
+        GlossaryRule::Translate => term.translation.as_deref().unwrap_or(&term.term),
+    }
+}
+
+/// Occurrences of `needle` in `text` as whole words, ignoring case
+pub fn count_term(text: &str, needle: &str) -> usize {
+    let needle = needle.trim().to_lowercase();
+    if needle.is_empty() {
+        return 0;
+    }
+    let text = text.to_lowercase();
+    let is_word_char = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+
+    let mut count = 0;
+    let mut from = 0;
+    while let Some(position) = text[from..].find(&needle) {
+        let start = from + position;
+        let end = start + needle.len();
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        if !is_word_char(before) && !is_word_char(after) {
+            count += 1;
+        }
+        from = start + needle.chars().next().map_or(1, char::len_utf8);
+    }
+    count
+}
+
+/// Glossary rules a translation breaks: fewer occurrences of the fixed text than of the term
+pub fn check_glossary(source: &str, translation: &str, terms: &[GlossaryTerm]) -> Vec<GlossaryViolation> {
+    let source = to_plain_text(source);
+    let translation = to_plain_text(translation);
+    terms
+        .iter()
+        .filter_map(|term| {
+            let source_occurrences = count_term(&source, &term.term);
+            if source_occurrences == 0 {
+                return None;
+            }
+            let expected = expected_text(term);
+            let translation_occurrences = count_term(&translation, expected);
+            (translation_occurrences < source_occurrences).then(|| GlossaryViolation {
+                term: term.term.clone(),
+                expected: expected.to_string(),
+                source_occurrences,
+                translation_occurrences,
+            })
+        })
+        .collect()
+}
+
+// Glossary rules of the terms in a segment, one per line
+fn glossary_instructions(segment: &str, terms: &[GlossaryTerm]) -> String {
+    let plain = to_plain_text(segment);
+    let lines: Vec<String> = terms
+        .iter()
+        .filter(|term| count_term(&plain, &term.term) > 0)
+        .map(|term| {
+            let rule = match term.rule {
+                GlossaryRule::Keep => format!("- Keep \"{}\" exactly as written, do not translate it", term.term),
+                GlossaryRule::Translate => format!("- Translate \"{}\" as \"{}\"", term.term, expected_text(term)),
+            };
+            match &term.notes {
+                Some(notes) => format!("{} ({})", rule, notes),
+                None => rule,
+            }
+        })
+        .collect();
+    if lines.is_empty() {
+        "(no glossary terms in this text)".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
+
+/// Prompt translating one segment
+pub fn translation_prompt(segment: &str, source_language: Option<&str>, target_language: &str, terms: &[GlossaryTerm]) -> Result<RenderedPrompt> {
+    let source_language = match source_language {
+        Some(language) => format!("the language with the code '{}'", language),
+        None => "its original language".to_string(),
+    };
+    templates::render("translate", &[
+        ("text", segment.into()),
+        ("source_language", source_language.into()),
+        ("target_language", target_language.into()),
+        ("glossary", glossary_instructions(segment, terms).into()),
+    ])
+}
+
+/// Prompt asking for a corrected translation of a segment that breaks the glossary
+pub fn repair_prompt(original: &RenderedPrompt, translation: &str, violations: &[GlossaryViolation]) -> Result<RenderedPrompt> {
+    let problems = violations
+        .iter()
+        .map(|violation| {
+            format!(
+                "- \"{}\" appears {} time(s) in the source, but \"{}\" appears {} time(s) in your translation",
+                violation.term, violation.source_occurrences, violation.expected, violation.translation_occurrences
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    templates::render("translate_repair", &[
+        ("original_prompt", original.text.as_str().into()),
+        ("translation", translation.into()),
+        ("violations", problems.into()),
+    ])
+}
+
+/// The translation of a segment without a code fence the model may have wrapped it in, with the
+/// whitespace around the source segment so that the segments join up again
+pub fn clean_translation(segment: &str, raw: &str) -> String {
+    let mut text = raw.trim();
+    if let Some(fenced) = text.strip_prefix("```") {
+        let body = fenced.split_once('\n').map(|(_, body)| body).unwrap_or(fenced);
+        text = body.trim_end().strip_suffix("```").unwrap_or(body).trim();
+    }
+    let leading = &segment[..segment.len() - segment.trim_start().len()];
+    let trailing = &segment[segment.trim_end().len()..];
+    format!("{}{}{}", leading, text, trailing)
+}
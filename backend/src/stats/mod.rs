@@ -0,0 +1,260 @@
+// Readability and writing statistics
+//
+// Computed from the plain text of documents (rag::chunk::to_plain_text): counts, reading time,
+// the usual readability formulas, vocabulary richness, adverb density and the most repeated words.
+// Every block (paragraph or heading) ends a sentence, so headings and list items without
+// punctuation do not run into the next sentence. Syllables are counted with the usual English
+// heuristic (vowel groups, silent e), the scores are estimates for English prose.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::proofread::{tokenize, Token};
+use crate::rag::chunk::to_plain_text;
+
+/// Silent reading speed used for the reading time, in words per minute
+const READING_WORDS_PER_MINUTE: f64 = 238.0;
+/// Window of the moving-average type-token ratio
+const MATTR_WINDOW: usize = 100;
+/// Most repeated words returned
+const MAX_REPEATED_WORDS: usize = 10;
+
+// Words followed by a period that do not end a sentence
+const ABBREVIATIONS: &[&str] = &["mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "vs", "etc", "e", "g", "i", "ie", "eg", "no", "fig", "approx", "dept"];
+
+// Words ending in -ly that are not adverbs
+const NOT_ADVERBS: &[&str] = &[
+    "ally", "anomaly", "apply", "assembly", "belly", "bully", "butterfly", "chilly", "costly", "curly", "daily",
+    "deadly", "early", "elderly", "family", "fly", "friendly", "holy", "hourly", "italy", "jelly", "jolly", "july",
+    "lily", "likely", "lively", "lonely", "lovely", "melancholy", "monopoly", "monthly", "multiply", "only",
+    "orderly", "rally", "reply", "silly", "supply", "ugly", "weekly", "wily", "yearly",
+];
+
+// Function words left out of the most repeated words
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "all", "also", "am", "an", "and", "any", "are", "as", "at", "be",
+    "because", "been", "before", "being", "between", "both", "but", "by", "can", "could", "did", "do", "does",
+    "doing", "down", "during", "each", "few", "for", "from", "further", "had", "has", "have", "having", "he",
+    "her", "here", "hers", "herself", "him", "himself", "his", "how", "i", "if", "in", "into", "is", "it", "its",
+    "itself", "just", "me", "more", "most", "my", "myself", "no", "nor", "not", "now", "of", "off", "on", "once",
+    "only", "or", "other", "our", "ours", "out", "over", "own", "same", "she", "should", "so", "some", "such",
+    "than", "that", "the", "their", "theirs", "them", "then", "there", "these", "they", "this", "those", "through",
+    "to", "too", "under", "until", "up", "very", "was", "we", "were", "what", "when", "where", "which", "while",
+    "who", "whom", "why", "will", "with", "would", "you", "your", "yours", "it's", "i'm", "don't", "didn't",
+    "said", "one", "like",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WordFrequency {
+    pub word: String,
+    pub count: i32,
+}
+
+/// Statistics of a text. Scores are rounded to two decimals and 0 for empty texts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct WritingMetrics {
+    pub word_count: i32,
+    pub character_count: i32,
+    pub sentence_count: i32,
+    pub paragraph_count: i32,
+    pub heading_count: i32,
+    pub syllable_count: i32,
+    /// Words of three syllables or more
+    pub complex_word_count: i32,
+    pub reading_time_minutes: f64,
+    pub average_sentence_length: f64,
+    pub average_word_length: f64,
+    /// 0 (very hard) to 100 (very easy)
+    pub flesch_reading_ease: f64,
+    /// US school grades
+    pub flesch_kincaid_grade: f64,
+    pub gunning_fog: f64,
+    pub coleman_liau_index: f64,
+    pub automated_readability_index: f64,
+    pub unique_words: i32,
+    /// Unique words / words, lower for longer texts
+    pub type_token_ratio: f64,
+    /// Average type-token ratio over windows of 100 words, comparable between texts of any length
+    pub moving_average_ttr: f64,
+    pub adverb_count: i32,
+    /// Adverbs per 100 words
+    pub adverb_density: f64,
+    pub most_repeated_words: Vec<WordFrequency>,
+}
+
+/// Statistics of stored document content (editor HTML or markdown)
+pub fn document_metrics(content: &str) -> WritingMetrics {
+    compute_metrics(&to_plain_text(content))
+}
+
+/// Words of stored document content, the same count as `document_metrics`
+pub fn word_count(content: &str) -> i32 {
+    tokenize(&to_plain_text(content)).len() as i32
+}
+
+/// Statistics of plain text, blocks separated by blank lines
+pub fn compute_metrics(text: &str) -> WritingMetrics {
+    let mut words: Vec<Token> = Vec::new();
+    let mut sentence_count = 0;
+    let mut paragraph_count = 0;
+    let mut heading_count = 0;
+
+    for block in text.split("\n\n").map(str::trim).filter(|block| !block.is_empty()) {
+        let tokens = tokenize(block);
+        if tokens.is_empty() {
+            continue;
+        }
+        if block.starts_with('#') {
+            heading_count += 1;
+        } else {
+            paragraph_count += 1;
+        }
+        sentence_count += count_sentences(block, &tokens);
+        words.extend(tokens);
+    }
+
+    let word_count = words.len();
+    if word_count == 0 {
+        return WritingMetrics::default();
+    }
+
+    let letters: usize = words.iter().map(|word| word.text.chars().filter(|c| c.is_alphanumeric()).count()).sum();
+    let syllables: Vec<usize> = words.iter().map(|word| count_syllables(&word.lowercase)).collect();
+    let syllable_count: usize = syllables.iter().sum();
+    let complex_word_count = syllables.iter().filter(|&&count| count >= 3).count();
+    let adverb_count = words.iter().filter(|word| is_adverb(&word.lowercase)).count();
+    let unique_words = words.iter().map(|word| word.lowercase.as_str()).collect::<HashSet<_>>().len();
+
+    let w = word_count as f64;
+    let s = sentence_count.max(1) as f64;
+    let words_per_sentence = w / s;
+    let syllables_per_word = syllable_count as f64 / w;
+    let letters_per_100_words = letters as f64 / w * 100.0;
+    let sentences_per_100_words = s / w * 100.0;
+
+    WritingMetrics {
+        word_count: word_count as i32,
+        character_count: text.chars().filter(|c| !c.is_whitespace() && *c != '#').count() as i32,
+        sentence_count: sentence_count as i32,
+        paragraph_count,
+        heading_count,
+        syllable_count: syllable_count as i32,
+        complex_word_count: complex_word_count as i32,
+        reading_time_minutes: round(w / READING_WORDS_PER_MINUTE),
+        average_sentence_length: round(words_per_sentence),
+        average_word_length: round(letters as f64 / w),
+        flesch_reading_ease: round(206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word),
+        flesch_kincaid_grade: round(0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59),
+        gunning_fog: round(0.4 * (words_per_sentence + 100.0 * complex_word_count as f64 / w)),
+        coleman_liau_index: round(0.0588 * letters_per_100_words - 0.296 * sentences_per_100_words - 15.8),
+        automated_readability_index: round(4.71 * (letters as f64 / w) + 0.5 * words_per_sentence - 21.43),
+        unique_words: unique_words as i32,
+        type_token_ratio: round(unique_words as f64 / w),
+        moving_average_ttr: round(moving_average_ttr(&words)),
+        adverb_count: adverb_count as i32,
+        adverb_density: round(adverb_count as f64 / w * 100.0),
+        most_repeated_words: most_repeated_words(&words),
+    }
+}
+
+// Sentences of a block: terminal punctuation between two words ends one, unless it follows an
+// abbreviation or an initial. Text after the last terminator is a sentence too.
+fn count_sentences(block: &str, tokens: &[Token]) -> usize {
+    let ends = tokens
+        .windows(2)
+        .filter(|pair| {
+            let (word, next) = (&pair[0], &pair[1]);
+            let gap = &block[word.end_byte..next.start_byte];
+            if !gap.contains(['.', '!', '?']) {
+                return false;
+            }
+            let initial = word.text.chars().count() == 1 && word.text.chars().all(char::is_uppercase);
+            let abbreviation = ABBREVIATIONS.contains(&word.lowercase.as_str()) && gap.trim_start().starts_with('.') && !gap.contains(['!', '?']);
+            gap.contains(['!', '?']) || !(initial || abbreviation)
+        })
+        .count();
+    ends + 1
+}
+
+/// Estimated syllables of a lowercase English word, at least 1
+pub fn count_syllables(word: &str) -> usize {
+    let letters: String = word.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.chars().count() <= 3 {
+        return 1;
+    }
+    let mut stem = letters.as_str();
+    // Silent endings: "hoped", "makes", "home", but not "wanted", "horses", "table"
+    if let Some(rest) = stem.strip_suffix("ed").filter(|rest| !rest.ends_with(['t', 'd'])) {
+        stem = rest;
+    } else if let Some(rest) = stem.strip_suffix("es").filter(|rest| !rest.ends_with(['s', 'x', 'z', 'c', 'g']) && !rest.ends_with("sh") && !rest.ends_with("ch")) {
+        stem = rest;
+    } else if let Some(rest) = stem.strip_suffix('e').filter(|rest| !rest.ends_with("l") || rest.ends_with("ll")) {
+        stem = rest;
+    }
+
+    let mut groups = 0;
+    let mut previous_vowel = false;
+    for c in stem.chars() {
+        let vowel = matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+        if vowel && !previous_vowel {
+            groups += 1;
+        }
+        previous_vowel = vowel;
+    }
+    groups.max(1)
+}
+
+fn is_adverb(word: &str) -> bool {
+    word.chars().count() > 4 && word.ends_with("ly") && !NOT_ADVERBS.contains(&word)
+}
+
+fn moving_average_ttr(words: &[Token]) -> f64 {
+    if words.len() <= MATTR_WINDOW {
+        return words.iter().map(|word| word.lowercase.as_str()).collect::<HashSet<_>>().len() as f64 / words.len() as f64;
+    }
+    // Slide the window one word at a time, keeping counts of the words inside it
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for word in &words[..MATTR_WINDOW] {
+        *counts.entry(word.lowercase.as_str()).or_default() += 1;
+    }
+    let mut total = counts.len() as f64 / MATTR_WINDOW as f64;
+    for index in MATTR_WINDOW..words.len() {
+        let leaving = words[index - MATTR_WINDOW].lowercase.as_str();
+        if let Some(count) = counts.get_mut(leaving) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(leaving);
+            }
+        }
+        *counts.entry(words[index].lowercase.as_str()).or_default() += 1;
+        total += counts.len() as f64 / MATTR_WINDOW as f64;
+    }
+    total / (words.len() - MATTR_WINDOW + 1) as f64
+}
+
+// Content words used at least twice, most frequent first, ties alphabetically
+fn most_repeated_words(words: &[Token]) -> Vec<WordFrequency> {
+    let mut counts: HashMap<&str, i32> = HashMap::new();
+    for word in words {
+        let word = word.lowercase.as_str();
+        if word.chars().count() >= 3 && !STOP_WORDS.contains(&word) && !word.chars().any(|c| c.is_numeric()) {
+            *counts.entry(word).or_default() += 1;
+        }
+    }
+    let mut repeated: Vec<WordFrequency> = counts
+        .into_iter()
+        .filter(|&(_, count)| count >= 2)
+        .map(|(word, count)| WordFrequency { word: word.to_string(), count })
+        .collect();
+    repeated.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.word.cmp(&b.word)));
+    repeated.truncate(MAX_REPEATED_WORDS);
+    repeated
+}
+
+fn round(value: f64) -> f64 {
+    if value.is_finite() {
+        (value * 100.0).round() / 100.0
+    } else {
+        0.0
+    }
+}
@@ -9,6 +9,25 @@
 / api_create_writing_session     POST    /                   - Create A New Writing Session
 / api_get_writing_session        GET     /:id                - Get Writing Session By ID With Messages
 / api_send_writing_message       POST    /:id/message        - Send Message And Get AI Response
+/ api_stream_writing_message     POST    /:id/message/stream - Send Message And Stream AI Response (SSE)
+/ api_agent_writing_message      POST    /:id/agent          - Answer A Message With Server-Side Tools (Agent Mode)
+/ api_get_tool_calls             GET     /:id/tool-calls     - List The Tool Calls Of A Session
+/ api_regenerate_writing_message POST    /:id/messages/:message_id/regenerate - Answer A User Message Again On A New Branch
+/ api_edit_writing_message       POST    /:id/messages/:message_id/edit       - Send An Edited User Message On A New Branch
+/ api_get_writing_branches       GET     /:id/branches              - List The Branches Of A Session
+/ api_switch_writing_branch      PUT     /:id/branches/:message_id  - Switch To The Branch Containing A Message
+/ api_get_personas               GET     /personas                  - List Built-in And Own Assistant Personas
+/ api_create_persona             POST    /personas                  - Create A Custom Assistant Persona
+/ api_delete_persona             DELETE  /personas/:persona_id      - Delete A Custom Assistant Persona
+/ api_set_session_persona        PUT     /:id/persona               - Set The Persona Or System Prompt Of A Session
+/ api_get_providers              GET     /providers                 - Show The Completion And Embedding Models In Use
+/ api_*_stream                   POST    /<action>/stream    - Stream A Quick Action (summarize, expand, rewrite, ...) (SSE)
+/ api_fact_check_evidence        POST    /factcheck/evidence        - Check The Claims Of A Text Against Project And Reference Documents
+/ api_translate                  POST    /translate                 - Translate Text, Enforcing A Project Glossary
+/ api_translate_document         POST    /translate/document/:id    - Translate A Document Into A Linked Document
+/ api_translate_project          POST    /translate/project/:id     - Translate Every Document Of A Project
+/ api_extract_entities           POST    /knowledge/extract/:id     - Add The Entities Of A Project's Documents To Its Knowledge Base
+/ api_start_consistency_check    POST    /consistency/:id           - Start A Background Consistency Check Of A Project
 / api_delete_writing_session     DELETE  /:id                - Delete Writing Session And All Messages
 / api_get_document_suggestions   GET     /:id/suggestions    - NOT IMPLEMENTED: Get Writing Suggestions For Document
 / api_analyze_document           POST    /analyze            - NOT IMPLEMENTED: Analyze Document For Writing Issues
@@ -18,23 +37,40 @@
 
 use axum::{
     extract::{Extension, Json, Path},
-    routing::{get, post, delete},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post, put, delete},
     Router,
 };
+use futures_util::stream::{self, BoxStream, StreamExt};
 use serde_json::{json, Value};
 use sqlx::PgPool;
+use sqlx::types::Json as SqlJson;
 use tower_cookies::Cookies;
 use chrono::Utc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::future::Future;
+use tokio::sync::mpsc;
 
 use crate::models::ai::{
     WritingAssistantSession, WritingAssistantMessage, SessionWithMessages, 
     CreateSessionPayload, SendMessagePayload, MessageRole, SelectedTextContext,
     RewritePayload, WritingAssistantSessionWithSnippet, SessionWithMessageContent,
-    ApplySuggestionPayload, SuggestedDocumentChange, LlmDocChange,
+    ApplySuggestionPayload, ApplySuggestionResult, SuggestedDocumentChange, LlmDocEdit, LlmDocEdits, DocumentChangeFailure,
     DecisionAgentPayload, DecisionAgentResponse,
-    SanitizeTextPayload, SanitizeTextResponse
+    SanitizeTextPayload, SanitizeTextResponse, Citation, EditMessagePayload, ChatBranch,
+    LlmAgentStep, AgentToolCall, AgentResponse
 };
+use crate::models::persona::{self, Persona, CreatePersonaPayload, PersonaSettings, PersonaManager};
+use crate::models::translation::{
+    DocumentTranslationFailure, DocumentTranslationResult, Glossary, GlossaryTerm, GlossaryViolation, ProjectTranslationResult,
+    TranslateDocumentPayload, TranslateTextPayload, TranslationManager, TranslationResult,
+};
+use crate::models::knowledge::{render_knowledge, spawn_mentions_refresh, ExtractEntitiesPayload, ExtractionResult, KnowledgeManager, LlmExtractedEntities};
+use crate::models::consistency::{ConsistencyManager, ConsistencyReport, LlmConsistencyCheck, StartConsistencyCheckPayload};
+use crate::models::fact_check::{EvidenceFactCheck, EvidenceFactCheckPayload, LlmClaimVerdicts, LlmClaims, MAX_CLAIMS, MAX_FACT_CHECK_CHARS};
+use crate::models::plan::PlanManager;
+use crate::models::stats::spawn_stats_refresh;
 // Commented out until implemented
 // use crate::cag::retrieval::semantic_search;
 use crate::{Error, Result};
@@ -42,12 +78,34 @@ use crate::{Error, Result};
 use backend::get_user_id_from_cookie;
 
 // Import RAG components
-use crate::rag::embed::{EmbeddingModel, embed_and_store_user_message, embed_and_store_assistant_message};
-use crate::rag::llm::QueryModel;
+use crate::rag::embed::{AssistantAnswer, EmbeddingModel, PendingUserMessage, embed_and_store_exchange};
+use crate::rag::llm::{charged_query, QueryModel};
 use crate::rag::prompt;
 use crate::rag::retrieval;
 use pgvector::Vector;
 use crate::rag::prompt::construct_context_decision_prompt;
+use crate::rag::citations::extract_citations;
+use crate::rag::templates::RenderedPrompt;
+use crate::rag::structured::charged_structured_query;
+use crate::rag::patch;
+use crate::rag::summary;
+use crate::rag::title;
+use crate::rag::translate;
+use crate::rag::consistency::{self, ProjectProfile, TimelineEvent};
+use crate::rag::fact_check::{self, EVIDENCE_PER_CLAIM};
+use crate::proofread;
+use crate::rag::jobs::enqueue_document_embedding;
+use crate::rag::agent::{self, AgentScope, AgentState, AgentTool};
+use crate::rag::tokenizer::Tokenizer;
+use crate::models::credits::{AiOperation, CreditLedger};
+use crate::web::middleware::middleware::{check_document_permission, check_project_permission};
+
+/// Summarized messages recalled into a chat prompt when they match the new message
+const RECALLED_MESSAGES_K: i64 = 4;
+/// Chunks retrieved to pick the documents of an apply-suggestion prompt that does not fit whole
+const APPLY_SUGGESTION_RETRIEVAL_K: i64 = 20;
+/// Tokens a tool result may always take in agent mode, however small the model's window
+const AGENT_MIN_RESULT_TOKENS: usize = 256;
 
 /// GET handler for retrieving all writing sessions for current user.
 /// Accessible via: GET /api/writing-assistant
@@ -130,6 +188,8 @@ pub async fn api_get_all_writing_sessions(
 /// Frontend: ai.ts/create_writing_session()
 /// Creates a new writing assistant session and initializes it with a welcome message.
 /// Can optionally be linked to a document by providing a document_id in the payload.
+/// `title` is optional, without one the title is generated from the first exchange.
+/// `persona_id` or a custom `system_prompt` choose the persona, otherwise the project's persona is used.
 pub async fn api_create_writing_session(
     cookies: Cookies,
     Extension(pool): Extension<PgPool>,
@@ -140,19 +200,38 @@ pub async fn api_create_writing_session(
     // Get user_id from cookies
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
 
+    // A session may only be linked to a document the user can read
+    if let Some(doc_id) = payload.document_id {
+        if !check_document_permission(&pool, user_id, doc_id, "viewer").await? {
+            return Err(Error::PermissionError);
+        }
+    }
+
+    if let Some(persona_id) = payload.persona_id {
+        PersonaManager::ensure_usable(&pool, user_id, persona_id).await?;
+    }
+    let system_prompt = persona::normalize_system_prompt(payload.system_prompt)?;
+
+    // Without a title one is generated from the first exchange, see rag::title
+    let title = payload.title.map(|title| title.trim().to_string()).filter(|title| !title.is_empty());
+    let auto_title = title.is_none();
+
     // Create a new chat session
-    let session = sqlx::query_as!(
+    let mut session = sqlx::query_as!(
         WritingAssistantSession,
         r#"
-        INSERT INTO writing_assistant_sessions (user_id, document_id, title, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5)
-        RETURNING id, user_id, document_id, title, created_at, updated_at
+        INSERT INTO writing_assistant_sessions (user_id, document_id, title, created_at, updated_at, persona_id, system_prompt, auto_title)
+        VALUES ($1, $2, COALESCE($3, 'New Writing Session'), $4, $5, $6, $7, $8)
+        RETURNING id, user_id, document_id, title, created_at, updated_at, active_message_id, persona_id, system_prompt, auto_title
         "#,
         user_id,
         payload.document_id,
-        payload.title,
+        title,
         Utc::now().naive_utc(),
-        Utc::now().naive_utc()
+        Utc::now().naive_utc(),
+        payload.persona_id,
+        system_prompt,
+        auto_title
     )
     .fetch_one(&pool)
     .await
@@ -160,19 +239,27 @@ pub async fn api_create_writing_session(
 
     // Add an assistant message to initialize the chat
     let system_message = "I'm your writing assistant. How can I help you today?";
-    sqlx::query!(
+    let welcome = sqlx::query!(
         r#"
-        INSERT INTO writing_assistant_messages (session_id, role, content, created_at)
-        VALUES ($1, $2, $3, $4)
+        WITH inserted AS (
+            INSERT INTO writing_assistant_messages (session_id, role, content, created_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+        )
+        UPDATE writing_assistant_sessions s SET active_message_id = inserted.id
+        FROM inserted
+        WHERE s.id = $1
+        RETURNING s.active_message_id
         "#,
         session.id,
         MessageRole::Assistant as _,
         system_message,
         Utc::now().naive_utc()
     )
-    .execute(&pool)
+    .fetch_one(&pool)
     .await
     .map_err(|_| Error::DatabaseError)?;
+    session.active_message_id = welcome.active_message_id;
 
     Ok(Json(session))
 }
@@ -181,7 +268,7 @@ pub async fn api_create_writing_session(
 /// Accessible via: GET /api/writing-assistant/:id
 /// Test: test_ai.rs/test_get_writing_session_success()
 /// Frontend: ai.ts/get_writing_session()
-/// Returns detailed information about a specific writing session including all messages.
+/// Returns detailed information about a specific writing session including the messages of its active branch.
 /// Only the owner of the session can access it.
 pub async fn api_get_writing_session(
     cookies: Cookies,
@@ -197,7 +284,7 @@ pub async fn api_get_writing_session(
     let session = sqlx::query_as!(
         WritingAssistantSession,
         r#"
-        SELECT id, user_id, document_id, title, created_at, updated_at
+        SELECT id, user_id, document_id, title, created_at, updated_at, active_message_id, persona_id, system_prompt, auto_title
         FROM writing_assistant_sessions
         WHERE id = $1 AND user_id = $2
         "#,
@@ -208,25 +295,8 @@ pub async fn api_get_writing_session(
     .await
     .map_err(|_| Error::DatabaseError)?;
 
-    // Get all messages for this session
-    let messages = sqlx::query_as!(
-        WritingAssistantMessage,
-        r#"
-        SELECT 
-            id, 
-            session_id, 
-            role AS "role: MessageRole",
-            content, 
-            created_at
-        FROM writing_assistant_messages
-        WHERE session_id = $1
-        ORDER BY created_at ASC
-        "#,
-        session_id
-    )
-    .fetch_all(&pool)
-    .await
-    .map_err(|_| Error::DatabaseError)?;
+    // Messages of the branch the session is on, see api_get_writing_branches for the others
+    let messages = retrieval::retrieve_active_branch(&pool, session_id).await?;
 
     Ok(Json(SessionWithMessages {
         session,
@@ -250,41 +320,623 @@ pub async fn api_send_writing_message(
     println!("->> {:<12} - Payload: {:?}", "HANDLER", payload);
 
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    let prepared = prepare_writing_message(&pool, user_id, session_id, UserTurn::New(&payload.content)).await?;
+
+    Ok(Json(answer_writing_message(&pool, user_id, session_id, prepared).await?))
+}
+
+/// POST handler for sending a message and streaming the AI response.
+/// Accessible via: POST /api/writing-assistant/:id/message/stream
+/// Test: test_ai.rs/test_stream_writing_message_success()
+/// Same as api_send_writing_message, but the response is sent as server-sent events while it is generated:
+/// `sources` once, `token` for every text delta, then `done` with the stored message (or `error`).
+/// Tokens are forwarded as generated; `done` carries the final content with invalid citation markers removed.
+/// The user message and the answer are stored together, with their embeddings, when the stream completes.
+/// If the client disconnects first, the upstream completion is cancelled, nothing is stored and the
+/// session stays on its current branch.
+pub async fn api_stream_writing_message(
+    cookies: Cookies,
+    Path(session_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<SendMessagePayload>,
+) -> Result<Sse<EventStream>> {
+    println!("->> {:<12} - stream_writing_message", "HANDLER");
+    println!("->> {:<12} - Payload: {:?}", "HANDLER", payload);
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    let prepared = prepare_writing_message(&pool, user_id, session_id, UserTurn::New(&payload.content)).await?;
+
+    let PreparedMessage { parent_message_id, user_message, prompt, sources, citation_sources, query_model, embedding_model } = prepared;
+    let first_events = vec![sse_event("sources", json!({ "sources": sources.clone() }))];
+
+    let prompt_template = prompt.template.clone();
+
+    stream_completion(pool.clone(), user_id, AiOperation::Chat, query_model, &prompt, first_events, move |llm_response_raw| async move {
+        println!("->> {:<12} - Streamed response complete ({} chars)", "RAG FUNCTION", llm_response_raw.len());
+        let (llm_response_content, citations) = extract_citations(&llm_response_raw, &citation_sources);
+        let message_id = embed_and_store_exchange(
+            &embedding_model,
+            &pool,
+            session_id,
+            parent_message_id,
+            user_message.as_ref(),
+            AssistantAnswer { content: &llm_response_content, citations: &citations, prompt_template: &prompt_template }
+        ).await?;
+        summary::spawn_summary_update(pool.clone(), session_id);
+        title::spawn_title_generation(pool.clone(), session_id);
+        Ok(json!({
+            "message_id": message_id,
+            "role": "assistant",
+            "content": llm_response_content,
+            "sources": sources,
+            "citations": citations,
+            "prompt_template": prompt_template
+        }))
+    }).await
+}
+
+/// POST handler for answering a message in agent mode.
+/// Accessible via: POST /api/writing-assistant/:id/agent
+/// Test: test_ai.rs/test_agent_writing_message_success()
+/// Frontend: ai.ts/send_agent_message()
+/// Instead of retrieving context up front, the assistant calls server-side tools (search the project,
+/// read a document, list the outline, count words, propose an edit) for up to AGENT_MAX_STEPS steps,
+/// see rag::agent. Every step is charged. The answer is stored like a chat answer, the tool calls
+/// are logged with it and returned together with the proposed (not applied) changes.
+pub async fn api_agent_writing_message(
+    cookies: Cookies,
+    Path(session_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<SendMessagePayload>,
+) -> Result<Json<AgentResponse>> {
+    println!("->> {:<12} - agent_writing_message for session {}", "HANDLER", session_id);
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    let session = fetch_owned_session(&pool, user_id, session_id).await?;
+    if payload.content.trim().is_empty() {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    CreditLedger::ensure_balance(&pool, user_id, AiOperation::AgentStep).await?;
+
+    // The user message is stored together with the answer, see embed_and_store_exchange
+    let embedding_model = EmbeddingModel::new()?;
+    let parent_message_id = retrieval::active_leaf(&pool, session_id).await?;
+    let user_message = PendingUserMessage {
+        content: payload.content.clone(),
+        embedding: embedding_model.embed_document(&payload.content).await?,
+    };
+    sqlx::query!(
+        "UPDATE writing_assistant_sessions SET updated_at = $1 WHERE id = $2",
+        Utc::now().naive_utc(),
+        session_id
+    )
+    .execute(&pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    let mut chat_history = retrieval::retrieve_chat_history(&pool, session_id, parent_message_id).await?;
+    chat_history.add_user_message(payload.content.clone());
+
+    // The tools work on the project of the linked document, if the user can still read it
+    let mut project_id = None;
+    let mut document_focus = "- No specific document associated with this chat.".to_string();
+    if let Some(doc_id) = session.document_id {
+        if check_document_permission(&pool, user_id, doc_id, "viewer").await? {
+            let doc_info = sqlx::query!(
+                r#"
+                SELECT d.name, dp.project_id AS "project_id?"
+                FROM documents d
+                LEFT JOIN document_projects dp ON d.id = dp.document_id
+                WHERE d.id = $1
+                "#,
+                doc_id
+            )
+            .fetch_optional(&pool)
+            .await
+            .map_err(|_| Error::DatabaseError)?;
+            if let Some(info) = doc_info {
+                project_id = info.project_id;
+                document_focus = format!("- ID: {}, Name: {}", doc_id, info.name);
+            }
+        }
+    }
+
+    let query_model = QueryModel::new()?;
+    let budget = prompt::PromptBudget::for_model(query_model.model());
+    let max_steps = agent::max_steps();
+    let scope = AgentScope {
+        pool: &pool,
+        user_id,
+        session_id,
+        project_id,
+        embedding_model: &embedding_model,
+        tokenizer: budget.tokenizer,
+        // Half of the input budget is shared by the results of all steps
+        result_tokens: (budget.input_tokens() / 2 / max_steps).max(AGENT_MIN_RESULT_TOKENS),
+    };
+
+    let mut state = AgentState::default();
+    let mut answer = None;
+    let mut prompt_template = String::new();
+    for step_number in 1..=max_steps {
+        // The last step has to answer, tools are not offered since they could not run anymore
+        let tools = if step_number == max_steps { json!([]) } else { AgentTool::descriptions() };
+        let step_prompt = prompt::construct_agent_step_prompt(
+            &payload.content,
+            &chat_history,
+            &document_focus,
+            tools,
+            &state.transcript,
+            max_steps - step_number + 1,
+            &budget,
+        )?;
+        prompt_template = step_prompt.template.clone();
+        let step: LlmAgentStep = charged_structured_query(&pool, user_id, AiOperation::AgentStep, &query_model, &step_prompt).await?;
+
+        // Validated by rag::structured, so anything but an answer is a known tool
+        let Some(tool) = AgentTool::parse(&step.action) else {
+            answer = step.answer;
+            break;
+        };
+        if step_number == max_steps {
+            println!("->> {:<12} - Session {} ran out of steps", "AGENT", session_id);
+            break;
+        }
+        agent::run_tool(&scope, &mut state, step_number as i32, tool, &step).await?;
+    }
+
+    let content = answer.unwrap_or_else(|| format!(
+        "I could not finish this within {} steps. Try asking about fewer documents or a more specific question.",
+        max_steps
+    ));
+    let message_id = embed_and_store_exchange(
+        &embedding_model,
+        &pool,
+        session_id,
+        parent_message_id,
+        Some(&user_message),
+        AssistantAnswer { content: &content, citations: &[], prompt_template: &prompt_template }
+    ).await?;
+    agent::link_tool_calls(&pool, &mut state, message_id).await?;
+    summary::spawn_summary_update(pool.clone(), session_id);
+    title::spawn_title_generation(pool.clone(), session_id);
+
+    Ok(Json(AgentResponse {
+        message_id,
+        role: "assistant".to_string(),
+        content,
+        proposed_changes: state.proposed_changes(),
+        tool_calls: state.tool_calls,
+        prompt_template,
+    }))
+}
+
+/// GET handler for the tool calls of a writing session.
+/// Accessible via: GET /api/writing-assistant/:id/tool-calls
+/// Test: test_ai.rs/test_agent_writing_message_success()
+/// Frontend: ai.ts/get_tool_calls()
+/// Returns every tool the assistant called in agent mode, oldest first, with the answer it led to.
+pub async fn api_get_tool_calls(
+    cookies: Cookies,
+    Path(session_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<AgentToolCall>>> {
+    println!("->> {:<12} - get_tool_calls for session {}", "HANDLER", session_id);
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    fetch_owned_session(&pool, user_id, session_id).await?;
+
+    Ok(Json(agent::session_tool_calls(&pool, session_id).await?))
+}
+
+/// POST handler for generating another answer to a user message.
+/// Accessible via: POST /api/writing-assistant/:id/messages/:message_id/regenerate
+/// Test: test_ai.rs/test_regenerate_writing_message_success()
+/// Frontend: ai.ts/regenerate_writing_message()
+/// `message_id` is the assistant answer to replace. The new answer is stored next to it (same parent),
+/// so the old one stays available as another branch. The new branch becomes the active one.
+pub async fn api_regenerate_writing_message(
+    cookies: Cookies,
+    Path((session_id, message_id)): Path<(i32, i32)>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - regenerate_writing_message {} in session {}", "HANDLER", message_id, session_id);
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    let answer = fetch_session_message(&pool, user_id, session_id, message_id).await?;
+    if answer.role != MessageRole::Assistant {
+        return Err(Error::InvalidRequestFormatError);
+    }
+
+    // The question the answer belongs to
+    let question_id = answer.parent_message_id.ok_or(Error::InvalidRequestFormatError)?;
+    let question = fetch_session_message(&pool, user_id, session_id, question_id).await?;
+    if question.role != MessageRole::User {
+        return Err(Error::InvalidRequestFormatError);
+    }
+
+    let turn = UserTurn::Regenerate { user_message_id: question.id, content: question.content };
+    let prepared = prepare_writing_message(&pool, user_id, session_id, turn).await?;
+
+    Ok(Json(answer_writing_message(&pool, user_id, session_id, prepared).await?))
+}
+
+/// POST handler for editing a user message and sending it again.
+/// Accessible via: POST /api/writing-assistant/:id/messages/:message_id/edit
+/// Test: test_ai.rs/test_edit_writing_message_success(), test_ai.rs/test_failed_edit_keeps_branch()
+/// Frontend: ai.ts/edit_writing_message()
+/// The edited message is stored next to the original (same parent) and answered, the original
+/// and everything after it stay available as another branch. The new branch becomes the active one.
+pub async fn api_edit_writing_message(
+    cookies: Cookies,
+    Path((session_id, message_id)): Path<(i32, i32)>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<EditMessagePayload>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - edit_writing_message {} in session {}", "HANDLER", message_id, session_id);
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    let original = fetch_session_message(&pool, user_id, session_id, message_id).await?;
+    if original.role != MessageRole::User || payload.content.trim().is_empty() {
+        return Err(Error::InvalidRequestFormatError);
+    }
+
+    let turn = UserTurn::Edit { parent_message_id: original.parent_message_id, content: &payload.content };
+    let prepared = prepare_writing_message(&pool, user_id, session_id, turn).await?;
+
+    Ok(Json(answer_writing_message(&pool, user_id, session_id, prepared).await?))
+}
+
+/// GET handler for listing the branches of a writing session.
+/// Accessible via: GET /api/writing-assistant/:id/branches
+/// Test: test_ai.rs/test_get_writing_branches_success()
+/// Frontend: ai.ts/get_writing_branches()
+/// Every branch ends in a message without replies. `fork_message_id` is where a branch leaves the
+/// active branch, e.g. the alternative answer or edited question. Newest branches first.
+pub async fn api_get_writing_branches(
+    cookies: Cookies,
+    Path(session_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<ChatBranch>>> {
+    println!("->> {:<12} - get_writing_branches for session {}", "HANDLER", session_id);
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    fetch_owned_session(&pool, user_id, session_id).await?;
+
+    let messages = retrieval::retrieve_session_messages(&pool, session_id).await?;
+    let active_ids: Vec<i32> = retrieval::retrieve_active_branch(&pool, session_id).await?
+        .iter()
+        .map(|message| message.id)
+        .collect();
+    let active_leaf = active_ids.last().copied();
+    let parents: HashMap<i32, Option<i32>> = messages.iter().map(|message| (message.id, message.parent_message_id)).collect();
+
+    let mut branches: Vec<ChatBranch> = messages
+        .iter()
+        .filter(|leaf| !messages.iter().any(|message| message.parent_message_id == Some(leaf.id)))
+        .map(|leaf| {
+            // Walk up to the first message, the last one not on the active branch is the fork
+            let mut fork_message_id = None;
+            let mut message_count = 0;
+            let mut current = Some(leaf.id);
+            while let Some(id) = current {
+                message_count += 1;
+                if !active_ids.contains(&id) {
+                    fork_message_id = Some(id);
+                }
+                current = parents.get(&id).copied().flatten();
+            }
+
+            let max_len = 30;
+            let last_message_snippet = if leaf.content.chars().count() > max_len {
+                format!("{}...", leaf.content.chars().take(max_len).collect::<String>())
+            } else {
+                leaf.content.clone()
+            };
+
+            ChatBranch {
+                leaf_message_id: leaf.id,
+                fork_message_id,
+                message_count,
+                last_message_snippet,
+                updated_at: leaf.created_at,
+                is_active: Some(leaf.id) == active_leaf,
+            }
+        })
+        .collect();
+    branches.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(b.leaf_message_id.cmp(&a.leaf_message_id)));
+
+    Ok(Json(branches))
+}
+
+/// PUT handler for switching the branch of a writing session.
+/// Accessible via: PUT /api/writing-assistant/:id/branches/:message_id
+/// Test: test_ai.rs/test_switch_writing_branch_success()
+/// Frontend: ai.ts/switch_writing_branch()
+/// Switches to the branch through `message_id`, continuing on its newest replies, and returns
+/// the session with the messages of that branch. New messages are added to it.
+pub async fn api_switch_writing_branch(
+    cookies: Cookies,
+    Path((session_id, message_id)): Path<(i32, i32)>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<SessionWithMessages>> {
+    println!("->> {:<12} - switch_writing_branch to {} in session {}", "HANDLER", message_id, session_id);
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    fetch_session_message(&pool, user_id, session_id, message_id).await?;
+
+    let messages = retrieval::retrieve_session_messages(&pool, session_id).await?;
+    let leaf_id = retrieval::latest_leaf(&messages, message_id);
+    set_active_message(&pool, session_id, Some(leaf_id)).await?;
+
+    let session = fetch_owned_session(&pool, user_id, session_id).await?;
+    Ok(Json(SessionWithMessages {
+        session,
+        messages: retrieval::branch_path(messages, leaf_id),
+    }))
+}
+
+/// GET handler for listing the assistant personas available to the user.
+/// Accessible via: GET /api/writing-assistant/personas
+/// Test: test_ai.rs/test_get_personas_success()
+/// Frontend: ai.ts/get_personas()
+/// Returns the built-in personas (no user_id) followed by the user's own.
+pub async fn api_get_personas(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<Persona>>> {
+    println!("->> {:<12} - get_personas", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    Ok(Json(PersonaManager::list(&pool, user_id).await?))
+}
+
+/// GET handler for the completion and embedding models answering the user, as "provider/model".
+/// Accessible via: GET /api/writing-assistant/providers
+/// Test: test_providers.rs/test_provider_selection()
+/// Frontend: ai.ts/get_providers()
+/// The providers are selected with LLM_PROVIDER and EMBEDDING_PROVIDER, see rag::provider.
+pub async fn api_get_providers(cookies: Cookies) -> Result<Json<Value>> {
+    println!("->> {:<12} - get_providers", "HANDLER");
+
+    get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    Ok(Json(json!({
+        "completion": QueryModel::new()?.model_name(),
+        "embedding": EmbeddingModel::new()?.model_name(),
+    })))
+}
+
+/// POST handler for creating a custom assistant persona.
+/// Accessible via: POST /api/writing-assistant/personas
+/// Test: test_ai.rs/test_create_persona_success()
+/// Frontend: ai.ts/create_persona()
+/// Custom personas are only visible to the user who created them.
+pub async fn api_create_persona(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<CreatePersonaPayload>,
+) -> Result<Json<Persona>> {
+    println!("->> {:<12} - create_persona", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    Ok(Json(PersonaManager::create(&pool, user_id, payload).await?))
+}
+
+/// DELETE handler for removing a custom assistant persona.
+/// Accessible via: DELETE /api/writing-assistant/personas/:persona_id
+/// Test: test_ai.rs/test_delete_persona_success()
+/// Frontend: ai.ts/delete_persona()
+/// Sessions and projects using the persona go back to the default. Built-in personas cannot be deleted.
+pub async fn api_delete_persona(
+    cookies: Cookies,
+    Path(persona_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - delete_persona {}", "HANDLER", persona_id);
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    PersonaManager::delete(&pool, user_id, persona_id).await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "message": "Persona deleted successfully"
+    })))
+}
+
+/// PUT handler for setting the persona of a writing session.
+/// Accessible via: PUT /api/writing-assistant/:id/persona
+/// Test: test_ai.rs/test_set_session_persona_success()
+/// Frontend: ai.ts/set_session_persona()
+/// Takes a `persona_id` and/or a custom `system_prompt` (which wins). Both null goes back to the
+/// project's persona or the default. Applies to the next answer, earlier answers are kept.
+pub async fn api_set_session_persona(
+    cookies: Cookies,
+    Path(session_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<PersonaSettings>,
+) -> Result<Json<WritingAssistantSession>> {
+    println!("->> {:<12} - set_session_persona for session {}", "HANDLER", session_id);
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    fetch_owned_session(&pool, user_id, session_id).await?;
+
+    let settings = payload.normalized()?;
+    if let Some(persona_id) = settings.persona_id {
+        PersonaManager::ensure_usable(&pool, user_id, persona_id).await?;
+    }
+
+    sqlx::query!(
+        "UPDATE writing_assistant_sessions SET persona_id = $2, system_prompt = $3, updated_at = $4 WHERE id = $1",
+        session_id,
+        settings.persona_id,
+        settings.system_prompt,
+        Utc::now().naive_utc()
+    )
+    .execute(&pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    Ok(Json(fetch_owned_session(&pool, user_id, session_id).await?))
+}
+
+// A session of the user, PermissionError if it does not exist or belongs to someone else
+async fn fetch_owned_session(pool: &PgPool, user_id: i32, session_id: i32) -> Result<WritingAssistantSession> {
+    sqlx::query_as!(
+        WritingAssistantSession,
+        r#"
+        SELECT id, user_id, document_id, title, created_at, updated_at, active_message_id, persona_id, system_prompt, auto_title
+        FROM writing_assistant_sessions
+        WHERE id = $1 AND user_id = $2
+        "#,
+        session_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?
+    .ok_or(Error::PermissionError)
+}
+
+// A message of one of the user's sessions
+async fn fetch_session_message(pool: &PgPool, user_id: i32, session_id: i32, message_id: i32) -> Result<WritingAssistantMessage> {
+    fetch_owned_session(pool, user_id, session_id).await?;
+    sqlx::query_as!(
+        WritingAssistantMessage,
+        r#"
+        SELECT 
+            id, 
+            session_id, 
+            parent_message_id,
+            role AS "role: MessageRole",
+            content, 
+            created_at,
+            citations AS "citations: SqlJson<Vec<Citation>>",
+            prompt_template
+        FROM writing_assistant_messages
+        WHERE id = $1 AND session_id = $2
+        "#,
+        message_id,
+        session_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?
+    .ok_or(Error::MessageNotFoundError { message_id })
+}
+
+// Moves a session to another branch, new messages are added below `message_id`
+async fn set_active_message(pool: &PgPool, session_id: i32, message_id: Option<i32>) -> Result<()> {
+    sqlx::query!(
+        "UPDATE writing_assistant_sessions SET active_message_id = $2, updated_at = $3 WHERE id = $1",
+        session_id,
+        message_id,
+        Utc::now().naive_utc()
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    Ok(())
+}
+
+// Queries the LLM for a prepared chat turn, stores the answer and builds the response
+async fn answer_writing_message(pool: &PgPool, user_id: i32, session_id: i32, prepared: PreparedMessage) -> Result<Value> {
+    // --- Query LLM --- 
+    println!("->> {:<12} - Querying LLM", "RAG FUNCTION");
+    let llm_response_raw = charged_query(pool, user_id, AiOperation::Chat, &prepared.query_model, &prepared.prompt).await?;
+    println!("->> {:<12} - LLM response received: \"{}...\"", "RAG FUNCTION", llm_response_raw.chars().take(70).collect::<String>());
+
+    // Keep only citations of sources that were actually in the prompt
+    let (llm_response_content, citations) = extract_citations(&llm_response_raw, &prepared.citation_sources);
+    println!("->> {:<12} - Answer cites {} of {} sources", "CITATIONS", citations.len(), prepared.citation_sources.len());
+
+    // --- Embed and Store Assistant Response --- 
+    println!("->> {:<12} - Assistant response content: \"{}\"", "RAG FUNCTION", llm_response_content);
+    let message_id = embed_and_store_exchange(
+        &prepared.embedding_model,
+        pool,
+        session_id,
+        prepared.parent_message_id,
+        prepared.user_message.as_ref(),
+        AssistantAnswer { content: &llm_response_content, citations: &citations, prompt_template: &prepared.prompt.template }
+    ).await?;
+    summary::spawn_summary_update(pool.clone(), session_id);
+    title::spawn_title_generation(pool.clone(), session_id);
+
+    // --- Return Response --- 
+    println!("->> {:<12} - Sending response", "RAG FUNCTION");
+    let response_json = json!({
+        "message_id": message_id,
+        "role": "assistant",
+        "content": llm_response_content,
+        "sources": prepared.sources,
+        "citations": citations,
+        "prompt_template": prepared.prompt.template
+    });
+    println!("->> {:<12} - Response JSON: {:?}", "RES_MAPPER", response_json);
+    Ok(response_json)
+}
+
+// The user message a chat turn answers
+enum UserTurn<'a> {
+    /// A new message below the session's active message
+    New(&'a str),
+    /// A new version of an earlier user message, stored as a sibling of it (a new branch)
+    Edit { parent_message_id: Option<i32>, content: &'a str },
+    /// Another answer to an existing user message, nothing new is stored for the user
+    Regenerate { user_message_id: i32, content: String },
+}
+
+// Everything needed to answer a chat message, built before the LLM is queried.
+// Nothing is stored yet: the user message is stored together with the answer.
+struct PreparedMessage {
+    // Where the turn goes, the user message or (regenerating) the answer is added below it
+    parent_message_id: Option<i32>,
+    // None when an existing user message is answered again
+    user_message: Option<PendingUserMessage>,
+    prompt: RenderedPrompt,
+    // Documents placed in the prompt, deduplicated
+    sources: Vec<Value>,
+    // Labelled chunks the answer may cite
+    citation_sources: Vec<Citation>,
+    query_model: QueryModel,
+    embedding_model: EmbeddingModel,
+}
 
-    // Check and decrement credits before proceeding
-    check_and_decrement_ai_credits(&pool, user_id).await?;
+// Checks the user's balance, embeds their message, retrieves context and builds the prompt
+async fn prepare_writing_message(
+    pool: &PgPool,
+    user_id: i32,
+    session_id: i32,
+    turn: UserTurn<'_>,
+) -> Result<PreparedMessage> {
+    // The message is charged once the prompt is known, but preparing it is not free either
+    CreditLedger::ensure_balance(pool, user_id, AiOperation::Chat).await?;
 
     let session = sqlx::query_as!(
         WritingAssistantSession,
         r#"
-        SELECT id, user_id, document_id, title, created_at, updated_at
+        SELECT id, user_id, document_id, title, created_at, updated_at, active_message_id, persona_id, system_prompt, auto_title
         FROM writing_assistant_sessions
         WHERE id = $1 AND user_id = $2
         "#,
         session_id,
         user_id
     )
-    .fetch_one(&pool)
+    .fetch_one(pool)
     .await
     .map_err(|_| Error::PermissionError)?;
 
+    // Edits and regenerations continue from an earlier point of the conversation. The session only
+    // moves there once the answer is stored, a failed answer leaves it on its current branch.
+    let (content, parent_message_id, is_new_message) = match turn {
+        UserTurn::New(content) => (content.to_string(), retrieval::active_leaf(pool, session_id).await?, true),
+        UserTurn::Edit { parent_message_id, content } => (content.to_string(), parent_message_id, true),
+        UserTurn::Regenerate { user_message_id, content } => (content, Some(user_message_id), false),
+    };
+
     println!("->> {:<12} - Embedding user message", "RAG FUNCTION");
+    println!("->> {:<12} - User message content: \"{}\"", "RAG FUNCTION", content);
     let embedding_model = EmbeddingModel::new()?;
-    // Create the message struct to pass
-    let user_message_to_store = WritingAssistantMessage {
-        id: 0, // Placeholder
-        session_id,
-        role: MessageRole::User,
-        content: payload.content.clone(), // Clone content from payload
-        created_at: Utc::now().naive_utc(), // Placeholder, actual time set during INSERT
-    };
-    println!("->> {:<12} - User message content: \"{}\"", "RAG FUNCTION", payload.content);
-    let user_embedding: Vector = embed_and_store_user_message(
-        &embedding_model,
-        &pool,
-        session_id,
-        &user_message_to_store
-    ).await?;
+    let user_embedding: Vector = embedding_model.embed_document(&content).await?;
     // Log a snippet of the embedding for verification
     println!("->> {:<12} - User embedding calculated (first 5 dims): {:?}", "RAG FUNCTION", user_embedding.as_slice().iter().take(5).collect::<Vec<_>>());
 
@@ -298,49 +950,68 @@ pub async fn api_send_writing_message(
         Utc::now().naive_utc(),
         session_id
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .map_err(|_| Error::DatabaseError)?;
 
     // Retrieve chat history using the dedicated function
     println!("->> {:<12} - Retrieving chat history", "RAG FUNCTION");
-    let chat_history = retrieval::retrieve_chat_history(&pool, session_id).await?;
+    let mut chat_history = retrieval::retrieve_chat_history(pool, session_id, parent_message_id).await?;
+    if is_new_message {
+        chat_history.add_user_message(content.clone());
+    }
     println!("->> {:<12} - Retrieved {} messages from history", "RETRIEVAL", chat_history.messages.len());
+    if chat_history.summary.is_some() {
+        // Parts of the summarized conversation that matter for this message
+        chat_history.recalled = retrieval::retrieve_earlier_messages(pool, session_id, parent_message_id, &user_embedding, RECALLED_MESSAGES_K).await?;
+    }
     
     // Determine Project ID and Current Document Name for context retrieval
     let mut project_id_for_context: Option<i32> = None;
     let mut current_doc_name: Option<String> = None;
     let mut current_doc_id: Option<i32> = None;
     
-    if let Some(doc_id) = session.document_id {
+    // Only use the linked document as context if the user can still read it
+    let linked_doc_id = match session.document_id {
+        Some(doc_id) if check_document_permission(pool, user_id, doc_id, "viewer").await? => Some(doc_id),
+        Some(doc_id) => {
+            println!("->> {:<12} - User {} lost access to linked document {}, ignoring it", "RAG FUNCTION", user_id, doc_id);
+            None
+        }
+        None => None,
+    };
+
+    if let Some(doc_id) = linked_doc_id {
         current_doc_id = Some(doc_id);
         // Fetch project ID and document name if document is linked
         let doc_info = sqlx::query!(
             r#"
-            SELECT dp.project_id, d.name 
+            SELECT dp.project_id AS "project_id?", d.name 
             FROM documents d 
             LEFT JOIN document_projects dp ON d.id = dp.document_id 
             WHERE d.id = $1
             "#,
             doc_id
         )
-        .fetch_optional(&pool)
+        .fetch_optional(pool)
         .await
         .map_err(|_| Error::DatabaseError)?;
 
         if let Some(info) = doc_info {
-            project_id_for_context = Some(info.project_id); // Wrap in Some() to match Option<i32>
+            project_id_for_context = info.project_id; // None for documents outside of a project
             current_doc_name = Some(info.name); // Store the name
         }
     }
 
     // Always retrieve relevant document chunks using semantic search
     println!("->> {:<12} - Retrieving relevant context via semantic search", "RAG FUNCTION");
-    let k_value = 3;
+    // Chunks are much smaller than whole documents, so more of them fit in the context budget
+    let k_value = 6;
     println!("->> {:<12} - Retrieving relevant chunks (k={}) for project_id: {:?}", "RETRIEVAL", k_value, project_id_for_context);
     
     let mut relevant_chunks = retrieval::semantic_search(
-        &pool, 
+        pool, 
+        user_id,
         project_id_for_context,
         &user_embedding,
         k_value
@@ -350,9 +1021,9 @@ pub async fn api_send_writing_message(
     
     // Use construct_context_decision_prompt to determine if we need additional context
     println!("->> {:<12} - Determining context needs", "CONTEXT DECISION");
-    let decision_prompt = construct_context_decision_prompt(&payload.content);
+    let decision_prompt = construct_context_decision_prompt(&content)?;
     let query_model = QueryModel::new()?;
-    let context_decision = query_model.query_model(&decision_prompt).await?;
+    let context_decision = charged_query(pool, user_id, AiOperation::ContextDecision, &query_model, &decision_prompt).await?;
     let context_decision = context_decision.trim().to_lowercase();
     
     println!("->> {:<12} - Context decision: '{}'", "CONTEXT DECISION", context_decision);
@@ -371,18 +1042,14 @@ pub async fn api_send_writing_message(
                     "#,
                     doc_id
                 )
-                .fetch_optional(&pool)
+                .fetch_optional(pool)
                 .await
                 .map_err(|_| Error::DatabaseError)?;
                 
                 if let Some(doc) = document {
                     if let Some(content) = doc.content {
                         let doc_name = doc.name;
-                        let doc_chunk = retrieval::RetrievedChunk {
-                            document_id: doc_id,
-                            document_name: doc_name,
-                            content,
-                        };
+                        let doc_chunk = retrieval::RetrievedChunk::whole_document(doc_id, doc_name, content);
                         println!("->> {:<12} - Added current document context: {} ({})", "CONTEXT ADDITION", doc_chunk.document_name, doc_chunk.content.len());
                         relevant_chunks.push(doc_chunk);
                     }
@@ -399,18 +1066,24 @@ pub async fn api_send_writing_message(
                     content: Option<String>,
                 }
                 
-                // Fetch all documents in the project
+                // Fetch all documents in the project that the user can read
                 let project_docs = sqlx::query_as!(DocumentContent,
                     r#"
-                    SELECT id, name, content 
-                    FROM documents 
-                    WHERE id IN (SELECT document_id FROM document_projects WHERE project_id = $1)
-                      AND is_trashed = false
-                    ORDER BY name ASC
+                    SELECT d.id, d.name, d.content 
+                    FROM documents d
+                    WHERE d.id IN (SELECT document_id FROM document_projects WHERE project_id = $1)
+                      AND d.is_trashed = false
+                      AND (
+                          d.user_id = $2
+                          OR EXISTS (SELECT 1 FROM document_permissions perm WHERE perm.document_id = d.id AND perm.user_id = $2)
+                          OR EXISTS (SELECT 1 FROM project_permissions pp WHERE pp.project_id = $1 AND pp.user_id = $2)
+                      )
+                    ORDER BY d.name ASC
                     "#,
-                    project_id
+                    project_id,
+                    user_id
                 )
-                .fetch_all(&pool)
+                .fetch_all(pool)
                 .await
                 .map_err(|_| Error::DatabaseError)?;
                 
@@ -423,11 +1096,7 @@ pub async fn api_send_writing_message(
                         // Only add non-empty content
                         if !content.is_empty() {
                             let doc_name = doc.name.unwrap_or_else(|| "Untitled".to_string());
-                            let doc_chunk = retrieval::RetrievedChunk {
-                                document_id: doc.id,
-                                document_name: doc_name,
-                                content,
-                            };
+                            let doc_chunk = retrieval::RetrievedChunk::whole_document(doc.id, doc_name, content);
                             println!("->> {:<12} - Added project document: {} ({})", "CONTEXT ADDITION", doc_chunk.document_name, doc_chunk.content.len());
                             relevant_chunks.push(doc_chunk);
                         }
@@ -456,37 +1125,36 @@ pub async fn api_send_writing_message(
 
     // --- Construct Prompt --- 
     println!("->> {:<12} - Constructing prompt", "RAG FUNCTION");
-    let final_prompt = prompt::construct_generic_prompt(
-        &payload.content, 
+    // Budget follows the window of the model that will answer
+    let budget = prompt::PromptBudget::for_model(query_model.model());
+    let (final_prompt, citation_sources) = prompt::construct_generic_prompt(
+        &content, 
         &chat_history, 
         &relevant_chunks, // Pass the Vec<RetrievedChunk> that now includes both semantic search results and any additional context
-        session.document_id, // Pass current doc ID
-        current_doc_name.as_deref() // Pass current doc name as &str
-    );
-    // Log prompt snippet and estimated tokens (simple space split estimate)
-    let estimated_tokens = final_prompt.split_whitespace().count();
-    println!("->> {:<12} - Prompt constructed ({} tokens estimated):\n---\n{}\n---", "PROMPT", estimated_tokens, final_prompt);
-
-    // --- Query LLM --- 
-    println!("->> {:<12} - Querying LLM", "RAG FUNCTION");
-    // Query model was already initialized above
-    let llm_response_content = query_model.query_model(&final_prompt).await?;
-    println!("->> {:<12} - LLM response received: \"{}...\"", "RAG FUNCTION", llm_response_content.chars().take(70).collect::<String>());
-
-    // --- Embed and Store Assistant Response --- 
-    println!("->> {:<12} - Assistant response content: \"{}\"", "RAG FUNCTION", llm_response_content);
-    embed_and_store_assistant_message(
-        &embedding_model,
-        &pool,
-        session_id,
-        &llm_response_content // Pass LLM response content
-    ).await?;
+        current_doc_id, // Pass current doc ID
+        current_doc_name.as_deref(), // Pass current doc name as &str
+        &budget
+    )?;
+    println!("->> {:<12} - Prompt constructed ({} tokens):\n---\n{}\n---", "PROMPT", budget.count(&final_prompt.text), final_prompt.text);
+
+    // Report which documents were placed in the prompt so clients can show sources
+    let mut sources: Vec<Value> = Vec::new();
+    for source in &citation_sources {
+        if !sources.iter().any(|s| s["document_id"] == source.document_id) {
+            sources.push(json!({ "document_id": source.document_id, "document_name": source.document_name }));
+        }
+    }
 
-    // --- Return Response --- 
-    println!("->> {:<12} - Sending response", "RAG FUNCTION");
-    let response_json = json!({ "role": "assistant", "content": llm_response_content });
-    println!("->> {:<12} - Response JSON: {:?}", "RES_MAPPER", response_json);
-    Ok(Json(response_json))
+    let user_message = is_new_message.then(|| PendingUserMessage { content: content.clone(), embedding: user_embedding });
+    Ok(PreparedMessage {
+        parent_message_id,
+        user_message,
+        prompt: final_prompt,
+        sources,
+        citation_sources,
+        query_model,
+        embedding_model,
+    })
 }
 
 /// DELETE handler for removing a writing session and all its messages.
@@ -541,14 +1209,11 @@ pub async fn api_check_grammer(
 ) -> Result<Json<Value>> {
     println!("->> {:<12} - api_check_grammer", "HANDLER");
 
-    // Get user_id and check/decrement credits
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
-    check_and_decrement_ai_credits(&pool, user_id).await?;
 
-    let prompt = prompt::construct_grammar_check_prompt(&payload.content);
+    let prompt = prompt::construct_grammar_check_prompt(&payload.content)?;
     
-    let query_model = QueryModel::new()?;
-    let response = query_model.query_model(&prompt).await?;
+    let response = query_with_credits(&pool, user_id, AiOperation::Grammar, &prompt).await?;
 
     Ok(Json(json!({ "response": response })))
 }
@@ -564,12 +1229,10 @@ pub async fn api_summarize(
 ) -> Result<Json<Value>> {
     println!("->> {:<12} - api_summarize", "HANDLER");
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
-    check_and_decrement_ai_credits(&pool, user_id).await?;
 
-    let prompt = prompt::construct_summarize_prompt(&payload.content);
+    let prompt = prompt::construct_summarize_prompt(&payload.content)?;
     
-    let query_model = QueryModel::new()?;
-    let response = query_model.query_model(&prompt).await?;
+    let response = query_with_credits(&pool, user_id, AiOperation::Summarize, &prompt).await?;
 
     Ok(Json(json!({ "response": response })))
 }
@@ -585,12 +1248,10 @@ pub async fn api_rephrase(
 ) -> Result<Json<Value>> {
     println!("->> {:<12} - api_rephrase", "HANDLER");
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
-    check_and_decrement_ai_credits(&pool, user_id).await?;
 
-    let prompt = prompt::construct_rephrase_prompt(&payload.content);
+    let prompt = prompt::construct_rephrase_prompt(&payload.content)?;
     
-    let query_model = QueryModel::new()?;
-    let response = query_model.query_model(&prompt).await?;
+    let response = query_with_credits(&pool, user_id, AiOperation::Rephrase, &prompt).await?;
 
     Ok(Json(json!({ "response": response })))
 }
@@ -606,12 +1267,10 @@ pub async fn api_expand(
 ) -> Result<Json<Value>> {
     println!("->> {:<12} - api_expand", "HANDLER");
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
-    check_and_decrement_ai_credits(&pool, user_id).await?;
 
-    let prompt = prompt::construct_expand_prompt(&payload.content);
+    let prompt = prompt::construct_expand_prompt(&payload.content)?;
     
-    let query_model = QueryModel::new()?;
-    let response = query_model.query_model(&prompt).await?;
+    let response = query_with_credits(&pool, user_id, AiOperation::Expand, &prompt).await?;
 
     Ok(Json(json!({ "response": response })))
 }
@@ -627,12 +1286,10 @@ pub async fn api_shrink(
 ) -> Result<Json<Value>> {
     println!("->> {:<12} - api_shrink", "HANDLER");
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
-    check_and_decrement_ai_credits(&pool, user_id).await?;
 
-    let prompt = prompt::construct_shrink_prompt(&payload.content);
+    let prompt = prompt::construct_shrink_prompt(&payload.content)?;
     
-    let query_model = QueryModel::new()?;
-    let response = query_model.query_model(&prompt).await?;
+    let response = query_with_credits(&pool, user_id, AiOperation::Shrink, &prompt).await?;
 
     Ok(Json(json!({ "response": response })))
 }
@@ -648,12 +1305,10 @@ pub async fn api_rewrite(
 ) -> Result<Json<Value>> {
     println!("->> {:<12} - api_rewrite", "HANDLER");
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
-    check_and_decrement_ai_credits(&pool, user_id).await?;
 
-    let prompt = prompt::construct_rewrite_prompt(&payload.content, &payload.style);
+    let prompt = prompt::construct_rewrite_prompt(&payload.content, &payload.style)?;
     
-    let query_model = QueryModel::new()?;
-    let response = query_model.query_model(&prompt).await?;
+    let response = query_with_credits(&pool, user_id, AiOperation::Rewrite, &prompt).await?;
 
     Ok(Json(json!({ "response": response })))
 }
@@ -669,16 +1324,83 @@ pub async fn api_fact_check(
 ) -> Result<Json<Value>> {
     println!("->> {:<12} - api_fact_check", "HANDLER");
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
-    check_and_decrement_ai_credits(&pool, user_id).await?;
 
-    let prompt = prompt::construct_fact_check_prompt(&payload.content);
+    let prompt = prompt::construct_fact_check_prompt(&payload.content)?;
     
-    let query_model = QueryModel::new()?;
-    let response = query_model.query_model(&prompt).await?;
+    let response = query_with_credits(&pool, user_id, AiOperation::FactCheck, &prompt).await?;
 
     Ok(Json(json!({ "response": response })))
 }
 
+/// POST handler for fact checking some text against the user's own sources instead of the model's knowledge
+/// Accessible via: POST /api/writing-assistant/factcheck/evidence
+/// Test: test_fact_check.rs/test_evidence_fact_check()
+/// Frontend: ai.ts/fact_check_with_sources()
+/// The sources are the documents of `project_id` and the `reference_document_ids`, without the
+/// `document_id` the text comes from. Every claim of the text gets a verdict (supported,
+/// contradicted or unknown) with the cited passages, see rag::fact_check. Both model steps are charged.
+pub async fn api_fact_check_evidence(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<EvidenceFactCheckPayload>,
+) -> Result<Json<EvidenceFactCheck>> {
+    println!("->> {:<12} - api_fact_check_evidence", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let content = payload.content.trim();
+    let mut references = payload.reference_document_ids.clone().unwrap_or_default();
+    references.sort_unstable();
+    references.dedup();
+    if content.is_empty() || content.chars().count() > MAX_FACT_CHECK_CHARS || (payload.project_id.is_none() && references.is_empty()) {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    if let Some(project_id) = payload.project_id {
+        if !check_project_permission(&pool, user_id, project_id, "viewer").await? {
+            return Err(Error::PermissionError);
+        }
+    }
+    for &document_id in &references {
+        if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+            return Err(Error::PermissionError);
+        }
+    }
+    CreditLedger::ensure_balance(&pool, user_id, AiOperation::EvidenceCheck).await?;
+
+    let query_model = QueryModel::new()?;
+    let budget = prompt::PromptBudget::for_model(query_model.model());
+    let claims_prompt = prompt::construct_fact_check_claims_prompt(content, &budget)?;
+    let LlmClaims { mut claims } =
+        charged_structured_query(&pool, user_id, AiOperation::EvidenceCheck, &query_model, &claims_prompt).await?;
+    claims.truncate(MAX_CLAIMS);
+    if claims.is_empty() {
+        return Ok(Json(EvidenceFactCheck { claims: Vec::new(), sources: Vec::new() }));
+    }
+
+    // The passages most similar to every claim
+    let embedding_model = EmbeddingModel::new()?;
+    let claim_texts: Vec<String> = claims.iter().map(|claim| claim.claim.trim().to_string()).collect();
+    let embeddings = embedding_model.embed_documents(&claim_texts).await?;
+    let mut per_claim = Vec::with_capacity(embeddings.len());
+    for embedding in &embeddings {
+        per_claim.push(
+            retrieval::search_sources(&pool, user_id, payload.project_id, &references, payload.document_id, embedding, EVIDENCE_PER_CLAIM).await?,
+        );
+    }
+    let (passages, claim_labels) = fact_check::label_passages(per_claim);
+
+    // Without passages every claim is unknown, there is nothing to ask the model
+    let answer = if passages.is_empty() {
+        None
+    } else {
+        let evidence_prompt = prompt::construct_fact_check_evidence_prompt(&claims, &claim_labels, &passages, &budget)?;
+        Some(charged_structured_query::<LlmClaimVerdicts>(&pool, user_id, AiOperation::EvidenceCheck, &query_model, &evidence_prompt).await?)
+    };
+
+    let checked = fact_check::checked_claims(&payload.content, claims, answer, &passages);
+    println!("->> {:<12} - Checked {} claims against {} passages for user {}", "FACT_CHECK", checked.len(), passages.len(), user_id);
+    Ok(Json(EvidenceFactCheck { claims: checked, sources: passages }))
+}
+
 /// POST handler for spell checking some text or a document
 /// Accessible via: POST /api/writing-assistant/spellcheck
 /// Test: test_ai.rs/test_spell_check_success()
@@ -690,74 +1412,867 @@ pub async fn api_spell_check(
 ) -> Result<Json<Value>> {
     println!("->> {:<12} - api_spell_check", "HANDLER");
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
-    check_and_decrement_ai_credits(&pool, user_id).await?;
 
-    let prompt = prompt::construct_spell_check_prompt(&payload.content);
+    let prompt = prompt::construct_spell_check_prompt(&payload.content)?;
     
-    let query_model = QueryModel::new()?;
-    let response = query_model.query_model(&prompt).await?;
+    let response = query_with_credits(&pool, user_id, AiOperation::SpellCheck, &prompt).await?;
 
     Ok(Json(json!({ "response": response })))
 }
 
-/// Helper function to check and decrement AI credits
-async fn check_and_decrement_ai_credits(pool: &PgPool, user_id: i32) -> Result<()> {
-    // Fetch current credits
-    let user_credits = sqlx::query!(
-        "SELECT ai_credits FROM users WHERE id = $1",
-        user_id
+/// POST handler for streaming a grammar check for some text or a document
+/// Accessible via: POST /api/writing-assistant/grammer/stream
+/// Test: test_ai.rs/test_stream_quick_actions_success()
+pub async fn api_check_grammer_stream(
+    cookies: Cookies,
+    pool: Extension<PgPool>,
+    Json(payload): Json<SelectedTextContext>,
+) -> Result<Sse<EventStream>> {
+    println!("->> {:<12} - api_check_grammer_stream", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let prompt = prompt::construct_grammar_check_prompt(&payload.content)?;
+    stream_quick_action(&pool, user_id, AiOperation::Grammar, &prompt).await
+}
+
+/// POST handler for streaming a spell check of some text or a document
+/// Accessible via: POST /api/writing-assistant/spellcheck/stream
+/// Test: test_ai.rs/test_stream_quick_actions_success()
+pub async fn api_spell_check_stream(
+    cookies: Cookies,
+    pool: Extension<PgPool>,
+    Json(payload): Json<SelectedTextContext>,
+) -> Result<Sse<EventStream>> {
+    println!("->> {:<12} - api_spell_check_stream", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let prompt = prompt::construct_spell_check_prompt(&payload.content)?;
+    stream_quick_action(&pool, user_id, AiOperation::SpellCheck, &prompt).await
+}
+
+/// POST handler for streaming a summary of some text or a document
+/// Accessible via: POST /api/writing-assistant/summarize/stream
+/// Test: test_ai.rs/test_stream_quick_actions_success()
+pub async fn api_summarize_stream(
+    cookies: Cookies,
+    pool: Extension<PgPool>,
+    Json(payload): Json<SelectedTextContext>,
+) -> Result<Sse<EventStream>> {
+    println!("->> {:<12} - api_summarize_stream", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let prompt = prompt::construct_summarize_prompt(&payload.content)?;
+    stream_quick_action(&pool, user_id, AiOperation::Summarize, &prompt).await
+}
+
+/// POST handler for streaming a rephrasing of some text or a document
+/// Accessible via: POST /api/writing-assistant/rephrase/stream
+/// Test: test_ai.rs/test_stream_quick_actions_success()
+pub async fn api_rephrase_stream(
+    cookies: Cookies,
+    pool: Extension<PgPool>,
+    Json(payload): Json<SelectedTextContext>,
+) -> Result<Sse<EventStream>> {
+    println!("->> {:<12} - api_rephrase_stream", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let prompt = prompt::construct_rephrase_prompt(&payload.content)?;
+    stream_quick_action(&pool, user_id, AiOperation::Rephrase, &prompt).await
+}
+
+/// POST handler for streaming an expansion of some text or a document
+/// Accessible via: POST /api/writing-assistant/expand/stream
+/// Test: test_ai.rs/test_stream_quick_actions_success()
+pub async fn api_expand_stream(
+    cookies: Cookies,
+    pool: Extension<PgPool>,
+    Json(payload): Json<SelectedTextContext>,
+) -> Result<Sse<EventStream>> {
+    println!("->> {:<12} - api_expand_stream", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let prompt = prompt::construct_expand_prompt(&payload.content)?;
+    stream_quick_action(&pool, user_id, AiOperation::Expand, &prompt).await
+}
+
+/// POST handler for streaming a shortened version of some text or a document
+/// Accessible via: POST /api/writing-assistant/shrink/stream
+/// Test: test_ai.rs/test_stream_quick_actions_success()
+pub async fn api_shrink_stream(
+    cookies: Cookies,
+    pool: Extension<PgPool>,
+    Json(payload): Json<SelectedTextContext>,
+) -> Result<Sse<EventStream>> {
+    println!("->> {:<12} - api_shrink_stream", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let prompt = prompt::construct_shrink_prompt(&payload.content)?;
+    stream_quick_action(&pool, user_id, AiOperation::Shrink, &prompt).await
+}
+
+/// POST handler for streaming a rewrite of some text or a document in a new style
+/// Accessible via: POST /api/writing-assistant/rewrite/stream
+/// Test: test_ai.rs/test_stream_quick_actions_success()
+pub async fn api_rewrite_stream(
+    cookies: Cookies,
+    pool: Extension<PgPool>,
+    Json(payload): Json<RewritePayload>,
+) -> Result<Sse<EventStream>> {
+    println!("->> {:<12} - api_rewrite_stream", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let prompt = prompt::construct_rewrite_prompt(&payload.content, &payload.style)?;
+    stream_quick_action(&pool, user_id, AiOperation::Rewrite, &prompt).await
+}
+
+/// POST handler for streaming a fact check of some text or a document
+/// Accessible via: POST /api/writing-assistant/factcheck/stream
+/// Test: test_ai.rs/test_stream_quick_actions_success()
+pub async fn api_fact_check_stream(
+    cookies: Cookies,
+    pool: Extension<PgPool>,
+    Json(payload): Json<SelectedTextContext>,
+) -> Result<Sse<EventStream>> {
+    println!("->> {:<12} - api_fact_check_stream", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let prompt = prompt::construct_fact_check_prompt(&payload.content)?;
+    stream_quick_action(&pool, user_id, AiOperation::FactCheck, &prompt).await
+}
+
+/// POST handler for translating some text
+/// The glossary of `project_id`, or of the projects of `document_id`, is enforced.
+/// Accessible via: POST /api/writing-assistant/translate
+/// Test: test_translation.rs/test_translate_selection()
+/// Frontend: translation.ts/translate_text()
+pub async fn api_translate(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<TranslateTextPayload>,
+) -> Result<Json<TranslationResult>> {
+    println!("->> {:<12} - api_translate", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let (target_language, source_language) = parse_languages(&payload.target_language, payload.source_language.as_deref())?;
+    if payload.content.trim().is_empty() {
+        return Err(Error::InvalidRequestFormatError);
+    }
+
+    let terms = match (payload.project_id, payload.document_id) {
+        (Some(project_id), _) => {
+            if !check_project_permission(&pool, user_id, project_id, "viewer").await? {
+                return Err(Error::PermissionError);
+            }
+            Glossary::list(&pool, project_id).await?
+        }
+        (None, Some(document_id)) => {
+            if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+                return Err(Error::PermissionError);
+            }
+            Glossary::for_document(&pool, document_id).await?
+        }
+        (None, None) => Vec::new(),
+    };
+    let terms = translate::applicable_terms(&terms, &target_language);
+
+    let query_model = QueryModel::new()?;
+    let (translation, glossary_violations) =
+        translate_content(&pool, user_id, &query_model, &payload.content, source_language.as_deref(), &target_language, &terms).await?;
+
+    Ok(Json(TranslationResult { translation, target_language, glossary_violations }))
+}
+
+/// POST handler for translating a document into a new document linked to it
+/// An existing translation into the language is updated instead, which is how stale translations are refreshed.
+/// The new document is added to the projects of the source the user can edit.
+/// Accessible via: POST /api/writing-assistant/translate/document/:id
+/// Test: test_translation.rs/test_translate_document()
+/// Frontend: translation.ts/translate_document()
+pub async fn api_translate_document(
+    cookies: Cookies,
+    Path(document_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<TranslateDocumentPayload>,
+) -> Result<Json<DocumentTranslationResult>> {
+    println!("->> {:<12} - api_translate_document", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let (target_language, source_language) = parse_languages(&payload.target_language, payload.source_language.as_deref())?;
+    if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+    CreditLedger::ensure_balance(&pool, user_id, AiOperation::Translate).await?;
+
+    let query_model = QueryModel::new()?;
+    let result = translate_document(&pool, user_id, &query_model, document_id, source_language.as_deref(), &target_language).await?;
+    Ok(Json(result))
+}
+
+/// POST handler for translating every document of a project
+/// Documents whose translation is up to date and documents that are translations themselves are skipped.
+/// Documents the user cannot read are reported as skipped, documents whose translation fails as failures.
+/// Accessible via: POST /api/writing-assistant/translate/project/:id
+/// Test: test_translation.rs/test_translate_project()
+/// Frontend: translation.ts/translate_project()
+pub async fn api_translate_project(
+    cookies: Cookies,
+    Path(project_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<TranslateDocumentPayload>,
+) -> Result<Json<ProjectTranslationResult>> {
+    println!("->> {:<12} - api_translate_project", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let (target_language, source_language) = parse_languages(&payload.target_language, payload.source_language.as_deref())?;
+    if !check_project_permission(&pool, user_id, project_id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+    CreditLedger::ensure_balance(&pool, user_id, AiOperation::Translate).await?;
+
+    // The documents of the project and whether the user can read them
+    let query = format!(
+        "SELECT d.id, {} AS readable FROM documents d \
+         JOIN document_projects dp ON dp.document_id = d.id \
+         WHERE dp.project_id = $2 AND NOT COALESCE(d.is_trashed, FALSE) \
+           AND NOT EXISTS (SELECT 1 FROM document_translations t WHERE t.document_id = d.id) \
+         ORDER BY d.id",
+        retrieval::DOCUMENT_ACCESS_FILTER
+    );
+    let documents: Vec<(i32, bool)> = sqlx::query_as(&query)
+        .bind(user_id)
+        .bind(project_id)
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("DB Error fetching project documents for translation: {:?}", e);
+            Error::DatabaseError
+        })?;
+
+    // Every document is translated on its own, one failure does not discard the others
+    let query_model = QueryModel::new()?;
+    let mut result = ProjectTranslationResult {
+        project_id,
+        target_language: target_language.clone(),
+        documents: Vec::new(),
+        up_to_date: Vec::new(),
+        skipped: Vec::new(),
+        failures: Vec::new(),
+    };
+    for (document_id, readable) in documents {
+        if !readable {
+            result.skipped.push(document_id);
+            continue;
+        }
+        if !TranslationManager::needs_translation(&pool, document_id, &target_language).await? {
+            result.up_to_date.push(document_id);
+            continue;
+        }
+        match translate_document(&pool, user_id, &query_model, document_id, source_language.as_deref(), &target_language).await {
+            Ok(translated) => result.documents.push(translated),
+            Err(e) => {
+                println!("->> {:<12} - Translating document {} failed: {:?}", "TRANSLATE", document_id, e);
+                let (_, client_error) = e.client_status_and_error();
+                result.failures.push(DocumentTranslationFailure { document_id, reason: client_error.as_ref().to_string() });
+            }
+        }
+    }
+
+    Ok(Json(result))
+}
+
+/// POST handler for finding the characters, places and terms of a project's documents with the model
+/// New entities are added to the knowledge base as extracted, known ones get new aliases and a missing description.
+/// Every analyzed document is charged.
+/// Accessible via: POST /api/writing-assistant/knowledge/extract/:id
+/// Test: test_knowledge.rs/test_extract_entities()
+/// Frontend: knowledge.ts/extract_entities()
+pub async fn api_extract_entities(
+    cookies: Cookies,
+    Path(project_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<ExtractEntitiesPayload>,
+) -> Result<Json<ExtractionResult>> {
+    println!("->> {:<12} - api_extract_entities", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if !check_project_permission(&pool, user_id, project_id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+    CreditLedger::ensure_balance(&pool, user_id, AiOperation::ExtractEntities).await?;
+
+    let project_name = sqlx::query_scalar!("SELECT name FROM projects WHERE id = $1", project_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::ProjectNotFoundError { project_id })?;
+    let documents = sqlx::query!(
+        r#"
+        SELECT d.id, d.name, d.content
+        FROM documents d
+        JOIN document_projects dp ON dp.document_id = d.id
+        WHERE dp.project_id = $1 AND NOT COALESCE(d.is_trashed, FALSE) AND ($2::INT[] IS NULL OR d.id = ANY($2))
+        ORDER BY d.id
+        "#,
+        project_id,
+        payload.document_ids.as_deref()
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    if documents.is_empty() {
+        return Err(Error::InvalidRequestFormatError);
+    }
+
+    let query_model = QueryModel::new()?;
+    let budget = prompt::PromptBudget::for_model(query_model.model());
+    let mut analyzed = Vec::new();
+    let mut created: Vec<i32> = Vec::new();
+    let mut updated: Vec<i32> = Vec::new();
+    for document in documents {
+        let content = document.content.unwrap_or_default();
+        if content.trim().is_empty() {
+            continue;
+        }
+        // Entities found in earlier documents are known to the later ones
+        let known = KnowledgeManager::list(&pool, project_id, None).await?;
+        let extract_prompt = prompt::construct_knowledge_extract_prompt(&project_name, &document.name, &content, &known, &budget)?;
+        let answer: LlmExtractedEntities =
+            charged_structured_query(&pool, user_id, AiOperation::ExtractEntities, &query_model, &extract_prompt).await?;
+        println!("->> {:<12} - {} entities found in document {}", "KNOWLEDGE", answer.entities.len(), document.id);
+
+        let (new_ids, changed_ids) = KnowledgeManager::merge_extracted(&pool, project_id, answer.entities).await?;
+        created.extend(new_ids);
+        for entity_id in changed_ids {
+            if !created.contains(&entity_id) && !updated.contains(&entity_id) {
+                updated.push(entity_id);
+            }
+        }
+        analyzed.push(document.id);
+    }
+
+    Ok(Json(ExtractionResult {
+        project_id,
+        documents: analyzed,
+        created: KnowledgeManager::entities(&pool, project_id, &created).await?,
+        updated: KnowledgeManager::entities(&pool, project_id, &updated).await?,
+    }))
+}
+
+/// POST handler for starting a consistency check of every document of a project.
+/// The check runs in the background, the report is returned pending and filled in as the documents
+/// are checked, see GET /api/project/:id/consistency/:report_id. With AI every document is charged.
+/// Accessible via: POST /api/writing-assistant/consistency/:id
+/// Test: test_consistency.rs/test_consistency_check(), test_consistency.rs/test_invalid_consistency_check()
+/// Frontend: consistency.ts/start_consistency_check()
+pub async fn api_start_consistency_check(
+    cookies: Cookies,
+    Path(project_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<StartConsistencyCheckPayload>,
+) -> Result<Json<ConsistencyReport>> {
+    println!("->> {:<12} - api_start_consistency_check", "HANDLER");
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    // The report and its findings are stored on the project
+    if !check_project_permission(&pool, user_id, project_id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+    let use_ai = payload.use_ai.unwrap_or(true);
+    if use_ai {
+        CreditLedger::ensure_balance(&pool, user_id, AiOperation::ConsistencyCheck).await?;
+    }
+
+    let documents_total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM documents d
+        JOIN document_projects dp ON dp.document_id = d.id
+        WHERE dp.project_id = $1 AND NOT COALESCE(d.is_trashed, FALSE)
+        "#,
+        project_id
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+    if documents_total == 0 {
+        return Err(Error::InvalidRequestFormatError);
+    }
+
+    let report = ConsistencyManager::create(&pool, project_id, user_id, use_ai, documents_total as i32).await?;
+    spawn_consistency_check(pool.clone(), user_id, project_id, report.id, use_ai);
+    Ok(Json(report))
+}
+
+/// Runs a consistency check on the tokio runtime, a check that stops with an error fails its report
+fn spawn_consistency_check(pool: PgPool, user_id: i32, project_id: i32, report_id: i32, use_ai: bool) {
+    tokio::spawn(async move {
+        if let Err(e) = run_consistency_check(&pool, user_id, project_id, report_id, use_ai).await {
+            eprintln!("->> {:<12} - Consistency check {} failed: {:?}", "CONSISTENCY", report_id, e);
+            if let Err(e) = ConsistencyManager::fail(&pool, report_id, &format!("{:?}", e)).await {
+                eprintln!("->> {:<12} - Failed to fail consistency check {}: {:?}", "CONSISTENCY", report_id, e);
+            }
+        }
+    });
+}
+
+/// Checks the documents of a project one by one, see rag::consistency.
+/// The findings of every document are stored as soon as it is checked, so the report shows progress.
+async fn run_consistency_check(pool: &PgPool, user_id: i32, project_id: i32, report_id: i32, use_ai: bool) -> Result<()> {
+    ConsistencyManager::start(pool, report_id).await?;
+
+    let project_name = sqlx::query_scalar!("SELECT name FROM projects WHERE id = $1", project_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?
+        .ok_or(Error::ProjectNotFoundError { project_id })?;
+    let documents = ConsistencyManager::documents(pool, project_id).await?;
+    let entities = KnowledgeManager::list(pool, project_id, None).await?;
+    let style_guide = KnowledgeManager::style_guide(pool, project_id).await?;
+    let glossary = Glossary::list(pool, project_id).await?;
+    // Loading a dictionary is CPU bound
+    let dictionary = tokio::task::spawn_blocking(|| proofread::dictionary(&proofread::default_language()))
+        .await
+        .unwrap_or(None);
+
+    let profile = ProjectProfile::build(&documents, &entities, style_guide.as_deref(), &glossary, dictionary.as_deref());
+    let knowledge = render_knowledge(&project_name, style_guide.as_deref(), &entities, &HashSet::new());
+    let query_model = if use_ai { Some(QueryModel::new()?) } else { None };
+    let mut timeline: Vec<TimelineEvent> = Vec::new();
+
+    for (index, document) in documents.iter().enumerate() {
+        let mut findings = profile.check_document(document);
+
+        let checked_by_model = document.translation_of.is_none() && !document.content.trim().is_empty();
+        if let Some(query_model) = query_model.as_ref().filter(|_| checked_by_model) {
+            let budget = prompt::PromptBudget::for_model(query_model.model());
+            let check_prompt = prompt::construct_consistency_check_prompt(
+                &project_name, &document.name, &document.content, knowledge.as_deref(), &timeline, &budget,
+            )?;
+            let answer: LlmConsistencyCheck =
+                charged_structured_query(pool, user_id, AiOperation::ConsistencyCheck, query_model, &check_prompt).await?;
+
+            findings.extend(consistency::ai_findings(document, answer.findings, &entities, &documents[..index]));
+            timeline.extend(answer.events.into_iter().take(consistency::MAX_EVENTS_PER_DOCUMENT).map(|event| TimelineEvent {
+                document_name: document.name.clone(),
+                event: event.trim().to_string(),
+            }));
+            findings.sort_by_key(|finding| finding.start_offset.unwrap_or(-1));
+        }
+
+        let stored = ConsistencyManager::record_document(pool, report_id, &findings).await?;
+        println!("->> {:<12} - Check {}: {} findings in document {}", "CONSISTENCY", report_id, stored, document.id);
+    }
+
+    ConsistencyManager::finish(pool, report_id).await
+}
+
+// Normalized target and source language codes, InvalidRequestFormatError if one is not a code
+fn parse_languages(target_language: &str, source_language: Option<&str>) -> Result<(String, Option<String>)> {
+    let target_language = translate::normalize_language(target_language).ok_or(Error::InvalidRequestFormatError)?;
+    let source_language = match source_language.map(str::trim).filter(|language| !language.is_empty()) {
+        Some(language) => Some(translate::normalize_language(language).ok_or(Error::InvalidRequestFormatError)?),
+        None => None,
+    };
+    Ok((target_language, source_language))
+}
+
+/// Helper function to translate content segment by segment, enforcing the glossary, see rag::translate.
+/// Every segment and every repair is charged. Returns the translation and the rules it still breaks.
+async fn translate_content(
+    pool: &PgPool,
+    user_id: i32,
+    query_model: &QueryModel,
+    content: &str,
+    source_language: Option<&str>,
+    target_language: &str,
+    terms: &[GlossaryTerm],
+) -> Result<(String, Vec<GlossaryViolation>)> {
+    let mut translation = String::with_capacity(content.len());
+    let mut violations: Vec<GlossaryViolation> = Vec::new();
+
+    for segment in translate::split_segments(content) {
+        if segment.trim().is_empty() {
+            translation.push_str(&segment);
+            continue;
+        }
+        let prompt = translate::translation_prompt(&segment, source_language, target_language, terms)?;
+        let raw = charged_query(pool, user_id, AiOperation::Translate, query_model, &prompt).await?;
+        let mut output = translate::clean_translation(&segment, &raw);
+        let mut problems = translate::check_glossary(&segment, &output, terms);
+
+        for attempt in 1..=translate::max_repairs() {
+            if problems.is_empty() {
+                break;
+            }
+            println!("->> {:<12} - Translation breaks {} glossary rule(s), repair {}", "TRANSLATE", problems.len(), attempt);
+            let repair = translate::repair_prompt(&prompt, &output, &problems)?;
+            let raw = charged_query(pool, user_id, AiOperation::Translate, query_model, &repair).await?;
+            output = translate::clean_translation(&segment, &raw);
+            problems = translate::check_glossary(&segment, &output, terms);
+        }
+
+        // One entry per term over all segments
+        for problem in problems {
+            match violations.iter_mut().find(|violation| violation.term == problem.term) {
+                Some(violation) => {
+                    violation.source_occurrences += problem.source_occurrences;
+                    violation.translation_occurrences += problem.translation_occurrences;
+                }
+                None => violations.push(problem),
+            }
+        }
+        translation.push_str(&output);
+    }
+
+    Ok((translation, violations))
+}
+
+/// Helper function to translate a document into its linked translation, creating the document if there is none
+async fn translate_document(
+    pool: &PgPool,
+    user_id: i32,
+    query_model: &QueryModel,
+    source_document_id: i32,
+    source_language: Option<&str>,
+    target_language: &str,
+) -> Result<DocumentTranslationResult> {
+    let source = sqlx::query!(
+        r#"SELECT name, content, md5(COALESCE(content, '')) AS "content_hash!" FROM documents WHERE id = $1"#,
+        source_document_id
     )
     .fetch_optional(pool)
     .await
     .map_err(|_| Error::DatabaseError)?
-    .ok_or(Error::UserNotFoundError { user_id })?
-    .ai_credits;
+    .ok_or(Error::DocumentNotFoundError { document_id: source_document_id })?;
+
+    let terms = translate::applicable_terms(&Glossary::for_document(pool, source_document_id).await?, target_language);
+    let (content, glossary_violations) =
+        translate_content(pool, user_id, query_model, source.content.as_deref().unwrap_or(""), source_language, target_language, &terms).await?;
+
+    // Update the existing translation if the user may edit it, otherwise create one
+    let existing = match TranslationManager::find_translation(pool, source_document_id, target_language).await? {
+        Some(document_id) if check_document_permission(pool, user_id, document_id, "editor").await? => Some(document_id),
+        _ => None,
+    };
+    let (document_id, created) = match existing {
+        Some(document_id) => {
+            update_translated_document(pool, document_id, &content).await?;
+            (document_id, false)
+        }
+        None => {
+            let name = format!("{} ({})", source.name, target_language);
+            (create_translated_document(pool, user_id, source_document_id, &name, &content).await?, true)
+        }
+    };
+    TranslationManager::link(pool, document_id, source_document_id, target_language, &source.content_hash).await?;
 
-    // Check if credits are sufficient
-    if user_credits <= 0 {
-        println!("->> {:<12} - User {} has insufficient AI credits ({})", "CREDIT_CHECK", user_id, user_credits);
-        return Err(Error::InsufficientAiCredits);
+    if let Err(e) = enqueue_document_embedding(pool, document_id).await {
+        println!("->> {:<12} - Failed to queue embedding for document {}: {:?}", "ERROR", document_id, e);
     }
+    spawn_stats_refresh(pool.clone(), document_id);
+    spawn_mentions_refresh(pool.clone(), document_id);
+    println!("->> {:<12} - Document {} translated into {} as document {}", "TRANSLATE", source_document_id, target_language, document_id);
+
+    Ok(DocumentTranslationResult {
+        document_id,
+        source_document_id,
+        target_language: target_language.to_string(),
+        created,
+        glossary_violations,
+    })
+}
+
+/// Helper function to create the document of a new translation within the plan limits of the user.
+/// It is added to the projects of the source the user can edit.
+async fn create_translated_document(pool: &PgPool, user_id: i32, source_document_id: i32, name: &str, content: &str) -> Result<i32> {
+    let limits = PlanManager::get_user_limits(pool, user_id).await?;
+    let owned = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!", COALESCE(SUM(LENGTH(COALESCE(d.content, ''))), 0)::BIGINT AS "total_bytes!"
+           FROM documents d
+           JOIN document_permissions dp ON d.id = dp.document_id
+           WHERE dp.user_id = $1 AND dp.role = 'owner'"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    if owned.count as i32 >= limits.max_documents {
+        return Err(Error::LimitExceededError { message: "Document limit reached".to_string() });
+    }
+    if owned.total_bytes + content.len() as i64 > limits.storage_quota_bytes {
+        return Err(Error::LimitExceededError {
+            message: format!("Storage limit of {}MB exceeded", limits.storage_quota_bytes / 1024 / 1024),
+        });
+    }
+
+    let now = Utc::now().naive_utc();
+    let document_id = sqlx::query_scalar!(
+        "INSERT INTO documents (name, content, user_id, created_at, updated_at) VALUES ($1, $2, $3, $4, $4) RETURNING id",
+        name,
+        content,
+        user_id,
+        now
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|_| Error::DocumentCreationError)?;
 
-    // Decrement credits
-    let update_result = sqlx::query!(
-        "UPDATE users SET ai_credits = ai_credits - 1 WHERE id = $1 AND ai_credits > 0",
+    sqlx::query!(
+        "INSERT INTO document_permissions (document_id, user_id, role) VALUES ($1, $2, 'owner')",
+        document_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| Error::PermissionCreationError)?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO document_projects (document_id, project_id)
+        SELECT $1, dp.project_id
+        FROM document_projects dp
+        JOIN project_permissions pp ON pp.project_id = dp.project_id
+        WHERE dp.document_id = $2 AND pp.user_id = $3 AND pp.role IN ('editor', 'owner')
+        ON CONFLICT DO NOTHING
+        "#,
+        document_id,
+        source_document_id,
         user_id
     )
     .execute(pool)
     .await
     .map_err(|_| Error::DatabaseError)?;
 
-    // Check if the update actually happened (in case of race condition where credits became 0 between check and update)
-    if update_result.rows_affected() == 0 {
-        println!("->> {:<12} - Failed to decrement credits for user {} (possible race condition or already 0)", "CREDIT_CHECK", user_id);
-        return Err(Error::InsufficientAiCredits); // Treat as insufficient if update failed
+    Ok(document_id)
+}
+
+/// Helper function to replace the content of an existing translation within the storage quota of its owner
+async fn update_translated_document(pool: &PgPool, document_id: i32, content: &str) -> Result<()> {
+    let owner = sqlx::query!(
+        r#"SELECT dp.user_id, LENGTH(COALESCE(d.content, ''))::BIGINT AS "current_bytes!"
+           FROM document_permissions dp
+           JOIN documents d ON d.id = dp.document_id
+           WHERE dp.document_id = $1 AND dp.role = 'owner'"#,
+        document_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?;
+
+    let growth = content.len() as i64 - owner.current_bytes;
+    if growth > 0 {
+        let limits = PlanManager::get_user_limits(pool, owner.user_id).await?;
+        let used = sqlx::query_scalar!(
+            r#"SELECT COALESCE(SUM(LENGTH(COALESCE(d.content, ''))), 0)::BIGINT AS "total_bytes!"
+               FROM documents d
+               JOIN document_permissions dp ON d.id = dp.document_id
+               WHERE dp.user_id = $1 AND dp.role = 'owner'"#,
+            owner.user_id
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|_| Error::DatabaseError)?;
+        if used + growth > limits.storage_quota_bytes {
+            return Err(Error::LimitExceededError {
+                message: format!("Storage limit of {}MB exceeded", limits.storage_quota_bytes / 1024 / 1024),
+            });
+        }
     }
 
-    println!("->> {:<12} - Decremented AI credits for user {}. Remaining: {}", "CREDIT_CHECK", user_id, user_credits - 1);
+    sqlx::query!(
+        "UPDATE documents SET content = $1, updated_at = $2 WHERE id = $3",
+        content,
+        Utc::now().naive_utc(),
+        document_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| Error::DocumentUpdateError { document_id })?;
     Ok(())
 }
 
+/// Helper function to query the LLM on behalf of a user with a fresh query model, see `charged_query`
+async fn query_with_credits(pool: &PgPool, user_id: i32, operation: AiOperation, prompt: &RenderedPrompt) -> Result<String> {
+    let query_model = QueryModel::new()?;
+    charged_query(pool, user_id, operation, &query_model, prompt).await
+}
+
+// Server-sent event stream returned by the streaming endpoints
+type EventStream = BoxStream<'static, std::result::Result<Event, Infallible>>;
+
+fn sse_event(name: &str, data: Value) -> Event {
+    Event::default().event(name).data(data.to_string())
+}
+
+// Same body as the error responses built by the response mapper
+fn sse_error_event(error: &Error) -> Event {
+    let (_, client_error) = error.client_status_and_error();
+    sse_event("error", json!({ "error": { "type": client_error.as_ref() } }))
+}
+
+/// Helper function to stream a quick action, `done` carries {"response": ...}
+async fn stream_quick_action(pool: &PgPool, user_id: i32, operation: AiOperation, prompt: &RenderedPrompt) -> Result<Sse<EventStream>> {
+    let query_model = QueryModel::new()?;
+    stream_completion(pool.clone(), user_id, operation, query_model, prompt, Vec::new(), |response| async move {
+        Ok(json!({ "response": response }))
+    }).await
+}
+
+/// Helper function to charge a user and stream a completion to the client as server-sent events.
+/// `first_events` are sent before any token, then every text delta as a `token` event.
+/// Once the upstream finishes `on_complete` receives the full text and its result is sent as `done`.
+/// The upstream is read by a spawned task which drops it as soon as the client disconnects,
+/// closing the upstream connection and skipping `on_complete`, so nothing is stored.
+/// The charge is refunded if the upstream fails or `on_complete` does; a disconnect is not refunded,
+/// the tokens generated until then are recorded with the charge.
+async fn stream_completion<F, Fut>(
+    pool: PgPool,
+    user_id: i32,
+    operation: AiOperation,
+    query_model: QueryModel,
+    prompt: &RenderedPrompt,
+    first_events: Vec<Event>,
+    on_complete: F,
+) -> Result<Sse<EventStream>>
+where
+    F: FnOnce(String) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Value>> + Send + 'static,
+{
+    let tokenizer = Tokenizer::for_model(query_model.model());
+    let charge = CreditLedger::charge(&pool, user_id, operation, tokenizer.count(&prompt.text), &prompt.template).await?;
+
+    // Start the upstream before responding, so provider failures are still plain HTTP errors
+    let mut upstream = match query_model.stream_model(prompt).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            CreditLedger::refund(&pool, &charge, "LLM stream failed to start").await?;
+            return Err(e);
+        }
+    };
+    let (tx, rx) = mpsc::channel::<Event>(32);
+
+    tokio::spawn(async move {
+        for event in first_events {
+            if tx.send(event).await.is_err() {
+                println!("->> {:<12} - Client disconnected, cancelling upstream completion", "STREAM");
+                let _ = CreditLedger::record_completion(&pool, &charge, 0).await;
+                return;
+            }
+        }
+
+        let mut content = String::new();
+        loop {
+            tokio::select! {
+                _ = tx.closed() => {
+                    println!("->> {:<12} - Client disconnected, cancelling upstream completion", "STREAM");
+                    let _ = CreditLedger::record_completion(&pool, &charge, tokenizer.count(&content)).await;
+                    return;
+                }
+                next = upstream.next() => match next {
+                    Some(Ok(token)) => {
+                        content.push_str(&token);
+                        if tx.send(sse_event("token", json!({ "content": token }))).await.is_err() {
+                            println!("->> {:<12} - Client disconnected, cancelling upstream completion", "STREAM");
+                            let _ = CreditLedger::record_completion(&pool, &charge, tokenizer.count(&content)).await;
+                            return;
+                        }
+                    }
+                    Some(Err(e)) => {
+                        eprintln!("->> {:<12} - Upstream completion failed: {:?}", "STREAM", e);
+                        if let Err(refund_error) = CreditLedger::refund(&pool, &charge, "LLM stream failed").await {
+                            eprintln!("->> {:<12} - Refund failed: {:?}", "STREAM", refund_error);
+                        }
+                        let _ = tx.send(sse_error_event(&e)).await;
+                        return;
+                    }
+                    None => break,
+                }
+            }
+        }
+        // The upstream is finished, release its connection before storing the result
+        drop(upstream);
+        if let Err(e) = CreditLedger::record_completion(&pool, &charge, tokenizer.count(&content)).await {
+            eprintln!("->> {:<12} - Failed to record completion tokens: {:?}", "CREDITS", e);
+        }
+
+        match on_complete(content).await {
+            Ok(data) => {
+                let _ = tx.send(sse_event("done", data)).await;
+            }
+            Err(e) => {
+                eprintln!("->> {:<12} - Failed to finish streamed completion: {:?}", "STREAM", e);
+                if let Err(refund_error) = CreditLedger::refund(&pool, &charge, "Storing the streamed result failed").await {
+                    eprintln!("->> {:<12} - Refund failed: {:?}", "STREAM", refund_error);
+                }
+                let _ = tx.send(sse_error_event(&e)).await;
+            }
+        }
+    });
+
+    let events = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (Ok(event), rx))
+    })
+    .boxed();
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Orders project documents for an apply-suggestion prompt that cannot hold the whole project:
+/// the active document first, then the documents whose chunks best match the suggestion.
+/// Documents without a matching chunk are left out.
+async fn rank_documents_for_suggestion(
+    pool: &PgPool,
+    user_id: i32,
+    project_id: i32,
+    suggestion: &str,
+    active_document_id: Option<i32>,
+    project_docs: Vec<(i32, String, String)>,
+) -> Result<Vec<(i32, String, String)>> {
+    let embedding_model = EmbeddingModel::new()?;
+    let suggestion_embedding = embedding_model.embed_document(suggestion).await?;
+    let chunks = retrieval::semantic_search(pool, user_id, Some(project_id), &suggestion_embedding, APPLY_SUGGESTION_RETRIEVAL_K).await?;
+
+    let mut ranked_ids: Vec<i32> = active_document_id.into_iter().collect();
+    for chunk in &chunks {
+        if !ranked_ids.contains(&chunk.document_id) {
+            ranked_ids.push(chunk.document_id);
+        }
+    }
+
+    let mut docs_by_id: HashMap<i32, (i32, String, String)> = project_docs.into_iter().map(|doc| (doc.0, doc)).collect();
+    let ranked: Vec<(i32, String, String)> = ranked_ids.iter().filter_map(|id| docs_by_id.remove(id)).collect();
+    println!("->> {:<12} - Ranked {} relevant documents for project {}", "RETRIEVAL", ranked.len(), project_id);
+    Ok(ranked)
+}
+
 /// POST handler for applying an AI suggestion to project documents.
 /// Accessible via: POST /api/ai/writing-assistant/:id/apply-suggestion
-/// Test: TODO
+/// Test: test_ai.rs/test_apply_suggestion_success()
+/// Frontend: ai.ts/apply_ai_suggestion()
+/// The model answers with schema-validated search/replace edits (see rag::structured), which are
+/// applied to the original documents on the server (see rag::patch). Projects too large for the
+/// model's window only send the active document and the documents most relevant to the suggestion.
+/// Returns {"changes": [...], "failures": [{"document_id", "reason"}], "considered_document_ids": [...]}:
+/// edits to documents outside the prompt, ones the user cannot edit, or edits that do not apply
+/// are reported as failures instead of failing the request.
 pub async fn api_apply_suggestion(
     cookies: Cookies,
     Path(session_id): Path<i32>,
     Extension(pool): Extension<PgPool>,
     Json(payload): Json<ApplySuggestionPayload>,
-) -> Result<Json<Vec<SuggestedDocumentChange>>> {
+) -> Result<Json<ApplySuggestionResult>> {
     println!("->> {:<12} - api_apply_suggestion for session {}", "HANDLER", session_id);
 
     let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
 
-    // Check and decrement credits before proceeding
-    check_and_decrement_ai_credits(&pool, user_id).await?;
+    // Fail early, the actual price depends on the size of the project
+    CreditLedger::ensure_balance(&pool, user_id, AiOperation::ApplySuggestion).await?;
 
     // 1. Fetch session to verify ownership and get linked document ID
     let session = sqlx::query_as!(
         WritingAssistantSession,
-        "SELECT id, user_id, document_id, title, created_at, updated_at FROM writing_assistant_sessions WHERE id = $1 AND user_id = $2",
+        "SELECT id, user_id, document_id, title, created_at, updated_at, active_message_id, persona_id, system_prompt, auto_title FROM writing_assistant_sessions WHERE id = $1 AND user_id = $2",
         session_id,
         user_id
     )
@@ -790,19 +2305,30 @@ pub async fn api_apply_suggestion(
     };
     println!("->> {:<12} - Found project_id {} for apply suggestion.", "HANDLER", project_id);
 
-    // 4. Fetch original content of all documents in the project
-    struct OriginalDoc { id: i32, name: Option<String>, content: Option<String> }
-    let original_docs = sqlx::query_as!(OriginalDoc,
-        r#"
-        SELECT id, name, content FROM documents 
-        WHERE id IN (SELECT document_id FROM document_projects WHERE project_id = $1)
-        AND is_trashed = false
-        "#,
-        project_id
-    )
-    .fetch_all(&pool)
-    .await
-    .map_err(|_| Error::DatabaseError)?;
+    // The active document is edited in place, the user has to be able to read it
+    if let Some(document_id) = payload.current_document_id {
+        if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+            return Err(Error::PermissionError);
+        }
+    }
+
+    // 4. Fetch original content of the documents in the project the user can read
+    let query = format!(
+        "SELECT d.id, d.name, d.content FROM documents d \
+         WHERE d.id IN (SELECT document_id FROM document_projects WHERE project_id = $2) \
+           AND d.is_trashed = false \
+           AND {}",
+        retrieval::DOCUMENT_ACCESS_FILTER
+    );
+    let original_docs: Vec<(i32, Option<String>, Option<String>)> = sqlx::query_as(&query)
+        .bind(user_id)
+        .bind(project_id)
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("DB Error fetching project documents for apply suggestion: {:?}", e);
+            Error::DatabaseError
+        })?;
 
     if original_docs.is_empty() {
          println!("->> {:<12} - Apply suggestion failed: Project {} has no documents.", "HANDLER", project_id);
@@ -811,77 +2337,111 @@ pub async fn api_apply_suggestion(
     println!("->> {:<12} - Fetched {} original documents for project {}.", "HANDLER", original_docs.len(), project_id);
 
     // Prepare data for prompt (id, name, content)
-    let prompt_docs: Vec<(i32, String, String)> = original_docs
-        .iter()
-        .map(|doc| (
-            doc.id,
-            doc.name.clone().unwrap_or_else(|| "Untitled".to_string()),
-            doc.content.clone().unwrap_or_default()
-        ))
-        .collect();
-
-    // Store original content mapped by ID for later diff generation
-    let original_content_map: HashMap<i32, String> = original_docs
+    let project_docs: Vec<(i32, String, String)> = original_docs
         .into_iter() // Consume original_docs here
-        .map(|doc| (doc.id, doc.content.unwrap_or_default()))
+        .map(|(id, name, content)| (
+            id,
+            name.unwrap_or_else(|| "Untitled".to_string()),
+            content.unwrap_or_default()
+        ))
         .collect();
 
-
-    // 5. Construct the prompt
-    let final_prompt = prompt::construct_apply_suggestion_prompt(
-        &prompt_docs,
+    // 5. Construct the prompt, with the whole project if it fits the model's window
+    let query_model = QueryModel::new()?;
+    let budget = prompt::PromptBudget::for_model(query_model.model());
+    let mut final_prompt = prompt::construct_apply_suggestion_prompt(
+        &project_docs,
         &payload.suggestion_content,
         payload.current_document_id
-    ).map_err(|e| {
-        eprintln!("Error serializing documents for prompt: {:?}", e);
-        Error::FailedApplyChanges
-    })?; // Handle potential serialization error
+    )?;
+    let mut prompt_docs = project_docs;
+
+    if budget.count(&final_prompt.text) > budget.input_tokens() {
+        // Too large: send the active document and the documents most relevant to the suggestion
+        println!("->> {:<12} - Project {} exceeds the prompt budget, retrieving relevant documents.", "HANDLER", project_id);
+        let ranked_docs = rank_documents_for_suggestion(&pool, user_id, project_id, &payload.suggestion_content, payload.current_document_id, prompt_docs).await?;
+        prompt_docs = prompt::select_apply_suggestion_documents(&ranked_docs, &payload.suggestion_content, payload.current_document_id, &budget)?;
+        if prompt_docs.is_empty() {
+            println!("->> {:<12} - Apply suggestion failed: no relevant document fits the prompt budget.", "HANDLER");
+            return Err(Error::FailedApplyChanges);
+        }
+        final_prompt = prompt::construct_apply_suggestion_prompt(
+            &prompt_docs,
+            &payload.suggestion_content,
+            payload.current_document_id
+        )?;
+    }
 
-    // 6. Query LLM
+    // Original content of the documents the model saw, edits are located in it
+    let original_content_map: HashMap<i32, String> = prompt_docs
+        .into_iter()
+        .map(|(id, _, content)| (id, content))
+        .collect();
+
+    // 6. Query LLM for schema-validated search/replace edits
     println!("->> {:<12} - Querying LLM for apply suggestion.", "HANDLER");
-    let query_model = QueryModel::new()?;
-    let llm_response_str = query_model.query_model(&final_prompt).await?;
-    println!("->> {:<12} - LLM response received ({} chars).", "HANDLER", llm_response_str.len());
-
-    // Trim markdown fences if present
-    let trimmed_response = llm_response_str
-        .strip_prefix("```json\n")
-        .unwrap_or(&llm_response_str)
-        .strip_suffix("\n```")
-        .unwrap_or(&llm_response_str)
-        .trim(); // Also trim leading/trailing whitespace just in case
-
-    // 7. Parse LLM response (JSON array of LlmDocChange)
-    let llm_changes: Vec<LlmDocChange> = serde_json::from_str(trimmed_response)
-        .map_err(|e| {
-            eprintln!("Error parsing LLM response JSON: {:?}\nTrimmed Response: {}", e, trimmed_response);
-            Error::FailedApplyChanges
-        })?;
-    println!("->> {:<12} - Parsed {} changes from LLM response.", "HANDLER", llm_changes.len());
-
-    // 8. Construct final response (Vec<SuggestedDocumentChange>)
-    let mut suggested_changes: Vec<SuggestedDocumentChange> = Vec::new();
-    for change in llm_changes {
-        if let Some(old_content) = original_content_map.get(&change.document_id) {
-            // Only include if the content actually changed
-            if old_content != &change.new_content {
-                 suggested_changes.push(SuggestedDocumentChange {
-                    document_id: change.document_id,
-                    old_content: old_content.clone(), // Clone original content
-                    new_content: change.new_content, // Use new content from LLM
-                });
-            } else {
-                 println!("->> {:<12} - LLM proposed no change for doc {}, skipping.", "HANDLER", change.document_id);
+    let llm_edits: LlmDocEdits = charged_structured_query(&pool, user_id, AiOperation::ApplySuggestion, &query_model, &final_prompt).await?;
+    println!("->> {:<12} - Parsed {} edits from LLM response.", "HANDLER", llm_edits.edits.len());
+
+    // Group the edits per document, keeping the order in which documents first appear
+    let mut edited_doc_ids: Vec<i32> = Vec::new();
+    let mut edits_by_doc: HashMap<i32, Vec<&LlmDocEdit>> = HashMap::new();
+    for edit in &llm_edits.edits {
+        if !edits_by_doc.contains_key(&edit.document_id) {
+            edited_doc_ids.push(edit.document_id);
+        }
+        edits_by_doc.entry(edit.document_id).or_default().push(edit);
+    }
+
+    // 7. Check and apply every document's edits on their own, one bad document does not discard the others
+    let mut considered_document_ids: Vec<i32> = original_content_map.keys().copied().collect();
+    considered_document_ids.sort_unstable();
+    let mut result = ApplySuggestionResult { changes: Vec::new(), failures: Vec::new(), considered_document_ids };
+    for document_id in edited_doc_ids {
+        let edits = &edits_by_doc[&document_id];
+        let Some(old_content) = original_content_map.get(&document_id) else {
+            println!("->> {:<12} - WARNING: LLM returned edits for document {} it was not given, rejecting.", "HANDLER", document_id);
+            result.failures.push(DocumentChangeFailure {
+                document_id,
+                reason: "Document was not among the documents sent with the suggestion".to_string(),
+            });
+            continue;
+        };
+
+        if !check_document_permission(&pool, user_id, document_id, "editor").await? {
+            println!("->> {:<12} - User {} cannot edit document {}, rejecting change.", "HANDLER", user_id, document_id);
+            result.failures.push(DocumentChangeFailure {
+                document_id,
+                reason: "You do not have permission to edit this document".to_string(),
+            });
+            continue;
+        }
+
+        let new_content = match patch::apply_edits(old_content, edits) {
+            Ok(new_content) => new_content,
+            Err(reason) => {
+                println!("->> {:<12} - Edits for doc {} do not apply: {}", "HANDLER", document_id, reason);
+                result.failures.push(DocumentChangeFailure { document_id, reason });
+                continue;
             }
-        } else {
-            // LLM returned an ID not in the original set - log warning but ignore
-             println!("->> {:<12} - WARNING: LLM returned change for unknown document ID {}, ignoring.", "HANDLER", change.document_id);
+        };
+
+        // Only include if the content actually changed
+        if old_content == &new_content {
+            println!("->> {:<12} - LLM proposed no change for doc {}, skipping.", "HANDLER", document_id);
+            continue;
         }
+        result.changes.push(SuggestedDocumentChange {
+            document_id,
+            old_content: old_content.clone(), // Clone original content
+            new_content,
+            edit_count: edits.len(),
+        });
     }
-     println!("->> {:<12} - Constructed {} SuggestedDocumentChange entries.", "HANDLER", suggested_changes.len());
+    println!("->> {:<12} - Constructed {} changes, {} rejected.", "HANDLER", result.changes.len(), result.failures.len());
 
-    // 9. Return the suggested changes
-    Ok(Json(suggested_changes))
+    // 8. Return the suggested changes and the rejected ones
+    Ok(Json(result))
 }
 
 /// POST handler for deciding if a diff should be proactively shown.
@@ -901,7 +2461,7 @@ pub async fn api_decide_proactive_diff(
         &payload.ai_response_content,
         &payload.context, // This is ProactiveDiffContextPayload
         payload.document_content_snippet.as_deref(), // Pass as Option<&str>
-    );
+    )?;
 
     println!("->> {:<12} - Decision Prompt: ...", "HANDLER"); // Avoid logging potentially large prompt for now
 
@@ -934,7 +2494,7 @@ pub async fn api_sanitize_text(
     let _user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
 
     // Construct the prompt for the sanitization AI
-    let sanitize_prompt = prompt::construct_sanitize_text_prompt(&payload.text_to_sanitize);
+    let sanitize_prompt = prompt::construct_sanitize_text_prompt(&payload.text_to_sanitize)?;
     println!("->> {:<12} - Sanitize Prompt: ... (Brief)", "HANDLER"); // Avoid logging large text
 
     // Query the LLM for sanitization
@@ -961,9 +2521,21 @@ pub fn writing_assistant_routes() -> Router {
     Router::new()
         .route("/", get(api_get_all_writing_sessions))
         .route("/", post(api_create_writing_session))
+        .route("/personas", get(api_get_personas))
+        .route("/personas", post(api_create_persona))
+        .route("/personas/:persona_id", delete(api_delete_persona))
+        .route("/providers", get(api_get_providers))
         .route("/:id", get(api_get_writing_session))
         .route("/:id", delete(api_delete_writing_session))
         .route("/:id/message", post(api_send_writing_message))
+        .route("/:id/message/stream", post(api_stream_writing_message))
+        .route("/:id/agent", post(api_agent_writing_message))
+        .route("/:id/tool-calls", get(api_get_tool_calls))
+        .route("/:id/messages/:message_id/regenerate", post(api_regenerate_writing_message))
+        .route("/:id/messages/:message_id/edit", post(api_edit_writing_message))
+        .route("/:id/branches", get(api_get_writing_branches))
+        .route("/:id/branches/:message_id", put(api_switch_writing_branch))
+        .route("/:id/persona", put(api_set_session_persona))
         .route("/:id/apply-suggestion", post(api_apply_suggestion))
         .route("/grammer", post(api_check_grammer))
         .route("/spellcheck", post(api_spell_check))
@@ -973,6 +2545,20 @@ pub fn writing_assistant_routes() -> Router {
         .route("/shrink", post(api_shrink))
         .route("/rewrite", post(api_rewrite))
         .route("/factcheck", post(api_fact_check))
+        .route("/factcheck/evidence", post(api_fact_check_evidence))
+        .route("/translate", post(api_translate))
+        .route("/translate/document/:id", post(api_translate_document))
+        .route("/translate/project/:id", post(api_translate_project))
+        .route("/knowledge/extract/:id", post(api_extract_entities))
+        .route("/consistency/:id", post(api_start_consistency_check))
+        .route("/grammer/stream", post(api_check_grammer_stream))
+        .route("/spellcheck/stream", post(api_spell_check_stream))
+        .route("/summarize/stream", post(api_summarize_stream))
+        .route("/rephrase/stream", post(api_rephrase_stream))
+        .route("/expand/stream", post(api_expand_stream))
+        .route("/shrink/stream", post(api_shrink_stream))
+        .route("/rewrite/stream", post(api_rewrite_stream))
+        .route("/factcheck/stream", post(api_fact_check_stream))
         .route("/decide-proactive-diff", post(api_decide_proactive_diff))
         .route("/sanitize-text", post(api_sanitize_text))
 }
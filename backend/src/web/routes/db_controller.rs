@@ -5,22 +5,33 @@
 / File containing various API Backend endpoints for manipulating the database and environment
 /
 / API Summary:
-/ api_test_db   GET    /test    - Test The Database Connection
-/ api_wipe_db   GET    /wipe    - Wipe The Database If Secret Code Matches
+/ api_test_db                GET    /test                       - Test The Database Connection
+/ api_wipe_db                GET    /wipe                       - Wipe The Database If Secret Code Matches
+/ api_backfill_embeddings    POST   /embeddings/backfill        - Queue Documents For (Re-)Embedding
+/ api_get_embedding_jobs     GET    /embeddings/jobs            - Get Embedding Job Counts Per Status
+/ api_retry_dead_embeddings  POST   /embeddings/retry-dead      - Re-Queue Dead-Lettered Embedding Jobs
+/ api_grant_credits          POST   /credits/grant              - Grant (Or Remove) AI Credits For A User
+/ api_refill_credits         POST   /credits/refill             - Run The Monthly Credit Refill Now
+/ api_set_user_plan          PUT    /users/:id/plan             - Move A User To Another Plan
+/ api_get_prompt_templates   GET    /prompts                    - List Prompt Templates And Their Versions
 /
 */
 use axum::{
-    extract::{Extension, Query},
+    extract::{Extension, Path, Query},
     response::Json,
-    routing::get,
+    routing::{get, post, put},
     Router,
 };
 use serde_json::{json, Value};
-use std::{fs, path::PathBuf, time::Duration};
+use std::{env, fs, path::PathBuf, time::Duration};
 use tokio::time;
 use reqwest::Client;
 
 use crate::models::db::WipeParams;
+use crate::models::job::BackfillParams;
+use crate::models::plan::{GrantCreditsPayload, PlanManager, RefillParams, SetPlanPayload};
+use crate::rag::jobs::{enqueue_backfill, job_status_counts, retry_dead_jobs};
+use crate::rag::templates;
 use crate::{Error, Result};
 
 /// GET handler for testing the database connection.
@@ -94,6 +105,219 @@ async fn api_db_reset(
     })))
 }
 
+/// POST handler for queueing documents for embedding, e.g. after changing the embedding model.
+/// Accessible via: POST /api/db/embeddings/backfill?secret=secret_key&mode=all
+/// Test: test_environment.rs/test_backfill_embeddings()
+/// Frontend: Not directly called from frontend
+async fn api_backfill_embeddings(
+    Extension(pool): Extension<sqlx::PgPool>,
+    Query(params): Query<BackfillParams>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - backfill_embeddings", "HANDLER");
+
+    // Check for secret key needed for admin operations
+    if params.secret != Some("secret_key".to_string()) {
+        return Err(Error::MigrationKeyError);
+    }
+
+    let only_missing = match params.mode.as_deref() {
+        None | Some("missing") => true,
+        Some("all") => false,
+        Some(_) => return Err(Error::InvalidRequestFormatError),
+    };
+
+    let queued = enqueue_backfill(&pool, only_missing).await?;
+
+    Ok(Json(json!({
+        "result": {
+            "success": true,
+            "queued": queued
+        }
+    })))
+}
+
+/// GET handler for inspecting the embedding job queue.
+/// Accessible via: GET /api/db/embeddings/jobs?secret=secret_key
+/// Test: test_environment.rs/test_backfill_embeddings()
+/// Frontend: Not directly called from frontend
+async fn api_get_embedding_jobs(
+    Extension(pool): Extension<sqlx::PgPool>,
+    Query(params): Query<WipeParams>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - get_embedding_jobs", "HANDLER");
+
+    // Check for secret key needed for admin operations
+    if params.secret != Some("secret_key".to_string()) {
+        return Err(Error::MigrationKeyError);
+    }
+
+    let counts = job_status_counts(&pool).await?;
+
+    Ok(Json(json!({
+        "result": {
+            "success": true,
+            "jobs": counts
+        }
+    })))
+}
+
+/// POST handler for moving dead-lettered embedding jobs back into the queue.
+/// Accessible via: POST /api/db/embeddings/retry-dead?secret=secret_key
+/// Test: test_ai.rs/test_retry_dead_embeddings()
+/// Frontend: Not directly called from frontend
+async fn api_retry_dead_embeddings(
+    Extension(pool): Extension<sqlx::PgPool>,
+    Query(params): Query<WipeParams>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - retry_dead_embeddings", "HANDLER");
+
+    // Check for secret key needed for admin operations
+    if params.secret != Some("secret_key".to_string()) {
+        return Err(Error::MigrationKeyError);
+    }
+
+    let requeued = retry_dead_jobs(&pool).await?;
+
+    Ok(Json(json!({
+        "result": {
+            "success": true,
+            "requeued": requeued
+        }
+    })))
+}
+
+/// POST handler for granting a user AI credits outside of the monthly refill, e.g. as compensation.
+/// A negative amount takes credits away. The grant is recorded in the user's credit ledger.
+/// Accessible via: POST /api/db/credits/grant?secret=<ADMIN_SECRET_KEY>
+/// Test: test_environment.rs/test_grant_credits()
+/// Frontend: Not directly called from frontend
+async fn api_grant_credits(
+    Extension(pool): Extension<sqlx::PgPool>,
+    Query(params): Query<WipeParams>,
+    Json(payload): Json<GrantCreditsPayload>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - grant_credits", "HANDLER");
+
+    check_admin_key(params.secret.as_deref())?;
+
+    if payload.amount == 0 {
+        return Err(Error::InvalidRequestFormatError);
+    }
+
+    let balance = PlanManager::grant_credits(&pool, payload.user_id, payload.amount, payload.note.as_deref()).await?;
+
+    Ok(Json(json!({
+        "result": {
+            "success": true,
+            "user_id": payload.user_id,
+            "balance": balance
+        }
+    })))
+}
+
+/// POST handler for running the monthly credit refill without waiting for the scheduled job.
+/// Only users whose refill is due are topped up, unless a user_id is given, then that user is topped up now.
+/// Accessible via: POST /api/db/credits/refill?secret=<ADMIN_SECRET_KEY>&user_id=2
+/// Test: test_environment.rs/test_refill_credits()
+/// Frontend: Not directly called from frontend
+async fn api_refill_credits(
+    Extension(pool): Extension<sqlx::PgPool>,
+    Query(params): Query<RefillParams>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - refill_credits", "HANDLER");
+
+    check_admin_key(params.secret.as_deref())?;
+
+    let refilled = PlanManager::refill_due_credits(&pool, params.user_id).await?;
+
+    Ok(Json(json!({
+        "result": {
+            "success": true,
+            "refilled": refilled
+        }
+    })))
+}
+
+/// PUT handler for moving a user to another plan by plan name.
+/// Accessible via: PUT /api/db/users/:id/plan?secret=<ADMIN_SECRET_KEY>
+/// Test: test_environment.rs/test_set_user_plan()
+/// Frontend: Not directly called from frontend
+async fn api_set_user_plan(
+    Extension(pool): Extension<sqlx::PgPool>,
+    Path(user_id): Path<i32>,
+    Query(params): Query<WipeParams>,
+    Json(payload): Json<SetPlanPayload>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - set_user_plan", "HANDLER");
+
+    check_admin_key(params.secret.as_deref())?;
+
+    let plan = PlanManager::set_user_plan(&pool, user_id, &payload.plan).await?;
+
+    Ok(Json(json!({
+        "result": {
+            "success": true,
+            "user_id": user_id,
+            "plan": plan
+        }
+    })))
+}
+
+/// GET handler for listing the prompt templates in use and the versions available on disk.
+/// Accessible via: GET /api/db/prompts?secret=secret_key
+/// Test: TODO
+/// Frontend: Not directly called from frontend
+async fn api_get_prompt_templates(Query(params): Query<WipeParams>) -> Result<Json<Value>> {
+    println!("->> {:<12} - get_prompt_templates", "HANDLER");
+
+    // Check for secret key needed for admin operations
+    if params.secret != Some("secret_key".to_string()) {
+        return Err(Error::MigrationKeyError);
+    }
+
+    let registry = templates::registry()?;
+    let prompts: Vec<Value> = registry
+        .active_templates()
+        .into_iter()
+        .map(|template| json!({
+            "name": template.name,
+            "active_version": template.version,
+            "tag": template.tag(),
+            "description": template.description,
+            "variables": template.variables,
+            "versions": registry.versions(&template.name)
+        }))
+        .collect();
+
+    Ok(Json(json!({
+        "result": {
+            "success": true,
+            "prompts": prompts
+        }
+    })))
+}
+
+// Credits and plans can only be changed with the key in ADMIN_SECRET_KEY, nobody can while it is not set
+fn check_admin_key(secret: Option<&str>) -> Result<()> {
+    let Some(expected) = env::var("ADMIN_SECRET_KEY").ok().filter(|key| !key.is_empty()) else {
+        eprintln!("->> {:<12} - ADMIN_SECRET_KEY is not set, rejecting admin request", "ADMIN");
+        return Err(Error::PermissionError);
+    };
+    match secret {
+        Some(secret) if constant_time_eq(secret.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(Error::PermissionError),
+    }
+}
+
+// Looks at every byte whatever the first difference, so the time taken does not tell how much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0u8, |difference, (x, y)| difference | (x ^ y));
+    std::hint::black_box(difference) == 0
+}
+
 async fn send_periodic_request(pool: sqlx::PgPool) {
     let client = Client::new();
     loop {
@@ -131,4 +355,11 @@ pub fn db_routes(pool: sqlx::PgPool) -> Router {
     Router::new()
         .route("/test", get(api_db_test))
         .route("/reset", get(api_db_reset))
+        .route("/embeddings/backfill", post(api_backfill_embeddings))
+        .route("/embeddings/jobs", get(api_get_embedding_jobs))
+        .route("/embeddings/retry-dead", post(api_retry_dead_embeddings))
+        .route("/credits/grant", post(api_grant_credits))
+        .route("/credits/refill", post(api_refill_credits))
+        .route("/users/:id/plan", put(api_set_user_plan))
+        .route("/prompts", get(api_get_prompt_templates))
 }
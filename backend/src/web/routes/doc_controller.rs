@@ -13,12 +13,22 @@
 / api_get_permissions       GET     /:id/permissions    - Get Users With Permissions to Current Document
 / api_update_permission     PUT     /:id/permissions    - Update Permissions on User to Current Document
 / api_remove_permissions    DELETE  /:id/permissions    - Delete Permissions on User to Current Document
+/ api_get_document_stats    GET     /:id/stats          - Get Readability And Writing Statistics Of Current Document
+/ api_get_document_stats_history GET /:id/stats/history - Get Daily Statistics Of Current Document (?days=30)
+/ api_get_document_translations GET /:id/translations  - Get The Translations And Translation Source Of Current Document
+/ api_get_document_entities GET     /:id/entities       - Get The Knowledge Base Entities Current Document Mentions
+/ api_get_similarity_report GET     /similarity         - Get Similar Passages And Copies Among The User's Or A Project's Documents
+/ api_get_tags              GET     /tags               - Get The Tags On Documents The User Can Read, Most Used First
+/ api_get_document_tags     GET     /:id/tags           - Get The Tags And Suggested Tags Of Current Document
+/ api_set_document_tags     PUT     /:id/tags           - Replace The Tags Of Current Document
+/ api_dismiss_tag_suggestions DELETE /:id/tags/suggestions - Dismiss The Suggested Tags Of Current Document
+/ api_get_related_documents GET     /:id/related        - Get The Documents Most Related To Current Document (?limit=5)
 /
 */
 
 use axum::routing::{delete, get, post, put};
 use axum::{
-    extract::{Extension, Json, Path},
+    extract::{Extension, Json, Path, Query},
     Router,
 };
 use serde_json::{json, Value};
@@ -26,18 +36,26 @@ use sqlx::PgPool;
 use tower_cookies::Cookies;
 
 use crate::models::document::{CreateDocumentPayload, Document, UpdateDocumentPayload};
+use crate::models::goals::WritingActivity;
+use crate::models::knowledge::{spawn_mentions_refresh, DocumentEntity, KnowledgeManager};
 use crate::models::permission::{
     CreatePermissionPayload, DocumentPermission, UpdatePermissionPayload, UserPermissions,
 };
-use crate::models::storage::StorageManager;
-use crate::web::middleware::middleware::check_document_permission;
+use crate::models::plan::PlanManager;
+use crate::models::tags::{
+    DocumentTags, DocumentTagsPayload, RelatedDocuments, RelatedDocumentsParams, TagCount, TagFilterParams, TagManager,
+    DEFAULT_RELATED_DOCUMENTS, MAX_RELATED_DOCUMENTS,
+};
+use crate::models::similarity::{SimilarityManager, SimilarityParams, SimilarityReport, DEFAULT_MIN_SCORE, MIN_SCORE_FLOOR};
+use crate::models::stats::{spawn_stats_refresh, DocumentStats, StatsHistoryParams, StatsManager, StatsSnapshot};
+use crate::models::translation::{DocumentTranslations, TranslationManager};
+use crate::web::middleware::middleware::{check_document_permission, check_project_permission};
 use crate::{Error, Result};
 
 use backend::get_user_id_from_cookie;
 
-// Import necessary items for embedding
-use crate::rag::embed::EmbeddingModel;
-use chrono::{Utc, Duration};
+// Import the embedding queue
+use crate::rag::jobs::enqueue_document_embedding;
 
 /// GET handler for retrieving a document by ID.
 /// Accessible via: GET /api/document/:id
@@ -83,12 +101,13 @@ pub async fn api_get_document(
     }
 }
 
-/// GET handler for retrieving all documents the user has access to.
-/// Accessible via: GET /api/document/
-/// Test: test_documents.rs/test_get_all_documents()
+/// GET handler for retrieving all documents the user has access to, optionally only those with a tag.
+/// Accessible via: GET /api/document/?tag=research
+/// Test: test_documents.rs/test_get_all_documents(), test_tags.rs/test_tag_filters()
 /// Frontend: document.ts/get_all_documents()
 pub async fn api_get_all_documents(
     cookies: Cookies,
+    Query(params): Query<TagFilterParams>,
     Extension(pool): Extension<PgPool>,
 ) -> Result<Json<Vec<Document>>> {
     println!("->> {:<12} - get_all_documents", "HANDLER");
@@ -102,8 +121,13 @@ pub async fn api_get_all_documents(
         r#"SELECT d.id, d.name, d.content, d.created_at, d.updated_at, d.user_id, is_starred, is_trashed
            FROM documents d
            JOIN document_permissions dp ON d.id = dp.document_id
-           WHERE dp.user_id = $1"#,
-        user_id
+           WHERE dp.user_id = $1
+           AND ($2::text IS NULL OR EXISTS (
+               SELECT 1 FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+               WHERE dt.document_id = d.id AND t.name = $2
+           ))"#,
+        user_id,
+        params.tag()
     )
     .fetch_all(&pool)
     .await
@@ -137,8 +161,9 @@ pub async fn api_create_document(
     .await
     .map_err(|_| Error::DatabaseError)?;
     
-    // Use hardcoded default limit
-    let max_documents = 10;
+    // Limits come from the user's plan
+    let limits = PlanManager::get_user_limits(&pool, user_id).await?;
+    let max_documents = limits.max_documents;
     
     if user_docs_count.count.unwrap_or(0) as i32 >= max_documents {
         return Err(Error::LimitExceededError { message: "Document limit reached".to_string() });
@@ -147,8 +172,8 @@ pub async fn api_create_document(
     // Calculate the content size in bytes
     let content_length = payload.content.as_ref().map_or(0, |s| s.len() as i64);
     
-    // Check if this would exceed the user's storage limit (using dynamic storage management)
-    let max_storage_bytes = StorageManager::get_user_quota();
+    // Check if this would exceed the user's storage quota
+    let max_storage_bytes = limits.storage_quota_bytes;
     
     // Get user's current storage usage
     let current_storage = sqlx::query!(
@@ -203,6 +228,18 @@ pub async fn api_create_document(
                 return Err(Error::PermissionCreationError);
             }
 
+            // Queue an embedding and compute the statistics of documents created with content
+            if payload.content.as_deref().is_some_and(|c| !c.trim().is_empty()) {
+                if let Err(e) = enqueue_document_embedding(&pool, record.id).await {
+                    println!("->> {:<12} - Failed to queue embedding for document {}: {:?}", "ERROR", record.id, e);
+                }
+                spawn_stats_refresh(pool.clone(), record.id);
+                // Words of a new document count towards the writing goals of its creator
+                if let Err(e) = WritingActivity::record(&pool, user_id, record.id, "", payload.content.as_deref().unwrap_or_default()).await {
+                    println!("->> {:<12} - Failed to record writing activity for document {}: {:?}", "ERROR", record.id, e);
+                }
+            }
+
             // Then fetch the document by id
             let document = sqlx::query_as!(
                 Document,
@@ -255,11 +292,10 @@ pub async fn api_update_document(
         return Err(Error::PermissionError);
     }
 
-    // --- Embedding Logic Start ---
-    // Fetch old content and embedding timestamp BEFORE updating
+    // Fetch old content BEFORE updating
     let old_data = sqlx::query!(
         r#"
-        SELECT content, embedding_updated_at
+        SELECT content
         FROM documents
         WHERE id = $1
         "#,
@@ -270,8 +306,6 @@ pub async fn api_update_document(
     .map_err(|_| Error::DatabaseError)?;
     
     let old_content = old_data.as_ref().and_then(|d| d.content.clone()).unwrap_or_default();
-    let old_embedding_time = old_data.as_ref().and_then(|d| d.embedding_updated_at);
-    // --- Embedding Logic End ---
 
     // Calculate the difference in content size
     let old_content_len = old_content.len() as i64;
@@ -280,9 +314,6 @@ pub async fn api_update_document(
 
     // If content is growing, check storage limits
     if size_diff > 0 {
-        // Check if this would exceed the user's storage limit (using dynamic storage management)
-        let max_storage_bytes = StorageManager::get_user_quota();
-        
         // Get document owner
         let owner = sqlx::query!(
             "SELECT user_id FROM document_permissions 
@@ -292,6 +323,9 @@ pub async fn api_update_document(
         .fetch_one(&pool)
         .await
         .map_err(|_| Error::DatabaseError)?;
+
+        // Check if this would exceed the storage quota of the owner's plan
+        let max_storage_bytes = PlanManager::get_user_limits(&pool, owner.user_id).await?.storage_quota_bytes;
         
         // Get owner's current storage usage
         let current_storage = sqlx::query!(
@@ -335,49 +369,19 @@ pub async fn api_update_document(
         return Err(Error::DocumentUpdateError { document_id });
     }
 
-    let should_update_embedding = match old_embedding_time {
-        Some(timestamp) => {
-            // Get length of new content, defaulting to 0 if None
-            let new_content_len = payload.content.as_ref().map_or(0, |s| s.len());
-            // Get length of old content, defaulting to 0 if None (already handled by unwrap_or_default earlier)
-            let old_content_len = old_content.len();
-            
-            let content_diff = (new_content_len as i64 - old_content_len as i64).abs();
-            let time_diff = Utc::now().signed_duration_since(timestamp);
-            
-            content_diff > 500 || time_diff > Duration::minutes(20)
-        },
-        None => true, // Always update if no previous embedding time exists
-    };
-
-    if should_update_embedding {
-        println!("->> {:<12} - Updating embedding for document {}", "INFO", document_id);
-        let embedding_model = EmbeddingModel::new()?;
-
-        // Handle Option<String> for content before embedding
-        if let Some(content_str) = payload.content.as_deref() {
-            // Generate new embedding only if content exists
-            let new_embedding = embedding_model.embed_document(content_str).await?;
-            
-            // Update the embedding and timestamp in the database
-            let embed_update_result = sqlx::query!(
-                r#"
-                UPDATE documents
-                SET embedding = $1, embedding_updated_at = $2
-                WHERE id = $3
-                "#,
-                new_embedding as _,
-                Utc::now(),
-                document_id
-            )
-            .execute(&pool)
-            .await;
-
-            if embed_update_result.is_err() {
-                println!("->> {:<12} - Failed to update embedding for document {}: {:?}", "ERROR", document_id, embed_update_result.err());
-            }
-        } else {
-            println!("->> {:<12} - Skipping embedding update for document {} as content is None", "INFO", document_id);
+    // Queue the document for re-embedding if its content changed
+    // The embedding worker debounces bursts of saves and retries failures
+    if payload.content.as_deref().unwrap_or_default() != old_content {
+        if let Err(e) = enqueue_document_embedding(&pool, document_id).await {
+            println!("->> {:<12} - Failed to queue embedding for document {}: {:?}", "ERROR", document_id, e);
+        }
+        // Refresh the cached statistics and today's snapshot of the document and its projects
+        spawn_stats_refresh(pool.clone(), document_id);
+        // Link the document to the knowledge base entities it mentions now
+        spawn_mentions_refresh(pool.clone(), document_id);
+        // Count the net words of the save towards the writing goals of the user who saved
+        if let Err(e) = WritingActivity::record(&pool, user_id, document_id, &old_content, payload.content.as_deref().unwrap_or_default()).await {
+            println!("->> {:<12} - Failed to record writing activity for document {}: {:?}", "ERROR", document_id, e);
         }
     }
     
@@ -508,6 +512,89 @@ pub async fn api_get_project_from_document(
     }
 }
 
+/// GET handler for the readability and writing statistics of a document
+/// Cached until the content changes.
+/// Accessible via: GET /api/document/:id/stats
+/// Test: test_documents.rs/test_get_document_stats()
+/// Frontend: document.ts/get_document_stats()
+pub async fn api_get_document_stats(
+    cookies: Cookies,
+    Path(document_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<DocumentStats>> {
+    println!("->> {:<12} - get_document_stats", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(StatsManager::document_stats(&pool, document_id).await?))
+}
+
+/// GET handler for the daily statistics of a document, one snapshot per day it changed
+/// Accessible via: GET /api/document/:id/stats/history?days=30
+/// Test: test_documents.rs/test_get_document_stats_history()
+/// Frontend: document.ts/get_document_stats_history()
+pub async fn api_get_document_stats_history(
+    cookies: Cookies,
+    Path(document_id): Path<i32>,
+    Query(params): Query<StatsHistoryParams>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<StatsSnapshot>>> {
+    println!("->> {:<12} - get_document_stats_history", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(StatsManager::document_history(&pool, document_id, params.days()).await?))
+}
+
+/// GET handler for the translations of a document and the document it was translated from
+/// `stale` translations were made from an older version of their source.
+/// Accessible via: GET /api/document/:id/translations
+/// Test: test_translation.rs/test_stale_translation()
+/// Frontend: translation.ts/get_document_translations()
+pub async fn api_get_document_translations(
+    cookies: Cookies,
+    Path(document_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<DocumentTranslations>> {
+    println!("->> {:<12} - get_document_translations", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(TranslationManager::for_document(&pool, document_id).await?))
+}
+
+/// GET handler for the knowledge base entities a document mentions, in order of first mention
+/// Accessible via: GET /api/document/:id/entities
+/// Test: test_knowledge.rs/test_entity_mentions()
+/// Frontend: knowledge.ts/get_document_entities()
+pub async fn api_get_document_entities(
+    cookies: Cookies,
+    Path(document_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<DocumentEntity>>> {
+    println!("->> {:<12} - get_document_entities", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(KnowledgeManager::for_document(&pool, document_id).await?))
+}
+
 /// POST handler for granting permission to a user for a document.
 /// Accessible via: POST /api/document/:id/permissions
 /// Test: test_documents.rs/test_add_permissions()
@@ -943,6 +1030,135 @@ pub async fn api_get_shared_documents(
     }
 }
 
+/// GET handler for the similarity report of the documents the user owns, or of a project's documents:
+/// near-duplicate passages, reused text and documents that are copies of each other
+/// Accessible via: GET /api/document/similarity?project_id=2&min_score=0.9
+/// Test: test_similarity.rs/test_similarity_report()
+/// Frontend: document.ts/get_similarity_report()
+pub async fn api_get_similarity_report(
+    cookies: Cookies,
+    Query(params): Query<SimilarityParams>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<SimilarityReport>> {
+    println!("->> {:<12} - get_similarity_report", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let min_score = params.min_score.unwrap_or(DEFAULT_MIN_SCORE);
+    if !(MIN_SCORE_FLOOR..=1.0).contains(&min_score) {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    if let Some(project_id) = params.project_id {
+        if !check_project_permission(&pool, user_id, project_id, "viewer").await? {
+            return Err(Error::PermissionError);
+        }
+    }
+
+    Ok(Json(SimilarityManager::report(&pool, user_id, params.project_id, min_score).await?))
+}
+
+/// GET handler for the tags on the documents the user can read, the most used first
+/// Accessible via: GET /api/document/tags
+/// Test: test_tags.rs/test_tag_filters()
+/// Frontend: document.ts/get_tags()
+pub async fn api_get_tags(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<TagCount>>> {
+    println!("->> {:<12} - get_tags", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    Ok(Json(TagManager::for_user(&pool, user_id).await?))
+}
+
+/// GET handler for the tags of a document and the tags and topics the AI suggested for it
+/// Accessible via: GET /api/document/:id/tags
+/// Test: test_tags.rs/test_tag_suggestions()
+/// Frontend: document.ts/get_document_tags()
+pub async fn api_get_document_tags(
+    cookies: Cookies,
+    Path(document_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<DocumentTags>> {
+    println!("->> {:<12} - get_document_tags", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(TagManager::for_document(&pool, document_id).await?))
+}
+
+/// PUT handler for replacing the tags of a document, accepting a suggestion is adding it to the tags
+/// Accessible via: PUT /api/document/:id/tags
+/// Test: test_tags.rs/test_set_document_tags()
+/// Frontend: document.ts/set_document_tags()
+pub async fn api_set_document_tags(
+    cookies: Cookies,
+    Path(document_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<DocumentTagsPayload>,
+) -> Result<Json<DocumentTags>> {
+    println!("->> {:<12} - set_document_tags", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if !check_document_permission(&pool, user_id, document_id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(TagManager::set_document_tags(&pool, document_id, user_id, &payload.tags).await?))
+}
+
+/// DELETE handler for dismissing the suggested tags of a document until its text changes
+/// Accessible via: DELETE /api/document/:id/tags/suggestions
+/// Test: test_tags.rs/test_dismiss_suggestions()
+/// Frontend: document.ts/dismiss_tag_suggestions()
+pub async fn api_dismiss_tag_suggestions(
+    cookies: Cookies,
+    Path(document_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<DocumentTags>> {
+    println!("->> {:<12} - dismiss_tag_suggestions", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if !check_document_permission(&pool, user_id, document_id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    TagManager::dismiss_suggestions(&pool, document_id).await?;
+    Ok(Json(TagManager::for_document(&pool, document_id).await?))
+}
+
+/// GET handler for the documents the user can read that are most related to a document, by their embeddings
+/// Accessible via: GET /api/document/:id/related?limit=5
+/// Test: test_tags.rs/test_related_documents()
+/// Frontend: document.ts/get_related_documents()
+pub async fn api_get_related_documents(
+    cookies: Cookies,
+    Path(document_id): Path<i32>,
+    Query(params): Query<RelatedDocumentsParams>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<RelatedDocuments>> {
+    println!("->> {:<12} - get_related_documents", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let limit = params.limit.unwrap_or(DEFAULT_RELATED_DOCUMENTS);
+    if !(1..=MAX_RELATED_DOCUMENTS).contains(&limit) {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    if !check_document_permission(&pool, user_id, document_id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(TagManager::related_documents(&pool, user_id, document_id, limit).await?))
+}
+
 pub fn doc_routes() -> Router {
     Router::new()
         .route("/", get(api_get_all_documents))
@@ -951,6 +1167,14 @@ pub fn doc_routes() -> Router {
         .route("/:id", put(api_update_document))
         .route("/:id", delete(api_delete_document))
         .route("/:id/project", get(api_get_project_from_document))
+        .route("/:id/stats", get(api_get_document_stats))
+        .route("/:id/stats/history", get(api_get_document_stats_history))
+        .route("/:id/translations", get(api_get_document_translations))
+        .route("/:id/entities", get(api_get_document_entities))
+        .route("/:id/tags", get(api_get_document_tags))
+        .route("/:id/tags", put(api_set_document_tags))
+        .route("/:id/tags/suggestions", delete(api_dismiss_tag_suggestions))
+        .route("/:id/related", get(api_get_related_documents))
         .route("/:id/permissions", post(api_add_permissions))
         .route("/:id/permissions", get(api_get_permissions))
         .route("/:id/permissions", put(api_update_permission))
@@ -961,4 +1185,6 @@ pub fn doc_routes() -> Router {
         .route("/starred", get(api_get_starred_documents))
         .route("/trash", get(api_get_trashed_documents))
         .route("/shared", get(api_get_shared_documents))
+        .route("/similarity", get(api_get_similarity_report))
+        .route("/tags", get(api_get_tags))
 }
@@ -0,0 +1,159 @@
+/*
+/ src/controllers/goal_controller.rs
+/ Request Handlers
+/
+/ File containing API Backend endpoints for writing goals.
+/ A goal is a word-count target for a project, optionally by a deadline, or a number of words
+/ every day. Progress is measured from the net words of document saves, see models::goals.
+/
+/ API Summary:
+/ api_get_goals          GET         /            - Get the user's goals with their progress (?project_id=)
+/ api_create_goal        POST        /            - Create a project or daily goal
+/ api_get_activity       GET         /activity    - Get the words the user wrote per day (?days=30&project_id=)
+/ api_get_goal           GET         /:id         - Get a goal with its progress, streaks and projected completion date
+/ api_update_goal        PUT         /:id         - Update the target, start date and deadline of a goal
+/ api_delete_goal        DELETE      /:id         - Delete a goal
+*/
+
+use axum::routing::get;
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    Router,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower_cookies::Cookies;
+
+use crate::models::goals::{
+    ActivityParams, CreateGoalPayload, DailyWords, GoalListParams, GoalManager, GoalProgress, UpdateGoalPayload,
+    WritingActivity,
+};
+use crate::web::middleware::middleware::check_project_permission;
+use crate::{Error, Result};
+
+use backend::get_user_id_from_cookie;
+
+/// GET handler for the user's writing goals
+/// Accessible via: GET /api/goals?project_id=1
+/// Test: test_goals.rs/test_daily_goal()
+/// Frontend: goals.ts/get_goals()
+pub async fn api_get_goals(
+    cookies: Cookies,
+    Query(params): Query<GoalListParams>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<GoalProgress>>> {
+    println!("->> {:<12} - api_get_goals", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    Ok(Json(GoalManager::list(&pool, user_id, params.project_id).await?))
+}
+
+/// POST handler for creating a writing goal
+/// Project goals need a project the user can edit, daily goals may be limited to one.
+/// Accessible via: POST /api/goals
+/// Test: test_goals.rs/test_project_goal()
+/// Frontend: goals.ts/create_goal()
+pub async fn api_create_goal(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<CreateGoalPayload>,
+) -> Result<Json<GoalProgress>> {
+    println!("->> {:<12} - api_create_goal", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if let Some(project_id) = payload.project_id {
+        if !check_project_permission(&pool, user_id, project_id, "editor").await? {
+            return Err(Error::PermissionError);
+        }
+    }
+
+    Ok(Json(GoalManager::create(&pool, user_id, &payload).await?))
+}
+
+/// GET handler for the words the user wrote per day
+/// Only days with activity are returned, oldest first.
+/// Accessible via: GET /api/goals/activity?days=30&project_id=1
+/// Test: test_goals.rs/test_writing_activity()
+/// Frontend: goals.ts/get_writing_activity()
+pub async fn api_get_activity(
+    cookies: Cookies,
+    Query(params): Query<ActivityParams>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<DailyWords>>> {
+    println!("->> {:<12} - api_get_activity", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    if let Some(project_id) = params.project_id {
+        if !check_project_permission(&pool, user_id, project_id, "viewer").await? {
+            return Err(Error::PermissionError);
+        }
+    }
+
+    Ok(Json(WritingActivity::history(&pool, user_id, params.project_id, params.days()).await?))
+}
+
+/// GET handler for a writing goal and its progress
+/// Accessible via: GET /api/goals/:id
+/// Test: test_goals.rs/test_daily_goal()
+/// Frontend: goals.ts/get_goal()
+pub async fn api_get_goal(
+    cookies: Cookies,
+    Path(goal_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<GoalProgress>> {
+    println!("->> {:<12} - api_get_goal", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    Ok(Json(GoalManager::get(&pool, user_id, goal_id).await?))
+}
+
+/// PUT handler for updating a writing goal
+/// Accessible via: PUT /api/goals/:id
+/// Test: test_goals.rs/test_project_goal()
+/// Frontend: goals.ts/update_goal()
+pub async fn api_update_goal(
+    cookies: Cookies,
+    Path(goal_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<UpdateGoalPayload>,
+) -> Result<Json<GoalProgress>> {
+    println!("->> {:<12} - api_update_goal", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    Ok(Json(GoalManager::update(&pool, user_id, goal_id, &payload).await?))
+}
+
+/// DELETE handler for deleting a writing goal
+/// The writing activity is kept.
+/// Accessible via: DELETE /api/goals/:id
+/// Test: test_goals.rs/test_delete_goal()
+/// Frontend: goals.ts/delete_goal()
+pub async fn api_delete_goal(
+    cookies: Cookies,
+    Path(goal_id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - api_delete_goal", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    GoalManager::delete(&pool, user_id, goal_id).await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "message": format!("Goal {} deleted", goal_id)
+    })))
+}
+
+// Combine all goal routes into one router
+pub fn goal_routes() -> Router {
+    Router::new()
+        .route("/", get(api_get_goals).post(api_create_goal))
+        .route("/activity", get(api_get_activity))
+        .route("/:id", get(api_get_goal).put(api_update_goal).delete(api_delete_goal))
+}
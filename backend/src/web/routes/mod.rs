@@ -4,4 +4,6 @@ pub mod proj_controller;
 pub mod doc_controller;
 pub mod key_controller;
 pub mod ai_controller;
-pub mod pref_controller;
\ No newline at end of file
+pub mod pref_controller;
+pub mod proofread_controller;
+pub mod goal_controller;
\ No newline at end of file
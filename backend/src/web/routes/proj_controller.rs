@@ -5,10 +5,28 @@
 / File containing various API Backend endpoints for manipulating a project and its permissions
 /
 / API Summary:
-/ api_get_all_projects       GET     /                          - Get All Projects For Current User
+/ api_get_all_projects       GET     /                          - Get All Projects For Current User (?tag=)
 / api_get_project            GET     /:id                       - Get Project By ID
 / api_create_project         POST    /                          - Create New Project
 / api_update_project         PUT     /:id                       - Update Project By ID
+/ api_get_project_persona    GET     /:id/persona               - Get The Assistant Persona Of A Project
+/ api_set_project_persona    PUT     /:id/persona               - Set The Assistant Persona Or System Prompt Of A Project
+/ api_get_project_stats      GET     /:id/stats                 - Get Readability And Writing Statistics Of A Project
+/ api_get_project_stats_history GET  /:id/stats/history         - Get Daily Statistics Of A Project (?days=30)
+/ api_get_glossary           GET     /:id/glossary              - Get The Translation Glossary Of A Project
+/ api_add_glossary_term      POST    /:id/glossary              - Add A Term To The Translation Glossary
+/ api_update_glossary_term   PUT     /:id/glossary/:term_id     - Replace A Term Of The Translation Glossary
+/ api_delete_glossary_term   DELETE  /:id/glossary/:term_id     - Remove A Term From The Translation Glossary
+/ api_get_project_translations GET   /:id/translations          - Get The Translations Of The Project's Documents And Whether They Are Stale
+/ api_get_knowledge_base     GET     /:id/knowledge             - Get The Style Guide And Entities Of A Project
+/ api_update_style_guide     PUT     /:id/style-guide           - Set Or Remove The Style Guide Of A Project
+/ api_get_entities           GET     /:id/entities              - Get The Knowledge Base Entities Of A Project (?kind=)
+/ api_add_entity             POST    /:id/entities              - Add A Character, Place Or Term To The Knowledge Base
+/ api_get_entity             GET     /:id/entities/:entity_id   - Get An Entity And The Documents Mentioning It
+/ api_update_entity          PUT     /:id/entities/:entity_id   - Replace An Entity Of The Knowledge Base
+/ api_delete_entity          DELETE  /:id/entities/:entity_id   - Remove An Entity From The Knowledge Base
+/ api_get_consistency_reports GET    /:id/consistency           - Get The Consistency Reports Of A Project, Newest First
+/ api_get_consistency_report GET     /:id/consistency/:report_id - Get The Progress And Findings Of A Consistency Report (?kind=&document_id=)
 / api_delete_project         DELETE  /:id                       - Delete Project By ID
 / api_add_permissions        POST    /:id/permissions           - Add Permissions to User on Project
 / api_get_permissions        GET     /:id/permissions           - Get Users With Permissions to Project
@@ -16,14 +34,14 @@
 / api_remove_permissions     DELETE  /:id/permissions/:user_id  - Delete Permissions on User to Project
 / api_force_delete_project   DELETE  /:id/force                 - Delete Project and All Associated Documents
 / api_add_document           POST    /:id/documents/:doc_id     - Add Document to Project
-/ api_get_documents          GET     /:id/documents             - Get All Documents in Project
+/ api_get_documents          GET     /:id/documents             - Get All Documents in Project (?tag=)
 / api_remove_document        DELETE  /:id/documents/:doc_id     - Remove Document from Project
 /
 */
 
 use axum::routing::{delete, get, post, put};
 use axum::{
-    extract::{Extension, Json, Path},
+    extract::{Extension, Json, Path, Query},
     Router,
 };
 use serde_json::{json, Value};
@@ -35,18 +53,29 @@ use crate::models::project_permission::{
     CreateProjectPermissionPayload, ProjectPermission, UpdateProjectPermissionPayload,
     UserProjectPermissions,
 };
+use crate::models::plan::PlanManager;
+use crate::models::persona::{PersonaManager, PersonaSettings};
+use crate::models::stats::{ProjectStats, StatsHistoryParams, StatsManager, StatsSnapshot};
+use crate::models::consistency::{ConsistencyManager, ConsistencyReport, ConsistencyReportDetail, FindingListParams};
+use crate::models::knowledge::{
+    spawn_mentions_refresh, EntityDetail, EntityListParams, EntityPayload, KnowledgeBase, KnowledgeManager, ProjectEntity,
+    StyleGuidePayload,
+};
+use crate::models::tags::TagFilterParams;
+use crate::models::translation::{Glossary, GlossaryTerm, GlossaryTermPayload, TranslationLink, TranslationManager};
 use crate::web::middleware::middleware::check_project_permission;
 use crate::{Error, Result};
 
 use crate::models::document::Document;
 use backend::get_user_id_from_cookie;
 
-/// GET handler for retrieving all projects for a user.
-/// Accessible via: GET /api/project
-/// Test: test_projects.rs/test_get_all_projects()
+/// GET handler for retrieving all projects for a user, optionally only those with a document with a tag.
+/// Accessible via: GET /api/project?tag=research
+/// Test: test_projects.rs/test_get_all_projects(), test_tags.rs/test_tag_filters()
 /// Frontend: project.ts/get_all_projects()
 async fn api_get_all_projects(
     cookies: Cookies,
+    Query(params): Query<TagFilterParams>,
     Extension(pool): Extension<PgPool>,
 ) -> Result<Json<Vec<Project>>> {
     // get user_id from cookies
@@ -58,8 +87,15 @@ async fn api_get_all_projects(
         r#"SELECT p.id, p.name, p.user_id, p.created_at, p.updated_at,is_trashed, is_starred
            FROM projects p
            JOIN project_permissions pp ON p.id = pp.project_id
-           WHERE pp.user_id = $1"#,
-        user_id
+           WHERE pp.user_id = $1
+           AND ($2::text IS NULL OR EXISTS (
+               SELECT 1 FROM document_projects dpr
+               JOIN document_tags dt ON dt.document_id = dpr.document_id
+               JOIN tags t ON t.id = dt.tag_id
+               WHERE dpr.project_id = p.id AND t.name = $2
+           ))"#,
+        user_id,
+        params.tag()
     )
     .fetch_all(&pool)
     .await;
@@ -132,8 +168,8 @@ async fn api_create_project(
     .await
     .map_err(|_| Error::DatabaseError)?;
     
-    // Use hardcoded default limit
-    let max_projects = 3;
+    // Limits come from the user's plan
+    let max_projects = PlanManager::get_user_limits(&pool, user_id).await?.max_projects;
     
     if user_projects_count.count.unwrap_or(0) as i32 >= max_projects {
         return Err(Error::LimitExceededError { message: "Project limit reached".to_string() });
@@ -218,6 +254,408 @@ async fn api_update_project(
     }
 }
 
+/// GET handler for the assistant persona of a project.
+/// Accessible via: GET /api/project/:id/persona
+/// Test: test_projects.rs/test_project_persona()
+/// Frontend: project.ts/get_project_persona()
+/// Writing assistant sessions on the project's documents use it unless they set their own.
+async fn api_get_project_persona(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<PersonaSettings>> {
+    println!("->> {:<12} - api_get_project_persona", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    let settings = sqlx::query_as!(
+        PersonaSettings,
+        "SELECT persona_id, system_prompt FROM projects WHERE id = $1",
+        id
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?
+    .ok_or(Error::ProjectNotFoundError { project_id: id })?;
+
+    Ok(Json(settings))
+}
+
+/// PUT handler for setting the assistant persona of a project.
+/// Accessible via: PUT /api/project/:id/persona
+/// Test: test_projects.rs/test_project_persona()
+/// Frontend: project.ts/set_project_persona()
+/// Takes a `persona_id` and/or a custom `system_prompt` (which wins), both null goes back to the default.
+/// The persona must be built-in or belong to the user setting it.
+async fn api_set_project_persona(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<PersonaSettings>,
+) -> Result<Json<PersonaSettings>> {
+    println!("->> {:<12} - api_set_project_persona", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    let settings = payload.normalized()?;
+    if let Some(persona_id) = settings.persona_id {
+        PersonaManager::ensure_usable(&pool, user_id, persona_id).await?;
+    }
+
+    let settings = sqlx::query_as!(
+        PersonaSettings,
+        r#"
+        UPDATE projects
+        SET persona_id = $2, system_prompt = $3, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1
+        RETURNING persona_id, system_prompt
+        "#,
+        id,
+        settings.persona_id,
+        settings.system_prompt
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| Error::DatabaseError)?
+    .ok_or(Error::ProjectNotFoundError { project_id: id })?;
+
+    Ok(Json(settings))
+}
+
+/// GET handler for the readability and writing statistics of a project
+/// Computed over all documents of the project that are not in the trash, cached until one changes.
+/// Accessible via: GET /api/project/:id/stats
+/// Test: test_projects.rs/test_project_stats()
+/// Frontend: project.ts/get_project_stats()
+async fn api_get_project_stats(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<ProjectStats>> {
+    println!("->> {:<12} - api_get_project_stats", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(StatsManager::project_stats(&pool, id).await?))
+}
+
+/// GET handler for the daily statistics of a project, one snapshot per day it changed
+/// Accessible via: GET /api/project/:id/stats/history?days=30
+/// Test: test_projects.rs/test_project_stats()
+/// Frontend: project.ts/get_project_stats_history()
+async fn api_get_project_stats_history(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Query(params): Query<StatsHistoryParams>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<StatsSnapshot>>> {
+    println!("->> {:<12} - api_get_project_stats_history", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(StatsManager::project_history(&pool, id, params.days()).await?))
+}
+
+/// GET handler for the translation glossary of a project
+/// Accessible via: GET /api/project/:id/glossary
+/// Test: test_translation.rs/test_glossary()
+/// Frontend: translation.ts/get_glossary()
+async fn api_get_glossary(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<GlossaryTerm>>> {
+    println!("->> {:<12} - api_get_glossary", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(Glossary::list(&pool, id).await?))
+}
+
+/// POST handler for adding a term to the translation glossary of a project
+/// Accessible via: POST /api/project/:id/glossary
+/// Test: test_translation.rs/test_glossary()
+/// Frontend: translation.ts/add_glossary_term()
+async fn api_add_glossary_term(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<GlossaryTermPayload>,
+) -> Result<Json<GlossaryTerm>> {
+    println!("->> {:<12} - api_add_glossary_term", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(Glossary::create(&pool, id, &payload).await?))
+}
+
+/// PUT handler for replacing a term of the translation glossary of a project
+/// Accessible via: PUT /api/project/:id/glossary/:term_id
+/// Test: test_translation.rs/test_glossary()
+/// Frontend: translation.ts/update_glossary_term()
+async fn api_update_glossary_term(
+    cookies: Cookies,
+    Path((id, term_id)): Path<(i32, i32)>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<GlossaryTermPayload>,
+) -> Result<Json<GlossaryTerm>> {
+    println!("->> {:<12} - api_update_glossary_term", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(Glossary::update(&pool, id, term_id, &payload).await?))
+}
+
+/// DELETE handler for removing a term from the translation glossary of a project
+/// Accessible via: DELETE /api/project/:id/glossary/:term_id
+/// Test: test_translation.rs/test_glossary()
+/// Frontend: translation.ts/delete_glossary_term()
+async fn api_delete_glossary_term(
+    cookies: Cookies,
+    Path((id, term_id)): Path<(i32, i32)>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - api_delete_glossary_term", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Glossary::delete(&pool, id, term_id).await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "message": format!("Glossary term {} deleted", term_id)
+    })))
+}
+
+/// GET handler for the translations of the documents of a project
+/// `stale` translations were made from an older version of their source.
+/// Accessible via: GET /api/project/:id/translations
+/// Test: test_translation.rs/test_translate_project()
+/// Frontend: translation.ts/get_project_translations()
+async fn api_get_project_translations(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<TranslationLink>>> {
+    println!("->> {:<12} - api_get_project_translations", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(TranslationManager::for_project(&pool, id).await?))
+}
+
+/// GET handler for the knowledge base of a project: its style guide and entities
+/// Accessible via: GET /api/project/:id/knowledge
+/// Test: test_knowledge.rs/test_style_guide()
+/// Frontend: knowledge.ts/get_knowledge_base()
+async fn api_get_knowledge_base(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<KnowledgeBase>> {
+    println!("->> {:<12} - api_get_knowledge_base", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(KnowledgeManager::knowledge_base(&pool, id).await?))
+}
+
+/// PUT handler for the style guide of a project, the assistant follows it for the project's documents
+/// Accessible via: PUT /api/project/:id/style-guide
+/// Test: test_knowledge.rs/test_style_guide()
+/// Frontend: knowledge.ts/update_style_guide()
+async fn api_update_style_guide(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<StyleGuidePayload>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - api_update_style_guide", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    let style_guide = KnowledgeManager::set_style_guide(&pool, id, payload.style_guide).await?;
+    Ok(Json(json!({ "project_id": id, "style_guide": style_guide })))
+}
+
+/// GET handler for the entities of a project's knowledge base
+/// Accessible via: GET /api/project/:id/entities?kind=character
+/// Test: test_knowledge.rs/test_entities()
+/// Frontend: knowledge.ts/get_entities()
+async fn api_get_entities(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Query(params): Query<EntityListParams>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<ProjectEntity>>> {
+    println!("->> {:<12} - api_get_entities", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(KnowledgeManager::list(&pool, id, params.kind).await?))
+}
+
+/// POST handler for adding an entity to a project's knowledge base
+/// The documents of the project mentioning its name or an alias are linked right away.
+/// Accessible via: POST /api/project/:id/entities
+/// Test: test_knowledge.rs/test_entities()
+/// Frontend: knowledge.ts/add_entity()
+async fn api_add_entity(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<EntityPayload>,
+) -> Result<Json<ProjectEntity>> {
+    println!("->> {:<12} - api_add_entity", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(KnowledgeManager::create(&pool, id, &payload).await?))
+}
+
+/// GET handler for an entity and the documents that mention it
+/// Accessible via: GET /api/project/:id/entities/:entity_id
+/// Test: test_knowledge.rs/test_entity_mentions()
+/// Frontend: knowledge.ts/get_entity()
+async fn api_get_entity(
+    cookies: Cookies,
+    Path((id, entity_id)): Path<(i32, i32)>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<EntityDetail>> {
+    println!("->> {:<12} - api_get_entity", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(KnowledgeManager::get(&pool, id, entity_id).await?))
+}
+
+/// PUT handler for replacing an entity of a project's knowledge base
+/// Accessible via: PUT /api/project/:id/entities/:entity_id
+/// Test: test_knowledge.rs/test_entities()
+/// Frontend: knowledge.ts/update_entity()
+async fn api_update_entity(
+    cookies: Cookies,
+    Path((id, entity_id)): Path<(i32, i32)>,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<EntityPayload>,
+) -> Result<Json<ProjectEntity>> {
+    println!("->> {:<12} - api_update_entity", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(KnowledgeManager::update(&pool, id, entity_id, &payload).await?))
+}
+
+/// DELETE handler for removing an entity from a project's knowledge base
+/// Accessible via: DELETE /api/project/:id/entities/:entity_id
+/// Test: test_knowledge.rs/test_entities()
+/// Frontend: knowledge.ts/delete_entity()
+async fn api_delete_entity(
+    cookies: Cookies,
+    Path((id, entity_id)): Path<(i32, i32)>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - api_delete_entity", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "editor").await? {
+        return Err(Error::PermissionError);
+    }
+
+    KnowledgeManager::delete(&pool, id, entity_id).await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "message": format!("Entity {} deleted", entity_id)
+    })))
+}
+
+/// GET handler for the consistency reports of a project, newest first
+/// Checks are started with POST /api/writing-assistant/consistency/:id.
+/// Accessible via: GET /api/project/:id/consistency
+/// Test: test_consistency.rs/test_consistency_reports()
+/// Frontend: consistency.ts/get_consistency_reports()
+async fn api_get_consistency_reports(
+    cookies: Cookies,
+    Path(id): Path<i32>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<ConsistencyReport>>> {
+    println!("->> {:<12} - api_get_consistency_reports", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(ConsistencyManager::list(&pool, id).await?))
+}
+
+/// GET handler for the progress and findings of a consistency report
+/// Findings are stored as the documents are checked, a running report shows the ones so far.
+/// Accessible via: GET /api/project/:id/consistency/:report_id?kind=name_variant&document_id=1
+/// Test: test_consistency.rs/test_consistency_check()
+/// Frontend: consistency.ts/get_consistency_report()
+async fn api_get_consistency_report(
+    cookies: Cookies,
+    Path((id, report_id)): Path<(i32, i32)>,
+    Query(params): Query<FindingListParams>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<ConsistencyReportDetail>> {
+    println!("->> {:<12} - api_get_consistency_report", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+    if !check_project_permission(&pool, user_id, id, "viewer").await? {
+        return Err(Error::PermissionError);
+    }
+
+    Ok(Json(ConsistencyManager::get(&pool, id, report_id, &params).await?))
+}
+
 /// DELETE handler for deleting a project.
 /// Accessible via: DELETE /api/project/:id
 /// Test: test_projects.rs/test_delete_project()
@@ -682,13 +1120,14 @@ async fn api_force_delete_project(
     })))
 }
 
-/// GET handler for retrieving all documents in a project.
-/// Accessible via: GET /api/project/:id/documents
-/// Test: test_projects.rs/test_get_project_documents()
+/// GET handler for retrieving all documents in a project, optionally only those with a tag.
+/// Accessible via: GET /api/project/:id/documents?tag=research
+/// Test: test_projects.rs/test_get_project_documents(), test_tags.rs/test_tag_filters()
 /// Frontend: project.ts/get_project_documents()
 async fn api_get_documents(
     cookies: Cookies,
     Path(project_id): Path<i32>,
+    Query(params): Query<TagFilterParams>,
     Extension(pool): Extension<PgPool>,
 ) -> Result<Json<Vec<Document>>> {
     println!("->> {:<12} - api_get_documents", "HANDLER");
@@ -714,9 +1153,14 @@ async fn api_get_documents(
                 AND pp.role IN ('owner', 'editor', 'viewer')
             )
         )
+        AND ($3::text IS NULL OR EXISTS (
+            SELECT 1 FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+            WHERE dt.document_id = d.id AND t.name = $3
+        ))
         ORDER BY d.id"#,
         project_id,
-        user_id
+        user_id,
+        params.tag()
     )
     .fetch_all(&pool)
     .await
@@ -793,12 +1237,16 @@ async fn api_add_document(
     .await;
 
     match result {
-        Ok(_) => Ok(Json(json!({
-            "result": {
-                "success": true,
-                "message": "Document added to project successfully"
-            }
-        }))),
+        Ok(_) => {
+            // Link the document to the knowledge base entities it mentions
+            spawn_mentions_refresh(pool.clone(), document_id);
+            Ok(Json(json!({
+                "result": {
+                    "success": true,
+                    "message": "Document added to project successfully"
+                }
+            })))
+        }
         Err(_) => Err(Error::DatabaseError),
     }
 }
@@ -1092,6 +1540,24 @@ pub fn project_routes() -> Router {
         .route("/:id", put(api_update_project))
         .route("/:id", delete(api_delete_project))
         .route("/:id/force", delete(api_force_delete_project))
+        .route("/:id/persona", get(api_get_project_persona))
+        .route("/:id/persona", put(api_set_project_persona))
+        .route("/:id/stats", get(api_get_project_stats))
+        .route("/:id/stats/history", get(api_get_project_stats_history))
+        .route("/:id/glossary", get(api_get_glossary))
+        .route("/:id/glossary", post(api_add_glossary_term))
+        .route("/:id/glossary/:term_id", put(api_update_glossary_term))
+        .route("/:id/glossary/:term_id", delete(api_delete_glossary_term))
+        .route("/:id/translations", get(api_get_project_translations))
+        .route("/:id/knowledge", get(api_get_knowledge_base))
+        .route("/:id/style-guide", put(api_update_style_guide))
+        .route("/:id/entities", get(api_get_entities))
+        .route("/:id/entities", post(api_add_entity))
+        .route("/:id/entities/:entity_id", get(api_get_entity))
+        .route("/:id/entities/:entity_id", put(api_update_entity))
+        .route("/:id/entities/:entity_id", delete(api_delete_entity))
+        .route("/:id/consistency", get(api_get_consistency_reports))
+        .route("/:id/consistency/:report_id", get(api_get_consistency_report))
         .route("/:id/permissions", post(api_add_permissions))
         .route("/:id/permissions", get(api_get_permissions))
         .route("/:id/permissions", put(api_update_permission))
@@ -0,0 +1,148 @@
+/*
+/ src/controllers/proofread_controller.rs
+/ Request Handlers
+/
+/ File containing API Backend endpoints for the offline grammar and spell checker.
+/ Checks run locally against Hunspell dictionaries and style rules and cost no AI credits,
+/ the LLM checks of the writing assistant remain available for deeper rewrites.
+/
+/ API Summary:
+/ api_proofread                 POST        /                   - Check text for spelling, repeated words, passive voice and long sentences
+/ api_get_languages             GET         /languages          - Get the languages with an installed dictionary
+/ api_get_dictionary            GET         /dictionary         - Get the words of the user's custom dictionary
+/ api_add_dictionary_word       POST        /dictionary         - Add a word to the user's custom dictionary
+/ api_delete_dictionary_word    DELETE      /dictionary/:word   - Remove a word from the user's custom dictionary
+*/
+
+use axum::routing::{delete, get, post};
+use axum::{
+    extract::{Extension, Json, Path},
+    Router,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower_cookies::Cookies;
+
+use crate::models::proofread::{
+    AddDictionaryWordPayload, DictionaryWord, ProofreadLanguages, ProofreadPayload, ProofreadResponse,
+    UserDictionary, MAX_PROOFREAD_CHARS,
+};
+use crate::proofread;
+use crate::{Error, Result};
+
+use backend::get_user_id_from_cookie;
+
+/// POST handler for checking text offline
+/// Spelling is only checked when a dictionary for the language is installed (`dictionary_loaded`).
+/// Issue offsets are character offsets into `content`.
+/// Accessible via: POST /api/proofread/
+/// Test: test_proofread.rs/test_proofread_style_issues()
+/// Frontend: proofread.ts/proofread_text()
+pub async fn api_proofread(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<ProofreadPayload>,
+) -> Result<Json<ProofreadResponse>> {
+    println!("->> {:<12} - api_proofread", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let language = payload.language.unwrap_or_else(proofread::default_language);
+    if !proofread::is_valid_language(&language) || payload.content.chars().count() > MAX_PROOFREAD_CHARS {
+        return Err(Error::InvalidRequestFormatError);
+    }
+    let custom_words = UserDictionary::words(&pool, user_id).await?;
+
+    // Loading a dictionary and generating suggestions are CPU bound
+    let content = payload.content;
+    let response = tokio::task::spawn_blocking(move || {
+        let dictionary = proofread::dictionary(&language);
+        let issues = proofread::check_text(&content, dictionary.as_deref(), &custom_words);
+        ProofreadResponse {
+            dictionary_loaded: dictionary.is_some(),
+            word_count: proofread::tokenize(&content).len(),
+            language,
+            issues,
+        }
+    })
+    .await
+    .map_err(|_| Error::ProofreadError)?;
+
+    Ok(Json(response))
+}
+
+/// GET handler for the languages with an installed dictionary
+/// Accessible via: GET /api/proofread/languages
+/// Test: test_proofread.rs/test_get_languages()
+/// Frontend: proofread.ts/get_proofread_languages()
+pub async fn api_get_languages(cookies: Cookies) -> Result<Json<ProofreadLanguages>> {
+    println!("->> {:<12} - api_get_languages", "HANDLER");
+
+    let _user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    Ok(Json(ProofreadLanguages {
+        default_language: proofread::default_language(),
+        languages: proofread::available_languages(),
+    }))
+}
+
+/// GET handler for the user's custom dictionary
+/// Accessible via: GET /api/proofread/dictionary
+/// Test: test_proofread.rs/test_custom_dictionary()
+/// Frontend: proofread.ts/get_dictionary()
+pub async fn api_get_dictionary(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Vec<DictionaryWord>>> {
+    println!("->> {:<12} - api_get_dictionary", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    Ok(Json(UserDictionary::list(&pool, user_id).await?))
+}
+
+/// POST handler for adding a word to the user's custom dictionary
+/// Accessible via: POST /api/proofread/dictionary
+/// Test: test_proofread.rs/test_custom_dictionary()
+/// Frontend: proofread.ts/add_dictionary_word()
+pub async fn api_add_dictionary_word(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+    Json(payload): Json<AddDictionaryWordPayload>,
+) -> Result<Json<DictionaryWord>> {
+    println!("->> {:<12} - api_add_dictionary_word", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    Ok(Json(UserDictionary::add(&pool, user_id, &payload.word).await?))
+}
+
+/// DELETE handler for removing a word from the user's custom dictionary
+/// Accessible via: DELETE /api/proofread/dictionary/:word
+/// Test: test_proofread.rs/test_custom_dictionary()
+/// Frontend: proofread.ts/delete_dictionary_word()
+pub async fn api_delete_dictionary_word(
+    cookies: Cookies,
+    Path(word): Path<String>,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - api_delete_dictionary_word", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    UserDictionary::remove(&pool, user_id, &word).await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "message": format!("Removed \"{}\" from the dictionary", word.trim())
+    })))
+}
+
+// Combine all proofreading routes into one router
+pub fn proofread_routes() -> Router {
+    Router::new()
+        .route("/", post(api_proofread))
+        .route("/languages", get(api_get_languages))
+        .route("/dictionary", get(api_get_dictionary).post(api_add_dictionary_word))
+        .route("/dictionary/:word", delete(api_delete_dictionary_word))
+}
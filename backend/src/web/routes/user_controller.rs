@@ -11,12 +11,14 @@
 / api_login             POST    /login          - Attempt Login And Set Cookies
 / api_logout            GET     /logout         - Logout By Wiping Cookies
 / api_check_auth        GET     /check-auth     - Check User Authentication
+/ api_get_credit_history GET    /credits        - Get AI Credit Balance And Usage History
+/ api_get_user_plan     GET     /plan           - Get Current Plan, Limits And Next Credit Refill
 /
 */
 
 use axum::routing::{get, post, put};
 use axum::{
-    extract::{Extension, Json, Path},
+    extract::{Extension, Json, Path, Query},
     Router,
 };
 use serde_json::{json, Value};
@@ -32,6 +34,8 @@ use std::sync::OnceLock;
 
 use crate::models::user::{CreateUserPayload, LoginUserPayload, UpdateUserPayload, User};
 use crate::models::storage::StorageManager;
+use crate::models::credits::{CreditHistoryParams, CreditLedger};
+use crate::models::plan::PlanManager;
 use crate::{Error, Result};
 use backend::get_user_id_from_cookie;
 
@@ -801,10 +805,11 @@ pub async fn api_get_user_storage(
     .await
     .map_err(|_| Error::DatabaseError)?;
 
-    // Get dynamic storage limits
-    let max_projects = 3; // Project limit remains fixed
-    let max_documents = 10; // Document limit remains fixed
-    let max_storage_bytes = StorageManager::get_user_quota();
+    // Limits of the user's plan
+    let limits = PlanManager::get_user_limits(&pool, user_id).await?;
+    let max_projects = limits.max_projects;
+    let max_documents = limits.max_documents;
+    let max_storage_bytes = limits.storage_quota_bytes;
     
     // Get overall database statistics
     let db_size = StorageManager::get_db_size(&pool).await.unwrap_or(0);
@@ -851,6 +856,59 @@ pub async fn api_get_user_storage(
     })))
 }
 
+/// GET handler for retrieving the user's AI credit balance and usage history.
+/// Accessible via: GET /api/users/credits?limit=50&offset=0
+/// Test: test_ai.rs/test_credit_history_records_usage()
+/// Returns the current balance and ledger entries (newest first): every charge with its operation,
+/// token counts and cost, plus refunds of failed AI calls.
+pub async fn api_get_credit_history(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+    Query(params): Query<CreditHistoryParams>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - get_credit_history", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let balance = CreditLedger::balance(&pool, user_id).await?;
+    let entries = CreditLedger::history(&pool, user_id, limit, offset).await?;
+
+    Ok(Json(json!({
+        "balance": balance,
+        "entries": entries,
+        "limit": limit,
+        "offset": offset
+    })))
+}
+
+/// GET handler for retrieving the current user's plan.
+/// Accessible via: GET /api/users/plan
+/// Test: test_users.rs/test_get_user_plan()
+/// Returns the plan with its limits and monthly credit allowance, the current balance,
+/// when credits were last refilled and when the next refill is due, plus all available plans.
+pub async fn api_get_user_plan(
+    cookies: Cookies,
+    Extension(pool): Extension<PgPool>,
+) -> Result<Json<Value>> {
+    println!("->> {:<12} - get_user_plan", "HANDLER");
+
+    let user_id = get_user_id_from_cookie(&cookies).ok_or(Error::PermissionError)?;
+
+    let user_plan = PlanManager::get_user_plan(&pool, user_id).await?;
+    let plans = PlanManager::list_plans(&pool).await?;
+
+    Ok(Json(json!({
+        "plan": user_plan.plan,
+        "ai_credits": user_plan.ai_credits,
+        "credits_refilled_at": user_plan.credits_refilled_at,
+        "next_refill_at": user_plan.next_refill_at,
+        "available_plans": plans
+    })))
+}
+
 // Combine user-related routes into one Router instance.
 pub fn user_routes() -> Router {
     Router::new()
@@ -865,6 +923,8 @@ pub fn user_routes() -> Router {
         .route("/current", get(api_get_current_user))
         .route("/storage", get(api_get_storage_usage))
         .route("/user-storage", get(api_get_user_storage))
+        .route("/credits", get(api_get_credit_history))
+        .route("/plan", get(api_get_user_plan))
         .route("/search", get(api_search_users))
         .route("/update", put(api_update_user))
 }
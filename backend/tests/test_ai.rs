@@ -1,7 +1,7 @@
 #![allow(unused)]
 
 use anyhow::{anyhow, Result};
-use backend::result_to_string;
+use backend::{admin_secret, result_to_string, wait_for_embedding_queue};
 use chrono::Utc;
 use httpc_test::Client;
 use serde_json::json;
@@ -18,6 +18,20 @@ async fn test_writing_assistant() -> Result<()> {
     let get_all_sessions = test_get_all_writing_sessions_success(&hc).await;
     let get_session = test_get_writing_session_success(&hc).await;
     let send_message = test_send_writing_message_success(&hc).await;
+    let stream_message = test_stream_writing_message_success(&hc).await;
+    let prompt_templates = test_messages_record_prompt_template(&hc).await;
+    let regenerate_message = test_regenerate_writing_message_success(&hc).await;
+    let edit_message = test_edit_writing_message_success(&hc).await;
+    let failed_edit = test_failed_edit_keeps_branch(&hc).await;
+    let get_branches = test_get_writing_branches_success(&hc).await;
+    let switch_branch = test_switch_writing_branch_success(&hc).await;
+    let get_personas = test_get_personas_success(&hc).await;
+    let create_persona = test_create_persona_success(&hc).await;
+    let session_persona = test_set_session_persona_success(&hc).await;
+    let delete_persona = test_delete_persona_success(&hc).await;
+    let session_title = test_session_title_generated(&hc).await;
+    let agent_message = test_agent_writing_message_success(&hc).await;
+    let stream_quick_actions = test_stream_quick_actions_success(&hc).await;
     let check_grammar = test_check_grammar_success(&hc).await;
     let spell_check = test_spell_check_success(&hc).await;
     let summarize = test_summarize_success(&hc).await;
@@ -30,6 +44,14 @@ async fn test_writing_assistant() -> Result<()> {
     let decide_proactive = test_decide_proactive_diff_success(&hc).await;
     let sanitize_text = test_sanitize_text_success(&hc).await;
     let delete_session = test_delete_writing_session_success(&hc).await;
+    let cross_tenant_search = test_semantic_search_excludes_other_users_documents(&hc).await;
+    let cross_tenant_session = test_create_session_for_unreadable_document_fails(&hc).await;
+    let cross_tenant_apply = test_apply_suggestion_only_reads_readable_documents(&hc).await;
+    let citations = test_send_message_returns_valid_citations(&hc).await;
+    let credit_history = test_credit_history_records_usage(&hc).await;
+    let retry_dead = test_retry_dead_embeddings(&hc).await;
+    let session_summary = test_session_summary_after_threshold(&hc).await;
+    let prompt_budget = test_prompt_budget_keeps_system_prompt_and_query(&hc).await;
     let reset_db = backend::test_reset_db(&hc).await;
 
     // Print summary
@@ -39,6 +61,20 @@ async fn test_writing_assistant() -> Result<()> {
     println!("Get All Sessions\t\t{}", result_to_string(&get_all_sessions));
     println!("Get Session\t\t{}", result_to_string(&get_session));
     println!("Send Message\t\t{}", result_to_string(&send_message));
+    println!("Stream Message\t\t{}", result_to_string(&stream_message));
+    println!("Prompt Templates\t{}", result_to_string(&prompt_templates));
+    println!("Regenerate Message\t{}", result_to_string(&regenerate_message));
+    println!("Edit Message\t\t{}", result_to_string(&edit_message));
+    println!("Failed Edit Keeps Branch\t{}", result_to_string(&failed_edit));
+    println!("Get Branches\t\t{}", result_to_string(&get_branches));
+    println!("Switch Branch\t\t{}", result_to_string(&switch_branch));
+    println!("Get Personas\t\t{}", result_to_string(&get_personas));
+    println!("Create Persona\t\t{}", result_to_string(&create_persona));
+    println!("Session Persona\t\t{}", result_to_string(&session_persona));
+    println!("Delete Persona\t\t{}", result_to_string(&delete_persona));
+    println!("Session Title\t\t{}", result_to_string(&session_title));
+    println!("Agent Message\t\t{}", result_to_string(&agent_message));
+    println!("Stream Quick Actions\t{}", result_to_string(&stream_quick_actions));
     println!("Check Grammar\t\t{}", result_to_string(&check_grammar));
     println!("Spell Check\t\t{}", result_to_string(&spell_check));
     println!("Summarize\t\t{}", result_to_string(&summarize));
@@ -51,6 +87,14 @@ async fn test_writing_assistant() -> Result<()> {
     println!("Decide Proactive\t\t{}", result_to_string(&decide_proactive));
     println!("Sanitize Text\t\t{}", result_to_string(&sanitize_text));
     println!("Delete Session\t\t{}", result_to_string(&delete_session));
+    println!("Cross-Tenant Search\t{}", result_to_string(&cross_tenant_search));
+    println!("Cross-Tenant Session\t{}", result_to_string(&cross_tenant_session));
+    println!("Cross-Tenant Apply\t{}", result_to_string(&cross_tenant_apply));
+    println!("Citations\t\t{}", result_to_string(&citations));
+    println!("Credit History\t\t{}", result_to_string(&credit_history));
+    println!("Retry Dead Embeddings\t{}", result_to_string(&retry_dead));
+    println!("Session Summary\t\t{}", result_to_string(&session_summary));
+    println!("Prompt Budget\t\t{}", result_to_string(&prompt_budget));
     println!("Reset Database\t\t{}", result_to_string(&reset_db));
     println!("==============================\n");
 
@@ -141,6 +185,7 @@ async fn test_get_writing_session_success(hc: &Client) -> Result<()> {
 async fn test_send_writing_message_success(hc: &Client) -> Result<()> {
     println!("TEST - Send Writing Message");
 
+    let ledger_start = backend::last_ledger_id(hc).await?;
     let response = hc
         .do_post(
             "/api/writing-assistant/1/message",
@@ -158,6 +203,104 @@ async fn test_send_writing_message_success(hc: &Client) -> Result<()> {
         ));
     }
 
+    // Deciding on extra context is a completion of its own and is charged like the answer
+    for operation in ["context_decision", "chat"] {
+        let charges = backend::credit_charges_since(hc, operation, ledger_start).await?;
+        if charges != 1 {
+            return Err(anyhow!("Expected 1 {} charge for a message, found {}", operation, charges));
+        }
+    }
+
+    Ok(())
+}
+
+async fn test_stream_writing_message_success(hc: &Client) -> Result<()> {
+    println!("TEST - Stream Writing Message");
+
+    let response = hc
+        .do_post(
+            "/api/writing-assistant/1/message/stream",
+            json!({
+                "content": "Can you stream your answer to me?"
+            }),
+        )
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Stream writing message failed with status: {}",
+            response.status()
+        ));
+    }
+
+    let body = response.text_body()?;
+    println!("Stream body:\n{}", body);
+    for event in ["event:sources", "event:token", "event:done"] {
+        if !body.contains(event) {
+            return Err(anyhow!("Stream is missing '{}'", event));
+        }
+    }
+
+    // The question and the streamed answer were stored together as the latest messages of the session
+    let session_response = hc.do_get("/api/writing-assistant/1").await?;
+    let session = session_response.json_body()?;
+    let messages = session["messages"].as_array().cloned().unwrap_or_default();
+    let [.., question, answer] = messages.as_slice() else {
+        return Err(anyhow!("Session has fewer than two messages"));
+    };
+    if answer["role"] != "Assistant" {
+        return Err(anyhow!("Streamed response was not stored: {}", answer));
+    }
+    if question["content"] != "Can you stream your answer to me?" || answer["parent_message_id"] != question["id"] {
+        return Err(anyhow!("Streamed response is not the answer to the question: {}", question));
+    }
+    if session["session"]["active_message_id"] != answer["id"] {
+        return Err(anyhow!("The session did not move to the streamed response"));
+    }
+
+    Ok(())
+}
+
+async fn test_stream_quick_actions_success(hc: &Client) -> Result<()> {
+    println!("TEST - Stream Quick Actions");
+
+    for action in ["summarize", "expand", "rephrase", "shrink"] {
+        let response = hc
+            .do_post(
+                &format!("/api/writing-assistant/{}/stream", action),
+                json!({
+                    "content": "The quick brown fox jumps over the lazy dog."
+                }),
+            )
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Stream {} failed with status: {}",
+                action,
+                response.status()
+            ));
+        }
+
+        let body = response.text_body()?;
+        if !body.contains("event:token") || !body.contains("event:done") {
+            return Err(anyhow!("Stream {} did not complete: {}", action, body));
+        }
+    }
+
+    let response = hc
+        .do_post(
+            "/api/writing-assistant/rewrite/stream",
+            json!({
+                "content": "The quick brown fox jumps over the lazy dog.",
+                "style": "formal"
+            }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Stream rewrite failed with status: {}", response.status()));
+    }
+
     Ok(())
 }
 
@@ -349,9 +492,29 @@ async fn test_fact_check_success(hc: &Client) -> Result<()> {
 async fn test_apply_suggestion_success(hc: &Client) -> Result<()> {
     println!("TEST - Apply Suggestion");
 
+    // Suggestions are applied to the project of the document the session is linked to
+    let session_response = hc
+        .do_post(
+            "/api/writing-assistant",
+            json!({
+                "title": "Apply Suggestion",
+                "document_id": 1
+            }),
+        )
+        .await?;
+    if !session_response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Create document session failed with status: {}",
+            session_response.status()
+        ));
+    }
+    let session_id = session_response.json_body()?["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow::anyhow!("Created session has no id"))?;
+
     let response = hc
         .do_post(
-            "/api/writing-assistant/1/apply-suggestion",
+            &format!("/api/writing-assistant/{}/apply-suggestion", session_id),
             json!({
                 "suggestion_content": "Fix the grammar in this document.",
                 "current_document_id": 1
@@ -367,6 +530,12 @@ async fn test_apply_suggestion_success(hc: &Client) -> Result<()> {
         ));
     }
 
+    // Usable changes and rejected ones are reported separately
+    let body = response.json_body()?;
+    if !body["changes"].is_array() || !body["failures"].is_array() || !body["considered_document_ids"].is_array() {
+        return Err(anyhow::anyhow!("Unexpected apply suggestion response: {}", body));
+    }
+
     Ok(())
 }
 
@@ -436,4 +605,970 @@ async fn test_delete_writing_session_success(hc: &Client) -> Result<()> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// Login as user 2 on a separate client so both users can act in the same test
+async fn login_second_user() -> Result<Client> {
+    let hc2 = httpc_test::new_client("http://localhost:3001")?;
+    let response = hc2
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "MarkoP@gmail.com",
+                "password": "MarkosPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Login as user 2 failed with status: {}",
+            response.status()
+        ));
+    }
+
+    Ok(hc2)
+}
+
+// Create and embed a private document for user 1, returning its ID
+async fn create_private_document(hc: &Client, name: &str, content: &str) -> Result<i64> {
+    let now = Utc::now().naive_utc();
+    let create_response = hc
+        .do_post(
+            "/api/document",
+            json!({
+                "name": name,
+                "content": content,
+                "created_at": now,
+                "updated_at": now
+            }),
+        )
+        .await?;
+    create_response.print().await?;
+
+    if !create_response.status().is_success() {
+        return Err(anyhow!(
+            "Private document creation failed with status: {}",
+            create_response.status()
+        ));
+    }
+
+    let body = create_response.json_body()?;
+    let document_id = body["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Created document has no id"))?;
+
+    // Updating the document queues its embedding
+    let update_response = hc
+        .do_put(
+            &format!("/api/document/{}", document_id),
+            json!({
+                "name": name,
+                "content": content,
+                "updated_at": Utc::now().naive_utc()
+            }),
+        )
+        .await?;
+    update_response.print().await?;
+
+    if !update_response.status().is_success() {
+        return Err(anyhow!(
+            "Private document update failed with status: {}",
+            update_response.status()
+        ));
+    }
+
+    Ok(document_id)
+}
+
+async fn test_semantic_search_excludes_other_users_documents(hc: &Client) -> Result<()> {
+    println!("TEST - Semantic Search Excludes Other Users' Documents");
+
+    // User 1 stores a secret that user 2 has no permission to read
+    let secret = "The vault code for Project Nightingale is 7391-ALPHA.";
+    let private_doc_id = create_private_document(hc, "Nightingale Vault", secret).await?;
+
+    wait_for_embedding_queue(hc).await?;
+
+    // User 2 opens a session that is not tied to any document or project
+    let hc2 = login_second_user().await?;
+    let session_response = hc2
+        .do_post(
+            "/api/writing-assistant",
+            json!({
+                "title": "Cross Tenant Probe",
+                "document_id": null
+            }),
+        )
+        .await?;
+    session_response.print().await?;
+
+    if !session_response.status().is_success() {
+        return Err(anyhow!(
+            "Create session as user 2 failed with status: {}",
+            session_response.status()
+        ));
+    }
+    let session_id = session_response.json_body()?["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Created session has no id"))?;
+
+    // Ask a question whose closest match is user 1's private document
+    let message_response = hc2
+        .do_post(
+            &format!("/api/writing-assistant/{}/message", session_id),
+            json!({
+                "content": "What is the vault code for Project Nightingale?"
+            }),
+        )
+        .await?;
+    message_response.print().await?;
+
+    if !message_response.status().is_success() {
+        return Err(anyhow!(
+            "Send message as user 2 failed with status: {}",
+            message_response.status()
+        ));
+    }
+
+    let body = message_response.json_body()?;
+    let sources = body["sources"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Response is missing the sources list"))?;
+
+    // The private document must never be placed in the prompt
+    if sources.iter().any(|s| s["document_id"].as_i64() == Some(private_doc_id)) {
+        return Err(anyhow!(
+            "Document {} owned by user 1 was retrieved for user 2",
+            private_doc_id
+        ));
+    }
+
+    // And none of its content may leak into the answer
+    let content = body["content"].as_str().unwrap_or_default();
+    if content.contains("7391") {
+        return Err(anyhow!("Private document content leaked into user 2's answer"));
+    }
+
+    Ok(())
+}
+
+async fn test_create_session_for_unreadable_document_fails(hc: &Client) -> Result<()> {
+    println!("TEST - Create Session For Unreadable Document Fails");
+
+    // User 1 owns document 2 and user 2 has no permission on it
+    let hc2 = login_second_user().await?;
+    let response = hc2
+        .do_post(
+            "/api/writing-assistant",
+            json!({
+                "title": "Linked To Someone Else's Document",
+                "document_id": 2
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if response.status().is_success() {
+        return Err(anyhow!(
+            "User 2 linked a session to document 2 without permission"
+        ));
+    }
+
+    Ok(())
+}
+
+async fn test_apply_suggestion_only_reads_readable_documents(hc: &Client) -> Result<()> {
+    println!("TEST - Apply Suggestion Only Reads Readable Documents");
+
+    // User 2 edits document 1, the other documents of project 1 belong to user 1 alone
+    let hc2 = login_second_user().await?;
+    let session_response = hc2
+        .do_post(
+            "/api/writing-assistant",
+            json!({
+                "title": "Apply Suggestion Probe",
+                "document_id": 1
+            }),
+        )
+        .await?;
+    if !session_response.status().is_success() {
+        return Err(anyhow!(
+            "Create session as user 2 failed with status: {}",
+            session_response.status()
+        ));
+    }
+    let session_id = session_response.json_body()?["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Created session has no id"))?;
+
+    let response = hc2
+        .do_post(
+            &format!("/api/writing-assistant/{}/apply-suggestion", session_id),
+            json!({
+                "suggestion_content": "Fix the grammar in this document.",
+                "current_document_id": 1
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Apply suggestion as user 2 failed with status: {}",
+            response.status()
+        ));
+    }
+    let body = response.json_body()?;
+    if body["considered_document_ids"] != json!([1]) {
+        return Err(anyhow!(
+            "Apply suggestion considered documents user 2 cannot read: {}",
+            body["considered_document_ids"]
+        ));
+    }
+
+    // User 2 has no permission on document 2
+    let response = hc2
+        .do_post(
+            &format!("/api/writing-assistant/{}/apply-suggestion", session_id),
+            json!({
+                "suggestion_content": "Fix the grammar in this document.",
+                "current_document_id": 2
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if response.status() != 403 {
+        return Err(anyhow!(
+            "Apply suggestion on an unreadable document returned {}",
+            response.status()
+        ));
+    }
+
+    Ok(())
+}
+
+async fn test_send_message_returns_valid_citations(hc: &Client) -> Result<()> {
+    println!("TEST - Send Message Returns Valid Citations");
+
+    let doc_id = create_private_document(
+        hc,
+        "Lighthouse Notes",
+        "<h1>The Lighthouse</h1><p>The lighthouse keeper Elena lights the lamp every evening at dusk.</p>",
+    )
+    .await?;
+    wait_for_embedding_queue(hc).await?;
+
+    let session_response = hc
+        .do_post(
+            "/api/writing-assistant",
+            json!({
+                "title": "Citation Check",
+                "document_id": doc_id
+            }),
+        )
+        .await?;
+    let session_id = session_response.json_body()?["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Created session has no id"))?;
+
+    let message_response = hc
+        .do_post(
+            &format!("/api/writing-assistant/{}/message", session_id),
+            json!({
+                "content": "When does the lighthouse keeper light the lamp?"
+            }),
+        )
+        .await?;
+    message_response.print().await?;
+
+    if !message_response.status().is_success() {
+        return Err(anyhow!(
+            "Send message failed with status: {}",
+            message_response.status()
+        ));
+    }
+
+    let body = message_response.json_body()?;
+    let citations = body["citations"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Response is missing the citations list"))?;
+    let sources = body["sources"].as_array().cloned().unwrap_or_default();
+    let content = body["content"].as_str().unwrap_or_default();
+
+    // Every citation must point at a document that was placed in the prompt,
+    // and its label must appear in the answer
+    for citation in citations {
+        let label = citation["label"].as_str().unwrap_or_default();
+        if !content.contains(&format!("[{}", label)) {
+            return Err(anyhow!("Citation {} is not referenced in the answer", label));
+        }
+        if !sources.iter().any(|s| s["document_id"] == citation["document_id"]) {
+            return Err(anyhow!("Citation {} points outside the retrieved sources", citation));
+        }
+    }
+
+    // Citations are stored with the message
+    let session = hc
+        .do_get(&format!("/api/writing-assistant/{}", session_id))
+        .await?
+        .json_body()?;
+    let stored = session["messages"]
+        .as_array()
+        .and_then(|messages| messages.last())
+        .map(|message| message["citations"].clone())
+        .ok_or_else(|| anyhow!("Session has no messages"))?;
+    if stored.as_array().map(|c| c.len()) != Some(citations.len()) {
+        return Err(anyhow!("Stored citations {} differ from the response", stored));
+    }
+
+    Ok(())
+}
+
+async fn test_session_summary_after_threshold(hc: &Client) -> Result<()> {
+    println!("TEST - Session Summary After Threshold");
+
+    let session_response = hc
+        .do_post(
+            "/api/writing-assistant",
+            json!({
+                "title": "Long Conversation",
+                "document_id": null
+            }),
+        )
+        .await?;
+    let session_id = session_response.json_body()?["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Created session has no id"))?;
+    let message_url = format!("/api/writing-assistant/{}/message", session_id);
+    let ledger_start = backend::last_ledger_id(hc).await?;
+
+    // A short conversation stays below the trigger and is not summarized
+    let response = hc.do_post(&message_url, json!({ "content": "How should the first chapter open?" })).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Send message failed with status: {}", response.status()));
+    }
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    if backend::credit_charges_since(hc, "session_summary", ledger_start).await? != 0 {
+        return Err(anyhow!("A short conversation was summarized"));
+    }
+
+    // A message longer than the history share of the default prompt budget crosses it
+    let long_message = "The lighthouse keeper wrote every storm into the logbook. ".repeat(400);
+    let response = hc.do_post(&message_url, json!({ "content": long_message })).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Send long message failed with status: {}", response.status()));
+    }
+
+    // The summary is written in the background and charged to the owner of the session
+    for _ in 0..15 {
+        if backend::credit_charges_since(hc, "session_summary", ledger_start).await? == 1 {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+
+    Err(anyhow!("No session summary was charged after crossing the threshold"))
+}
+
+// Input budget of the mock model: the default context window of 8192 tokens less the 1024 kept for the answer
+const MOCK_INPUT_TOKENS: i64 = 8192 - 1024;
+
+async fn test_prompt_budget_keeps_system_prompt_and_query(hc: &Client) -> Result<()> {
+    println!("TEST - Prompt Budget Keeps System Prompt And Query");
+
+    let session_response = hc
+        .do_post(
+            "/api/writing-assistant",
+            json!({
+                "title": "Overflowing Conversation",
+                "document_id": null
+            }),
+        )
+        .await?;
+    let session_id = session_response.json_body()?["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Created session has no id"))?;
+    let system_prompt = "You are a grizzled sea captain reviewing a whaling novel.";
+    let response = hc
+        .do_put(
+            &format!("/api/writing-assistant/{}/persona", session_id),
+            json!({ "persona_id": null, "system_prompt": system_prompt }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Set system prompt failed with status: {}", response.status()));
+    }
+    let message_url = format!("/api/writing-assistant/{}/message", session_id);
+    let ledger_start = backend::last_ledger_id(hc).await?;
+
+    // Three long exchanges make a history several times the input budget
+    let long_message = "The whale surfaced beside the harbour wall at dawn. ".repeat(300);
+    for _ in 0..3 {
+        let response = hc.do_post(&message_url, json!({ "content": long_message })).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Send long message failed with status: {}", response.status()));
+        }
+    }
+
+    // The history is cut, the system prompt and the question are sent whole
+    let question = "Where did the whale surface?";
+    let response = hc.do_post(&message_url, json!({ "content": question })).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Send question failed with status: {}", response.status()));
+    }
+    let content = response.json_body()?["content"].as_str().unwrap_or_default().to_string();
+    if !content.starts_with(&format!("Mock assistant response to: {}", question)) {
+        return Err(anyhow!("The question was not sent whole: {}", content));
+    }
+    if !content.ends_with(&format!("(system prompt: {})", system_prompt)) {
+        return Err(anyhow!("The system prompt was not sent: {}", content));
+    }
+
+    // Every prompt fits the budget, and the last one fills it with history instead of dropping it all
+    let history = hc.do_get("/api/users/credits?limit=200").await?.json_body()?;
+    let prompt_tokens: Vec<i64> = history["entries"]
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .filter(|entry| entry["id"].as_i64().unwrap_or(0) > ledger_start && entry["operation"] == "chat")
+                .filter_map(|entry| entry["prompt_tokens"].as_i64())
+                .collect()
+        })
+        .unwrap_or_default();
+    if prompt_tokens.len() != 4 || prompt_tokens.iter().any(|tokens| *tokens > MOCK_INPUT_TOKENS) {
+        return Err(anyhow!("Chat prompts exceed the budget of {} tokens: {:?}", MOCK_INPUT_TOKENS, prompt_tokens));
+    }
+    // Newest first
+    if prompt_tokens[0] < MOCK_INPUT_TOKENS / 2 {
+        return Err(anyhow!("The last prompt left most of the budget unused: {:?}", prompt_tokens));
+    }
+
+    Ok(())
+}
+
+// Number of embedding jobs with the given status
+async fn embedding_job_count(hc: &Client, status: &str) -> Result<i64> {
+    let response = hc.do_get("/api/db/embeddings/jobs?secret=secret_key").await?;
+    let body = response.json_body()?;
+    Ok(body["result"]["jobs"]
+        .as_array()
+        .and_then(|jobs| jobs.iter().find(|job| job["status"] == status))
+        .and_then(|job| job["count"].as_i64())
+        .unwrap_or(0))
+}
+
+async fn test_retry_dead_embeddings(hc: &Client) -> Result<()> {
+    println!("TEST - Retry Dead Embeddings");
+
+    // The mock provider fails to embed this document, with EMBEDDING_JOB_MAX_ATTEMPTS=1 its job dies at once
+    wait_for_embedding_queue(hc).await?;
+    let dead_before = embedding_job_count(hc, "dead").await?;
+    create_private_document(hc, "Unembeddable", "This text contains MOCK_EMBEDDING_FAILURE.").await?;
+    wait_for_embedding_queue(hc).await?;
+
+    let dead = embedding_job_count(hc, "dead").await?;
+    if dead <= dead_before {
+        return Err(anyhow!("The failing job was not dead-lettered, {} dead jobs", dead));
+    }
+
+    let response = hc.do_post("/api/db/embeddings/retry-dead?secret=wrong", json!({})).await?;
+    if response.status().is_success() {
+        return Err(anyhow!("Retrying dead jobs with a wrong secret succeeded"));
+    }
+
+    let response = hc.do_post("/api/db/embeddings/retry-dead?secret=secret_key", json!({})).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Retry dead embeddings failed with status: {}", response.status()));
+    }
+    let requeued = response.json_body()?["result"]["requeued"].as_i64().unwrap_or(-1);
+    if requeued < 1 || requeued > dead {
+        return Err(anyhow!("Unexpected number of requeued jobs: {}", requeued));
+    }
+
+    // The revived jobs run again and die again, they are moved rather than copied
+    wait_for_embedding_queue(hc).await?;
+    let dead_after = embedding_job_count(hc, "dead").await?;
+    if dead_after > dead {
+        return Err(anyhow!("Expected at most {} dead jobs after the retry, found {}", dead, dead_after));
+    }
+
+    Ok(())
+}
+
+async fn test_credit_history_records_usage(hc: &Client) -> Result<()> {
+    println!("TEST - Credit History Records Usage");
+
+    let before = hc.do_get("/api/users/credits").await?.json_body()?;
+    let balance_before = before["balance"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Credit history has no balance"))?;
+
+    let response = hc
+        .do_post(
+            "/api/writing-assistant/spellcheck",
+            json!({
+                "content": "Teh quick brown fox."
+            }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Spell check failed with status: {}", response.status()));
+    }
+
+    let after = hc.do_get("/api/users/credits?limit=5").await?;
+    after.print().await?;
+    let after = after.json_body()?;
+    let latest = &after["entries"][0];
+
+    if latest["operation"] != "spellcheck" {
+        return Err(anyhow!("Latest ledger entry is not the spell check: {}", latest));
+    }
+    let amount = latest["amount"].as_i64().unwrap_or(0);
+    if amount >= 0 {
+        return Err(anyhow!("Spell check was not recorded as a debit: {}", latest));
+    }
+    if after["balance"].as_i64() != Some(balance_before + amount) {
+        return Err(anyhow!(
+            "Balance {} does not match {} {}",
+            after["balance"], balance_before, amount
+        ));
+    }
+    if latest["prompt_tokens"].as_i64().unwrap_or(0) <= 0 {
+        return Err(anyhow!("Prompt tokens were not recorded: {}", latest));
+    }
+    if !latest["prompt_template"].as_str().unwrap_or("").starts_with("spell_check@v") {
+        return Err(anyhow!("Prompt template was not recorded: {}", latest));
+    }
+
+    Ok(())
+}
+
+async fn test_messages_record_prompt_template(hc: &Client) -> Result<()> {
+    println!("TEST - Messages Record Prompt Template");
+
+    let response = hc.do_get("/api/writing-assistant/1").await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Get session failed with status: {}", response.status()));
+    }
+
+    let body = response.json_body()?;
+    let messages = body["messages"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Session has no messages array"))?;
+    // The welcome message (the only one without a parent) is not generated
+    let assistant_messages: Vec<_> = messages
+        .iter()
+        .filter(|message| message["role"] == "Assistant" && !message["parent_message_id"].is_null())
+        .collect();
+    if assistant_messages.is_empty() {
+        return Err(anyhow!("Session has no assistant messages"));
+    }
+
+    // Every answer knows the template version that produced it, user messages have none
+    for message in assistant_messages {
+        if !message["prompt_template"].as_str().unwrap_or("").starts_with("chat@v") {
+            return Err(anyhow!("Assistant message without prompt template: {}", message));
+        }
+    }
+    if messages.iter().any(|message| message["role"] == "User" && !message["prompt_template"].is_null()) {
+        return Err(anyhow!("User message has a prompt template"));
+    }
+
+    Ok(())
+}
+
+// Last message with the given role on the active branch of session 1
+async fn last_session_message_id(hc: &Client, role: &str) -> Result<i64> {
+    let response = hc.do_get("/api/writing-assistant/1").await?;
+    let body = response.json_body()?;
+    body["messages"]
+        .as_array()
+        .and_then(|messages| messages.iter().rev().find(|message| message["role"] == role))
+        .and_then(|message| message["id"].as_i64())
+        .ok_or_else(|| anyhow!("Session 1 has no {} message", role))
+}
+
+async fn test_regenerate_writing_message_success(hc: &Client) -> Result<()> {
+    println!("TEST - Regenerate Writing Message");
+
+    let answer_id = last_session_message_id(hc, "Assistant").await?;
+    let response = hc
+        .do_post(&format!("/api/writing-assistant/1/messages/{}/regenerate", answer_id), json!({}))
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Regenerate failed with status: {}", response.status()));
+    }
+
+    // The new answer is a sibling of the old one and the session moved to it
+    let body = response.json_body()?;
+    let new_answer_id = body["message_id"].as_i64().ok_or_else(|| anyhow!("Response has no message_id"))?;
+    if new_answer_id == answer_id || last_session_message_id(hc, "Assistant").await? != new_answer_id {
+        return Err(anyhow!("Session is not on the regenerated answer"));
+    }
+
+    Ok(())
+}
+
+async fn test_edit_writing_message_success(hc: &Client) -> Result<()> {
+    println!("TEST - Edit Writing Message");
+
+    let question_id = last_session_message_id(hc, "User").await?;
+    let response = hc
+        .do_post(
+            &format!("/api/writing-assistant/1/messages/{}/edit", question_id),
+            json!({ "content": "Could you help me tighten my opening paragraph instead?" }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Edit message failed with status: {}", response.status()));
+    }
+    if last_session_message_id(hc, "User").await? == question_id {
+        return Err(anyhow!("Edited message did not start a new branch"));
+    }
+
+    // Only user messages can be edited
+    let answer_id = last_session_message_id(hc, "Assistant").await?;
+    let response = hc
+        .do_post(
+            &format!("/api/writing-assistant/1/messages/{}/edit", answer_id),
+            json!({ "content": "Not allowed" }),
+        )
+        .await?;
+    if response.status().is_success() {
+        return Err(anyhow!("Editing an assistant message should fail"));
+    }
+
+    Ok(())
+}
+
+// Sets the balance of user 1 through the admin grant endpoint
+async fn set_balance(hc: &Client, balance: i64) -> Result<()> {
+    let current = hc.do_get("/api/users/credits").await?.json_body()?["balance"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Credit history has no balance"))?;
+    if current == balance {
+        return Ok(());
+    }
+    let response = hc
+        .do_post(
+            &format!("/api/db/credits/grant?secret={}", admin_secret()),
+            json!({ "user_id": 1, "amount": balance - current, "note": "Test balance" }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Setting the balance failed with status: {}", response.status()));
+    }
+    Ok(())
+}
+
+async fn test_failed_edit_keeps_branch(hc: &Client) -> Result<()> {
+    println!("TEST - Failed Edit Keeps Branch");
+
+    let session = hc.do_get("/api/writing-assistant/1").await?.json_body()?;
+    let active_before = session["session"]["active_message_id"].clone();
+    let branches_before = hc.do_get("/api/writing-assistant/1/branches").await?.json_body()?;
+    let balance = hc.do_get("/api/users/credits").await?.json_body()?["balance"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Credit history has no balance"))?;
+
+    // Enough to start the edit, not enough to answer it
+    set_balance(hc, 1).await?;
+    let question_id = last_session_message_id(hc, "User").await?;
+    let response = hc
+        .do_post(
+            &format!("/api/writing-assistant/1/messages/{}/edit", question_id),
+            json!({ "content": "This edit cannot be answered" }),
+        )
+        .await?;
+    let status = response.status();
+    set_balance(hc, balance).await?;
+    if status != 402 {
+        return Err(anyhow!("Edit without credits returned {}", status));
+    }
+
+    // Neither the edited message nor a branch for it was stored
+    let session = hc.do_get("/api/writing-assistant/1").await?.json_body()?;
+    if session["session"]["active_message_id"] != active_before {
+        return Err(anyhow!("The failed edit moved the session from {} to {}", active_before, session["session"]["active_message_id"]));
+    }
+    let branches_after = hc.do_get("/api/writing-assistant/1/branches").await?.json_body()?;
+    if branches_after != branches_before {
+        return Err(anyhow!("The failed edit changed the branches: {}", branches_after));
+    }
+
+    Ok(())
+}
+
+async fn test_get_writing_branches_success(hc: &Client) -> Result<()> {
+    println!("TEST - Get Writing Branches");
+
+    let response = hc.do_get("/api/writing-assistant/1/branches").await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Get branches failed with status: {}", response.status()));
+    }
+
+    // Regenerating and editing each left a branch behind
+    let body = response.json_body()?;
+    let branches = body.as_array().ok_or_else(|| anyhow!("Branches is not an array"))?;
+    if branches.len() < 3 {
+        return Err(anyhow!("Expected at least 3 branches, got {}", branches.len()));
+    }
+    if branches.iter().filter(|branch| branch["is_active"] == true).count() != 1 {
+        return Err(anyhow!("Expected exactly one active branch"));
+    }
+
+    Ok(())
+}
+
+async fn test_switch_writing_branch_success(hc: &Client) -> Result<()> {
+    println!("TEST - Switch Writing Branch");
+
+    let response = hc.do_get("/api/writing-assistant/1/branches").await?;
+    let body = response.json_body()?;
+    let leaf_id = body
+        .as_array()
+        .and_then(|branches| branches.iter().find(|branch| branch["is_active"] == false))
+        .and_then(|branch| branch["leaf_message_id"].as_i64())
+        .ok_or_else(|| anyhow!("No inactive branch to switch to"))?;
+
+    let response = hc
+        .do_put(&format!("/api/writing-assistant/1/branches/{}", leaf_id), json!({}))
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Switch branch failed with status: {}", response.status()));
+    }
+
+    // The returned branch and the session both end at the chosen message
+    let body = response.json_body()?;
+    let last_id = body["messages"].as_array().and_then(|messages| messages.last()).and_then(|message| message["id"].as_i64());
+    if last_id != Some(leaf_id) || body["session"]["active_message_id"].as_i64() != Some(leaf_id) {
+        return Err(anyhow!("Session did not switch to branch {}", leaf_id));
+    }
+
+    Ok(())
+}
+
+async fn test_get_personas_success(hc: &Client) -> Result<()> {
+    println!("TEST - Get Personas");
+
+    let response = hc.do_get("/api/writing-assistant/personas").await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Get personas failed with status: {}", response.status()));
+    }
+
+    // The built-in personas come first
+    let body = response.json_body()?;
+    let personas = body.as_array().ok_or_else(|| anyhow!("Personas is not an array"))?;
+    if !personas.iter().any(|persona| persona["name"] == "Developmental editor" && persona["user_id"].is_null()) {
+        return Err(anyhow!("Built-in personas are missing"));
+    }
+
+    Ok(())
+}
+
+// Id of the user's own persona named `name`
+async fn own_persona_id(hc: &Client, name: &str) -> Result<Option<i64>> {
+    let response = hc.do_get("/api/writing-assistant/personas").await?;
+    let body = response.json_body()?;
+    Ok(body
+        .as_array()
+        .and_then(|personas| personas.iter().find(|persona| persona["name"] == name && !persona["user_id"].is_null()))
+        .and_then(|persona| persona["id"].as_i64()))
+}
+
+async fn test_create_persona_success(hc: &Client) -> Result<()> {
+    println!("TEST - Create Persona");
+
+    let response = hc
+        .do_post(
+            "/api/writing-assistant/personas",
+            json!({
+                "name": "Poetry critic",
+                "description": "Feedback on rhythm and imagery",
+                "system_prompt": "You are a poetry critic. Comment on rhythm, sound and imagery."
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Create persona failed with status: {}", response.status()));
+    }
+    if own_persona_id(hc, "Poetry critic").await?.is_none() {
+        return Err(anyhow!("Created persona is not listed"));
+    }
+
+    // A persona needs a system prompt
+    let response = hc
+        .do_post("/api/writing-assistant/personas", json!({ "name": "Empty", "system_prompt": "  " }))
+        .await?;
+    if response.status().is_success() {
+        return Err(anyhow!("Persona without system prompt should be rejected"));
+    }
+
+    Ok(())
+}
+
+async fn test_set_session_persona_success(hc: &Client) -> Result<()> {
+    println!("TEST - Set Session Persona");
+
+    let persona_id = own_persona_id(hc, "Poetry critic").await?.ok_or_else(|| anyhow!("Persona missing"))?;
+    let response = hc
+        .do_put("/api/writing-assistant/1/persona", json!({ "persona_id": persona_id, "system_prompt": null }))
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Set session persona failed with status: {}", response.status()));
+    }
+    let body = response.json_body()?;
+    if body["persona_id"].as_i64() != Some(persona_id) {
+        return Err(anyhow!("Session persona was not stored"));
+    }
+
+    // The persona is used for the next answer
+    let response = hc
+        .do_post("/api/writing-assistant/1/message", json!({ "content": "What do you think of my last stanza?" }))
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Message with persona failed with status: {}", response.status()));
+    }
+
+    // Unknown personas are rejected
+    let response = hc
+        .do_put("/api/writing-assistant/1/persona", json!({ "persona_id": 999999, "system_prompt": null }))
+        .await?;
+    if response.status().is_success() {
+        return Err(anyhow!("Setting an unknown persona should fail"));
+    }
+
+    Ok(())
+}
+
+async fn test_delete_persona_success(hc: &Client) -> Result<()> {
+    println!("TEST - Delete Persona");
+
+    let persona_id = own_persona_id(hc, "Poetry critic").await?.ok_or_else(|| anyhow!("Persona missing"))?;
+    let response = hc.do_delete(&format!("/api/writing-assistant/personas/{}", persona_id)).await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Delete persona failed with status: {}", response.status()));
+    }
+
+    // The session that used it goes back to the default
+    let body = hc.do_get("/api/writing-assistant/1").await?.json_body()?;
+    if !body["session"]["persona_id"].is_null() {
+        return Err(anyhow!("Session still refers to the deleted persona"));
+    }
+
+    // Built-in personas cannot be deleted
+    let response = hc.do_delete("/api/writing-assistant/personas/1").await?;
+    if response.status().is_success() {
+        return Err(anyhow!("Deleting a built-in persona should fail"));
+    }
+
+    Ok(())
+}
+
+async fn test_session_title_generated(hc: &Client) -> Result<()> {
+    println!("TEST - Session Title Generated");
+
+    let response = hc
+        .do_post("/api/writing-assistant", json!({ "document_id": null, "persona_id": 3 }))
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Create session without title failed with status: {}", response.status()));
+    }
+    let body = response.json_body()?;
+    let session_id = body["id"].as_i64().ok_or_else(|| anyhow!("Session has no id"))?;
+    if body["auto_title"] != true {
+        return Err(anyhow!("Session without title is not marked for a generated title"));
+    }
+
+    let response = hc
+        .do_post(
+            &format!("/api/writing-assistant/{}/message", session_id),
+            json!({ "content": "Please review the error handling section of my API guide." }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Send message failed with status: {}", response.status()));
+    }
+
+    // The title is generated in the background, the session is claimed before the title is stored
+    for _ in 0..15 {
+        let body = hc.do_get(&format!("/api/writing-assistant/{}", session_id)).await?.json_body()?;
+        let title = body["session"]["title"].as_str().unwrap_or("");
+        if body["session"]["auto_title"] == false && !title.is_empty() && title != "New Writing Session" {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+
+    Err(anyhow!("Session title was not generated in time"))
+}
+
+async fn test_agent_writing_message_success(hc: &Client) -> Result<()> {
+    println!("TEST - Agent Writing Message");
+
+    let response = hc
+        .do_post(
+            "/api/writing-assistant/1/agent",
+            json!({ "content": "Which of my documents never mention Elena?" }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Agent message failed with status: {}", response.status()));
+    }
+
+    // The mock model lists the outline before answering
+    let body = response.json_body()?;
+    let message_id = body["message_id"].as_i64().ok_or_else(|| anyhow!("Response has no message_id"))?;
+    let tool_calls = body["tool_calls"].as_array().ok_or_else(|| anyhow!("Response has no tool_calls"))?;
+    if !tool_calls.iter().any(|call| call["tool"] == "list_outline" && call["status"] == "ok") {
+        return Err(anyhow!("Agent did not call list_outline"));
+    }
+    if !body["content"].as_str().unwrap_or("").starts_with("Mock agent answer to:") {
+        return Err(anyhow!("Unexpected agent answer: {}", body["content"]));
+    }
+    if !body["prompt_template"].as_str().unwrap_or("").starts_with("agent_step@v") {
+        return Err(anyhow!("Agent answer has no prompt template"));
+    }
+
+    // The calls are logged in the session and linked to the answer
+    let response = hc.do_get("/api/writing-assistant/1/tool-calls").await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Get tool calls failed with status: {}", response.status()));
+    }
+    let logged = response.json_body()?;
+    if !logged
+        .as_array()
+        .map(|calls| calls.iter().any(|call| call["message_id"].as_i64() == Some(message_id)))
+        .unwrap_or(false)
+    {
+        return Err(anyhow!("Tool calls were not linked to answer {}", message_id));
+    }
+
+    Ok(())
+}
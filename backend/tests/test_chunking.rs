@@ -0,0 +1,308 @@
+#![allow(unused)]
+
+use anyhow::{anyhow, Result};
+use backend::result_to_string;
+use chrono::Utc;
+use httpc_test::Client;
+use pgvector::Vector;
+use serde_json::json;
+use sqlx::PgPool;
+
+// The API only shows chunks as retrieved passages, so the stored chunks are read straight from the
+// database of the server under test (DATABASE_URL, see the .env file).
+const HARBOUR_HTML: &str = "<h1>Harbour</h1><p>Salt &amp; rope were stored in the <em>harbour</em> shed.</p>";
+// The first section as plain text, without the markup and entities the chunker has to strip
+const HARBOUR_TEXT: &str = "# Harbour\n\nSalt & rope were stored in the harbour shed.";
+// rag::chunk::CHUNK_OVERLAP_CHARS
+const CHUNK_OVERLAP_CHARS: usize = 200;
+
+struct Documents {
+    sections: i32,
+    empty: i32,
+}
+
+// A row of document_chunks
+struct StoredChunk {
+    heading: Option<String>,
+    content: String,
+    start_offset: usize,
+    end_offset: usize,
+    embedding: Vec<f32>,
+}
+
+// Heading, content, offsets and embedding as read from the database
+type ChunkRow = (Option<String>, String, i32, i32, Option<Vector>);
+
+#[tokio::test]
+async fn test_chunking() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+    dotenvy::dotenv().ok();
+    let pool = PgPool::connect(&std::env::var("DATABASE_URL")?).await?;
+
+    println!("\n===== RUNNING CHUNKING TESTS =====\n");
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let setup = setup_documents(&hc, &pool).await;
+    let (headings, overlap, offsets, embedding, empty) = match &setup {
+        Ok(documents) => (
+            test_heading_boundaries(&pool, documents).await,
+            test_chunk_overlap(&pool, documents).await,
+            test_chunk_offsets(&pool, documents).await,
+            test_document_embedding(&pool, documents).await,
+            test_empty_document(&pool, documents).await,
+        ),
+        Err(_) => (
+            Err(anyhow!("Skipped")),
+            Err(anyhow!("Skipped")),
+            Err(anyhow!("Skipped")),
+            Err(anyhow!("Skipped")),
+            Err(anyhow!("Skipped")),
+        ),
+    };
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Documents Setup\t\t{}", result_to_string(&setup.as_ref().map(|_| ()).map_err(|e| anyhow!("{}", e))));
+    println!("Heading Boundaries\t{}", result_to_string(&headings));
+    println!("Chunk Overlap\t\t{}", result_to_string(&overlap));
+    println!("Chunk Offsets\t\t{}", result_to_string(&offsets));
+    println!("Document Embedding\t{}", result_to_string(&embedding));
+    println!("Empty Document\t\t{}", result_to_string(&empty));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+// Two paragraphs of about 650 characters each, together too long for one chunk
+fn storm_paragraphs() -> (String, String) {
+    let paragraph = |from: usize| (from..from + 20).map(|i| format!("Storm {} broke the harbour wall.", i)).collect::<Vec<_>>().join(" ");
+    (paragraph(1), paragraph(21))
+}
+
+// A short section under a heading, then a section too long for one chunk
+fn sections_html() -> String {
+    let (first, second) = storm_paragraphs();
+    format!("{}<h2>Storms</h2><p>{}</p><p>{}</p>", HARBOUR_HTML, first, second)
+}
+
+// The plain text the chunker works on, see rag::chunk::to_plain_text
+fn sections_text() -> String {
+    let (first, second) = storm_paragraphs();
+    format!("{}\n\n## Storms\n\n{}\n\n{}", HARBOUR_TEXT, first, second)
+}
+
+// Creates and saves a document, saving is what gets it embedded
+async fn create_document(hc: &Client, name: &str, content: &str) -> Result<i32> {
+    let now = Utc::now().naive_utc();
+    let response = hc
+        .do_post("/api/document", json!({ "name": name, "content": content, "created_at": now, "updated_at": now }))
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Creating {} failed with status: {}", name, response.status()));
+    }
+    let document_id = response.json_body()?["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Created document has no id"))?;
+
+    let response = hc
+        .do_put(
+            &format!("/api/document/{}", document_id),
+            json!({ "name": name, "content": content, "updated_at": Utc::now().naive_utc() }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Saving {} failed with status: {}", name, response.status()));
+    }
+
+    Ok(document_id as i32)
+}
+
+// Waits until a saved document has been embedded
+async fn wait_for_embedding(pool: &PgPool, document_id: i32) -> Result<()> {
+    for _ in 0..60 {
+        let (embedded,): (bool,) = sqlx::query_as("SELECT embedding_updated_at IS NOT NULL FROM documents WHERE id = $1")
+            .bind(document_id)
+            .fetch_one(pool)
+            .await?;
+        if embedded {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    }
+
+    Err(anyhow!("Document {} was not embedded in time", document_id))
+}
+
+async fn setup_documents(hc: &Client, pool: &PgPool) -> Result<Documents> {
+    println!("TEST - Documents Setup");
+
+    let documents = Documents {
+        sections: create_document(hc, "Sections", &sections_html()).await?,
+        empty: create_document(hc, "Empty", "<p> </p><br>").await?,
+    };
+    wait_for_embedding(pool, documents.sections).await?;
+    wait_for_embedding(pool, documents.empty).await?;
+    Ok(documents)
+}
+
+// The stored chunks of a document in order
+async fn stored_chunks(pool: &PgPool, document_id: i32) -> Result<Vec<StoredChunk>> {
+    let rows: Vec<ChunkRow> = sqlx::query_as(
+        "SELECT heading, content, start_offset, end_offset, embedding FROM document_chunks WHERE document_id = $1 ORDER BY chunk_index",
+    )
+    .bind(document_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(heading, content, start_offset, end_offset, embedding)| StoredChunk {
+            heading,
+            content,
+            start_offset: start_offset as usize,
+            end_offset: end_offset as usize,
+            embedding: embedding.map(|embedding| embedding.to_vec()).unwrap_or_default(),
+        })
+        .collect())
+}
+
+// The three chunks of the sections document: the first section, then the long one split in two
+async fn section_chunks(pool: &PgPool, documents: &Documents) -> Result<Vec<StoredChunk>> {
+    let chunks = stored_chunks(pool, documents.sections).await?;
+    if chunks.len() != 3 {
+        return Err(anyhow!("Expected 3 chunks, got {}: {:?}", chunks.len(), chunks.iter().map(|chunk| &chunk.content).collect::<Vec<_>>()));
+    }
+    Ok(chunks)
+}
+
+async fn test_heading_boundaries(pool: &PgPool, documents: &Documents) -> Result<()> {
+    println!("TEST - Heading Boundaries");
+
+    let chunks = section_chunks(pool, documents).await?;
+    let headings: Vec<Option<&str>> = chunks.iter().map(|chunk| chunk.heading.as_deref()).collect();
+    if headings != [Some("Harbour"), Some("Storms"), Some("Storms")] {
+        return Err(anyhow!("Unexpected chunk headings: {:?}", headings));
+    }
+
+    // Every heading starts a chunk, and no chunk reaches into the next section
+    if chunks[0].content != HARBOUR_TEXT || !chunks[1].content.starts_with("## Storms\n\n") {
+        return Err(anyhow!("Sections were not split at their headings: {:?}, {:?}", chunks[0].content, chunks[1].content));
+    }
+    if chunks[0].end_offset >= chunks[1].start_offset {
+        return Err(anyhow!("The first section overlaps the second"));
+    }
+
+    Ok(())
+}
+
+async fn test_chunk_overlap(pool: &PgPool, documents: &Documents) -> Result<()> {
+    println!("TEST - Chunk Overlap");
+
+    // The long section is split between its paragraphs, the third chunk repeats the end of the second
+    let chunks = section_chunks(pool, documents).await?;
+    let (first, second) = storm_paragraphs();
+    let (previous, next) = (&chunks[1], &chunks[2]);
+    if next.start_offset >= previous.end_offset || previous.end_offset - next.start_offset > CHUNK_OVERLAP_CHARS {
+        return Err(anyhow!("Unexpected overlap of {} characters", previous.end_offset as i64 - next.start_offset as i64));
+    }
+
+    let overlap = previous.end_offset - next.start_offset;
+    let repeated: String = previous.content.chars().skip(previous.content.chars().count() - overlap).collect();
+    if !previous.content.ends_with(&first) || !next.content.starts_with(&repeated) || !next.content.ends_with(&second) {
+        return Err(anyhow!("The third chunk does not continue the second: {:?}", next.content));
+    }
+    // The overlap starts at a word
+    if repeated.starts_with(' ') || !first.contains(&format!(" {}", repeated)) {
+        return Err(anyhow!("Overlap does not start at a word: {:?}", repeated));
+    }
+
+    Ok(())
+}
+
+async fn test_chunk_offsets(pool: &PgPool, documents: &Documents) -> Result<()> {
+    println!("TEST - Chunk Offsets");
+
+    // Offsets are characters of the plain text, each chunk is exactly the text between them
+    let chunks = section_chunks(pool, documents).await?;
+    let text = sections_text();
+    for chunk in &chunks {
+        let expected: String = text.chars().skip(chunk.start_offset).take(chunk.end_offset - chunk.start_offset).collect();
+        if chunk.content != expected {
+            return Err(anyhow!("Chunk does not match its offsets: {:?} != {:?}", chunk.content, expected));
+        }
+    }
+    // Together the chunks cover the document
+    if chunks[0].start_offset != 0 || chunks[2].end_offset != text.chars().count() {
+        return Err(anyhow!("Chunks do not cover the document"));
+    }
+
+    Ok(())
+}
+
+async fn test_document_embedding(pool: &PgPool, documents: &Documents) -> Result<()> {
+    println!("TEST - Document Embedding");
+
+    // Every chunk is embedded, and the document embedding is their mean
+    let chunks = section_chunks(pool, documents).await?;
+    if chunks.iter().any(|chunk| chunk.embedding.is_empty()) {
+        return Err(anyhow!("A chunk was stored without an embedding"));
+    }
+    let (embedding,): (Option<Vector>,) = sqlx::query_as("SELECT embedding FROM documents WHERE id = $1")
+        .bind(documents.sections)
+        .fetch_one(pool)
+        .await?;
+    let embedding = embedding.ok_or_else(|| anyhow!("The document has no embedding"))?.to_vec();
+
+    let mean: Vec<f32> = (0..embedding.len())
+        .map(|i| chunks.iter().map(|chunk| chunk.embedding[i]).sum::<f32>() / chunks.len() as f32)
+        .collect();
+    if let Some(i) = (0..embedding.len()).find(|&i| (embedding[i] - mean[i]).abs() > 1e-5) {
+        return Err(anyhow!("Document embedding differs from the chunk mean at {}: {} != {}", i, embedding[i], mean[i]));
+    }
+
+    Ok(())
+}
+
+async fn test_empty_document(pool: &PgPool, documents: &Documents) -> Result<()> {
+    println!("TEST - Empty Document");
+
+    // Markup without text has no chunks, and so no document embedding
+    let chunks = stored_chunks(pool, documents.empty).await?;
+    if !chunks.is_empty() {
+        return Err(anyhow!("Empty document has chunks: {:?}", chunks.iter().map(|chunk| &chunk.content).collect::<Vec<_>>()));
+    }
+    let (embedded,): (bool,) = sqlx::query_as("SELECT embedding IS NOT NULL FROM documents WHERE id = $1")
+        .bind(documents.empty)
+        .fetch_one(pool)
+        .await?;
+    if embedded {
+        return Err(anyhow!("Empty document has an embedding"));
+    }
+
+    Ok(())
+}
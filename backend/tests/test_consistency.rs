@@ -0,0 +1,279 @@
+#![allow(unused)]
+
+use anyhow::{anyhow, Result};
+use backend::{expect_success, result_to_string};
+use chrono::Utc;
+use httpc_test::Client;
+use serde_json::{json, Value};
+
+// Past tense, third person, like the style guide asks for. The mock model reports the last sentence
+// of every document as a contradiction, so each chapter gets one finding from the model.
+const CHAPTER_ONE: &str = "<p>Mira Holt sailed to Eldham. She was tired, and Mira saw the harbour burn.</p>";
+// A misspelled name, present tense, first person, a glossary term in the wrong case and a contradiction
+const CHAPTER_TWO: &str = "<p>Mria walks to the harbour. I am cold and the VYNN is late.</p><p>Mira never sailed to Eldham.</p>";
+
+#[tokio::test]
+async fn test_consistency() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+
+    println!("\n===== RUNNING CONSISTENCY CHECK API TESTS =====\n");
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let setup = setup_project(&hc).await;
+    let check = match &setup {
+        Ok(chapter_two) => test_consistency_check(&hc, *chapter_two).await,
+        Err(_) => Err(anyhow!("Project setup failed")),
+    };
+    let rules_only = test_rules_only_check(&hc).await;
+    let reports = test_consistency_reports(&hc).await;
+    let invalid = test_invalid_consistency_check(&hc).await;
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Project Setup\t\t{}", result_to_string(&setup.map(|_| ())));
+    println!("Consistency Check\t{}", result_to_string(&check));
+    println!("Rules Only Check\t{}", result_to_string(&rules_only));
+    println!("Consistency Reports\t{}", result_to_string(&reports));
+    println!("Invalid Check\t\t{}", result_to_string(&invalid));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+// Project 2 gets a style guide, a character, a glossary term and two chapters. Returns the id of chapter two.
+async fn setup_project(hc: &Client) -> Result<i64> {
+    println!("TEST - Project Setup");
+
+    let response = hc.do_put("/api/project/2/style-guide", json!({ "style_guide": "Past tense, third person." })).await?;
+    expect_success(response, "Setting the style guide").await?;
+    let response = hc
+        .do_post("/api/project/2/entities", json!({ "kind": "character", "name": "Mira Holt", "aliases": ["Mira"] }))
+        .await?;
+    expect_success(response, "Adding a character").await?;
+    let response = hc.do_post("/api/project/2/glossary", json!({ "term": "Vynn", "rule": "keep" })).await?;
+    expect_success(response, "Adding a glossary term").await?;
+
+    let response = hc
+        .do_put(
+            "/api/document/2",
+            json!({ "name": "Chapter One", "content": CHAPTER_ONE, "updated_at": Utc::now().naive_utc() }),
+        )
+        .await?;
+    expect_success(response, "Saving document 2").await?;
+    let response = hc.do_post("/api/project/2/documents/2", json!({})).await?;
+    expect_success(response, "Adding document 2 to project 2").await?;
+
+    let now = Utc::now().naive_utc();
+    let response = hc
+        .do_post(
+            "/api/document",
+            json!({ "name": "Chapter Two", "content": CHAPTER_TWO, "created_at": now, "updated_at": now }),
+        )
+        .await?;
+    let chapter_two = expect_success(response, "Creating chapter two").await?["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Created document has no id"))?;
+    let response = hc.do_post(&format!("/api/project/2/documents/{}", chapter_two), json!({})).await?;
+    expect_success(response, "Adding chapter two to project 2").await?;
+
+    Ok(chapter_two)
+}
+
+// Starts a check of project 2 and waits until its report is finished
+async fn run_check(hc: &Client, payload: Value) -> Result<Value> {
+    let response = hc.do_post("/api/writing-assistant/consistency/2", payload).await?;
+    response.print().await?;
+    let report = expect_success(response, "Starting a consistency check").await?;
+    if report["status"] != "pending" || report["documents_total"] != 2 {
+        return Err(anyhow!("Unexpected new report: {}", report));
+    }
+
+    for _ in 0..40 {
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        let current = hc.do_get(&format!("/api/project/2/consistency/{}", report["id"])).await?.json_body()?;
+        match current["status"].as_str() {
+            Some("done") => return Ok(current),
+            Some("failed") => return Err(anyhow!("Consistency check failed: {}", current)),
+            _ => {}
+        }
+    }
+    Err(anyhow!("Consistency check {} did not finish", report["id"]))
+}
+
+fn findings_of<'a>(report: &'a Value, kind: &str) -> Vec<&'a Value> {
+    report["findings"]
+        .as_array()
+        .map(|findings| findings.iter().filter(|finding| finding["kind"] == kind).collect())
+        .unwrap_or_default()
+}
+
+async fn test_consistency_check(hc: &Client, chapter_two: i64) -> Result<()> {
+    println!("TEST - Consistency Check");
+
+    let ledger_start = backend::last_ledger_id(hc).await?;
+    let report = run_check(hc, json!({})).await?;
+    println!("{}", report);
+    if report["documents_checked"] != 2 || report["use_ai"] != true {
+        return Err(anyhow!("Unexpected finished report: {}", report));
+    }
+    // Every document is checked by the model and charged
+    let charges = backend::credit_charges_since(hc, "consistency_check", ledger_start).await?;
+    if charges != 2 {
+        return Err(anyhow!("Expected 2 consistency check charges, found {}", charges));
+    }
+
+    // The rules find nothing in chapter one, only the model's finding is stored for it
+    let findings = report["findings"].as_array().cloned().unwrap_or_default();
+    let chapter_one: Vec<&Value> = findings.iter().filter(|finding| finding["document_id"] == 2).collect();
+    let quote = "She was tired, and Mira saw the harbour burn.";
+    let located = chapter_one.len() == 1
+        && chapter_one[0]["source"] == "ai"
+        && chapter_one[0]["excerpt"] == quote
+        && chapter_one[0]["start_offset"] == CHAPTER_ONE.find(quote).unwrap_or(0);
+    if !located {
+        return Err(anyhow!("Unexpected findings in chapter one: {:?}", chapter_one));
+    }
+    if findings.len() != 6 || findings.iter().any(|finding| finding["document_id"] != 2 && finding["document_id"] != chapter_two) {
+        return Err(anyhow!("Unexpected findings: {}", report));
+    }
+
+    // Offsets are characters of the stored content, after "<p>"
+    let variant = findings_of(&report, "name_variant");
+    if variant.len() != 1 || variant[0]["excerpt"] != "Mria" || variant[0]["start_offset"] != 3 || variant[0]["suggestion"] != "Mira" {
+        return Err(anyhow!("Unexpected name variants: {:?}", variant));
+    }
+    if variant[0]["entity_id"].is_null() {
+        return Err(anyhow!("Name variant is not linked to Mira Holt: {}", variant[0]));
+    }
+
+    // Chapter two drifted as a whole, one finding each
+    if findings_of(&report, "tense_drift").len() != 1 || findings_of(&report, "pov_drift").len() != 1 {
+        return Err(anyhow!("Unexpected tense or point of view findings: {}", report));
+    }
+
+    let glossary = findings_of(&report, "glossary_violation");
+    if glossary.len() != 1 || glossary[0]["excerpt"] != "VYNN" || glossary[0]["suggestion"] != "Vynn" {
+        return Err(anyhow!("Unexpected glossary findings: {:?}", glossary));
+    }
+
+    let quote = "Mira never sailed to Eldham.";
+    let contradictions = findings_of(&report, "knowledge_contradiction");
+    let expected_offset = CHAPTER_TWO.find(quote).unwrap_or(0);
+    let located = contradictions.iter().any(|finding| {
+        finding["source"] == "ai" && finding["document_id"] == chapter_two && finding["excerpt"] == quote && finding["start_offset"] == expected_offset
+    });
+    if !located {
+        return Err(anyhow!("Contradiction was not located: {:?}", contradictions));
+    }
+
+    // Findings can be filtered by kind and document
+    let filtered = hc
+        .do_get(&format!("/api/project/2/consistency/{}?kind=name_variant", report["id"]))
+        .await?
+        .json_body()?;
+    if filtered["findings"].as_array().is_none_or(|findings| findings.len() != 1) {
+        return Err(anyhow!("Kind filter returned: {}", filtered));
+    }
+    let filtered = hc
+        .do_get(&format!("/api/project/2/consistency/{}?document_id=2", report["id"]))
+        .await?
+        .json_body()?;
+    if filtered["findings"].as_array().is_none_or(|findings| findings.len() != 1) {
+        return Err(anyhow!("Document filter returned: {}", filtered));
+    }
+
+    Ok(())
+}
+
+async fn test_rules_only_check(hc: &Client) -> Result<()> {
+    println!("TEST - Rules Only Check");
+
+    let ledger_start = backend::last_ledger_id(hc).await?;
+    let report = run_check(hc, json!({ "use_ai": false })).await?;
+    let findings = report["findings"].as_array().cloned().unwrap_or_default();
+    if report["use_ai"] != false || findings.iter().any(|finding| finding["source"] != "rule") {
+        return Err(anyhow!("Rules only check used the model: {}", report));
+    }
+    if findings.len() != 4 {
+        return Err(anyhow!("Expected the 4 rule findings: {}", report));
+    }
+    if backend::credit_charges_since(hc, "consistency_check", ledger_start).await? != 0 {
+        return Err(anyhow!("Rules only check was charged"));
+    }
+
+    Ok(())
+}
+
+async fn test_consistency_reports(hc: &Client) -> Result<()> {
+    println!("TEST - Consistency Reports");
+
+    let response = hc.do_get("/api/project/2/consistency").await?;
+    response.print().await?;
+    let reports = expect_success(response, "Listing consistency reports").await?;
+    let reports = reports.as_array().cloned().unwrap_or_default();
+    // Newest first
+    if reports.len() != 2 || reports[0]["use_ai"] != false || reports[1]["finding_count"] != 6 {
+        return Err(anyhow!("Unexpected reports: {:?}", reports));
+    }
+
+    let response = hc.do_get("/api/project/2/consistency/999999").await?;
+    if response.status() != 404 {
+        return Err(anyhow!("Missing report returned {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn test_invalid_consistency_check(hc: &Client) -> Result<()> {
+    println!("TEST - Invalid Consistency Check");
+
+    // A project without documents has nothing to check
+    let response = hc.do_post("/api/project", json!({ "_name": "Empty Project" })).await?;
+    let project = expect_success(response, "Creating a project").await?;
+    let response = hc
+        .do_post(&format!("/api/writing-assistant/consistency/{}", project["id"]), json!({}))
+        .await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Check of an empty project returned {}", response.status()));
+    }
+
+    // Checks store reports on the project, viewers may read them but not start one
+    let response = hc.do_put("/api/project/2/permissions", json!({ "user_id": 2, "role": "viewer" })).await?;
+    expect_success(response, "Sharing project 2 with user 2").await?;
+    let hc2 = httpc_test::new_client("http://localhost:3001")?;
+    let response = hc2
+        .do_post("/api/users/login", json!({ "email": "MarkoP@gmail.com", "password": "MarkosPassword" }))
+        .await?;
+    expect_success(response, "Login as user 2").await?;
+    let response = hc2.do_post("/api/writing-assistant/consistency/2", json!({ "use_ai": false })).await?;
+    if response.status() != 403 {
+        return Err(anyhow!("Check by a viewer returned {}", response.status()));
+    }
+
+    Ok(())
+}
@@ -21,6 +21,8 @@ async fn test_documents() -> Result<()> {
     let get_docs_res = test_get_all_doc(&hc).await;
     let proj_from_doc = test_get_project_from_document(&hc).await;
     let update_result = test_update_document(&hc).await;
+    let stats_result = test_get_document_stats(&hc).await;
+    let stats_history = test_get_document_stats_history(&hc).await;
     let add_permissions = test_add_permissions(&hc).await;
     let upd_perm = test_update_permissions(&hc).await;
     let get_perm = test_get_permissions(&hc).await;
@@ -36,6 +38,8 @@ async fn test_documents() -> Result<()> {
     println!("Get All Documents\t{}", result_to_string(&get_docs_res));
     println!("Get Project From Doc\t{}", result_to_string(&proj_from_doc));
     println!("Update Document:\t{}", result_to_string(&update_result));
+    println!("Get Document Stats:\t{}", result_to_string(&stats_result));
+    println!("Get Stats History:\t{}", result_to_string(&stats_history));
     println!("Add Permissions:\t{}", result_to_string(&add_permissions));
     println!("Update Permissions:\t{}", result_to_string(&upd_perm));
     println!("Get Users Permissions:\t{}", result_to_string(&get_perm));
@@ -184,6 +188,79 @@ async fn test_update_document(hc: &Client) -> Result<()> {
     Ok(())
 }
 
+async fn test_get_document_stats(hc: &Client) -> Result<()> {
+    println!("TEST - Get Document Stats");
+
+    // Document 2 was just updated to "This document has been updated"
+    let response = hc.do_get("/api/document/2/stats").await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Get document stats failed with status: {}", response.status()));
+    }
+    let metrics = &response.json_body()?["metrics"];
+    if metrics["word_count"] != 5 || metrics["sentence_count"] != 1 || metrics["paragraph_count"] != 1 {
+        return Err(anyhow!("Unexpected stats: {}", metrics));
+    }
+
+    // The cached stats follow the content
+    let now = Utc::now().naive_utc();
+    let response = hc
+        .do_put(
+            "/api/document/2",
+            json!({
+                "name": "Updated Test Document",
+                "content": "<h1>Notes</h1><p>The cat sat. The cat slept quietly!</p><p>Another paragraph here.</p>",
+                "updated_at": now
+            }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Update document failed with status: {}", response.status()));
+    }
+
+    let response = hc.do_get("/api/document/2/stats").await?;
+    response.print().await?;
+    let metrics = &response.json_body()?["metrics"];
+    if metrics["word_count"] != 11
+        || metrics["sentence_count"] != 4
+        || metrics["paragraph_count"] != 2
+        || metrics["heading_count"] != 1
+        || metrics["adverb_count"] != 1
+    {
+        return Err(anyhow!("Stats were not refreshed after the update: {}", metrics));
+    }
+    if metrics["most_repeated_words"][0] != json!({ "word": "cat", "count": 2 }) {
+        return Err(anyhow!("Unexpected repeated words: {}", metrics["most_repeated_words"]));
+    }
+    if metrics["flesch_reading_ease"].as_f64().is_none() || metrics["reading_time_minutes"].as_f64().is_none() {
+        return Err(anyhow!("Readability scores are missing: {}", metrics));
+    }
+
+    Ok(())
+}
+
+async fn test_get_document_stats_history(hc: &Client) -> Result<()> {
+    println!("TEST - Get Document Stats History");
+
+    let response = hc.do_get("/api/document/2/stats/history?days=7").await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Get stats history failed with status: {}", response.status()));
+    }
+
+    // One snapshot per day, today's has the current word count
+    let history = response.json_body()?;
+    let today = history
+        .as_array()
+        .and_then(|snapshots| snapshots.last())
+        .ok_or_else(|| anyhow!("Stats history is empty"))?;
+    if today["word_count"] != 11 || today["metrics"]["word_count"] != 11 {
+        return Err(anyhow!("Unexpected latest snapshot: {}", today));
+    }
+
+    Ok(())
+}
+
 async fn test_get_project_from_document(hc: &Client) -> Result<()> {
     println!("TEST - Get Project From Document");
 
@@ -2,7 +2,7 @@
 
 use anyhow::Result;
 use axum::http::response;
-use backend::result_to_string;
+use backend::{admin_secret, result_to_string};
 use httpc_test::Client;
 use serde_json::json;
 
@@ -15,12 +15,24 @@ async fn test_environment() -> Result<()> {
     // Run all tests and collect results
     let db_result = test_database(&hc).await;
     let fallback_result = trigger_fallback(&hc).await;
+    let backfill_result = test_backfill_embeddings(&hc).await;
+    let backfill_bad_secret = test_backfill_embeddings_bad_secret(&hc).await;
+    let grant_result = test_grant_credits(&hc).await;
+    let grant_bad_secret = test_grant_credits_bad_secret(&hc).await;
+    let set_plan_result = test_set_user_plan(&hc).await;
+    let refill_result = test_refill_credits(&hc).await;
     let db_reset = backend::test_reset_db(&hc).await;
 
     // Print summary
     println!("\n==== TEST RESULTS ====");
     println!("Database Query:\t{}", result_to_string(&db_result));
     println!("Test Fallback:\t{}", result_to_string(&fallback_result));
+    println!("Backfill:\t{}", result_to_string(&backfill_result));
+    println!("Backfill Secret:\t{}", result_to_string(&backfill_bad_secret));
+    println!("Grant Credits:\t{}", result_to_string(&grant_result));
+    println!("Grant Secret:\t{}", result_to_string(&grant_bad_secret));
+    println!("Set Plan:\t{}", result_to_string(&set_plan_result));
+    println!("Refill Credits:\t{}", result_to_string(&refill_result));
     println!("Reset Database:\t{}", result_to_string(&db_reset));
     println!("======================\n");
 
@@ -56,3 +68,216 @@ async fn test_database(hc: &Client) -> Result<()> {
 
     Ok(())
 }
+
+
+async fn test_backfill_embeddings(hc: &Client) -> Result<()> {
+    print!("TEST - Backfill Embeddings");
+    let response = hc
+        .do_post("/api/db/embeddings/backfill?secret=secret_key&mode=all", json!({}))
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Backfill failed with status: {}",
+            response.status()
+        ));
+    }
+
+    // Every seeded document has content, so something must have been queued
+    let queued = response.json_body()?["result"]["queued"].as_i64().unwrap_or(0);
+    if queued == 0 {
+        return Err(anyhow::anyhow!("Backfill queued no documents"));
+    }
+
+    let jobs_response = hc.do_get("/api/db/embeddings/jobs?secret=secret_key").await?;
+    jobs_response.print().await?;
+
+    if !jobs_response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Get embedding jobs failed with status: {}",
+            jobs_response.status()
+        ));
+    }
+
+    Ok(())
+}
+
+async fn test_backfill_embeddings_bad_secret(hc: &Client) -> Result<()> {
+    print!("TEST - Backfill Embeddings With Bad Secret");
+    let response = hc
+        .do_post("/api/db/embeddings/backfill?secret=wrong", json!({}))
+        .await?;
+    response.print().await?;
+
+    if response.status().is_success() {
+        return Err(anyhow::anyhow!("Backfill succeeded without the secret"));
+    }
+
+    Ok(())
+}
+
+async fn test_grant_credits(hc: &Client) -> Result<()> {
+    print!("TEST - Grant Credits");
+    let response = hc
+        .do_post(
+            &format!("/api/db/credits/grant?secret={}", admin_secret()),
+            json!({
+                "user_id": 2,
+                "amount": 25,
+                "note": "Test grant"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Grant credits failed with status: {}",
+            response.status()
+        ));
+    }
+
+    // Seeded user 2 starts with 999 credits
+    let balance = response.json_body()?["result"]["balance"].as_i64().unwrap_or(0);
+    if balance < 25 {
+        return Err(anyhow::anyhow!("Unexpected balance after grant: {}", balance));
+    }
+
+    Ok(())
+}
+
+async fn test_grant_credits_bad_secret(hc: &Client) -> Result<()> {
+    print!("TEST - Grant Credits With Bad Secret");
+    let response = hc
+        .do_post(
+            "/api/db/credits/grant?secret=wrong",
+            json!({
+                "user_id": 2,
+                "amount": 1000
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if response.status() != 403 {
+        return Err(anyhow::anyhow!("Grant with a wrong secret returned {}", response.status()));
+    }
+
+    // Neither a missing secret nor the former built-in one are accepted
+    for path in ["/api/db/credits/grant", "/api/db/credits/grant?secret=secret_key"] {
+        if path == format!("/api/db/credits/grant?secret={}", admin_secret()) {
+            continue;
+        }
+        let response = hc.do_post(path, json!({ "user_id": 2, "amount": 1000 })).await?;
+        if response.status() != 403 {
+            return Err(anyhow::anyhow!("Grant via {} returned {}", path, response.status()));
+        }
+    }
+
+    Ok(())
+}
+
+async fn test_set_user_plan(hc: &Client) -> Result<()> {
+    print!("TEST - Set User Plan");
+    let response = hc
+        .do_put(&format!("/api/db/users/2/plan?secret={}", admin_secret()), json!({ "plan": "pro" }))
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Set plan failed with status: {}",
+            response.status()
+        ));
+    }
+
+    // Unknown plans are rejected
+    let unknown = hc
+        .do_put(&format!("/api/db/users/2/plan?secret={}", admin_secret()), json!({ "plan": "platinum" }))
+        .await?;
+    if unknown.status().is_success() {
+        return Err(anyhow::anyhow!("Moving to an unknown plan succeeded"));
+    }
+
+    Ok(())
+}
+
+async fn test_refill_credits(hc: &Client) -> Result<()> {
+    print!("TEST - Refill Credits");
+
+    // User 2 reads their plan and ledger
+    let hc2 = httpc_test::new_client("http://localhost:3001")?;
+    let login = hc2
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "MarkoP@gmail.com",
+                "password": "MarkosPassword"
+            }),
+        )
+        .await?;
+    if !login.status().is_success() {
+        return Err(anyhow::anyhow!("Login as user 2 failed with status: {}", login.status()));
+    }
+    let plan = hc2.do_get("/api/users/plan").await?.json_body()?;
+    let allowance = plan["plan"]["monthly_credits"].as_i64().unwrap_or(0);
+    let balance = plan["ai_credits"].as_i64().unwrap_or(0);
+
+    // Drain the balance
+    if balance > 0 {
+        let response = hc
+            .do_post(
+                &format!("/api/db/credits/grant?secret={}", admin_secret()),
+                json!({
+                    "user_id": 2,
+                    "amount": -balance,
+                    "note": "Drain before refill"
+                }),
+            )
+            .await?;
+        if response.json_body()?["result"]["balance"] != 0 {
+            return Err(anyhow::anyhow!("Balance of user 2 was not drained"));
+        }
+    }
+
+    let response = hc.do_post(&format!("/api/db/credits/refill?secret={}&user_id=2", admin_secret()), json!({})).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Refill credits failed with status: {}",
+            response.status()
+        ));
+    }
+    if response.json_body()?["result"]["refilled"] != 1 {
+        return Err(anyhow::anyhow!("User 2 was not refilled"));
+    }
+
+    // The refill is the newest ledger entry and tops the balance up to the allowance
+    let history = hc2.do_get("/api/users/credits?limit=1").await?.json_body()?;
+    let entry = &history["entries"][0];
+    if history["balance"] != allowance
+        || entry["operation"] != "monthly_refill"
+        || entry["amount"] != allowance
+        || entry["balance_after"] != allowance
+    {
+        return Err(anyhow::anyhow!("Unexpected credit history after refill: {}", history));
+    }
+
+    // A full balance gets no top-up
+    let due = hc.do_post(&format!("/api/db/credits/refill?secret={}&user_id=2", admin_secret()), json!({})).await?;
+    if due.json_body()?["result"]["refilled"] != 0 {
+        return Err(anyhow::anyhow!("User 2 was refilled twice"));
+    }
+    let history = hc2.do_get("/api/users/credits").await?.json_body()?;
+    if history["balance"] != allowance {
+        return Err(anyhow::anyhow!("Balance changed after the second refill: {}", history["balance"]));
+    }
+
+    let response = hc.do_post("/api/db/credits/refill?secret=wrong", json!({})).await?;
+    if response.status().is_success() {
+        return Err(anyhow::anyhow!("Refill succeeded without the secret"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,279 @@
+#![allow(unused)]
+
+use anyhow::{anyhow, Result};
+use backend::{result_to_string, wait_for_embedding_queue};
+use chrono::Utc;
+use httpc_test::Client;
+use serde_json::{json, Value};
+
+// Research notes in document 2 and a second source, both added to project 2 while the tests run
+const NOTES: &str = "<p>The lighthouse at Eldham was built in 1852.</p>";
+const ALMANAC: &str = "<p>The moon is made of green cheese, said the baker.</p>";
+// One claim about each source. The mock model supports a claim by the first passage retrieved for it,
+// so the citations show which passages the server retrieved.
+const DRAFT: &str = "The lighthouse at Eldham was built in 1852. The moon is made of cheese.";
+
+#[tokio::test]
+async fn test_fact_check() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+
+    println!("\n===== RUNNING EVIDENCE FACT CHECK API TESTS =====\n");
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let setup = setup_sources(&hc).await;
+    let (evidence, references, excluded, unreadable) = match &setup {
+        Ok(almanac) => (
+            test_evidence_fact_check(&hc, *almanac).await,
+            test_reference_documents(&hc).await,
+            test_excluded_document(&hc, *almanac).await,
+            test_unreadable_reference(&hc).await,
+        ),
+        Err(_) => (Err(anyhow!("Skipped")), Err(anyhow!("Skipped")), Err(anyhow!("Skipped")), Err(anyhow!("Skipped"))),
+    };
+    let invalid = test_invalid_fact_check(&hc).await;
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Sources Setup\t\t{}", result_to_string(&setup.as_ref().map(|_| ()).map_err(|e| anyhow!("{}", e))));
+    println!("Evidence Fact Check\t{}", result_to_string(&evidence));
+    println!("Reference Documents\t{}", result_to_string(&references));
+    println!("Excluded Document\t{}", result_to_string(&excluded));
+    println!("Unreadable Reference\t{}", result_to_string(&unreadable));
+    println!("Invalid Fact Check\t{}", result_to_string(&invalid));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+// Stores the notes in document 2 and creates the almanac, both in project 2. Returns the almanac's id.
+async fn setup_sources(hc: &Client) -> Result<i64> {
+    println!("TEST - Sources Setup");
+
+    let response = hc
+        .do_put(
+            "/api/document/2",
+            json!({ "name": "Research Notes", "content": NOTES, "updated_at": Utc::now().naive_utc() }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Saving document 2 failed with status: {}", response.status()));
+    }
+    let now = Utc::now().naive_utc();
+    let response = hc
+        .do_post("/api/document", json!({ "name": "Almanac", "content": ALMANAC, "created_at": now, "updated_at": now }))
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Creating the almanac failed with status: {}", response.status()));
+    }
+    let almanac = response.json_body()?["id"].as_i64().ok_or_else(|| anyhow!("Created document has no id"))?;
+
+    for document_id in [2, almanac] {
+        let response = hc.do_post(&format!("/api/project/2/documents/{}", document_id), json!({})).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Adding document {} to project 2 failed with status: {}", document_id, response.status()));
+        }
+    }
+
+    wait_for_embedding_queue(hc).await?;
+    Ok(almanac)
+}
+
+async fn fact_check(hc: &Client, payload: Value) -> Result<Value> {
+    let response = hc.do_post("/api/writing-assistant/factcheck/evidence", payload).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Fact check failed with status: {}", response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+fn verdicts(result: &Value) -> Vec<String> {
+    result["claims"]
+        .as_array()
+        .map(|claims| claims.iter().map(|claim| claim["verdict"].as_str().unwrap_or_default().to_string()).collect())
+        .unwrap_or_default()
+}
+
+// Documents of the passages cited for every claim
+fn cited_documents(result: &Value) -> Vec<Vec<i64>> {
+    result["claims"]
+        .as_array()
+        .map(|claims| {
+            claims
+                .iter()
+                .map(|claim| {
+                    claim["citations"]
+                        .as_array()
+                        .map(|citations| citations.iter().filter_map(|citation| citation["document_id"].as_i64()).collect())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Documents of the passages retrieved for the check
+fn source_documents(result: &Value) -> Vec<i64> {
+    let mut documents: Vec<i64> = result["sources"]
+        .as_array()
+        .map(|sources| sources.iter().filter_map(|source| source["document_id"].as_i64()).collect())
+        .unwrap_or_default();
+    documents.sort_unstable();
+    documents.dedup();
+    documents
+}
+
+async fn test_evidence_fact_check(hc: &Client, almanac: i64) -> Result<()> {
+    println!("TEST - Evidence Fact Check");
+
+    let ledger_start = backend::last_ledger_id(hc).await?;
+    let result = fact_check(hc, json!({ "content": DRAFT, "project_id": 2 })).await?;
+    if verdicts(&result) != ["supported", "supported"] {
+        return Err(anyhow!("Unexpected verdicts: {}", result));
+    }
+
+    // Every claim is checked against the passage most similar to it
+    if cited_documents(&result) != [vec![2], vec![almanac]] {
+        return Err(anyhow!("Claims cite the wrong passages: {}", result));
+    }
+    let citation = &result["claims"][0]["citations"][0];
+    if citation["label"] != "S1" || !citation["text"].as_str().is_some_and(|text| text.contains("1852")) {
+        return Err(anyhow!("Unexpected citation: {}", citation));
+    }
+    if source_documents(&result) != [2, almanac] {
+        return Err(anyhow!("Unexpected sources: {}", result["sources"]));
+    }
+
+    // Claims are located in the checked text
+    let quote = "The moon is made of cheese.";
+    let claim = &result["claims"][1];
+    if claim["quote"] != quote || claim["start_offset"] != DRAFT.find(quote).unwrap_or(0) {
+        return Err(anyhow!("Claim was not located: {}", claim));
+    }
+
+    // Listing the claims and judging them are charged separately
+    let charges = backend::credit_charges_since(hc, "evidence_check", ledger_start).await?;
+    if charges != 2 {
+        return Err(anyhow!("Expected 2 evidence check charges, found {}", charges));
+    }
+
+    Ok(())
+}
+
+async fn test_reference_documents(hc: &Client) -> Result<()> {
+    println!("TEST - Reference Documents");
+
+    // Without a project only the reference documents are searched
+    let result = fact_check(hc, json!({ "content": DRAFT, "reference_document_ids": [2] })).await?;
+    if source_documents(&result) != [2] || cited_documents(&result) != [vec![2], vec![2]] {
+        return Err(anyhow!("Passages outside the reference documents: {}", result));
+    }
+
+    Ok(())
+}
+
+async fn test_excluded_document(hc: &Client, almanac: i64) -> Result<()> {
+    println!("TEST - Excluded Document");
+
+    // The document the text comes from is no evidence for it
+    let result = fact_check(hc, json!({ "content": DRAFT, "project_id": 2, "document_id": 2 })).await?;
+    if source_documents(&result) != [almanac] {
+        return Err(anyhow!("Text was checked against its own document: {}", result));
+    }
+
+    // Without any passage every claim is unknown and the model is only asked for the claims
+    let ledger_start = backend::last_ledger_id(hc).await?;
+    let result = fact_check(hc, json!({ "content": DRAFT, "reference_document_ids": [2], "document_id": 2 })).await?;
+    if verdicts(&result) != ["unknown", "unknown"] || !result["sources"].as_array().is_some_and(|sources| sources.is_empty()) {
+        return Err(anyhow!("Claims were judged without passages: {}", result));
+    }
+    let charges = backend::credit_charges_since(hc, "evidence_check", ledger_start).await?;
+    if charges != 1 {
+        return Err(anyhow!("Expected 1 evidence check charge without passages, found {}", charges));
+    }
+
+    Ok(())
+}
+
+async fn test_unreadable_reference(hc: &Client) -> Result<()> {
+    println!("TEST - Unreadable Reference");
+
+    // User 2 writes a document user 1 has no permission on
+    let hc2 = httpc_test::new_client("http://localhost:3001")?;
+    let response = hc2
+        .do_post("/api/users/login", json!({ "email": "MarkoP@gmail.com", "password": "MarkosPassword" }))
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Login as user 2 failed with status: {}", response.status()));
+    }
+    let now = Utc::now().naive_utc();
+    let response = hc2
+        .do_post("/api/document", json!({ "name": "Private Notes", "content": NOTES, "created_at": now, "updated_at": now }))
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Creating a document as user 2 failed with status: {}", response.status()));
+    }
+    let private = response.json_body()?["id"].as_i64().ok_or_else(|| anyhow!("Created document has no id"))?;
+
+    let ledger_start = backend::last_ledger_id(hc).await?;
+    let response = hc
+        .do_post("/api/writing-assistant/factcheck/evidence", json!({ "content": DRAFT, "reference_document_ids": [private] }))
+        .await?;
+    if response.status() != 403 {
+        return Err(anyhow!("Fact check against another user's document returned {}", response.status()));
+    }
+    if backend::credit_charges_since(hc, "evidence_check", ledger_start).await? != 0 {
+        return Err(anyhow!("A refused fact check was charged"));
+    }
+
+    Ok(())
+}
+
+async fn test_invalid_fact_check(hc: &Client) -> Result<()> {
+    println!("TEST - Invalid Fact Check");
+
+    // Without a project or reference documents there is nothing to check against
+    let response = hc.do_post("/api/writing-assistant/factcheck/evidence", json!({ "content": DRAFT })).await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Fact check without sources returned {}", response.status()));
+    }
+    let response = hc
+        .do_post("/api/writing-assistant/factcheck/evidence", json!({ "content": "  ", "project_id": 2 }))
+        .await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Fact check of an empty text returned {}", response.status()));
+    }
+    let response = hc
+        .do_post("/api/writing-assistant/factcheck/evidence", json!({ "content": DRAFT, "project_id": 99999 }))
+        .await?;
+    if response.status() != 403 {
+        return Err(anyhow!("Fact check against a foreign project returned {}", response.status()));
+    }
+
+    Ok(())
+}
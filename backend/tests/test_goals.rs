@@ -0,0 +1,255 @@
+#![allow(unused)]
+
+use anyhow::{anyhow, Result};
+use backend::result_to_string;
+use chrono::Utc;
+use httpc_test::Client;
+use serde_json::{json, Value};
+
+// Words appended to document 1 (in project 1) by the tests. Document 1 is seeded as markdown, the
+// blank line keeps its last word apart from the first new one.
+const NEW_WORDS: &str = "\n\n<p>one two three four five six</p>";
+
+#[tokio::test]
+async fn test_goals() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+
+    println!("\n===== RUNNING WRITING GOAL API TESTS =====\n");
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let daily_goal = test_daily_goal(&hc).await;
+    let project_goal = test_project_goal(&hc).await;
+    let invalid_goal = test_invalid_goal(&hc).await;
+    let activity = test_writing_activity(&hc).await;
+    let delete_goal = test_delete_goal(&hc).await;
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Daily Goal\t\t{}", result_to_string(&daily_goal));
+    println!("Project Goal\t\t{}", result_to_string(&project_goal));
+    println!("Invalid Goal\t\t{}", result_to_string(&invalid_goal));
+    println!("Writing Activity\t{}", result_to_string(&activity));
+    println!("Delete Goal\t\t{}", result_to_string(&delete_goal));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+// Saves document 1 with new content, returns the previous content
+async fn save_document_1(hc: &Client, content: impl FnOnce(&str) -> String) -> Result<String> {
+    let document = hc.do_get("/api/document/1").await?.json_body()?;
+    let old_content = document["content"].as_str().unwrap_or("").to_string();
+
+    let response = hc
+        .do_put(
+            "/api/document/1",
+            json!({
+                "name": document["name"],
+                "content": content(&old_content),
+                "updated_at": Utc::now().naive_utc()
+            }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Saving document 1 failed with status: {}", response.status()));
+    }
+    Ok(old_content)
+}
+
+async fn create_goal(hc: &Client, payload: Value) -> Result<Value> {
+    let response = hc.do_post("/api/goals", payload).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Create goal failed with status: {}", response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+async fn delete_goal(hc: &Client, goal: &Value) -> Result<()> {
+    let response = hc.do_delete(&format!("/api/goals/{}", goal["id"])).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Delete goal failed with status: {}", response.status()));
+    }
+    Ok(())
+}
+
+async fn test_daily_goal(hc: &Client) -> Result<()> {
+    println!("TEST - Daily Goal");
+
+    let goal = create_goal(hc, json!({ "kind": "daily", "target_words": 5 })).await?;
+    if goal["kind"] != "daily" || goal["start_date"] != goal["today"] || goal["daily_target"] != 5 {
+        return Err(anyhow!("Unexpected daily goal: {}", goal));
+    }
+
+    // Six new words meet the target of today
+    save_document_1(hc, |content| format!("{}{}", content, NEW_WORDS)).await?;
+
+    let response = hc.do_get(&format!("/api/goals/{}", goal["id"])).await?;
+    response.print().await?;
+    let progress = response.json_body()?;
+    let words_today = progress["words_today"].as_i64().unwrap_or(0);
+    if words_today < 6 || progress["words_written"] != words_today {
+        return Err(anyhow!("New words were not counted: {}", progress));
+    }
+    if progress["completed"] != true || progress["completed_on"] != progress["today"] {
+        return Err(anyhow!("Daily goal is not completed: {}", progress));
+    }
+    if progress["current_streak"].as_i64().unwrap_or(0) < 1 || progress["longest_streak"].as_i64().unwrap_or(0) < 1 {
+        return Err(anyhow!("Today does not count towards the streak: {}", progress));
+    }
+    if !progress["projected_completion_date"].is_null() || !progress["on_track"].is_null() {
+        return Err(anyhow!("Daily goals have no projection: {}", progress));
+    }
+
+    let goals = hc.do_get("/api/goals").await?.json_body()?;
+    if !goals.as_array().is_some_and(|goals| goals.iter().any(|listed| listed["id"] == goal["id"])) {
+        return Err(anyhow!("Goal is not listed: {}", goals));
+    }
+
+    delete_goal(hc, &goal).await
+}
+
+async fn test_project_goal(hc: &Client) -> Result<()> {
+    println!("TEST - Project Goal");
+
+    save_document_1(hc, |content| format!("{}{}", content, NEW_WORDS)).await?;
+
+    let goal = create_goal(
+        hc,
+        json!({
+            "kind": "project",
+            "project_id": 1,
+            "target_words": 100000,
+            "start_date": "2020-01-01",
+            "deadline": "2099-12-31"
+        }),
+    )
+    .await?;
+
+    let written = goal["words_written"].as_i64().unwrap_or(0);
+    if written < 6 || goal["words_remaining"] != 100000 - written || goal["completed"] != false {
+        return Err(anyhow!("Unexpected project goal progress: {}", goal));
+    }
+    if !goal["daily_target"].is_i64() || !goal["words_needed_per_day"].is_i64() || !goal["days_remaining"].is_i64() {
+        return Err(anyhow!("Pace to the deadline is missing: {}", goal));
+    }
+    if !goal["projected_completion_date"].is_string() || !goal["on_track"].is_boolean() {
+        return Err(anyhow!("Projection is missing: {}", goal));
+    }
+
+    // Lower the target to the words written so far and remove the deadline
+    let response = hc
+        .do_put(
+            &format!("/api/goals/{}", goal["id"]),
+            json!({ "target_words": written }),
+        )
+        .await?;
+    response.print().await?;
+    let progress = response.json_body()?;
+    if progress["completed"] != true || progress["percent_complete"] != 100.0 || !progress["deadline"].is_null() {
+        return Err(anyhow!("Goal is not completed after lowering the target: {}", progress));
+    }
+    if progress["start_date"] != "2020-01-01" || progress["projected_completion_date"] != progress["completed_on"] {
+        return Err(anyhow!("Unexpected completed goal: {}", progress));
+    }
+
+    let listed = hc.do_get("/api/goals?project_id=1").await?.json_body()?;
+    if !listed.as_array().is_some_and(|goals| goals.len() == 1 && goals[0]["id"] == goal["id"]) {
+        return Err(anyhow!("Unexpected goals of project 1: {}", listed));
+    }
+
+    delete_goal(hc, &goal).await
+}
+
+async fn test_invalid_goal(hc: &Client) -> Result<()> {
+    println!("TEST - Invalid Goal");
+
+    let invalid = [
+        json!({ "kind": "project", "target_words": 1000 }),
+        json!({ "kind": "daily", "target_words": 0 }),
+        json!({ "kind": "daily", "target_words": 500, "start_date": "2024-11-30", "deadline": "2024-11-01" }),
+    ];
+    for payload in invalid {
+        let response = hc.do_post("/api/goals", payload.clone()).await?;
+        if response.status() != 400 {
+            return Err(anyhow!("{} returned {}", payload, response.status()));
+        }
+    }
+
+    // A project the user has no access to
+    let response = hc
+        .do_post("/api/goals", json!({ "kind": "project", "project_id": 9999, "target_words": 1000 }))
+        .await?;
+    if response.status().is_success() {
+        return Err(anyhow!("Goal created on a project without permission"));
+    }
+
+    Ok(())
+}
+
+async fn test_writing_activity(hc: &Client) -> Result<()> {
+    println!("TEST - Writing Activity");
+
+    // Removing the words again is recorded as removed words
+    save_document_1(hc, |content| content.replace(NEW_WORDS, "")).await?;
+
+    for path in ["/api/goals/activity?days=7", "/api/goals/activity?days=7&project_id=1"] {
+        let response = hc.do_get(path).await?;
+        response.print().await?;
+        let activity = response.json_body()?;
+        let today = activity
+            .as_array()
+            .and_then(|days| days.last())
+            .ok_or_else(|| anyhow!("No activity for {}", path))?;
+        let added = today["words_added"].as_i64().unwrap_or(0);
+        let removed = today["words_removed"].as_i64().unwrap_or(0);
+        if added < 12 || removed < 12 || today["net_words"] != added - removed {
+            return Err(anyhow!("Unexpected activity for {}: {}", path, today));
+        }
+    }
+
+    Ok(())
+}
+
+async fn test_delete_goal(hc: &Client) -> Result<()> {
+    println!("TEST - Delete Goal");
+
+    let goal = create_goal(hc, json!({ "kind": "daily", "target_words": 500 })).await?;
+    delete_goal(hc, &goal).await?;
+
+    let response = hc.do_get(&format!("/api/goals/{}", goal["id"])).await?;
+    if response.status() != 404 {
+        return Err(anyhow!("Deleted goal returned {}", response.status()));
+    }
+    let response = hc.do_delete(&format!("/api/goals/{}", goal["id"])).await?;
+    if response.status() != 404 {
+        return Err(anyhow!("Deleting a missing goal returned {}", response.status()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,340 @@
+#![allow(unused)]
+
+use anyhow::{anyhow, Result};
+use backend::result_to_string;
+use chrono::Utc;
+use httpc_test::Client;
+use serde_json::{json, Value};
+
+// Content of document 2 (added to project 2) while the tests run
+const STORY: &str = "<p>Mira Holt sailed to Eldham. At dawn Mira saw Eldham burn.</p>";
+
+#[tokio::test]
+async fn test_knowledge() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+
+    println!("\n===== RUNNING KNOWLEDGE BASE API TESTS =====\n");
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let style_guide = test_style_guide(&hc).await;
+    let entities = test_entities(&hc).await;
+    let mentions = test_entity_mentions(&hc).await;
+    let extract = test_extract_entities(&hc).await;
+    let assistant = test_assistant_with_knowledge(&hc).await;
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Style Guide\t\t{}", result_to_string(&style_guide));
+    println!("Entities\t\t{}", result_to_string(&entities));
+    println!("Entity Mentions\t\t{}", result_to_string(&mentions));
+    println!("Extract Entities\t{}", result_to_string(&extract));
+    println!("Assistant Context\t{}", result_to_string(&assistant));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn save_document_2(hc: &Client, content: &str) -> Result<()> {
+    let response = hc
+        .do_put(
+            "/api/document/2",
+            json!({
+                "name": "Test Document 2",
+                "content": content,
+                "updated_at": Utc::now().naive_utc()
+            }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Saving document 2 failed with status: {}", response.status()));
+    }
+    Ok(())
+}
+
+async fn add_entity(hc: &Client, payload: Value) -> Result<Value> {
+    let response = hc.do_post("/api/project/2/entities", payload).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Adding an entity failed with status: {}", response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+async fn get_entity(hc: &Client, entity_id: &Value) -> Result<Value> {
+    let response = hc.do_get(&format!("/api/project/2/entities/{}", entity_id)).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Getting entity {} failed with status: {}", entity_id, response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+async fn entity_named(hc: &Client, name: &str) -> Result<Value> {
+    let entities = hc.do_get("/api/project/2/entities").await?.json_body()?;
+    entities
+        .as_array()
+        .and_then(|entities| entities.iter().find(|entity| entity["name"] == name).cloned())
+        .ok_or_else(|| anyhow!("No entity named {}: {}", name, entities))
+}
+
+async fn test_style_guide(hc: &Client) -> Result<()> {
+    println!("TEST - Style Guide");
+
+    let guide = "Past tense, third person. British spelling.";
+    let response = hc.do_put("/api/project/2/style-guide", json!({ "style_guide": format!("  {}  ", guide) })).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Setting the style guide failed with status: {}", response.status()));
+    }
+
+    let knowledge = hc.do_get("/api/project/2/knowledge").await?.json_body()?;
+    if knowledge["style_guide"] != guide || !knowledge["entities"].is_array() {
+        return Err(anyhow!("Unexpected knowledge base: {}", knowledge));
+    }
+
+    let response = hc.do_put("/api/project/2/style-guide", json!({ "style_guide": "x".repeat(20001) })).await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Overlong style guide returned {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn test_entities(hc: &Client) -> Result<()> {
+    println!("TEST - Entities");
+
+    // Document 2 tells the story and belongs to project 2
+    save_document_2(hc, STORY).await?;
+    let response = hc.do_post("/api/project/2/documents/2", json!({})).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Adding document 2 to project 2 failed with status: {}", response.status()));
+    }
+
+    let mira = add_entity(
+        hc,
+        json!({
+            "kind": "character",
+            "name": " Mira Holt ",
+            "aliases": ["Mira", "mira", "Mira Holt"],
+            "description": "Captain of the Kestrel."
+        }),
+    )
+    .await?;
+    if mira["name"] != "Mira Holt" || mira["aliases"] != json!(["Mira"]) || mira["source"] != "manual" {
+        return Err(anyhow!("Unexpected entity: {}", mira));
+    }
+    // "Mira Holt" and "Mira" once each
+    if mira["mention_count"] != 2 || mira["document_count"] != 1 {
+        return Err(anyhow!("Mentions were not linked: {}", mira));
+    }
+
+    // Names are unique per project ignoring case, and must not be empty
+    let response = hc.do_post("/api/project/2/entities", json!({ "kind": "character", "name": "MIRA HOLT" })).await?;
+    if response.status() != 409 {
+        return Err(anyhow!("Duplicate entity returned {}", response.status()));
+    }
+    let response = hc.do_post("/api/project/2/entities", json!({ "kind": "place", "name": "  " })).await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Entity without name returned {}", response.status()));
+    }
+
+    let response = hc
+        .do_put(
+            &format!("/api/project/2/entities/{}", mira["id"]),
+            json!({
+                "kind": "character",
+                "name": "Mira Holt",
+                "aliases": ["Mira"],
+                "description": "Captain of the Kestrel.",
+                "notes": "Left-handed since chapter 2."
+            }),
+        )
+        .await?;
+    response.print().await?;
+    let updated = response.json_body()?;
+    if updated["notes"] != "Left-handed since chapter 2." || updated["mention_count"] != 2 {
+        return Err(anyhow!("Entity was not replaced: {}", updated));
+    }
+
+    let characters = hc.do_get("/api/project/2/entities?kind=character").await?.json_body()?;
+    if !characters.as_array().is_some_and(|entities| entities.len() == 1 && entities[0]["id"] == mira["id"]) {
+        return Err(anyhow!("Unexpected characters: {}", characters));
+    }
+
+    // Remove a temporary entity
+    let temporary = add_entity(hc, json!({ "kind": "item", "name": "Brass Compass" })).await?;
+    let response = hc.do_delete(&format!("/api/project/2/entities/{}", temporary["id"])).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Deleting an entity failed with status: {}", response.status()));
+    }
+    let response = hc.do_get(&format!("/api/project/2/entities/{}", temporary["id"])).await?;
+    if response.status() != 404 {
+        return Err(anyhow!("Deleted entity returned {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn test_entity_mentions(hc: &Client) -> Result<()> {
+    println!("TEST - Entity Mentions");
+
+    let mira = entity_named(hc, "Mira Holt").await?;
+    let detail = get_entity(hc, &mira["id"]).await?;
+    let mention = &detail["mentions"][0];
+    // Offsets are characters of the stored content, after "<p>"
+    if mention["document_id"] != 2 || mention["mention_count"] != 2 || mention["offsets"][0] != 3 {
+        return Err(anyhow!("Unexpected mentions: {}", detail));
+    }
+
+    let response = hc.do_get("/api/document/2/entities").await?;
+    response.print().await?;
+    let document_entities = response.json_body()?;
+    if !document_entities.as_array().is_some_and(|entities| entities.iter().any(|entity| entity["entity_id"] == mira["id"])) {
+        return Err(anyhow!("Document 2 does not list Mira Holt: {}", document_entities));
+    }
+
+    // Mentions follow the saved content, in the background
+    save_document_2(hc, "<p>The harbour was empty.</p>").await?;
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    let detail = get_entity(hc, &mira["id"]).await?;
+    if detail["mention_count"] != 0 || !detail["mentions"].as_array().is_some_and(|mentions| mentions.is_empty()) {
+        return Err(anyhow!("Mentions were not removed after saving: {}", detail));
+    }
+
+    save_document_2(hc, STORY).await?;
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    let detail = get_entity(hc, &mira["id"]).await?;
+    if detail["mention_count"] != 2 {
+        return Err(anyhow!("Mentions were not found again after saving: {}", detail));
+    }
+
+    Ok(())
+}
+
+async fn test_extract_entities(hc: &Client) -> Result<()> {
+    println!("TEST - Extract Entities");
+
+    // The mock model reports every capitalized word of the document: Mira, Holt and Eldham
+    let ledger_start = backend::last_ledger_id(hc).await?;
+    let response = hc
+        .do_post("/api/writing-assistant/knowledge/extract/2", json!({ "document_ids": [2] }))
+        .await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Extraction failed with status: {}", response.status()));
+    }
+    let result = response.json_body()?;
+    if result["documents"] != json!([2]) {
+        return Err(anyhow!("Unexpected analyzed documents: {}", result));
+    }
+
+    // "Mira" is an alias of Mira Holt, "Eldham" is new
+    let created = result["created"].as_array().cloned().unwrap_or_default();
+    let eldham = created
+        .iter()
+        .find(|entity| entity["name"] == "Eldham")
+        .ok_or_else(|| anyhow!("Eldham was not created: {}", result))?;
+    if eldham["source"] != "extracted" || eldham["mention_count"] != 2 {
+        return Err(anyhow!("Unexpected extracted entity: {}", eldham));
+    }
+    if created.iter().any(|entity| entity["name"] == "Mira") {
+        return Err(anyhow!("Known alias was added as a new entity: {}", result));
+    }
+    // One document was analyzed
+    let charges = backend::credit_charges_since(hc, "extract_entities", ledger_start).await?;
+    if charges != 1 {
+        return Err(anyhow!("Expected 1 extraction charge, found {}", charges));
+    }
+
+    // Editing an extracted entity makes it manual
+    let response = hc
+        .do_put(
+            &format!("/api/project/2/entities/{}", eldham["id"]),
+            json!({ "kind": "place", "name": "Eldham", "description": "Harbour town." }),
+        )
+        .await?;
+    let edited = response.json_body()?;
+    if edited["source"] != "manual" || edited["kind"] != "place" {
+        return Err(anyhow!("Unexpected edited entity: {}", edited));
+    }
+
+    // Entities found before are not created again
+    let response = hc
+        .do_post("/api/writing-assistant/knowledge/extract/2", json!({ "document_ids": [2] }))
+        .await?;
+    let result = response.json_body()?;
+    if !result["created"].as_array().is_some_and(|created| created.is_empty()) {
+        return Err(anyhow!("Known entities were created again: {}", result));
+    }
+
+    // Extraction needs documents of the project
+    let response = hc
+        .do_post("/api/writing-assistant/knowledge/extract/2", json!({ "document_ids": [1] }))
+        .await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Extraction without project documents returned {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn test_assistant_with_knowledge(hc: &Client) -> Result<()> {
+    println!("TEST - Assistant With Knowledge Base");
+
+    let response = hc
+        .do_post("/api/writing-assistant", json!({ "title": "Continuity", "document_id": 2 }))
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Create session failed with status: {}", response.status()));
+    }
+    let session_id = response.json_body()?["id"].as_i64().ok_or_else(|| anyhow!("Created session has no id"))?;
+
+    let response = hc
+        .do_post(
+            &format!("/api/writing-assistant/{}/message", session_id),
+            json!({ "content": "Where did Mira sail to?" }),
+        )
+        .await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Message with a knowledge base failed with status: {}", response.status()));
+    }
+
+    let response = hc
+        .do_post(
+            &format!("/api/writing-assistant/{}/agent", session_id),
+            json!({ "content": "Which documents mention Eldham?" }),
+        )
+        .await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Agent with a knowledge base failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
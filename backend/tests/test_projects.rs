@@ -15,6 +15,8 @@ async fn test_projects() -> Result<()> {
     let get_all_p_result = test_get_all_projects(&hc).await;
     let get_p_result = test_get_project(&hc).await;
     let update_p_result = test_update_project(&hc).await;
+    let persona_result = test_project_persona(&hc).await;
+    let stats_result = test_project_stats(&hc).await;
     let add_perm_result = test_add_permissions(&hc).await;
     let get_perm_result = test_get_permissions(&hc).await;
     let upd_perm_result = test_update_permission(&hc).await;
@@ -39,6 +41,8 @@ async fn test_projects() -> Result<()> {
     println!("Get All Projects:\t{}", result_to_string(&get_all_p_result));
     println!("Get Project:\t\t{}", result_to_string(&get_p_result));
     println!("Update Project:\t\t{}", result_to_string(&update_p_result));
+    println!("Project Persona:\t{}", result_to_string(&persona_result));
+    println!("Project Stats:\t\t{}", result_to_string(&stats_result));
     println!("Add Permissions:\t{}", result_to_string(&add_perm_result));
     println!("Get Permissions:\t{}", result_to_string(&get_perm_result));
     println!("Update Permission:\t{}", result_to_string(&upd_perm_result));
@@ -167,6 +171,74 @@ async fn test_update_project(hc: &Client) -> Result<()> {
     Ok(())
 }
 
+async fn test_project_persona(hc: &Client) -> Result<()> {
+    println!("TEST - Project Persona");
+
+    // Use the built-in technical reviewer for the project
+    let set_response = hc
+        .do_put(
+            "/api/project/2/persona",
+            json!({
+                "persona_id": 3,
+                "system_prompt": null
+            }),
+        )
+        .await?;
+
+    set_response.print().await?;
+
+    if !set_response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Set project persona failed with status: {}",
+            set_response.status()
+        ));
+    }
+
+    let get_response = hc.do_get("/api/project/2/persona").await?;
+    get_response.print().await?;
+
+    let body = get_response.json_body()?;
+    if body["persona_id"].as_i64() != Some(3) || !body["system_prompt"].is_null() {
+        return Err(anyhow::anyhow!("Project persona was not stored: {}", body));
+    }
+
+    Ok(())
+}
+
+async fn test_project_stats(hc: &Client) -> Result<()> {
+    println!("TEST - Project Stats");
+
+    // Project 1 holds the seeded documents
+    let response = hc.do_get("/api/project/1/stats").await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Get project stats failed with status: {}", response.status()));
+    }
+
+    // The project counts every word of its documents
+    let body = response.json_body()?;
+    let documents = body["documents"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Project stats have no documents: {}", body))?;
+    let document_words: i64 = documents.iter().map(|document| document["word_count"].as_i64().unwrap_or(0)).sum();
+    if documents.is_empty() || body["metrics"]["word_count"].as_i64() != Some(document_words) {
+        return Err(anyhow::anyhow!("Project word count differs from its documents: {}", body));
+    }
+
+    let history_response = hc.do_get("/api/project/1/stats/history").await?;
+    history_response.print().await?;
+    let history = history_response.json_body()?;
+    let latest = history
+        .as_array()
+        .and_then(|snapshots| snapshots.last())
+        .ok_or_else(|| anyhow::anyhow!("Project stats history is empty"))?;
+    if latest["word_count"].as_i64() != Some(document_words) {
+        return Err(anyhow::anyhow!("Unexpected latest project snapshot: {}", latest));
+    }
+
+    Ok(())
+}
+
 async fn test_delete_project(hc: &Client) -> Result<()> {
     println!("TEST - Delete Project");
 
@@ -0,0 +1,223 @@
+#![allow(unused)]
+
+use anyhow::{anyhow, Result};
+use backend::result_to_string;
+use httpc_test::Client;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_proofread() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+
+    println!("\n===== RUNNING PROOFREAD API TESTS =====\n");
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let languages = test_get_languages(&hc).await;
+    let style_issues = test_proofread_style_issues(&hc).await;
+    let spelling = test_proofread_spelling(&hc).await;
+    let custom_dictionary = test_custom_dictionary(&hc).await;
+    let no_credits = test_proofread_costs_no_credits(&hc).await;
+    let bad_language = test_proofread_invalid_language(&hc).await;
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Get Languages\t\t{}", result_to_string(&languages));
+    println!("Style Issues\t\t{}", result_to_string(&style_issues));
+    println!("Spelling\t\t{}", result_to_string(&spelling));
+    println!("Custom Dictionary\t{}", result_to_string(&custom_dictionary));
+    println!("Costs No Credits\t{}", result_to_string(&no_credits));
+    println!("Invalid Language\t{}", result_to_string(&bad_language));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn proofread(hc: &Client, content: &str) -> Result<Value> {
+    let response = hc.do_post("/api/proofread", json!({ "content": content })).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Proofread failed with status: {}", response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+// Issues of one kind, as (start_offset, end_offset, text)
+fn issues_of(body: &Value, kind: &str) -> Vec<(u64, u64, String)> {
+    body["issues"]
+        .as_array()
+        .map(|issues| {
+            issues
+                .iter()
+                .filter(|issue| issue["kind"] == kind)
+                .map(|issue| {
+                    (
+                        issue["start_offset"].as_u64().unwrap_or(0),
+                        issue["end_offset"].as_u64().unwrap_or(0),
+                        issue["text"].as_str().unwrap_or("").to_string(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn test_get_languages(hc: &Client) -> Result<()> {
+    println!("TEST - Get Proofread Languages");
+
+    let response = hc.do_get("/api/proofread/languages").await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Get languages failed with status: {}", response.status()));
+    }
+
+    let body = response.json_body()?;
+    if body["default_language"].as_str().is_none() || !body["languages"].is_array() {
+        return Err(anyhow!("Unexpected languages response: {}", body));
+    }
+
+    Ok(())
+}
+
+async fn test_proofread_style_issues(hc: &Client) -> Result<()> {
+    println!("TEST - Proofread Style Issues");
+
+    let long_sentence = "and the story went on ".repeat(8);
+    let content = format!("The letter was written by the the mayor. Then {}forever.", long_sentence);
+    let body = proofread(hc, &content).await?;
+
+    let repeated = issues_of(&body, "repeated_word");
+    if repeated != vec![(26, 33, "the the".to_string())] {
+        return Err(anyhow!("Unexpected repeated word issues: {:?}", repeated));
+    }
+    let passive = issues_of(&body, "passive_voice");
+    if passive != vec![(11, 22, "was written".to_string())] {
+        return Err(anyhow!("Unexpected passive voice issues: {:?}", passive));
+    }
+    let long = issues_of(&body, "long_sentence");
+    if long.len() != 1 || long[0].0 != 41 || long[0].1 as usize != content.chars().count() {
+        return Err(anyhow!("Unexpected long sentence issues: {:?}", long));
+    }
+
+    let repeated_issue = body["issues"]
+        .as_array()
+        .and_then(|issues| issues.iter().find(|issue| issue["kind"] == "repeated_word"))
+        .ok_or_else(|| anyhow!("Repeated word issue missing"))?;
+    if repeated_issue["suggestions"] != json!(["the"]) {
+        return Err(anyhow!("Unexpected repeated word suggestions: {}", repeated_issue));
+    }
+
+    Ok(())
+}
+
+async fn test_proofread_spelling(hc: &Client) -> Result<()> {
+    println!("TEST - Proofread Spelling");
+
+    let body = proofread(hc, "Teh quick brown fox jumps over the lazy dog.").await?;
+
+    // Spelling is only checked when the server has a dictionary (deploy/fetch_dictionaries.sh)
+    if body["dictionary_loaded"] != true {
+        println!("No dictionary installed, skipping spelling assertions");
+        return if issues_of(&body, "spelling").is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Spelling issues without a dictionary: {}", body))
+        };
+    }
+
+    let spelling = issues_of(&body, "spelling");
+    if spelling != vec![(0, 3, "Teh".to_string())] {
+        return Err(anyhow!("Unexpected spelling issues: {:?}", spelling));
+    }
+    let suggestions = &body["issues"][0]["suggestions"];
+    if !suggestions.as_array().is_some_and(|suggestions| suggestions.contains(&json!("The"))) {
+        return Err(anyhow!("\"The\" was not suggested: {}", suggestions));
+    }
+
+    Ok(())
+}
+
+async fn test_custom_dictionary(hc: &Client) -> Result<()> {
+    println!("TEST - Custom Dictionary");
+
+    let response = hc.do_post("/api/proofread/dictionary", json!({ "word": "Vynnish" })).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Add dictionary word failed with status: {}", response.status()));
+    }
+
+    let words = hc.do_get("/api/proofread/dictionary").await?.json_body()?;
+    if !words.as_array().is_some_and(|words| words.iter().any(|word| word["word"] == "vynnish")) {
+        return Err(anyhow!("Added word is not in the dictionary: {}", words));
+    }
+
+    // A custom word is never reported, whatever the dictionary
+    let body = proofread(hc, "The Vynnish editor.").await?;
+    if !issues_of(&body, "spelling").is_empty() {
+        return Err(anyhow!("Custom word was reported: {}", body));
+    }
+
+    let response = hc.do_delete("/api/proofread/dictionary/vynnish").await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Delete dictionary word failed with status: {}", response.status()));
+    }
+
+    let response = hc.do_delete("/api/proofread/dictionary/vynnish").await?;
+    if response.status() != 404 {
+        return Err(anyhow!("Deleting a missing word returned {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn test_proofread_costs_no_credits(hc: &Client) -> Result<()> {
+    println!("TEST - Proofread Costs No Credits");
+
+    let before = hc.do_get("/api/users/credits").await?.json_body()?;
+    proofread(hc, "It was decided that the the plan is good.").await?;
+    let after = hc.do_get("/api/users/credits").await?.json_body()?;
+
+    if before["balance"] != after["balance"] {
+        return Err(anyhow!("Balance changed from {} to {}", before["balance"], after["balance"]));
+    }
+
+    Ok(())
+}
+
+async fn test_proofread_invalid_language(hc: &Client) -> Result<()> {
+    println!("TEST - Proofread Invalid Language");
+
+    let response = hc
+        .do_post("/api/proofread", json!({ "content": "Hello", "language": "../secrets" }))
+        .await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Invalid language returned {}", response.status()));
+    }
+
+    Ok(())
+}
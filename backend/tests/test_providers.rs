@@ -0,0 +1,160 @@
+#![allow(unused)]
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use axum::{extract::Extension, routing::post, Json, Router};
+use backend::result_to_string;
+use httpc_test::Client;
+use serde_json::{json, Value};
+
+// Address of the fake Ollama server, start the server under test with
+// LLM_PROVIDER=ollama OLLAMA_BASE_URL=http://localhost:11435 to send its requests here
+const FAKE_OLLAMA_ADDRESS: &str = "127.0.0.1:11435";
+const FAKE_OLLAMA_ANSWER: &str = "Fake Ollama answer";
+
+// Bodies of the requests the fake Ollama server received, by path
+type Received = Arc<Mutex<Vec<(String, Value)>>>;
+
+#[tokio::test]
+async fn test_providers() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+
+    println!("\n===== RUNNING PROVIDER API TESTS =====\n");
+
+    let received = start_fake_ollama()?;
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let selection = test_provider_selection(&hc, &received).await;
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Provider Selection\t{}", result_to_string(&selection));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+// Serves the two Ollama endpoints the server uses, recording every request
+fn start_fake_ollama() -> Result<Received> {
+    let received: Received = Arc::new(Mutex::new(Vec::new()));
+
+    async fn chat(Extension(received): Extension<Received>, Json(body): Json<Value>) -> Json<Value> {
+        received.lock().unwrap().push(("/api/chat".to_string(), body));
+        Json(json!({ "message": { "role": "assistant", "content": FAKE_OLLAMA_ANSWER }, "done": true }))
+    }
+    async fn embed(Extension(received): Extension<Received>, Json(body): Json<Value>) -> Json<Value> {
+        let inputs = body["input"].as_array().map(|inputs| inputs.len()).unwrap_or(1);
+        received.lock().unwrap().push(("/api/embed".to_string(), body));
+        Json(json!({ "embeddings": vec![vec![0.6, 0.8, 0.0, 0.0]; inputs] }))
+    }
+
+    let app = Router::new()
+        .route("/api/chat", post(chat))
+        .route("/api/embed", post(embed))
+        .layer(Extension(received.clone()));
+    let address: SocketAddr = FAKE_OLLAMA_ADDRESS.parse()?;
+    let server = axum::Server::try_bind(&address)?.serve(app.into_make_service());
+    tokio::spawn(server);
+
+    Ok(received)
+}
+
+// Models the fake Ollama server was asked for at a path
+fn requested_models(received: &Received, path: &str) -> Vec<String> {
+    received
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(request_path, _)| request_path == path)
+        .filter_map(|(_, body)| body["model"].as_str().map(str::to_string))
+        .collect()
+}
+
+async fn test_provider_selection(hc: &Client, received: &Received) -> Result<()> {
+    println!("TEST - Provider Selection");
+
+    let response = hc.do_get("/api/writing-assistant/providers").await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Getting the providers failed with status: {}", response.status()));
+    }
+    let providers = response.json_body()?;
+    let completion = providers["completion"].as_str().unwrap_or_default().to_string();
+    let embedding = providers["embedding"].as_str().unwrap_or_default().to_string();
+
+    let session = hc
+        .do_post("/api/writing-assistant", json!({ "title": "Provider Check", "document_id": null }))
+        .await?
+        .json_body()?;
+    let response = hc
+        .do_post(&format!("/api/writing-assistant/{}/message", session["id"]), json!({ "content": "Which model answers?" }))
+        .await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Send message failed with status: {}", response.status()));
+    }
+    let answer = response.json_body()?["content"].as_str().unwrap_or_default().to_string();
+
+    match (completion.split_once('/'), embedding.split_once('/')) {
+        // LLM_PROVIDER=mock, as in the offline test setup
+        (Some(("mock", "mock-completion")), Some(("mock", "mock-embedding"))) => {
+            if !answer.starts_with("Mock assistant response to: Which model answers?") {
+                return Err(anyhow!("The mock provider did not answer: {}", answer));
+            }
+            if !received.lock().unwrap().is_empty() {
+                return Err(anyhow!("The mock provider sent requests to Ollama"));
+            }
+        }
+        // LLM_PROVIDER=ollama with OLLAMA_BASE_URL pointing at the fake server
+        (Some(("ollama", model)), Some(("ollama", embedding_model))) => {
+            if answer != FAKE_OLLAMA_ANSWER {
+                return Err(anyhow!("The answer did not come from Ollama: {}", answer));
+            }
+            let chat_models = requested_models(received, "/api/chat");
+            if chat_models.is_empty() || chat_models.iter().any(|requested| requested != model) {
+                return Err(anyhow!("Ollama was asked for {:?} instead of {}", chat_models, model));
+            }
+            let embedding_models = requested_models(received, "/api/embed");
+            if embedding_models.is_empty() || embedding_models.iter().any(|requested| requested != embedding_model) {
+                return Err(anyhow!("Ollama embeddings were asked for {:?} instead of {}", embedding_models, embedding_model));
+            }
+        }
+        _ => return Err(anyhow!("Unexpected providers, start the server with LLM_PROVIDER=mock or ollama: {}", providers)),
+    }
+
+    // The providers are only shown to signed in users
+    let anonymous = httpc_test::new_client("http://localhost:3001")?;
+    let response = anonymous.do_get("/api/writing-assistant/providers").await?;
+    if response.status() != 403 {
+        return Err(anyhow!("Providers without login returned {}", response.status()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+#![allow(unused)]
+
+use anyhow::{anyhow, Result};
+use backend::{result_to_string, wait_for_embedding_queue};
+use chrono::Utc;
+use httpc_test::Client;
+use serde_json::{json, Value};
+
+// Document B is a copy of document A, document C reuses the first sentence of A
+const ORIGINAL: &str = "<p>Every lantern along our northern quay was lit before that storm arrived tonight. Harbour masters counted twelve ships drifting past broken piers while gulls screamed overhead.</p>";
+const REUSED: &str = "<p>Nobody knew. Every lantern along our northern quay was lit before that storm arrived tonight. Mira laughed.</p>";
+
+#[tokio::test]
+async fn test_similarity() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+
+    println!("\n===== RUNNING SIMILARITY API TESTS =====\n");
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let documents = setup_documents(&hc).await;
+    let (report, reused, own) = match &documents {
+        Ok(ids) => (
+            test_similarity_report(&hc, ids).await,
+            test_reused_text(&hc, ids).await,
+            test_own_documents(&hc, ids).await,
+        ),
+        Err(_) => (Err(anyhow!("Skipped")), Err(anyhow!("Skipped")), Err(anyhow!("Skipped"))),
+    };
+    let invalid = test_invalid_similarity(&hc).await;
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Documents Setup\t\t{}", result_to_string(&documents.map(|_| ())));
+    println!("Similarity Report\t{}", result_to_string(&report));
+    println!("Reused Text\t\t{}", result_to_string(&reused));
+    println!("Own Documents\t\t{}", result_to_string(&own));
+    println!("Invalid Similarity\t{}", result_to_string(&invalid));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+// Creates documents A, B and C in project 2 and returns their ids
+async fn setup_documents(hc: &Client) -> Result<[i64; 3]> {
+    println!("TEST - Documents Setup");
+
+    let mut ids = [0; 3];
+    for (index, (name, content)) in [("Original", ORIGINAL), ("Copy", ORIGINAL), ("Reuse", REUSED)].into_iter().enumerate() {
+        let now = Utc::now().naive_utc();
+        let response = hc
+            .do_post("/api/document", json!({ "name": name, "content": content, "created_at": now, "updated_at": now }))
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Creating {} failed with status: {}", name, response.status()));
+        }
+        ids[index] = response.json_body()?["id"].as_i64().ok_or_else(|| anyhow!("Created document has no id"))?;
+
+        let response = hc.do_post(&format!("/api/project/2/documents/{}", ids[index]), json!({})).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Adding {} to project 2 failed with status: {}", name, response.status()));
+        }
+    }
+
+    wait_for_embedding_queue(hc).await?;
+    Ok(ids)
+}
+
+async fn similarity_report(hc: &Client, query: &str) -> Result<Value> {
+    let response = hc.do_get(&format!("/api/document/similarity{}", query)).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Similarity report failed with status: {}", response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+fn find_pair(report: &Value, document_id: i64, other_document_id: i64) -> Option<&Value> {
+    report["pairs"]
+        .as_array()?
+        .iter()
+        .find(|pair| pair["document_id"] == document_id && pair["other_document_id"] == other_document_id)
+}
+
+async fn test_similarity_report(hc: &Client, [original, copy, reuse]: &[i64; 3]) -> Result<()> {
+    println!("TEST - Similarity Report");
+
+    let report = similarity_report(hc, "?project_id=2").await?;
+    if report["min_score"] != 0.9 || report["project_id"] != 2 {
+        return Err(anyhow!("Unexpected report parameters: {}", report));
+    }
+
+    // The copy covers the whole original and comes first
+    let pair = find_pair(&report, *original, *copy).ok_or_else(|| anyhow!("Copy was not found: {}", report))?;
+    if pair["is_copy"] != true || pair["coverage"] != 1.0 || report["pairs"][0]["is_copy"] != true {
+        return Err(anyhow!("Copy was not reported as one: {}", pair));
+    }
+    let span = &pair["spans"][0];
+    if span["start_offset"] != 0 || span["other_start_offset"] != 0 || span["word_count"] != 26 {
+        return Err(anyhow!("Unexpected span: {}", span));
+    }
+    if pair["passages"][0]["kind"] != "near_duplicate" {
+        return Err(anyhow!("Copied passage is no near duplicate: {}", pair["passages"]));
+    }
+
+    // One reused sentence is not similar enough by default
+    if find_pair(&report, *original, *reuse).is_some() {
+        return Err(anyhow!("Reused sentence passed the default score: {}", report));
+    }
+
+    Ok(())
+}
+
+async fn test_reused_text(hc: &Client, [original, _, reuse]: &[i64; 3]) -> Result<()> {
+    println!("TEST - Reused Text");
+
+    let report = similarity_report(hc, "?project_id=2&min_score=0.5").await?;
+    let pair = find_pair(&report, *original, *reuse).ok_or_else(|| anyhow!("Reused text was not found: {}", report))?;
+    if pair["is_copy"] != false {
+        return Err(anyhow!("Reused sentence was reported as a copy: {}", pair));
+    }
+
+    // The span is aligned in both documents
+    let span = &pair["spans"][0];
+    if span["start_offset"] != 0 || span["other_start_offset"] != 13 || span["word_count"] != 13 {
+        return Err(anyhow!("Unexpected span: {}", span));
+    }
+    if span["text"] != "Every lantern along our northern quay was lit before that storm arrived tonight" {
+        return Err(anyhow!("Unexpected span text: {}", span));
+    }
+    if pair["passages"][0]["kind"] != "reused_text" {
+        return Err(anyhow!("Unexpected passage kind: {}", pair["passages"]));
+    }
+
+    Ok(())
+}
+
+async fn test_own_documents(hc: &Client, [original, copy, _]: &[i64; 3]) -> Result<()> {
+    println!("TEST - Own Documents");
+
+    // Without a project the documents the user owns are compared
+    let report = similarity_report(hc, "").await?;
+    if !report["project_id"].is_null() || find_pair(&report, *original, *copy).is_none() {
+        return Err(anyhow!("Copy was not found among the user's documents: {}", report));
+    }
+
+    Ok(())
+}
+
+async fn test_invalid_similarity(hc: &Client) -> Result<()> {
+    println!("TEST - Invalid Similarity");
+
+    let response = hc.do_get("/api/document/similarity?min_score=0.2").await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Similarity report with a low score returned {}", response.status()));
+    }
+    let response = hc.do_get("/api/document/similarity?project_id=99999").await?;
+    if response.status() != 403 {
+        return Err(anyhow!("Similarity report of a foreign project returned {}", response.status()));
+    }
+
+    Ok(())
+}
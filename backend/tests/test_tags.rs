@@ -0,0 +1,295 @@
+#![allow(unused)]
+
+use anyhow::{anyhow, Result};
+use backend::{expect_success, result_to_string, wait_for_embedding_queue};
+use chrono::Utc;
+use httpc_test::Client;
+use serde_json::{json, Value};
+
+// The mock model always suggests the tags "Draft", "#Notes" and "draft" and the topics "Writing" and "notes"
+const HARBOUR: &str = "<p>The harbour lanterns burned all night. Every harbour keeper watched the lanterns while storms gathered over the harbour.</p>";
+const HARBOUR_NOTES: &str = "<p>Harbour keepers lit the lanterns before storms. The harbour stayed bright all night.</p>";
+const RECIPE: &str = "<p>Whisk flour, butter and sugar until smooth, then bake the cake for forty minutes.</p>";
+
+struct Documents {
+    harbour: i64,
+    notes: i64,
+    recipe: i64,
+}
+
+#[tokio::test]
+async fn test_tags() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+
+    println!("\n===== RUNNING TAGS API TESTS =====\n");
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let setup = setup_documents(&hc).await;
+    let (suggestions, set_tags, filters, related, dismiss) = match &setup {
+        Ok(documents) => (
+            test_tag_suggestions(&hc, documents).await,
+            test_set_document_tags(&hc, documents).await,
+            test_tag_filters(&hc, documents).await,
+            test_related_documents(&hc, documents).await,
+            test_dismiss_suggestions(&hc, documents).await,
+        ),
+        Err(_) => (Err(anyhow!("Skipped")), Err(anyhow!("Skipped")), Err(anyhow!("Skipped")), Err(anyhow!("Skipped")), Err(anyhow!("Skipped"))),
+    };
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Documents Setup\t\t{}", result_to_string(&setup.map(|_| ())));
+    println!("Tag Suggestions\t\t{}", result_to_string(&suggestions));
+    println!("Set Document Tags\t{}", result_to_string(&set_tags));
+    println!("Tag Filters\t\t{}", result_to_string(&filters));
+    println!("Related Documents\t{}", result_to_string(&related));
+    println!("Dismiss Suggestions\t{}", result_to_string(&dismiss));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn create_document(hc: &Client, name: &str, content: &str) -> Result<i64> {
+    let now = Utc::now().naive_utc();
+    let response = hc
+        .do_post("/api/document", json!({ "name": name, "content": content, "created_at": now, "updated_at": now }))
+        .await?;
+    expect_success(response, &format!("Creating {}", name)).await?["id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Created document has no id"))
+}
+
+// Three new documents, the first one in project 2. Tags are suggested once they are embedded.
+async fn setup_documents(hc: &Client) -> Result<Documents> {
+    println!("TEST - Documents Setup");
+
+    let documents = Documents {
+        harbour: create_document(hc, "Harbour", HARBOUR).await?,
+        notes: create_document(hc, "Harbour Notes", HARBOUR_NOTES).await?,
+        recipe: create_document(hc, "Recipe", RECIPE).await?,
+    };
+    let response = hc.do_post(&format!("/api/project/2/documents/{}", documents.harbour), json!({})).await?;
+    expect_success(response, "Adding the harbour document to project 2").await?;
+
+    wait_for_embedding_queue(hc).await?;
+    Ok(documents)
+}
+
+async fn document_tags(hc: &Client, document_id: i64) -> Result<Value> {
+    let response = hc.do_get(&format!("/api/document/{}/tags", document_id)).await?;
+    response.print().await?;
+    expect_success(response, "Getting the tags").await
+}
+
+fn names(values: &Value) -> Vec<String> {
+    values
+        .as_array()
+        .map(|values| values.iter().map(|value| value["name"].as_str().or(value.as_str()).unwrap_or_default().to_string()).collect())
+        .unwrap_or_default()
+}
+
+fn ids(documents: &Value) -> Vec<i64> {
+    documents
+        .as_array()
+        .map(|documents| documents.iter().filter_map(|document| document["id"].as_i64()).collect())
+        .unwrap_or_default()
+}
+
+async fn test_tag_suggestions(hc: &Client, documents: &Documents) -> Result<()> {
+    println!("TEST - Tag Suggestions");
+
+    // Suggestions are made when the document is saved and are not applied on their own
+    let tags = document_tags(hc, documents.harbour).await?;
+    if !tags["tags"].as_array().is_some_and(|tags| tags.is_empty()) {
+        return Err(anyhow!("Suggestions were applied: {}", tags));
+    }
+    // Names are normalized and each is suggested once, a topic that is also a tag as the tag
+    if names(&tags["suggestions"]) != ["draft", "notes", "writing"] {
+        return Err(anyhow!("Unexpected suggestions: {}", tags["suggestions"]));
+    }
+    let kinds: Vec<&str> = tags["suggestions"]
+        .as_array()
+        .map(|suggestions| suggestions.iter().filter_map(|suggestion| suggestion["kind"].as_str()).collect())
+        .unwrap_or_default();
+    if kinds != ["tag", "tag", "topic"] {
+        return Err(anyhow!("Unexpected suggestion kinds: {}", tags["suggestions"]));
+    }
+
+    Ok(())
+}
+
+async fn test_set_document_tags(hc: &Client, documents: &Documents) -> Result<()> {
+    println!("TEST - Set Document Tags");
+
+    // Names are normalized and duplicates dropped, an accepted suggestion is no suggestion anymore
+    let response = hc
+        .do_put(
+            &format!("/api/document/{}/tags", documents.harbour),
+            json!({ "tags": ["Harbour", " Night  Shift ", "#harbour", "Draft"] }),
+        )
+        .await?;
+    let tags = expect_success(response, "Setting the tags").await?;
+    if tags["tags"] != json!(["draft", "harbour", "night shift"]) {
+        return Err(anyhow!("Unexpected tags: {}", tags));
+    }
+    if names(&tags["suggestions"]) != ["notes", "writing"] {
+        return Err(anyhow!("Accepted suggestion is still suggested: {}", tags["suggestions"]));
+    }
+
+    let response = hc
+        .do_put(&format!("/api/document/{}/tags", documents.harbour), json!({ "tags": ["x".repeat(51)] }))
+        .await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Too long tag returned {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn test_tag_filters(hc: &Client, documents: &Documents) -> Result<()> {
+    println!("TEST - Tag Filters");
+
+    let response = hc.do_get("/api/document?tag=Harbour").await?;
+    let listed = expect_success(response, "Listing documents by tag").await?;
+    if ids(&listed) != [documents.harbour] {
+        return Err(anyhow!("Unexpected documents with the tag: {}", listed));
+    }
+
+    let response = hc.do_get("/api/project?tag=night%20shift").await?;
+    let projects = expect_success(response, "Listing projects by tag").await?;
+    if ids(&projects) != [2] {
+        return Err(anyhow!("Unexpected projects with the tag: {}", projects));
+    }
+
+    let response = hc.do_get("/api/project/2/documents?tag=harbour").await?;
+    let listed = expect_success(response, "Listing project documents by tag").await?;
+    if ids(&listed) != [documents.harbour] {
+        return Err(anyhow!("Unexpected project documents with the tag: {}", listed));
+    }
+    let response = hc.do_get("/api/project/2/documents?tag=unknown").await?;
+    let listed = expect_success(response, "Listing project documents by an unused tag").await?;
+    if !ids(&listed).is_empty() {
+        return Err(anyhow!("Documents listed for an unused tag: {}", listed));
+    }
+
+    let response = hc.do_get("/api/document/tags").await?;
+    let tags = expect_success(response, "Listing the user's tags").await?;
+    let harbour = tags.as_array().and_then(|tags| tags.iter().find(|tag| tag["name"] == "harbour"));
+    if !harbour.is_some_and(|tag| tag["document_count"] == 1) {
+        return Err(anyhow!("Unexpected tag counts: {}", tags));
+    }
+
+    Ok(())
+}
+
+async fn test_related_documents(hc: &Client, documents: &Documents) -> Result<()> {
+    println!("TEST - Related Documents");
+
+    let response = hc
+        .do_put(&format!("/api/document/{}/tags", documents.notes), json!({ "tags": ["harbour"] }))
+        .await?;
+    expect_success(response, "Tagging the notes").await?;
+
+    let response = hc.do_get(&format!("/api/document/{}/related?limit=20", documents.harbour)).await?;
+    response.print().await?;
+    let related = expect_success(response, "Getting related documents").await?;
+    if related["pending"] != false {
+        return Err(anyhow!("Embedded document is pending: {}", related));
+    }
+    let listed = ids(&related["documents"]);
+    let position = |id: i64| listed.iter().position(|listed| *listed == id);
+    match (position(documents.notes), position(documents.recipe)) {
+        (Some(notes), Some(recipe)) if notes < recipe => {}
+        _ => return Err(anyhow!("Notes are not more related than the recipe: {}", related)),
+    }
+    if position(documents.harbour).is_some() {
+        return Err(anyhow!("Document is related to itself: {}", related));
+    }
+    let notes = &related["documents"][position(documents.notes).unwrap_or(0)];
+    if notes["shared_tags"] != json!(["harbour"]) {
+        return Err(anyhow!("Unexpected shared tags: {}", notes));
+    }
+
+    let response = hc.do_get(&format!("/api/document/{}/related?limit=0", documents.harbour)).await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Related documents with limit 0 returned {}", response.status()));
+    }
+    let response = hc.do_get("/api/document/99999/related").await?;
+    if response.status() != 403 {
+        return Err(anyhow!("Related documents of a foreign document returned {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn test_dismiss_suggestions(hc: &Client, documents: &Documents) -> Result<()> {
+    println!("TEST - Dismiss Suggestions");
+
+    let response = hc.do_delete(&format!("/api/document/{}/tags/suggestions", documents.harbour)).await?;
+    let tags = expect_success(response, "Dismissing the suggestions").await?;
+    if !tags["suggestions"].as_array().is_some_and(|suggestions| suggestions.is_empty()) || names(&tags["tags"]).len() != 3 {
+        return Err(anyhow!("Unexpected tags after dismissing: {}", tags));
+    }
+
+    // Saving the same text again suggests nothing and charges nothing
+    let content = format!("{}<p>More storms.</p>", HARBOUR);
+    let ledger_start = backend::last_ledger_id(hc).await?;
+    save_harbour(hc, documents, HARBOUR).await?;
+    let tags = document_tags(hc, documents.harbour).await?;
+    if !tags["suggestions"].as_array().is_some_and(|suggestions| suggestions.is_empty()) {
+        return Err(anyhow!("Unchanged text was tagged again: {}", tags["suggestions"]));
+    }
+    if backend::credit_charges_since(hc, "tag_suggestion", ledger_start).await? != 0 {
+        return Err(anyhow!("Unchanged text was charged for tag suggestions"));
+    }
+
+    // Changing the text suggests tags again for one charge, leaving out the tags it has
+    save_harbour(hc, documents, &content).await?;
+    let tags = document_tags(hc, documents.harbour).await?;
+    if names(&tags["suggestions"]) != ["notes", "writing"] {
+        return Err(anyhow!("Unexpected suggestions after saving: {}", tags["suggestions"]));
+    }
+    let charges = backend::credit_charges_since(hc, "tag_suggestion", ledger_start).await?;
+    if charges != 1 {
+        return Err(anyhow!("Expected 1 tag suggestion charge, found {}", charges));
+    }
+
+    Ok(())
+}
+
+// Saves the harbour document and waits until it is embedded and tagged
+async fn save_harbour(hc: &Client, documents: &Documents, content: &str) -> Result<()> {
+    let response = hc
+        .do_put(
+            &format!("/api/document/{}", documents.harbour),
+            json!({ "name": "Harbour", "content": content, "updated_at": Utc::now().naive_utc() }),
+        )
+        .await?;
+    expect_success(response, "Saving the harbour document").await?;
+    wait_for_embedding_queue(hc).await
+}
@@ -0,0 +1,298 @@
+#![allow(unused)]
+
+use anyhow::{anyhow, Result};
+use backend::result_to_string;
+use chrono::Utc;
+use httpc_test::Client;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_translation() -> Result<()> {
+    let hc = httpc_test::new_client("http://localhost:3001")?;
+
+    println!("\n===== RUNNING TRANSLATION API TESTS =====\n");
+
+    // Run all tests and collect results
+    let login_result = test_good_login(&hc).await;
+    let glossary = test_glossary(&hc).await;
+    let selection = test_translate_selection(&hc).await;
+    let document = test_translate_document(&hc).await;
+    let project = test_translate_project(&hc).await;
+    let stale = test_stale_translation(&hc).await;
+    let invalid = test_invalid_language(&hc).await;
+    let reset_db = backend::test_reset_db(&hc).await;
+
+    // Print summary
+    println!("\n======== TEST RESULTS ========");
+    println!("Login as User 1\t\t{}", result_to_string(&login_result));
+    println!("Glossary\t\t{}", result_to_string(&glossary));
+    println!("Translate Selection\t{}", result_to_string(&selection));
+    println!("Translate Document\t{}", result_to_string(&document));
+    println!("Translate Project\t{}", result_to_string(&project));
+    println!("Stale Translation\t{}", result_to_string(&stale));
+    println!("Invalid Language\t{}", result_to_string(&invalid));
+    println!("Reset Database\t\t{}", result_to_string(&reset_db));
+    println!("==============================\n");
+
+    Ok(())
+}
+
+// Test login to set the auth cookie and allow for validation
+pub async fn test_good_login(hc: &Client) -> Result<()> {
+    print!("TEST - Good Login");
+    let response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("Login failed with status: {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn add_glossary_term(hc: &Client, payload: Value) -> Result<Value> {
+    let response = hc.do_post("/api/project/2/glossary", payload).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Adding a glossary term failed with status: {}", response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+// Translation links of a document
+async fn document_translations(hc: &Client, document_id: i64) -> Result<Value> {
+    let response = hc.do_get(&format!("/api/document/{}/translations", document_id)).await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Getting translations failed with status: {}", response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+async fn translate_document_2(hc: &Client) -> Result<Value> {
+    let response = hc
+        .do_post("/api/writing-assistant/translate/document/2", json!({ "target_language": "de" }))
+        .await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Translating document 2 failed with status: {}", response.status()));
+    }
+    Ok(response.json_body()?)
+}
+
+async fn test_glossary(hc: &Client) -> Result<()> {
+    println!("TEST - Glossary");
+
+    let keep = add_glossary_term(hc, json!({ "term": "Vynn", "rule": "keep", "notes": "Product name" })).await?;
+    let translate = add_glossary_term(
+        hc,
+        json!({ "term": "document", "rule": "translate", "translation": "Dokument", "language": "DE" }),
+    )
+    .await?;
+    if keep["rule"] != "keep" || !keep["language"].is_null() || translate["language"] != "de" {
+        return Err(anyhow!("Unexpected glossary terms: {} {}", keep, translate));
+    }
+
+    // Terms are unique per language ignoring case
+    let response = hc
+        .do_post("/api/project/2/glossary", json!({ "term": "VYNN", "rule": "keep" }))
+        .await?;
+    if response.status() != 409 {
+        return Err(anyhow!("Duplicate term returned {}", response.status()));
+    }
+    // Translate rules need a translation and a language
+    let response = hc
+        .do_post("/api/project/2/glossary", json!({ "term": "editor", "rule": "translate", "language": "de" }))
+        .await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Translate rule without translation returned {}", response.status()));
+    }
+
+    // Replace and remove a temporary term
+    let temporary = add_glossary_term(hc, json!({ "term": "draft", "rule": "keep" })).await?;
+    let response = hc
+        .do_put(
+            &format!("/api/project/2/glossary/{}", temporary["id"]),
+            json!({ "term": "draft", "rule": "translate", "translation": "Entwurf", "language": "de" }),
+        )
+        .await?;
+    response.print().await?;
+    let updated = response.json_body()?;
+    if updated["rule"] != "translate" || updated["translation"] != "Entwurf" {
+        return Err(anyhow!("Term was not replaced: {}", updated));
+    }
+    let response = hc.do_delete(&format!("/api/project/2/glossary/{}", temporary["id"])).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Deleting a term failed with status: {}", response.status()));
+    }
+    let response = hc.do_delete(&format!("/api/project/2/glossary/{}", temporary["id"])).await?;
+    if response.status() != 404 {
+        return Err(anyhow!("Deleting a missing term returned {}", response.status()));
+    }
+
+    let glossary = hc.do_get("/api/project/2/glossary").await?.json_body()?;
+    if glossary.as_array().is_none_or(|terms| terms.len() != 2) {
+        return Err(anyhow!("Unexpected glossary: {}", glossary));
+    }
+
+    Ok(())
+}
+
+async fn test_translate_selection(hc: &Client) -> Result<()> {
+    println!("TEST - Translate Selection");
+
+    // The mock model hands the text back untranslated, which keeps "Vynn" but breaks the "document" rule
+    let content = "Vynn keeps every document in sync.";
+    let ledger_start = backend::last_ledger_id(hc).await?;
+    let response = hc
+        .do_post(
+            "/api/writing-assistant/translate",
+            json!({
+                "content": content,
+                "target_language": "de",
+                "project_id": 2
+            }),
+        )
+        .await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Translation failed with status: {}", response.status()));
+    }
+
+    let result = response.json_body()?;
+    if result["translation"] != content {
+        return Err(anyhow!("Unexpected translation: {}", result));
+    }
+    // The broken rule is reported once the repair did not fix it, the kept term is no violation
+    let expected = json!([{ "term": "document", "expected": "Dokument", "source_occurrences": 1, "translation_occurrences": 0 }]);
+    if result["glossary_violations"] != expected {
+        return Err(anyhow!("Unexpected glossary violations: {}", result));
+    }
+
+    // The segment and its one repair are charged
+    let charges = backend::credit_charges_since(hc, "translate", ledger_start).await?;
+    if charges != 2 {
+        return Err(anyhow!("Expected 2 translate charges, found {}", charges));
+    }
+
+    Ok(())
+}
+
+async fn test_translate_document(hc: &Client) -> Result<()> {
+    println!("TEST - Translate Document");
+
+    // Document 2 uses the glossary of project 2
+    let response = hc.do_post("/api/project/2/documents/2", json!({})).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Adding document 2 to project 2 failed with status: {}", response.status()));
+    }
+
+    let result = translate_document_2(hc).await?;
+    if result["created"] != true || result["source_document_id"] != 2 || result["target_language"] != "de" {
+        return Err(anyhow!("Unexpected document translation: {}", result));
+    }
+
+    let translation_id = result["document_id"].as_i64().unwrap_or(0);
+    let links = document_translations(hc, translation_id).await?;
+    if links["source"]["source_document_id"] != 2 || links["source"]["stale"] != false {
+        return Err(anyhow!("Translation is not linked to its source: {}", links));
+    }
+
+    let source_links = document_translations(hc, 2).await?;
+    let listed = source_links["translations"]
+        .as_array()
+        .is_some_and(|links| links.iter().any(|link| link["document_id"] == translation_id));
+    if !listed || !source_links["source"].is_null() {
+        return Err(anyhow!("Translation is not listed for document 2: {}", source_links));
+    }
+
+    Ok(())
+}
+
+async fn test_translate_project(hc: &Client) -> Result<()> {
+    println!("TEST - Translate Project");
+
+    // Document 2 is up to date and its translation is not translated again
+    let response = hc
+        .do_post("/api/writing-assistant/translate/project/2", json!({ "target_language": "de" }))
+        .await?;
+    response.print().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Translating project 2 failed with status: {}", response.status()));
+    }
+    let result = response.json_body()?;
+    if result["up_to_date"] != json!([2]) || !result["documents"].as_array().is_some_and(|documents| documents.is_empty()) {
+        return Err(anyhow!("Unexpected project translation: {}", result));
+    }
+    if result["skipped"] != json!([]) || result["failures"] != json!([]) {
+        return Err(anyhow!("Project translation skipped or failed documents: {}", result));
+    }
+
+    let links = hc.do_get("/api/project/2/translations").await?.json_body()?;
+    if !links.as_array().is_some_and(|links| links.len() == 1 && links[0]["source_document_id"] == 2) {
+        return Err(anyhow!("Unexpected translations of project 2: {}", links));
+    }
+
+    Ok(())
+}
+
+async fn test_stale_translation(hc: &Client) -> Result<()> {
+    println!("TEST - Stale Translation");
+
+    let document = hc.do_get("/api/document/2").await?.json_body()?;
+    let content = format!("{}<p>A new document paragraph.</p>", document["content"].as_str().unwrap_or(""));
+    let response = hc
+        .do_put(
+            "/api/document/2",
+            json!({
+                "name": document["name"],
+                "content": content,
+                "updated_at": Utc::now().naive_utc()
+            }),
+        )
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Saving document 2 failed with status: {}", response.status()));
+    }
+
+    let links = document_translations(hc, 2).await?;
+    if links["translations"][0]["stale"] != true {
+        return Err(anyhow!("Translation is not stale after editing the source: {}", links));
+    }
+
+    // Translating again updates the linked document
+    let result = translate_document_2(hc).await?;
+    if result["created"] != false || result["document_id"] != links["translations"][0]["document_id"] {
+        return Err(anyhow!("Existing translation was not updated: {}", result));
+    }
+    let links = document_translations(hc, 2).await?;
+    if links["translations"][0]["stale"] != false {
+        return Err(anyhow!("Translation is still stale: {}", links));
+    }
+
+    Ok(())
+}
+
+async fn test_invalid_language(hc: &Client) -> Result<()> {
+    println!("TEST - Invalid Language");
+
+    let response = hc
+        .do_post(
+            "/api/writing-assistant/translate",
+            json!({ "content": "Hello", "target_language": "not a language" }),
+        )
+        .await?;
+    if response.status() != 400 {
+        return Err(anyhow!("Invalid language returned {}", response.status()));
+    }
+
+    Ok(())
+}
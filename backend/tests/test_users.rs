@@ -24,6 +24,7 @@ async fn test_users() -> Result<()> {
     let get_user_result = test_get_user(&hc).await;
     let get_current_user_result = test_get_current_user(&hc).await;
     let check_auth_result = test_check_auth(&hc).await;
+    let get_plan_result = test_get_user_plan(&hc).await;
     let upload_image_result = test_upload_profile_image(&hc).await;
     let get_image_result = test_get_profile_image(&hc, 1).await; // Assuming user 1 exists
     let logout_result = test_logout(&hc).await;
@@ -38,6 +39,7 @@ async fn test_users() -> Result<()> {
     println!("Get User:\t{}", result_to_string(&get_user_result));
     println!("Get Current User:\t{}", result_to_string(&get_current_user_result));
     println!("Check Auth:\t\t{}", result_to_string(&check_auth_result));
+    println!("Get Plan:\t\t{}", result_to_string(&get_plan_result));
     println!("Upload Image:\t{}", result_to_string(&upload_image_result));
     println!("Get Image:\t\t{}", result_to_string(&get_image_result));
     println!("Logout:\t\t{}", result_to_string(&logout_result));
@@ -219,6 +221,41 @@ async fn test_check_auth(hc: &Client) -> Result<()> {
     Ok(())
 }
 
+async fn test_get_user_plan(hc: &Client) -> Result<()> {
+    print!("TEST - Get User Plan");
+
+    // Users start on the free plan, seeded users included
+    let login_response = hc
+        .do_post(
+            "/api/users/login",
+            json!({
+                "email": "CFdefence@gmail.com",
+                "password": "MyPassword"
+            }),
+        )
+        .await?;
+    if !login_response.status().is_success() {
+        return Err(anyhow::anyhow!("Could not login for plan test"));
+    }
+
+    let response = hc.do_get("/api/users/plan").await?;
+    response.print().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Get User Plan failed with status: {}",
+            response.status()
+        ));
+    }
+
+    let body = response.json_body()?;
+    if body["plan"]["name"] != "free" || body["next_refill_at"].is_null() {
+        return Err(anyhow::anyhow!("Unexpected plan response: {}", body));
+    }
+
+    Ok(())
+}
+
 async fn test_upload_profile_image(hc: &Client) -> Result<()> {
     println!("TEST - Upload Profile Image");
     // TODO: Implement actual file upload logic here
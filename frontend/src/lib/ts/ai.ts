@@ -10,6 +10,7 @@
 / - WritingAssistantSession: Represents a writing assistant session.
 / - WritingAssistantMessage: Represents a message within a session.
 / - SessionWithMessages: Combines session data with its messages.
+/ - ChatBranch: One branch of a session's conversation, identified by its last message.
 / - CreateSessionPayload: Payload for creating a new session.
 / - SendMessagePayload: Payload for sending a message to a session.
 / - AssistantResponse: Expected structure for an AI assistant's response message.
@@ -17,14 +18,27 @@
 / - AiRewritePayload: Payload for the rewrite command, including style.
 / - AiCommandResponse: Expected structure for responses from AI text commands.
 / - SuggestedDocumentChange: Represents the proposed changes for a single document.
+/ - DocumentChangeFailure: A proposed change rejected by the backend, with the reason.
 / - SanitizeTextPayload: Payload for the new sanitize-text endpoint.
 / - SanitizeTextResponse: Response for the new sanitize-text endpoint.
+/ - EvidenceFactCheck: Verdicts on the claims of a text, with the passages of the user's sources they rest on.
 / 
 / Functions:
 / - get_all_writing_sessions: Fetches all sessions for the current user.
 / - create_writing_session: Creates a new writing session.
 / - get_writing_session: Fetches a specific session and its messages.
 / - send_writing_message: Sends a message to a session and gets the AI response.
+/ - send_agent_message: Sends a message in agent mode, the assistant answers using server-side tools.
+/ - get_tool_calls: Lists the tool calls of a session.
+/ - regenerate_writing_message: Gets another answer to a user message on a new branch.
+/ - edit_writing_message: Sends an edited user message on a new branch.
+/ - get_writing_branches: Lists the branches of a session.
+/ - switch_writing_branch: Switches a session to the branch containing a message.
+/ - get_personas: Lists the built-in and the user's own assistant personas.
+/ - create_persona: Creates a custom assistant persona.
+/ - delete_persona: Deletes a custom assistant persona.
+/ - set_session_persona: Sets the persona or system prompt of a session.
+/ - get_providers: Shows the completion and embedding models in use.
 / - delete_writing_session: Deletes a specific writing session.
 / - check_grammar: Sends text to the backend for grammar checking.
 / - summarize_text: Sends text to the backend for summarization.
@@ -33,6 +47,7 @@
 / - shrink_text: Sends text to the backend for shrinking.
 / - rewrite_text_as: Sends text and a style to the backend for rewriting.
 / - fact_check_text: Sends text to the backend for fact-checking.
+/ - fact_check_with_sources: Checks the claims of a text against project and reference documents.
 / - check_spelling: Sends text to the backend for spell checking.
 / - apply_ai_suggestion: Sends an AI suggestion to the backend to determine necessary document changes.
 / - getProactiveDiffDecision: Calls the backend to get AI decision on proactive diff.
@@ -75,12 +90,17 @@ export interface WritingAssistantSession {
     created_at: string;
     updated_at: string; 
     last_message_snippet?: string | null;
+    active_message_id?: number | null; // Last message of the branch the session is on
+    persona_id?: number | null;
+    system_prompt?: string | null; // Custom system prompt, takes precedence over persona_id
+    auto_title?: boolean; // Title is still to be generated from the first exchange
 }
 
 export interface WritingAssistantMessage {
     id: number;
     session_id: number;
-    role: 'user' | 'assistant' | string;
+    parent_message_id: number | null; // Edited and regenerated messages share the parent of the one they replace
+    role: 'system' | 'user' | 'assistant' | string;
     content: string;
     created_at: string;
 }
@@ -90,10 +110,67 @@ export interface SessionWithMessages {
     messages: WritingAssistantMessage[];
 }
 
+// One branch of a session's conversation, identified by its last message
+export interface ChatBranch {
+    leaf_message_id: number;
+    fork_message_id: number | null; // Where the branch leaves the active branch, null for the active branch
+    message_count: number;
+    last_message_snippet: string;
+    updated_at: string;
+    is_active: boolean;
+}
+
 // Payload for creating a new session
 export interface CreateSessionPayload {
     document_id: number | null;
-    title: string;
+    title?: string | null; // Generated from the first exchange when missing
+    persona_id?: number | null;
+    system_prompt?: string | null;
+}
+
+// A tool the assistant called in agent mode
+export interface AgentToolCall {
+    id: number;
+    session_id: number;
+    message_id: number | null; // Answer the call led to
+    step: number;
+    tool: 'search_project' | 'read_document' | 'list_outline' | 'count_words' | 'propose_edit' | string;
+    arguments: Record<string, unknown>;
+    status: 'ok' | 'denied' | 'error' | string;
+    result_summary: string;
+    created_at: string;
+}
+
+// Response of an agent turn, proposed changes are not applied
+export interface AgentResponse {
+    message_id: number;
+    role: 'assistant';
+    content: string;
+    tool_calls: AgentToolCall[];
+    proposed_changes: SuggestedDocumentChange[];
+    prompt_template: string;
+}
+
+// System prompt for the writing assistant, built-in personas have no user_id
+export interface Persona {
+    id: number;
+    user_id: number | null;
+    name: string;
+    description: string;
+    system_prompt: string;
+    created_at: string;
+}
+
+// Models in use, as "provider/model", e.g. "openai/gpt-3.5-turbo"
+export interface ProviderInfo {
+    completion: string;
+    embedding: string;
+}
+
+// Persona of a session or project, a custom system_prompt takes precedence over persona_id
+export interface PersonaSettings {
+    persona_id: number | null;
+    system_prompt: string | null;
 }
 
 // Payload for sending a message
@@ -103,6 +180,7 @@ export interface SendMessagePayload {
 
 // Expected structure for the response from the send_message API
 export interface AssistantResponse {
+    message_id: number;
     role: 'assistant';
     content: string;
 }
@@ -123,6 +201,20 @@ export interface SuggestedDocumentChange {
     document_id: number;
     old_content: string;
     new_content: string;
+    edit_count: number; // Search/replace edits the backend applied to old_content
+}
+
+// A proposed change the backend rejected, e.g. for a document the user cannot edit or an edit that did not apply
+export interface DocumentChangeFailure {
+    document_id: number;
+    reason: string;
+}
+
+// Response of the apply-suggestion endpoint
+interface ApplySuggestionResult {
+    changes: SuggestedDocumentChange[];
+    failures: DocumentChangeFailure[];
+    considered_document_ids: number[]; // Large projects only send the relevant documents to the model
 }
 
 // Define expected Response structure
@@ -130,6 +222,41 @@ interface AiCommandResponse {
 	response: string;
 }
 
+// Sources of an evidence fact check, the project's documents and/or further documents
+export interface EvidenceFactCheckPayload {
+    content: string;
+    project_id?: number;
+    reference_document_ids?: number[];
+    document_id?: number; // The document the text comes from, never evidence for itself
+}
+
+// A passage of the user's sources, offsets are characters of the document's plain text
+export interface EvidencePassage {
+    label: string; // S1, S2, ...
+    document_id: number;
+    document_name: string;
+    chunk_id: number | null;
+    heading: string | null;
+    start_offset: number | null;
+    end_offset: number | null;
+    text: string;
+}
+
+export interface CheckedClaim {
+    claim: string;
+    quote: string; // Where the claim is made, as written in the checked text
+    start_offset: number | null; // Characters of the checked text, null when the quote was not found
+    end_offset: number | null;
+    verdict: 'supported' | 'contradicted' | 'unknown';
+    explanation: string;
+    citations: EvidencePassage[]; // Empty for unknown claims
+}
+
+export interface EvidenceFactCheck {
+    claims: CheckedClaim[];
+    sources: EvidencePassage[]; // Every passage the claims were checked against
+}
+
 
 
 /**
@@ -227,6 +354,123 @@ export async function send_writing_message(sessionId: number, payload: SendMessa
     }
 }
 
+/**
+ * Sends a message in agent mode: the assistant answers using server-side tools.
+ * Calls: POST /api/writing-assistant/:sessionId/agent
+ * Test: test_ai.rs/test_agent_writing_message_success()
+ */
+export async function send_agent_message(sessionId: number, payload: SendMessagePayload): Promise<AgentResponse> {
+    return makeRequest<AgentResponse>(`${API_BASE_URL}/api/writing-assistant/${sessionId}/agent`, 'POST', payload);
+}
+
+/**
+ * Lists the tool calls of a session, oldest first.
+ * Calls: GET /api/writing-assistant/:sessionId/tool-calls
+ * Test: test_ai.rs/test_agent_writing_message_success()
+ */
+export async function get_tool_calls(sessionId: number): Promise<AgentToolCall[]> {
+    return makeRequest<AgentToolCall[]>(`${API_BASE_URL}/api/writing-assistant/${sessionId}/tool-calls`, 'GET');
+}
+
+/**
+ * Gets another answer to the user message an assistant message replied to.
+ * The old answer stays available as another branch.
+ * Calls: POST /api/writing-assistant/:sessionId/messages/:messageId/regenerate
+ * Test: test_ai.rs/test_regenerate_writing_message_success()
+ */
+export async function regenerate_writing_message(sessionId: number, assistantMessageId: number): Promise<AssistantResponse> {
+    return makeRequest<AssistantResponse>(
+        `${API_BASE_URL}/api/writing-assistant/${sessionId}/messages/${assistantMessageId}/regenerate`,
+        'POST',
+        {}
+    );
+}
+
+/**
+ * Sends an edited version of a user message and gets the answer to it.
+ * The original message and its replies stay available as another branch.
+ * Calls: POST /api/writing-assistant/:sessionId/messages/:messageId/edit
+ * Test: test_ai.rs/test_edit_writing_message_success()
+ */
+export async function edit_writing_message(sessionId: number, userMessageId: number, content: string): Promise<AssistantResponse> {
+    return makeRequest<AssistantResponse>(
+        `${API_BASE_URL}/api/writing-assistant/${sessionId}/messages/${userMessageId}/edit`,
+        'POST',
+        { content }
+    );
+}
+
+/**
+ * Lists the branches of a session, newest first.
+ * Calls: GET /api/writing-assistant/:sessionId/branches
+ * Test: test_ai.rs/test_get_writing_branches_success()
+ */
+export async function get_writing_branches(sessionId: number): Promise<ChatBranch[]> {
+    return makeRequest<ChatBranch[]>(`${API_BASE_URL}/api/writing-assistant/${sessionId}/branches`, 'GET');
+}
+
+/**
+ * Switches a session to the branch containing a message and returns that branch's messages.
+ * Calls: PUT /api/writing-assistant/:sessionId/branches/:messageId
+ * Test: test_ai.rs/test_switch_writing_branch_success()
+ */
+export async function switch_writing_branch(sessionId: number, messageId: number): Promise<SessionWithMessages> {
+    return makeRequest<SessionWithMessages>(
+        `${API_BASE_URL}/api/writing-assistant/${sessionId}/branches/${messageId}`,
+        'PUT',
+        {}
+    );
+}
+
+/**
+ * Lists the built-in personas followed by the user's own.
+ * Calls: GET /api/writing-assistant/personas
+ * Test: test_ai.rs/test_get_personas_success()
+ */
+export async function get_personas(): Promise<Persona[]> {
+    return makeRequest<Persona[]>(`${API_BASE_URL}/api/writing-assistant/personas`, 'GET');
+}
+
+/**
+ * Shows the completion and embedding models answering the user.
+ * Calls: GET /api/writing-assistant/providers
+ * Test: test_providers.rs/test_provider_selection()
+ */
+export async function get_providers(): Promise<ProviderInfo> {
+    return makeRequest<ProviderInfo>(`${API_BASE_URL}/api/writing-assistant/providers`, 'GET');
+}
+
+/**
+ * Creates a custom persona.
+ * Calls: POST /api/writing-assistant/personas
+ * Test: test_ai.rs/test_create_persona_success()
+ */
+export async function create_persona(name: string, system_prompt: string, description?: string): Promise<Persona> {
+    return makeRequest<Persona>(`${API_BASE_URL}/api/writing-assistant/personas`, 'POST', {
+        name,
+        description,
+        system_prompt
+    });
+}
+
+/**
+ * Deletes a custom persona, sessions and projects using it go back to the default.
+ * Calls: DELETE /api/writing-assistant/personas/:personaId
+ * Test: test_ai.rs/test_delete_persona_success()
+ */
+export async function delete_persona(personaId: number): Promise<void> {
+    await makeRequest<unknown>(`${API_BASE_URL}/api/writing-assistant/personas/${personaId}`, 'DELETE');
+}
+
+/**
+ * Sets the persona or custom system prompt of a session, both null goes back to the project's persona.
+ * Calls: PUT /api/writing-assistant/:sessionId/persona
+ * Test: test_ai.rs/test_set_session_persona_success()
+ */
+export async function set_session_persona(sessionId: number, settings: PersonaSettings): Promise<WritingAssistantSession> {
+    return makeRequest<WritingAssistantSession>(`${API_BASE_URL}/api/writing-assistant/${sessionId}/persona`, 'PUT', settings);
+}
+
 /**
  * Deletes a specific writing session.
  * Calls: DELETE /api/writing-assistant/:sessionId
@@ -459,6 +703,33 @@ export async function fact_check_text(content: string): Promise<AiCommandRespons
     }
 }
 
+/**
+ * Checks the claims of a text against the user's own sources instead of the AI's knowledge.
+ * Every claim is supported, contradicted or unknown, with the passages cited. Both AI steps are charged.
+ * Calls: POST /api/writing-assistant/factcheck/evidence
+ * Test: test_fact_check.rs/test_evidence_fact_check()
+ */
+export async function fact_check_with_sources(payload: EvidenceFactCheckPayload): Promise<EvidenceFactCheck | null> {
+    console.log('Fact-checking against sources:', payload.content.substring(0, 50) + '...');
+    const apiUrl = `${API_BASE_URL}/api/writing-assistant/factcheck/evidence`;
+    try {
+        const response = await fetch(apiUrl, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(payload),
+            credentials: 'include'
+        });
+        if (!response.ok) {
+            console.error('Evidence fact check failed:', response.status, response.statusText);
+            throw response;
+        }
+        return await response.json();
+    } catch (error) {
+        console.error('Error during fact_check_with_sources:', error);
+        throw error;
+    }
+}
+
 /**
  * Function to check spelling using the backend API.
  * Calls: POST /api/writing-assistant/spellcheck
@@ -503,13 +774,16 @@ export async function apply_ai_suggestion(
         suggestion_content: suggestionContent,
         current_document_id: currentDocumentId 
     };
-    const result = await makeRequest<SuggestedDocumentChange[]>(
+    const result = await makeRequest<ApplySuggestionResult>(
         `${API_BASE_URL}/api/writing-assistant/${sessionId}/apply-suggestion`, 
         'POST', 
         payload
     );
+    if (result?.failures?.length) {
+        console.warn('[apply_ai_suggestion] Changes rejected by the backend:', result.failures);
+    }
     // Ensure the result is always an array, even if the backend sends null/undefined by mistake
-    return result || []; 
+    return result?.changes || []; 
 }
 
 // Define interfaces for ProactiveDiffDecision - these were previously inline
@@ -0,0 +1,120 @@
+/*
+/ consistency.ts
+/
+/ File containing functions for the consistency checks of a project.
+/ A check reads every document of a project in the background and reports name variants, tense and point of view drift,
+/ glossary violations and, with the AI, contradictions of the knowledge base and of the timeline.
+/ Reports fill up while documents are checked, poll get_consistency_report until its status is done or failed.
+/
+/ Summary:
+/ Interfaces:
+/ - ConsistencyReport: The status and progress of a check.
+/ - ConsistencyFinding: Something that does not fit, with offsets into the stored content of a document.
+/ - ConsistencyReportDetail: A report with its findings.
+/
+/ Functions:
+/ - start_consistency_check: Starts a check of a project.
+/ - get_consistency_reports: Lists the checks of a project.
+/ - get_consistency_report: Gets a check and its findings, optionally of one kind or document.
+/
+*/
+
+const API_BASE_URL = process.env.API_BASE_URL;
+
+export type FindingKind =
+    | 'name_variant'
+    | 'knowledge_contradiction'
+    | 'tense_drift'
+    | 'pov_drift'
+    | 'glossary_violation'
+    | 'timeline_conflict';
+
+export interface ConsistencyReport {
+    id: number;
+    project_id: number;
+    user_id: number;
+    status: 'pending' | 'running' | 'done' | 'failed';
+    use_ai: boolean; // Whether the AI checked the documents too, or only the rules
+    documents_total: number;
+    documents_checked: number;
+    finding_count: number;
+    error: string | null;
+    created_at: string;
+    started_at: string | null;
+    finished_at: string | null;
+}
+
+export interface ConsistencyFinding {
+    id: number;
+    kind: FindingKind;
+    source: 'rule' | 'ai';
+    document_id: number | null;
+    document_name: string | null;
+    start_offset: number | null; // Characters of the stored content, null when the text was not found
+    end_offset: number | null;
+    excerpt: string | null;
+    message: string;
+    suggestion: string | null;
+    entity_id: number | null; // The knowledge base entity the finding is about
+    related_document_id: number | null; // The document the finding conflicts with
+    related_offset: number | null;
+}
+
+export interface ConsistencyReportDetail extends ConsistencyReport {
+    findings: ConsistencyFinding[];
+}
+
+async function request<T>(path: string, method: string, body?: object): Promise<T | null> {
+    try {
+        const response = await fetch(`${API_BASE_URL}/api${path}`, {
+            method,
+            headers: body ? { 'Content-Type': 'application/json' } : {},
+            body: body ? JSON.stringify(body) : undefined,
+            credentials: 'include'
+        });
+        if (!response.ok) {
+            console.error(`Consistency request ${method} ${path} failed:`, response.status);
+            return null;
+        }
+        return await response.json();
+    } catch (error) {
+        console.error(`Error during consistency request ${method} ${path}:`, error);
+        return null;
+    }
+}
+
+/**
+ * Starts a consistency check of a project, with the AI unless useAi is false.
+ * With the AI every document is charged. A project has one unfinished check at a time.
+ * Calls: POST /api/writing-assistant/consistency/:id
+ * Test: test_consistency.rs/test_consistency_check()
+ */
+export async function start_consistency_check(projectId: number, useAi = true): Promise<ConsistencyReport | null> {
+    return request<ConsistencyReport>(`/writing-assistant/consistency/${projectId}`, 'POST', { use_ai: useAi });
+}
+
+/**
+ * Lists the consistency checks of a project, newest first.
+ * Calls: GET /api/project/:id/consistency
+ * Test: test_consistency.rs/test_consistency_reports()
+ */
+export async function get_consistency_reports(projectId: number): Promise<ConsistencyReport[] | null> {
+    return request<ConsistencyReport[]>(`/project/${projectId}/consistency`, 'GET');
+}
+
+/**
+ * Gets a consistency check and its findings, optionally only of one kind or document.
+ * Calls: GET /api/project/:id/consistency/:report_id?kind=name_variant&document_id=2
+ * Test: test_consistency.rs/test_consistency_check()
+ */
+export async function get_consistency_report(
+    projectId: number,
+    reportId: number,
+    filter: { kind?: FindingKind; documentId?: number } = {}
+): Promise<ConsistencyReportDetail | null> {
+    const params = new URLSearchParams();
+    if (filter.kind) params.set('kind', filter.kind);
+    if (filter.documentId !== undefined) params.set('document_id', String(filter.documentId));
+    const query = params.toString();
+    return request<ConsistencyReportDetail>(`/project/${projectId}/consistency/${reportId}${query ? `?${query}` : ''}`, 'GET');
+}
@@ -15,6 +15,14 @@
 / update_document: Function to update a document
 / setup_auto_save: Function to setup interval of 30 seconds for auto-save
 / saveDocument: Manual save function for when we want to bind this
+/ get_document_stats: Function to get the readability and writing statistics of a document
+/ get_document_stats_history: Function to get the daily statistics of a document
+/ get_similarity_report: Function to find near-duplicate passages and copies among documents
+/ get_tags: Function to get the tags on the documents the user can read
+/ get_document_tags: Function to get the tags and suggested tags of a document
+/ set_document_tags: Function to replace the tags of a document
+/ dismiss_tag_suggestions: Function to dismiss the suggested tags of a document
+/ get_related_documents: Function to get the documents most related to a document
 / delete_document: Function to delete a document
 / add_document_permissions: Function to add permissions for a user on a document
 / update_document_permissions: Function to update a user's permissions for a document
@@ -61,6 +69,125 @@ export class Document {
 	}
 }
 
+// Readability and writing statistics, computed by the backend from the document content
+export interface WordFrequency {
+	word: string;
+	count: number;
+}
+
+export interface WritingMetrics {
+	word_count: number;
+	character_count: number;
+	sentence_count: number;
+	paragraph_count: number;
+	heading_count: number;
+	syllable_count: number;
+	complex_word_count: number;
+	reading_time_minutes: number;
+	average_sentence_length: number;
+	average_word_length: number;
+	flesch_reading_ease: number;
+	flesch_kincaid_grade: number;
+	gunning_fog: number;
+	coleman_liau_index: number;
+	automated_readability_index: number;
+	unique_words: number;
+	type_token_ratio: number;
+	moving_average_ttr: number;
+	adverb_count: number;
+	adverb_density: number; // Adverbs per 100 words
+	most_repeated_words: WordFrequency[];
+}
+
+export interface DocumentStats {
+	document_id: number;
+	metrics: WritingMetrics;
+	computed_at: string;
+}
+
+// Statistics at the end of a day on which the text changed
+export interface StatsSnapshot {
+	day: string;
+	word_count: number;
+	metrics: WritingMetrics;
+}
+
+// A run of words two documents share, offsets into their plain text
+export interface AlignedSpan {
+	start_offset: number;
+	end_offset: number;
+	other_start_offset: number;
+	other_end_offset: number;
+	word_count: number;
+	text: string;
+}
+
+// Two passages with similar embeddings
+export interface SimilarPassage {
+	kind: 'near_duplicate' | 'reused_text' | 'similar';
+	score: number;
+	chunk_id: number;
+	start_offset: number;
+	end_offset: number;
+	excerpt: string;
+	other_chunk_id: number;
+	other_start_offset: number;
+	other_end_offset: number;
+	other_excerpt: string;
+}
+
+// Two documents with similar passages, or one document repeating itself when both ids are the same
+export interface SimilarDocumentPair {
+	document_id: number;
+	document_name: string;
+	other_document_id: number;
+	other_document_name: string;
+	document_score: number | null;
+	coverage: number;
+	is_copy: boolean;
+	passages: SimilarPassage[];
+	spans: AlignedSpan[];
+}
+
+export interface SimilarityReport {
+	project_id: number | null;
+	min_score: number;
+	documents_compared: number;
+	pending_document_ids: number[];
+	pairs: SimilarDocumentPair[];
+}
+
+// A tag or topic the AI suggested for a document, accepted by adding it to the tags
+export interface TagSuggestion {
+	name: string;
+	kind: 'tag' | 'topic';
+}
+
+export interface DocumentTags {
+	document_id: number;
+	tags: string[];
+	suggestions: TagSuggestion[];
+}
+
+export interface TagCount {
+	name: string;
+	document_count: number;
+}
+
+export interface RelatedDocument {
+	id: number;
+	name: string;
+	score: number;
+	shared_tags: string[];
+}
+
+// Pending until the document has been embedded
+export interface RelatedDocuments {
+	document_id: number;
+	pending: boolean;
+	documents: RelatedDocument[];
+}
+
 // Define a User type for document permissions
 export class DocumentUser {
 	id: number;
@@ -194,13 +321,14 @@ export async function get_document_permissions(document_id: number): Promise<Doc
 }
 
 /**
- * Function to get all documents the user has access to
- * Calls: GET /api/document
- * Test: test_documents.rs/test_get_all_documents()
+ * Function to get all documents the user has access to, optionally only those with a tag
+ * Calls: GET /api/document?tag=research
+ * Test: test_documents.rs/test_get_all_documents(), test_tags.rs/test_tag_filters()
  */
-export async function get_all_documents(): Promise<Document[] | null> {
+export async function get_all_documents(tag?: string): Promise<Document[] | null> {
 	try {
-		const apiUrl = `${API_BASE_URL}/api/document`;
+		const query = tag ? `?tag=${encodeURIComponent(tag)}` : '';
+		const apiUrl = `${API_BASE_URL}/api/document${query}`;
 
 		console.log('Fetching documents from:', apiUrl);
 
@@ -268,6 +396,201 @@ export async function create_document(name: string, content: string): Promise<Do
 	}
 }
 
+/**
+ * Function to get the readability and writing statistics of a document
+ * Calls: GET /api/document/:id/stats
+ * Test: test_documents.rs/test_get_document_stats()
+ */
+export async function get_document_stats(documentId: number): Promise<DocumentStats | null> {
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/document/${documentId}/stats`, {
+			credentials: 'include'
+		});
+
+		if (!response.ok) {
+			console.error('Failed to fetch document stats:', response.status);
+			return null;
+		}
+
+		return await response.json();
+	} catch (error) {
+		console.error('Error fetching document stats:', error);
+		return null;
+	}
+}
+
+/**
+ * Function to get the daily statistics of a document over the last days, oldest first
+ * Calls: GET /api/document/:id/stats/history?days=30
+ * Test: test_documents.rs/test_get_document_stats_history()
+ */
+export async function get_document_stats_history(documentId: number, days: number = 30): Promise<StatsSnapshot[] | null> {
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/document/${documentId}/stats/history?days=${days}`, {
+			credentials: 'include'
+		});
+
+		if (!response.ok) {
+			console.error('Failed to fetch document stats history:', response.status);
+			return null;
+		}
+
+		return await response.json();
+	} catch (error) {
+		console.error('Error fetching document stats history:', error);
+		return null;
+	}
+}
+
+/**
+ * Function to find near-duplicate passages, reused text and copies among the documents the user owns,
+ * or among the documents of a project
+ * Calls: GET /api/document/similarity?project_id=2&min_score=0.9
+ * Test: test_similarity.rs/test_similarity_report()
+ */
+export async function get_similarity_report(projectId?: number, minScore?: number): Promise<SimilarityReport | null> {
+	const params = new URLSearchParams();
+	if (projectId !== undefined) params.set('project_id', String(projectId));
+	if (minScore !== undefined) params.set('min_score', String(minScore));
+
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/document/similarity?${params}`, {
+			credentials: 'include'
+		});
+
+		if (!response.ok) {
+			console.error('Failed to fetch similarity report:', response.status);
+			return null;
+		}
+
+		return await response.json();
+	} catch (error) {
+		console.error('Error fetching similarity report:', error);
+		return null;
+	}
+}
+
+/**
+ * Function to get the tags on the documents the user can read, the most used first
+ * Calls: GET /api/document/tags
+ * Test: test_tags.rs/test_tag_filters()
+ */
+export async function get_tags(): Promise<TagCount[] | null> {
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/document/tags`, {
+			credentials: 'include'
+		});
+
+		if (!response.ok) {
+			console.error('Failed to fetch tags:', response.status);
+			return null;
+		}
+
+		return await response.json();
+	} catch (error) {
+		console.error('Error fetching tags:', error);
+		return null;
+	}
+}
+
+/**
+ * Function to get the tags of a document and the tags and topics suggested for it
+ * Calls: GET /api/document/:id/tags
+ * Test: test_tags.rs/test_tag_suggestions()
+ */
+export async function get_document_tags(documentId: number): Promise<DocumentTags | null> {
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/document/${documentId}/tags`, {
+			credentials: 'include'
+		});
+
+		if (!response.ok) {
+			console.error('Failed to fetch document tags:', response.status);
+			return null;
+		}
+
+		return await response.json();
+	} catch (error) {
+		console.error('Error fetching document tags:', error);
+		return null;
+	}
+}
+
+/**
+ * Function to replace the tags of a document, accepting a suggestion is adding it to the tags
+ * Calls: PUT /api/document/:id/tags
+ * Test: test_tags.rs/test_set_document_tags()
+ */
+export async function set_document_tags(documentId: number, tags: string[]): Promise<DocumentTags | null> {
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/document/${documentId}/tags`, {
+			method: 'PUT',
+			credentials: 'include',
+			headers: {
+				'Content-Type': 'application/json'
+			},
+			body: JSON.stringify({ tags })
+		});
+
+		if (!response.ok) {
+			console.error('Failed to set document tags:', response.status);
+			return null;
+		}
+
+		return await response.json();
+	} catch (error) {
+		console.error('Error setting document tags:', error);
+		return null;
+	}
+}
+
+/**
+ * Function to dismiss the suggested tags of a document until its text changes
+ * Calls: DELETE /api/document/:id/tags/suggestions
+ * Test: test_tags.rs/test_dismiss_suggestions()
+ */
+export async function dismiss_tag_suggestions(documentId: number): Promise<DocumentTags | null> {
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/document/${documentId}/tags/suggestions`, {
+			method: 'DELETE',
+			credentials: 'include'
+		});
+
+		if (!response.ok) {
+			console.error('Failed to dismiss tag suggestions:', response.status);
+			return null;
+		}
+
+		return await response.json();
+	} catch (error) {
+		console.error('Error dismissing tag suggestions:', error);
+		return null;
+	}
+}
+
+/**
+ * Function to get the documents most related to a document, by their embeddings
+ * Calls: GET /api/document/:id/related?limit=5
+ * Test: test_tags.rs/test_related_documents()
+ */
+export async function get_related_documents(documentId: number, limit: number = 5): Promise<RelatedDocuments | null> {
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/document/${documentId}/related?limit=${limit}`, {
+			credentials: 'include'
+		});
+
+		if (!response.ok) {
+			console.error('Failed to fetch related documents:', response.status);
+			return null;
+		}
+
+		return await response.json();
+	} catch (error) {
+		console.error('Error fetching related documents:', error);
+		return null;
+	}
+}
+
 /**
  * Function to delete a document
  * Calls: DELETE /api/document/:id
@@ -65,12 +65,13 @@ export async function create_document(document_payload: Document): Promise<Boole
 }
 
 /**
- * Function to get all documents the user has access to
- * Calls: GET /api/document
- * Test: test_documents.rs/test_get_all_documents()
+ * Function to get all documents the user has access to, optionally only those with a tag
+ * Calls: GET /api/document?tag=research
+ * Test: test_documents.rs/test_get_all_documents(), test_tags.rs/test_tag_filters()
  */
-export async function get_all_documents(): Promise<Document[] | null> {
-	const apiUrl = `${API_BASE_URL}/api/document/`;
+export async function get_all_documents(tag?: string): Promise<Document[] | null> {
+	const query = tag ? `?tag=${encodeURIComponent(tag)}` : '';
+	const apiUrl = `${API_BASE_URL}/api/document/${query}`;
 
 	try {
 		const response = await fetch(apiUrl, {
@@ -151,12 +152,13 @@ export async function create_project(name: string): Promise<Project | null> {
 }
 
 /**
- * Function to get all projects for the current user
- * Calls: GET /api/project
- * Test: test_projects.rs/test_get_all_projects()
+ * Function to get all projects for the current user, optionally only those with a document with a tag
+ * Calls: GET /api/project?tag=research
+ * Test: test_projects.rs/test_get_all_projects(), test_tags.rs/test_tag_filters()
  */
-export async function get_all_projects(): Promise<Project[] | null> {
-	const apiUrl = `${API_BASE_URL}/api/project`;
+export async function get_all_projects(tag?: string): Promise<Project[] | null> {
+	const query = tag ? `?tag=${encodeURIComponent(tag)}` : '';
+	const apiUrl = `${API_BASE_URL}/api/project${query}`;
 
 	try {
 		console.log('Fetching projects from:', apiUrl);
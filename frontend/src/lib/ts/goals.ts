@@ -0,0 +1,138 @@
+/*
+/ goals.ts
+/
+/ File containing functions for writing goals.
+/ A goal is a word-count target for a project, optionally by a deadline, or a number of words every day.
+/ Progress is measured from the net words of every document save, on the server's dates.
+/
+/ Summary:
+/ Interfaces:
+/ - WritingGoal: A goal with its progress, streaks and projected completion date.
+/ - DailyWords: Words the user added and removed on one day.
+/
+/ Functions:
+/ - get_goals: Lists the user's goals, optionally of one project.
+/ - get_goal: Gets a goal with its progress.
+/ - create_goal: Creates a project or daily goal.
+/ - update_goal: Updates the target, start date and deadline of a goal.
+/ - delete_goal: Deletes a goal.
+/ - get_writing_activity: Gets the words the user wrote per day.
+/
+*/
+
+const API_BASE_URL = process.env.API_BASE_URL;
+
+export type GoalKind = 'project' | 'daily';
+
+export interface WritingGoal {
+    id: number;
+    project_id: number | null;
+    kind: GoalKind;
+    target_words: number;
+    start_date: string; // YYYY-MM-DD
+    deadline: string | null;
+    created_at: string;
+    today: string;
+    words_today: number;
+    words_written: number; // Since the start date for project goals, today for daily goals
+    words_remaining: number;
+    percent_complete: number;
+    completed: boolean;
+    completed_on: string | null;
+    daily_target: number | null; // Words a day that count towards a streak
+    words_needed_per_day: number | null; // To reach the target by the deadline
+    days_elapsed: number;
+    days_remaining: number | null;
+    average_words_per_day: number;
+    current_streak: number;
+    longest_streak: number;
+    projected_completion_date: string | null;
+    on_track: boolean | null;
+}
+
+export interface GoalSettings {
+    target_words: number;
+    start_date?: string; // Defaults to today, kept on update
+    deadline?: string | null; // Removed on update when missing
+}
+
+export interface DailyWords {
+    day: string;
+    words_added: number;
+    words_removed: number;
+    net_words: number;
+}
+
+async function request<T>(path: string, method: string, body?: object): Promise<T | null> {
+    try {
+        const response = await fetch(`${API_BASE_URL}/api/goals${path}`, {
+            method,
+            headers: body ? { 'Content-Type': 'application/json' } : {},
+            body: body ? JSON.stringify(body) : undefined,
+            credentials: 'include'
+        });
+        if (!response.ok) {
+            console.error(`Goal request ${method} ${path} failed:`, response.status);
+            return null;
+        }
+        return await response.json();
+    } catch (error) {
+        console.error(`Error during goal request ${method} ${path}:`, error);
+        return null;
+    }
+}
+
+/**
+ * Lists the user's goals with their progress.
+ * Calls: GET /api/goals?project_id=1
+ * Test: test_goals.rs/test_daily_goal()
+ */
+export async function get_goals(projectId?: number): Promise<WritingGoal[] | null> {
+    return request<WritingGoal[]>(projectId !== undefined ? `?project_id=${projectId}` : '', 'GET');
+}
+
+/**
+ * Gets a goal with its progress, streaks and projected completion date.
+ * Calls: GET /api/goals/:id
+ * Test: test_goals.rs/test_daily_goal()
+ */
+export async function get_goal(goalId: number): Promise<WritingGoal | null> {
+    return request<WritingGoal>(`/${goalId}`, 'GET');
+}
+
+/**
+ * Creates a goal. Project goals need a project, daily goals count every document unless one is given.
+ * Calls: POST /api/goals
+ * Test: test_goals.rs/test_project_goal()
+ */
+export async function create_goal(kind: GoalKind, settings: GoalSettings, projectId?: number): Promise<WritingGoal | null> {
+    return request<WritingGoal>('', 'POST', { kind, project_id: projectId, ...settings });
+}
+
+/**
+ * Updates the target, start date and deadline of a goal.
+ * Calls: PUT /api/goals/:id
+ * Test: test_goals.rs/test_project_goal()
+ */
+export async function update_goal(goalId: number, settings: GoalSettings): Promise<WritingGoal | null> {
+    return request<WritingGoal>(`/${goalId}`, 'PUT', settings);
+}
+
+/**
+ * Deletes a goal, the writing activity is kept.
+ * Calls: DELETE /api/goals/:id
+ * Test: test_goals.rs/test_delete_goal()
+ */
+export async function delete_goal(goalId: number): Promise<boolean> {
+    return (await request<unknown>(`/${goalId}`, 'DELETE')) !== null;
+}
+
+/**
+ * Gets the words the user wrote on each day with activity, oldest first.
+ * Calls: GET /api/goals/activity?days=30&project_id=1
+ * Test: test_goals.rs/test_writing_activity()
+ */
+export async function get_writing_activity(days: number = 30, projectId?: number): Promise<DailyWords[] | null> {
+    const project = projectId !== undefined ? `&project_id=${projectId}` : '';
+    return request<DailyWords[]>(`/activity?days=${days}${project}`, 'GET');
+}
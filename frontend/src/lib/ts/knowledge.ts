@@ -0,0 +1,186 @@
+/*
+/ knowledge.ts
+/
+/ File containing functions for the knowledge base of a project.
+/ A knowledge base holds the characters, places and terms of a project and its style guide.
+/ The writing assistant always sees it for documents of the project, entities are linked to the documents mentioning them.
+/
+/ Summary:
+/ Interfaces:
+/ - ProjectEntity: A character, place or term with its aliases, description and notes.
+/ - EntityMention: Where a document mentions an entity.
+/ - KnowledgeBase: The style guide and entities of a project.
+/
+/ Functions:
+/ - get_knowledge_base: Gets the style guide and entities of a project.
+/ - update_style_guide: Sets or removes the style guide of a project.
+/ - get_entities: Lists the entities of a project, optionally of one kind.
+/ - get_entity: Gets an entity and the documents mentioning it.
+/ - add_entity: Adds an entity to a project.
+/ - update_entity: Replaces an entity.
+/ - delete_entity: Removes an entity.
+/ - extract_entities: Finds the entities of a project's documents with the AI.
+/ - get_document_entities: Gets the entities a document mentions.
+/
+*/
+
+const API_BASE_URL = process.env.API_BASE_URL;
+
+export type EntityKind = 'character' | 'place' | 'organization' | 'item' | 'term' | 'other';
+
+export interface ProjectEntity {
+    id: number;
+    project_id: number;
+    kind: EntityKind;
+    name: string;
+    aliases: string[];
+    description: string | null;
+    notes: string | null;
+    source: 'manual' | 'extracted'; // Extracted entities become manual once edited
+    created_at: string;
+    updated_at: string;
+    document_count: number;
+    mention_count: number;
+}
+
+export interface EntityInput {
+    kind: EntityKind;
+    name: string;
+    aliases?: string[];
+    description?: string;
+    notes?: string;
+}
+
+export interface EntityMention {
+    document_id: number;
+    document_name: string;
+    mention_count: number;
+    offsets: number[]; // Characters of the stored content, the first 100 mentions
+}
+
+export interface EntityDetail extends ProjectEntity {
+    mentions: EntityMention[];
+}
+
+export interface DocumentEntity {
+    entity_id: number;
+    project_id: number;
+    kind: EntityKind;
+    name: string;
+    mention_count: number;
+    offsets: number[];
+}
+
+export interface KnowledgeBase {
+    project_id: number;
+    style_guide: string | null;
+    entities: ProjectEntity[];
+}
+
+export interface ExtractionResult {
+    project_id: number;
+    documents: number[]; // Documents that were analyzed
+    created: ProjectEntity[];
+    updated: ProjectEntity[]; // Known entities that got aliases or a description
+}
+
+async function request<T>(path: string, method: string, body?: object): Promise<T | null> {
+    try {
+        const response = await fetch(`${API_BASE_URL}/api${path}`, {
+            method,
+            headers: body ? { 'Content-Type': 'application/json' } : {},
+            body: body ? JSON.stringify(body) : undefined,
+            credentials: 'include'
+        });
+        if (!response.ok) {
+            console.error(`Knowledge base request ${method} ${path} failed:`, response.status);
+            return null;
+        }
+        return await response.json();
+    } catch (error) {
+        console.error(`Error during knowledge base request ${method} ${path}:`, error);
+        return null;
+    }
+}
+
+/**
+ * Gets the style guide and entities of a project.
+ * Calls: GET /api/project/:id/knowledge
+ * Test: test_knowledge.rs/test_style_guide()
+ */
+export async function get_knowledge_base(projectId: number): Promise<KnowledgeBase | null> {
+    return request<KnowledgeBase>(`/project/${projectId}/knowledge`, 'GET');
+}
+
+/**
+ * Sets the style guide of a project, null or an empty text removes it.
+ * Calls: PUT /api/project/:id/style-guide
+ * Test: test_knowledge.rs/test_style_guide()
+ */
+export async function update_style_guide(projectId: number, styleGuide: string | null): Promise<boolean> {
+    return (await request<unknown>(`/project/${projectId}/style-guide`, 'PUT', { style_guide: styleGuide })) !== null;
+}
+
+/**
+ * Lists the entities of a project by kind and name.
+ * Calls: GET /api/project/:id/entities?kind=character
+ * Test: test_knowledge.rs/test_entities()
+ */
+export async function get_entities(projectId: number, kind?: EntityKind): Promise<ProjectEntity[] | null> {
+    return request<ProjectEntity[]>(`/project/${projectId}/entities${kind ? `?kind=${kind}` : ''}`, 'GET');
+}
+
+/**
+ * Gets an entity and the documents of the project that mention it.
+ * Calls: GET /api/project/:id/entities/:entity_id
+ * Test: test_knowledge.rs/test_entity_mentions()
+ */
+export async function get_entity(projectId: number, entityId: number): Promise<EntityDetail | null> {
+    return request<EntityDetail>(`/project/${projectId}/entities/${entityId}`, 'GET');
+}
+
+/**
+ * Adds an entity to a project. Names are unique per project, ignoring case.
+ * Calls: POST /api/project/:id/entities
+ * Test: test_knowledge.rs/test_entities()
+ */
+export async function add_entity(projectId: number, entity: EntityInput): Promise<ProjectEntity | null> {
+    return request<ProjectEntity>(`/project/${projectId}/entities`, 'POST', entity);
+}
+
+/**
+ * Replaces an entity of a project.
+ * Calls: PUT /api/project/:id/entities/:entity_id
+ * Test: test_knowledge.rs/test_entities()
+ */
+export async function update_entity(projectId: number, entityId: number, entity: EntityInput): Promise<ProjectEntity | null> {
+    return request<ProjectEntity>(`/project/${projectId}/entities/${entityId}`, 'PUT', entity);
+}
+
+/**
+ * Removes an entity from a project.
+ * Calls: DELETE /api/project/:id/entities/:entity_id
+ * Test: test_knowledge.rs/test_entities()
+ */
+export async function delete_entity(projectId: number, entityId: number): Promise<boolean> {
+    return (await request<unknown>(`/project/${projectId}/entities/${entityId}`, 'DELETE')) !== null;
+}
+
+/**
+ * Finds the entities of a project's documents with the AI, all documents unless some are given.
+ * Every analyzed document is charged.
+ * Calls: POST /api/writing-assistant/knowledge/extract/:id
+ * Test: test_knowledge.rs/test_extract_entities()
+ */
+export async function extract_entities(projectId: number, documentIds?: number[]): Promise<ExtractionResult | null> {
+    return request<ExtractionResult>(`/writing-assistant/knowledge/extract/${projectId}`, 'POST', { document_ids: documentIds });
+}
+
+/**
+ * Gets the knowledge base entities a document mentions, in order of first mention.
+ * Calls: GET /api/document/:id/entities
+ * Test: test_knowledge.rs/test_entity_mentions()
+ */
+export async function get_document_entities(documentId: number): Promise<DocumentEntity[] | null> {
+    return request<DocumentEntity[]>(`/document/${documentId}/entities`, 'GET');
+}
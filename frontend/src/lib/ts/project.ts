@@ -9,6 +9,10 @@
 / Class ProjectUser: Represents a user with project permissions
 / get_project: Function to get a project by ID
 / update_project: Function to update a project
+/ get_project_persona: Function to get the writing assistant persona of a project
+/ set_project_persona: Function to set the writing assistant persona of a project
+/ get_project_stats: Function to get the readability and writing statistics of a project
+/ get_project_stats_history: Function to get the daily statistics of a project
 / delete_project: Function to delete a project
 / force_delete_project: Function to delete a project and all documents in it
 / add_document_to_project: Function to add a document to a project
@@ -21,10 +25,28 @@
 /
 */
 
-import type { Document } from './document';
+import type { Document, StatsSnapshot, WritingMetrics } from './document';
+import type { PersonaSettings } from './ai';
 
 const API_BASE_URL = process.env.API_BASE_URL;
 
+// Headline numbers of one document of a project
+export interface ProjectDocumentStats {
+	document_id: number;
+	name: string;
+	word_count: number;
+	reading_time_minutes: number;
+	flesch_reading_ease: number;
+}
+
+// Statistics of all documents of a project that are not in the trash
+export interface ProjectStats {
+	project_id: number;
+	metrics: WritingMetrics;
+	documents: ProjectDocumentStats[];
+	computed_at: string;
+}
+
 export class Project {
 	id: number;
 	name: string;
@@ -79,11 +101,12 @@ export async function get_project(project_id: number): Promise<Project | null> {
 }
 
 /**
- * Function to get all projects for the current user
- * Calls: GET /api/project
+ * Function to get all projects for the current user, optionally only those with a document with a tag
+ * Calls: GET /api/project?tag=research
  */
-export async function get_all_projects(): Promise<Project[] | null> {
-	const apiUrl = `${API_BASE_URL}/api/project`;
+export async function get_all_projects(tag?: string): Promise<Project[] | null> {
+	const query = tag ? `?tag=${encodeURIComponent(tag)}` : '';
+	const apiUrl = `${API_BASE_URL}/api/project${query}`;
 
 	try {
 		const response = await fetch(apiUrl, {
@@ -300,11 +323,12 @@ export async function remove_project_permissions(projectId: number, userId: numb
 }
 
 /**
- * Function to get all documents in a project
- * Calls: GET /api/project/:id/documents
+ * Function to get all documents in a project, optionally only those with a tag
+ * Calls: GET /api/project/:id/documents?tag=research
  */
-export async function get_project_documents(project_id: number): Promise<Document[] | null> {
-	const apiUrl = `${API_BASE_URL}/api/project/${project_id}/documents`;
+export async function get_project_documents(project_id: number, tag?: string): Promise<Document[] | null> {
+	const query = tag ? `?tag=${encodeURIComponent(tag)}` : '';
+	const apiUrl = `${API_BASE_URL}/api/project/${project_id}/documents${query}`;
 
 	try {
 		const response = await fetch(apiUrl, {
@@ -376,3 +400,103 @@ export async function remove_document_from_project(projectId: number, documentId
 		return false;
 	}
 }
+
+/**
+ * Function to get the writing assistant persona of a project
+ * Calls: GET /api/project/:id/persona
+ */
+export async function get_project_persona(projectId: number): Promise<PersonaSettings | null> {
+	const apiUrl = `${API_BASE_URL}/api/project/${projectId}/persona`;
+
+	try {
+		const response = await fetch(apiUrl, {
+			method: 'GET',
+			credentials: 'include'
+		});
+
+		if (response.ok) {
+			return (await response.json()) as PersonaSettings;
+		} else {
+			console.error('Get project persona failed with status:', response.status);
+			return null;
+		}
+	} catch (error) {
+		console.error('Get project persona error:', error);
+		return null;
+	}
+}
+
+/**
+ * Function to set the writing assistant persona of a project, a custom system_prompt takes precedence
+ * Calls: PUT /api/project/:id/persona
+ */
+export async function set_project_persona(projectId: number, settings: PersonaSettings): Promise<boolean> {
+	const apiUrl = `${API_BASE_URL}/api/project/${projectId}/persona`;
+
+	try {
+		const response = await fetch(apiUrl, {
+			method: 'PUT',
+			headers: {
+				'Content-Type': 'application/json'
+			},
+			body: JSON.stringify(settings),
+			credentials: 'include'
+		});
+
+		if (response.ok) {
+			return true;
+		} else {
+			console.error('Set project persona failed with status:', response.status);
+			return false;
+		}
+	} catch (error) {
+		console.error('Set project persona error:', error);
+		return false;
+	}
+}
+
+/**
+ * Function to get the readability and writing statistics of a project
+ * Calls: GET /api/project/:id/stats
+ * Test: test_projects.rs/test_project_stats()
+ */
+export async function get_project_stats(projectId: number): Promise<ProjectStats | null> {
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/project/${projectId}/stats`, {
+			credentials: 'include'
+		});
+
+		if (response.ok) {
+			return (await response.json()) as ProjectStats;
+		} else {
+			console.error('Get project stats failed with status:', response.status);
+			return null;
+		}
+	} catch (error) {
+		console.error('Get project stats error:', error);
+		return null;
+	}
+}
+
+/**
+ * Function to get the daily statistics of a project over the last days, oldest first
+ * Calls: GET /api/project/:id/stats/history?days=30
+ * Test: test_projects.rs/test_project_stats()
+ */
+export async function get_project_stats_history(projectId: number, days: number = 30): Promise<StatsSnapshot[] | null> {
+	try {
+		const response = await fetch(`${API_BASE_URL}/api/project/${projectId}/stats/history?days=${days}`, {
+			credentials: 'include'
+		});
+
+		if (response.ok) {
+			return (await response.json()) as StatsSnapshot[];
+		} else {
+			console.error('Get project stats history failed with status:', response.status);
+			return null;
+		}
+	} catch (error) {
+		console.error('Get project stats history error:', error);
+		return null;
+	}
+}
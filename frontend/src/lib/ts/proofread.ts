@@ -0,0 +1,116 @@
+/*
+/ proofread.ts
+/
+/ File containing functions for the offline grammar and spell checker.
+/ Checks run on the backend against Hunspell dictionaries and style rules and cost no AI credits;
+/ check_grammar and check_spelling in ai.ts remain for deeper rewrites by the assistant.
+/
+/ Summary:
+/ Interfaces:
+/ - ProofreadIssue: A problem in the checked text, with character offsets and suggestions.
+/ - ProofreadResponse: Issues of a check and whether spelling was checked.
+/ - ProofreadLanguages: Languages with an installed dictionary.
+/ - DictionaryWord: A word of the user's custom dictionary.
+/
+/ Functions:
+/ - proofread_text: Checks text for spelling, repeated words, passive voice and long sentences.
+/ - get_proofread_languages: Lists the languages with an installed dictionary.
+/ - get_dictionary: Lists the words of the user's custom dictionary.
+/ - add_dictionary_word: Adds a word to the user's custom dictionary.
+/ - delete_dictionary_word: Removes a word from the user's custom dictionary.
+/
+*/
+
+const API_BASE_URL = process.env.API_BASE_URL;
+
+export type ProofreadIssueKind = 'spelling' | 'repeated_word' | 'passive_voice' | 'long_sentence';
+
+export interface ProofreadIssue {
+    kind: ProofreadIssueKind;
+    message: string;
+    start_offset: number; // Character offsets into the checked text, end exclusive
+    end_offset: number;
+    text: string;
+    suggestions: string[]; // Replacements for the whole span, best first
+}
+
+export interface ProofreadResponse {
+    language: string;
+    dictionary_loaded: boolean; // False when the server has no dictionary for the language, only style issues are reported
+    word_count: number;
+    issues: ProofreadIssue[];
+}
+
+export interface ProofreadLanguages {
+    default_language: string;
+    languages: string[];
+}
+
+export interface DictionaryWord {
+    word: string;
+    created_at: string;
+}
+
+async function request<T>(path: string, method: string, body?: object): Promise<T | null> {
+    try {
+        const response = await fetch(`${API_BASE_URL}/api/proofread${path}`, {
+            method,
+            headers: body ? { 'Content-Type': 'application/json' } : {},
+            body: body ? JSON.stringify(body) : undefined,
+            credentials: 'include'
+        });
+        if (!response.ok) {
+            console.error(`Proofread request ${method} ${path} failed:`, response.status);
+            return null;
+        }
+        return await response.json();
+    } catch (error) {
+        console.error(`Error during proofread request ${method} ${path}:`, error);
+        return null;
+    }
+}
+
+/**
+ * Checks text offline, without AI credits.
+ * Calls: POST /api/proofread
+ * Test: test_proofread.rs/test_proofread_style_issues()
+ */
+export async function proofread_text(content: string, language?: string): Promise<ProofreadResponse | null> {
+    return request<ProofreadResponse>('', 'POST', { content, language });
+}
+
+/**
+ * Lists the languages with an installed dictionary.
+ * Calls: GET /api/proofread/languages
+ * Test: test_proofread.rs/test_get_languages()
+ */
+export async function get_proofread_languages(): Promise<ProofreadLanguages | null> {
+    return request<ProofreadLanguages>('/languages', 'GET');
+}
+
+/**
+ * Lists the words of the user's custom dictionary.
+ * Calls: GET /api/proofread/dictionary
+ * Test: test_proofread.rs/test_custom_dictionary()
+ */
+export async function get_dictionary(): Promise<DictionaryWord[] | null> {
+    return request<DictionaryWord[]>('/dictionary', 'GET');
+}
+
+/**
+ * Adds a word to the user's custom dictionary, it is never reported as misspelled.
+ * Calls: POST /api/proofread/dictionary
+ * Test: test_proofread.rs/test_custom_dictionary()
+ */
+export async function add_dictionary_word(word: string): Promise<DictionaryWord | null> {
+    return request<DictionaryWord>('/dictionary', 'POST', { word });
+}
+
+/**
+ * Removes a word from the user's custom dictionary.
+ * Calls: DELETE /api/proofread/dictionary/:word
+ * Test: test_proofread.rs/test_custom_dictionary()
+ */
+export async function delete_dictionary_word(word: string): Promise<boolean> {
+    return (await request<unknown>(`/dictionary/${encodeURIComponent(word)}`, 'DELETE')) !== null;
+}
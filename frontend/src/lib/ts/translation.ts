@@ -0,0 +1,214 @@
+/*
+/ translation.ts
+/
+/ File containing functions for translating text, documents and projects.
+/ Translations follow the glossary of the project: terms are kept as written or translated a fixed way.
+/ A translated document is linked to its source and becomes stale when the source changes.
+/
+/ Summary:
+/ Interfaces:
+/ - GlossaryTerm: A term of a project glossary and its rule.
+/ - GlossaryViolation: A glossary rule a translation still breaks.
+/ - TranslationLink: A translated document, its source and whether it is stale.
+/
+/ Functions:
+/ - translate_text: Translates a selection, enforcing the glossary of a project or document.
+/ - translate_document: Translates a document into a linked document, or updates its translation.
+/ - translate_project: Translates every document of a project that has no up to date translation.
+/ - get_glossary: Gets the glossary of a project.
+/ - add_glossary_term: Adds a term to the glossary of a project.
+/ - update_glossary_term: Replaces a term of the glossary of a project.
+/ - delete_glossary_term: Removes a term from the glossary of a project.
+/ - get_document_translations: Gets the translations and translation source of a document.
+/ - get_project_translations: Gets the translations of the documents of a project.
+/
+*/
+
+const API_BASE_URL = process.env.API_BASE_URL;
+
+export type GlossaryRule = 'keep' | 'translate';
+
+export interface GlossaryTerm {
+    id: number;
+    project_id: number;
+    term: string;
+    rule: GlossaryRule;
+    translation: string | null; // Set for translate rules
+    language: string | null; // Keep rules without a language apply to every language
+    notes: string | null;
+    created_at: string;
+}
+
+export interface GlossaryTermInput {
+    term: string;
+    rule: GlossaryRule;
+    translation?: string;
+    language?: string;
+    notes?: string;
+}
+
+export interface GlossaryViolation {
+    term: string;
+    expected: string; // Text the translation should contain
+    source_occurrences: number;
+    translation_occurrences: number;
+}
+
+export interface TranslationResult {
+    translation: string;
+    target_language: string;
+    glossary_violations: GlossaryViolation[];
+}
+
+export interface DocumentTranslationResult {
+    document_id: number;
+    source_document_id: number;
+    target_language: string;
+    created: boolean; // False when an existing translation was updated
+    glossary_violations: GlossaryViolation[];
+}
+
+export interface DocumentTranslationFailure {
+    document_id: number;
+    reason: string; // Error type, e.g. INSUFFICIENT_AI_CREDITS
+}
+
+export interface ProjectTranslationResult {
+    project_id: number;
+    target_language: string;
+    documents: DocumentTranslationResult[];
+    up_to_date: number[]; // Documents whose translation was already current
+    skipped: number[]; // Documents of the project the user cannot read
+    failures: DocumentTranslationFailure[]; // The other documents are translated anyway
+}
+
+export interface TranslationLink {
+    document_id: number;
+    document_name: string;
+    source_document_id: number;
+    source_name: string;
+    target_language: string;
+    translated_at: string;
+    stale: boolean;
+}
+
+export interface DocumentTranslations {
+    source: TranslationLink | null; // Set when the document is a translation
+    translations: TranslationLink[];
+}
+
+async function request<T>(path: string, method: string, body?: object): Promise<T | null> {
+    try {
+        const response = await fetch(`${API_BASE_URL}/api${path}`, {
+            method,
+            headers: body ? { 'Content-Type': 'application/json' } : {},
+            body: body ? JSON.stringify(body) : undefined,
+            credentials: 'include'
+        });
+        if (!response.ok) {
+            console.error(`Translation request ${method} ${path} failed:`, response.status);
+            return null;
+        }
+        return await response.json();
+    } catch (error) {
+        console.error(`Error during translation request ${method} ${path}:`, error);
+        return null;
+    }
+}
+
+/**
+ * Translates a selection. The glossary of the project, or of the projects of the document, is enforced.
+ * Calls: POST /api/writing-assistant/translate
+ * Test: test_translation.rs/test_translate_selection()
+ */
+export async function translate_text(
+    content: string,
+    targetLanguage: string,
+    options: { sourceLanguage?: string; projectId?: number; documentId?: number } = {}
+): Promise<TranslationResult | null> {
+    return request<TranslationResult>('/writing-assistant/translate', 'POST', {
+        content,
+        target_language: targetLanguage,
+        source_language: options.sourceLanguage,
+        project_id: options.projectId,
+        document_id: options.documentId
+    });
+}
+
+/**
+ * Translates a document into a new linked document, or updates its existing translation.
+ * Calls: POST /api/writing-assistant/translate/document/:id
+ * Test: test_translation.rs/test_translate_document()
+ */
+export async function translate_document(documentId: number, targetLanguage: string, sourceLanguage?: string): Promise<DocumentTranslationResult | null> {
+    return request<DocumentTranslationResult>(`/writing-assistant/translate/document/${documentId}`, 'POST', {
+        target_language: targetLanguage,
+        source_language: sourceLanguage
+    });
+}
+
+/**
+ * Translates every document of a project whose translation is missing or stale.
+ * Calls: POST /api/writing-assistant/translate/project/:id
+ * Test: test_translation.rs/test_translate_project()
+ */
+export async function translate_project(projectId: number, targetLanguage: string, sourceLanguage?: string): Promise<ProjectTranslationResult | null> {
+    return request<ProjectTranslationResult>(`/writing-assistant/translate/project/${projectId}`, 'POST', {
+        target_language: targetLanguage,
+        source_language: sourceLanguage
+    });
+}
+
+/**
+ * Gets the glossary of a project.
+ * Calls: GET /api/project/:id/glossary
+ * Test: test_translation.rs/test_glossary()
+ */
+export async function get_glossary(projectId: number): Promise<GlossaryTerm[] | null> {
+    return request<GlossaryTerm[]>(`/project/${projectId}/glossary`, 'GET');
+}
+
+/**
+ * Adds a term to the glossary of a project. Terms are unique per language, ignoring case.
+ * Calls: POST /api/project/:id/glossary
+ * Test: test_translation.rs/test_glossary()
+ */
+export async function add_glossary_term(projectId: number, term: GlossaryTermInput): Promise<GlossaryTerm | null> {
+    return request<GlossaryTerm>(`/project/${projectId}/glossary`, 'POST', term);
+}
+
+/**
+ * Replaces a term of the glossary of a project.
+ * Calls: PUT /api/project/:id/glossary/:term_id
+ * Test: test_translation.rs/test_glossary()
+ */
+export async function update_glossary_term(projectId: number, termId: number, term: GlossaryTermInput): Promise<GlossaryTerm | null> {
+    return request<GlossaryTerm>(`/project/${projectId}/glossary/${termId}`, 'PUT', term);
+}
+
+/**
+ * Removes a term from the glossary of a project.
+ * Calls: DELETE /api/project/:id/glossary/:term_id
+ * Test: test_translation.rs/test_glossary()
+ */
+export async function delete_glossary_term(projectId: number, termId: number): Promise<boolean> {
+    return (await request<unknown>(`/project/${projectId}/glossary/${termId}`, 'DELETE')) !== null;
+}
+
+/**
+ * Gets the translations of a document and the document it was translated from.
+ * Calls: GET /api/document/:id/translations
+ * Test: test_translation.rs/test_stale_translation()
+ */
+export async function get_document_translations(documentId: number): Promise<DocumentTranslations | null> {
+    return request<DocumentTranslations>(`/document/${documentId}/translations`, 'GET');
+}
+
+/**
+ * Gets the translations of the documents of a project and whether they are stale.
+ * Calls: GET /api/project/:id/translations
+ * Test: test_translation.rs/test_translate_project()
+ */
+export async function get_project_translations(projectId: number): Promise<TranslationLink[] | null> {
+    return request<TranslationLink[]>(`/project/${projectId}/translations`, 'GET');
+}